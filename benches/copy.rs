@@ -0,0 +1,77 @@
+//! Throughput of copying a file into a backup, through buffers of several
+//! sizes, compared with `std::fs::copy` that backups used to rely on.
+//!
+//! Run with `cargo bench --bench copy`, setting `BENCH_SIZE_MIB` to change the
+//! size of the copied file (default: 256).
+
+use std::env;
+use std::fs;
+use std::time::{Duration, Instant};
+
+use backup::backup::{self as backups, BackupOptions, Fsync, Reflink};
+use backup::checksum::Algorithm;
+use tempfile::TempDir;
+
+/// Number of times each copy is timed, keeping the fastest.
+const ROUNDS: usize = 5;
+
+fn main() {
+    let mib = env::var("BENCH_SIZE_MIB")
+        .ok()
+        .and_then(|size| size.parse().ok())
+        .unwrap_or(256);
+    let tmp = TempDir::new().unwrap();
+    let source = tmp.path().join("source.bin");
+    let contents: Vec<u8> = (0..1 << 20).map(|i: u32| (i * 31 % 251) as u8).collect();
+    fs::write(&source, contents.repeat(mib)).unwrap();
+    let bytes = mib as u64 * (1 << 20);
+
+    let fastest = time(|| {
+        let target = tmp.path().join("std");
+        fs::copy(&source, &target).unwrap();
+        fs::remove_file(target).unwrap();
+    });
+    print(&format!("std::fs::copy, {mib} MiB"), bytes, fastest);
+
+    for checksum in [None, Some(Algorithm::Blake3)] {
+        for buffer_size in [64 << 10, 1 << 20, 8 << 20] {
+            let options = BackupOptions::new()
+                .reflink(Reflink::Never)
+                .fsync(Fsync::None)
+                .space_check(false)
+                .checksum(checksum)
+                .buffer_size(buffer_size);
+            let target = tmp.path().join("backups");
+            let fastest = time(|| {
+                backups::backup(&source, &target, &options).unwrap();
+                fs::remove_dir_all(&target).unwrap();
+            });
+            let hashed = match checksum {
+                Some(_) => ", hashed",
+                None => "",
+            };
+            print(
+                &format!("buffer of {} KiB{hashed}", buffer_size >> 10),
+                bytes,
+                fastest,
+            );
+        }
+    }
+}
+
+/// Runs `copy` [`ROUNDS`] times, returning the fastest run.
+fn time(mut copy: impl FnMut()) -> Duration {
+    (0..ROUNDS)
+        .map(|_| {
+            let start = Instant::now();
+            copy();
+            start.elapsed()
+        })
+        .min()
+        .unwrap()
+}
+
+fn print(name: &str, bytes: u64, elapsed: Duration) {
+    let throughput = bytes as f64 / (1 << 20) as f64 / elapsed.as_secs_f64();
+    println!("{name:<32} {throughput:>10.0} MiB/s");
+}
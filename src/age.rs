@@ -0,0 +1,650 @@
+//! Encryption of archives to age X25519 recipients or with a passphrase.
+//!
+//! Encrypted archives follow version 1 of the age format
+//! (<https://age-encryption.org/v1>), so the `age` tool decrypts them too: a
+//! text header wraps a random file key for each recipient, then the archive
+//! is encrypted in chunks of 64 KiB with ChaCha20-Poly1305. Recipients are
+//! public keys such as `age1...`, and identities the matching secret keys,
+//! `AGE-SECRET-KEY-1...`, as written by `age-keygen`. A passphrase instead
+//! wraps the file key with a key derived from it with scrypt.
+//!
+//! Identities and passphrases are never printed: their `Debug` output and
+//! the errors about them only show public information.
+
+use std::fmt;
+use std::fs;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::path::Path;
+use std::str::FromStr;
+
+use crate::crypto;
+use crate::error::BackupError;
+use crate::split;
+
+/// First line of the header of encrypted files.
+pub const MAGIC: &[u8] = b"age-encryption.org/v1\n";
+
+/// Extension appended to encrypted archives.
+pub const EXTENSION: &str = "age";
+
+/// Human readable part of the bech32 encoding of recipients.
+const RECIPIENT_HRP: &str = "age";
+
+/// Human readable part of the bech32 encoding of identities.
+const IDENTITY_HRP: &str = "age-secret-key-";
+
+/// Label of the key wrapping the file key for an X25519 recipient.
+const X25519_LABEL: &[u8] = b"age-encryption.org/v1/X25519";
+
+/// Label prefixed to the salt of the key wrapping the file key for a
+/// passphrase.
+const SCRYPT_LABEL: &[u8] = b"age-encryption.org/v1/scrypt";
+
+/// Default base 2 logarithm of the scrypt cost of passphrases, which takes
+/// about a second.
+pub const WORK_FACTOR: u8 = 18;
+
+/// Highest work factor accepted, as scrypt needs 2^(work factor + 10) bytes
+/// of memory.
+pub const MAX_WORK_FACTOR: u8 = 22;
+
+/// Size of the plaintext chunks of the payload.
+const CHUNK_SIZE: usize = 64 * 1024;
+
+/// Size of the authentication tag following each encrypted chunk.
+const TAG_SIZE: usize = 16;
+
+/// Columns of the base64 lines of the header.
+const COLUMNS: usize = 64;
+
+/// An X25519 public key that archives are encrypted to.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct Recipient([u8; 32]);
+
+/// An X25519 secret key that decrypts the archives encrypted to its
+/// [`Recipient`].
+#[derive(Clone)]
+pub struct Identity([u8; 32]);
+
+impl FromStr for Recipient {
+    type Err = BackupError;
+
+    /// Parses a recipient such as `age1...`.
+    fn from_str(text: &str) -> Result<Self, BackupError> {
+        crypto::bech32_decode(text)
+            .filter(|(hrp, _)| hrp == RECIPIENT_HRP && !text.starts_with("AGE"))
+            .and_then(|(_, key)| key.try_into().ok())
+            .map(Recipient)
+            .ok_or_else(|| {
+                BackupError::InvalidOption(format!(
+                    "Invalid age recipient '{text}', expected a public key starting with age1"
+                ))
+            })
+    }
+}
+
+impl fmt::Display for Recipient {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&crypto::bech32_encode(RECIPIENT_HRP, &self.0))
+    }
+}
+
+impl fmt::Debug for Recipient {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Recipient({self})")
+    }
+}
+
+impl Identity {
+    /// Parses an identity such as `AGE-SECRET-KEY-1...`, returning `None` if
+    /// it is not one.
+    pub fn parse(text: &str) -> Option<Self> {
+        crypto::bech32_decode(text)
+            .filter(|(hrp, _)| hrp == IDENTITY_HRP)
+            .and_then(|(_, key)| key.try_into().ok())
+            .map(Identity)
+    }
+
+    /// Reads the identities of the file at `path`, one per line, as written
+    /// by `age-keygen`. Blank lines and lines starting with `#` are ignored.
+    pub fn load(path: &Path) -> Result<Vec<Self>, BackupError> {
+        let text = fs::read_to_string(path).map_err(|source| BackupError::ReadFailed {
+            path: path.to_path_buf(),
+            source,
+        })?;
+        text.lines()
+            .enumerate()
+            .map(|(i, line)| (i + 1, line.trim()))
+            .filter(|(_, line)| !line.is_empty() && !line.starts_with('#'))
+            .map(|(line, text)| {
+                Identity::parse(text).ok_or_else(|| BackupError::Config {
+                    path: path.to_path_buf(),
+                    line,
+                    message: "Expected an age identity, AGE-SECRET-KEY-1...".to_owned(),
+                })
+            })
+            .collect()
+    }
+
+    /// Returns the recipient that archives decrypted by this identity are
+    /// encrypted to.
+    pub fn recipient(&self) -> Recipient {
+        Recipient(crypto::x25519(&self.0, &crypto::BASEPOINT))
+    }
+}
+
+impl fmt::Debug for Identity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("Identity").field(&self.recipient()).finish()
+    }
+}
+
+/// A passphrase that archives are encrypted with.
+#[derive(Clone, PartialEq, Eq)]
+pub struct Passphrase(String);
+
+impl Passphrase {
+    /// Wraps `text`, failing if it is empty.
+    pub fn new(text: String) -> Result<Self, BackupError> {
+        match text.is_empty() {
+            true => Err(BackupError::InvalidOption(
+                "The passphrase is empty".to_owned(),
+            )),
+            false => Ok(Passphrase(text)),
+        }
+    }
+
+    /// Derives the key wrapping the file key from the passphrase and `salt`.
+    fn key(&self, salt: &[u8], work_factor: u8) -> [u8; 32] {
+        let salt = [SCRYPT_LABEL, salt].concat();
+        crypto::scrypt(self.0.as_bytes(), &salt, work_factor, 8, 1)
+    }
+}
+
+impl fmt::Debug for Passphrase {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Passphrase(..)")
+    }
+}
+
+/// Why an encrypted file cannot be decrypted with the keys given.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Locked {
+    /// The file is encrypted to none of the identities.
+    NoIdentity,
+    /// The file is encrypted with a passphrase and none was given.
+    NoPassphrase,
+    /// The file is encrypted with another passphrase.
+    WrongPassphrase,
+}
+
+/// Checks whether the file at `path` is encrypted with a passphrase, rather
+/// than to recipients.
+pub fn needs_passphrase(path: &Path) -> bool {
+    let mut header = Vec::new();
+    split::open(path)
+        .and_then(|file| {
+            BufReader::new(file.take(1024))
+                .take((MAGIC.len() + 10) as u64)
+                .read_to_end(&mut header)
+        })
+        .is_ok_and(|_| {
+            header
+                .strip_prefix(MAGIC)
+                .is_some_and(|rest| rest.starts_with(b"-> scrypt "))
+        })
+}
+
+/// Checks whether `header`, the first bytes of a file or stream, starts an
+/// encrypted file.
+pub(crate) fn detect(header: &[u8]) -> bool {
+    header.starts_with(MAGIC)
+}
+
+/// Checks whether the file at `path` is encrypted.
+pub fn is_encrypted(path: &Path) -> bool {
+    let mut header = Vec::with_capacity(MAGIC.len());
+    split::open(path)
+        .and_then(|file| file.take(MAGIC.len() as u64).read_to_end(&mut header))
+        .is_ok_and(|_| detect(&header))
+}
+
+/// Derives the key of the chunks of the payload from the file key and the
+/// nonce starting the payload.
+fn payload_key(file_key: &[u8; 16], nonce: &[u8; 16]) -> [u8; 32] {
+    crypto::hkdf_sha256(file_key, nonce, b"payload")
+}
+
+/// Computes the MAC of the header, which authenticates it with the file key.
+fn header_mac(file_key: &[u8; 16], header: &[u8]) -> [u8; 32] {
+    let key = crypto::hkdf_sha256(file_key, &[], b"header");
+    crypto::hmac_sha256(&key, header)
+}
+
+/// Returns the nonce of the chunk numbered `counter`, flagged if last.
+fn chunk_nonce(counter: u64, last: bool) -> [u8; 12] {
+    let mut nonce = [0; 12];
+    nonce[3..11].copy_from_slice(&counter.to_be_bytes());
+    nonce[11] = u8::from(last);
+    nonce
+}
+
+/// Formats the stanza of the header wrapping `file_key` in `body`, sealed
+/// with `key`, after its `arguments`.
+fn stanza(arguments: &str, key: &[u8; 32], file_key: &[u8; 16]) -> String {
+    let body = crypto::seal(key, &[0; 12], &[], file_key);
+    format!("-> {arguments}\n{}", wrap(&crypto::base64_encode(&body)))
+}
+
+/// Wraps `text` in lines of [`COLUMNS`], the last one always shorter.
+fn wrap(text: &str) -> String {
+    let mut wrapped = String::new();
+    let mut rest = text;
+    loop {
+        let (line, tail) = rest.split_at(rest.len().min(COLUMNS));
+        wrapped.push_str(line);
+        wrapped.push('\n');
+        if line.len() < COLUMNS {
+            return wrapped;
+        }
+        rest = tail;
+    }
+}
+
+/// Writer encrypting what is written to it into the inner writer.
+///
+/// The header is written when created, and the last chunk by
+/// [`Encryptor::finish`], without which the output cannot be decrypted.
+pub(crate) struct Encryptor<W: Write> {
+    inner: W,
+    key: [u8; 32],
+    counter: u64,
+    buffer: Vec<u8>,
+}
+
+impl<W: Write> Encryptor<W> {
+    /// Writes the header wrapping a new file key for each of `recipients`
+    /// into `inner`.
+    pub(crate) fn new(inner: W, recipients: &[Recipient]) -> io::Result<Self> {
+        let mut file_key = [0; 16];
+        crypto::random(&mut file_key)?;
+
+        let mut stanzas = String::new();
+        for recipient in recipients {
+            let mut ephemeral = [0; 32];
+            crypto::random(&mut ephemeral)?;
+            let share = crypto::x25519(&ephemeral, &crypto::BASEPOINT);
+            let shared = crypto::x25519(&ephemeral, &recipient.0);
+            let salt = [share, recipient.0].concat();
+            let key = crypto::hkdf_sha256(&shared, &salt, X25519_LABEL);
+            let arguments = format!("X25519 {}", crypto::base64_encode(&share));
+            stanzas.push_str(&stanza(&arguments, &key, &file_key));
+        }
+        Self::start(inner, &file_key, &stanzas)
+    }
+
+    /// Writes the header wrapping a new file key with `passphrase`, at a
+    /// scrypt cost of 2^`work_factor`, into `inner`.
+    pub(crate) fn with_passphrase(
+        inner: W,
+        passphrase: &Passphrase,
+        work_factor: u8,
+    ) -> io::Result<Self> {
+        let mut file_key = [0; 16];
+        crypto::random(&mut file_key)?;
+        let mut salt = [0; 16];
+        crypto::random(&mut salt)?;
+        let key = passphrase.key(&salt, work_factor);
+        let arguments = format!("scrypt {} {work_factor}", crypto::base64_encode(&salt));
+        Self::start(inner, &file_key, &stanza(&arguments, &key, &file_key))
+    }
+
+    /// Writes the header made of `stanzas` and authenticated with
+    /// `file_key`, then the nonce of the payload, into `inner`.
+    fn start(mut inner: W, file_key: &[u8; 16], stanzas: &str) -> io::Result<Self> {
+        let mut header = String::from_utf8_lossy(MAGIC).into_owned();
+        header.push_str(stanzas);
+        header.push_str("---");
+        let mac = header_mac(file_key, header.as_bytes());
+        header.push_str(&format!(" {}\n", crypto::base64_encode(&mac)));
+
+        let mut nonce = [0; 16];
+        crypto::random(&mut nonce)?;
+        inner.write_all(header.as_bytes())?;
+        inner.write_all(&nonce)?;
+        Ok(Encryptor {
+            inner,
+            key: payload_key(file_key, &nonce),
+            counter: 0,
+            buffer: Vec::with_capacity(CHUNK_SIZE),
+        })
+    }
+
+    /// Encrypts the buffered chunk into the inner writer.
+    fn flush_chunk(&mut self, last: bool) -> io::Result<()> {
+        let nonce = chunk_nonce(self.counter, last);
+        let sealed = crypto::seal(&self.key, &nonce, &[], &self.buffer);
+        self.inner.write_all(&sealed)?;
+        self.buffer.clear();
+        self.counter += 1;
+        Ok(())
+    }
+
+    /// Writes the last chunk and returns the inner writer.
+    pub(crate) fn finish(mut self) -> io::Result<W> {
+        self.flush_chunk(true)?;
+        Ok(self.inner)
+    }
+}
+
+impl<W: Write> Write for Encryptor<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        // A full chunk is only written once more data follows, as the last
+        // one is flagged.
+        if self.buffer.len() == CHUNK_SIZE && !buf.is_empty() {
+            self.flush_chunk(false)?;
+        }
+        let len = buf.len().min(CHUNK_SIZE - self.buffer.len());
+        self.buffer.extend_from_slice(&buf[..len]);
+        Ok(len)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Reader decrypting an encrypted file read from the inner reader.
+pub(crate) struct Decryptor<R: Read> {
+    inner: BufReader<R>,
+    key: [u8; 32],
+    counter: u64,
+    chunk: Vec<u8>,
+    position: usize,
+    done: bool,
+}
+
+impl<R: Read> Decryptor<R> {
+    /// Reads the header from `inner` and unwraps the file key with one of
+    /// `identities`, or with `passphrase` if encrypted with one, returning
+    /// why it cannot be if not.
+    pub(crate) fn new(
+        inner: R,
+        identities: &[Identity],
+        passphrase: Option<&Passphrase>,
+    ) -> io::Result<Result<Self, Locked>> {
+        let invalid =
+            |message: &str| io::Error::new(io::ErrorKind::InvalidData, message.to_owned());
+        let mut inner = BufReader::new(inner);
+        let mut header = Vec::new();
+        let mut read_line = |header: &mut Vec<u8>| -> io::Result<String> {
+            let start = header.len();
+            inner.by_ref().take(1024).read_until(b'\n', header)?;
+            let line = header[start..]
+                .strip_suffix(b"\n")
+                .ok_or_else(|| invalid("truncated age header"))?;
+            String::from_utf8(line.to_vec()).map_err(|_| invalid("invalid age header"))
+        };
+
+        if read_line(&mut header)?.as_bytes() != &MAGIC[..MAGIC.len() - 1] {
+            return Err(invalid("not an age file"));
+        }
+        let mut stanzas = Vec::new();
+        let mac = loop {
+            let line = read_line(&mut header)?;
+            if let Some(mac) = line.strip_prefix("--- ") {
+                break mac.to_owned();
+            }
+            let arguments = line
+                .strip_prefix("-> ")
+                .ok_or_else(|| invalid("invalid age header"))?;
+            let arguments: Vec<_> = arguments.split(' ').map(str::to_owned).collect();
+            let mut body = String::new();
+            loop {
+                let line = read_line(&mut header)?;
+                body.push_str(&line);
+                if line.len() < COLUMNS {
+                    break;
+                }
+            }
+            stanzas.push((arguments, body));
+        };
+
+        let scrypt = stanzas
+            .iter()
+            .any(|(arguments, _)| arguments.first().is_some_and(|kind| kind == "scrypt"));
+        let file_key = match stanzas.as_slice() {
+            // A passphrase must be the only way to unwrap the file key.
+            _ if !scrypt => stanzas.iter().find_map(|(arguments, body)| {
+                let [kind, share] = arguments.as_slice() else {
+                    return None;
+                };
+                let share: [u8; 32] = crypto::base64_decode(share)?.try_into().ok()?;
+                let body = crypto::base64_decode(body)?;
+                (kind == "X25519").then_some(())?;
+                identities.iter().find_map(|identity| {
+                    let shared = crypto::x25519(&identity.0, &share);
+                    if shared == [0; 32] {
+                        return None;
+                    }
+                    let salt = [share, identity.recipient().0].concat();
+                    let key = crypto::hkdf_sha256(&shared, &salt, X25519_LABEL);
+                    crypto::open(&key, &[0; 12], &[], &body)?.try_into().ok()
+                })
+            }),
+            [(arguments, body)] => {
+                let [_, salt, work_factor] = arguments.as_slice() else {
+                    return Err(invalid("invalid age header"));
+                };
+                let salt = crypto::base64_decode(salt).filter(|salt| salt.len() == 16);
+                let work_factor = work_factor
+                    .parse::<u8>()
+                    .ok()
+                    .filter(|&factor| (1..=MAX_WORK_FACTOR).contains(&factor));
+                let body = crypto::base64_decode(body);
+                let (Some(salt), Some(work_factor), Some(body)) = (salt, work_factor, body) else {
+                    return Err(invalid("invalid or too costly age passphrase stanza"));
+                };
+                let Some(passphrase) = passphrase else {
+                    return Ok(Err(Locked::NoPassphrase));
+                };
+                let key = passphrase.key(&salt, work_factor);
+                match crypto::open(&key, &[0; 12], &[], &body) {
+                    Some(file_key) => file_key.try_into().ok(),
+                    None => return Ok(Err(Locked::WrongPassphrase)),
+                }
+            }
+            _ => return Err(invalid("invalid age header")),
+        };
+        let Some(file_key) = file_key else {
+            return Ok(Err(Locked::NoIdentity));
+        };
+
+        // The MAC covers the header up to and including the "---" mark.
+        let covered = header.len() - mac.len() - 2;
+        let mac = crypto::base64_decode(&mac).ok_or_else(|| invalid("invalid age header"))?;
+        if !crypto::equal(&header_mac(&file_key, &header[..covered]), &mac) {
+            return Err(invalid("the age header was modified"));
+        }
+
+        let mut nonce = [0; 16];
+        inner.read_exact(&mut nonce)?;
+        Ok(Ok(Decryptor {
+            inner,
+            key: payload_key(&file_key, &nonce),
+            counter: 0,
+            chunk: Vec::new(),
+            position: 0,
+            done: false,
+        }))
+    }
+
+    /// Reads and decrypts the next chunk.
+    fn next_chunk(&mut self) -> io::Result<()> {
+        let mut sealed = Vec::with_capacity(CHUNK_SIZE + TAG_SIZE);
+        self.inner
+            .by_ref()
+            .take((CHUNK_SIZE + TAG_SIZE) as u64)
+            .read_to_end(&mut sealed)?;
+        let last = sealed.len() < CHUNK_SIZE + TAG_SIZE || self.inner.fill_buf()?.is_empty();
+        let nonce = chunk_nonce(self.counter, last);
+        self.chunk = crypto::open(&self.key, &nonce, &[], &sealed).ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                "the encrypted archive is truncated or was modified",
+            )
+        })?;
+        if self.chunk.is_empty() && self.counter > 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "the encrypted archive ends with an empty chunk",
+            ));
+        }
+        self.position = 0;
+        self.counter += 1;
+        self.done = last;
+        Ok(())
+    }
+}
+
+impl<R: Read> Read for Decryptor<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.position == self.chunk.len() {
+            if self.done {
+                return Ok(0);
+            }
+            self.next_chunk()?;
+        }
+        let len = buf.len().min(self.chunk.len() - self.position);
+        buf[..len].copy_from_slice(&self.chunk[self.position..self.position + len]);
+        self.position += len;
+        Ok(len)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Key pair whose secret key is 32 bytes of 0x42.
+    const IDENTITY: &str =
+        "AGE-SECRET-KEY-1GFPYYSJZGFPYYSJZGFPYYSJZGFPYYSJZGFPYYSJZGFPYYSJZGFPQ4EGAEX";
+    const RECIPIENT: &str = "age1zvkyg2lqzraa2lnjvqej32nkuu0ues2s82hzrye869xeexvn73equnujwj";
+
+    fn encrypt(data: &[u8], recipients: &[Recipient]) -> Vec<u8> {
+        let mut encryptor = Encryptor::new(Vec::new(), recipients).unwrap();
+        encryptor.write_all(data).unwrap();
+        encryptor.finish().unwrap()
+    }
+
+    fn decrypt(encrypted: &[u8], identities: &[Identity]) -> io::Result<Option<Vec<u8>>> {
+        let Ok(mut decryptor) = Decryptor::new(encrypted, identities, None)? else {
+            return Ok(None);
+        };
+        let mut data = Vec::new();
+        decryptor.read_to_end(&mut data)?;
+        Ok(Some(data))
+    }
+
+    #[test]
+    fn keys_use_the_encodings_of_age() {
+        let identity = Identity::parse(IDENTITY).unwrap();
+        assert_eq!(identity.0, [0x42; 32]);
+        assert_eq!(identity.recipient().to_string(), RECIPIENT);
+        assert_eq!(
+            RECIPIENT.parse::<Recipient>().unwrap(),
+            identity.recipient()
+        );
+        assert!(format!("{identity:?}").contains(RECIPIENT));
+        assert!(!format!("{identity:?}").contains("GFPYY"));
+
+        assert!(IDENTITY.parse::<Recipient>().is_err());
+        assert!(Identity::parse(RECIPIENT).is_none());
+        assert!("age1invalid".parse::<Recipient>().is_err());
+    }
+
+    #[test]
+    fn archives_round_trip_across_chunks() {
+        let identity = Identity::parse(IDENTITY).unwrap();
+        let other = Identity([7; 32]);
+        let recipients = [other.recipient(), identity.recipient()];
+        for len in [0, 1, CHUNK_SIZE, CHUNK_SIZE + 1, 3 * CHUNK_SIZE] {
+            let data: Vec<u8> = (0..len).map(|i| (i % 251) as u8).collect();
+            let encrypted = encrypt(&data, &recipients);
+            assert!(detect(&encrypted));
+            assert_eq!(
+                decrypt(&encrypted, std::slice::from_ref(&identity))
+                    .unwrap()
+                    .unwrap(),
+                data
+            );
+            assert_eq!(
+                decrypt(&encrypted, std::slice::from_ref(&other))
+                    .unwrap()
+                    .unwrap(),
+                data
+            );
+        }
+    }
+
+    #[test]
+    fn wrong_keys_and_tampering_are_detected() {
+        let identity = Identity::parse(IDENTITY).unwrap();
+        let encrypted = encrypt(b"secret", &[identity.recipient()]);
+        assert!(decrypt(&encrypted, &[Identity([7; 32])]).unwrap().is_none());
+        assert!(decrypt(&encrypted, &[]).unwrap().is_none());
+
+        let mut tampered = encrypted.clone();
+        let last = tampered.len() - 1;
+        tampered[last] ^= 1;
+        assert!(decrypt(&tampered, std::slice::from_ref(&identity)).is_err());
+
+        let truncated = &encrypted[..encrypted.len() - 1];
+        assert!(decrypt(truncated, &[identity]).is_err());
+    }
+
+    #[test]
+    fn passphrases_unwrap_the_file_key() {
+        let passphrase = Passphrase::new("correct horse".to_owned()).unwrap();
+        let mut encryptor = Encryptor::with_passphrase(Vec::new(), &passphrase, 2).unwrap();
+        encryptor.write_all(b"secret").unwrap();
+        let encrypted = encryptor.finish().unwrap();
+        assert!(String::from_utf8_lossy(&encrypted).contains("\n-> scrypt "));
+        assert!(format!("{passphrase:?}").contains(".."));
+        assert!(!format!("{passphrase:?}").contains("horse"));
+
+        let mut decryptor = Decryptor::new(&encrypted[..], &[], Some(&passphrase))
+            .unwrap()
+            .unwrap();
+        let mut data = Vec::new();
+        decryptor.read_to_end(&mut data).unwrap();
+        assert_eq!(data, b"secret");
+
+        let wrong = Passphrase::new("wrong horse".to_owned()).unwrap();
+        let locked = |passphrase| match Decryptor::new(&encrypted[..], &[], passphrase) {
+            Ok(Err(locked)) => Some(locked),
+            _ => None,
+        };
+        assert_eq!(locked(Some(&wrong)), Some(Locked::WrongPassphrase));
+        assert_eq!(locked(None), Some(Locked::NoPassphrase));
+        assert!(Passphrase::new(String::new()).is_err());
+
+        let mut tampered = encrypted.clone();
+        let last = tampered.len() - 1;
+        tampered[last] ^= 1;
+        let mut decryptor = Decryptor::new(&tampered[..], &[], Some(&passphrase))
+            .unwrap()
+            .unwrap();
+        assert!(decryptor.read_to_end(&mut Vec::new()).is_err());
+    }
+
+    #[test]
+    fn header_lines_are_wrapped_at_64_columns() {
+        assert_eq!(wrap(""), "\n");
+        assert_eq!(wrap(&"a".repeat(64)), format!("{}\n\n", "a".repeat(64)));
+        assert_eq!(
+            wrap(&"a".repeat(70)),
+            format!("{}\n{}\n", "a".repeat(64), "a".repeat(6))
+        );
+    }
+}
@@ -0,0 +1,238 @@
+//! Reading of the tar archives that directories are backed up to.
+
+use std::collections::HashMap;
+use std::io::{self, Read};
+use std::path::{Component, Path, PathBuf};
+
+use chrono::{Local, TimeZone};
+use flate2::read::GzDecoder;
+
+use crate::backup::Compression;
+use crate::list::{ContentEntry, EntryKind};
+use crate::split;
+
+/// Leading bytes of a gzip stream.
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+
+/// Leading bytes of a zstd frame (0xFD2FB528, little endian).
+const ZSTD_MAGIC: [u8; 4] = [0x28, 0xb5, 0x2f, 0xfd];
+
+/// Detects whether `path` is an archive by its magic bytes, returning its compression.
+///
+/// Compressed files are assumed to wrap a tar archive.
+pub fn format(path: &Path) -> Option<Compression> {
+    let mut header = Vec::with_capacity(512);
+    split::open(path)
+        .and_then(|file| file.take(512).read_to_end(&mut header))
+        .ok()?;
+    detect(&header)
+}
+
+/// Detects whether `header`, the first 512 bytes of a file or stream or as
+/// many as there are, starts an archive, returning its compression.
+pub(crate) fn detect(header: &[u8]) -> Option<Compression> {
+    if header.starts_with(&GZIP_MAGIC) {
+        Some(Compression::Gzip(Compression::GZIP_DEFAULT_LEVEL))
+    } else if header.starts_with(&ZSTD_MAGIC) {
+        Some(Compression::Zstd(Compression::ZSTD_DEFAULT_LEVEL))
+    } else if header.get(257..262) == Some(b"ustar") {
+        Some(Compression::None)
+    } else {
+        None
+    }
+}
+
+/// Opens the tar archive at `path`, decompressing it with `compression`.
+pub(crate) fn open(
+    path: &Path,
+    compression: Compression,
+) -> io::Result<tar::Archive<Box<dyn Read>>> {
+    read(split::open(path)?, compression)
+}
+
+/// Reads a tar archive from `reader`, decompressing it with `compression`.
+pub(crate) fn read<'a>(
+    reader: impl Read + 'a,
+    compression: Compression,
+) -> io::Result<tar::Archive<Box<dyn Read + 'a>>> {
+    let reader: Box<dyn Read + 'a> = match compression {
+        Compression::None => Box::new(reader),
+        Compression::Gzip(_) => Box::new(GzDecoder::new(reader)),
+        Compression::Zstd(_) => Box::new(zstd::Decoder::new(reader)?),
+    };
+    Ok(tar::Archive::new(reader))
+}
+
+/// Strips the `.` components of an archive entry path, leaving the path
+/// relative to the archive root, which is itself empty.
+pub(crate) fn relative_path(path: &Path) -> PathBuf {
+    path.components()
+        .filter(|component| *component != Component::CurDir)
+        .collect()
+}
+
+/// Describes each entry of the archive at `path` and hands it to `visit`
+/// along with its contents, in archive order.
+///
+/// Hard links are described as files with the size of the file they link
+/// to, named as their link, and no contents of their own.
+pub(crate) fn for_each(
+    path: &Path,
+    compression: Compression,
+    mut visit: impl FnMut(ContentEntry, &mut dyn Read) -> io::Result<()>,
+) -> io::Result<()> {
+    let mut archive = open(path, compression)?;
+    let mut sizes = HashMap::new();
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        let header = entry.header();
+        let entry_type = header.entry_type();
+        let kind = if entry_type.is_dir() {
+            EntryKind::Directory
+        } else if entry_type.is_symlink() {
+            EntryKind::Link
+        } else if entry_type.is_file() || entry_type.is_contiguous() || entry_type.is_hard_link() {
+            EntryKind::File
+        } else {
+            EntryKind::Other
+        };
+        let path = relative_path(&entry.path()?);
+        let mut size = header.size()?;
+        let mut link = entry.link_name()?.map(|link| link.into_owned());
+        if entry_type.is_hard_link() {
+            link = link.map(|link| relative_path(&link));
+            size = link
+                .as_ref()
+                .and_then(|link| sizes.get(link))
+                .copied()
+                .unwrap_or(0);
+        } else if kind == EntryKind::File {
+            sizes.insert(path.clone(), size);
+        }
+        let description = ContentEntry {
+            path,
+            kind,
+            size,
+            mode: header.mode()? & 0o7777,
+            modified: Local.timestamp_opt(header.mtime()? as i64, 0).single(),
+            link,
+        };
+        visit(description, &mut entry)?;
+    }
+    Ok(())
+}
+
+/// Lists the entries of the archive at `path`, in archive order.
+pub(crate) fn contents(path: &Path, compression: Compression) -> io::Result<Vec<ContentEntry>> {
+    let mut contents = Vec::new();
+    for_each(path, compression, |entry, _| {
+        contents.push(entry);
+        Ok(())
+    })?;
+    Ok(contents)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::{self, File};
+
+    use flate2::write::GzEncoder;
+
+    use super::*;
+
+    /// Writes a gzip compressed archive holding a directory, a file and a link.
+    fn sample(dir: &Path) -> PathBuf {
+        let path = dir.join("sample.tar.gz");
+        let encoder = GzEncoder::new(File::create(&path).unwrap(), Default::default());
+        let mut builder = tar::Builder::new(encoder);
+
+        let mut header = tar::Header::new_gnu();
+        header.set_entry_type(tar::EntryType::Directory);
+        header.set_mode(0o755);
+        header.set_mtime(1_700_000_000);
+        header.set_size(0);
+        builder
+            .append_data(&mut header, "./src", io::empty())
+            .unwrap();
+
+        let mut header = tar::Header::new_gnu();
+        header.set_mode(0o644);
+        header.set_mtime(1_700_000_000);
+        header.set_size(5);
+        builder
+            .append_data(&mut header, "./src/main.rs", &b"fn m;"[..])
+            .unwrap();
+
+        let mut header = tar::Header::new_gnu();
+        header.set_entry_type(tar::EntryType::Symlink);
+        header.set_mode(0o777);
+        header.set_mtime(1_700_000_000);
+        header.set_size(0);
+        builder
+            .append_link(&mut header, "./latest", "src/main.rs")
+            .unwrap();
+
+        builder.into_inner().unwrap().finish().unwrap();
+        path
+    }
+
+    #[test]
+    fn formats_are_detected_by_magic_bytes() {
+        let tmp = tempfile::TempDir::new().unwrap();
+        let archive = sample(tmp.path());
+        let text = tmp.path().join("notes.tar.gz");
+        fs::write(&text, "not an archive").unwrap();
+
+        assert_eq!(
+            format(&archive),
+            Some(Compression::Gzip(Compression::GZIP_DEFAULT_LEVEL))
+        );
+        assert_eq!(format(&text), None);
+        assert_eq!(format(&tmp.path().join("missing")), None);
+    }
+
+    #[test]
+    fn entries_are_described_relative_to_the_root() {
+        let tmp = tempfile::TempDir::new().unwrap();
+        let archive = sample(tmp.path());
+
+        let contents = contents(&archive, format(&archive).unwrap()).unwrap();
+        let described: Vec<_> = contents
+            .iter()
+            .map(|entry| (entry.path.to_str().unwrap(), entry.kind, entry.size))
+            .collect();
+        assert_eq!(
+            described,
+            [
+                ("src", EntryKind::Directory, 0),
+                ("src/main.rs", EntryKind::File, 5),
+                ("latest", EntryKind::Link, 0),
+            ]
+        );
+        assert_eq!(contents[1].mode, 0o644);
+        assert_eq!(contents[1].modified.unwrap().timestamp(), 1_700_000_000);
+        assert_eq!(contents[2].link.as_deref(), Some(Path::new("src/main.rs")));
+    }
+
+    #[test]
+    fn contents_are_visited_along_with_entries() {
+        let tmp = tempfile::TempDir::new().unwrap();
+        let archive = sample(tmp.path());
+
+        let mut read = String::new();
+        for_each(&archive, Compression::Gzip(6), |entry, contents| {
+            if entry.kind == EntryKind::File {
+                contents.read_to_string(&mut read)?;
+            }
+            Ok(())
+        })
+        .unwrap();
+        assert_eq!(read, "fn m;");
+    }
+
+    #[test]
+    fn relative_paths_drop_current_directory_components() {
+        assert_eq!(relative_path(Path::new("./a/./b")), Path::new("a/b"));
+        assert_eq!(relative_path(Path::new(".")), Path::new(""));
+    }
+}
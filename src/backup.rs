@@ -0,0 +1,4415 @@
+//! Creation of timestamped backups.
+
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::ffi::{OsStr, OsString};
+use std::fmt;
+use std::fs::{self, File, Metadata};
+use std::io::{self, BufWriter, IntoInnerError, Read, Write};
+use std::num::NonZeroUsize;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use chrono::{DateTime, Local, Utc};
+use filetime::FileTime;
+use flate2::write::GzEncoder;
+use serde::{Deserialize, Serialize};
+
+use crate::age::{self, Encryptor, Passphrase, Recipient};
+use crate::catalog;
+use crate::checksum::{self, Algorithm, HashingWriter};
+use crate::error::BackupError;
+use crate::exclude::Excludes;
+use crate::interrupt::{self, Interruptible};
+use crate::list;
+use crate::lock::Lock;
+use crate::meta::{self, BackupMeta};
+use crate::naming;
+use crate::pipe::Pipe;
+use crate::reflink;
+use crate::remote::Remote;
+use crate::restore;
+use crate::s3::Bucket;
+use crate::sign::{self, SigningKey};
+use crate::split::{self, SplitWriter};
+use crate::stats::Stats;
+use crate::store::{Store, Upload};
+use crate::target::{Target, TargetStore};
+use crate::throttle::{Throttle, Throttled};
+use crate::walk::{self, Entry, Walker};
+use crate::writer::{self, Level};
+use crate::xattrs;
+
+/// Format of the timestamp embedded in backup names.
+pub const TIMESTAMP_FORMAT: &str = "%Y-%m-%d_%H-%M-%S";
+
+/// Extension appended to every backup name.
+pub const BACKUP_EXTENSION: &str = "backup";
+
+/// Size of the buffer files are copied through when they cannot be cloned or
+/// copied by the kernel, see [`BackupOptions::buffer_size`].
+pub const DEFAULT_BUFFER_SIZE: usize = 1 << 20;
+
+/// Name of the file marking the root of a mirror, holding the absolute path
+/// of its source, see [`BackupOptions::mirror`].
+pub const MIRROR_MARKER: &str = ".backup-mirror";
+
+/// The kind of backup to perform, derived from the source and target paths.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BackupType {
+    /// Source is a file and target is an existing file, or a missing path
+    /// written as a file.
+    FileFile,
+    /// Source is a file and target is a directory, created if missing.
+    FileDirectory,
+    /// Source and target are both directories, the target created if missing.
+    DirectoryDirectory,
+    /// Source is a directory and target is an existing file, or a missing
+    /// path written as a file.
+    DirectoryFile,
+}
+
+/// Whether copied files are cloned, sharing their contents with the source
+/// until either is modified, on filesystems that allow it.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Reflink {
+    /// Clone when possible, otherwise copy.
+    #[default]
+    Auto,
+    /// Always clone, failing where the filesystem cannot.
+    Always,
+    /// Always copy the contents.
+    Never,
+}
+
+impl Reflink {
+    /// Parses the `--reflink` name.
+    pub fn parse(name: &str) -> Result<Self, BackupError> {
+        match name {
+            "auto" => Ok(Reflink::Auto),
+            "always" => Ok(Reflink::Always),
+            "never" => Ok(Reflink::Never),
+            other => Err(BackupError::InvalidOption(format!(
+                "Unknown reflink mode '{other}', expected auto, always or never"
+            ))),
+        }
+    }
+}
+
+/// What is flushed to disk before a backup is renamed into place, so that a
+/// crash does not leave a backup looking complete with missing contents.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Fsync {
+    /// Leave writing to the operating system.
+    None,
+    /// Flush each file written.
+    #[default]
+    File,
+    /// Flush each file written and the directories holding them, including
+    /// the one the backup is renamed in.
+    Dir,
+}
+
+impl Fsync {
+    /// Parses the `--fsync` name.
+    pub fn parse(name: &str) -> Result<Self, BackupError> {
+        match name {
+            "none" => Ok(Fsync::None),
+            "file" => Ok(Fsync::File),
+            "dir" => Ok(Fsync::Dir),
+            other => Err(BackupError::InvalidOption(format!(
+                "Unknown fsync policy '{other}', expected none, file or dir"
+            ))),
+        }
+    }
+}
+
+/// What happens to special files, such as named pipes, sockets and devices,
+/// met while backing up a directory.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SpecialFiles {
+    /// Leave them out with a warning.
+    #[default]
+    Skip,
+    /// Fail on each of them.
+    Fail,
+    /// Recreate them in copies and store them in archives, which for devices
+    /// requires root. Sockets cannot be archived and are still left out.
+    Record,
+}
+
+impl SpecialFiles {
+    /// Parses the `--special-files` name.
+    pub fn parse(name: &str) -> Result<Self, BackupError> {
+        match name {
+            "skip" => Ok(SpecialFiles::Skip),
+            "fail" => Ok(SpecialFiles::Fail),
+            "record" => Ok(SpecialFiles::Record),
+            other => Err(BackupError::InvalidOption(format!(
+                "Unknown special files handling '{other}', expected skip, fail or record"
+            ))),
+        }
+    }
+}
+
+/// Compression applied to directory archives.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Compression {
+    /// Plain tar archive.
+    #[default]
+    None,
+    /// Gzip-compressed tar archive with the given level (1-9).
+    Gzip(u32),
+    /// Zstandard-compressed tar archive with the given level (1-19).
+    Zstd(u32),
+}
+
+impl Compression {
+    /// Default gzip compression level.
+    pub const GZIP_DEFAULT_LEVEL: u32 = 6;
+
+    /// Default zstd compression level.
+    pub const ZSTD_DEFAULT_LEVEL: u32 = 3;
+
+    /// Names accepted by [`Compression::resolve`], one per kind.
+    pub const NAMES: [&'static str; 3] = ["none", "gzip", "zstd"];
+
+    /// Resolves the compression for a backup from the `--compress` name and
+    /// `--level`, inferring it from the target extension when no name is given.
+    pub fn resolve(
+        name: Option<&str>,
+        level: Option<u32>,
+        target: &Path,
+    ) -> Result<Self, BackupError> {
+        let target = target.to_string_lossy();
+        let name = name.or_else(|| {
+            if target.ends_with(".tar.gz") || target.ends_with(".tgz") {
+                Some("gzip")
+            } else if target.ends_with(".tar.zst") || target.ends_with(".tzst") {
+                Some("zstd")
+            } else {
+                None
+            }
+        });
+
+        match name {
+            None | Some("none") => Ok(Compression::None),
+            Some("gzip" | "gz") => {
+                let level = level.unwrap_or(Self::GZIP_DEFAULT_LEVEL);
+                if !(1..=9).contains(&level) {
+                    return Err(BackupError::InvalidOption(format!(
+                        "Invalid gzip level {level}, expected 1-9"
+                    )));
+                }
+                Ok(Compression::Gzip(level))
+            }
+            Some("zstd" | "zst") => {
+                let level = level.unwrap_or(Self::ZSTD_DEFAULT_LEVEL);
+                if !(1..=19).contains(&level) {
+                    return Err(BackupError::InvalidOption(format!(
+                        "Invalid zstd level {level}, expected 1-19"
+                    )));
+                }
+                Ok(Compression::Zstd(level))
+            }
+            Some(other) => Err(BackupError::InvalidOption(format!(
+                "Unknown compression '{other}'"
+            ))),
+        }
+    }
+
+    /// Extension appended to archives created with this compression.
+    pub fn extension(self) -> &'static str {
+        match self {
+            Compression::None => "tar",
+            Compression::Gzip(_) => "tar.gz",
+            Compression::Zstd(_) => "tar.zst",
+        }
+    }
+}
+
+/// Settings of a backup, built by chaining setters on [`BackupOptions::new`].
+///
+/// The default options refuse to overwrite an existing destination, keep
+/// symbolic links as links, preserve modes and modification times and archive
+/// directories only when the target is a file, without compression.
+///
+/// # Examples
+///
+/// ```
+/// use backup::backup::{BackupOptions, Compression};
+///
+/// let options = BackupOptions::new()
+///     .force(true)
+///     .compress(Compression::Zstd(3))
+///     .exclude("*.tmp");
+/// ```
+#[derive(Debug, Clone)]
+pub struct BackupOptions {
+    force: bool,
+    dereference: bool,
+    preserve: bool,
+    preserve_owner: bool,
+    xattrs: bool,
+    reflink: Reflink,
+    buffer_size: usize,
+    fsync: Fsync,
+    special_files: SpecialFiles,
+    compression: Compression,
+    recipients: Vec<Recipient>,
+    passphrase: Option<Passphrase>,
+    work_factor: u8,
+    signing_key: Option<SigningKey>,
+    split: Option<u64>,
+    excludes: Excludes,
+    ignore_files: bool,
+    exclude_caches: bool,
+    max_depth: Option<usize>,
+    max_files: Option<u64>,
+    min_file_size: Option<u64>,
+    max_file_size: Option<u64>,
+    as_file: bool,
+    checksum: Option<Algorithm>,
+    incremental: bool,
+    compare_checksums: bool,
+    skip_unchanged: bool,
+    mirror: bool,
+    delete: bool,
+    init: bool,
+    jobs: usize,
+    keep_going: bool,
+    retries: u32,
+    retry_delay: Duration,
+    throttle: Option<Arc<Throttle>>,
+    space_check: bool,
+    wait_lock: Duration,
+    /// Time the backup is named after, when not the time it starts.
+    created: Option<DateTime<Local>>,
+}
+
+impl Default for BackupOptions {
+    fn default() -> Self {
+        BackupOptions {
+            force: false,
+            dereference: false,
+            preserve: true,
+            preserve_owner: false,
+            xattrs: xattrs::enabled_by_default(),
+            reflink: Reflink::Auto,
+            buffer_size: DEFAULT_BUFFER_SIZE,
+            fsync: Fsync::File,
+            special_files: SpecialFiles::Skip,
+            compression: Compression::None,
+            recipients: Vec::new(),
+            passphrase: None,
+            work_factor: age::WORK_FACTOR,
+            signing_key: None,
+            split: None,
+            excludes: Excludes::new(),
+            ignore_files: true,
+            exclude_caches: false,
+            max_depth: None,
+            max_files: None,
+            min_file_size: None,
+            max_file_size: None,
+            as_file: false,
+            checksum: Some(Algorithm::Sha256),
+            incremental: false,
+            compare_checksums: false,
+            skip_unchanged: false,
+            mirror: false,
+            delete: false,
+            init: false,
+            jobs: thread::available_parallelism()
+                .map_or(1, NonZeroUsize::get)
+                .min(8),
+            keep_going: true,
+            retries: 0,
+            retry_delay: Duration::from_secs(1),
+            throttle: None,
+            space_check: true,
+            wait_lock: Duration::ZERO,
+            created: None,
+        }
+    }
+}
+
+impl BackupOptions {
+    /// Creates the default options.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Overwrites an existing destination.
+    pub fn force(mut self, force: bool) -> Self {
+        self.force = force;
+        self
+    }
+
+    /// Follows symbolic links and backs up their targets.
+    pub fn dereference(mut self, dereference: bool) -> Self {
+        self.dereference = dereference;
+        self
+    }
+
+    /// Preserves the mode and modification time of every file and directory.
+    pub fn preserve(mut self, preserve: bool) -> Self {
+        self.preserve = preserve;
+        self
+    }
+
+    /// Gives copies the owner and group of their source. Archives always
+    /// record them, whatever this setting.
+    pub fn preserve_owner(mut self, preserve_owner: bool) -> Self {
+        self.preserve_owner = preserve_owner;
+        self
+    }
+
+    /// Copies the extended attributes of every file and directory, which
+    /// hold their POSIX ACLs and SELinux labels, or records them in archives.
+    /// Enabled by default when running as root, on Linux only.
+    ///
+    /// A filesystem that does not support them, or attributes the process
+    /// may not set, only cause a warning.
+    pub fn xattrs(mut self, xattrs: bool) -> Self {
+        self.xattrs = xattrs;
+        self
+    }
+
+    /// Sets whether copied files are cloned on filesystems that allow it,
+    /// such as btrfs and XFS, which makes them nearly instant to copy.
+    /// Only Linux supports cloning.
+    pub fn reflink(mut self, reflink: Reflink) -> Self {
+        self.reflink = reflink;
+        self
+    }
+
+    /// Sets what happens to the named pipes, sockets and devices of a
+    /// directory, which are skipped with a warning by default. A special
+    /// file backed up on its own is never skipped, but fails unless recorded.
+    pub fn special_files(mut self, special_files: SpecialFiles) -> Self {
+        self.special_files = special_files;
+        self
+    }
+
+    /// Sets the compression of directory archives. Anything other than
+    /// [`Compression::None`] archives a directory backed up into a directory.
+    pub fn compress(mut self, compression: Compression) -> Self {
+        self.compression = compression;
+        self
+    }
+
+    /// Encrypts archives to `recipient`, along with any other recipient
+    /// added, so that any of their identities decrypts them. A directory
+    /// backed up into a directory is then archived even without compression.
+    pub fn recipient(mut self, recipient: Recipient) -> Self {
+        self.recipients.push(recipient);
+        self
+    }
+
+    /// Encrypts archives with `passphrase` instead of to recipients, as
+    /// [`BackupOptions::recipient`] does otherwise.
+    pub fn passphrase(mut self, passphrase: Passphrase) -> Self {
+        self.passphrase = Some(passphrase);
+        self
+    }
+
+    /// Sets the base 2 logarithm of the scrypt cost of deriving the key from
+    /// the passphrase, between 1 and [`age::MAX_WORK_FACTOR`]. Defaults to
+    /// [`age::WORK_FACTOR`]; each step doubles the time and memory needed to
+    /// encrypt and decrypt, and to guess the passphrase.
+    pub fn work_factor(mut self, work_factor: u8) -> Self {
+        self.work_factor = work_factor.clamp(1, age::MAX_WORK_FACTOR);
+        self
+    }
+
+    /// Signs the checksum manifest of the backup with `key`, writing the
+    /// signature next to the backup, see [`sign`]. Requires a checksum
+    /// algorithm, and is refused for mirrors.
+    pub fn sign(mut self, key: SigningKey) -> Self {
+        self.signing_key = Some(key);
+        self
+    }
+
+    /// Writes the archive of a directory in parts of at most `size` bytes,
+    /// see [`split`]. A directory backed up into a directory is then
+    /// archived even without compression.
+    pub fn split(mut self, size: u64) -> Self {
+        self.split = Some(size.max(1));
+        self
+    }
+
+    /// Excludes the entries of a directory backup matching `pattern`, see
+    /// [`Excludes`] for the syntax.
+    pub fn exclude(mut self, pattern: &str) -> Self {
+        self.excludes.add(pattern);
+        self
+    }
+
+    /// Excludes the directories of version control systems, listed in
+    /// [`VCS_DIRS`](crate::exclude::VCS_DIRS), when `enabled`. Patterns
+    /// excluded later may include them again.
+    pub fn exclude_vcs(mut self, enabled: bool) -> Self {
+        if enabled {
+            for dir in crate::exclude::VCS_DIRS {
+                self.excludes.add(&format!("{dir}/"));
+            }
+        }
+        self
+    }
+
+    /// Excludes the directories holding a valid `CACHEDIR.TAG` file, see
+    /// [`is_cache_dir`](crate::exclude::is_cache_dir).
+    pub fn exclude_caches(mut self, enabled: bool) -> Self {
+        self.exclude_caches = enabled;
+        self
+    }
+
+    /// Reads the `.backupignore` file of each directory backed up and excludes
+    /// the entries it lists, see [`Excludes`] for the syntax.
+    pub fn ignore_files(mut self, ignore_files: bool) -> Self {
+        self.ignore_files = ignore_files;
+        self
+    }
+
+    /// Writes the backup to a nonexistent target itself, as a copy of a file
+    /// or an archive of a directory, instead of creating a directory there.
+    pub fn as_file(mut self, as_file: bool) -> Self {
+        self.as_file = as_file;
+        self
+    }
+
+    /// Records the checksums of the backed up files with `algorithm` in a
+    /// manifest next to the backup, see [`checksum`]. SHA-256 is used by
+    /// default.
+    pub fn checksum(mut self, algorithm: Option<Algorithm>) -> Self {
+        self.checksum = algorithm;
+        self
+    }
+
+    /// Hard links the files left unchanged since the newest previous copy of
+    /// a directory in the same target to that copy, instead of copying them
+    /// again. Each backup remains a complete snapshot.
+    pub fn incremental(mut self, incremental: bool) -> Self {
+        self.incremental = incremental;
+        self
+    }
+
+    /// Compares files by checksum rather than by size and modification time
+    /// when looking for unchanged files.
+    pub fn compare_checksums(mut self, compare_checksums: bool) -> Self {
+        self.compare_checksums = compare_checksums;
+        self
+    }
+
+    /// Skips the backup when the newest previous copy of the source in the
+    /// same target is identical to it, failing with
+    /// [`BackupError::Unchanged`]. Archives are never considered identical.
+    pub fn skip_unchanged(mut self, skip_unchanged: bool) -> Self {
+        self.skip_unchanged = skip_unchanged;
+        self
+    }
+
+    /// Mirrors a directory into the target itself rather than into a new
+    /// timestamped backup, copying only the files that differ from their
+    /// copy there by size and modification time, or by checksum with
+    /// [`BackupOptions::compare_checksums`]. The target must be a mirror of
+    /// the same source unless [`BackupOptions::init`] is set. No checksum
+    /// manifest or metadata file is written.
+    pub fn mirror(mut self, mirror: bool) -> Self {
+        self.mirror = mirror;
+        self
+    }
+
+    /// Removes from a mirror the entries no longer in its source. Entries
+    /// excluded from the mirror are kept.
+    pub fn delete(mut self, delete: bool) -> Self {
+        self.delete = delete;
+        self
+    }
+
+    /// Starts a mirror in a target that is not one yet, recording the source
+    /// in a [`MIRROR_MARKER`] file there.
+    pub fn init(mut self, init: bool) -> Self {
+        self.init = init;
+        self
+    }
+
+    /// Copies the files of a directory copied into a directory with up to
+    /// `jobs` threads, zero counting as one. Defaults to the number of CPUs,
+    /// up to 8. Archives are always written by a single thread.
+    pub fn jobs(mut self, jobs: usize) -> Self {
+        self.jobs = jobs.max(1);
+        self
+    }
+
+    /// Leaves out the contents of the directories `depth` levels below a
+    /// backed up directory, its own entries being at depth 1. The backup then
+    /// warns that it is partial, and [`BackupReport::too_deep`] counts them.
+    pub fn max_depth(mut self, depth: Option<usize>) -> Self {
+        self.max_depth = depth;
+        self
+    }
+
+    /// Stops backing up a directory past `files` entries other than
+    /// directories, which fails the backup unless [keeping
+    /// going](BackupOptions::keep_going), in which case it is kept with a
+    /// warning that it is partial.
+    pub fn max_files(mut self, files: Option<u64>) -> Self {
+        self.max_files = files;
+        self
+    }
+
+    /// Leaves out the files of a backed up directory smaller than `bytes`,
+    /// logging each at verbose level. [`BackupReport::size_skipped`] counts
+    /// them along with those left out by [`BackupOptions::max_file_size`].
+    pub fn min_file_size(mut self, bytes: Option<u64>) -> Self {
+        self.min_file_size = bytes;
+        self
+    }
+
+    /// Leaves out the files of a backed up directory larger than `bytes`,
+    /// logging each at verbose level.
+    pub fn max_file_size(mut self, bytes: Option<u64>) -> Self {
+        self.max_file_size = bytes;
+        self
+    }
+
+    /// Keeps copying the rest of a directory copied into a directory when
+    /// some of its entries cannot be copied, which is the default. The
+    /// failures are then listed in [`BackupReport::failed`]. Otherwise the
+    /// first failure aborts the backup and nothing is left behind.
+    pub fn keep_going(mut self, keep_going: bool) -> Self {
+        self.keep_going = keep_going;
+        self
+    }
+
+    /// Copies again a file of a copy that failed with an error that may be
+    /// transient, such as `EIO` or `ETIMEDOUT` on a network filesystem, up to
+    /// `retries` times before reporting it. Errors such as a missing file or
+    /// a denied permission are reported at once. Files of archives are not
+    /// retried.
+    pub fn retries(mut self, retries: u32) -> Self {
+        self.retries = retries;
+        self
+    }
+
+    /// Waits `delay` before the first retry of a file, then twice as long
+    /// before each next one. Defaults to a second.
+    pub fn retry_delay(mut self, delay: Duration) -> Self {
+        self.retry_delay = delay;
+        self
+    }
+
+    /// Limits the writes of the backup to `rate` bytes per second, shared by
+    /// all of its jobs, and by every backup made with these options or their
+    /// clones. Unlimited by default.
+    pub fn bwlimit(mut self, rate: Option<u64>) -> Self {
+        self.throttle = rate.map(|rate| Arc::new(Throttle::new(rate)));
+        self
+    }
+
+    /// Checks that the target filesystem has room for the whole source before
+    /// copying anything, which is the default. Incremental backups, which
+    /// link unchanged files, are not checked, and archives, whose compressed
+    /// size is not known beforehand, only warn.
+    pub fn space_check(mut self, space_check: bool) -> Self {
+        self.space_check = space_check;
+        self
+    }
+
+    /// Waits up to `wait` for another backup of the same source into the same
+    /// target to finish, instead of failing at once with
+    /// [`BackupError::Locked`].
+    pub fn wait_lock(mut self, wait: Duration) -> Self {
+        self.wait_lock = wait;
+        self
+    }
+
+    /// Copies files through a buffer of `size` bytes, at least one, when they
+    /// are not cloned or copied by the kernel, and hashes them as they go.
+    /// Defaults to [`DEFAULT_BUFFER_SIZE`].
+    pub fn buffer_size(mut self, size: usize) -> Self {
+        self.buffer_size = size.max(1);
+        self
+    }
+
+    /// Flushes what the backup wrote to disk as `fsync` says before renaming
+    /// it into place. Defaults to [`Fsync::File`].
+    pub fn fsync(mut self, fsync: Fsync) -> Self {
+        self.fsync = fsync;
+        self
+    }
+
+    /// How archives are encrypted.
+    fn encryption(&self) -> Encryption<'_> {
+        match &self.passphrase {
+            Some(passphrase) => Encryption::Passphrase(passphrase, self.work_factor),
+            None if !self.recipients.is_empty() => Encryption::Recipients(&self.recipients),
+            None => Encryption::None,
+        }
+    }
+
+    /// Settings of the copies made by this backup.
+    fn copy_options(&self) -> CopyOptions<'_> {
+        CopyOptions {
+            dereference: self.dereference,
+            preserve: self.preserve,
+            preserve_owner: self.preserve_owner && check_owner_privilege(),
+            xattrs: self.xattrs,
+            reflink: self.reflink,
+            buffer_size: self.buffer_size,
+            fsync: self.fsync,
+            special_files: self.special_files,
+            excludes: &self.excludes,
+            ignore_files: self.ignore_files,
+            exclude_caches: self.exclude_caches,
+            max_depth: self.max_depth,
+            max_files: self.max_files,
+            min_file_size: self.min_file_size,
+            max_file_size: self.max_file_size,
+            checksum: self.checksum,
+            previous: None,
+            mirror: None,
+            jobs: self.jobs,
+            keep_going: self.keep_going,
+            retries: self.retries,
+            retry_delay: self.retry_delay,
+            throttle: self.throttle.as_deref(),
+        }
+    }
+}
+
+/// Settings shared by the copies made when backing up and restoring.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct CopyOptions<'a> {
+    /// Follow symbolic links and copy their targets.
+    pub dereference: bool,
+    /// Apply the mode and modification time of each source entry to its copy.
+    pub preserve: bool,
+    /// Apply the owner and group of each source entry to its copy.
+    pub preserve_owner: bool,
+    /// Copy the extended attributes of each source entry, or record them in
+    /// archives.
+    pub xattrs: bool,
+    /// Whether copied files are cloned.
+    pub reflink: Reflink,
+    /// Size of the buffer files are copied through.
+    pub buffer_size: usize,
+    /// What is flushed to disk once written.
+    pub fsync: Fsync,
+    /// What happens to special files.
+    pub special_files: SpecialFiles,
+    /// Entries of a directory left out of the copy.
+    pub excludes: &'a Excludes,
+    /// Also leave out the entries listed in ignore files.
+    pub ignore_files: bool,
+    /// Also leave out the directories tagged as caches.
+    pub exclude_caches: bool,
+    /// Depth of the directories of a tree whose contents are left out.
+    pub max_depth: Option<usize>,
+    /// Number of files of a tree past which the copy stops.
+    pub max_files: Option<u64>,
+    /// Size of the smallest files of a tree copied.
+    pub min_file_size: Option<u64>,
+    /// Size of the largest files of a tree copied.
+    pub max_file_size: Option<u64>,
+    /// Hash the contents of copied files with this algorithm.
+    pub checksum: Option<Algorithm>,
+    /// Previous copy that unchanged files are hard linked from.
+    pub previous: Option<&'a Snapshot>,
+    /// Mirror updated in place, whose up to date entries are left as they
+    /// are and the others replaced.
+    pub mirror: Option<&'a Snapshot>,
+    /// Number of threads copying the files of a tree.
+    pub jobs: usize,
+    /// Copy the rest of a tree after an entry fails, rather than stopping.
+    pub keep_going: bool,
+    /// Number of times a file is copied again after a transient error.
+    pub retries: u32,
+    /// Wait before the first retry, doubled before each next one.
+    pub retry_delay: Duration,
+    /// Limit on the rate of the writes.
+    pub throttle: Option<&'a Throttle>,
+}
+
+/// A previous directory backup that an incremental backup links to.
+#[derive(Debug)]
+pub(crate) struct Snapshot {
+    /// Root of the previous backup.
+    root: PathBuf,
+    /// Algorithm of the digests recorded for the previous backup, if any.
+    algorithm: Option<Algorithm>,
+    /// Recorded digests, by path relative to the root.
+    digests: HashMap<PathBuf, String>,
+    /// Compare contents by checksum rather than size and modification time.
+    compare_checksums: bool,
+}
+
+impl Snapshot {
+    /// Loads the previous backup at `root` along with its checksum manifest,
+    /// if it has a readable one.
+    fn load(root: &Path, compare_checksums: bool) -> Self {
+        let manifest = Algorithm::ALL.into_iter().find_map(|algorithm| {
+            let entries = checksum::read_manifest(&algorithm.manifest_path(root)).ok()?;
+            Some((algorithm, entries))
+        });
+        let name = root.file_name().map(Path::new).unwrap_or(Path::new(""));
+        let (algorithm, digests) = match manifest {
+            Some((algorithm, entries)) => {
+                let digests = entries
+                    .into_iter()
+                    .filter_map(|(path, digest)| {
+                        Some((path.strip_prefix(name).ok()?.to_path_buf(), digest))
+                    })
+                    .collect();
+                (Some(algorithm), digests)
+            }
+            None => (None, HashMap::new()),
+        };
+
+        Snapshot {
+            root: root.to_path_buf(),
+            algorithm,
+            digests,
+            compare_checksums,
+        }
+    }
+
+    /// Describes the mirror at `root`, whose files are compared with no
+    /// recorded digest.
+    fn mirror(root: &Path, compare_checksums: bool) -> Self {
+        Snapshot {
+            root: root.to_path_buf(),
+            algorithm: None,
+            digests: HashMap::new(),
+            compare_checksums,
+        }
+    }
+
+    /// Path of the previous copy of `entry`.
+    fn path(&self, entry: &Entry) -> PathBuf {
+        match entry.relative.as_os_str().is_empty() {
+            true => self.root.clone(),
+            false => self.root.join(&entry.relative),
+        }
+    }
+
+    /// Checks whether the previous copy of `entry` is identical to it:
+    /// directories are compared by kind, symbolic links by target, and files
+    /// as [`Snapshot::unchanged`] does.
+    fn same(&self, entry: &Entry) -> bool {
+        let path = self.path(entry);
+        let metadata = &entry.metadata;
+        if metadata.is_dir() {
+            fs::symlink_metadata(&path).is_ok_and(|previous| previous.is_dir())
+        } else if metadata.is_symlink() {
+            fs::read_link(&path).ok() == fs::read_link(&entry.path).ok()
+        } else {
+            self.unchanged(entry, None).is_some()
+        }
+    }
+
+    /// Checks whether `entry` is unchanged since the previous backup, in
+    /// which case the path of its previous copy is returned, along with its
+    /// digest computed with `algorithm` if given.
+    ///
+    /// Files are unchanged when their size and permissions match, and either
+    /// their modification time or, when comparing checksums, their digest.
+    /// Any failure to tell counts as a change.
+    fn unchanged(
+        &self,
+        entry: &Entry,
+        algorithm: Option<Algorithm>,
+    ) -> Option<(PathBuf, Option<String>)> {
+        let path = self.path(entry);
+        let previous = fs::symlink_metadata(&path).ok()?;
+        let metadata = &entry.metadata;
+        if !metadata.is_file()
+            || !previous.is_file()
+            || previous.len() != metadata.len()
+            || previous.permissions() != metadata.permissions()
+        {
+            return None;
+        }
+
+        let recorded = |algorithm| {
+            let recorded = self.digests.get(&entry.relative).cloned();
+            match recorded {
+                Some(digest) if self.algorithm == Some(algorithm) => Some(digest),
+                _ => checksum::hash_file(&path, algorithm).ok(),
+            }
+        };
+        if self.compare_checksums {
+            let algorithm = algorithm.unwrap_or_default();
+            let digest = checksum::hash_file(&entry.path, algorithm).ok()?;
+            if recorded(algorithm)? != digest {
+                return None;
+            }
+            return Some((path, Some(digest)));
+        }
+
+        let modified = FileTime::from_last_modification_time;
+        if modified(&previous) != modified(metadata) {
+            return None;
+        }
+        match algorithm {
+            Some(algorithm) => Some((path.clone(), Some(recorded(algorithm)?))),
+            None => Some((path, None)),
+        }
+    }
+}
+
+/// Checks whether the process may give files any owner, warning that owners
+/// are not preserved if it may not.
+pub(crate) fn check_owner_privilege() -> bool {
+    #[cfg(unix)]
+    // SAFETY: geteuid has no preconditions and cannot fail.
+    let privileged = unsafe { libc::geteuid() } == 0;
+    #[cfg(not(unix))]
+    let privileged = false;
+
+    if !privileged {
+        writer::log(
+            Level::Warning,
+            "Not running as root, files keep the current owner instead of the original one",
+        );
+    }
+    privileged
+}
+
+/// Outcome of a successful backup.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BackupReport {
+    /// Path of the created backup.
+    pub path: PathBuf,
+    /// Number of files backed up, not counting directories.
+    pub files: u64,
+    /// Number of bytes written, which is the archive size for archives.
+    pub bytes: u64,
+    /// Number of files hard linked from a previous backup instead of being
+    /// copied, which are counted in `files` but not in `bytes`.
+    pub linked: u64,
+    /// Files stored as hard links to another file of the same backup, as
+    /// they were in the source, which are counted in `files` but not in
+    /// `bytes`.
+    pub hardlinks: Hardlinks,
+    /// Number of special files of a directory left out with a warning, see
+    /// [`BackupOptions::special_files`].
+    pub special: u64,
+    /// Number of directories whose contents were left out by
+    /// [`BackupOptions::max_depth`].
+    pub too_deep: u64,
+    /// Number of files left out by [`BackupOptions::min_file_size`] and
+    /// [`BackupOptions::max_file_size`].
+    pub size_skipped: u64,
+    /// Number of files copied again after a transient error, see
+    /// [`BackupOptions::retries`], whether or not they failed in the end.
+    pub retried: u64,
+    /// Number of files of a mirror already up to date and left as they were,
+    /// which are counted in `files` but not in `bytes`.
+    pub up_to_date: u64,
+    /// Number of entries removed from a mirror as no longer in the source,
+    /// see [`BackupOptions::delete`].
+    pub removed: u64,
+    /// Errors of the entries of a directory copy that could not be backed
+    /// up and are missing from it, each naming the path involved. Only ever
+    /// non-empty when [`BackupOptions::keep_going`] is set.
+    pub failed: Vec<String>,
+    /// Time taken to write the backup.
+    pub duration: Duration,
+    /// Statistics of the backup, summing up the counts above.
+    pub stats: Stats,
+}
+
+/// Outcome of backing up several sources with [`backup_all`], or one source
+/// to several targets with [`backup_to_targets`].
+#[derive(Debug, Default)]
+pub struct BatchReport {
+    /// Outcome for each source, or each target, in the order given.
+    pub results: Vec<(PathBuf, Result<BackupReport, BackupError>)>,
+}
+
+impl BatchReport {
+    /// Number of sources that could not be backed up, or targets that could
+    /// not be backed up to, or only partially. Those skipped as unchanged are
+    /// not counted.
+    pub fn failures(&self) -> usize {
+        self.results
+            .iter()
+            .filter(|(_, result)| match result {
+                Ok(report) => !report.failed.is_empty(),
+                Err(error) => !matches!(error, BackupError::Unchanged(_)),
+            })
+            .count()
+    }
+}
+
+/// What a backup will do, computed without touching the filesystem.
+#[derive(Debug, Clone)]
+pub struct BackupPlan {
+    /// Kind of backup derived from the source and target paths.
+    pub backup_type: BackupType,
+    /// Path being backed up.
+    pub source: PathBuf,
+    /// Time of the backup, embedded in timestamped names.
+    pub created: DateTime<Local>,
+    /// Final path of the backup, including the timestamped name if any.
+    pub destination: PathBuf,
+    /// Compression of the archive, or `None` if the backup is a plain copy.
+    pub archive: Option<Compression>,
+    /// Number of non-directory entries to copy.
+    pub files: u64,
+    /// Paths of the entries left out by exclude patterns, relative to the source.
+    pub excluded: Vec<PathBuf>,
+    /// Paths of the files left out by [`BackupOptions::min_file_size`] and
+    /// [`BackupOptions::max_file_size`], relative to the source, with their
+    /// size.
+    pub size_skipped: Vec<(PathBuf, u64)>,
+    /// Paths of the directories whose contents are left out by
+    /// [`BackupOptions::max_depth`], relative to the source.
+    pub too_deep: Vec<PathBuf>,
+    /// Whether the source has more files than [`BackupOptions::max_files`],
+    /// in which case `files` only counts those backed up.
+    pub reached_max_files: bool,
+    /// Total size in bytes of the entries to copy.
+    pub bytes: u64,
+    /// Free space in bytes on the filesystem of the target, if known.
+    pub free: Option<u64>,
+    /// Whether the target already exists, rather than being created along
+    /// with any missing parent directories.
+    pub target_exists: bool,
+    /// Whether the destination already exists.
+    pub destination_exists: bool,
+    /// Previous backup that unchanged files are linked to, for incremental
+    /// backups.
+    pub previous: Option<PathBuf>,
+    /// Previous backup identical to the source, in which case the backup is
+    /// skipped. Only looked for with [`BackupOptions::skip_unchanged`].
+    pub unchanged_since: Option<PathBuf>,
+    /// Number of files of a mirror already up to date, left out of `files`
+    /// and `bytes`, see [`BackupOptions::mirror`].
+    pub up_to_date: u64,
+    /// Paths of the entries of a mirror no longer in the source, relative to
+    /// it, removed with [`BackupOptions::delete`].
+    pub stale: Vec<PathBuf>,
+    /// Whether the target of a mirror is a mirror of the source already.
+    pub is_mirror: bool,
+    /// Options the backup is performed with.
+    pub options: BackupOptions,
+}
+
+impl BackupPlan {
+    /// Checks whether an existing destination prevents the backup, or for a
+    /// mirror, a target that is not one of the source.
+    pub fn is_blocked(&self) -> bool {
+        match self.options.mirror {
+            true => !self.is_mirror && !self.options.init,
+            false => self.destination_exists && !self.options.force,
+        }
+    }
+}
+
+impl fmt::Display for BackupPlan {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "type: {:?}", self.backup_type)?;
+        writeln!(f, "source: {}", self.source.display())?;
+        writeln!(f, "destination: {}", self.destination.display())?;
+        if !self.target_exists {
+            writeln!(f, "target exists: no, it will be created")?;
+        }
+        if let Some(compression) = self.archive {
+            writeln!(f, "archive: {}", compression.extension())?;
+            match self.options.encryption() {
+                Encryption::None => {}
+                Encryption::Recipients(recipients) => {
+                    writeln!(f, "encrypted: {} recipients", recipients.len())?
+                }
+                Encryption::Passphrase(..) => writeln!(f, "encrypted: passphrase")?,
+            }
+        }
+        if let Some(algorithm) = self.options.checksum.filter(|_| !self.options.mirror) {
+            writeln!(f, "checksum: {}", algorithm.extension())?;
+        }
+        if let Some(size) = self.options.split.filter(|_| self.archive.is_some()) {
+            writeln!(f, "split: parts of {}", writer::human_bytes(size))?;
+        }
+        if let Some(key) = &self.options.signing_key {
+            writeln!(f, "signed by: {}", key.verifying_key())?;
+        }
+        writeln!(
+            f,
+            "files: {} ({})",
+            self.files,
+            writer::human_bytes(self.bytes)
+        )?;
+        if self.options.mirror {
+            writeln!(f, "up to date: {} files", self.up_to_date)?;
+        }
+        for path in &self.stale {
+            writeln!(f, "removed: {}", path.display())?;
+        }
+        if let Some(previous) = &self.previous {
+            writeln!(f, "incremental from: {}", previous.display())?;
+        }
+        if let Some(previous) = &self.unchanged_since {
+            writeln!(
+                f,
+                "unchanged since: {}, the backup is skipped",
+                previous.display()
+            )?;
+        }
+        for path in &self.excluded {
+            writeln!(f, "excluded: {}", path.display())?;
+        }
+        if let Some(bytes) = self.options.min_file_size {
+            writeln!(f, "min file size: {}", writer::human_bytes(bytes))?;
+        }
+        if let Some(bytes) = self.options.max_file_size {
+            writeln!(f, "max file size: {}", writer::human_bytes(bytes))?;
+        }
+        for (path, bytes) in &self.size_skipped {
+            writeln!(
+                f,
+                "skipped for its size: {} ({})",
+                path.display(),
+                writer::human_bytes(*bytes)
+            )?;
+        }
+        if let Some(depth) = self.options.max_depth {
+            writeln!(f, "max depth: {depth}")?;
+        }
+        for path in &self.too_deep {
+            writeln!(f, "beyond max depth: {}", path.display())?;
+        }
+        match (self.options.max_files, self.reached_max_files) {
+            (Some(files), false) => writeln!(f, "max files: {files}")?,
+            (Some(files), true) => {
+                writeln!(f, "max files: {files}, exceeded, the backup stops there")?
+            }
+            (None, _) => {}
+        }
+        match (self.destination_exists, self.options.force) {
+            _ if self.is_mirror => write!(f, "mirror: yes, updated in place"),
+            _ if self.options.mirror && self.options.init => {
+                write!(f, "mirror: no, it will be started")
+            }
+            _ if self.options.mirror => write!(
+                f,
+                "mirror: no, the mirror is blocked, use --init to start one"
+            ),
+            (false, _) => write!(f, "destination exists: no"),
+            (true, true) => write!(f, "destination exists: yes, it will be overwritten"),
+            (true, false) => write!(f, "destination exists: yes, the backup is blocked"),
+        }?;
+        match self.free {
+            Some(free) if free < self.bytes && self.options.space_check => write!(
+                f,
+                "\nfree space: {}, not enough for the backup",
+                writer::human_bytes(free)
+            ),
+            Some(free) => write!(f, "\nfree space: {}", writer::human_bytes(free)),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Backs up `source` into `target`, returning where the backup was created.
+///
+/// The behavior depends on the kind of paths received:
+///
+/// 1. File to directory: the file is copied to `<target>/<name>.<timestamp>.backup`.
+/// 2. File to file: the target file is overwritten with the source contents.
+/// 3. Directory to directory: the tree is copied to `<target>/<name>.<timestamp>.backup/`,
+///    or archived to `<target>/<name>.<timestamp>.backup.tar.<ext>` when compressed.
+/// 4. Directory to file: the tree is archived into the target file, compressed
+///    according to [`BackupOptions::compress`].
+///
+/// A target that does not exist is created as a directory, along with any
+/// missing parents, and the backup proceeds as in cases 1 and 3. With
+/// [`BackupOptions::as_file`] set, it is instead written as a file, as in
+/// cases 2 and 4.
+///
+/// Whenever the final destination already exists the backup is refused unless
+/// [`BackupOptions::force`] is set, in which case the destination is overwritten.
+///
+/// Two backups of the same source into the same target do not run at once:
+/// the second fails with [`BackupError::Locked`], or waits as long as
+/// [`BackupOptions::wait_lock`] allows. The lock is a `.<name>.backup.lock`
+/// file in the directory the backup is created in, taken by [`execute`] and
+/// removed once done.
+///
+/// With [`BackupOptions::mirror`] set, a directory is instead mirrored into
+/// the target itself, which holds the lock, updating what changed since the
+/// previous mirror.
+///
+/// A symbolic link given as source, or found inside a source directory, is
+/// backed up as a link to the same target rather than followed. Links are
+/// preserved even when dangling. With [`BackupOptions::dereference`] set,
+/// links are followed and their contents backed up instead.
+///
+/// # Examples
+///
+/// ```no_run
+/// use std::path::Path;
+///
+/// use backup::backup::{BackupOptions, Compression};
+///
+/// let options = BackupOptions::new().compress(Compression::Zstd(3));
+/// let report = backup::backup(Path::new("/etc"), Path::new("/var/backups"), &options)?;
+/// println!("{} files in {}", report.files, report.path.display());
+/// # Ok::<(), backup::BackupError>(())
+/// ```
+pub fn backup(
+    source: &Path,
+    target: &Path,
+    options: &BackupOptions,
+) -> Result<BackupReport, BackupError> {
+    let plan = plan(source, target, options)?;
+    execute(&plan)
+}
+
+/// Backs up each of `sources` into the directory `target`, as [`backup`] does
+/// for each, continuing with the next source when one fails.
+///
+/// The error of each failed source is logged as it happens and kept in the
+/// report, along with the report of each source backed up.
+///
+/// # Examples
+///
+/// ```no_run
+/// use std::path::{Path, PathBuf};
+///
+/// use backup::backup::{backup_all, BackupOptions};
+///
+/// let sources = [PathBuf::from("/etc/hosts"), PathBuf::from("/etc/fstab")];
+/// let report = backup_all(&sources, Path::new("/var/backups"), &BackupOptions::new());
+/// println!("{} of {} sources failed", report.failures(), sources.len());
+/// ```
+pub fn backup_all(sources: &[PathBuf], target: &Path, options: &BackupOptions) -> BatchReport {
+    let mut report = BatchReport::default();
+    for source in sources {
+        if interrupt::is_interrupted() {
+            break;
+        }
+        let result = backup(source, target, options);
+        match &result {
+            Err(error @ BackupError::Unchanged(_)) => writer::log(Level::Info, error),
+            Err(BackupError::Interrupted) => {}
+            Err(error) => writer::log(Level::Error, error),
+            Ok(_) => {}
+        }
+        report.results.push((source.clone(), result));
+    }
+    report
+}
+
+/// Writes the directory `source` into `writer` as a tar archive, compressed
+/// as set in `options`, such as to pipe a backup to another program.
+///
+/// No checksum manifest or metadata file is written, and the path of the
+/// report is `-`.
+///
+/// # Examples
+///
+/// ```no_run
+/// use std::io;
+/// use std::path::Path;
+///
+/// use backup::backup::{stream, BackupOptions, Compression};
+///
+/// let options = BackupOptions::new().compress(Compression::Zstd(3));
+/// stream(Path::new("/srv/data"), io::stdout().lock(), &options)?;
+/// # Ok::<(), backup::BackupError>(())
+/// ```
+pub fn stream(
+    source: &Path,
+    writer: impl Write,
+    options: &BackupOptions,
+) -> Result<BackupReport, BackupError> {
+    let started = Instant::now();
+    let metadata = match options.dereference {
+        true => fs::metadata(source),
+        false => fs::symlink_metadata(source),
+    };
+    match metadata {
+        Ok(metadata) if metadata.is_dir() => {}
+        Ok(_) => {
+            return Err(BackupError::InvalidOption(format!(
+                "'{}': Only directories can be written to stdout",
+                source.display()
+            )))
+        }
+        Err(_) => return Err(BackupError::NotFound(source.to_path_buf())),
+    }
+
+    let copy_options = CopyOptions {
+        dereference: options.dereference,
+        preserve: options.preserve,
+        preserve_owner: false,
+        xattrs: options.xattrs,
+        reflink: options.reflink,
+        buffer_size: options.buffer_size,
+        fsync: options.fsync,
+        special_files: options.special_files,
+        excludes: &options.excludes,
+        ignore_files: options.ignore_files,
+        exclude_caches: options.exclude_caches,
+        max_depth: options.max_depth,
+        max_files: options.max_files,
+        min_file_size: options.min_file_size,
+        max_file_size: options.max_file_size,
+        checksum: None,
+        previous: None,
+        mirror: None,
+        jobs: 1,
+        keep_going: false,
+        retries: 0,
+        retry_delay: Duration::ZERO,
+        throttle: options.throttle.as_deref(),
+    };
+    let mut counter = Counter {
+        inner: writer,
+        bytes: 0,
+    };
+    let mut copied = Copied::default();
+    write_encrypted(
+        source,
+        &mut counter,
+        options.compression,
+        options.encryption(),
+        copy_options,
+        &mut copied,
+    )
+    .and_then(Write::flush)
+    .map_err(|e| match interrupt::is_interrupted() {
+        true => BackupError::Interrupted,
+        false => copy_error(source, Path::new("-"), e),
+    })?;
+
+    let files = Walker::new(source, options.dereference).map_or(0, |walker| {
+        let mut walker = walker
+            .exclude(&options.excludes)
+            .ignore_files(options.ignore_files)
+            .exclude_caches(options.exclude_caches)
+            .max_depth(options.max_depth)
+            .max_files(options.max_files)
+            .min_file_size(options.min_file_size)
+            .max_file_size(options.max_file_size);
+        totals(&mut walker).0
+    });
+    let duration = started.elapsed();
+    let compressed = options.compression != Compression::None;
+    let copied = Copied {
+        files,
+        bytes: counter.bytes,
+        ..copied
+    };
+    Ok(BackupReport {
+        path: PathBuf::from("-"),
+        files,
+        bytes: counter.bytes,
+        linked: 0,
+        hardlinks: copied.hardlinks,
+        special: copied.special,
+        too_deep: copied.too_deep,
+        size_skipped: copied.size_skipped,
+        retried: copied.retried,
+        up_to_date: 0,
+        removed: 0,
+        failed: Vec::new(),
+        duration,
+        stats: copied.stats(compressed, duration),
+    })
+}
+
+/// Backs up what `reader` yields, such as stdin, to a file named after `name`
+/// in the directory `target`, as [`backup`] does for a file named `name`.
+///
+/// A checksum manifest is written as set in `options`, but no metadata file,
+/// as there is no source path to record. Empty input makes an empty backup,
+/// with a warning.
+///
+/// # Examples
+///
+/// ```no_run
+/// use std::io;
+/// use std::path::Path;
+///
+/// use backup::backup::{backup_reader, BackupOptions};
+///
+/// let report = backup_reader(io::stdin().lock(), "mydb.sql", Path::new("/var/backups"), &BackupOptions::new())?;
+/// println!("{} bytes in {}", report.bytes, report.path.display());
+/// # Ok::<(), backup::BackupError>(())
+/// ```
+pub fn backup_reader(
+    mut reader: impl Read,
+    name: &str,
+    target: &Path,
+    options: &BackupOptions,
+) -> Result<BackupReport, BackupError> {
+    let started = Instant::now();
+    let source = Path::new("-");
+    if !matches!(options.encryption(), Encryption::None) {
+        return Err(BackupError::InvalidOption(
+            "Only directory archives can be encrypted, not stdin".to_owned(),
+        ));
+    }
+    if options.split.is_some() {
+        return Err(BackupError::InvalidOption(
+            "Only directory archives can be split, not stdin".to_owned(),
+        ));
+    }
+    check_signing(options)?;
+    if name.is_empty() || name.contains(std::path::is_separator) || name == "." || name == ".." {
+        return Err(BackupError::InvalidName(PathBuf::from(name)));
+    }
+    fs::create_dir_all(target).map_err(|source| BackupError::CreateFailed {
+        path: target.to_path_buf(),
+        source,
+    })?;
+
+    let destination = backup_path(Path::new(name), target, Local::now(), None)?;
+    check_overwrite(&destination, options.force)?;
+    let partial = partial_path(&destination);
+    let result = File::create(&partial)
+        .and_then(|file| {
+            let mut writer = HashingWriter::new(file, options.checksum);
+            let mut reader = Interruptible(&mut reader);
+            let throttle = options.throttle.as_deref();
+            let bytes = reflink::copy_buffered(
+                &mut reader,
+                &mut Throttled(&mut writer, throttle),
+                options.buffer_size,
+            )?;
+            let (file, digest) = writer.finish();
+            if options.fsync != Fsync::None {
+                file.sync_all()?;
+            }
+            Ok((bytes, digest))
+        })
+        .and_then(|written| fs::rename(&partial, &destination).map(|_| written))
+        .and_then(|written| sync_parent(&destination, options.fsync).map(|_| written));
+    let (bytes, digest) = result.map_err(|e| {
+        let _ = fs::remove_file(&partial);
+        match interrupt::is_interrupted() {
+            true => BackupError::Interrupted,
+            false => copy_error(source, &destination, e),
+        }
+    })?;
+
+    if bytes == 0 {
+        writer::log(
+            Level::Warning,
+            format_args!("stdin was empty, {} is empty", destination.display()),
+        );
+    }
+    if let (Some(algorithm), Some(digest)) = (options.checksum, digest) {
+        let manifest =
+            checksum::write_manifest(&destination, algorithm, &[(PathBuf::new(), digest)])?;
+        if let Some(key) = &options.signing_key {
+            sign::sign(&destination, &manifest, key)?;
+        }
+    }
+    writer::log(
+        Level::Verbose,
+        format_args!(
+            "backed up {} of stdin to {}",
+            writer::human_bytes(bytes),
+            destination.display()
+        ),
+    );
+    let duration = started.elapsed();
+    Ok(BackupReport {
+        path: destination,
+        files: 1,
+        bytes,
+        linked: 0,
+        hardlinks: Hardlinks::default(),
+        special: 0,
+        too_deep: 0,
+        size_skipped: 0,
+        retried: 0,
+        up_to_date: 0,
+        removed: 0,
+        failed: Vec::new(),
+        duration,
+        stats: Stats {
+            files: 1,
+            bytes_read: bytes,
+            bytes_written: bytes,
+            elapsed: duration,
+            ..Stats::default()
+        },
+    })
+}
+
+/// Backs up `source` to the directory `target` on another machine over SSH,
+/// see [`crate::remote`], as [`backup`] does to a local directory.
+///
+/// Directories are written as tar archives, compressed and encrypted as set
+/// in `options`, and files as they are. The checksum manifest, metadata and
+/// signature files are written next to the backup, and the path of the
+/// report is the remote one, `host:path`.
+///
+/// # Examples
+///
+/// ```no_run
+/// use std::path::Path;
+///
+/// use backup::backup::{backup_remote, BackupOptions};
+/// use backup::remote::Remote;
+///
+/// let target = Remote::parse("admin@nas:/srv/backups").unwrap();
+/// let report = backup_remote(Path::new("/etc/nginx"), &target, &BackupOptions::new())?;
+/// println!("{}", report.path.display());
+/// # Ok::<(), backup::BackupError>(())
+/// ```
+pub fn backup_remote(
+    source: &Path,
+    target: &Remote,
+    options: &BackupOptions,
+) -> Result<BackupReport, BackupError> {
+    backup_to_store(source, target, options)
+}
+
+/// Backs up `source` under the prefix `target` of an S3 bucket, see
+/// [`crate::s3`], as [`backup_remote`] does to another machine.
+///
+/// The backup and its checksum manifest, metadata and signature files are
+/// uploaded as objects, and the path of the report is the URL of the backup,
+/// `s3://bucket/key`.
+///
+/// # Examples
+///
+/// ```no_run
+/// use std::path::Path;
+///
+/// use backup::backup::{backup_s3, BackupOptions};
+/// use backup::s3::Bucket;
+///
+/// let target = Bucket::parse("s3://backups/web1").unwrap();
+/// let report = backup_s3(Path::new("/etc/nginx"), &target, &BackupOptions::new())?;
+/// println!("{}", report.path.display());
+/// # Ok::<(), backup::BackupError>(())
+/// ```
+pub fn backup_s3(
+    source: &Path,
+    target: &Bucket,
+    options: &BackupOptions,
+) -> Result<BackupReport, BackupError> {
+    backup_to_store(source, target, options)
+}
+
+/// Backs up `source` through the command `target`, see [`crate::pipe`], as
+/// [`backup_remote`] does to another machine.
+///
+/// The command receives the backup on its standard input, and the path of
+/// the report is the file name of the backup.
+///
+/// # Examples
+///
+/// ```no_run
+/// use std::path::Path;
+///
+/// use backup::backup::{backup_pipe, BackupOptions};
+/// use backup::pipe::Pipe;
+///
+/// let target = Pipe::new("rclone rcat remote:backups/{filename}");
+/// let report = backup_pipe(Path::new("/etc/nginx"), &target, &BackupOptions::new())?;
+/// println!("{}", report.path.display());
+/// # Ok::<(), backup::BackupError>(())
+/// ```
+pub fn backup_pipe(
+    source: &Path,
+    target: &Pipe,
+    options: &BackupOptions,
+) -> Result<BackupReport, BackupError> {
+    let target = target.source(&source.to_string_lossy());
+    backup_to_store(source, &target, options)
+}
+
+/// Backs up `source` to each of `targets`, producing the same backup in
+/// every one, and returns the outcome for each target in the order given.
+///
+/// A directory backed up as an archive, compressed or encrypted as set in
+/// `options`, is read and written once, each target receiving a copy of the
+/// archive as it is written, see [`crate::target`]. Other backups are made
+/// into one target after the other, as [`backup`], [`backup_remote`] or
+/// [`backup_s3`] do, under the same name. A target failing does not stop the
+/// others, and an error is only returned for the backup as a whole, such as
+/// when the source cannot be read.
+///
+/// # Examples
+///
+/// ```no_run
+/// use std::path::{Path, PathBuf};
+///
+/// use backup::backup::{backup_to_targets, BackupOptions, Compression};
+/// use backup::remote::Remote;
+/// use backup::target::Target;
+///
+/// let targets = [
+///     Target::Local(PathBuf::from("/mnt/backups")),
+///     Target::Remote(Remote::parse("nas:/volume1/backups").unwrap()),
+/// ];
+/// let options = BackupOptions::new().compress(Compression::Zstd(3));
+/// let report = backup_to_targets(Path::new("/srv/data"), &targets, &options)?;
+/// println!("{} of {} targets failed", report.failures(), targets.len());
+/// # Ok::<(), backup::BackupError>(())
+/// ```
+pub fn backup_to_targets(
+    source: &Path,
+    targets: &[Target],
+    options: &BackupOptions,
+) -> Result<BatchReport, BackupError> {
+    if options.mirror || options.split.is_some() || options.as_file {
+        return Err(BackupError::InvalidOption(
+            "--mirror, --split and --as-file cannot be used with several targets".to_owned(),
+        ));
+    }
+    let is_dir = match options.dereference {
+        true => fs::metadata(source),
+        false => fs::symlink_metadata(source),
+    }
+    .map_err(|_| BackupError::NotFound(source.to_path_buf()))?
+    .is_dir();
+    let options = BackupOptions {
+        created: Some(options.created.unwrap_or_else(Local::now)),
+        ..options.clone()
+    };
+
+    let archive = options.compression != Compression::None
+        || !matches!(options.encryption(), Encryption::None);
+    let results = match is_dir && archive {
+        true => {
+            let stores: Vec<_> = targets
+                .iter()
+                .map(|target| TargetStore {
+                    target,
+                    fsync: options.fsync,
+                })
+                .collect();
+            backup_to_stores(source, &stores, &options)?
+        }
+        false => targets
+            .iter()
+            .map(|target| match target {
+                Target::Local(dir) => backup(source, dir, &options),
+                Target::Remote(remote) => backup_remote(source, remote, &options),
+                Target::S3(bucket) => backup_s3(source, bucket, &options),
+            })
+            .collect(),
+    };
+
+    let mut report = BatchReport::default();
+    for (target, result) in targets.iter().zip(results) {
+        match (target, &result) {
+            (Target::Local(_), Ok(backup))
+                if is_dir && archive && naming::format().has_timestamp() =>
+            {
+                if let Err(error) = update_latest(&backup.path) {
+                    writer::log(
+                        Level::Warning,
+                        format_args!(
+                            "'{}': Could not update the link to the latest backup: {error}",
+                            backup.path.display()
+                        ),
+                    );
+                }
+                let dir = backup.path.parent().unwrap_or(Path::new("."));
+                catalog::add(dir, &backup.path);
+            }
+            (_, Err(error @ BackupError::Unchanged(_))) => writer::log(Level::Info, error),
+            (_, Err(BackupError::Interrupted)) => {}
+            (_, Err(error)) => writer::log(Level::Error, format_args!("{target}: {error}")),
+            _ => {}
+        }
+        report.results.push((target.display_path(), result));
+    }
+    Ok(report)
+}
+
+/// Backs up `source` to `target`, see [`backup_remote`].
+fn backup_to_store<S: Store>(
+    source: &Path,
+    target: &S,
+    options: &BackupOptions,
+) -> Result<BackupReport, BackupError> {
+    if options.mirror
+        || options.incremental
+        || options.skip_unchanged
+        || options.split.is_some()
+        || options.as_file
+    {
+        return Err(BackupError::InvalidOption(
+            "--mirror, --incremental, --skip-unchanged, --split and --as-file cannot be used with a remote target"
+                .to_owned(),
+        ));
+    }
+    let mut results = backup_to_stores(source, std::slice::from_ref(target), options)?;
+    results.pop().expect("one outcome per target")
+}
+
+/// Backs up `source` to each of `targets` at once, see [`backup_to_targets`],
+/// returning the outcome for each target.
+fn backup_to_stores<S: Store>(
+    source: &Path,
+    targets: &[S],
+    options: &BackupOptions,
+) -> Result<Vec<Result<BackupReport, BackupError>>, BackupError> {
+    let started = Instant::now();
+    if options.passphrase.is_some() && !options.recipients.is_empty() {
+        return Err(BackupError::InvalidOption(
+            "--encrypt and --encrypt-passphrase cannot be combined".to_owned(),
+        ));
+    }
+    check_signing(options)?;
+    let metadata = match options.dereference {
+        true => fs::metadata(source),
+        false => fs::symlink_metadata(source),
+    }
+    .map_err(|_| BackupError::NotFound(source.to_path_buf()))?;
+    let encrypted = !matches!(options.encryption(), Encryption::None);
+    let (backup_type, extension) = match metadata.is_dir() {
+        true if encrypted => (
+            BackupType::DirectoryDirectory,
+            Some(format!(
+                "{}.{}",
+                options.compression.extension(),
+                age::EXTENSION
+            )),
+        ),
+        true => (
+            BackupType::DirectoryDirectory,
+            Some(options.compression.extension().to_owned()),
+        ),
+        false if encrypted => {
+            return Err(BackupError::InvalidOption(format!(
+                "'{}': Only directory archives can be encrypted",
+                source.display()
+            )))
+        }
+        false => (BackupType::FileDirectory, None),
+    };
+
+    let created = options.created.unwrap_or_else(Local::now);
+    let name = unique_path(
+        source,
+        Path::new(""),
+        created,
+        extension.as_deref(),
+        |name| targets.iter().any(|target| target.taken(name)),
+    )?;
+    let name = name
+        .to_str()
+        .ok_or_else(|| BackupError::InvalidName(name.clone()))?;
+    let mut tee = Tee {
+        locations: targets.iter().map(|target| target.location(name)).collect(),
+        uploads: targets
+            .iter()
+            .map(|target| target.create(name, options.force))
+            .collect(),
+    };
+    if tee.live() == 0 {
+        return Ok(tee
+            .uploads
+            .into_iter()
+            .filter_map(Result::err)
+            .map(Err)
+            .collect());
+    }
+    let destination = tee.locations[0].clone();
+    let mut writer = HashingWriter::new(
+        Counter {
+            inner: &mut tee,
+            bytes: 0,
+        },
+        options.checksum,
+    );
+    let written = match metadata.is_dir() {
+        true => stream(source, &mut writer, options),
+        false => File::open(source)
+            .and_then(|mut file| {
+                let mut reader = Interruptible(&mut file);
+                let throttle = options.throttle.as_deref();
+                reflink::copy_buffered(
+                    &mut reader,
+                    &mut Throttled(&mut writer, throttle),
+                    options.buffer_size,
+                )
+            })
+            .map(|bytes| BackupReport {
+                path: PathBuf::new(),
+                files: 1,
+                bytes,
+                linked: 0,
+                hardlinks: Hardlinks::default(),
+                special: 0,
+                too_deep: 0,
+                size_skipped: 0,
+                retried: 0,
+                up_to_date: 0,
+                removed: 0,
+                failed: Vec::new(),
+                duration: Duration::ZERO,
+                stats: Stats {
+                    files: 1,
+                    bytes_read: bytes,
+                    bytes_written: bytes,
+                    ..Stats::default()
+                },
+            })
+            .map_err(|e| match interrupt::is_interrupted() {
+                true => BackupError::Interrupted,
+                false => copy_error(source, &destination, e),
+            }),
+    };
+    let (counter, digest) = writer.finish();
+    let bytes = counter.bytes;
+    let report = match written {
+        Ok(report) => report,
+        // The error is that of the only upload left when there is one, see
+        // `Tee`, and otherwise of the source.
+        Err(error) => return tee.fail(error),
+    };
+
+    let sidecars = remote_sidecars(source, name, created, backup_type, digest, &report, options)?;
+    let duration = started.elapsed();
+    let results = targets
+        .iter()
+        .zip(tee.uploads)
+        .zip(tee.locations)
+        .map(|((target, upload), location)| {
+            commit_upload(target, upload?, &sidecars)?;
+            writer::log(
+                Level::Verbose,
+                format_args!("backed up {} to {}", source.display(), location.display()),
+            );
+            Ok(BackupReport {
+                path: location,
+                bytes,
+                duration,
+                stats: Stats {
+                    elapsed: duration,
+                    ..report.stats
+                },
+                ..report.clone()
+            })
+        })
+        .collect();
+    Ok(results)
+}
+
+/// Finishes writing `upload` to `target`, then writes the `sidecars` along
+/// with it if the target takes them, and puts them all into place.
+fn commit_upload<S: Store>(
+    target: &S,
+    upload: S::Upload,
+    sidecars: &[(String, Vec<u8>)],
+) -> Result<(), BackupError> {
+    let mut staged = vec![upload.finish()?];
+    let mut committed = Ok(());
+    if target.sidecars() {
+        for (name, contents) in sidecars {
+            match target.put(name, contents) {
+                Ok(file) => staged.push(file),
+                Err(error) => {
+                    committed = Err(error);
+                    break;
+                }
+            }
+        }
+    }
+    let committed = committed.and_then(|()| target.commit(&staged));
+    if committed.is_err() {
+        target.discard(&staged);
+    }
+    committed
+}
+
+/// Writer copying what is written to each upload still going. An upload
+/// failing is stopped, keeping its error, without failing the write, unless
+/// it is the only one left: its error is then that of the write.
+struct Tee<U> {
+    uploads: Vec<Result<U, BackupError>>,
+    /// Location of the file written by each upload, for errors.
+    locations: Vec<PathBuf>,
+}
+
+impl<U: Upload> Tee<U> {
+    /// Returns the number of uploads still going.
+    fn live(&self) -> usize {
+        self.uploads.iter().filter(|upload| upload.is_ok()).count()
+    }
+
+    /// Stops the upload at `index` after `error`.
+    fn stop(&mut self, index: usize, error: io::Error) {
+        let upload = std::mem::replace(&mut self.uploads[index], Err(BackupError::Interrupted));
+        if let Ok(upload) = upload {
+            self.uploads[index] = Err(upload.fail(BackupError::CreateFailed {
+                path: self.locations[index].clone(),
+                source: error,
+            }));
+        }
+    }
+
+    /// Stops every upload after the backup failed with `error`. With a single
+    /// upload left, the backup fails there only, and otherwise as a whole.
+    fn fail<T>(self, error: BackupError) -> Result<Vec<Result<T, BackupError>>, BackupError> {
+        if self.live() > 1 {
+            let upload = self.uploads.into_iter().find_map(Result::ok);
+            return Err(upload.expect("uploads left").fail(error));
+        }
+        let mut error = Some(error);
+        Ok(self
+            .uploads
+            .into_iter()
+            .map(|upload| match upload {
+                Ok(upload) => Err(upload.fail(error.take().expect("a single upload left"))),
+                Err(failed) => Err(failed),
+            })
+            .collect())
+    }
+}
+
+impl<U: Upload> Write for Tee<U> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        for index in 0..self.uploads.len() {
+            let single = self.live() == 1;
+            let Ok(upload) = &mut self.uploads[index] else {
+                continue;
+            };
+            if let Err(error) = upload.write_all(buf) {
+                if single {
+                    return Err(error);
+                }
+                self.stop(index, error);
+            }
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        for index in 0..self.uploads.len() {
+            let single = self.live() == 1;
+            let Ok(upload) = &mut self.uploads[index] else {
+                continue;
+            };
+            if let Err(error) = upload.flush() {
+                if single {
+                    return Err(error);
+                }
+                self.stop(index, error);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Returns the names and contents of the checksum manifest, metadata and
+/// signature files of the backup named `name` of `source` sent to another
+/// machine or a bucket, see [`backup_remote`].
+fn remote_sidecars(
+    source: &Path,
+    name: &str,
+    created: DateTime<Local>,
+    backup_type: BackupType,
+    digest: Option<String>,
+    report: &BackupReport,
+    options: &BackupOptions,
+) -> Result<Vec<(String, Vec<u8>)>, BackupError> {
+    let backup = Path::new(name);
+    let file_name = |path: PathBuf| path.to_string_lossy().into_owned();
+    let mut sidecars = Vec::new();
+    let checksum = match (options.checksum, digest) {
+        (Some(algorithm), Some(digest)) => {
+            let manifest =
+                checksum::manifest(OsStr::new(name), &[(PathBuf::new(), digest.clone())]);
+            let manifest_name = file_name(algorithm.manifest_path(backup));
+            let signature = options
+                .signing_key
+                .as_ref()
+                .map(|key| sign::signature(&manifest_name, &manifest, key));
+            sidecars.push((manifest_name, manifest));
+            if let Some(signature) = signature {
+                sidecars.push((file_name(sign::path(backup)), signature.into_bytes()));
+            }
+            Some(meta::Checksum {
+                algorithm: algorithm.extension().to_owned(),
+                digest,
+            })
+        }
+        _ => None,
+    };
+    if let Some(meta) = new_meta(source, options.dereference, created, backup_type, checksum)? {
+        let meta = BackupMeta {
+            size: report.bytes,
+            files: report.files,
+            ..meta
+        };
+        let contents = serde_json::to_vec_pretty(&meta).map_err(|e| BackupError::CreateFailed {
+            path: meta::path(backup),
+            source: e.into(),
+        })?;
+        sidecars.push((file_name(meta::path(backup)), contents));
+    }
+    Ok(sidecars)
+}
+
+/// Reader or writer counting the bytes read or written through it.
+pub(crate) struct Counter<T> {
+    pub(crate) inner: T,
+    pub(crate) bytes: u64,
+}
+
+impl<R: Read> Read for Counter<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = self.inner.read(buf)?;
+        self.bytes += read as u64;
+        Ok(read)
+    }
+}
+
+impl<W: Write> Write for Counter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        self.bytes += written as u64;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Computes what [`backup`] would do with the same arguments, without
+/// modifying the filesystem.
+///
+/// # Examples
+///
+/// ```no_run
+/// use std::path::Path;
+///
+/// use backup::backup::{execute, plan, BackupOptions};
+///
+/// let plan = plan(Path::new("notes.txt"), Path::new("."), &BackupOptions::new())?;
+/// if !plan.is_blocked() {
+///     execute(&plan)?;
+/// }
+/// # Ok::<(), backup::BackupError>(())
+/// ```
+pub fn plan(
+    source: &Path,
+    target: &Path,
+    options: &BackupOptions,
+) -> Result<BackupPlan, BackupError> {
+    if options.mirror {
+        return plan_mirror(source, target, options);
+    }
+    let BackupOptions {
+        dereference,
+        compression,
+        as_file,
+        ..
+    } = *options;
+    let backup_type = determine_backup_type(source, target, dereference, as_file)?;
+    let created = options.created.unwrap_or_else(Local::now);
+    check_not_inside(source, target, dereference)?;
+    let encrypted = !matches!(options.encryption(), Encryption::None);
+    if options.passphrase.is_some() && !options.recipients.is_empty() {
+        return Err(BackupError::InvalidOption(
+            "--encrypt and --encrypt-passphrase cannot be combined".to_owned(),
+        ));
+    }
+    check_signing(options)?;
+    if encrypted
+        && matches!(
+            backup_type,
+            BackupType::FileFile | BackupType::FileDirectory
+        )
+    {
+        return Err(BackupError::InvalidOption(format!(
+            "'{}': Only directory archives can be encrypted",
+            source.display()
+        )));
+    }
+    let split = options.split.is_some();
+    if split
+        && matches!(
+            backup_type,
+            BackupType::FileFile | BackupType::FileDirectory
+        )
+    {
+        return Err(BackupError::InvalidOption(format!(
+            "'{}': Only directory archives can be split",
+            source.display()
+        )));
+    }
+
+    let (destination, archive) = match backup_type {
+        BackupType::FileFile => (target.to_path_buf(), None),
+        BackupType::FileDirectory => (backup_path(source, target, created, None)?, None),
+        BackupType::DirectoryDirectory
+            if compression != Compression::None || encrypted || split =>
+        {
+            let extension = match encrypted {
+                true => Cow::Owned(format!("{}.{}", compression.extension(), age::EXTENSION)),
+                false => Cow::Borrowed(compression.extension()),
+            };
+            (
+                backup_path(source, target, created, Some(&extension))?,
+                Some(compression),
+            )
+        }
+        BackupType::DirectoryDirectory => (backup_path(source, target, created, None)?, None),
+        BackupType::DirectoryFile => (target.to_path_buf(), Some(compression)),
+    };
+
+    let previous = match (backup_type, archive) {
+        (BackupType::DirectoryDirectory, None) if options.incremental => {
+            list::backups_of(target, &source_name(source)?)
+                .into_iter()
+                .rev()
+                .find(|path| fs::symlink_metadata(path).is_ok_and(|metadata| metadata.is_dir()))
+        }
+        _ => None,
+    };
+
+    let unchanged_since = match backup_type {
+        BackupType::FileDirectory | BackupType::DirectoryDirectory if options.skip_unchanged => {
+            list::backups_of(target, &source_name(source)?)
+                .pop()
+                .filter(|previous| archive.is_none() && is_unchanged(source, previous, options))
+        }
+        _ => None,
+    };
+
+    let mut excluded = Vec::new();
+    let mut size_skipped = Vec::new();
+    let mut too_deep = Vec::new();
+    let mut reached_max_files = false;
+    let (files, bytes) = match backup_type {
+        BackupType::FileFile | BackupType::FileDirectory => {
+            let metadata = if dereference {
+                fs::metadata(source)
+            } else {
+                fs::symlink_metadata(source)
+            };
+            (1, metadata.map_or(0, |metadata| metadata.len()))
+        }
+        BackupType::DirectoryDirectory | BackupType::DirectoryFile => {
+            match Walker::new(source, dereference) {
+                Ok(walker) => {
+                    let mut walker = walker
+                        .exclude(&options.excludes)
+                        .ignore_files(options.ignore_files)
+                        .exclude_caches(options.exclude_caches)
+                        .max_depth(options.max_depth)
+                        .max_files(options.max_files)
+                        .min_file_size(options.min_file_size)
+                        .max_file_size(options.max_file_size);
+                    let totals = totals(&mut walker);
+                    excluded = walker.excluded().to_vec();
+                    size_skipped = walker.size_skipped().to_vec();
+                    too_deep = walker.too_deep().to_vec();
+                    reached_max_files = walker.reached_max_files();
+                    totals
+                }
+                Err(_) => (0, 0),
+            }
+        }
+    };
+
+    Ok(BackupPlan {
+        backup_type,
+        source: source.to_path_buf(),
+        created,
+        target_exists: fs::symlink_metadata(target).is_ok(),
+        destination_exists: fs::symlink_metadata(&destination).is_ok(),
+        destination,
+        previous,
+        unchanged_since,
+        up_to_date: 0,
+        stale: Vec::new(),
+        is_mirror: false,
+        archive,
+        files,
+        excluded,
+        size_skipped,
+        too_deep,
+        reached_max_files,
+        bytes,
+        free: free_space(target),
+        options: options.clone(),
+    })
+}
+
+/// Computes what mirroring `source` into `target` will do, see
+/// [`BackupOptions::mirror`]: the files and bytes planned are those to copy.
+fn plan_mirror(
+    source: &Path,
+    target: &Path,
+    options: &BackupOptions,
+) -> Result<BackupPlan, BackupError> {
+    if options.compression != Compression::None
+        || !matches!(options.encryption(), Encryption::None)
+        || options.signing_key.is_some()
+        || options.split.is_some()
+        || options.incremental
+        || options.skip_unchanged
+        || options.as_file
+    {
+        return Err(BackupError::InvalidOption(
+            "--mirror cannot be combined with --compress, --encrypt, --sign, --split, --incremental, --skip-unchanged or --as-file"
+                .to_owned(),
+        ));
+    }
+    let backup_type = determine_backup_type(source, target, options.dereference, false)?;
+    if backup_type != BackupType::DirectoryDirectory {
+        return Err(BackupError::InvalidOption(format!(
+            "'{}': Only directories can be mirrored, into a directory",
+            source.display()
+        )));
+    }
+    check_not_inside(source, target, options.dereference)?;
+
+    let mirror = Snapshot::mirror(target, options.compare_checksums);
+    let mut walker = Walker::new(source, options.dereference)?
+        .exclude(&options.excludes)
+        .ignore_files(options.ignore_files)
+        .exclude_caches(options.exclude_caches)
+        .max_depth(options.max_depth)
+        .max_files(options.max_files)
+        .min_file_size(options.min_file_size)
+        .max_file_size(options.max_file_size);
+    let (mut files, mut bytes, mut up_to_date) = (0, 0, 0);
+    for entry in walker.by_ref().flatten() {
+        if entry.metadata.is_dir() {
+            continue;
+        }
+        if mirror.same(&entry) {
+            up_to_date += 1;
+        } else {
+            files += 1;
+            bytes += entry.metadata.len();
+        }
+    }
+    let stale = match options.delete {
+        true => stale_entries(source, target, options.copy_options()),
+        false => Vec::new(),
+    };
+
+    Ok(BackupPlan {
+        backup_type,
+        source: source.to_path_buf(),
+        created: Local::now(),
+        target_exists: fs::symlink_metadata(target).is_ok(),
+        destination_exists: fs::symlink_metadata(target).is_ok(),
+        destination: target.to_path_buf(),
+        previous: None,
+        unchanged_since: None,
+        up_to_date,
+        stale,
+        is_mirror: is_mirror_of(source, target, options.dereference),
+        archive: None,
+        files,
+        excluded: walker.excluded().to_vec(),
+        size_skipped: walker.size_skipped().to_vec(),
+        too_deep: walker.too_deep().to_vec(),
+        reached_max_files: walker.reached_max_files(),
+        bytes,
+        free: free_space(target),
+        options: options.clone(),
+    })
+}
+
+/// Performs a backup previously computed by [`plan`].
+///
+/// The destination is checked again, so a backup created since the plan was
+/// computed is not overwritten unless forced. The lock of the backups of the
+/// source into the target is held while copying, see [`backup`].
+pub fn execute(plan: &BackupPlan) -> Result<BackupReport, BackupError> {
+    let started = Instant::now();
+    let source = &plan.source;
+    let destination = &plan.destination;
+    let options = &plan.options;
+    if let Some(previous) = &plan.unchanged_since {
+        return Err(BackupError::Unchanged(previous.clone()));
+    }
+    interrupt::check().map_err(|_| BackupError::Interrupted)?;
+    if !options.mirror {
+        check_overwrite(destination, options.force)?;
+    } else if !options.init && !is_mirror_of(source, destination, options.dereference) {
+        return Err(BackupError::NotAMirror(destination.clone()));
+    }
+    if options.space_check && plan.previous.is_none() {
+        check_space(destination, plan.bytes, plan.archive.is_some())?;
+    }
+    let dir = match options.mirror {
+        true => Some(destination.as_path()),
+        false => destination.parent(),
+    };
+    if let Some(dir) = dir.filter(|dir| !dir.as_os_str().is_empty()) {
+        fs::create_dir_all(dir).map_err(|source| BackupError::CreateFailed {
+            path: dir.to_path_buf(),
+            source,
+        })?;
+    }
+    let _lock = lock(source, destination, options)?;
+    let copied = match (plan.backup_type, plan.archive) {
+        _ if options.mirror => mirror_directory(source, destination, options),
+        (_, Some(_)) => backup_directory_file(source, destination, options).map(|copied| Copied {
+            files: plan.files,
+            ..copied
+        }),
+        (BackupType::DirectoryDirectory, None) => {
+            let previous = plan
+                .previous
+                .as_deref()
+                .map(|previous| Snapshot::load(previous, options.compare_checksums));
+            backup_directory_directory(source, destination, options, previous.as_ref())
+        }
+        _ => backup_file(source, destination, options),
+    };
+    // What was written is removed on failure, interrupted or not.
+    let copied = copied.map_err(|error| match interrupt::is_interrupted() {
+        true => BackupError::Interrupted,
+        false => error,
+    })?;
+
+    let checksum = match options.checksum.filter(|_| !options.mirror) {
+        Some(algorithm) => {
+            let manifest = checksum::write_manifest(destination, algorithm, &copied.digests)?;
+            if let Some(key) = &options.signing_key {
+                sign::sign(destination, &manifest, key)?;
+            }
+            let digest = match copied.digests.as_slice() {
+                [(path, digest)] if path.as_os_str().is_empty() => digest.clone(),
+                _ => checksum::hash_file(&manifest, algorithm).map_err(|source| {
+                    BackupError::ReadFailed {
+                        path: manifest,
+                        source,
+                    }
+                })?,
+            };
+            Some(meta::Checksum {
+                algorithm: algorithm.extension().to_owned(),
+                digest,
+            })
+        }
+        None => None,
+    };
+    if !options.mirror {
+        write_meta(plan, &copied, checksum)?;
+    }
+    let into_dir = matches!(
+        plan.backup_type,
+        BackupType::FileDirectory | BackupType::DirectoryDirectory
+    );
+    if into_dir && !options.mirror && naming::format().has_timestamp() {
+        if let Err(error) = update_latest(destination) {
+            writer::log(
+                Level::Warning,
+                format_args!(
+                    "'{}': Could not update the link to the latest backup: {error}",
+                    destination.display()
+                ),
+            );
+        }
+        let dir = destination.parent().unwrap_or(Path::new("."));
+        catalog::add(dir, destination);
+    }
+    let Copied { files, bytes, .. } = copied;
+
+    let mut linked = match plan.previous {
+        Some(_) => format!(", {} linked to the previous backup", copied.linked),
+        None => String::new(),
+    };
+    if options.mirror {
+        linked.push_str(&format!(
+            ", {} up to date, {} removed",
+            copied.up_to_date, copied.removed
+        ));
+    }
+    if copied.hardlinks.files > 0 {
+        linked.push_str(&format!(
+            ", {} files stored as hardlinks, saving {}",
+            copied.hardlinks.files,
+            writer::human_bytes(copied.hardlinks.bytes)
+        ));
+    }
+    if copied.special > 0 {
+        linked.push_str(&format!(", {} special files skipped", copied.special));
+    }
+    if copied.retried > 0 {
+        linked.push_str(&format!(", {} files needed retries", copied.retried));
+    }
+    if copied.size_skipped > 0 {
+        linked.push_str(&format!(
+            ", {} files skipped for their size",
+            copied.size_skipped
+        ));
+    }
+    if copied.too_deep > 0 {
+        linked.push_str(&format!(
+            ", contents of {} directories not backed up due to depth limit",
+            copied.too_deep
+        ));
+    }
+    if let Some(throttle) = &options.throttle {
+        let seconds = started.elapsed().as_secs_f64().max(f64::EPSILON);
+        linked.push_str(&format!(
+            ", written at {}/s with --bwlimit {}/s",
+            writer::human_bytes((bytes as f64 / seconds) as u64),
+            writer::human_bytes(throttle.rate())
+        ));
+    }
+    writer::log(
+        Level::Verbose,
+        format_args!(
+            "backed up {files} files ({}{linked}) to {}",
+            writer::human_bytes(bytes),
+            destination.display()
+        ),
+    );
+    let duration = started.elapsed();
+    let compressed = plan
+        .archive
+        .is_some_and(|archive| archive != Compression::None);
+    Ok(BackupReport {
+        path: destination.clone(),
+        files,
+        bytes,
+        linked: copied.linked,
+        hardlinks: copied.hardlinks,
+        special: copied.special,
+        too_deep: copied.too_deep,
+        size_skipped: copied.size_skipped,
+        retried: copied.retried,
+        up_to_date: copied.up_to_date,
+        removed: copied.removed,
+        failed: copied.failed.iter().map(ToString::to_string).collect(),
+        duration,
+        stats: copied.stats(compressed, duration),
+    })
+}
+
+/// Checks whether the copy of `source` at `previous` is identical to it, as
+/// far as a new backup with `options` would copy it.
+///
+/// Files are compared as for incremental backups, directories entry by entry,
+/// and symbolic links by target. A previous backup with entries missing from
+/// the source, or that cannot be read, differs.
+fn is_unchanged(source: &Path, previous: &Path, options: &BackupOptions) -> bool {
+    let snapshot = Snapshot::load(previous, options.compare_checksums);
+    let Ok(metadata) = fs::symlink_metadata(previous) else {
+        return false;
+    };
+    if !metadata.is_dir() {
+        let metadata = match options.dereference {
+            true => fs::metadata(source),
+            false => fs::symlink_metadata(source),
+        };
+        return metadata.is_ok_and(|metadata| {
+            let entry = Entry {
+                path: source.to_path_buf(),
+                relative: PathBuf::new(),
+                metadata,
+            };
+            !entry.metadata.is_dir() && snapshot.same(&entry)
+        });
+    }
+
+    let Ok(walker) = Walker::new(source, options.dereference) else {
+        return false;
+    };
+    let mut entries = 0;
+    for entry in walker
+        .exclude(&options.excludes)
+        .ignore_files(options.ignore_files)
+        .exclude_caches(options.exclude_caches)
+        .max_depth(options.max_depth)
+        .max_files(options.max_files)
+        .min_file_size(options.min_file_size)
+        .max_file_size(options.max_file_size)
+    {
+        match entry {
+            Ok(entry) if snapshot.same(&entry) => entries += 1,
+            _ => return false,
+        }
+    }
+    Walker::new(previous, false).is_ok_and(|walker| walker.count() == entries)
+}
+
+/// Writes the metadata file describing the backup performed for `plan`.
+///
+/// Sources whose absolute path is not valid UTF-8 cannot be described in JSON,
+/// and are left without one.
+fn write_meta(
+    plan: &BackupPlan,
+    copied: &Copied,
+    checksum: Option<meta::Checksum>,
+) -> Result<(), BackupError> {
+    let meta = new_meta(
+        &plan.source,
+        plan.options.dereference,
+        plan.created,
+        plan.backup_type,
+        checksum,
+    )?;
+    match meta {
+        Some(meta) => meta::write(
+            &plan.destination,
+            &BackupMeta {
+                size: copied.bytes,
+                files: copied.files,
+                parts: copied.parts.clone(),
+                ..meta
+            },
+        ),
+        None => Ok(()),
+    }
+}
+
+/// Describes the backup of `source` created at `created`, with no size nor
+/// files yet, or returns `None` with a warning if the path of the source
+/// cannot be recorded.
+fn new_meta(
+    source: &Path,
+    dereference: bool,
+    created: DateTime<Local>,
+    backup_type: BackupType,
+    checksum: Option<meta::Checksum>,
+) -> Result<Option<BackupMeta>, BackupError> {
+    let source = resolve_source(source, dereference)
+        .map_err(|_| BackupError::NotFound(source.to_path_buf()))?;
+    if source.to_str().is_none() {
+        writer::log(
+            Level::Warning,
+            format_args!(
+                "'{}': Path is not valid UTF-8, no metadata file written",
+                source.display()
+            ),
+        );
+        return Ok(None);
+    }
+
+    let created = match naming::format().is_utc() {
+        true => created.with_timezone(&Utc).fixed_offset(),
+        false => created.fixed_offset(),
+    };
+    Ok(Some(BackupMeta {
+        source,
+        created,
+        version: env!("CARGO_PKG_VERSION").to_owned(),
+        backup_type,
+        size: 0,
+        files: 0,
+        checksum,
+        parts: Vec::new(),
+    }))
+}
+
+/// Counts the non-directory entries below `source` and their total size.
+///
+/// Entries that cannot be read are left out; they are reported when the
+/// backup is executed.
+pub(crate) fn tree_totals(source: &Path, dereference: bool) -> (u64, u64) {
+    match Walker::new(source, dereference) {
+        Ok(mut walker) => totals(&mut walker),
+        Err(_) => (0, 0),
+    }
+}
+
+/// Counts the non-directory entries yielded by `walker` and their total size,
+/// leaving out the entries that cannot be read.
+fn totals(walker: &mut Walker) -> (u64, u64) {
+    walker
+        .take_while(|_| !interrupt::is_interrupted())
+        .filter_map(Result::ok)
+        .filter(|entry| !entry.metadata.is_dir())
+        .fold((0, 0), |(files, bytes), entry| {
+            (files + 1, bytes + entry.metadata.len())
+        })
+}
+
+/// Classifies the pair of paths into a [`BackupType`].
+///
+/// The source must exist. A missing target is a directory unless `as_file`
+/// is set or it is named like an archive. Unless `dereference` is set, a
+/// symbolic link source is classified as a file so the link itself is backed
+/// up, whereas a symbolic link target is always followed.
+fn determine_backup_type(
+    source: &Path,
+    target: &Path,
+    dereference: bool,
+    as_file: bool,
+) -> Result<BackupType, BackupError> {
+    let source_metadata =
+        fs::symlink_metadata(source).map_err(|_| BackupError::NotFound(source.to_path_buf()))?;
+    let source_metadata = if dereference && source_metadata.is_symlink() {
+        fs::metadata(source).map_err(|_| BackupError::DanglingLink(source.to_path_buf()))?
+    } else {
+        source_metadata
+    };
+    let target_is_dir = match fs::metadata(target) {
+        Ok(metadata) => metadata.is_dir(),
+        Err(_) if fs::symlink_metadata(target).is_ok() => {
+            return Err(BackupError::DanglingLink(target.to_path_buf()))
+        }
+        Err(_) => !as_file && !is_archive_name(target),
+    };
+
+    match (source_metadata.is_dir(), target_is_dir) {
+        (false, false) => Ok(BackupType::FileFile),
+        (false, true) => Ok(BackupType::FileDirectory),
+        (true, true) => Ok(BackupType::DirectoryDirectory),
+        (true, false) => Ok(BackupType::DirectoryFile),
+    }
+}
+
+/// Checks whether `path` ends with the extension of a tar archive, which a
+/// missing target is written as rather than created as a directory.
+fn is_archive_name(path: &Path) -> bool {
+    let name = path.to_string_lossy();
+    [".tar", ".tar.gz", ".tgz", ".tar.zst", ".tzst"]
+        .iter()
+        .any(|extension| name.ends_with(extension))
+}
+
+/// Fails if `target` resolves to `source` itself, or to a path inside it, as
+/// the backup would then copy itself over and over.
+fn check_not_inside(source: &Path, target: &Path, dereference: bool) -> Result<(), BackupError> {
+    let source_path = resolve_source(source, dereference);
+    let (Ok(source_path), Ok(target_path)) = (source_path, canonicalize_missing(target)) else {
+        return Ok(());
+    };
+
+    if target_path.starts_with(&source_path) || same_file(source, target, dereference) {
+        return Err(BackupError::TargetInsideSource {
+            source: source_path,
+            target: target_path,
+        });
+    }
+    Ok(())
+}
+
+/// Returns the canonical path of `source`.
+///
+/// Unless `dereference` is set, a symbolic link source is backed up as a link
+/// and only the directory holding it is resolved.
+fn resolve_source(source: &Path, dereference: bool) -> io::Result<PathBuf> {
+    let is_link = fs::symlink_metadata(source).is_ok_and(|metadata| metadata.is_symlink());
+    match (is_link && !dereference, source.parent(), source.file_name()) {
+        (true, Some(parent), Some(name)) => {
+            let parent = if parent.as_os_str().is_empty() {
+                Path::new(".")
+            } else {
+                parent
+            };
+            fs::canonicalize(parent).map(|parent| parent.join(name))
+        }
+        _ => fs::canonicalize(source),
+    }
+}
+
+/// Canonicalizes `path`, which may not exist yet, by resolving its deepest
+/// existing ancestor and appending the remaining components.
+fn canonicalize_missing(path: &Path) -> io::Result<PathBuf> {
+    let mut missing = Vec::new();
+    let mut existing = path;
+    loop {
+        match fs::canonicalize(existing) {
+            Ok(resolved) => {
+                return Ok(missing
+                    .iter()
+                    .rev()
+                    .fold(resolved, |path, name| path.join(name)))
+            }
+            Err(error) => match (existing.parent(), existing.file_name()) {
+                (Some(parent), Some(name)) => {
+                    missing.push(name);
+                    existing = if parent.as_os_str().is_empty() {
+                        Path::new(".")
+                    } else {
+                        parent
+                    };
+                }
+                _ => return Err(error),
+            },
+        }
+    }
+}
+
+/// Checks whether `source` and `target` are the same file, such as two hard
+/// links to it.
+#[cfg(unix)]
+fn same_file(source: &Path, target: &Path, dereference: bool) -> bool {
+    use std::os::unix::fs::MetadataExt;
+
+    let source = if dereference {
+        fs::metadata(source)
+    } else {
+        fs::symlink_metadata(source)
+    };
+    match (source, fs::metadata(target)) {
+        (Ok(source), Ok(target)) => source.dev() == target.dev() && source.ino() == target.ino(),
+        _ => false,
+    }
+}
+
+#[cfg(not(unix))]
+fn same_file(_source: &Path, _target: &Path, _dereference: bool) -> bool {
+    false
+}
+
+/// Copies the source file to the backup path, which is either a timestamped
+/// file inside the target directory or the target file itself, returning the
+/// number of bytes copied.
+///
+/// The copy is written to a temporary file next to the backup path, synced
+/// and renamed over it once complete, so an interrupted backup never leaves a
+/// truncated file behind.
+fn backup_file(
+    source: &Path,
+    backup_path: &Path,
+    options: &BackupOptions,
+) -> Result<Copied, BackupError> {
+    let partial = partial_path(backup_path);
+    let (result, retries) = copy_entry_retrying(source, &partial, options.copy_options());
+    let result = result.and_then(|copied| {
+        fs::rename(&partial, backup_path)
+            .and_then(|()| sync_parent(backup_path, options.fsync))
+            .map_err(|e| copy_error(source, backup_path, e))?;
+        Ok(copied)
+    });
+
+    let (bytes, digest) = result.inspect_err(|_| {
+        let _ = fs::remove_file(&partial);
+    })?;
+    Ok(Copied {
+        files: 1,
+        bytes,
+        read: bytes,
+        retried: u64::from(retries > 0),
+        digests: digest
+            .into_iter()
+            .map(|digest| (PathBuf::new(), digest))
+            .collect(),
+        ..Copied::default()
+    })
+}
+
+/// Copies the source tree into the timestamped backup directory, replacing it
+/// if it already exists.
+///
+/// The tree is copied into a temporary directory next to the backup path and
+/// renamed once the walk is complete, so an interrupted backup never leaves an
+/// incomplete directory looking like a backup. Entries that could not be
+/// copied are reported individually and do not prevent the rename, as with a
+/// regular copy. An existing backup is only removed once the new copy is done.
+fn backup_directory_directory(
+    source: &Path,
+    backup_path: &Path,
+    options: &BackupOptions,
+    previous: Option<&Snapshot>,
+) -> Result<Copied, BackupError> {
+    let partial = partial_path(backup_path);
+    if fs::symlink_metadata(&partial).is_ok() {
+        restore::remove(&partial)?;
+    }
+
+    let copy_options = CopyOptions {
+        previous,
+        ..options.copy_options()
+    };
+    let mut copied = copy_tree(source, &partial, copy_options);
+    if interrupt::is_interrupted() {
+        restore::remove(&partial)?;
+        return Err(BackupError::Interrupted);
+    }
+    if !options.keep_going && !copied.failed.is_empty() {
+        restore::remove(&partial)?;
+        return Err(first_failure(&mut copied));
+    }
+    if fs::symlink_metadata(backup_path).is_ok() {
+        restore::remove(backup_path)?;
+    }
+    fs::rename(&partial, backup_path)
+        .and_then(|()| sync_parent(backup_path, options.fsync))
+        .map_err(|e| copy_error(source, backup_path, e))?;
+    Ok(copied)
+}
+
+/// Updates the mirror of the source tree at `target` in place, copying the
+/// entries that differ from their copy there and, with
+/// [`BackupOptions::delete`], removing those gone from the source.
+///
+/// Nothing is removed once interrupted, or once an entry failed without
+/// keeping going, and what was already updated stays so.
+fn mirror_directory(
+    source: &Path,
+    target: &Path,
+    options: &BackupOptions,
+) -> Result<Copied, BackupError> {
+    if options.init {
+        let resolved = resolve_source(source, options.dereference)
+            .map_err(|_| BackupError::NotFound(source.to_path_buf()))?;
+        let marker = target.join(MIRROR_MARKER);
+        fs::write(&marker, format!("{}\n", resolved.to_string_lossy())).map_err(|source| {
+            BackupError::CreateFailed {
+                path: marker,
+                source,
+            }
+        })?;
+    }
+
+    let mirror = Snapshot::mirror(target, options.compare_checksums);
+    let copy_options = CopyOptions {
+        checksum: None,
+        mirror: Some(&mirror),
+        ..options.copy_options()
+    };
+    let mut copied = copy_tree(source, target, copy_options);
+    if interrupt::is_interrupted() {
+        return Err(BackupError::Interrupted);
+    }
+    if !options.keep_going && !copied.failed.is_empty() {
+        return Err(first_failure(&mut copied));
+    }
+    if options.delete {
+        for relative in stale_entries(source, target, copy_options) {
+            let path = target.join(relative);
+            match restore::remove(&path) {
+                Ok(()) => {
+                    writer::log(Level::Verbose, format_args!("{} (removed)", path.display()));
+                    copied.removed += 1;
+                }
+                Err(error) => report(&mut copied, error, copy_options),
+            }
+        }
+    }
+    Ok(copied)
+}
+
+/// Lists the entries of the mirror at `target` whose path no longer exists
+/// in `source`, relative to both, leaving out the contents of the
+/// directories listed. Entries left out of the copy by `options` are kept,
+/// as are the marker and lock of the mirror.
+fn stale_entries(source: &Path, target: &Path, options: CopyOptions) -> Vec<PathBuf> {
+    let Ok(walker) = Walker::new(target, false) else {
+        return Vec::new();
+    };
+    let walker = walker
+        .exclude(options.excludes)
+        .ignore_files(options.ignore_files)
+        .exclude_caches(options.exclude_caches)
+        .max_depth(options.max_depth);
+    let mut stale: Vec<PathBuf> = Vec::new();
+    for entry in walker.flatten() {
+        let relative = entry.relative;
+        let is_own = relative.parent() == Some(Path::new(""))
+            && (relative == Path::new(MIRROR_MARKER)
+                || relative.to_string_lossy().ends_with(LOCK_SUFFIX));
+        if is_own || stale.last().is_some_and(|dir| relative.starts_with(dir)) {
+            continue;
+        }
+        if fs::symlink_metadata(source.join(&relative)).is_err() {
+            stale.push(relative);
+        }
+    }
+    stale
+}
+
+/// Checks whether `target` is a mirror of `source`, its [`MIRROR_MARKER`]
+/// recording the absolute path of `source`.
+fn is_mirror_of(source: &Path, target: &Path, dereference: bool) -> bool {
+    let Ok(source) = resolve_source(source, dereference) else {
+        return false;
+    };
+    fs::read_to_string(target.join(MIRROR_MARKER))
+        .is_ok_and(|marked| marked.strip_suffix('\n') == Some(&*source.to_string_lossy()))
+}
+
+/// Creates the directory `path` of a mirror, unless it already is one,
+/// replacing any other kind of entry there.
+fn create_mirror_dir(path: &Path) -> io::Result<()> {
+    match fs::symlink_metadata(path) {
+        Ok(metadata) if metadata.is_dir() => return Ok(()),
+        Ok(_) => fs::remove_file(path)?,
+        Err(_) => {}
+    }
+    fs::create_dir(path)
+}
+
+/// Archives the source tree into the target file as a tarball, optionally compressed.
+///
+/// The archive is written to a temporary file next to the target and renamed
+/// over it once complete, so an interrupted backup never leaves a truncated
+/// archive behind. The bytes counted are the size of the archive, and the
+/// digest recorded is that of the archive itself; files are not counted.
+fn backup_directory_file(
+    source: &Path,
+    target: &Path,
+    options: &BackupOptions,
+) -> Result<Copied, BackupError> {
+    let compression = options.compression;
+    let encryption = options.encryption();
+    let algorithm = options.checksum;
+    let split = options.split;
+    let options = CopyOptions {
+        dereference: options.dereference,
+        preserve: options.preserve,
+        preserve_owner: false,
+        xattrs: options.xattrs,
+        reflink: options.reflink,
+        buffer_size: options.buffer_size,
+        fsync: options.fsync,
+        special_files: options.special_files,
+        excludes: &options.excludes,
+        ignore_files: options.ignore_files,
+        exclude_caches: options.exclude_caches,
+        max_depth: options.max_depth,
+        max_files: options.max_files,
+        min_file_size: options.min_file_size,
+        max_file_size: options.max_file_size,
+        checksum: None,
+        previous: None,
+        mirror: None,
+        jobs: 1,
+        keep_going: options.keep_going,
+        retries: 0,
+        retry_delay: Duration::ZERO,
+        throttle: options.throttle.as_deref(),
+    };
+    if let Some(size) = split {
+        return backup_split(
+            source,
+            target,
+            size,
+            compression,
+            encryption,
+            algorithm,
+            options,
+        );
+    }
+    let partial = partial_path(target);
+    let mut copied = Copied::default();
+    let result = File::create(&partial)
+        .map(|file| {
+            HashingWriter::new(
+                BufWriter::with_capacity(options.buffer_size, file),
+                algorithm,
+            )
+        })
+        .and_then(|file| {
+            write_encrypted(source, file, compression, encryption, options, &mut copied)
+        })
+        .and_then(|writer| {
+            let (file, digest) = writer.finish();
+            let file = file.into_inner().map_err(IntoInnerError::into_error)?;
+            if options.fsync != Fsync::None {
+                file.sync_all()?;
+            }
+            Ok((file.metadata()?.len(), digest))
+        })
+        .and_then(|written| fs::rename(&partial, target).map(|_| written))
+        .and_then(|written| sync_parent(target, options.fsync).map(|_| written));
+
+    let (bytes, digest) = result.map_err(|e| {
+        let _ = fs::remove_file(&partial);
+        copy_error(source, target, e)
+    })?;
+    Ok(Copied {
+        bytes,
+        digests: digest
+            .into_iter()
+            .map(|digest| (PathBuf::new(), digest))
+            .collect(),
+        ..copied
+    })
+}
+
+/// Archives the tree rooted at `source` in parts of at most `size` bytes
+/// named after `target`, see [`split`], as [`backup_directory_file`] does
+/// otherwise.
+fn backup_split(
+    source: &Path,
+    target: &Path,
+    size: u64,
+    compression: Compression,
+    encryption: Encryption,
+    algorithm: Option<Algorithm>,
+    options: CopyOptions,
+) -> Result<Copied, BackupError> {
+    let mut copied = Copied::default();
+    let parts = SplitWriter::new(
+        target,
+        size,
+        algorithm,
+        options.buffer_size,
+        options.fsync != Fsync::None,
+    );
+    let result = write_encrypted(
+        source,
+        HashingWriter::new(parts, algorithm),
+        compression,
+        encryption,
+        options,
+        &mut copied,
+    )
+    .and_then(|writer| {
+        let (parts, digest) = writer.finish();
+        Ok((parts.finish()?, digest))
+    })
+    .and_then(|written| sync_parent(target, options.fsync).map(|_| written));
+
+    let (parts, digest) = result.map_err(|e| copy_error(source, target, e))?;
+    Ok(Copied {
+        bytes: parts.iter().map(|part| part.size).sum(),
+        digests: digest
+            .into_iter()
+            .map(|digest| (PathBuf::new(), digest))
+            .collect(),
+        parts,
+        ..copied
+    })
+}
+
+/// Refuses signing backups without a checksum manifest to sign.
+fn check_signing(options: &BackupOptions) -> Result<(), BackupError> {
+    match options.signing_key.is_some() && options.checksum.is_none() {
+        true => Err(BackupError::InvalidOption(
+            "--sign requires a checksum manifest to sign".to_owned(),
+        )),
+        false => Ok(()),
+    }
+}
+
+/// How the archive of a backup is encrypted.
+#[derive(Clone, Copy)]
+enum Encryption<'a> {
+    None,
+    /// To the recipients, see [`BackupOptions::recipient`].
+    Recipients(&'a [Recipient]),
+    /// With the passphrase at the work factor, see
+    /// [`BackupOptions::passphrase`].
+    Passphrase(&'a Passphrase, u8),
+}
+
+/// Writes the tree rooted at `source` as a tar archive compressed with
+/// `compression` into `writer`, encrypted as `encryption` says, see
+/// [`write_compressed`].
+fn write_encrypted<W: Write>(
+    source: &Path,
+    writer: W,
+    compression: Compression,
+    encryption: Encryption,
+    options: CopyOptions,
+    copied: &mut Copied,
+) -> io::Result<W> {
+    let encryptor = match encryption {
+        Encryption::None => return write_compressed(source, writer, compression, options, copied),
+        Encryption::Recipients(recipients) => Encryptor::new(writer, recipients)?,
+        Encryption::Passphrase(passphrase, work_factor) => {
+            Encryptor::with_passphrase(writer, passphrase, work_factor)?
+        }
+    };
+    write_compressed(source, encryptor, compression, options, copied)?.finish()
+}
+
+/// Writes the tree rooted at `source` as a tar archive compressed with
+/// `compression` into `writer`, see [`write_archive`].
+fn write_compressed<W: Write>(
+    source: &Path,
+    writer: W,
+    compression: Compression,
+    options: CopyOptions,
+    copied: &mut Copied,
+) -> io::Result<W> {
+    match compression {
+        Compression::None => write_archive(source, writer, options, copied),
+        Compression::Gzip(level) => {
+            let encoder = GzEncoder::new(writer, flate2::Compression::new(level));
+            write_archive(source, encoder, options, copied)?.finish()
+        }
+        Compression::Zstd(level) => {
+            let encoder = zstd::Encoder::new(writer, level as i32)?;
+            write_archive(source, encoder, options, copied)?.finish()
+        }
+    }
+}
+
+/// Writes the tree rooted at `source` as a tar archive into `writer`.
+///
+/// Entries are stored with paths relative to `source`, along with their modes
+/// and modification times unless preservation is disabled, in which case
+/// default modes and a fixed time are stored. Symbolic links are stored as
+/// links unless dereferencing. Files hard linked to one archived before are
+/// stored as links to it, and special files skipped as set in `options`, both
+/// counted in `copied`. The first entry that cannot be archived aborts the
+/// whole archive.
+fn write_archive<W: Write>(
+    source: &Path,
+    writer: W,
+    options: CopyOptions,
+    copied: &mut Copied,
+) -> io::Result<W> {
+    let mut builder = tar::Builder::new(Interruptible(Throttled(writer, options.throttle)));
+    builder.follow_symlinks(options.dereference);
+    let mode = match options.preserve {
+        true => tar::HeaderMode::Complete,
+        false => tar::HeaderMode::Deterministic,
+    };
+    builder.mode(mode);
+    if options.xattrs {
+        append_xattrs(&mut builder, source)?;
+    }
+    builder.append_dir(".", source)?;
+
+    let mut walker = Walker::new(source, options.dereference)
+        .map_err(io::Error::other)?
+        .exclude(options.excludes)
+        .ignore_files(options.ignore_files)
+        .exclude_caches(options.exclude_caches)
+        .max_depth(options.max_depth)
+        .max_files(options.max_files)
+        .min_file_size(options.min_file_size)
+        .max_file_size(options.max_file_size);
+    let mut inodes = Inodes::default();
+    for entry in walker.by_ref() {
+        let entry = entry.map_err(io::Error::other)?;
+        if is_special(&entry.metadata) {
+            match options.special_files {
+                SpecialFiles::Fail => {
+                    return Err(io::Error::other(BackupError::SpecialFile(entry.path)))
+                }
+                SpecialFiles::Record if !is_socket(&entry.metadata) => {}
+                _ => {
+                    skip_special(copied, &entry);
+                    continue;
+                }
+            }
+        }
+        let first = inodes.first(&entry);
+        match &first {
+            Some(first) => log_link(&entry, first),
+            None if !entry.metadata.is_dir() => log_entry(&entry),
+            None => {}
+        }
+        if options.xattrs {
+            append_xattrs(&mut builder, &entry.path)?;
+        }
+        match first {
+            Some(first) => {
+                let mut header = tar::Header::new_gnu();
+                header.set_metadata_in_mode(&entry.metadata, mode);
+                header.set_entry_type(tar::EntryType::Link);
+                header.set_size(0);
+                builder.append_link(&mut header, &entry.relative, &first)?;
+                copied.hardlinks.add(&entry);
+            }
+            None if is_special(&entry.metadata) => append_special(&mut builder, &entry, mode)?,
+            None => {
+                builder.append_path_with_name(&entry.path, &entry.relative)?;
+                if entry.metadata.is_file() {
+                    copied.read += entry.metadata.len();
+                }
+            }
+        }
+    }
+    log_excluded(source, walker.excluded());
+    copied.excluded = walker.excluded().len() as u64;
+    copied.size_skipped = log_size_skipped(source, walker.size_skipped(), options);
+    copied.too_deep = log_too_deep(source, walker.too_deep());
+    check_max_files(&walker, source, options).map_err(io::Error::other)?;
+
+    builder.into_inner().map(|writer| writer.0 .0)
+}
+
+/// Appends the special file `entry` to `builder` under its relative path,
+/// which [`tar::Builder::append_path_with_name`] does not give it.
+#[cfg(unix)]
+fn append_special<W: Write>(
+    builder: &mut tar::Builder<W>,
+    entry: &Entry,
+    mode: tar::HeaderMode,
+) -> io::Result<()> {
+    use std::os::unix::fs::{FileTypeExt, MetadataExt};
+
+    let file_type = entry.metadata.file_type();
+    let entry_type = if file_type.is_fifo() {
+        tar::EntryType::Fifo
+    } else if file_type.is_char_device() {
+        tar::EntryType::Char
+    } else {
+        tar::EntryType::Block
+    };
+    let mut header = tar::Header::new_gnu();
+    header.set_metadata_in_mode(&entry.metadata, mode);
+    header.set_entry_type(entry_type);
+    header.set_size(0);
+    let device = entry.metadata.rdev() as libc::dev_t;
+    header.set_device_major(libc::major(device) as u32)?;
+    header.set_device_minor(libc::minor(device) as u32)?;
+    builder.append_data(&mut header, &entry.relative, io::empty())
+}
+
+#[cfg(not(unix))]
+fn append_special<W: Write>(
+    _builder: &mut tar::Builder<W>,
+    _entry: &Entry,
+    _mode: tar::HeaderMode,
+) -> io::Result<()> {
+    Err(io::Error::from(io::ErrorKind::Unsupported))
+}
+
+/// Records the extended attributes of `path` in a pax extended header,
+/// applying to the entry appended next.
+fn append_xattrs<W: Write>(builder: &mut tar::Builder<W>, path: &Path) -> io::Result<()> {
+    let records = xattrs::pax_records(path)?;
+    if records.is_empty() {
+        return Ok(());
+    }
+    builder.append_pax_extensions(
+        records
+            .iter()
+            .map(|(key, value)| (key.as_str(), value.as_slice())),
+    )
+}
+
+/// Logs a file being backed up, with its size, at verbose level.
+fn log_entry(entry: &Entry) {
+    writer::log(
+        Level::Verbose,
+        format_args!(
+            "{} ({})",
+            entry.path.display(),
+            writer::human_bytes(entry.metadata.len())
+        ),
+    );
+}
+
+/// Logs a file being backed up as a hard link to the file `first` of the same
+/// tree, at verbose level.
+fn log_link(entry: &Entry, first: &Path) {
+    writer::log(
+        Level::Verbose,
+        format_args!(
+            "{} (hard link to {})",
+            entry.path.display(),
+            first.display()
+        ),
+    );
+}
+
+/// Logs the entries of `source` left out by exclude patterns, at verbose level.
+fn log_excluded(source: &Path, excluded: &[PathBuf]) {
+    for path in excluded {
+        writer::log(
+            Level::Verbose,
+            format_args!("excluded {}", source.join(path).display()),
+        );
+    }
+}
+
+/// Logs the files of `source` left out for their size, each at verbose level,
+/// and returns their number.
+fn log_size_skipped(source: &Path, skipped: &[(PathBuf, u64)], options: CopyOptions) -> u64 {
+    for (path, bytes) in skipped {
+        let limit = match options.min_file_size {
+            Some(min) if *bytes < min => "smaller than --min-file-size",
+            _ => "larger than --max-file-size",
+        };
+        writer::log(
+            Level::Verbose,
+            format_args!(
+                "skipped {} ({}), {limit}",
+                source.join(path).display(),
+                writer::human_bytes(*bytes)
+            ),
+        );
+    }
+    skipped.len() as u64
+}
+
+/// Logs the directories of `source` whose contents were left out by the depth
+/// limit, each at verbose level and their number as a warning, and returns
+/// that number.
+fn log_too_deep(source: &Path, too_deep: &[PathBuf]) -> u64 {
+    for path in too_deep {
+        writer::log(
+            Level::Verbose,
+            format_args!("beyond max depth {}", source.join(path).display()),
+        );
+    }
+    if !too_deep.is_empty() {
+        writer::log(
+            Level::Warning,
+            format_args!(
+                "'{}': Contents of {} directories not backed up due to depth limit",
+                source.display(),
+                too_deep.len()
+            ),
+        );
+    }
+    too_deep.len() as u64
+}
+
+/// Checks whether the walk of `source` stopped at the limit of
+/// [`CopyOptions::max_files`], which is an error unless keeping going, in
+/// which case it only warns that the backup is partial.
+fn check_max_files(
+    walker: &Walker,
+    source: &Path,
+    options: CopyOptions,
+) -> Result<(), BackupError> {
+    let Some(limit) = options.max_files.filter(|_| walker.reached_max_files()) else {
+        return Ok(());
+    };
+    if !options.keep_going {
+        return Err(BackupError::TooManyFiles {
+            path: source.to_path_buf(),
+            limit,
+        });
+    }
+    writer::log(
+        Level::Warning,
+        format_args!(
+            "'{}': Stopped after {limit} files as set with --max-files, the backup is partial",
+            source.display()
+        ),
+    );
+    Ok(())
+}
+
+/// Returns the hidden temporary path used while `path` is being written.
+pub(crate) fn partial_path(path: &Path) -> PathBuf {
+    let mut name = OsString::from(".");
+    name.push(path.file_name().unwrap_or(path.as_os_str()));
+    name.push(".partial");
+    path.with_file_name(name)
+}
+
+/// Points the link to the newest backup of the name of the backup at
+/// `destination`, see [`naming::latest_name`], to it, or to its first part if
+/// it is split, see [`split::first_part`]. The link is made under
+/// a temporary name then renamed over the previous one, so that it always
+/// leads to a complete backup. An entry of that name that is not a link is
+/// left alone.
+pub(crate) fn update_latest(destination: &Path) -> io::Result<()> {
+    let (Some(dir), Some(name)) = (destination.parent(), destination.file_name()) else {
+        return Ok(());
+    };
+    let Some(parsed) = restore::BackupName::parse(name) else {
+        return Ok(());
+    };
+    let link = dir.join(naming::latest_name(&parsed.original));
+    let first_part = split::first_part(destination);
+    let name = first_part.file_name().unwrap_or(name);
+    if fs::symlink_metadata(&link).is_ok_and(|metadata| !metadata.is_symlink()) {
+        return Err(io::Error::new(
+            io::ErrorKind::AlreadyExists,
+            format!("'{}' is not a link", link.display()),
+        ));
+    }
+
+    let partial = partial_path(&link);
+    let _ = fs::remove_file(&partial);
+    symlink(Path::new(name), &partial)?;
+    fs::rename(&partial, &link).inspect_err(|_| {
+        let _ = fs::remove_file(&partial);
+    })
+}
+
+/// Removes the link to the newest backup of the name of the backup at
+/// `backup` if it points to it, as when that backup is undone.
+pub(crate) fn remove_latest(backup: &Path) -> io::Result<()> {
+    let (Some(dir), Some(name)) = (backup.parent(), backup.file_name()) else {
+        return Ok(());
+    };
+    let Some(parsed) = restore::BackupName::parse(name) else {
+        return Ok(());
+    };
+    let link = dir.join(naming::latest_name(&parsed.original));
+    let first_part = split::part_path(Path::new(name), 0);
+    match fs::read_link(&link) {
+        Ok(target) if target == Path::new(name) || target == first_part => fs::remove_file(&link),
+        _ => Ok(()),
+    }
+}
+
+/// Creates at `link` a symbolic link to `target`.
+#[cfg(unix)]
+pub(crate) fn symlink(target: &Path, link: &Path) -> io::Result<()> {
+    std::os::unix::fs::symlink(target, link)
+}
+
+/// Links need a privilege on Windows that backups should not require, so
+/// none is made there.
+#[cfg(not(unix))]
+pub(crate) fn symlink(_target: &Path, _link: &Path) -> io::Result<()> {
+    Err(io::Error::from(io::ErrorKind::Unsupported))
+}
+
+/// Digests of copied files, by path relative to the root of the copy.
+pub(crate) type Digests = Vec<(PathBuf, String)>;
+
+/// Recursively copies the directory `source` to `target`, creating `target` if needed.
+///
+/// Failures on individual entries are logged and do not stop the copy of the
+/// remaining entries; an error is returned at the end if any entry could not
+/// be copied. Returns the number of files and bytes copied, and the digests
+/// of the files by relative path if [`CopyOptions::checksum`] is set.
+pub(crate) fn copy_directory(
+    source: &Path,
+    target: &Path,
+    options: CopyOptions,
+) -> Result<(u64, u64, Digests), BackupError> {
+    let copied = copy_tree(source, target, options);
+    let (files, bytes) = copied.result(source)?;
+    Ok((files, bytes, copied.digests))
+}
+
+/// Counts of a recursive copy.
+#[derive(Default)]
+struct Copied {
+    files: u64,
+    bytes: u64,
+    /// Number of bytes read from the source, which differs from `bytes` for
+    /// archives.
+    read: u64,
+    /// Number of entries left out by exclude patterns.
+    excluded: u64,
+    /// Errors of the entries that could not be copied, already logged if
+    /// keeping going.
+    failed: Vec<BackupError>,
+    /// Number of files hard linked from a previous backup, out of `files`.
+    linked: u64,
+    /// Files hard linked to another file of the copy, out of `files`.
+    hardlinks: Hardlinks,
+    /// Number of special files left out, not counted in `files`.
+    special: u64,
+    /// Number of directories whose contents were left out by the depth limit.
+    too_deep: u64,
+    /// Number of files left out for their size, not counted in `files`.
+    size_skipped: u64,
+    /// Number of files copied again after a transient error.
+    retried: u64,
+    /// Number of files of a mirror left as they were, out of `files`.
+    up_to_date: u64,
+    /// Number of entries removed from a mirror.
+    removed: u64,
+    /// Digests of the files copied, by path relative to the copy root.
+    digests: Digests,
+    /// Parts of a split archive.
+    parts: Vec<meta::Part>,
+}
+
+impl Copied {
+    /// Adds the counts of `other` to these.
+    fn merge(&mut self, other: Copied) {
+        self.files += other.files;
+        self.bytes += other.bytes;
+        self.read += other.read;
+        self.excluded += other.excluded;
+        self.failed.extend(other.failed);
+        self.linked += other.linked;
+        self.hardlinks.files += other.hardlinks.files;
+        self.hardlinks.bytes += other.hardlinks.bytes;
+        self.special += other.special;
+        self.too_deep += other.too_deep;
+        self.size_skipped += other.size_skipped;
+        self.retried += other.retried;
+        self.up_to_date += other.up_to_date;
+        self.removed += other.removed;
+        self.digests.extend(other.digests);
+    }
+
+    /// Gathers the counts into [`Stats`], for a copy that took `elapsed` and
+    /// whose output is `compressed` or not.
+    fn stats(&self, compressed: bool, elapsed: Duration) -> Stats {
+        Stats {
+            files: self.files,
+            bytes_read: self.read,
+            bytes_written: self.bytes,
+            compressed,
+            skipped: self.special + self.size_skipped,
+            excluded: self.excluded,
+            failed: self.failed.len() as u64,
+            elapsed,
+        }
+    }
+
+    /// Returns the number of files and bytes copied from `source`, or an
+    /// error if any entry could not be copied.
+    fn result(&self, source: &Path) -> Result<(u64, u64), BackupError> {
+        if !self.failed.is_empty() {
+            return Err(BackupError::PartialCopy {
+                path: source.to_path_buf(),
+                failures: self.failed.len(),
+            });
+        }
+
+        Ok((self.files, self.bytes))
+    }
+}
+
+/// Files of a backup stored as hard links to another of its files, reported
+/// in [`BackupReport::hardlinks`].
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Hardlinks {
+    /// Number of files stored as hard links.
+    pub files: u64,
+    /// Total size of those files, which is not stored again.
+    pub bytes: u64,
+}
+
+impl Hardlinks {
+    /// Counts `entry` as stored as a hard link.
+    fn add(&mut self, entry: &Entry) {
+        self.files += 1;
+        self.bytes += entry.metadata.len();
+    }
+}
+
+/// Relative paths of the files with several hard links met so far in a
+/// walk, by device and inode, so that the next ones met are stored as links
+/// to the first.
+#[derive(Default)]
+struct Inodes(HashMap<(u64, u64), PathBuf>);
+
+impl Inodes {
+    /// Returns the relative path of the file met before that `entry` is a
+    /// hard link to, or records `entry` if it is the first met of its inode.
+    fn first(&mut self, entry: &Entry) -> Option<PathBuf> {
+        let inode = inode(&entry.metadata)?;
+        match self.0.get(&inode) {
+            Some(first) => Some(first.clone()),
+            None => {
+                self.0.insert(inode, entry.relative.clone());
+                None
+            }
+        }
+    }
+}
+
+/// Returns the device and inode of a regular file with several hard links.
+#[cfg(unix)]
+fn inode(metadata: &Metadata) -> Option<(u64, u64)> {
+    use std::os::unix::fs::MetadataExt;
+
+    (metadata.is_file() && metadata.nlink() > 1).then(|| (metadata.dev(), metadata.ino()))
+}
+
+#[cfg(not(unix))]
+fn inode(_metadata: &Metadata) -> Option<(u64, u64)> {
+    None
+}
+
+/// Checks whether `metadata` is that of a special file, such as a named pipe,
+/// a socket or a device, rather than a file, directory or symbolic link.
+fn is_special(metadata: &Metadata) -> bool {
+    !metadata.is_file() && !metadata.is_dir() && !metadata.is_symlink()
+}
+
+#[cfg(unix)]
+fn is_socket(metadata: &Metadata) -> bool {
+    use std::os::unix::fs::FileTypeExt;
+
+    metadata.file_type().is_socket()
+}
+
+#[cfg(not(unix))]
+fn is_socket(_metadata: &Metadata) -> bool {
+    false
+}
+
+/// Names the kind of the special file described by `metadata`.
+#[cfg(unix)]
+fn special_kind(metadata: &Metadata) -> &'static str {
+    use std::os::unix::fs::FileTypeExt;
+
+    let file_type = metadata.file_type();
+    if file_type.is_fifo() {
+        "named pipe"
+    } else if file_type.is_socket() {
+        "socket"
+    } else if file_type.is_block_device() {
+        "block device"
+    } else if file_type.is_char_device() {
+        "character device"
+    } else {
+        "special file"
+    }
+}
+
+#[cfg(not(unix))]
+fn special_kind(_metadata: &Metadata) -> &'static str {
+    "special file"
+}
+
+/// Leaves the special file `entry` out of a copy, with a warning.
+fn skip_special(copied: &mut Copied, entry: &Entry) {
+    writer::log(
+        Level::Warning,
+        format_args!(
+            "'{}': Skipping {}",
+            entry.path.display(),
+            special_kind(&entry.metadata)
+        ),
+    );
+    copied.special += 1;
+}
+
+/// Counts `error` as a failure of the copy, logging it if keeping going.
+/// Otherwise, the copy stops and the first failure is returned instead, see
+/// [`first_failure`].
+fn report(copied: &mut Copied, error: BackupError, options: CopyOptions) {
+    if options.keep_going {
+        writer::log(Level::Error, &error);
+    }
+    copied.failed.push(error);
+}
+
+/// Takes the first failure of a copy that stopped on it, logging the others
+/// met by concurrent jobs meanwhile, which are not returned.
+fn first_failure(copied: &mut Copied) -> BackupError {
+    for error in copied.failed.drain(1..) {
+        writer::log(Level::Error, &error);
+    }
+    copied.failed.swap_remove(0)
+}
+
+/// Copies the tree rooted at `source` to `target`.
+///
+/// Directories are created in walk order by the calling thread, so that they
+/// exist before their contents, while the other entries are copied by as many
+/// threads as [`CopyOptions::jobs`], or by the calling thread if it is one.
+/// Digests are sorted by path so that the result does not depend on which
+/// thread copied what.
+///
+/// When preserving metadata, directories get their mode and modification
+/// time once their whole contents have been copied, deepest first, so that
+/// neither copying into them nor a read-only mode gets in the way.
+fn copy_tree(source: &Path, target: &Path, options: CopyOptions) -> Copied {
+    let target = &*walk::long_path(target);
+    let mut copied = Copied::default();
+
+    if let Err(e) = fs::create_dir_all(target) {
+        let error = BackupError::CreateFailed {
+            path: target.to_path_buf(),
+            source: e,
+        };
+        report(&mut copied, error, options);
+        return copied;
+    }
+
+    let mut walker = match Walker::new(source, options.dereference) {
+        Ok(walker) => walker
+            .exclude(options.excludes)
+            .ignore_files(options.ignore_files)
+            .exclude_caches(options.exclude_caches)
+            .max_depth(options.max_depth)
+            .max_files(options.max_files)
+            .min_file_size(options.min_file_size)
+            .max_file_size(options.max_file_size),
+        Err(error) => {
+            report(&mut copied, error, options);
+            return copied;
+        }
+    };
+
+    let mut directories = Vec::new();
+    if let Ok(metadata) = fs::metadata(source) {
+        if options.preserve_owner {
+            if let Err(error) = preserve_owner(target, &metadata) {
+                report(&mut copied, error, options);
+            }
+        }
+        if options.xattrs {
+            if let Err(error) = copy_xattrs(source, target) {
+                report(&mut copied, error, options);
+            }
+        }
+        directories.push((target.to_path_buf(), metadata));
+    }
+
+    let stop = AtomicBool::new(false);
+    let mut inodes = Inodes::default();
+    let mut links = Vec::new();
+    let (sender, receiver) = mpsc::channel::<Entry>();
+    let receiver = Mutex::new(receiver);
+    thread::scope(|scope| {
+        let threads = if options.jobs > 1 { options.jobs } else { 0 };
+        let workers: Vec<_> = (0..threads)
+            .map(|_| {
+                scope.spawn(|| {
+                    let mut copied = Copied::default();
+                    loop {
+                        let entry = receiver.lock().map(|receiver| receiver.recv());
+                        let Ok(Ok(entry)) = entry else {
+                            break copied;
+                        };
+                        if !stop.load(Ordering::Relaxed) {
+                            copy_file_entry(&mut copied, entry, target, options);
+                            check_stop(&copied, options, &stop);
+                        }
+                    }
+                })
+            })
+            .collect();
+
+        copy_entries(
+            &mut copied,
+            &mut walker,
+            target,
+            options,
+            &mut directories,
+            &stop,
+            |copied, entry| {
+                if let Some(first) = inodes.first(&entry) {
+                    links.push((first, entry));
+                } else if workers.is_empty() {
+                    copy_file_entry(copied, entry, target, options);
+                } else if let Err(mpsc::SendError(entry)) = sender.send(entry) {
+                    copy_file_entry(copied, entry, target, options);
+                }
+            },
+        );
+        drop(sender);
+        for worker in workers {
+            if let Ok(worker) = worker.join() {
+                copied.merge(worker);
+            }
+        }
+    });
+    copied.digests.sort();
+
+    // Linked once their first file is copied, by whichever worker.
+    if !links.is_empty() && !stop.load(Ordering::Relaxed) {
+        let digests: HashMap<_, _> = copied.digests.iter().cloned().collect();
+        for (first, entry) in links {
+            let digest = digests.get(&first).cloned();
+            link_file_entry(&mut copied, &first, digest, entry, target, options);
+        }
+        copied.digests.sort();
+    }
+
+    log_excluded(source, walker.excluded());
+    copied.excluded = walker.excluded().len() as u64;
+    copied.size_skipped = log_size_skipped(source, walker.size_skipped(), options);
+    copied.too_deep = log_too_deep(source, walker.too_deep());
+    if let Err(error) = check_max_files(&walker, source, options) {
+        report(&mut copied, error, options);
+    }
+
+    if options.preserve && !stop.load(Ordering::Relaxed) {
+        for (path, metadata) in directories.iter().rev() {
+            if let Err(error) = preserve_metadata(path, metadata) {
+                report(&mut copied, error, options);
+            }
+        }
+    }
+    if options.fsync == Fsync::Dir && !stop.load(Ordering::Relaxed) {
+        for (path, _) in directories.iter().rev() {
+            if let Err(error) = sync_dir(path) {
+                report(&mut copied, copy_error(source, path, error), options);
+            }
+        }
+    }
+
+    copied
+}
+
+/// Asks every thread of a copy to stop once `copied` has failures, unless
+/// keeping going, or once interrupted.
+fn check_stop(copied: &Copied, options: CopyOptions, stop: &AtomicBool) {
+    if interrupt::is_interrupted() || (!options.keep_going && !copied.failed.is_empty()) {
+        stop.store(true, Ordering::Relaxed);
+    }
+}
+
+/// Walks the entries of `walker`, creating directories below `target` and
+/// recording them in `directories`, and hands every other entry to `copy`,
+/// until `stop` is set.
+fn copy_entries(
+    copied: &mut Copied,
+    walker: &mut Walker,
+    target: &Path,
+    options: CopyOptions,
+    directories: &mut Vec<(PathBuf, Metadata)>,
+    stop: &AtomicBool,
+    mut copy: impl FnMut(&mut Copied, Entry),
+) {
+    for entry in walker {
+        check_stop(copied, options, stop);
+        if stop.load(Ordering::Relaxed) {
+            break;
+        }
+        let entry = match entry {
+            Ok(entry) => entry,
+            Err(error) => {
+                report(copied, error, options);
+                continue;
+            }
+        };
+
+        let destination = target.join(&entry.relative);
+        if entry.metadata.is_dir() {
+            let created = match options.mirror {
+                Some(_) => create_mirror_dir(&destination),
+                None => fs::create_dir(&destination),
+            };
+            let created = created
+                .map_err(|e| BackupError::CreateFailed {
+                    path: destination.clone(),
+                    source: e,
+                })
+                .and_then(|()| {
+                    if options.preserve_owner {
+                        preserve_owner(&destination, &entry.metadata)
+                    } else {
+                        Ok(())
+                    }
+                })
+                .and_then(|()| match options.xattrs {
+                    true => copy_xattrs(&entry.path, &destination),
+                    false => Ok(()),
+                });
+            match created {
+                Ok(()) => directories.push((destination, entry.metadata)),
+                Err(error) => report(copied, error, options),
+            }
+            continue;
+        }
+        if is_special(&entry.metadata) {
+            match options.special_files {
+                SpecialFiles::Skip => {
+                    skip_special(copied, &entry);
+                    continue;
+                }
+                SpecialFiles::Fail => {
+                    report(copied, BackupError::SpecialFile(entry.path), options);
+                    continue;
+                }
+                SpecialFiles::Record => {}
+            }
+        }
+
+        copy(copied, entry);
+    }
+}
+
+/// Copies the non-directory `entry` to its place below `target`, or hard
+/// links it to its previous copy if unchanged since then. In a mirror, an
+/// up to date copy is left as it is and any other entry there replaced.
+fn copy_file_entry(copied: &mut Copied, entry: Entry, target: &Path, options: CopyOptions) {
+    let destination = target.join(&entry.relative);
+    if let Some(mirror) = options.mirror {
+        if mirror.same(&entry) {
+            writer::log(
+                Level::Verbose,
+                format_args!("{} (up to date)", entry.path.display()),
+            );
+            copied.files += 1;
+            copied.up_to_date += 1;
+            return;
+        }
+        if fs::symlink_metadata(&destination).is_ok() {
+            if let Err(error) = restore::remove(&destination) {
+                return report(copied, error, options);
+            }
+        }
+    }
+    let unchanged = options
+        .previous
+        .and_then(|previous| previous.unchanged(&entry, options.checksum));
+    if let Some((previous, digest)) = unchanged {
+        if fs::hard_link(&previous, &destination).is_ok() {
+            writer::log(
+                Level::Verbose,
+                format_args!("{} (unchanged)", entry.path.display()),
+            );
+            copied.files += 1;
+            copied.linked += 1;
+            copied
+                .digests
+                .extend(digest.map(|digest| (entry.relative, digest)));
+            return;
+        }
+    }
+
+    log_entry(&entry);
+    let (result, retries) = copy_entry_retrying(&entry.path, &destination, options);
+    copied.retried += u64::from(retries > 0);
+    match result {
+        Ok((bytes, digest)) => {
+            copied.files += 1;
+            copied.bytes += bytes;
+            copied.read += bytes;
+            if let Some(digest) = digest {
+                copied.digests.push((entry.relative, digest));
+            }
+        }
+        Err(error) => report(copied, error, options),
+    }
+}
+
+/// Copies an entry as [`copy_entry`] does, trying again up to
+/// [`CopyOptions::retries`] times after a transient error, waiting twice as
+/// long each time unless interrupted. Returns the result of the last attempt
+/// and the number of retries.
+fn copy_entry_retrying(
+    source: &Path,
+    target: &Path,
+    options: CopyOptions,
+) -> (Result<(u64, Option<String>), BackupError>, u32) {
+    let mut delay = options.retry_delay;
+    let mut retries = 0;
+    loop {
+        let result = copy_entry(source, target, options);
+        match &result {
+            Err(error) if retries < options.retries && is_transient(error) => {
+                retries += 1;
+                writer::log(
+                    Level::Verbose,
+                    format_args!(
+                        "{error}, retrying in {delay:?} (attempt {} of {})",
+                        retries + 1,
+                        options.retries + 1
+                    ),
+                );
+                if !interrupt::sleep(delay) {
+                    return (result, retries);
+                }
+                delay = delay.saturating_mul(2);
+                let _ = fs::remove_file(target);
+            }
+            _ => return (result, retries),
+        }
+    }
+}
+
+/// Checks whether copying may succeed if tried again after `error`: an I/O
+/// error, a timeout or a busy or unreachable resource, as on a network
+/// filesystem, unless the backup was interrupted.
+fn is_transient(error: &BackupError) -> bool {
+    let BackupError::CopyFailed { source, .. } = error else {
+        return false;
+    };
+    #[cfg(unix)]
+    let io_error = source.raw_os_error() == Some(libc::EIO);
+    #[cfg(not(unix))]
+    let io_error = false;
+    !interrupt::is_interrupted()
+        && (io_error
+            || matches!(
+                source.kind(),
+                io::ErrorKind::TimedOut
+                    | io::ErrorKind::Interrupted
+                    | io::ErrorKind::WouldBlock
+                    | io::ErrorKind::ResourceBusy
+                    | io::ErrorKind::StaleNetworkFileHandle
+                    | io::ErrorKind::ConnectionReset
+                    | io::ErrorKind::ConnectionAborted
+                    | io::ErrorKind::NetworkDown
+                    | io::ErrorKind::NetworkUnreachable
+                    | io::ErrorKind::HostUnreachable
+            ))
+}
+
+/// Hard links the file `entry` to the copy of the file `first` of the same
+/// tree, sharing its inode in the source, or copies it if that fails. The
+/// link is recorded with the `digest` of the first file, if any.
+fn link_file_entry(
+    copied: &mut Copied,
+    first: &Path,
+    digest: Option<String>,
+    entry: Entry,
+    target: &Path,
+    options: CopyOptions,
+) {
+    let destination = target.join(&entry.relative);
+    if fs::hard_link(target.join(first), &destination).is_err() {
+        return copy_file_entry(copied, entry, target, options);
+    }
+
+    log_link(&entry, first);
+    copied.files += 1;
+    copied.hardlinks.add(&entry);
+    copied
+        .digests
+        .extend(digest.map(|digest| (entry.relative, digest)));
+}
+
+/// Copies a single non-directory entry, recreating symbolic links instead of
+/// following them unless dereferencing. Returns the number of bytes copied,
+/// which is zero for a recreated link, and the digest of the contents of a
+/// copied file if requested.
+pub(crate) fn copy_entry(
+    source: &Path,
+    target: &Path,
+    options: CopyOptions,
+) -> Result<(u64, Option<String>), BackupError> {
+    let mut metadata =
+        fs::symlink_metadata(source).map_err(|_| BackupError::NotFound(source.to_path_buf()))?;
+    let mut followed = Cow::Borrowed(source);
+
+    let (bytes, digest) = if metadata.is_symlink() && !options.dereference {
+        copy_symlink(source, target).map(|_| (0, None))?
+    } else {
+        if metadata.is_symlink() {
+            metadata = fs::metadata(source)
+                .map_err(|_| BackupError::DanglingLink(source.to_path_buf()))?;
+            followed = fs::canonicalize(source).map_or(followed, Cow::Owned);
+        }
+        if is_special(&metadata) && options.special_files != SpecialFiles::Record {
+            return Err(BackupError::SpecialFile(source.to_path_buf()));
+        }
+        match options.checksum {
+            _ if is_special(&metadata) => record_special(target, &metadata).map(|()| (0, None)),
+            Some(algorithm) => copy_file_hashed(source, target, &metadata, algorithm, options)
+                .map(|(bytes, digest)| (bytes, Some(digest))),
+            None => copy_file(source, target, &metadata, options).map(|bytes| (bytes, None)),
+        }
+        .map_err(|e| copy_error(source, target, e))?
+    };
+
+    if options.preserve_owner {
+        preserve_owner(target, &metadata)?;
+    }
+    if options.xattrs {
+        copy_xattrs(&followed, target)?;
+    }
+    if options.preserve {
+        preserve_metadata(target, &metadata)?;
+    }
+
+    Ok((bytes, digest))
+}
+
+/// Copies the contents of the file `source` to `target`, cloning them if
+/// [`CopyOptions::reflink`] allows, and gives `target` the permissions of
+/// `source` like [`fs::copy`].
+fn copy_file(
+    source: &Path,
+    target: &Path,
+    metadata: &Metadata,
+    options: CopyOptions,
+) -> io::Result<u64> {
+    let mut reader = File::open(source)?;
+    let mut writer = File::create(target)?;
+    let bytes = reflink::copy(
+        &mut reader,
+        &mut writer,
+        options.reflink,
+        options.buffer_size,
+        options.throttle,
+    )?;
+    writer.set_permissions(metadata.permissions())?;
+    if options.fsync != Fsync::None {
+        writer.sync_all()?;
+    }
+    Ok(bytes)
+}
+
+/// Copies the contents of the file `source` to `target` as [`copy_file`]
+/// does, hashing them on the way, or reading them again once cloned.
+fn copy_file_hashed(
+    source: &Path,
+    target: &Path,
+    metadata: &Metadata,
+    algorithm: Algorithm,
+    options: CopyOptions,
+) -> io::Result<(u64, String)> {
+    let reader = File::open(source)?;
+    let writer = File::create(target)?;
+    let cloned = match options.reflink {
+        Reflink::Never => false,
+        reflink => reflink::clone(&reader, &writer, reflink)?,
+    };
+    let mut reader = Interruptible(reader);
+    let size = options.buffer_size;
+    let copied = match cloned {
+        true => checksum::copy_hashed(&mut reader, io::sink(), algorithm, size)?,
+        false => {
+            let writer = Throttled(&writer, options.throttle);
+            checksum::copy_hashed(&mut reader, writer, algorithm, size)?
+        }
+    };
+    writer.set_permissions(metadata.permissions())?;
+    if options.fsync != Fsync::None {
+        writer.sync_all()?;
+    }
+    Ok(copied)
+}
+
+/// Creates at `target` a special file of the kind and device described by
+/// `metadata`.
+#[cfg(unix)]
+fn record_special(target: &Path, metadata: &Metadata) -> io::Result<()> {
+    use std::os::unix::fs::MetadataExt;
+
+    make_node(target, metadata.mode(), metadata.rdev() as libc::dev_t)
+}
+
+#[cfg(not(unix))]
+fn record_special(_target: &Path, _metadata: &Metadata) -> io::Result<()> {
+    Err(io::Error::from(io::ErrorKind::Unsupported))
+}
+
+/// Creates at `path` the special file whose type and permissions are given
+/// by `mode`, for the `device` if it is one.
+#[cfg(unix)]
+pub(crate) fn make_node(path: &Path, mode: u32, device: libc::dev_t) -> io::Result<()> {
+    use std::ffi::CString;
+    use std::os::unix::ffi::OsStrExt;
+
+    let path = CString::new(path.as_os_str().as_bytes())?;
+    // SAFETY: the path is NUL-terminated.
+    let result = unsafe { libc::mknod(path.as_ptr(), mode as libc::mode_t, device) };
+    match result {
+        0 => Ok(()),
+        _ => Err(io::Error::last_os_error()),
+    }
+}
+
+/// Gives `path` the owner and group of `metadata`, without following `path`
+/// if it is a symbolic link.
+#[cfg(unix)]
+fn preserve_owner(path: &Path, metadata: &Metadata) -> Result<(), BackupError> {
+    use std::os::unix::fs::MetadataExt;
+
+    std::os::unix::fs::lchown(path, Some(metadata.uid()), Some(metadata.gid())).map_err(|source| {
+        BackupError::MetadataFailed {
+            path: path.to_path_buf(),
+            source,
+        }
+    })
+}
+
+#[cfg(not(unix))]
+fn preserve_owner(_path: &Path, _metadata: &Metadata) -> Result<(), BackupError> {
+    Ok(())
+}
+
+/// Copies the extended attributes of `source` to `target`, see
+/// [`xattrs::copy`].
+fn copy_xattrs(source: &Path, target: &Path) -> Result<(), BackupError> {
+    xattrs::copy(source, target).map_err(|source| BackupError::MetadataFailed {
+        path: target.to_path_buf(),
+        source,
+    })
+}
+
+/// Applies the mode and the access and modification times of `metadata` to
+/// `path`, without following `path` if it is a symbolic link.
+fn preserve_metadata(path: &Path, metadata: &Metadata) -> Result<(), BackupError> {
+    let metadata_error = |source| BackupError::MetadataFailed {
+        path: path.to_path_buf(),
+        source,
+    };
+
+    if !metadata.is_symlink() {
+        fs::set_permissions(path, metadata.permissions()).map_err(metadata_error)?;
+    }
+
+    let accessed = FileTime::from_last_access_time(metadata);
+    let modified = FileTime::from_last_modification_time(metadata);
+    filetime::set_symlink_file_times(path, accessed, modified).map_err(metadata_error)
+}
+
+/// Creates at `target` a symbolic link pointing where the link `source` points.
+#[cfg(unix)]
+fn copy_symlink(source: &Path, target: &Path) -> Result<(), BackupError> {
+    let link = fs::read_link(source).map_err(|e| copy_error(source, target, e))?;
+    if fs::symlink_metadata(target).is_ok() {
+        fs::remove_file(target).map_err(|e| BackupError::RemoveFailed {
+            path: target.to_path_buf(),
+            source: e,
+        })?;
+    }
+
+    std::os::unix::fs::symlink(link, target).map_err(|e| copy_error(source, target, e))
+}
+
+/// Creates at `target` a symbolic link pointing where the link `source` points.
+///
+/// Windows tells links to directories from links to files: the link is made
+/// to a directory if `source` leads to one, and to a file otherwise, such as
+/// when dangling.
+#[cfg(windows)]
+fn copy_symlink(source: &Path, target: &Path) -> Result<(), BackupError> {
+    use std::os::windows::fs::{symlink_dir, symlink_file};
+
+    let link = fs::read_link(source).map_err(|e| copy_error(source, target, e))?;
+    if fs::symlink_metadata(target).is_ok() {
+        restore::remove(target)?;
+    }
+
+    let is_dir = fs::metadata(source).is_ok_and(|metadata| metadata.is_dir());
+    let result = match is_dir {
+        true => symlink_dir(link, target),
+        false => symlink_file(link, target),
+    };
+    result.map_err(|e| copy_error(source, target, e))
+}
+
+#[cfg(not(any(unix, windows)))]
+fn copy_symlink(source: &Path, target: &Path) -> Result<(), BackupError> {
+    let error = io::Error::new(
+        io::ErrorKind::Unsupported,
+        "symbolic links are not supported on this platform",
+    );
+    Err(copy_error(source, target, error))
+}
+
+/// Length of the longest suffix added to the name of a backup by the files
+/// written next to it, that of its metadata file while being written.
+const SIDECAR_SUFFIX_MAX: usize = ".".len() + meta::META_EXTENSION.len() + ".partial".len();
+
+/// Picks a free path inside `target` for `source`, named by the installed
+/// [`naming::NameFormat`] and followed by the archive `extension` if any.
+///
+/// Backups made within the same second get a counter after the timestamp, as
+/// in `hosts.2024-05-01_10-00-00_2.backup`, instead of replacing the first.
+/// Names without a timestamp are returned even when taken.
+///
+/// The name of the source is made valid on filesystems such as FAT or NTFS
+/// first, see [`naming::sanitize`], short enough for the backup and its
+/// metadata file to be named after it. A warning shows it when it changes.
+pub(crate) fn backup_path(
+    source: &Path,
+    target: &Path,
+    created: DateTime<Local>,
+    extension: Option<&str>,
+) -> Result<PathBuf, BackupError> {
+    unique_path(source, target, created, extension, exists)
+}
+
+/// Returns the path of the backup of `source` in `target` as
+/// [`backup_path`] does, telling the paths taken with `taken`.
+fn unique_path(
+    source: &Path,
+    target: &Path,
+    created: DateTime<Local>,
+    extension: Option<&str>,
+    taken: impl Fn(&Path) -> bool,
+) -> Result<PathBuf, BackupError> {
+    let original = source_name(source)?;
+    let format = naming::format();
+
+    let mut sequence = 1;
+    loop {
+        let mut suffix = format.render(OsStr::new(""), created, sequence);
+        if let Some(extension) = extension {
+            suffix.push(".");
+            suffix.push(extension);
+        }
+        let max_len = naming::NAME_MAX
+            .saturating_sub(suffix.len() + SIDECAR_SUFFIX_MAX)
+            .max(1);
+        let name = naming::sanitize(&original, max_len);
+        let mut file_name = format.render(&name, created, sequence);
+        if let Some(extension) = extension {
+            file_name.push(".");
+            file_name.push(extension);
+        }
+
+        let path = target.join(file_name);
+        if !format.has_timestamp() || !taken(&path) {
+            if name != original {
+                writer::log(
+                    Level::Warning,
+                    format_args!(
+                        "'{}': Named '{}' in the backup, which the target may not allow otherwise",
+                        original.to_string_lossy(),
+                        name.to_string_lossy()
+                    ),
+                );
+            }
+            return Ok(path);
+        }
+        sequence += 1;
+    }
+}
+
+/// Returns the final component of `source`, resolving paths such as `.` or `..`.
+fn source_name(source: &Path) -> Result<OsString, BackupError> {
+    let canonical;
+    let path = match source.file_name() {
+        Some(_) => source,
+        None => {
+            canonical = fs::canonicalize(source)
+                .map_err(|_| BackupError::NotFound(source.to_path_buf()))?;
+            &canonical
+        }
+    };
+
+    path.file_name()
+        .map(OsStr::to_os_string)
+        .ok_or_else(|| BackupError::InvalidName(source.to_path_buf()))
+}
+
+/// Suffix of the lock files of [`backup`], after a dot and the sanitized name
+/// of the source.
+pub(crate) const LOCK_SUFFIX: &str = ".backup.lock";
+
+/// Takes the lock of the backups of `source`, in the directory `destination`
+/// is created in, or in a mirror itself.
+fn lock(source: &Path, destination: &Path, options: &BackupOptions) -> Result<Lock, BackupError> {
+    let dir = match destination.parent() {
+        _ if options.mirror => destination,
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    let mut name = OsString::from(".");
+    let max_len = naming::NAME_MAX - name.len() - LOCK_SUFFIX.len();
+    name.push(naming::sanitize(&source_name(source)?, max_len));
+    name.push(LOCK_SUFFIX);
+    Lock::acquire(&dir.join(name), options.wait_lock)
+}
+
+/// Fails if the filesystem `destination` is to be written to has less than
+/// `bytes` free, or only warns for an `archive`, which compression may make
+/// fit.
+fn check_space(destination: &Path, bytes: u64, archive: bool) -> Result<(), BackupError> {
+    let Some(free) = free_space(destination).filter(|&free| free < bytes) else {
+        return Ok(());
+    };
+    let dir = match destination.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    if !archive {
+        return Err(BackupError::NoSpace {
+            path: dir.to_path_buf(),
+            needed: bytes,
+            free,
+        });
+    }
+    writer::log(
+        Level::Warning,
+        format_args!(
+            "The archive may not fit, {} to compress, only {} free on {}",
+            writer::human_bytes(bytes),
+            writer::human_bytes(free),
+            dir.display()
+        ),
+    );
+    Ok(())
+}
+
+/// Returns the space in bytes available to unprivileged users on the
+/// filesystem of `path`, or of its nearest existing ancestor, if known.
+#[cfg(unix)]
+pub(crate) fn free_space(path: &Path) -> Option<u64> {
+    use std::ffi::CString;
+    use std::os::unix::ffi::OsStrExt;
+
+    let existing = path
+        .ancestors()
+        .map(|path| match path.as_os_str().is_empty() {
+            true => Path::new("."),
+            false => path,
+        })
+        .find(|path| path.exists())?;
+    let existing = CString::new(existing.as_os_str().as_bytes()).ok()?;
+    // SAFETY: the path is NUL-terminated and an all-zero statvfs is valid.
+    let mut stats: libc::statvfs = unsafe { std::mem::zeroed() };
+    match unsafe { libc::statvfs(existing.as_ptr(), &mut stats) } {
+        #[allow(clippy::useless_conversion)]
+        0 => Some(u64::from(stats.f_bavail).saturating_mul(u64::from(stats.f_frsize))),
+        _ => None,
+    }
+}
+
+#[cfg(not(unix))]
+pub(crate) fn free_space(_path: &Path) -> Option<u64> {
+    None
+}
+
+/// Flushes to disk the directory holding `path` with [`Fsync::Dir`], so that
+/// renaming `path` into it persists.
+pub(crate) fn sync_parent(path: &Path, fsync: Fsync) -> io::Result<()> {
+    match path.parent() {
+        _ if fsync != Fsync::Dir => Ok(()),
+        Some(dir) if !dir.as_os_str().is_empty() => sync_dir(dir),
+        _ => sync_dir(Path::new(".")),
+    }
+}
+
+/// Flushes to disk the entries of the directory at `path`.
+#[cfg(unix)]
+fn sync_dir(path: &Path) -> io::Result<()> {
+    File::open(path)?.sync_all()
+}
+
+/// Directories cannot be opened to be flushed here, and need not be.
+#[cfg(not(unix))]
+fn sync_dir(_path: &Path) -> io::Result<()> {
+    Ok(())
+}
+
+/// Fails if `path` exists and overwriting was not requested.
+pub(crate) fn check_overwrite(path: &Path, force: bool) -> Result<(), BackupError> {
+    if !force && exists(path) {
+        return Err(BackupError::AlreadyExists(path.to_path_buf()));
+    }
+
+    Ok(())
+}
+
+/// Checks whether there is an entry at `path`, or the first part of an
+/// archive split there.
+pub(crate) fn exists(path: &Path) -> bool {
+    fs::symlink_metadata(path).is_ok() || fs::symlink_metadata(split::part_path(path, 0)).is_ok()
+}
+
+fn copy_error(source: &Path, target: &Path, error: io::Error) -> BackupError {
+    BackupError::CopyFailed {
+        from: source.to_path_buf(),
+        to: target.to_path_buf(),
+        source: error,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn copy_failed(error: io::Error) -> BackupError {
+        copy_error(Path::new("data"), Path::new("backup"), error)
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn only_transient_copy_errors_are_retried() {
+        for transient in [
+            io::Error::from(io::ErrorKind::TimedOut),
+            io::Error::from(io::ErrorKind::Interrupted),
+            io::Error::from_raw_os_error(libc::EIO),
+            io::Error::from_raw_os_error(libc::EAGAIN),
+            io::Error::from_raw_os_error(libc::ESTALE),
+        ] {
+            assert!(is_transient(&copy_failed(transient)));
+        }
+        for permanent in [
+            io::Error::from(io::ErrorKind::NotFound),
+            io::Error::from(io::ErrorKind::PermissionDenied),
+            io::Error::from_raw_os_error(libc::ENOSPC),
+            io::Error::from_raw_os_error(libc::EEXIST),
+            io::Error::other("input/output error"),
+            io::Error::other(BackupError::Interrupted),
+        ] {
+            assert!(!is_transient(&copy_failed(permanent)));
+        }
+        assert!(!is_transient(&BackupError::NotFound(PathBuf::from("data"))));
+    }
+}
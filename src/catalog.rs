@@ -0,0 +1,318 @@
+//! Catalog of the backups in a directory, listing them without looking into
+//! each one.
+//!
+//! Listing a directory reads the metadata file of every backup and walks
+//! every directory backup to sum its size, which is slow for large
+//! directories on network filesystems. The catalog, a `.backup-catalog.json`
+//! file in the directory, records what [`list`] found about each backup: its
+//! original name, timestamp, kind, size and metadata, which holds its source
+//! and checksum. It is updated after every backup into the directory and
+//! every prune of it, under a lock, and written to a partial file renamed
+//! into place, so that concurrent runs never corrupt it.
+//!
+//! [`list::list`], and prune which lists, use the catalog when it matches the
+//! directory: the names of its entries are still read, but no backup is
+//! looked into. A catalog that does not match, because backups were added or
+//! removed by other means, is ignored with a warning until [`rebuild`] or the
+//! next backup into the directory writes it anew.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! use std::path::Path;
+//!
+//! let backups = backup::catalog::rebuild(Path::new("/mnt/backups"))?;
+//! println!("{backups} backups cataloged");
+//! # Ok::<(), backup::BackupError>(())
+//! ```
+
+use std::collections::BTreeSet;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use chrono::NaiveDateTime;
+use serde::{Deserialize, Serialize};
+
+use crate::backup;
+use crate::error::BackupError;
+use crate::list::{self, BackupKind, ListEntry};
+use crate::lock::Lock;
+use crate::meta::BackupMeta;
+use crate::writer::{self, Level};
+
+/// Name of the catalog file in a backup directory.
+pub const CATALOG_NAME: &str = ".backup-catalog.json";
+
+/// Version of the format of the catalog, bumped when it changes so that
+/// older catalogs are rebuilt rather than misread.
+const VERSION: u32 = 1;
+
+/// Longest time waited for another run updating the same catalog.
+const LOCK_WAIT: Duration = Duration::from_secs(10);
+
+/// Contents of the catalog file.
+#[derive(Debug, Serialize, Deserialize)]
+struct Catalog {
+    version: u32,
+    backups: Vec<Record>,
+}
+
+/// A backup recorded in the catalog, as [`ListEntry`] describes it.
+#[derive(Debug, Serialize, Deserialize)]
+struct Record {
+    /// File name of the backup in the directory.
+    file_name: String,
+    name: String,
+    timestamp: Option<NaiveDateTime>,
+    /// Sequence number among the backups of the same name and second.
+    sequence: u32,
+    kind: BackupKind,
+    size: u64,
+    meta: Option<BackupMeta>,
+}
+
+impl Record {
+    /// Records `entry`, whose `sequence` is given, or returns `None` if its
+    /// file name is not valid UTF-8.
+    fn new((entry, sequence): (ListEntry, u32)) -> Option<Self> {
+        Some(Record {
+            file_name: entry.path.file_name()?.to_str()?.to_owned(),
+            name: entry.name,
+            timestamp: entry.timestamp,
+            sequence,
+            kind: entry.kind,
+            size: entry.size,
+            meta: entry.meta,
+        })
+    }
+
+    /// Returns the entry of the backup in `dir`, and its sequence number.
+    fn entry(self, dir: &Path) -> (ListEntry, u32) {
+        let entry = ListEntry {
+            name: self.name,
+            timestamp: self.timestamp,
+            size: self.size,
+            kind: self.kind,
+            path: dir.join(self.file_name),
+            meta: self.meta,
+        };
+        (entry, self.sequence)
+    }
+}
+
+/// Returns the path of the catalog of the backup directory `dir`.
+pub fn path(dir: &Path) -> PathBuf {
+    dir.join(CATALOG_NAME)
+}
+
+/// Returns the backups of `dir` recorded in its catalog, with their sequence
+/// numbers, or `None` if it has no catalog or one that does not match the
+/// backups found in it.
+pub(crate) fn entries(dir: &Path) -> Option<Vec<(ListEntry, u32)>> {
+    let catalog = read(dir)?;
+    let recorded: BTreeSet<_> = catalog
+        .backups
+        .iter()
+        .map(|record| record.file_name.clone())
+        .collect();
+    if list::backup_names(dir).ok()? != recorded {
+        writer::log(
+            Level::Warning,
+            format_args!(
+                "'{}': The catalog does not match the backups, run 'backup catalog rebuild {}'",
+                path(dir).display(),
+                dir.display()
+            ),
+        );
+        return None;
+    }
+    Some(
+        catalog
+            .backups
+            .into_iter()
+            .map(|record| record.entry(dir))
+            .collect(),
+    )
+}
+
+/// Records in the catalog of `dir` the backup at `backup`, just created in
+/// it, creating the catalog if it is missing or rebuilding it if it did not
+/// match the directory before.
+///
+/// Failing to is only a warning, as the backup itself is fine and listing it
+/// falls back to reading the directory.
+pub(crate) fn add(dir: &Path, backup: &Path) {
+    update(dir, &[backup.to_path_buf()], &[]);
+}
+
+/// Removes from the catalog of `dir`, if it has one, the backups `removed`
+/// from it, warning on failure as [`add`] does.
+pub(crate) fn forget(dir: &Path, removed: &[PathBuf]) {
+    if path(dir).exists() {
+        update(dir, &[], removed);
+    }
+}
+
+/// Updates the catalog of `dir` after the backups `added` were created in it
+/// and `removed` removed from it, warning on failure.
+fn update(dir: &Path, added: &[PathBuf], removed: &[PathBuf]) {
+    if let Err(error) = try_update(dir, added, removed) {
+        writer::log(
+            Level::Warning,
+            format_args!(
+                "'{}': Could not update the catalog: {error}",
+                path(dir).display()
+            ),
+        );
+    }
+}
+
+/// Updates the catalog of `dir` as [`update`] does, or rebuilds it if it is
+/// missing or did not match the directory before, failing on errors.
+fn try_update(dir: &Path, added: &[PathBuf], removed: &[PathBuf]) -> Result<(), BackupError> {
+    let _lock = lock(dir)?;
+    let file_name = |path: &PathBuf| {
+        path.file_name()
+            .and_then(|name| name.to_str())
+            .map(str::to_owned)
+    };
+    let added: BTreeSet<_> = added.iter().filter_map(file_name).collect();
+    let removed: BTreeSet<_> = removed.iter().filter_map(file_name).collect();
+
+    let mut catalog = match read(dir) {
+        Some(catalog) => catalog,
+        None => return write(dir, scan(dir)?),
+    };
+    catalog.backups.retain(|record| {
+        !removed.contains(&record.file_name) && !added.contains(&record.file_name)
+    });
+    let mut expected: BTreeSet<_> = catalog
+        .backups
+        .iter()
+        .map(|record| record.file_name.clone())
+        .collect();
+    expected.extend(added.iter().cloned());
+    if list::backup_names(dir).ok() != Some(expected) {
+        return write(dir, scan(dir)?);
+    }
+
+    for name in &added {
+        let entry = list::describe(&dir.join(name))?;
+        let sequence = list::sequence(name.as_ref());
+        catalog.backups.extend(Record::new((entry, sequence)));
+    }
+    write(dir, catalog)
+}
+
+/// Writes the catalog of `dir` anew from the backups found in it, returning
+/// how many it records.
+pub fn rebuild(dir: &Path) -> Result<usize, BackupError> {
+    let _lock = lock(dir)?;
+    let catalog = scan(dir)?;
+    let backups = catalog.backups.len();
+    write(dir, catalog)?;
+    Ok(backups)
+}
+
+/// Takes the lock of the catalog of `dir`.
+fn lock(dir: &Path) -> Result<Lock, BackupError> {
+    Lock::acquire(&dir.join(format!("{CATALOG_NAME}.lock")), LOCK_WAIT)
+}
+
+/// Returns the catalog of the backups found in `dir`.
+fn scan(dir: &Path) -> Result<Catalog, BackupError> {
+    let backups = list::scan(dir, None, false)?;
+    Ok(Catalog {
+        version: VERSION,
+        backups: backups.into_iter().filter_map(Record::new).collect(),
+    })
+}
+
+/// Reads the catalog of `dir`, or returns `None` if it has none, or one that
+/// cannot be read, such as one of another version.
+fn read(dir: &Path) -> Option<Catalog> {
+    let path = path(dir);
+    let text = match fs::read(&path) {
+        Ok(text) => text,
+        Err(error) if error.kind() == io::ErrorKind::NotFound => return None,
+        Err(error) => {
+            writer::log(
+                Level::Warning,
+                format_args!("'{}': Cannot read the catalog: {error}", path.display()),
+            );
+            return None;
+        }
+    };
+    match serde_json::from_slice::<Catalog>(&text) {
+        Ok(catalog) if catalog.version == VERSION => Some(catalog),
+        _ => {
+            writer::log(
+                Level::Warning,
+                format_args!("'{}': Ignoring an invalid catalog", path.display()),
+            );
+            None
+        }
+    }
+}
+
+/// Writes `catalog` as that of `dir`, atomically.
+fn write(dir: &Path, mut catalog: Catalog) -> Result<(), BackupError> {
+    catalog
+        .backups
+        .sort_by(|a, b| a.file_name.cmp(&b.file_name));
+    let path = path(dir);
+    let partial = backup::partial_path(&path);
+    let text = serde_json::to_vec_pretty(&catalog).expect("catalogs are serializable");
+    fs::write(&partial, text)
+        .and_then(|()| fs::rename(&partial, &path))
+        .map_err(|source| {
+            let _ = fs::remove_file(&partial);
+            BackupError::CreateFailed { path, source }
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn updates_follow_the_directory() {
+        let dir = tempfile::tempdir().unwrap();
+        let first = dir.path().join("notes.txt.2024-05-01_10-00-00.backup");
+        let second = dir.path().join("notes.txt.2024-05-02_10-00-00.backup");
+        fs::write(&first, "12345").unwrap();
+        assert!(entries(dir.path()).is_none());
+
+        add(dir.path(), &first);
+        fs::write(&second, "1234567").unwrap();
+        add(dir.path(), &second);
+        let listed = entries(dir.path()).unwrap();
+        assert_eq!(listed.len(), 2);
+        let sizes: Vec<_> = listed.iter().map(|(entry, _)| entry.size).collect();
+        assert_eq!(sizes, [5, 7]);
+
+        fs::remove_file(&first).unwrap();
+        assert!(entries(dir.path()).is_none());
+        forget(dir.path(), &[first]);
+        let listed = entries(dir.path()).unwrap();
+        assert_eq!(listed[0].0.path, second);
+    }
+
+    #[test]
+    fn backups_added_by_other_means_trigger_a_rebuild() {
+        let dir = tempfile::tempdir().unwrap();
+        let first = dir.path().join("a.2024-05-01_10-00-00.backup");
+        let copied = dir.path().join("b.2024-05-01_10-00-00.backup");
+        fs::write(&first, "1").unwrap();
+        assert_eq!(rebuild(dir.path()).unwrap(), 1);
+
+        fs::write(&copied, "22").unwrap();
+        assert!(entries(dir.path()).is_none());
+        let second = dir.path().join("a.2024-05-02_10-00-00.backup");
+        fs::write(&second, "333").unwrap();
+        add(dir.path(), &second);
+        assert_eq!(entries(dir.path()).unwrap().len(), 3);
+    }
+}
@@ -0,0 +1,348 @@
+//! Checksums of backed up files, recorded in manifests next to the backups.
+//!
+//! The manifest of a backup is named after it with the algorithm as extension,
+//! e.g. `hosts.2024-05-01_10-00-00.backup.sha256`, and lists one file per line
+//! in the format of `sha256sum`: the hex digest, two spaces and the path of
+//! the file relative to the directory holding the manifest.
+
+use std::ffi::{OsStr, OsString};
+use std::fmt::Write as _;
+use std::fs::{self, File};
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+
+use sha2::{Digest, Sha256};
+
+use crate::backup::DEFAULT_BUFFER_SIZE;
+use crate::error::BackupError;
+use crate::reflink;
+use crate::split;
+
+/// Hash algorithm used for checksums.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Algorithm {
+    /// SHA-256, readable by `sha256sum --check`.
+    #[default]
+    Sha256,
+    /// BLAKE3, readable by `b3sum --check`.
+    Blake3,
+}
+
+impl Algorithm {
+    /// Every supported algorithm.
+    pub const ALL: [Algorithm; 2] = [Algorithm::Sha256, Algorithm::Blake3];
+
+    /// Parses the `--algorithm` name.
+    pub fn parse(name: &str) -> Result<Self, BackupError> {
+        match name {
+            "sha256" => Ok(Algorithm::Sha256),
+            "blake3" => Ok(Algorithm::Blake3),
+            other => Err(BackupError::InvalidOption(format!(
+                "Unknown checksum algorithm '{other}', expected sha256 or blake3"
+            ))),
+        }
+    }
+
+    /// Extension of the manifests written with this algorithm.
+    pub fn extension(self) -> &'static str {
+        match self {
+            Algorithm::Sha256 => "sha256",
+            Algorithm::Blake3 => "blake3",
+        }
+    }
+
+    /// Returns the path of the manifest of `backup`.
+    pub fn manifest_path(self, backup: &Path) -> PathBuf {
+        let mut name = OsString::from(backup.as_os_str());
+        name.push(".");
+        name.push(self.extension());
+        PathBuf::from(name)
+    }
+}
+
+/// Incremental digest of a stream of bytes.
+pub(crate) enum Hasher {
+    Sha256(Sha256),
+    Blake3(Box<blake3::Hasher>),
+}
+
+impl Hasher {
+    pub(crate) fn new(algorithm: Algorithm) -> Self {
+        match algorithm {
+            Algorithm::Sha256 => Hasher::Sha256(Sha256::new()),
+            Algorithm::Blake3 => Hasher::Blake3(Box::default()),
+        }
+    }
+
+    pub(crate) fn update(&mut self, data: &[u8]) {
+        match self {
+            Hasher::Sha256(hasher) => hasher.update(data),
+            Hasher::Blake3(hasher) => {
+                hasher.update(data);
+            }
+        }
+    }
+
+    /// Returns the lowercase hex digest of the bytes received.
+    pub(crate) fn finish(self) -> String {
+        match self {
+            Hasher::Sha256(hasher) => {
+                hasher
+                    .finalize()
+                    .iter()
+                    .fold(String::with_capacity(64), |mut hex, byte| {
+                        let _ = write!(hex, "{byte:02x}");
+                        hex
+                    })
+            }
+            Hasher::Blake3(hasher) => hasher.finalize().to_hex().to_string(),
+        }
+    }
+}
+
+/// Writer hashing everything written through it, if given an algorithm.
+pub(crate) struct HashingWriter<W> {
+    inner: W,
+    hasher: Option<Hasher>,
+}
+
+impl<W: Write> HashingWriter<W> {
+    pub(crate) fn new(inner: W, algorithm: Option<Algorithm>) -> Self {
+        HashingWriter {
+            inner,
+            hasher: algorithm.map(Hasher::new),
+        }
+    }
+
+    /// Returns the inner writer and the digest of the bytes written.
+    pub(crate) fn finish(self) -> (W, Option<String>) {
+        (self.inner, self.hasher.map(Hasher::finish))
+    }
+}
+
+impl<W: Write> Write for HashingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        if let Some(hasher) = &mut self.hasher {
+            hasher.update(&buf[..written]);
+        }
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Reader hashing everything read through it, if given an algorithm.
+pub(crate) struct HashingReader<R> {
+    inner: R,
+    hasher: Option<Hasher>,
+}
+
+impl<R: Read> HashingReader<R> {
+    pub(crate) fn new(inner: R, algorithm: Option<Algorithm>) -> Self {
+        HashingReader {
+            inner,
+            hasher: algorithm.map(Hasher::new),
+        }
+    }
+
+    /// Reads the rest of the inner reader, and returns the digest of
+    /// everything read.
+    pub(crate) fn finish(mut self) -> io::Result<Option<String>> {
+        if self.hasher.is_some() {
+            io::copy(&mut self, &mut io::sink())?;
+        }
+        Ok(self.hasher.map(Hasher::finish))
+    }
+}
+
+impl<R: Read> Read for HashingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = self.inner.read(buf)?;
+        if let Some(hasher) = &mut self.hasher {
+            hasher.update(&buf[..read]);
+        }
+        Ok(read)
+    }
+}
+
+/// Copies `reader` into `writer` through a buffer of `buffer_size` bytes,
+/// returning the number of bytes copied and their digest.
+pub(crate) fn copy_hashed(
+    reader: &mut impl Read,
+    writer: impl Write,
+    algorithm: Algorithm,
+    buffer_size: usize,
+) -> io::Result<(u64, String)> {
+    let mut writer = HashingWriter::new(writer, Some(algorithm));
+    let bytes = reflink::copy_buffered(reader, &mut writer, buffer_size)?;
+    let (_, digest) = writer.finish();
+    Ok((bytes, digest.unwrap_or_default()))
+}
+
+/// Computes the digest of the file at `path`.
+pub(crate) fn hash_file(path: &Path, algorithm: Algorithm) -> io::Result<String> {
+    let (_, digest) = copy_hashed(
+        &mut split::open(path)?,
+        io::sink(),
+        algorithm,
+        DEFAULT_BUFFER_SIZE,
+    )?;
+    Ok(digest)
+}
+
+/// Writes the manifest of `backup`, listing the `digests` of paths relative
+/// to the backup, where an empty path is the backup file itself.
+///
+/// The manifest is written to a temporary file first and renamed into place.
+pub(crate) fn write_manifest(
+    backup: &Path,
+    algorithm: Algorithm,
+    digests: &[(PathBuf, String)],
+) -> Result<PathBuf, BackupError> {
+    let path = algorithm.manifest_path(backup);
+    let contents = manifest(backup.file_name().unwrap_or(backup.as_os_str()), digests);
+
+    let mut partial = OsString::from(path.as_os_str());
+    partial.push(".partial");
+    let partial = PathBuf::from(partial);
+    let create_error = |source| BackupError::CreateFailed {
+        path: path.clone(),
+        source,
+    };
+    let written = File::create(&partial)
+        .and_then(|mut file| {
+            file.write_all(&contents)?;
+            file.sync_all()
+        })
+        .and_then(|()| fs::rename(&partial, &path));
+    if let Err(e) = written {
+        let _ = fs::remove_file(&partial);
+        return Err(create_error(e));
+    }
+
+    Ok(path)
+}
+
+/// Returns the contents of the manifest of the backup named `name`, listing
+/// `digests` as [`write_manifest`] does.
+pub(crate) fn manifest(name: &OsStr, digests: &[(PathBuf, String)]) -> Vec<u8> {
+    let name = Path::new(name);
+    let mut contents = Vec::new();
+    for (file, digest) in digests {
+        let file = if file.as_os_str().is_empty() {
+            name.to_path_buf()
+        } else {
+            name.join(file)
+        };
+        contents.extend_from_slice(digest.as_bytes());
+        contents.extend_from_slice(b"  ");
+        contents.extend_from_slice(file.as_os_str().as_encoded_bytes());
+        contents.push(b'\n');
+    }
+    contents
+}
+
+/// Removes the manifests of `backup`, whatever their algorithm.
+pub(crate) fn remove_manifests(backup: &Path) -> Result<(), BackupError> {
+    for algorithm in Algorithm::ALL {
+        let path = algorithm.manifest_path(backup);
+        match fs::remove_file(&path) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => {
+                return Err(BackupError::RemoveFailed { path, source: e });
+            }
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+/// Reads the entries of the manifest at `path`, skipping blank lines.
+pub(crate) fn read_manifest(path: &Path) -> Result<Vec<(PathBuf, String)>, BackupError> {
+    let read_error = |source| BackupError::ReadFailed {
+        path: path.to_path_buf(),
+        source,
+    };
+    let contents = fs::read(path).map_err(read_error)?;
+    parse_manifest(path, &contents)
+}
+
+/// Parses the `contents` of the manifest at `path`, skipping blank lines.
+pub(crate) fn parse_manifest(
+    path: &Path,
+    contents: &[u8],
+) -> Result<Vec<(PathBuf, String)>, BackupError> {
+    let read_error = |source| BackupError::ReadFailed {
+        path: path.to_path_buf(),
+        source,
+    };
+    let mut entries = Vec::new();
+    for line in contents
+        .split(|&b| b == b'\n')
+        .filter(|line| !line.is_empty())
+    {
+        let Some(split) = line.windows(2).position(|pair| pair == b"  ") else {
+            let error = io::Error::new(io::ErrorKind::InvalidData, "malformed checksum line");
+            return Err(read_error(error));
+        };
+        let digest = String::from_utf8_lossy(&line[..split]).into_owned();
+        entries.push((path_from_bytes(&line[split + 2..]), digest));
+    }
+    Ok(entries)
+}
+
+#[cfg(unix)]
+fn path_from_bytes(bytes: &[u8]) -> PathBuf {
+    use std::os::unix::ffi::OsStrExt;
+
+    PathBuf::from(std::ffi::OsStr::from_bytes(bytes))
+}
+
+#[cfg(not(unix))]
+fn path_from_bytes(bytes: &[u8]) -> PathBuf {
+    PathBuf::from(String::from_utf8_lossy(bytes).into_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn digests_match_known_values() {
+        let mut hasher = Hasher::new(Algorithm::Sha256);
+        hasher.update(b"abc");
+        assert_eq!(
+            hasher.finish(),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+
+        let mut hasher = Hasher::new(Algorithm::Blake3);
+        hasher.update(b"abc");
+        assert_eq!(
+            hasher.finish(),
+            "6437b3ac38465133ffb63b75273a8db548c558465d79db03fd359c6cd5bd9d85"
+        );
+    }
+
+    #[test]
+    fn manifests_round_trip() {
+        let tmp = tempfile::TempDir::new().unwrap();
+        let backup = tmp.path().join("etc.backup");
+        let digests = vec![
+            (PathBuf::from("hosts"), "00ff".to_owned()),
+            (PathBuf::from("with  spaces"), "ff00".to_owned()),
+        ];
+
+        let path = write_manifest(&backup, Algorithm::Blake3, &digests).unwrap();
+        assert_eq!(path, tmp.path().join("etc.backup.blake3"));
+        assert_eq!(
+            read_manifest(&path).unwrap(),
+            [
+                (PathBuf::from("etc.backup/hosts"), "00ff".to_owned()),
+                (PathBuf::from("etc.backup/with  spaces"), "ff00".to_owned()),
+            ]
+        );
+    }
+}
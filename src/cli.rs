@@ -0,0 +1,1234 @@
+//! Modes and options of the command line, which drive both parsing and the
+//! usage printed for each mode.
+
+use backup::pattern;
+
+use crate::Mode;
+
+/// A mode of operation, selected by the first argument.
+#[derive(Debug)]
+pub struct Command {
+    pub mode: Mode,
+    /// Name of the mode, as shown in the usage.
+    pub name: &'static str,
+    /// Shorter names accepted for the mode.
+    pub aliases: &'static [&'static str],
+    /// Positional arguments, as shown in the usage.
+    pub arguments: &'static str,
+    /// One line description.
+    pub about: &'static str,
+    /// Long names of the options accepted besides the global ones.
+    pub options: &'static [&'static str],
+}
+
+/// An option, given as `--long` or `-s`.
+#[derive(Debug)]
+pub struct Opt {
+    pub long: &'static str,
+    pub short: Option<char>,
+    /// Name of the value taken, or `None` for flags.
+    pub value: Option<&'static str>,
+    /// Description, with a line break where it wraps in the usage.
+    pub help: &'static str,
+}
+
+/// Options accepted by every mode.
+const GLOBAL: &[&str] = &[
+    "json",
+    "config",
+    "verbose",
+    "quiet",
+    "log-file",
+    "log-format",
+    "log-max-size",
+    "log-keep",
+    "log-syslog",
+    "help",
+];
+
+/// Options shared by backups and profiles run from the configuration file.
+const BACKUP: &[&str] = &[
+    "target",
+    "files-from",
+    "null",
+    "name",
+    "force",
+    "dereference",
+    "exclude",
+    "exclude-from",
+    "no-ignore-file",
+    "exclude-vcs",
+    "exclude-caches",
+    "max-depth",
+    "max-files",
+    "min-file-size",
+    "max-file-size",
+    "as-file",
+    "algorithm",
+    "incremental",
+    "checksum",
+    "skip-unchanged",
+    "mirror",
+    "delete",
+    "init",
+    "jobs",
+    "keep-going",
+    "fail-fast",
+    "retries",
+    "retry-delay",
+    "no-space-check",
+    "wait-lock",
+    "allow-empty-glob",
+    "dry-run",
+    "no-preserve",
+    "preserve-owner",
+    "xattrs",
+    "no-xattrs",
+    "reflink",
+    "buffer-size",
+    "fsync",
+    "bwlimit",
+    "special-files",
+    "compress",
+    "level",
+    "encrypt",
+    "recipient",
+    "encrypt-passphrase",
+    "passphrase-file",
+    "work-factor",
+    "sign",
+    "split",
+    "ssh-option",
+    "endpoint-url",
+    "pipe-to",
+    "every",
+    "at",
+    "status-file",
+    "notify-cmd",
+    "notify-on",
+    "metrics-file",
+    "name-format",
+    "timestamp-format",
+    "utc",
+    "precision",
+    "no-timestamp",
+    "suffix",
+];
+
+/// Options of `watch`: those of local backups that apply to each backup
+/// made, and the retention rules of the prunes following them.
+const WATCH: &[&str] = &[
+    "settle",
+    "keep-last",
+    "keep-daily",
+    "keep-weekly",
+    "keep-monthly",
+    "keep-yearly",
+    "older-than",
+    "dereference",
+    "exclude",
+    "exclude-from",
+    "no-ignore-file",
+    "exclude-vcs",
+    "exclude-caches",
+    "max-depth",
+    "max-files",
+    "min-file-size",
+    "max-file-size",
+    "algorithm",
+    "incremental",
+    "checksum",
+    "jobs",
+    "retries",
+    "retry-delay",
+    "no-space-check",
+    "wait-lock",
+    "no-preserve",
+    "preserve-owner",
+    "xattrs",
+    "no-xattrs",
+    "reflink",
+    "fsync",
+    "special-files",
+    "compress",
+    "level",
+    "encrypt",
+    "recipient",
+    "passphrase-file",
+    "sign",
+    "name-format",
+    "timestamp-format",
+    "utc",
+    "precision",
+];
+
+pub const COMMANDS: &[Command] = &[
+    Command {
+        mode: Mode::Backup,
+        name: "backup",
+        aliases: &["b"],
+        arguments: "<source>... [target]",
+        about: "Create a timestamped backup of the file or directory",
+        options: BACKUP,
+    },
+    Command {
+        mode: Mode::Restore,
+        name: "restore",
+        aliases: &["r"],
+        arguments: "<backup> [target]",
+        about: "Restore the file or directory from a backup",
+        options: &[
+            "force",
+            "strict",
+            "no-preserve",
+            "preserve-owner",
+            "xattrs",
+            "no-xattrs",
+            "original-path",
+            "latest",
+            "from",
+            "select",
+            "path",
+            "list",
+            "no-safety",
+            "no-verify",
+            "identity",
+            "passphrase-file",
+            "verify-key",
+            "no-verify-signature",
+            "ssh-option",
+            "endpoint-url",
+            "pipe-from",
+            "name-format",
+            "timestamp-format",
+            "no-timestamp",
+            "suffix",
+        ],
+    },
+    Command {
+        mode: Mode::List,
+        name: "list",
+        aliases: &["l"],
+        arguments: "[dir]",
+        about: "List the backups found in a directory",
+        options: &[
+            "name",
+            "all",
+            "endpoint-url",
+            "name-format",
+            "timestamp-format",
+            "no-timestamp",
+            "suffix",
+        ],
+    },
+    Command {
+        mode: Mode::ListContents,
+        name: "list-contents",
+        aliases: &[],
+        arguments: "<backup>",
+        about: "List the files inside a backup without restoring it",
+        options: &["name-format", "timestamp-format", "no-timestamp", "suffix"],
+    },
+    Command {
+        mode: Mode::Prune,
+        name: "prune",
+        aliases: &[],
+        arguments: "[dir]",
+        about: "Remove old backups from a directory",
+        options: &[
+            "keep-last",
+            "keep-daily",
+            "keep-weekly",
+            "keep-monthly",
+            "keep-yearly",
+            "older-than",
+            "allow-empty",
+            "dry-run",
+            "trash",
+            "trash-dir",
+            "endpoint-url",
+            "name-format",
+            "timestamp-format",
+            "no-timestamp",
+            "suffix",
+            "utc",
+        ],
+    },
+    Command {
+        mode: Mode::Gc,
+        name: "gc",
+        aliases: &[],
+        arguments: "[dir]",
+        about: "Remove what crashed or deleted backups left behind, or empty the trash",
+        options: &["yes", "older-than", "dry-run", "empty-trash", "trash-dir"],
+    },
+    Command {
+        mode: Mode::Catalog,
+        name: "catalog",
+        aliases: &[],
+        arguments: "rebuild [dir]",
+        about: "Rebuild the catalog of a backup directory with 'catalog rebuild'",
+        options: &[],
+    },
+    Command {
+        mode: Mode::Doctor,
+        name: "doctor",
+        aliases: &[],
+        arguments: "[dir]",
+        about: "Check a backup directory and the configuration for problems",
+        options: &[],
+    },
+    Command {
+        mode: Mode::Verify,
+        name: "verify",
+        aliases: &[],
+        arguments: "<backup>",
+        about: "Check a backup against its checksum manifest",
+        options: &["verify-key", "endpoint-url"],
+    },
+    Command {
+        mode: Mode::Keygen,
+        name: "keygen",
+        aliases: &[],
+        arguments: "<key-file>",
+        about: "Generate a key pair to sign backups with",
+        options: &["force"],
+    },
+    Command {
+        mode: Mode::Diff,
+        name: "diff",
+        aliases: &[],
+        arguments: "<backup> <path>",
+        about: "Compare a backup with a file or directory, such as its source",
+        options: &["checksum"],
+    },
+    Command {
+        mode: Mode::History,
+        name: "history",
+        aliases: &[],
+        arguments: "",
+        about: "List the backups and restores performed",
+        options: &[],
+    },
+    Command {
+        mode: Mode::Undo,
+        name: "undo",
+        aliases: &[],
+        arguments: "",
+        about: "Revert the most recent backup or restore not undone yet",
+        options: &[],
+    },
+    Command {
+        mode: Mode::Run,
+        name: "run",
+        aliases: &[],
+        arguments: "<profile> [target]",
+        about: "Back up as described by a profile of the configuration file",
+        options: BACKUP,
+    },
+    Command {
+        mode: Mode::Watch,
+        name: "watch",
+        aliases: &[],
+        arguments: "<source> [target]",
+        about: "Back up a file or directory again whenever it changes",
+        options: WATCH,
+    },
+    Command {
+        mode: Mode::SystemdInstall,
+        name: "systemd-install",
+        aliases: &[],
+        arguments: "<profile>",
+        about: "Write systemd units running a profile on a schedule",
+        options: &["on-calendar", "stdout", "force", "dry-run"],
+    },
+    Command {
+        mode: Mode::SystemdUninstall,
+        name: "systemd-uninstall",
+        aliases: &[],
+        arguments: "<profile>",
+        about: "Remove the systemd units of a profile",
+        options: &["dry-run"],
+    },
+    Command {
+        mode: Mode::Config,
+        name: "config",
+        aliases: &[],
+        arguments: "check",
+        about: "Validate the configuration file with 'config check'",
+        options: &[],
+    },
+    Command {
+        mode: Mode::Version,
+        name: "version",
+        aliases: &[],
+        arguments: "",
+        about: "Print the version, commit and build date of the program",
+        options: &[],
+    },
+    Command {
+        mode: Mode::Help,
+        name: "help",
+        aliases: &["h"],
+        arguments: "[mode]",
+        about: "Display this help message, or the usage of a mode",
+        options: &[],
+    },
+];
+
+pub const OPTIONS: &[Opt] = &[
+    Opt {
+        long: "target",
+        short: Some('t'),
+        value: Some("dir"),
+        help: "Back up into this directory, taking every argument as\n\
+               a source; repeat to back up to several targets",
+    },
+    Opt {
+        long: "force",
+        short: Some('f'),
+        value: None,
+        help: "Overwrite the target if it already exists",
+    },
+    Opt {
+        long: "dereference",
+        short: Some('L'),
+        value: None,
+        help: "Follow symbolic links and back up what they point to",
+    },
+    Opt {
+        long: "strict",
+        short: None,
+        value: None,
+        help: "Refuse to restore files not named like a backup",
+    },
+    Opt {
+        long: "name",
+        short: None,
+        value: Some("pattern"),
+        help: "List only backups whose original name matches the\n\
+               pattern, where '*' and '?' are wildcards, or name\n\
+               the backup of stdin",
+    },
+    Opt {
+        long: "list",
+        short: None,
+        value: None,
+        help: "Restore nothing, list the contents of the backup\n\
+               like list-contents",
+    },
+    Opt {
+        long: "json",
+        short: None,
+        value: None,
+        help: "Print the outcome as JSON on stdout, and messages and\n\
+               errors on stderr",
+    },
+    Opt {
+        long: "all",
+        short: None,
+        value: None,
+        help: "List entries not named like a backup too",
+    },
+    Opt {
+        long: "keep-last",
+        short: None,
+        value: Some("n"),
+        help: "Keep the newest <n> backups of each file when pruning",
+    },
+    Opt {
+        long: "keep-daily",
+        short: None,
+        value: Some("n"),
+        help: "Keep the newest backup of each of the last <n> days\n\
+               with backups when pruning",
+    },
+    Opt {
+        long: "keep-weekly",
+        short: None,
+        value: Some("n"),
+        help: "Keep the newest backup of each of the last <n> weeks\n\
+               with backups when pruning",
+    },
+    Opt {
+        long: "keep-monthly",
+        short: None,
+        value: Some("n"),
+        help: "Keep the newest backup of each of the last <n> months\n\
+               with backups when pruning",
+    },
+    Opt {
+        long: "keep-yearly",
+        short: None,
+        value: Some("n"),
+        help: "Keep the newest backup of each of the last <n> years\n\
+               with backups when pruning",
+    },
+    Opt {
+        long: "older-than",
+        short: None,
+        value: Some("age"),
+        help: "Prune backups older than <age>, e.g. 30d, 12h or 2w",
+    },
+    Opt {
+        long: "allow-empty",
+        short: None,
+        value: None,
+        help: "Allow pruning the newest backup of a file",
+    },
+    Opt {
+        long: "trash",
+        short: None,
+        value: None,
+        help: "Move pruned backups to the trash, ~/.local/share/Trash,\n\
+               rather than deleting them",
+    },
+    Opt {
+        long: "trash-dir",
+        short: None,
+        value: Some("dir"),
+        help: "Use this directory as the trash, implies --trash",
+    },
+    Opt {
+        long: "empty-trash",
+        short: None,
+        value: None,
+        help: "Delete the backups moved to the trash, or only those\n\
+               moved there before --older-than",
+    },
+    Opt {
+        long: "yes",
+        short: Some('y'),
+        value: None,
+        help: "Remove the files found without asking for confirmation",
+    },
+    Opt {
+        long: "wait-lock",
+        short: None,
+        value: Some("secs"),
+        help: "Wait up to secs seconds for another backup of the\n\
+               same source into the same target to finish",
+    },
+    Opt {
+        long: "no-space-check",
+        short: None,
+        value: None,
+        help: "Back up even if the target filesystem looks too\n\
+               full for it",
+    },
+    Opt {
+        long: "no-preserve",
+        short: None,
+        value: None,
+        help: "Do not preserve modes and modification times",
+    },
+    Opt {
+        long: "preserve-owner",
+        short: None,
+        value: None,
+        help: "Preserve owners and groups, which requires root",
+    },
+    Opt {
+        long: "xattrs",
+        short: None,
+        value: None,
+        help: "Preserve extended attributes, ACLs and SELinux\n\
+               labels on Linux (default when running as root)",
+    },
+    Opt {
+        long: "no-xattrs",
+        short: None,
+        value: None,
+        help: "Do not preserve extended attributes",
+    },
+    Opt {
+        long: "reflink",
+        short: None,
+        value: Some("auto|always|never"),
+        help: "Clone files on filesystems that allow it, such as\n\
+               btrfs and XFS, or fail with always (default: auto)",
+    },
+    Opt {
+        long: "buffer-size",
+        short: None,
+        value: Some("bytes"),
+        help: "Size of the buffer files are copied through when\n\
+               not cloned (default: 1048576)",
+    },
+    Opt {
+        long: "fsync",
+        short: None,
+        value: Some("none|file|dir"),
+        help: "Flush each file, and with dir each directory, to disk\n\
+               before completing the backup (default: file)",
+    },
+    Opt {
+        long: "bwlimit",
+        short: None,
+        value: Some("rate"),
+        help: "Write at most rate bytes per second across all jobs,\n\
+               e.g. 500K or 10M (default: 0, unlimited)",
+    },
+    Opt {
+        long: "special-files",
+        short: None,
+        value: Some("skip|fail|record"),
+        help: "Skip named pipes, sockets and devices with a\n\
+               warning (default), fail on them, or record them",
+    },
+    Opt {
+        long: "exclude",
+        short: None,
+        value: Some("glob"),
+        help: "Leave out entries of a directory matching the pattern,\n\
+               may be repeated",
+    },
+    Opt {
+        long: "files-from",
+        short: None,
+        value: Some("file"),
+        help: "Back up the paths listed in the file, one per line,\n\
+               or read from stdin if the file is -",
+    },
+    Opt {
+        long: "null",
+        short: Some('0'),
+        value: None,
+        help: "Separate the paths of --files-from with NUL\n\
+               characters, as printed by find -print0",
+    },
+    Opt {
+        long: "exclude-from",
+        short: None,
+        value: Some("file"),
+        help: "Read exclude patterns from the file, one per line",
+    },
+    Opt {
+        long: "no-ignore-file",
+        short: None,
+        value: None,
+        help: "Do not read .backupignore files",
+    },
+    Opt {
+        long: "exclude-vcs",
+        short: None,
+        value: None,
+        help: "Leave out .git, .hg and .svn directories",
+    },
+    Opt {
+        long: "exclude-caches",
+        short: None,
+        value: None,
+        help: "Leave out directories holding a CACHEDIR.TAG file",
+    },
+    Opt {
+        long: "as-file",
+        short: None,
+        value: None,
+        help: "Write a nonexistent target as a file instead of\n\
+               creating a directory there",
+    },
+    Opt {
+        long: "algorithm",
+        short: None,
+        value: Some("name"),
+        help: "Checksum backups with 'sha256' (default) or 'blake3'",
+    },
+    Opt {
+        long: "original-path",
+        short: None,
+        value: None,
+        help: "Restore a backup to the path it was created from",
+    },
+    Opt {
+        long: "latest",
+        short: None,
+        value: Some("name"),
+        help: "Restore the newest backup of the file or directory\n\
+               with this name, or this path if ambiguous, taking\n\
+               the target as first argument",
+    },
+    Opt {
+        long: "from",
+        short: None,
+        value: Some("dir"),
+        help: "Directory holding the backups searched by --latest\n\
+               (default: the current directory), or where to pick\n\
+               a backup of the file named by the first argument",
+    },
+    Opt {
+        long: "select",
+        short: None,
+        value: Some("n"),
+        help: "Restore the nth newest backup found with --from\n\
+               instead of asking",
+    },
+    Opt {
+        long: "path",
+        short: None,
+        value: Some("pattern"),
+        help: "Restore only the entries of a directory backup or\n\
+               archive matching the pattern, relative to its root\n\
+               (may be repeated)",
+    },
+    Opt {
+        long: "no-safety",
+        short: None,
+        value: None,
+        help: "Delete a destination replaced with --force instead of\n\
+               keeping a safety copy of it",
+    },
+    Opt {
+        long: "no-verify",
+        short: None,
+        value: None,
+        help: "Restore without checking files against the checksum\n\
+               manifest of the backup",
+    },
+    Opt {
+        long: "identity",
+        short: None,
+        value: Some("file"),
+        help: "Decrypt encrypted archives with the age identities of\n\
+               the key file (may be repeated, default: $AGE_IDENTITY)",
+    },
+    Opt {
+        long: "incremental",
+        short: None,
+        value: None,
+        help: "Hard link files unchanged since the previous backup of\n\
+               a directory instead of copying them",
+    },
+    Opt {
+        long: "checksum",
+        short: None,
+        value: None,
+        help: "Compare files by checksum instead of size and\n\
+               modification time to find unchanged ones, also\n\
+               in mirrors",
+    },
+    Opt {
+        long: "max-depth",
+        short: None,
+        value: Some("n"),
+        help: "Leave out the contents of directories n levels below\n\
+               a backed up directory",
+    },
+    Opt {
+        long: "max-files",
+        short: None,
+        value: Some("n"),
+        help: "Stop backing up a directory after n files, which\n\
+               fails the backup with --fail-fast",
+    },
+    Opt {
+        long: "min-file-size",
+        short: None,
+        value: Some("size"),
+        help: "Skip the files of a directory smaller than size,\n\
+               e.g. 1 to skip empty files",
+    },
+    Opt {
+        long: "max-file-size",
+        short: None,
+        value: Some("size"),
+        help: "Skip the files of a directory larger than size,\n\
+               e.g. 500M or 1G",
+    },
+    Opt {
+        long: "skip-unchanged",
+        short: None,
+        value: None,
+        help: "Create no backup if the source is identical to its\n\
+               newest backup in the target",
+    },
+    Opt {
+        long: "mirror",
+        short: None,
+        value: None,
+        help: "Keep the target an up to date copy of the source\n\
+               directory instead of adding a timestamped backup",
+    },
+    Opt {
+        long: "delete",
+        short: None,
+        value: None,
+        help: "Remove from a mirror what is no longer in the source",
+    },
+    Opt {
+        long: "init",
+        short: None,
+        value: None,
+        help: "Start a mirror in a target that is not one yet",
+    },
+    Opt {
+        long: "jobs",
+        short: Some('j'),
+        value: Some("n"),
+        help: "Copy the files of a directory with n threads\n\
+               (default: the number of CPUs, up to 8)",
+    },
+    Opt {
+        long: "keep-going",
+        short: Some('k'),
+        value: None,
+        help: "Copy the rest of a directory when some of its entries\n\
+               fail, and list the failures at the end (default)",
+    },
+    Opt {
+        long: "fail-fast",
+        short: None,
+        value: None,
+        help: "Abort a directory backup at the first failed entry,\n\
+               leaving nothing behind",
+    },
+    Opt {
+        long: "retries",
+        short: None,
+        value: Some("n"),
+        help: "Copy a file again up to n times after a transient\n\
+               error such as EIO (default: 0)",
+    },
+    Opt {
+        long: "every",
+        short: None,
+        value: Some("duration"),
+        help: "Back up again every <duration>, e.g. 24h, until\n\
+               interrupted, starting at once or at --at",
+    },
+    Opt {
+        long: "at",
+        short: None,
+        value: Some("HH:MM"),
+        help: "Back up every day at the time of day, or every\n\
+               --every from it, until interrupted",
+    },
+    Opt {
+        long: "status-file",
+        short: None,
+        value: Some("file"),
+        help: "Write the exit status, times and error of the last\n\
+               scheduled backup to the file as JSON",
+    },
+    Opt {
+        long: "notify-cmd",
+        short: None,
+        value: Some("command"),
+        help: "Run the shell command once the backup finishes, even if\n\
+               it failed, with a summary on its stdin",
+    },
+    Opt {
+        long: "notify-on",
+        short: None,
+        value: Some("when"),
+        help: "Run --notify-cmd on 'always', 'failure' or 'success'\n\
+               (default: always)",
+    },
+    Opt {
+        long: "metrics-file",
+        short: None,
+        value: Some("file"),
+        help: "Write Prometheus gauges of the backup into the file,\n\
+               keeping the series of other sources and profiles",
+    },
+    Opt {
+        long: "on-calendar",
+        short: None,
+        value: Some("calendar"),
+        help: "Run the profile at the systemd calendar expression,\n\
+               e.g. 'Mon..Fri 22:00' (default: daily)",
+    },
+    Opt {
+        long: "stdout",
+        short: None,
+        value: None,
+        help: "Print the units rather than writing them",
+    },
+    Opt {
+        long: "settle",
+        short: None,
+        value: Some("duration"),
+        help: "Back up once no change was seen for <duration>, e.g.\n\
+               30s or 2m (default: 5s)",
+    },
+    Opt {
+        long: "retry-delay",
+        short: None,
+        value: Some("duration"),
+        help: "Wait before the first retry, doubled for each next\n\
+               one, e.g. 5s (default: 1s)",
+    },
+    Opt {
+        long: "allow-empty-glob",
+        short: None,
+        value: None,
+        help: "Only warn about a source pattern matching nothing,\n\
+               instead of failing",
+    },
+    Opt {
+        long: "dry-run",
+        short: Some('n'),
+        value: None,
+        help: "Print what would be done without doing it",
+    },
+    Opt {
+        long: "config",
+        short: None,
+        value: Some("file"),
+        help: "Read the configuration from the file instead of\n\
+               ~/.config/backup/config.toml",
+    },
+    Opt {
+        long: "verbose",
+        short: Some('v'),
+        value: None,
+        help: "Print each file copied and a summary when done",
+    },
+    Opt {
+        long: "quiet",
+        short: Some('q'),
+        value: None,
+        help: "Print nothing but errors",
+    },
+    Opt {
+        long: "log-file",
+        short: None,
+        value: Some("file"),
+        help: "Append every message but the verbose ones to the file,\n\
+               whatever the verbosity",
+    },
+    Opt {
+        long: "log-format",
+        short: None,
+        value: Some("format"),
+        help: "Write the log file as 'text' lines or 'json' objects\n\
+               (default: text)",
+    },
+    Opt {
+        long: "log-max-size",
+        short: None,
+        value: Some("size"),
+        help: "Rotate the log file once it would grow past the size,\n\
+               e.g. 10M",
+    },
+    Opt {
+        long: "log-keep",
+        short: None,
+        value: Some("count"),
+        help: "Number of old log files kept by --log-max-size\n\
+               (default: 5)",
+    },
+    Opt {
+        long: "log-syslog",
+        short: None,
+        value: None,
+        help: "Send every message but the verbose ones to journald or\n\
+               syslog, as 'backup'",
+    },
+    Opt {
+        long: "compress",
+        short: Some('c'),
+        value: Some("name"),
+        help: "Archive directories compressed with 'gzip' or 'zstd'\n\
+               (inferred from a .tar.gz, .tgz, .tar.zst or .tzst\n\
+               target), or 'none'",
+    },
+    Opt {
+        long: "level",
+        short: None,
+        value: Some("n"),
+        help: "Compression level, 1-9 for gzip (default: 6) and\n\
+               1-19 for zstd (default: 3)",
+    },
+    Opt {
+        long: "encrypt",
+        short: None,
+        value: None,
+        help: "Encrypt the archive of a directory to the recipients\n\
+               given with --recipient, appending .age to its name",
+    },
+    Opt {
+        long: "recipient",
+        short: None,
+        value: Some("key"),
+        help: "Age public key, age1..., that encrypted archives can\n\
+               be decrypted by (may be repeated)",
+    },
+    Opt {
+        long: "encrypt-passphrase",
+        short: None,
+        value: None,
+        help: "Encrypt the archive of a directory with a passphrase,\n\
+               asked for twice on the terminal",
+    },
+    Opt {
+        long: "passphrase-file",
+        short: None,
+        value: Some("file"),
+        help: "Read the passphrase of encrypted archives from the\n\
+               first line of the file (default: $BACKUP_PASSPHRASE)",
+    },
+    Opt {
+        long: "work-factor",
+        short: None,
+        value: Some("n"),
+        help: "Make deriving the key from the passphrase cost 2^n,\n\
+               1-22 (default: 18)",
+    },
+    Opt {
+        long: "sign",
+        short: None,
+        value: Some("key-file"),
+        help: "Sign the checksum manifest with the ed25519 secret\n\
+               key, writing the signature to <backup>.sig",
+    },
+    Opt {
+        long: "split",
+        short: None,
+        value: Some("size"),
+        help: "Write the archive in parts of at most <size>, e.g. 4000M,\n\
+               named <backup>.000, <backup>.001, ...",
+    },
+    Opt {
+        long: "ssh-option",
+        short: None,
+        value: Some("option"),
+        help: "Pass -o <option> to ssh for a [user@]host:path target\n\
+               or backup, such as Port=2222, may be repeated",
+    },
+    Opt {
+        long: "endpoint-url",
+        short: None,
+        value: Some("url"),
+        help: "Send the requests about an s3:// target or backup to\n\
+               this URL, e.g. MinIO (default: $AWS_ENDPOINT_URL)",
+    },
+    Opt {
+        long: "pipe-to",
+        short: None,
+        value: Some("command"),
+        help: "Write the backup to the stdin of the shell command,\n\
+               with {filename} and {source} filled in",
+    },
+    Opt {
+        long: "pipe-from",
+        short: None,
+        value: Some("command"),
+        help: "Read the backup from the stdout of the shell command,\n\
+               with {filename} and {source} filled in",
+    },
+    Opt {
+        long: "verify-key",
+        short: None,
+        value: Some("key"),
+        help: "Refuse backups not signed by this public key,\n\
+               ed25519:... or a .pub file (default: $BACKUP_VERIFY_KEY)",
+    },
+    Opt {
+        long: "no-verify-signature",
+        short: None,
+        value: None,
+        help: "Restore even if the signature does not check with\n\
+               --verify-key, with a warning",
+    },
+    Opt {
+        long: "name-format",
+        short: None,
+        value: Some("template"),
+        help: "Name backups after the template, such as\n\
+               '{name}-{hostname}-{timestamp}.bak', and recognize\n\
+               them by it (default: '{name}.{timestamp}.backup')",
+    },
+    Opt {
+        long: "timestamp-format",
+        short: None,
+        value: Some("format"),
+        help: "Write timestamps with the strftime format, and read\n\
+               them back with it (default: '%Y-%m-%d_%H-%M-%S')",
+    },
+    Opt {
+        long: "utc",
+        short: None,
+        value: None,
+        help: "Write timestamps in UTC rather than local time, and\n\
+               prune by UTC days",
+    },
+    Opt {
+        long: "precision",
+        short: None,
+        value: Some("s|ms|ns"),
+        help: "Write timestamps to the second, millisecond or\n\
+               nanosecond (default: s)",
+    },
+    Opt {
+        long: "no-timestamp",
+        short: None,
+        value: None,
+        help: "Name backups <name>.backup, without a timestamp,\n\
+               refusing to replace an existing one unless forced",
+    },
+    Opt {
+        long: "suffix",
+        short: None,
+        value: Some("suffix"),
+        help: "Suffix of the names given by --no-timestamp, such\n\
+               as '.bak' (default: '.backup')",
+    },
+    Opt {
+        long: "help",
+        short: Some('h'),
+        value: None,
+        help: "Display the usage of the mode",
+    },
+];
+
+impl Command {
+    /// Finds the mode named `name` or one of its aliases.
+    pub fn find(name: &str) -> Option<&'static Command> {
+        COMMANDS
+            .iter()
+            .find(|command| command.name == name || command.aliases.contains(&name))
+    }
+
+    /// Returns the definition of `mode`.
+    pub fn of(mode: Mode) -> &'static Command {
+        COMMANDS
+            .iter()
+            .find(|command| command.mode == mode)
+            .expect("every mode is defined")
+    }
+
+    /// Checks whether the mode accepts the option named `long`.
+    fn accepts(&self, long: &str) -> bool {
+        GLOBAL.contains(&long) || self.options.contains(&long)
+    }
+
+    /// Returns the options accepted by the mode, in the order of [`OPTIONS`].
+    pub fn options(&self) -> impl Iterator<Item = &'static Opt> + '_ {
+        OPTIONS.iter().filter(|opt| self.accepts(opt.long))
+    }
+}
+
+/// Command line split into its mode, options and positional arguments.
+#[derive(Debug)]
+pub struct Parsed {
+    pub command: &'static Command,
+    /// Options in the order given, with their value if they take one.
+    pub options: Vec<(&'static Opt, Option<String>)>,
+    pub arguments: Vec<String>,
+}
+
+/// Splits `<mode> [options] [arguments]`.
+///
+/// Options may be given anywhere after the mode, as `--long`, `-s` or a
+/// cluster of short flags such as `-fv`. A value follows its option as the
+/// next argument, after `=` for long options or right after the letter for
+/// short ones. Everything after `--` is a positional argument.
+pub fn parse(args: impl IntoIterator<Item = String>) -> Result<Parsed, String> {
+    let mut iter = args.into_iter();
+
+    let command = match iter.next().as_deref() {
+        None | Some("-h" | "--help") => Command::of(Mode::Help),
+        Some("-V" | "--version") => Command::of(Mode::Version),
+        Some(name) => Command::find(name).ok_or_else(|| unknown_command(name))?,
+    };
+
+    let mut parsed = Parsed {
+        command,
+        options: Vec::new(),
+        arguments: Vec::new(),
+    };
+    while let Some(arg) = iter.next() {
+        if arg == "--" {
+            parsed.arguments.extend(iter.by_ref());
+        } else if let Some(long) = arg.strip_prefix("--") {
+            let (name, inline) = match long.split_once('=') {
+                Some((name, value)) => (name, Some(value.to_owned())),
+                None => (long, None),
+            };
+            let opt = find_long(command, name)?;
+            let value = match (opt.value, inline) {
+                (None, Some(_)) => return Err(format!("Option '--{name}' takes no value")),
+                (None, None) => None,
+                (Some(_), Some(value)) => Some(value),
+                (Some(_), None) => Some(value(&mut iter, opt)?),
+            };
+            parsed.options.push((opt, value));
+        } else if let Some(cluster) = arg.strip_prefix('-').filter(|flags| !flags.is_empty()) {
+            for (i, letter) in cluster.char_indices() {
+                let opt = find_short(command, letter)?;
+                if opt.value.is_none() {
+                    parsed.options.push((opt, None));
+                    continue;
+                }
+                let rest = &cluster[i + letter.len_utf8()..];
+                let value = match rest.is_empty() {
+                    true => value(&mut iter, opt)?,
+                    false => rest.to_owned(),
+                };
+                parsed.options.push((opt, Some(value)));
+                break;
+            }
+        } else {
+            parsed.arguments.push(arg);
+        }
+    }
+    Ok(parsed)
+}
+
+/// Finds the option `--name` accepted by `command`.
+fn find_long(command: &Command, name: &str) -> Result<&'static Opt, String> {
+    match OPTIONS.iter().find(|opt| opt.long == name) {
+        Some(opt) if command.accepts(name) => Ok(opt),
+        Some(_) => Err(format!(
+            "Option '--{name}' does not apply to {}",
+            command.name
+        )),
+        None => {
+            let known = command.options().map(|opt| opt.long);
+            Err(match suggestion(name, known) {
+                Some(close) => format!("Unknown option '--{name}', did you mean '--{close}'?"),
+                None => format!("Unknown option '--{name}'"),
+            })
+        }
+    }
+}
+
+/// Returns the error message for the unknown mode `name`, suggesting the
+/// mode it is likely a typo of.
+pub fn unknown_command(name: &str) -> String {
+    let names = COMMANDS.iter().map(|command| command.name);
+    match suggestion(name, names) {
+        Some(close) => format!("Unknown command '{name}', did you mean '{close}'?"),
+        None => format!("Unknown command '{name}'"),
+    }
+}
+
+/// Returns the one of `words` that `word` is likely an abbreviation or a
+/// typo of: the only one it starts, or else the closest by edit distance.
+fn suggestion<'a>(word: &str, words: impl IntoIterator<Item = &'a str>) -> Option<&'a str> {
+    let words: Vec<_> = words.into_iter().collect();
+    let mut started = words.iter().filter(|candidate| candidate.starts_with(word));
+    match (started.next(), started.next()) {
+        (Some(only), None) => Some(only),
+        _ => pattern::closest_word(word, words.iter().copied()),
+    }
+}
+
+/// Finds the option `-letter` accepted by `command`.
+fn find_short(command: &Command, letter: char) -> Result<&'static Opt, String> {
+    match OPTIONS.iter().find(|opt| opt.short == Some(letter)) {
+        Some(opt) if command.accepts(opt.long) => Ok(opt),
+        Some(_) => Err(format!(
+            "Option '-{letter}' does not apply to {}",
+            command.name
+        )),
+        None => Err(format!("Unknown option '-{letter}'")),
+    }
+}
+
+/// Takes the value following `opt`.
+fn value(iter: &mut impl Iterator<Item = String>, opt: &Opt) -> Result<String, String> {
+    iter.next()
+        .ok_or_else(|| format!("Option '--{}' requires a value", opt.long))
+}
@@ -0,0 +1,646 @@
+//! Configuration file holding default options and named profiles.
+//!
+//! The configuration is read from `~/.config/backup/config.toml`, or
+//! `$XDG_CONFIG_HOME/backup/config.toml`, and written in a subset of TOML:
+//! top-level keys are defaults for every invocation, and each
+//! `[profile.<name>]` table describes a backup run by `backup run <name>`.
+//!
+//! ```toml
+//! target = "~/backups"
+//! compress = "zstd"
+//! exclude = ["target/", "*.tmp"]
+//!
+//! [profile.photos]
+//! source = "~/photos"
+//! target = "/mnt/backups"
+//! incremental = true
+//! ```
+//!
+//! Values are double-quoted strings with `\` escapes, single-quoted literal
+//! strings, integers, booleans and arrays of strings, which may span several
+//! lines. Comments start with `#`.
+//!
+//! Some settings can also be given through environment variables, see
+//! [`Settings::from_env`]. Each setting is taken from the command line, else
+//! the environment, else the configuration file, as done by [`resolve`].
+
+use std::collections::BTreeMap;
+use std::env;
+use std::ffi::OsString;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::error::BackupError;
+use crate::naming::{NameFormat, Precision};
+use crate::writer::Level;
+
+/// Options that the configuration file can set, each `None` when unset.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Settings {
+    /// Path to back up, only in profiles.
+    pub source: Option<PathBuf>,
+    /// Directory or file the backup is written to.
+    pub target: Option<PathBuf>,
+    /// Compression of directory archives, as accepted by `--compress`.
+    pub compress: Option<String>,
+    /// Compression level.
+    pub level: Option<u32>,
+    /// Exclude patterns, added to those given on the command line.
+    pub excludes: Vec<String>,
+    /// Least important level of the messages printed.
+    pub verbosity: Option<Level>,
+    /// Checksum algorithm, as accepted by `--algorithm`.
+    pub algorithm: Option<String>,
+    /// Hard link files unchanged since the previous backup.
+    pub incremental: Option<bool>,
+    /// Skip backups of sources unchanged since their newest backup.
+    pub skip_unchanged: Option<bool>,
+    /// Leave out the directories of version control systems.
+    pub exclude_vcs: Option<bool>,
+    /// Leave out the directories tagged as caches.
+    pub exclude_caches: Option<bool>,
+    /// Follow symbolic links.
+    pub dereference: Option<bool>,
+    /// Number of threads copying the files of a directory.
+    pub jobs: Option<usize>,
+    /// Color the output.
+    pub color: Option<bool>,
+    /// Template of backup names, see [`NameFormat`].
+    pub name_format: Option<String>,
+    /// Format of the timestamp of backup names, see
+    /// [`NameFormat::timestamp_format`].
+    pub timestamp_format: Option<String>,
+    /// Render the timestamps of backups in UTC.
+    pub utc: Option<bool>,
+    /// Name backups without a timestamp, see
+    /// [`NameFormat::without_timestamp`].
+    pub no_timestamp: Option<bool>,
+    /// Suffix of the backups named without a timestamp.
+    pub suffix: Option<String>,
+    /// Resolution of the timestamps of backups, see [`Precision`].
+    pub precision: Option<String>,
+}
+
+impl Settings {
+    /// Returns these settings, with the unset ones taken from `fallback`.
+    /// Exclude patterns of both are kept, those of `fallback` first.
+    pub fn or(self, fallback: &Settings) -> Settings {
+        let fallback = fallback.clone();
+        Settings {
+            source: self.source.or(fallback.source),
+            target: self.target.or(fallback.target),
+            compress: self.compress.or(fallback.compress),
+            level: self.level.or(fallback.level),
+            excludes: fallback.excludes.into_iter().chain(self.excludes).collect(),
+            verbosity: self.verbosity.or(fallback.verbosity),
+            algorithm: self.algorithm.or(fallback.algorithm),
+            incremental: self.incremental.or(fallback.incremental),
+            skip_unchanged: self.skip_unchanged.or(fallback.skip_unchanged),
+            exclude_vcs: self.exclude_vcs.or(fallback.exclude_vcs),
+            exclude_caches: self.exclude_caches.or(fallback.exclude_caches),
+            dereference: self.dereference.or(fallback.dereference),
+            jobs: self.jobs.or(fallback.jobs),
+            color: self.color.or(fallback.color),
+            name_format: self.name_format.or(fallback.name_format),
+            timestamp_format: self.timestamp_format.or(fallback.timestamp_format),
+            utc: self.utc.or(fallback.utc),
+            no_timestamp: self.no_timestamp.or(fallback.no_timestamp),
+            suffix: self.suffix.or(fallback.suffix),
+            precision: self.precision.or(fallback.precision),
+        }
+    }
+
+    /// Reads the settings given by environment variables: `BACKUP_DIR` is
+    /// the target, `BACKUP_COMPRESS` the compression, and `NO_COLOR` or
+    /// `BACKUP_NO_COLOR` disable colors. Empty variables are ignored.
+    pub fn from_env() -> Settings {
+        Settings::from_vars(env::var_os)
+    }
+
+    fn from_vars(var: impl Fn(&'static str) -> Option<OsString>) -> Settings {
+        let var = |name| var(name).filter(|value| !value.is_empty());
+        let no_color = var("NO_COLOR").or_else(|| var("BACKUP_NO_COLOR"));
+        Settings {
+            target: var("BACKUP_DIR").map(PathBuf::from),
+            compress: var("BACKUP_COMPRESS").map(|value| value.to_string_lossy().into_owned()),
+            color: no_color.map(|_| false),
+            ..Settings::default()
+        }
+    }
+}
+
+/// Resolves the settings of an invocation, each taken from `cli` if set
+/// there, else from `env`, else from `config`. Settings unset in all three
+/// keep their built-in default. Exclude patterns of all three are kept.
+///
+/// # Examples
+///
+/// ```
+/// use backup::config::{self, Settings};
+///
+/// let env = Settings { compress: Some("gzip".to_owned()), ..Settings::default() };
+/// let config = Settings { compress: Some("zstd".to_owned()), level: Some(19), ..Settings::default() };
+/// let settings = config::resolve(Settings::default(), env, &config);
+/// assert_eq!(settings.compress.as_deref(), Some("gzip"));
+/// assert_eq!(settings.level, Some(19));
+/// ```
+pub fn resolve(cli: Settings, env: Settings, config: &Settings) -> Settings {
+    cli.or(&env.or(config))
+}
+
+/// Contents of a configuration file.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Config {
+    /// Settings applying to every invocation.
+    pub defaults: Settings,
+    /// Named profiles, each overriding the defaults.
+    pub profiles: BTreeMap<String, Settings>,
+}
+
+impl Config {
+    /// Returns the default location of the configuration file, or `None` if
+    /// neither `XDG_CONFIG_HOME` nor the home directory is set.
+    pub fn default_path() -> Option<PathBuf> {
+        config_home().map(|dir| dir.join("backup").join("config.toml"))
+    }
+
+    /// Reads the configuration file at `path`.
+    pub fn load(path: &Path) -> Result<Config, BackupError> {
+        let text = fs::read_to_string(path).map_err(|source| match source.kind() {
+            io::ErrorKind::NotFound => BackupError::NotFound(path.to_path_buf()),
+            _ => BackupError::ReadFailed {
+                path: path.to_path_buf(),
+                source,
+            },
+        })?;
+        Config::parse(&text).map_err(|(line, message)| BackupError::Config {
+            path: path.to_path_buf(),
+            line,
+            message,
+        })
+    }
+
+    /// Parses the contents of a configuration file, failing with the line
+    /// number and a description of the first error.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use backup::config::Config;
+    ///
+    /// let config = Config::parse("compress = 'zstd'\n[profile.etc]\nsource = '/etc'").unwrap();
+    /// assert_eq!(config.defaults.compress.as_deref(), Some("zstd"));
+    /// assert!(config.profiles.contains_key("etc"));
+    /// ```
+    pub fn parse(text: &str) -> Result<Config, (usize, String)> {
+        let mut config = Config::default();
+        let mut profile: Option<String> = None;
+        let mut lines = text.lines().enumerate().map(|(i, line)| (i + 1, line));
+
+        while let Some((number, line)) = lines.next() {
+            let line = strip_comment(line).trim();
+            if line.is_empty() {
+                continue;
+            }
+
+            if let Some(header) = line.strip_prefix('[') {
+                let name = header
+                    .strip_suffix(']')
+                    .map(str::trim)
+                    .and_then(|table| table.strip_prefix("profile."))
+                    .map(|name| unquote(name.trim()))
+                    .filter(|name| !name.is_empty())
+                    .ok_or_else(|| {
+                        let message = format!("invalid table '{line}', expected [profile.<name>]");
+                        (number, message)
+                    })?;
+                if config.profiles.contains_key(&name) {
+                    return Err((number, format!("profile '{name}' is defined twice")));
+                }
+                config.profiles.insert(name.clone(), Settings::default());
+                profile = Some(name);
+                continue;
+            }
+
+            let (key, value) = line
+                .split_once('=')
+                .ok_or_else(|| (number, format!("expected 'key = value', found '{line}'")))?;
+            let key = unquote(key.trim());
+            let mut value = value.trim().to_owned();
+            // Arrays may continue over the following lines.
+            while value.starts_with('[') && !value.ends_with(']') {
+                let (_, next) = lines
+                    .next()
+                    .ok_or_else(|| (number, format!("unterminated array for '{key}'")))?;
+                value.push(' ');
+                value.push_str(strip_comment(next).trim());
+            }
+
+            let table = match &profile {
+                Some(name) => format!("[profile.{name}]"),
+                None => "the defaults".to_owned(),
+            };
+            let settings = match &profile {
+                Some(name) => config.profiles.get_mut(name).expect("profile was inserted"),
+                None => &mut config.defaults,
+            };
+            let value =
+                parse_value(&value).map_err(|message| (number, format!("'{key}': {message}")))?;
+            set(settings, &key, value, profile.is_some())
+                .map_err(|message| (number, format!("'{key}' in {table}: {message}")))?;
+        }
+
+        Ok(config)
+    }
+
+    /// Returns the settings of the profile `name`, completed with the
+    /// defaults.
+    pub fn profile(&self, name: &str) -> Result<Settings, BackupError> {
+        let profile = self.profiles.get(name).ok_or_else(|| {
+            let known: Vec<_> = self.profiles.keys().map(String::as_str).collect();
+            BackupError::InvalidOption(format!(
+                "Unknown profile '{name}', expected one of: {}",
+                known.join(", ")
+            ))
+        })?;
+        Ok(profile.clone().or(&self.defaults))
+    }
+}
+
+/// A value of the configuration file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Value {
+    String(String),
+    Integer(i64),
+    Boolean(bool),
+    Array(Vec<String>),
+}
+
+impl Value {
+    fn type_name(&self) -> &'static str {
+        match self {
+            Value::String(_) => "a string",
+            Value::Integer(_) => "an integer",
+            Value::Boolean(_) => "a boolean",
+            Value::Array(_) => "an array",
+        }
+    }
+}
+
+/// Stores `value` as the setting named `key`.
+fn set(settings: &mut Settings, key: &str, value: Value, in_profile: bool) -> Result<(), String> {
+    let mismatch =
+        |expected: &str, value: &Value| format!("expected {expected}, found {}", value.type_name());
+    let string = |value: Value| match value {
+        Value::String(string) => Ok(string),
+        other => Err(mismatch("a string", &other)),
+    };
+    let boolean = |value: Value| match value {
+        Value::Boolean(boolean) => Ok(boolean),
+        other => Err(mismatch("a boolean", &other)),
+    };
+    let number = |value: Value| match value {
+        Value::Integer(number) => {
+            u32::try_from(number).map_err(|_| "expected a positive number".to_owned())
+        }
+        other => Err(mismatch("an integer", &other)),
+    };
+
+    match key {
+        "source" if !in_profile => return Err("only allowed in profiles".to_owned()),
+        "source" => settings.source = Some(expand_home(&string(value)?)),
+        "target" => settings.target = Some(expand_home(&string(value)?)),
+        "compress" => settings.compress = Some(string(value)?),
+        "level" => settings.level = Some(number(value)?),
+        "exclude" => match value {
+            Value::Array(patterns) => settings.excludes.extend(patterns),
+            Value::String(pattern) => settings.excludes.push(pattern),
+            other => return Err(mismatch("an array of strings", &other)),
+        },
+        "verbosity" => {
+            settings.verbosity = Some(match string(value)?.as_str() {
+                "quiet" => Level::Error,
+                "normal" => Level::Info,
+                "verbose" => Level::Verbose,
+                other => {
+                    return Err(format!(
+                        "unknown verbosity '{other}', expected quiet, normal or verbose"
+                    ))
+                }
+            })
+        }
+        "algorithm" => settings.algorithm = Some(string(value)?),
+        "incremental" => settings.incremental = Some(boolean(value)?),
+        "skip-unchanged" => settings.skip_unchanged = Some(boolean(value)?),
+        "exclude-vcs" => settings.exclude_vcs = Some(boolean(value)?),
+        "exclude-caches" => settings.exclude_caches = Some(boolean(value)?),
+        "dereference" => settings.dereference = Some(boolean(value)?),
+        "color" => settings.color = Some(boolean(value)?),
+        "name-format" => {
+            let template = string(value)?;
+            NameFormat::new(&template).map_err(|error| error.to_string())?;
+            settings.name_format = Some(template);
+        }
+        "timestamp-format" => {
+            let format = string(value)?;
+            NameFormat::default()
+                .timestamp_format(&format)
+                .map_err(|error| error.to_string())?;
+            settings.timestamp_format = Some(format);
+        }
+        "utc" => settings.utc = Some(boolean(value)?),
+        "no-timestamp" => settings.no_timestamp = Some(boolean(value)?),
+        "suffix" => {
+            let suffix = string(value)?;
+            NameFormat::without_timestamp(&suffix).map_err(|error| error.to_string())?;
+            settings.suffix = Some(suffix);
+        }
+        "precision" => {
+            let precision = string(value)?;
+            Precision::parse(&precision).map_err(|error| error.to_string())?;
+            settings.precision = Some(precision);
+        }
+        "jobs" => match number(value)? {
+            0 => return Err("expected at least 1".to_owned()),
+            jobs => settings.jobs = Some(jobs as usize),
+        },
+        _ => return Err("unknown key".to_owned()),
+    }
+    Ok(())
+}
+
+/// Parses a single value.
+fn parse_value(text: &str) -> Result<Value, String> {
+    if let Some(items) = text.strip_prefix('[') {
+        let items = items.strip_suffix(']').ok_or("unterminated array")?;
+        let mut strings = Vec::new();
+        let mut rest = items.trim();
+        while !rest.is_empty() {
+            let (string, after) = parse_string(rest)?;
+            strings.push(string);
+            rest = after.trim_start();
+            rest = match rest.strip_prefix(',') {
+                Some(after) => after.trim_start(),
+                None if rest.is_empty() => rest,
+                None => return Err(format!("expected ',' in array, found '{rest}'")),
+            };
+        }
+        return Ok(Value::Array(strings));
+    }
+    if text.starts_with(['"', '\'']) {
+        return match parse_string(text)? {
+            (string, "") => Ok(Value::String(string)),
+            (_, rest) => Err(format!("unexpected '{rest}' after string")),
+        };
+    }
+    match text {
+        "true" => Ok(Value::Boolean(true)),
+        "false" => Ok(Value::Boolean(false)),
+        _ => text
+            .replace('_', "")
+            .parse()
+            .map(Value::Integer)
+            .map_err(|_| format!("invalid value '{text}'")),
+    }
+}
+
+/// Parses the string at the start of `text`, returning it and what follows.
+fn parse_string(text: &str) -> Result<(String, &str), String> {
+    if let Some(literal) = text.strip_prefix('\'') {
+        let end = literal.find('\'').ok_or("unterminated string")?;
+        return Ok((literal[..end].to_owned(), literal[end + 1..].trim_start()));
+    }
+
+    let basic = text
+        .strip_prefix('"')
+        .ok_or_else(|| format!("expected a string, found '{text}'"))?;
+    let mut string = String::new();
+    let mut chars = basic.char_indices();
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '"' => return Ok((string, basic[i + 1..].trim_start())),
+            '\\' => match chars.next().map(|(_, c)| c) {
+                Some('n') => string.push('\n'),
+                Some('t') => string.push('\t'),
+                Some(c @ ('"' | '\\')) => string.push(c),
+                Some(c) => return Err(format!("unknown escape '\\{c}'")),
+                None => break,
+            },
+            c => string.push(c),
+        }
+    }
+    Err("unterminated string".to_owned())
+}
+
+/// Removes a `#` comment from `line`, unless it is inside a string.
+fn strip_comment(line: &str) -> &str {
+    let mut quote = None;
+    let mut escaped = false;
+    for (i, c) in line.char_indices() {
+        match (quote, c) {
+            (Some('"'), '\\') if !escaped => {
+                escaped = true;
+                continue;
+            }
+            (Some(q), c) if c == q && !escaped => quote = None,
+            (None, '"' | '\'') => quote = Some(c),
+            (None, '#') => return &line[..i],
+            _ => {}
+        }
+        escaped = false;
+    }
+    line
+}
+
+/// Strips the quotes around a key or table name, if any.
+fn unquote(name: &str) -> String {
+    match parse_string(name) {
+        Ok((name, "")) => name,
+        _ => name.to_owned(),
+    }
+}
+
+/// Returns the home directory, if `HOME`, or `USERPROFILE` on Windows, is
+/// set.
+pub(crate) fn home() -> Option<PathBuf> {
+    let var = |name| env::var_os(name).filter(|home| !home.is_empty());
+    var("HOME")
+        .or_else(|| var("USERPROFILE").filter(|_| cfg!(windows)))
+        .map(PathBuf::from)
+}
+
+/// Returns the configuration directory of the user, `$XDG_CONFIG_HOME` or
+/// `~/.config`, or `None` if neither the variable nor the home directory is
+/// set.
+pub(crate) fn config_home() -> Option<PathBuf> {
+    env::var_os("XDG_CONFIG_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| home().map(|home| home.join(".config")))
+}
+
+/// Returns the data directory of the user, `$XDG_DATA_HOME` or
+/// `~/.local/share`, or `None` if neither the variable nor the home directory
+/// is set.
+pub(crate) fn data_home() -> Option<PathBuf> {
+    env::var_os("XDG_DATA_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| home().map(|home| home.join(".local/share")))
+}
+
+/// Expands a leading `~/` in `path` to the home directory.
+fn expand_home(path: &str) -> PathBuf {
+    match (path.strip_prefix("~/"), home()) {
+        (Some(rest), Some(home)) => home.join(rest),
+        _ => PathBuf::from(path),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn defaults_and_profiles_are_parsed() {
+        let config = Config::parse(
+            r#"
+# Defaults for every run.
+target = "/mnt/backups"   # trailing comment
+compress = 'zstd'
+level = 9
+exclude = [
+    "target/",
+    '*.tmp', # temporary files
+]
+exclude-vcs = true
+
+[profile.photos]
+source = "/home/user/photos"
+incremental = true
+jobs = 4
+exclude = ["*.xmp"]
+exclude-caches = true
+
+[profile."web server"]
+source = "/srv/www#1"
+verbosity = "quiet"
+"#,
+        )
+        .unwrap();
+
+        assert_eq!(config.defaults.target, Some(PathBuf::from("/mnt/backups")));
+        assert_eq!(config.defaults.compress.as_deref(), Some("zstd"));
+        assert_eq!(config.defaults.level, Some(9));
+        assert_eq!(config.defaults.excludes, ["target/", "*.tmp"]);
+
+        let photos = config.profile("photos").unwrap();
+        assert_eq!(photos.source, Some(PathBuf::from("/home/user/photos")));
+        assert_eq!(photos.target, Some(PathBuf::from("/mnt/backups")));
+        assert_eq!(photos.incremental, Some(true));
+        assert_eq!(photos.jobs, Some(4));
+        assert_eq!(photos.excludes, ["target/", "*.tmp", "*.xmp"]);
+        assert_eq!(photos.exclude_vcs, Some(true));
+        assert_eq!(photos.exclude_caches, Some(true));
+
+        let web = config.profile("web server").unwrap();
+        assert_eq!(web.source, Some(PathBuf::from("/srv/www#1")));
+        assert_eq!(web.verbosity, Some(Level::Error));
+        assert_eq!(web.exclude_caches, None);
+    }
+
+    #[test]
+    fn strings_support_escapes() {
+        let config = Config::parse(r#"exclude = ["a\"b", "c\\d", 'e\f']"#).unwrap();
+        assert_eq!(config.defaults.excludes, ["a\"b", "c\\d", "e\\f"]);
+    }
+
+    #[test]
+    fn errors_report_the_line_and_key() {
+        let cases = [
+            ("compress = zstd", 1, "'compress': invalid value 'zstd'"),
+            ("\nlevel = 'high'", 2, "'level' in the defaults: expected an integer, found a string"),
+            ("source = '/etc'", 1, "'source' in the defaults: only allowed in profiles"),
+            ("[profile.a]\ncolour = true", 2, "'colour' in [profile.a]: unknown key"),
+            ("[profiles]", 1, "invalid table '[profiles]', expected [profile.<name>]"),
+            ("[profile.a]\n[profile.a]", 2, "profile 'a' is defined twice"),
+            ("exclude = ['a'", 1, "unterminated array for 'exclude'"),
+            ("target", 1, "expected 'key = value', found 'target'"),
+            ("verbosity = 'loud'", 1, "'verbosity' in the defaults: unknown verbosity 'loud', expected quiet, normal or verbose"),
+        ];
+        for (text, line, message) in cases {
+            assert_eq!(
+                Config::parse(text),
+                Err((line, message.to_owned())),
+                "{text}"
+            );
+        }
+    }
+
+    #[test]
+    fn unknown_profiles_list_the_known_ones() {
+        let config = Config::parse("[profile.a]\n[profile.b]").unwrap();
+        let error = config.profile("c").unwrap_err().to_string();
+        assert_eq!(error, "Unknown profile 'c', expected one of: a, b");
+    }
+
+    #[test]
+    fn environment_variables_are_read() {
+        let vars = |name: &str| match name {
+            "BACKUP_DIR" => Some(OsString::from("/mnt/backups")),
+            "BACKUP_COMPRESS" => Some(OsString::from("zstd")),
+            "NO_COLOR" => Some(OsString::new()),
+            "BACKUP_NO_COLOR" => Some(OsString::from("1")),
+            _ => None,
+        };
+        let settings = Settings::from_vars(vars);
+        assert_eq!(settings.target, Some(PathBuf::from("/mnt/backups")));
+        assert_eq!(settings.compress.as_deref(), Some("zstd"));
+        assert_eq!(settings.color, Some(false));
+
+        assert_eq!(Settings::from_vars(|_| None), Settings::default());
+        assert_eq!(
+            Settings::from_vars(|_| Some(OsString::new())),
+            Settings::default()
+        );
+    }
+
+    #[test]
+    fn command_line_overrides_environment_overrides_configuration() {
+        let layer = |value: &str| Settings {
+            target: Some(PathBuf::from(value)),
+            compress: Some(value.to_owned()),
+            color: Some(value == "cli"),
+            excludes: vec![value.to_owned()],
+            ..Settings::default()
+        };
+        let unset = Settings::default;
+
+        // Every combination of the command line, environment and
+        // configuration file setting a value or not.
+        for mask in 0..8 {
+            let set = |bit: u32, value: &str| match mask & (1 << bit) {
+                0 => unset(),
+                _ => layer(value),
+            };
+            let (cli, env, config) = (set(0, "cli"), set(1, "env"), set(2, "config"));
+            let expected = ["cli", "env", "config"]
+                .into_iter()
+                .enumerate()
+                .find(|(bit, _)| mask & (1 << bit) != 0)
+                .map(|(_, value)| value);
+
+            let settings = resolve(cli, env, &config);
+            assert_eq!(settings.target, expected.map(PathBuf::from), "{mask:03b}");
+            assert_eq!(settings.compress.as_deref(), expected, "{mask:03b}");
+            assert_eq!(settings.color, expected.map(|value| value == "cli"));
+            let excludes: Vec<_> = [(2, "config"), (1, "env"), (0, "cli")]
+                .into_iter()
+                .filter(|(bit, _)| mask & (1 << bit) != 0)
+                .map(|(_, value)| value)
+                .collect();
+            assert_eq!(settings.excludes, excludes, "{mask:03b}");
+        }
+    }
+}
@@ -0,0 +1,1207 @@
+//! Console output of the command line tool.
+
+use std::fmt::{self, Display};
+use std::io::{self, IsTerminal, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU8, Ordering};
+use std::sync::OnceLock;
+
+use backup::diff::{Change, Difference};
+use backup::doctor::{Check, Status};
+use backup::gc::Garbage;
+use backup::journal::Operation;
+use backup::list::{ContentEntry, EntryKind, ListEntry};
+use backup::logfile::LogFile;
+use backup::meta::BackupMeta;
+use backup::stats::Stats;
+use backup::syslog::Syslog;
+use backup::writer::{self, Level};
+use backup::{BackupError, ExitCode};
+use serde::Serialize;
+
+use crate::cli::{self, Command, Opt};
+
+/// Format used to display backup timestamps.
+pub const DISPLAY_FORMAT: &str = "%Y-%m-%d %H:%M:%S";
+
+/// Least important level printed, stored as its discriminant.
+static VERBOSITY: AtomicU8 = AtomicU8::new(Level::Info as u8);
+
+/// Whether output may be colored, cleared by `NO_COLOR`. Output is only ever
+/// colored when written to a terminal.
+static COLOR: AtomicBool = AtomicBool::new(true);
+
+/// Whether errors are reported as JSON, set by `--json`.
+static JSON: AtomicBool = AtomicBool::new(false);
+
+/// Whether stdout is reserved for the result of the operation, so that every
+/// message goes to stderr.
+static RESULT_ONLY: AtomicBool = AtomicBool::new(false);
+
+/// File every message at the info level or above is recorded in, set by
+/// `--log-file`.
+static LOG_FILE: OnceLock<LogFile> = OnceLock::new();
+
+/// System log every message at the info level or above is sent to, set by
+/// `--log-syslog`.
+static SYSLOG: OnceLock<Syslog> = OnceLock::new();
+
+/// Sets the least important level printed, whether output may be colored,
+/// whether errors are reported as JSON and whether stdout is reserved for the
+/// result, and routes the messages of the library through [`print`].
+pub fn init(verbosity: Level, color: bool, json: bool, result_only: bool) {
+    VERBOSITY.store(verbosity as u8, Ordering::Relaxed);
+    COLOR.store(color, Ordering::Relaxed);
+    JSON.store(json, Ordering::Relaxed);
+    RESULT_ONLY.store(json || result_only, Ordering::Relaxed);
+    writer::set_logger(print);
+}
+
+/// Records the messages in `file` from now on, whatever the verbosity.
+pub fn set_log_file(file: LogFile) {
+    let _ = LOG_FILE.set(file);
+}
+
+/// Sends the messages to `syslog` from now on, whatever the verbosity.
+pub fn set_syslog(syslog: Syslog) {
+    let _ = SYSLOG.set(syslog);
+}
+
+/// Records `message` at `level` in the log file and the system log only, if
+/// set and `level` is info or above.
+pub fn record(level: Level, message: impl Display) {
+    if level > Level::Info {
+        return;
+    }
+    if let Some(file) = LOG_FILE.get() {
+        file.write(level, format_args!("{message}"));
+    }
+    if let Some(syslog) = SYSLOG.get() {
+        syslog.send(level, format_args!("{message}"));
+    }
+}
+
+/// Checks whether messages at `level` are printed.
+fn enabled(level: Level) -> bool {
+    level as u8 <= VERBOSITY.load(Ordering::Relaxed)
+}
+
+/// Prints `message` if `level` is enabled by the current verbosity.
+///
+/// Errors and warnings go to stderr, everything else to stdout, or to stderr
+/// as well when stdout is reserved for the result. On a terminal, the prefix
+/// of errors is red and that of warnings yellow, and successes are green.
+///
+/// The message is recorded in the log file and the system log whether or not
+/// it is printed.
+pub fn print(level: Level, message: fmt::Arguments<'_>) {
+    record(level, message);
+    if !enabled(level) {
+        return;
+    }
+
+    let to_stderr = level <= Level::Warning || RESULT_ONLY.load(Ordering::Relaxed);
+    let color = COLOR.load(Ordering::Relaxed)
+        && match to_stderr {
+            true => io::stderr().is_terminal(),
+            false => io::stdout().is_terminal(),
+        };
+    let line = match level {
+        Level::Error => format!("{} {message}", paint("backup:", RED, color)),
+        Level::Warning => format!("{} {message}", paint("backup: warning:", YELLOW, color)),
+        Level::Success => paint(&message.to_string(), GREEN, color),
+        Level::Info | Level::Verbose => message.to_string(),
+    };
+
+    match to_stderr {
+        true => eprintln!("{line}"),
+        false => out(line),
+    }
+}
+
+/// Writes `line` on stdout, exiting quietly with a success status once the
+/// reader of a pipe is gone, as `head` is after enough lines.
+pub fn out(line: impl Display) {
+    let mut stdout = io::stdout().lock();
+    if let Err(error) = writeln!(stdout, "{line}") {
+        if error.kind() == io::ErrorKind::BrokenPipe {
+            std::process::exit(ExitCode::Success.code());
+        }
+        log(
+            Level::Error,
+            format_args!("Cannot write to stdout: {error}"),
+        );
+        std::process::exit(ExitCode::Failure.code());
+    }
+}
+
+/// ANSI codes of the colors of messages.
+const RED: &str = "31";
+const YELLOW: &str = "33";
+const GREEN: &str = "32";
+
+/// Wraps `text` in the ANSI escapes of the bold `color` if `enabled`.
+fn paint(text: &str, color: &str, enabled: bool) -> String {
+    match enabled {
+        true => format!("\x1b[1;{color}m{text}\x1b[0m"),
+        false => text.to_owned(),
+    }
+}
+
+/// Prints `message` at `level`, see [`print`].
+pub fn log(level: Level, message: impl Display) {
+    print(level, format_args!("{message}"));
+}
+
+/// Reports `error` at `level`, or as a JSON object with its message and code
+/// on stderr when stdout is reserved for JSON.
+pub fn report(level: Level, error: &BackupError) {
+    if !JSON.load(Ordering::Relaxed) {
+        return log(level, error);
+    }
+
+    record(level, error);
+    let object = serde_json::json!({
+        "error": error.to_string(),
+        "code": error.code(),
+    });
+    eprintln!("{object}");
+}
+
+/// Prints the errors of the entries a backup left out, once it is done.
+pub fn print_failures(failed: &[String]) {
+    log(
+        Level::Error,
+        "the following entries could not be backed up:",
+    );
+    for error in failed {
+        log(Level::Error, format_args!("  {error}"));
+    }
+}
+
+/// Asks which of `entries`, sorted from the newest, to use, returning its
+/// index. Plain Enter picks the newest, and `None` is returned at the end of
+/// input.
+pub fn pick(entries: &[ListEntry]) -> Option<usize> {
+    for (i, entry) in entries.iter().enumerate() {
+        let timestamp = entry.timestamp.map_or_else(
+            || "-".to_owned(),
+            |timestamp| timestamp.format(DISPLAY_FORMAT).to_string(),
+        );
+        eprintln!(
+            "{:>3}) {timestamp}  {:>10}  {}",
+            i + 1,
+            writer::human_bytes(entry.size),
+            entry.path.display()
+        );
+    }
+
+    let mut line = String::new();
+    loop {
+        eprint!("Backup to restore [1]: ");
+        line.clear();
+        match io::stdin().read_line(&mut line) {
+            Ok(0) | Err(_) => return None,
+            Ok(_) => {}
+        }
+        match line.trim() {
+            "" => return Some(0),
+            choice => match choice.parse::<usize>() {
+                Ok(n) if (1..=entries.len()).contains(&n) => return Some(n - 1),
+                _ => eprintln!("Enter a number between 1 and {}", entries.len()),
+            },
+        }
+    }
+}
+
+/// Lists the files `garbage` found by `backup gc` and asks whether to remove
+/// them. Anything but `y` or `yes`, or the end of input, declines.
+pub fn confirm_removal(garbage: &[Garbage]) -> bool {
+    for Garbage { path, kind, size } in garbage {
+        eprintln!(
+            "{:>10}  {:<16}  {}",
+            writer::human_bytes(*size),
+            kind.as_str(),
+            path.display()
+        );
+    }
+
+    let bytes = garbage.iter().map(|garbage| garbage.size).sum();
+    eprint!(
+        "Remove {} files, freeing {}? [y/N] ",
+        garbage.len(),
+        writer::human_bytes(bytes)
+    );
+    let mut line = String::new();
+    match io::stdin().read_line(&mut line) {
+        Ok(0) | Err(_) => {
+            eprintln!();
+            false
+        }
+        Ok(_) => matches!(line.trim().to_lowercase().as_str(), "y" | "yes"),
+    }
+}
+
+/// Asks for a passphrase after `prompt` on stderr, reading it from stdin, a
+/// terminal, without echoing it.
+pub fn ask_passphrase(prompt: &str) -> io::Result<String> {
+    eprint!("{prompt}: ");
+    let _echo = Echo::disable();
+    let mut line = String::new();
+    let read = io::stdin().read_line(&mut line);
+    eprintln!();
+    match read? {
+        0 => Err(io::ErrorKind::UnexpectedEof.into()),
+        _ => Ok(line.trim_end_matches(['\n', '\r']).to_owned()),
+    }
+}
+
+/// Turns off the echo of the terminal on stdin until dropped.
+struct Echo(#[cfg(unix)] Option<libc::termios>);
+
+impl Echo {
+    #[cfg(unix)]
+    fn disable() -> Self {
+        // SAFETY: termios is plain data filled by tcgetattr, and the
+        // attributes set are those read with only the echo cleared.
+        unsafe {
+            let mut termios: libc::termios = std::mem::zeroed();
+            if libc::tcgetattr(libc::STDIN_FILENO, &mut termios) != 0 {
+                return Echo(None);
+            }
+            let saved = termios;
+            termios.c_lflag &= !libc::ECHO;
+            libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &termios);
+            Echo(Some(saved))
+        }
+    }
+
+    /// The passphrase is echoed where the terminal cannot be configured.
+    #[cfg(not(unix))]
+    fn disable() -> Self {
+        Echo()
+    }
+}
+
+impl Drop for Echo {
+    fn drop(&mut self) {
+        #[cfg(unix)]
+        if let Some(saved) = &self.0 {
+            // SAFETY: the attributes are those read when disabling the echo.
+            unsafe { libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, saved) };
+        }
+    }
+}
+
+/// Prints the usage of `command` to stdout, with only the options it accepts.
+pub fn command_usage(command: &Command) {
+    out(format_args!(
+        "Usage: backup {} [options] {}",
+        command.name, command.arguments
+    ));
+    out(command.about);
+    if !command.aliases.is_empty() {
+        out(format_args!("\nAliases: {}", command.aliases.join(", ")));
+    }
+    out("\nOptions:");
+    print_options(command.options());
+}
+
+/// Prints a table of `options` and their description.
+fn print_options<'a>(options: impl Iterator<Item = &'a Opt>) {
+    const WIDTH: usize = 22;
+    for opt in options {
+        let mut label = match opt.short {
+            Some(short) => format!("-{short}, --{}", opt.long),
+            None => format!("    --{}", opt.long),
+        };
+        if let Some(value) = opt.value {
+            label = format!("{label} <{value}>");
+        }
+
+        let mut lines = opt.help.lines();
+        match label.len() > WIDTH {
+            true => out(format_args!("  {label}")),
+            false => out(format_args!(
+                "  {label:<WIDTH$}  {}",
+                lines.next().unwrap_or_default()
+            )),
+        }
+        for line in lines {
+            out(format_args!("  {:WIDTH$}  {line}", ""));
+        }
+    }
+}
+
+/// Prints a hint at the usage after a command line error, to stderr.
+pub fn usage_hint() {
+    eprintln!("Run 'backup help' or 'backup <mode> --help' for usage");
+}
+
+/// Prints the program usage to stdout.
+pub fn usage() {
+    out("Usage: backup <mode> [options] <path/to/file/or/directory> [target]");
+    out("Backup and restore files and directories.\n\nMode:");
+    for command in cli::COMMANDS {
+        let names: Vec<_> = command
+            .aliases
+            .iter()
+            .chain([&command.name])
+            .copied()
+            .collect();
+        out(format_args!(
+            "  {:<13}  {}",
+            names.join(", "),
+            command.about
+        ));
+    }
+    out("\nOptions:");
+    print_options(cli::OPTIONS.iter());
+    out(format_args!(
+        "
+When backing up a directory into an existing file, the file is replaced by a
+tar archive of the directory.
+
+If the target is not specified, the backup will be generated in the current directory.
+A target that does not exist is created as a directory, along with its parents,
+unless --as-file is given or it ends with .tar, .tar.gz, .tgz, .tar.zst or .tzst,
+in which case the backup is written to it directly.
+A backup whose target is its own source, or lies inside it, is refused.
+
+Given more than two paths, a backup takes the last one as target and each of the
+others as a source, or every path as a source with --target. Each source gets a
+backup of its own, and one that fails does not stop the others.
+
+A source containing *, ? or [...] that does not name an existing path is
+expanded to the paths it matches, sorted, with ** matching any number of
+directories. A pattern matching nothing is an error, or only a warning with
+--allow-empty-glob. Quote patterns to keep the shell from expanding them.
+
+With --files-from, the paths listed in a file, or on stdin if the file is -, are
+backed up as well, and the last argument is the target. Paths are listed one per
+line, skipping blank lines and lines starting with #, or separated by NUL
+characters with -0. Relative paths are taken from the current directory. Listed
+paths that do not exist are skipped with a warning, or fail the backup with
+--fail-fast.
+
+The backup file or directory will be named as follows:
+  <target>/<filename>.<timestamp>.backup
+
+Compressed directory backups into a target directory are named:
+  <target>/<filename>.<timestamp>.backup.tar.gz
+  <target>/<filename>.<timestamp>.backup.tar.zst
+
+Backups made within the same second get a counter after the timestamp, e.g.
+hosts.2018-01-01_00-00-00_2.backup, instead of replacing the earlier one.
+
+Names are made valid on FAT, exFAT and NTFS targets: characters such as : or |
+and trailing dots become _, device names such as CON get a _ appended, and
+long names are shortened to fit 255 bytes. A warning shows the name used, and
+restore brings back the original one from the metadata file.
+
+With --name-format, or the name-format key of the configuration file, backups
+are named after a template instead, which restore, list and prune also use to
+recognize them. Its placeholders are {{name}}, the name of the source, {{stem}} and
+{{ext}}, the same split before its extension, {{timestamp}}, {{date}}, {{time}} and
+{{hostname}}. A template holds {{name}}, or {{stem}} and {{ext}}, and {{timestamp}} or
+{{date}}, as in {{name}}-{{hostname}}-{{timestamp}}.bak.
+
+Timestamps are written in local time with the format %Y-%m-%d_%H-%M-%S, or in
+UTC with --utc, which keeps backups made in different time zones in order. The
+format is changed with --timestamp-format, which refuses formats writing
+characters such as / or : that some filesystems do not allow. Backups named
+with the default format are still recognized. With --precision ms or ns, the
+time is followed by milliseconds or nanoseconds, as in 00-00-00.123, for
+backups made more often than once a second. Names of any precision are
+recognized and sorted together.
+
+With --no-timestamp, backups are named <target>/<filename>.backup, or after the
+suffix given with --suffix such as .bak, and an existing one is only replaced
+with --force, which suits quick copies made before editing a file. Restore
+also recognizes <filename>.backup names without these options.
+
+A target of - writes the backup of a directory to stdout as a tar archive,
+compressed with --compress, to be piped to another program. Nothing else is
+printed on stdout, and no checksum manifest or metadata file is written.
+Conversely, restoring - extracts the archive read from stdin to <target>.
+
+A source of - backs up stdin, such as the output of a command, to a file named
+<target>/<name>.<timestamp>.backup after --name. It is not recorded in the
+history, and empty input makes an empty backup with a warning.
+
+With --encrypt, the archive of a directory is encrypted with age to each
+public key given with --recipient, and named <filename>.<timestamp>.backup.tar.age,
+or .tar.gz.age and .tar.zst.age when compressed. Restore detects encrypted
+archives, including on stdin, and decrypts them with the key files given with
+--identity, as written by age-keygen, or the one AGE_IDENTITY names, or the
+AGE-SECRET-KEY-1... identity it holds. Keys are never logged nor recorded in the
+metadata file.
+
+With --encrypt-passphrase instead, the archive is encrypted with a passphrase,
+asked for twice on the terminal, from which a key is derived with scrypt at a
+cost of 2^18, or 2^n with --work-factor n. Restore asks for it once. Scripts
+pass it with --passphrase-file, or BACKUP_PASSPHRASE, which other processes of
+the same user can read. A wrong passphrase is reported as such, while an
+archive modified or cut short fails to extract.
+
+backup keygen <key-file> writes an ed25519 secret key readable by its owner
+only, and the public key to <key-file>.pub. With --sign <key-file>, the
+checksum manifest of the backup is signed, which covers every file it lists,
+and the signature written to <backup>.sig. Verify and restore check it with
+--verify-key, or BACKUP_VERIFY_KEY, holding the ed25519:... public key or the
+path of the .pub file, and refuse backups that are unsigned, signed by another
+key or modified since; --no-verify-signature restores them anyway, with a
+warning. The signature file holds 'key: value' lines: backup-signature: 1,
+algorithm: ed25519, key, manifest, the name of the manifest next to the backup,
+and signature, the base64 of the ed25519 signature of its exact bytes.
+
+With --split <size>, the archive of a directory, made even without
+--compress, is written in parts of at most <size>, such as 4000M for FAT32,
+named <backup>.000, <backup>.001 and so on. The checksum manifest holds the
+digest of the whole archive, and the metadata file the name, size and digest
+of each part. Restore, verify and list-contents take the backup name or any
+part, read the parts back to back, and fail listing the parts that are missing.
+
+A target written [user@]host:path, as for scp, is a directory on another
+machine reached with ssh, using the ssh agent and ~/.ssh/config as usual, plus
+any -o options given with --ssh-option. Directories are sent as archives,
+created with mkdir -p and written to hidden .partial files, renamed into place
+with their checksum manifest, metadata and signature files once all are
+written, or removed if the transfer fails. Restoring host:path reads the backup
+back over ssh, without checking its checksums. A local path holding a colon is
+taken as local if it exists; start it with ./ otherwise, as for a new target.
+
+A target written s3://bucket/prefix stores the backup as an object of the S3
+bucket, under the key prefix/<name>, with its sidecar files next to it, using
+a multipart upload above 8M that is aborted if the backup fails. Requests are
+made with curl and signed with the credentials of AWS_ACCESS_KEY_ID and
+AWS_SECRET_ACCESS_KEY or of the AWS_PROFILE profile in ~/.aws/credentials, in
+the region of AWS_REGION or ~/.aws/config; --endpoint-url sends them to
+another service such as MinIO. The ETag of each upload is checked against the
+data sent. Restore, verify, list and prune accept s3:// keys and prefixes too.
+
+Repeating --target backs up to several targets at once, local or not, under
+the same name. An archive is read and compressed once and written to every
+target as it goes; other backups are made into one target after the other. A
+target failing does not stop the others: each is reported, as a JSON array
+entry with --json, and the backup exits with 4 if some failed, or 1 if all did.
+
+With --pipe-to <command> in place of a target, the backup is written to the
+stdin of the shell command, with {{filename}} replaced by the name of the backup
+and {{source}} by the source, both already quoted. A command using {{filename}}
+receives the checksum manifest, metadata and signature files too, one run each.
+The stderr of the command is shown, and the backup fails if the command fails
+or exits before reading it all. Restore --pipe-from <command> <backup> reads
+the backup named <backup> from the stdout of the command instead.
+
+After each timestamped backup into a directory, a link named
+<target>/<filename>.latest.backup is pointed at it. Restore, list-contents and
+verify accept the link in place of the backup, list leaves it out, and prune
+never removes the backup it points to.
+
+When performing a restore operation without a <target>, the backup is restored
+next to itself under its original name. If <target> is an existing directory,
+the backup is restored inside it under its original name; otherwise it is
+restored to exactly <target>. An existing destination is only replaced with
+--force, and is then first moved aside to <name>.pre-restore.<timestamp>.backup
+in the same directory, which is printed, unless --no-safety is given. If the
+restore fails, the safety copy is moved back. Files not named like a backup are
+restored under their own name with a warning, unless --strict is given. With
+--original-path, the backup is restored to the path recorded in its metadata
+file instead.
+
+The list and prune modes scan the given directory, or the current one, for
+backups. Entries not named like a backup are never pruned. A backup is pruned
+only if no retention rule keeps it, and the newest backup of each file is always
+kept unless --allow-empty is given.
+
+With --keep-daily, --keep-weekly, --keep-monthly and --keep-yearly, backups are
+grouped by the calendar day, ISO week, month or year of their timestamp, and
+the newest backup of each of the last <n> periods with backups is kept. A
+backup kept by several of these rules counts toward each. Periods follow the
+timestamps in the names, in UTC with --utc and in local time otherwise.
+
+With --trash, pruned backups are moved along with their checksum manifests and
+metadata files to the trash of the desktop, $XDG_DATA_HOME/Trash or
+~/.local/share/Trash, where file managers can put them back, or to the
+directory given with --trash-dir, laid out the same way. Backups are copied to
+a trash on another filesystem then removed, with a warning. The gc mode with
+--empty-trash deletes them for good, with --older-than only those moved there
+longer ago, and leaves other entries of the trash alone.
+
+Given a directory instead, gc removes what backups left behind in it: the
+partial files of interrupted backups last written more than --older-than ago
+(default: 24h), the stale lock files of backups that are gone, and the checksum
+manifests, metadata and signature files of backups removed by hand. It lists
+them and asks before removing them unless given --yes, and prints the space
+freed. Declining exits with status 3.
+
+Exclude patterns are matched against paths relative to the backed up
+directory. '*' and '?' match within a name and '**' across directories. A
+pattern without '/' matches entries of that name at any depth, one with a '/'
+is matched from the top of the directory, and a trailing '/' only matches
+directories. Excluded directories are skipped with all their contents.
+
+A .backupignore file in the backed up directory, or in any directory below it,
+lists patterns in the same syntax, one per line, relative to the directory it
+is in. Blank lines and lines starting with '#' are ignored, and patterns
+starting with '!' include again what an earlier pattern excluded. The last
+matching pattern decides, and a deeper file overrides a shallower one.
+
+--exclude-vcs leaves out the .git, .hg and .svn directories, as if excluded
+with a pattern before the others, which may include them again with '!'.
+--exclude-caches leaves out every directory holding a CACHEDIR.TAG file that
+starts with the signature of the Cache Directory Tagging Specification.
+
+Every backup gets a checksum manifest next to it, named after the backup with
+a .sha256 or .blake3 extension, listing the digest of each file it contains in
+the format of sha256sum or b3sum. The verify mode recomputes them and reports
+each file as OK or FAILED; it fails if any file differs or the manifest is
+missing.
+
+Every backup also gets a <backup>.meta.json file recording the absolute path of
+its source, its creation time, the version of this tool, the kind of backup,
+its size, file count and checksum. Listings show the recorded source, and
+pruning a backup removes its manifest and metadata file along with it.
+
+With --incremental, a directory backed up into a directory is compared with the
+newest previous copy of it in the target. Files whose size, permissions and
+modification time are unchanged, or whose checksum is unchanged with
+--checksum, are hard linked to their previous copy instead of being copied
+again. Every backup is still a complete snapshot that can be restored or
+removed on its own.
+
+On Linux, files are cloned when the backup is on the same btrfs or XFS
+filesystem as their source, sharing their contents until either is changed,
+which copies even large files almost instantly. Otherwise they are copied by
+the kernel where possible. --reflink never always copies the contents, and
+--reflink always fails the backup of a file that cannot be cloned.
+
+Special files met in a backed up directory, such as named pipes, sockets and
+devices, are skipped with a warning and counted in the verbose summary. With
+--special-files fail, each of them fails the backup instead, and with
+--special-files record, they are recreated in copies and stored in archives,
+which for devices requires root. Sockets cannot be archived and are skipped.
+
+--bwlimit caps the rate at which a backup writes, such as 10M for 10 MiB per
+second, shared by all of its --jobs, to leave bandwidth to other programs.
+Files are then always copied through the buffer rather than by the kernel.
+With --verbose, the rate written is shown every second while the backup runs,
+and the summary shows the rate reached overall.
+
+Files that cannot be cloned or copied by the kernel are copied through a buffer
+of --buffer-size bytes, 1 MiB by default, and hashed on the way. Each copied
+file is flushed to disk before its backup is renamed into place, so that a
+crash never leaves a backup that looks complete without its contents. --fsync
+dir also flushes the directories written and the one the backup is renamed in,
+and --fsync none leaves writing to the operating system, which is faster but
+unsafe on power loss.
+
+With --retries n, a file of a copy that fails with an error that may not last,
+such as EIO or ETIMEDOUT on a network filesystem, is copied again up to n
+times, after --retry-delay and then twice as long each time. Only I/O errors,
+timeouts and busy, stale or unreachable resources are retried, not missing
+files, denied permissions or a full disk, and Ctrl+C stops the wait at once.
+Retries are logged with --verbose, and the summary counts the files that needed
+them.
+
+Ctrl+C or SIGTERM stops a backup between files, or within a large file, and
+removes the partial copy or archive it was writing before exiting with status
+130. A second Ctrl+C exits at once, leaving them behind.
+
+Only one backup of a source into a target runs at a time, guarded by a
+.<name>.backup.lock file next to its backups holding the PID of the process. A
+second one exits with status 6 at once, or waits up to --wait-lock seconds for
+the first to finish. A lock left behind by a process that no longer runs is
+taken over with a warning.
+
+Before copying, the size of the source is compared with the free space on the
+filesystem of the target, and the backup fails if it cannot fit, unless
+--no-space-check is given. Archives only warn, as compression may make them
+fit, and incremental backups are not checked, as they link unchanged files.
+The free space is shown by --dry-run.
+
+--max-file-size size leaves out the files of a backed up directory larger than
+size, such as 1G, and --min-file-size size those smaller, such as 1 for empty
+files. The files left out are listed with --verbose and by --dry-run, counted
+in the summary, and not included in the free space needed.
+
+--max-depth n leaves out the contents of the directories n levels below a
+backed up directory, whose own entries are at level 1, and the summary counts
+the directories left out. --max-files n stops backing up a directory holding
+more than n files there, with a warning that the backup is partial, or fails
+the backup with --fail-fast. Both limits are shown by --dry-run.
+
+Files hard linked to each other within a backed up directory are stored once,
+the others as hard links to it, in copies and archives alike, and restored the
+same way. The verbose summary tells how many files were and the space saved.
+
+With --skip-unchanged, the source is first compared in the same way with its
+newest copy in the target, entry by entry for directories. If nothing changed,
+no backup is created and the exit status is 3. Archives are never considered
+identical to the source.
+
+With --mirror, a directory is copied into the target itself rather than into a
+timestamped backup, keeping a single copy of it up to date: only the files
+whose size or modification time differ from their copy, or whose checksum does
+with --checksum, are copied again, and --delete removes the entries no longer
+in the source, except excluded ones. A mirror is marked by a .backup-mirror
+file naming its source, and mirroring into a target without one is refused
+unless --init is given to start a mirror there. Mirrors get no checksum
+manifest or metadata file, and are not recorded for undo.
+
+Restored files are hashed as they are written and compared with the checksum
+manifest of the backup, archives as a whole while they are read. The restore
+fails listing the entries that do not match, unless --no-verify is given. A
+backup without a manifest is restored unverified, with a note saying so.
+
+Restoring with --from takes the name of the backed up file or directory instead
+of a backup. When it has several backups, they are listed with their timestamp
+and size to choose from, the newest by default. Without a terminal to ask on,
+--select or --latest must tell which one to restore.
+
+With --path, only the matching entries of a directory backup or archive are
+restored, at the same relative path inside the destination, which may exist.
+'*' and '?' match within a path component and '**' across components. Existing
+files are only replaced with --force, and a pattern matching nothing fails with
+the closest paths of the backup.
+
+List-contents prints the entries of a directory backup or archive like
+'tar -tvf', with their mode, size in bytes, modification time and path. A copy
+of a single file is listed as one entry, followed by the source, creation time
+and checksum recorded in its metadata file.
+
+Diff takes a backup and the path to compare it with, and prints the entries
+added, removed or modified since the backup like 'diff -rq'. Files are modified
+when their size or modification time differ, or their contents with --checksum.
+Archives are compared through their listing. The exit status is 0 if nothing
+differs, 1 if something does and 2 on errors.
+
+Given --every or --at, a backup or profile is run on a schedule until
+interrupted: at once and then every interval, or at the time of day and then
+every interval (default: 24h). Due times do not drift with the length of the
+runs, a run missed while the machine was asleep happens when it wakes up, and a
+failing run does not stop the schedule. --status-file writes the outcome of
+the last run as {{\"status\", \"started\", \"finished\", \"next\", \"error\",
+\"code\"}} for health checks.
+
+--notify-cmd runs a shell command once a backup or profile finishes, including
+when it failed before copying anything, and after each scheduled run. The
+command reads a short summary on stdin and finds BACKUP_STATUS, BACKUP_RESULT
+(success or failure), BACKUP_SOURCE, BACKUP_TARGET, BACKUP_ERROR and
+BACKUP_ERROR_CODE in its environment. --notify-on failure or success only runs
+it for those outcomes, backups skipped by --skip-unchanged counting as
+successes. Its output goes to stderr, and it failing does not change the exit
+status.
+
+--metrics-file writes the outcome of a backup or profile, and of each scheduled
+run, as gauges for the textfile collector of the Prometheus node exporter:
+backup_last_run_timestamp_seconds, backup_last_success_timestamp_seconds,
+backup_last_run_duration_seconds, backup_last_run_bytes, backup_last_run_files
+and backup_last_run_failed, labeled with the profile or the source. Series of
+other profiles and sources are kept, so that they can share the file, which is
+replaced atomically.
+
+Systemd-install writes a backup-<profile>.service unit running 'backup run
+<profile>' and a backup-<profile>.timer unit starting it --on-calendar (default:
+daily) into $XDG_CONFIG_HOME/systemd/user, or ~/.config/systemd/user, or prints
+them with --stdout. The profile must exist, and --config is passed on to the
+service. Existing units are only replaced with --force, and systemd-uninstall
+removes them. Neither reloads systemd or starts the timer.
+
+Watch backs up its source, then again each time it changes, once no further
+change was seen for --settle (default: 5s), until interrupted. Backups are
+timestamped and skipped when nothing differs from the latest one, and the
+retention options, if any, prune older backups after each. A failing backup is
+reported without stopping the watch.
+
+Every backup and restore is recorded in $XDG_DATA_HOME/backup/history.jsonl,
+or ~/.local/share/backup/history.jsonl, which history prints. Undo removes what
+the most recent operation not undone yet created: the backup along with its
+manifest and metadata file, or the restored copy, putting back the safety copy
+of what a restore replaced. It refuses if that changed since, by size or
+modification time. Restores of selected paths cannot be undone.
+
+Every backup into a local directory records itself in the .backup-catalog.json
+file of that directory, along with its name, timestamp, kind, size, source and
+checksum, and prune removes what it removed from it. List and prune read the
+catalog instead of looking into each backup, as long as it names the backups
+found in the directory; when it does not, because backups were copied or removed
+by hand, they warn and read the backups themselves. Catalog rebuild writes it
+anew from the backups of a directory, or the current one.
+
+Doctor checks a backup directory, or the current one: that it can be written
+to and has room for a backup as large as the largest one in it, whether its
+filesystem supports symbolic links, hard links and extended attributes, which it
+finds out by creating some, whether backups are dated in the future, and whether
+interrupted backups left partial files or stale locks behind. It also checks the
+configuration file. Each check prints pass, warn or fail along with a hint at
+the fix, and the exit status is 0 if all passed, 4 on warnings and 1 on
+failures.
+
+Defaults are read from $XDG_CONFIG_HOME/backup/config.toml, or
+~/.config/backup/config.toml, written in TOML. Its top-level keys apply to
+every invocation and [profile.<name>] tables describe backups run with
+'backup run <name> [target]', overriding the defaults. Options given on the
+command line take precedence, and exclude patterns add up. The keys are source
+(profiles only), target, compress, level, exclude, verbosity (quiet, normal or
+verbose), algorithm, incremental, skip-unchanged, exclude-vcs, exclude-caches,
+dereference, jobs, color, name-format, timestamp-format, utc, precision,
+no-timestamp and suffix:
+
+  target = \"~/backups\"
+  exclude = [\"*.tmp\"]
+
+  [profile.photos]
+  source = \"~/photos\"
+  incremental = true
+
+Environment variables take precedence over the configuration file, but not over
+the command line: BACKUP_DIR is the target of backups given none, BACKUP_COMPRESS
+the compression, and NO_COLOR or BACKUP_NO_COLOR disable colors. Otherwise, on a
+terminal, errors are marked in red, warnings in yellow and successes in green.
+
+A backup prints the absolute path of what it created on stdout, and a restore
+the path it restored to, while messages go to stderr:
+
+  path=$(backup b /etc/hosts /home/user/backups)
+
+Both then print a summary on stderr, unless --quiet: the files processed, bytes
+read and written, the compression ratio of compressed archives, the entries
+skipped, excluded and failed, the time taken and the average throughput.
+
+--log-file appends every message but the verbose ones to a file, whatever the
+verbosity, along with the command line and the exit status of each run. Lines
+start with the time, the level and the PID, or are JSON objects {{\"time\",
+\"level\", \"pid\", \"message\"}} with --log-format json, and runs sharing the
+file never interleave them. With --log-max-size 10M, the file is renamed to
+<file>.1 once it would grow past 10 MiB, older ones moving to <file>.2 and so on,
+keeping --log-keep (default: 5) of them.
+
+--log-syslog sends the same messages to journald, or to the syslog daemon on
+/dev/log when journald does not run, with the user facility under the
+identifier backup. Errors and failed runs are logged with the err priority,
+warnings with warning and everything else with info. The system log being
+unreachable is only a warning.
+
+With --json, backups print {{\"action\": \"backup\", \"source\", \"backup_path\",
+\"bytes\", \"files\", \"duration_ms\", \"stats\"}}, or {{\"action\": \"plan\", \"source\",
+\"backup_path\", \"bytes\", \"files\", \"blocked\"}} with --dry-run. Restores print
+{{\"action\": \"restore\", \"source\", \"restore_path\", \"bytes\", \"files\", \"duration_ms\",
+\"safety_path\", \"stats\"}}, where stats holds the numbers of the summary as
+\"files\", \"bytes_read\", \"bytes_written\", \"compressed\", \"skipped\", \"excluded\",
+\"failed\" and \"elapsed_ms\". Prune and gc print {{\"action\": \"prune\" or \"gc\", \"source\",
+\"removed\", \"bytes\", \"dry_run\"}}, verify {{\"action\": \"verify\", \"source\", \"manifest\", \"files\"}},
+config check {{\"action\": \"config\", \"path\", \"profiles\"}}, version {{\"action\":
+\"version\", \"version\", \"commit\", \"built\", \"compression\", \"checksums\"}}, doctor
+{{\"action\": \"doctor\", \"dir\", \"checks\"}}, each check being {{\"name\", \"status\",
+\"message\", \"hint\"}}, catalog rebuild {{\"action\": \"catalog\", \"path\",
+\"backups\"}}, and undo
+{{\"action\": \"undo\", \"undone\"}}, the undone entry of the history. List,
+list-contents, diff and history print an array of entries. Errors are printed on
+stderr as {{\"error\": <message>, \"code\": <code>}}, where the code, such as
+not_found or already_exists, does not change.
+
+With --dry-run, a backup prints the kind of backup, its destination, the number
+of files and bytes to copy and whether an existing destination blocks it, without
+creating or writing anything.
+
+Exit statuses do not change from one version to the next:
+
+  0    success
+  1    failure
+  2    invalid command line or configuration file
+  3    nothing to do, such as a backup skipped by --skip-unchanged
+  4    partial success: some entries, sources or targets failed, some
+       backups could not be pruned, or doctor found warnings
+  5    a backup failed verification: checksums or signature do not match, the
+       signature is missing or the archive is corrupted
+  6    another backup of the same source into the same target was running
+  130  interrupted
+
+Examples:
+  backup b /etc/hosts
+  backup b /etc/hosts /home/user/backups
+  backup b /etc/hosts /etc/fstab /home/user/.bashrc /home/user/backups
+  backup b -t /mnt/backups '/var/log/**/*.log'
+  find ~/.config -name '*.toml' -print0 | backup b -0 --files-from - /mnt/backups
+  backup b --force /etc/hosts /home/user/hosts.copy
+  backup b -c zstd /srv/data - | ssh host 'cat > data.tar.zst'
+  pg_dump mydb | backup b - /home/user/backups --name mydb.sql
+  ssh host 'cat data.tar.zst' | backup r - /srv/data
+  backup b --dry-run /home/user/projects /home/user/backups
+  backup b --exclude target/ --exclude '*.tmp' /home/user/project
+  backup b --incremental /home/user/photos /mnt/backups
+  backup b --skip-unchanged /home/user/notes /mnt/backups
+  backup b --mirror --delete /srv/data /mnt/mirror
+  backup b -c zstd --encrypt --recipient age1... /srv/data /mnt/backups
+  backup keygen ~/.config/backup/sign.key
+  backup b --sign ~/.config/backup/sign.key /srv/data /mnt/backups
+  backup b -c zstd --split 4000M /srv/data /mnt/fat32
+  backup b /etc/nginx admin@nas:/srv/backups
+  backup r --ssh-option Port=2222 nas:backups/nginx.2024-05-01_10-00-00.backup.tar /etc
+  backup b -c zstd --endpoint-url http://localhost:9000 /srv/data s3://backups/web1
+  backup prune --keep-daily 7 s3://backups/web1
+  backup b -c zstd /srv/data --target /mnt/backups --target nas:/volume1/backups
+  backup b --pipe-to 'rclone rcat remote:backups/{{filename}}' /srv/data
+  backup r --pipe-from 'rclone cat remote:backups/{{filename}}' data.2024-05-01_10-00-00.backup.tar
+  backup r /home/user/backups/hosts.2018-01-01_00-00-00.backup
+  backup r /home/user/backups/hosts.2018-01-01_00-00-00.backup /tmp/staging
+  backup r --latest hosts --from /home/user/backups /tmp/staging
+  backup r --from /home/user/backups --select 2 hosts /tmp/staging
+  backup r --identity ~/.config/age/key.txt /mnt/backups/data.2018-01-01_00-00-00.backup.tar.zst.age
+  backup r --path 'nginx/**/*.conf' /home/user/backups/etc.2018-01-01_00-00-00.backup /tmp/etc
+  backup list-contents /home/user/backups/etc.2018-01-01_00-00-00.backup.tar.gz
+  backup diff /home/user/backups/project.2018-01-01_00-00-00.backup project
+  backup l --name 'host*' /home/user/backups
+  backup prune --keep-last 5 /home/user/backups
+  backup prune --keep-daily 7 --keep-weekly 4 --keep-monthly 12 /home/user/backups
+  backup gc --empty-trash --older-than 30d
+  backup gc --dry-run /home/user/backups
+  backup verify /home/user/backups/hosts.2018-01-01_00-00-00.backup
+  backup doctor /mnt/backups
+  backup catalog rebuild /mnt/backups
+  backup verify --verify-key ~/.config/backup/sign.key.pub /mnt/backups/data.2018-01-01_00-00-00.backup
+  backup undo
+  backup run photos
+  backup run nightly --at 03:30 --status-file /var/lib/backup/nightly.json
+  backup run nightly --notify-on failure --notify-cmd 'mail -s \"backup failed\" admin@example.com'
+  backup run nightly --metrics-file /var/lib/node_exporter/textfile/backup.prom
+  backup run nightly --log-file /var/log/backup.log --log-max-size 10M --log-keep 5
+  backup run nightly --log-syslog --quiet
+  backup systemd-install --on-calendar 'Mon..Fri 22:00' nightly
+  backup systemd-uninstall nightly
+  backup watch --settle 10s --keep-last 20 /home/user/notes /mnt/backups"
+    ));
+}
+
+/// Prints `entries` as an aligned table on stdout.
+pub fn print_table(entries: &[ListEntry]) {
+    let rows: Vec<[String; 6]> = entries
+        .iter()
+        .map(|entry| {
+            [
+                entry.name.clone(),
+                entry.timestamp.map_or_else(
+                    || "-".to_owned(),
+                    |timestamp| timestamp.format(DISPLAY_FORMAT).to_string(),
+                ),
+                writer::human_bytes(entry.size),
+                entry.kind.as_str().to_owned(),
+                entry.path.display().to_string(),
+                entry
+                    .meta
+                    .as_ref()
+                    .map_or_else(|| "-".to_owned(), |meta| meta.source.display().to_string()),
+            ]
+        })
+        .collect();
+    print_rows(
+        ["NAME", "TIMESTAMP", "SIZE", "TYPE", "PATH", "SOURCE"],
+        &rows,
+    );
+}
+
+/// Prints the `operations` of the journal as an aligned table on stdout.
+pub fn print_history(operations: &[Operation]) {
+    let rows: Vec<[String; 4]> = operations
+        .iter()
+        .map(|operation| {
+            [
+                operation.timestamp.format(DISPLAY_FORMAT).to_string(),
+                operation.action.as_str().to_owned(),
+                operation.source.display().to_string(),
+                operation.destination.display().to_string(),
+            ]
+        })
+        .collect();
+    print_rows(["TIME", "ACTION", "SOURCE", "DESTINATION"], &rows);
+}
+
+/// Prints `rows` under `header`, each column as wide as its widest cell.
+fn print_rows<const N: usize>(header: [&str; N], rows: &[[String; N]]) {
+    let header = header.map(str::to_owned);
+    let mut widths = header.clone().map(|column| column.len());
+    for row in rows {
+        for (width, column) in widths.iter_mut().zip(row) {
+            *width = (*width).max(column.len());
+        }
+    }
+
+    for row in std::iter::once(&header).chain(rows) {
+        let line = row
+            .iter()
+            .zip(widths)
+            .map(|(column, width)| format!("{column:width$}"))
+            .collect::<Vec<_>>()
+            .join("  ");
+        out(line.trim_end());
+    }
+}
+
+/// Prints `entries` as a JSON array on stdout.
+pub fn print_json(entries: &[impl Serialize]) {
+    out(serde_json::to_string_pretty(entries).expect("entries are serializable"));
+}
+
+/// Outcome of an operation, printed as a JSON object with `--json`.
+#[derive(Debug, Serialize)]
+#[serde(tag = "action", rename_all = "lowercase")]
+pub enum Outcome<'a> {
+    Backup {
+        source: &'a Path,
+        backup_path: &'a Path,
+        bytes: u64,
+        files: u64,
+        duration_ms: u128,
+        stats: &'a Stats,
+    },
+    /// What a backup run with `--dry-run` would do.
+    #[serde(rename = "plan")]
+    Plan {
+        source: &'a Path,
+        backup_path: &'a Path,
+        bytes: u64,
+        files: u64,
+        blocked: bool,
+    },
+    Restore {
+        source: &'a Path,
+        restore_path: &'a Path,
+        bytes: u64,
+        files: u64,
+        duration_ms: u128,
+        safety_path: Option<&'a Path>,
+        stats: &'a Stats,
+    },
+    Prune {
+        source: &'a Path,
+        removed: &'a [PathBuf],
+        bytes: u64,
+        dry_run: bool,
+    },
+    Gc {
+        source: &'a Path,
+        removed: &'a [PathBuf],
+        bytes: u64,
+        dry_run: bool,
+    },
+    Verify {
+        source: &'a Path,
+        manifest: &'a Path,
+        files: usize,
+        /// Public key the signature was checked with, if any.
+        signed_by: Option<String>,
+    },
+    Keygen {
+        path: &'a Path,
+        public_key_path: &'a Path,
+        public_key: &'a str,
+    },
+    Undo {
+        undone: &'a Operation,
+    },
+    Config {
+        path: &'a Path,
+        profiles: usize,
+    },
+    /// Units written, or that would be by a dry run.
+    #[serde(rename = "systemd-install")]
+    SystemdInstall {
+        profile: &'a str,
+        units: &'a [PathBuf],
+        dry_run: bool,
+    },
+    /// Units removed, or that would be by a dry run.
+    #[serde(rename = "systemd-uninstall")]
+    SystemdUninstall {
+        profile: &'a str,
+        units: &'a [PathBuf],
+        dry_run: bool,
+    },
+    Version(Version<'a>),
+    Doctor {
+        dir: &'a Path,
+        checks: &'a [Check],
+    },
+    Catalog {
+        path: &'a Path,
+        backups: usize,
+    },
+}
+
+/// Outcome of a backup for one of several targets, printed as part of a JSON
+/// array with `--json`: that of the backup, or the error it failed with.
+#[derive(Debug, Serialize)]
+#[serde(untagged)]
+pub enum TargetOutcome<'a> {
+    Done {
+        target: &'a Path,
+        #[serde(flatten)]
+        outcome: Outcome<'a>,
+    },
+    Failed {
+        target: &'a Path,
+        error: String,
+        code: &'static str,
+    },
+}
+
+/// Build of the program, printed by `backup version`.
+#[derive(Debug, Serialize)]
+pub struct Version<'a> {
+    pub version: &'a str,
+    /// Abbreviated hash of the git commit built, or `unknown`.
+    pub commit: &'a str,
+    /// Date of the build.
+    pub built: String,
+    /// Compressions supported for archives.
+    pub compression: &'a [&'a str],
+    /// Checksum algorithms supported.
+    pub checksums: [&'a str; 2],
+}
+
+/// Prints `version` as `backup <version>` followed by its details.
+pub fn print_version(version: &Version<'_>) {
+    out(format_args!("backup {}", version.version));
+    out(format_args!("commit: {}", version.commit));
+    out(format_args!("built: {}", version.built));
+    out(format_args!(
+        "compression: {}",
+        version.compression.join(", ")
+    ));
+    out(format_args!("checksums: {}", version.checksums.join(", ")));
+}
+
+/// Prints the outcome of each of the `checks` of `backup doctor` on stdout,
+/// followed by the hint at the fix of those that did not pass.
+pub fn print_checks(checks: &[Check]) {
+    let color = COLOR.load(Ordering::Relaxed) && io::stdout().is_terminal();
+    let width = checks
+        .iter()
+        .map(|check| check.name.len())
+        .max()
+        .unwrap_or(0);
+    for check in checks {
+        let status = match check.status {
+            Status::Pass => paint("pass", GREEN, color),
+            Status::Warn => paint("warn", YELLOW, color),
+            Status::Fail => paint("fail", RED, color),
+        };
+        out(format_args!(
+            "{status}  {:width$}  {}",
+            check.name, check.message
+        ));
+        if let Some(hint) = &check.hint {
+            out(format_args!("      {:width$}  hint: {hint}", ""));
+        }
+    }
+}
+
+/// Prints `outcome` as a JSON object on stdout.
+pub fn print_outcome(outcome: &Outcome<'_>) {
+    out(serde_json::to_string_pretty(outcome).expect("outcomes are serializable"));
+}
+
+/// Prints the entries inside a backup like `tar -tvf`, with their mode,
+/// size, modification time and path.
+pub fn print_contents(entries: &[ContentEntry]) {
+    let width = entries
+        .iter()
+        .map(|entry| entry.size.to_string().len())
+        .max()
+        .unwrap_or(0);
+
+    for entry in entries {
+        let modified = entry.modified.map_or_else(
+            || "-".to_owned(),
+            |modified| modified.format(DISPLAY_FORMAT).to_string(),
+        );
+        let mut path = match entry.path.as_os_str().is_empty() {
+            true => ".".to_owned(),
+            false => entry.path.display().to_string(),
+        };
+        if entry.kind == EntryKind::Directory {
+            path.push('/');
+        }
+        match (&entry.link, entry.kind) {
+            (Some(link), EntryKind::File) => path = format!("{path} link to {}", link.display()),
+            (Some(link), _) => path = format!("{path} -> {}", link.display()),
+            (None, _) => {}
+        }
+        out(format_args!(
+            "{} {:>width$} {modified} {path}",
+            mode_string(entry.kind, entry.mode),
+            entry.size
+        ));
+    }
+}
+
+/// Formats `mode` like `ls -l`, e.g. `drwxr-xr-x`.
+fn mode_string(kind: EntryKind, mode: u32) -> String {
+    let kind = match kind {
+        EntryKind::File => '-',
+        EntryKind::Directory => 'd',
+        EntryKind::Link => 'l',
+        EntryKind::Other => '?',
+    };
+    let mut string = String::from(kind);
+    for (shift, special, set) in [(6, 0o4000, 's'), (3, 0o2000, 's'), (0, 0o1000, 't')] {
+        let bits = mode >> shift;
+        string.push(if bits & 0o4 != 0 { 'r' } else { '-' });
+        string.push(if bits & 0o2 != 0 { 'w' } else { '-' });
+        string.push(match (bits & 0o1 != 0, mode & special != 0) {
+            (true, true) => set,
+            (false, true) => set.to_ascii_uppercase(),
+            (true, false) => 'x',
+            (false, false) => '-',
+        });
+    }
+    string
+}
+
+/// Prints the metadata recorded for a backup.
+pub fn print_meta(meta: &BackupMeta) {
+    out(format_args!("source: {}", meta.source.display()));
+    out(format_args!(
+        "created: {}",
+        meta.created.format(DISPLAY_FORMAT)
+    ));
+    if let Some(checksum) = &meta.checksum {
+        out(format_args!(
+            "checksum: {}:{}",
+            checksum.algorithm, checksum.digest
+        ));
+    }
+}
+
+/// Prints `differences` between `backup` and `current` like `diff -rq`.
+pub fn print_diff(backup: &Path, current: &Path, differences: &[Difference]) {
+    let under = |root: &Path, path: &Path| -> PathBuf {
+        match path.as_os_str().is_empty() {
+            true => root.to_path_buf(),
+            false => root.join(path),
+        }
+    };
+
+    for difference in differences {
+        let path = &difference.path;
+        let parent = path.parent().unwrap_or(Path::new(""));
+        let name = path.file_name().unwrap_or_default().to_string_lossy();
+        match difference.change {
+            Change::Added => out(format_args!(
+                "Only in {}: {name}",
+                under(current, parent).display()
+            )),
+            Change::Removed => out(format_args!(
+                "Only in {}: {name}",
+                under(backup, parent).display()
+            )),
+            Change::Modified => out(format_args!(
+                "Files {} and {} differ",
+                under(backup, path).display(),
+                under(current, path).display()
+            )),
+        }
+    }
+}
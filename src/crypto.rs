@@ -0,0 +1,1146 @@
+//! Cryptographic primitives of the age format, see [`crate::age`]: X25519,
+//! ChaCha20-Poly1305, HKDF-SHA-256, scrypt, and the bech32 and base64
+//! encodings of keys and headers; Ed25519 for signatures, see
+//! [`crate::sign`]; and MD5 for the integrity checks of uploads to S3, see
+//! [`crate::s3`].
+
+use sha2::{Digest, Sha256, Sha512};
+
+/// Fills `bytes` with random bytes from the operating system.
+pub(crate) fn random(bytes: &mut [u8]) -> std::io::Result<()> {
+    getrandom::fill(bytes).map_err(|e| std::io::Error::other(e.to_string()))
+}
+
+/// Computes HMAC-SHA-256 (RFC 2104) of `data` with `key`.
+pub(crate) fn hmac_sha256(key: &[u8], data: &[u8]) -> [u8; 32] {
+    let mut block = [0; 64];
+    if key.len() > 64 {
+        block[..32].copy_from_slice(&Sha256::digest(key));
+    } else {
+        block[..key.len()].copy_from_slice(key);
+    }
+    let pad = |byte: u8| block.map(|b| b ^ byte);
+    let inner = Sha256::new()
+        .chain_update(pad(0x36))
+        .chain_update(data)
+        .finalize();
+    Sha256::new()
+        .chain_update(pad(0x5c))
+        .chain_update(inner)
+        .finalize()
+        .into()
+}
+
+/// Derives 32 bytes from `ikm` with HKDF-SHA-256 (RFC 5869).
+pub(crate) fn hkdf_sha256(ikm: &[u8], salt: &[u8], info: &[u8]) -> [u8; 32] {
+    let prk = hmac_sha256(salt, ikm);
+    let mut input = info.to_vec();
+    input.push(1);
+    hmac_sha256(&prk, &input)
+}
+
+/// Fills `output` with PBKDF2-HMAC-SHA-256 (RFC 8018) of `password` and
+/// `salt` over `iterations`.
+fn pbkdf2_sha256(password: &[u8], salt: &[u8], iterations: u32, output: &mut [u8]) {
+    for (i, block) in output.chunks_mut(32).enumerate() {
+        let mut input = salt.to_vec();
+        input.extend_from_slice(&(i as u32 + 1).to_be_bytes());
+        let mut u = hmac_sha256(password, &input);
+        let mut t = u;
+        for _ in 1..iterations {
+            u = hmac_sha256(password, &u);
+            t.iter_mut().zip(u).for_each(|(t, u)| *t ^= u);
+        }
+        block.copy_from_slice(&t[..block.len()]);
+    }
+}
+
+/// Applies the Salsa20/8 core to the 64 byte block `b`, as words.
+fn salsa20_8(b: &mut [u32; 16]) {
+    let mut x = *b;
+    let mut step = |a: usize, b: usize, c: usize, shift: u32| {
+        x[a] ^= x[b].wrapping_add(x[c]).rotate_left(shift);
+    };
+    for _ in 0..4 {
+        for [a, b, c, d] in [[0, 4, 8, 12], [5, 9, 13, 1], [10, 14, 2, 6], [15, 3, 7, 11]] {
+            step(b, a, d, 7);
+            step(c, b, a, 9);
+            step(d, c, b, 13);
+            step(a, d, c, 18);
+        }
+        for [a, b, c, d] in [[0, 1, 2, 3], [5, 6, 7, 4], [10, 11, 8, 9], [15, 12, 13, 14]] {
+            step(b, a, d, 7);
+            step(c, b, a, 9);
+            step(d, c, b, 13);
+            step(a, d, c, 18);
+        }
+    }
+    b.iter_mut()
+        .zip(x)
+        .for_each(|(b, x)| *b = b.wrapping_add(x));
+}
+
+/// Mixes the `2 * r` blocks of `input` into `output`, the BlockMix of
+/// scrypt.
+fn block_mix(input: &[u32], output: &mut [u32]) {
+    let blocks = input.len() / 16;
+    let mut x: [u32; 16] = input[input.len() - 16..].try_into().unwrap();
+    for (i, block) in input.chunks_exact(16).enumerate() {
+        x.iter_mut().zip(block).for_each(|(x, b)| *x ^= b);
+        salsa20_8(&mut x);
+        // Even blocks go to the first half of the output, odd ones after.
+        let at = (i / 2 + (i % 2) * blocks / 2) * 16;
+        output[at..at + 16].copy_from_slice(&x);
+    }
+}
+
+/// Derives 32 bytes from `password` and `salt` with scrypt (RFC 7914), with
+/// a cost of 2^`log_n`, a block size `r` and a parallelization `p`.
+pub(crate) fn scrypt(password: &[u8], salt: &[u8], log_n: u8, r: usize, p: usize) -> [u8; 32] {
+    let mut output = [0; 32];
+    scrypt_into(password, salt, log_n, r, p, &mut output);
+    output
+}
+
+/// Fills `output` with scrypt, see [`scrypt`].
+fn scrypt_into(password: &[u8], salt: &[u8], log_n: u8, r: usize, p: usize, output: &mut [u8]) {
+    let n = 1usize << log_n;
+    let words = 32 * r;
+    let mut bytes = vec![0; p * 4 * words];
+    pbkdf2_sha256(password, salt, 1, &mut bytes);
+
+    let mut v = vec![0u32; n * words];
+    let mut x = vec![0u32; words];
+    let mut y = vec![0u32; words];
+    for chunk in bytes.chunks_exact_mut(4 * words) {
+        for (x, word) in x.iter_mut().zip(chunk.chunks_exact(4)) {
+            *x = u32::from_le_bytes(word.try_into().unwrap());
+        }
+        for i in 0..n {
+            v[i * words..(i + 1) * words].copy_from_slice(&x);
+            block_mix(&x, &mut y);
+            std::mem::swap(&mut x, &mut y);
+        }
+        for _ in 0..n {
+            let j = x[words - 16] as usize & (n - 1);
+            x.iter_mut()
+                .zip(&v[j * words..(j + 1) * words])
+                .for_each(|(x, v)| *x ^= v);
+            block_mix(&x, &mut y);
+            std::mem::swap(&mut x, &mut y);
+        }
+        for (word, x) in chunk.chunks_exact_mut(4).zip(&x) {
+            word.copy_from_slice(&x.to_le_bytes());
+        }
+    }
+    pbkdf2_sha256(password, &bytes, 1, output);
+}
+
+/// Compares two byte strings in constant time.
+pub(crate) fn equal(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |diff, (a, b)| diff | (a ^ b)) == 0
+}
+
+/// The u-coordinate of the base point of Curve25519.
+pub(crate) const BASEPOINT: [u8; 32] = {
+    let mut point = [0; 32];
+    point[0] = 9;
+    point
+};
+
+/// Element of the field of integers modulo 2^255 - 19, as five limbs of 51
+/// bits, least significant first.
+type Fe = [u64; 5];
+
+const MASK: u64 = (1 << 51) - 1;
+
+fn fe_from_bytes(bytes: &[u8; 32]) -> Fe {
+    let load = |i: usize| u64::from_le_bytes(bytes[i..i + 8].try_into().unwrap());
+    [
+        load(0) & MASK,
+        (load(6) >> 3) & MASK,
+        (load(12) >> 6) & MASK,
+        (load(19) >> 1) & MASK,
+        (load(24) >> 12) & MASK,
+    ]
+}
+
+fn fe_to_bytes(h: Fe) -> [u8; 32] {
+    let mut limbs = fe_carry(h);
+    // Subtract p if the value is at least p: adding 19 then carrying out of
+    // bit 255 tells whether it is.
+    let mut q = (limbs[0] + 19) >> 51;
+    for limb in &limbs[1..] {
+        q = (limb + q) >> 51;
+    }
+    limbs[0] += 19 * q;
+    for i in 0..4 {
+        limbs[i + 1] += limbs[i] >> 51;
+        limbs[i] &= MASK;
+    }
+    limbs[4] &= MASK;
+
+    let mut bytes = [0; 32];
+    let (mut acc, mut bits, mut i) = (0u128, 0, 0);
+    for limb in limbs {
+        acc |= u128::from(limb) << bits;
+        bits += 51;
+        while bits >= 8 {
+            bytes[i] = acc as u8;
+            acc >>= 8;
+            bits -= 8;
+            i += 1;
+        }
+    }
+    bytes[i] = acc as u8;
+    bytes
+}
+
+/// Carries each limb into the next so that all fit in a little over 51 bits.
+fn fe_carry(mut h: Fe) -> Fe {
+    let carries = h.map(|limb| limb >> 51);
+    for limb in &mut h {
+        *limb &= MASK;
+    }
+    h[0] += carries[4] * 19;
+    for i in 0..4 {
+        h[i + 1] += carries[i];
+    }
+    h
+}
+
+fn fe_add(a: &Fe, b: &Fe) -> Fe {
+    fe_carry([0, 1, 2, 3, 4].map(|i| a[i] + b[i]))
+}
+
+fn fe_sub(a: &Fe, b: &Fe) -> Fe {
+    // Adding 2p keeps every limb positive.
+    const TWO_P: Fe = [
+        0xf_ffff_ffff_ffda,
+        0xf_ffff_ffff_fffe,
+        0xf_ffff_ffff_fffe,
+        0xf_ffff_ffff_fffe,
+        0xf_ffff_ffff_fffe,
+    ];
+    fe_carry([0, 1, 2, 3, 4].map(|i| a[i] + TWO_P[i] - b[i]))
+}
+
+fn fe_mul(a: &Fe, b: &Fe) -> Fe {
+    let m = |x: u64, y: u64| u128::from(x) * u128::from(y);
+    let [a0, a1, a2, a3, a4] = *a;
+    let [b0, b1, b2, b3, b4] = *b;
+    let (b1_19, b2_19, b3_19, b4_19) = (b1 * 19, b2 * 19, b3 * 19, b4 * 19);
+    let c0 = m(a0, b0) + m(a4, b1_19) + m(a3, b2_19) + m(a2, b3_19) + m(a1, b4_19);
+    let mut c1 = m(a1, b0) + m(a0, b1) + m(a4, b2_19) + m(a3, b3_19) + m(a2, b4_19);
+    let mut c2 = m(a2, b0) + m(a1, b1) + m(a0, b2) + m(a4, b3_19) + m(a3, b4_19);
+    let mut c3 = m(a3, b0) + m(a2, b1) + m(a1, b2) + m(a0, b3) + m(a4, b4_19);
+    let mut c4 = m(a4, b0) + m(a3, b1) + m(a2, b2) + m(a1, b3) + m(a0, b4);
+
+    c1 += c0 >> 51;
+    c2 += c1 >> 51;
+    c3 += c2 >> 51;
+    c4 += c3 >> 51;
+    let low = |c: u128| c as u64 & MASK;
+    let c0 = u128::from(low(c0)) + (c4 >> 51) * 19;
+    [
+        low(c0),
+        low(c1) + (c0 >> 51) as u64,
+        low(c2),
+        low(c3),
+        low(c4),
+    ]
+}
+
+fn fe_square(a: &Fe) -> Fe {
+    fe_mul(a, a)
+}
+
+/// Inverts `z` as z^(p - 2).
+fn fe_invert(z: &Fe) -> Fe {
+    // p - 2 = 2^255 - 21, little endian.
+    let mut exponent = [0xff; 32];
+    exponent[0] = 0xeb;
+    exponent[31] = 0x7f;
+    fe_pow(z, &exponent)
+}
+
+/// Swaps `a` and `b` if `swap` is 1, in constant time.
+fn fe_swap(swap: u64, a: &mut Fe, b: &mut Fe) {
+    let mask = swap.wrapping_neg();
+    for i in 0..5 {
+        let t = mask & (a[i] ^ b[i]);
+        a[i] ^= t;
+        b[i] ^= t;
+    }
+}
+
+/// Multiplies the point of u-coordinate `u` by `scalar`, clamped, as the
+/// X25519 function of RFC 7748.
+pub(crate) fn x25519(scalar: &[u8; 32], u: &[u8; 32]) -> [u8; 32] {
+    let mut k = *scalar;
+    k[0] &= 248;
+    k[31] &= 127;
+    k[31] |= 64;
+
+    let x1 = fe_from_bytes(u);
+    let (mut x2, mut z2) = ([1, 0, 0, 0, 0], [0; 5]);
+    let (mut x3, mut z3) = (x1, [1, 0, 0, 0, 0]);
+    let a24 = [121665, 0, 0, 0, 0];
+    let mut swap = 0;
+    for t in (0..255).rev() {
+        let bit = u64::from(k[t / 8] >> (t % 8) & 1);
+        swap ^= bit;
+        fe_swap(swap, &mut x2, &mut x3);
+        fe_swap(swap, &mut z2, &mut z3);
+        swap = bit;
+
+        let a = fe_add(&x2, &z2);
+        let aa = fe_square(&a);
+        let b = fe_sub(&x2, &z2);
+        let bb = fe_square(&b);
+        let e = fe_sub(&aa, &bb);
+        let c = fe_add(&x3, &z3);
+        let d = fe_sub(&x3, &z3);
+        let da = fe_mul(&d, &a);
+        let cb = fe_mul(&c, &b);
+        x3 = fe_square(&fe_add(&da, &cb));
+        z3 = fe_mul(&x1, &fe_square(&fe_sub(&da, &cb)));
+        x2 = fe_mul(&aa, &bb);
+        z2 = fe_mul(&e, &fe_add(&aa, &fe_mul(&a24, &e)));
+    }
+    fe_swap(swap, &mut x2, &mut x3);
+    fe_swap(swap, &mut z2, &mut z3);
+    fe_to_bytes(fe_mul(&x2, &fe_invert(&z2)))
+}
+
+/// Raises `z` to the power `exponent`, given little endian.
+fn fe_pow(z: &Fe, exponent: &[u8; 32]) -> Fe {
+    let mut result = [1, 0, 0, 0, 0];
+    for bit in (0..256).rev() {
+        result = fe_square(&result);
+        if exponent[bit / 8] >> (bit % 8) & 1 == 1 {
+            result = fe_mul(&result, z);
+        }
+    }
+    result
+}
+
+fn fe_neg(a: &Fe) -> Fe {
+    fe_sub(&[0; 5], a)
+}
+
+fn fe_is_negative(a: &Fe) -> bool {
+    fe_to_bytes(*a)[0] & 1 == 1
+}
+
+fn fe_equal(a: &Fe, b: &Fe) -> bool {
+    fe_to_bytes(*a) == fe_to_bytes(*b)
+}
+
+/// The constant d of edwards25519, -121665/121666.
+const EDWARDS_D: [u8; 32] = [
+    163, 120, 89, 19, 202, 77, 235, 117, 171, 216, 65, 65, 77, 10, 112, 0, 152, 232, 121, 119, 121,
+    64, 199, 140, 115, 254, 111, 43, 238, 108, 3, 82,
+];
+
+/// Twice the constant d of edwards25519.
+const EDWARDS_D2: [u8; 32] = [
+    89, 241, 178, 38, 148, 155, 214, 235, 86, 177, 131, 130, 154, 20, 224, 0, 48, 209, 243, 238,
+    242, 128, 142, 25, 231, 252, 223, 86, 220, 217, 6, 36,
+];
+
+/// A square root of -1 modulo 2^255 - 19.
+const SQRT_M1: [u8; 32] = [
+    176, 160, 14, 74, 39, 27, 238, 196, 120, 228, 47, 173, 6, 24, 67, 47, 167, 215, 251, 61, 153,
+    0, 77, 43, 11, 223, 193, 79, 128, 36, 131, 43,
+];
+
+/// The base point of edwards25519, encoded: y = 4/5 and x even.
+const ED25519_BASE: [u8; 32] = {
+    let mut point = [0x66; 32];
+    point[0] = 0x58;
+    point
+};
+
+/// Point of edwards25519 in extended coordinates, x = X/Z, y = Y/Z and
+/// xy = T/Z.
+#[derive(Clone, Copy)]
+struct Point {
+    x: Fe,
+    y: Fe,
+    z: Fe,
+    t: Fe,
+}
+
+impl Point {
+    const IDENTITY: Point = Point {
+        x: [0; 5],
+        y: [1, 0, 0, 0, 0],
+        z: [1, 0, 0, 0, 0],
+        t: [0; 5],
+    };
+
+    /// Decodes a point as of RFC 8032 section 5.1.3, or `None` if `bytes`
+    /// encode no point of the curve.
+    fn decode(bytes: &[u8; 32]) -> Option<Point> {
+        let sign = bytes[31] >> 7 == 1;
+        let mut y_bytes = *bytes;
+        y_bytes[31] &= 0x7f;
+        let y = fe_from_bytes(&y_bytes);
+        if fe_to_bytes(y) != y_bytes {
+            return None;
+        }
+        let one = [1, 0, 0, 0, 0];
+        let y2 = fe_square(&y);
+        let u = fe_sub(&y2, &one);
+        let v = fe_add(&fe_mul(&fe_from_bytes(&EDWARDS_D), &y2), &one);
+        // x = u v^3 (u v^7)^((p - 5) / 8), with (p - 5) / 8 = 2^252 - 3.
+        let mut exponent = [0xff; 32];
+        exponent[0] = 0xfd;
+        exponent[31] = 0x0f;
+        let v3 = fe_mul(&fe_square(&v), &v);
+        let v7 = fe_mul(&fe_square(&v3), &v);
+        let mut x = fe_mul(&fe_mul(&u, &v3), &fe_pow(&fe_mul(&u, &v7), &exponent));
+        let vx2 = fe_mul(&v, &fe_square(&x));
+        if fe_equal(&vx2, &fe_neg(&u)) {
+            x = fe_mul(&x, &fe_from_bytes(&SQRT_M1));
+        } else if !fe_equal(&vx2, &u) {
+            return None;
+        }
+        if fe_equal(&x, &[0; 5]) && sign {
+            return None;
+        }
+        if fe_is_negative(&x) != sign {
+            x = fe_neg(&x);
+        }
+        Some(Point {
+            x,
+            y,
+            z: one,
+            t: fe_mul(&x, &y),
+        })
+    }
+
+    fn encode(&self) -> [u8; 32] {
+        let z = fe_invert(&self.z);
+        let x = fe_mul(&self.x, &z);
+        let mut bytes = fe_to_bytes(fe_mul(&self.y, &z));
+        bytes[31] |= u8::from(fe_is_negative(&x)) << 7;
+        bytes
+    }
+
+    /// Adds two points with the unified formulas of RFC 8032 section 5.1.4,
+    /// which also double.
+    fn add(&self, other: &Point) -> Point {
+        let a = fe_mul(&fe_sub(&self.y, &self.x), &fe_sub(&other.y, &other.x));
+        let b = fe_mul(&fe_add(&self.y, &self.x), &fe_add(&other.y, &other.x));
+        let c = fe_mul(&fe_mul(&self.t, &fe_from_bytes(&EDWARDS_D2)), &other.t);
+        let d = fe_mul(&fe_add(&self.z, &self.z), &other.z);
+        let (e, f, g, h) = (
+            fe_sub(&b, &a),
+            fe_sub(&d, &c),
+            fe_add(&d, &c),
+            fe_add(&b, &a),
+        );
+        Point {
+            x: fe_mul(&e, &f),
+            y: fe_mul(&g, &h),
+            z: fe_mul(&f, &g),
+            t: fe_mul(&e, &h),
+        }
+    }
+
+    fn neg(&self) -> Point {
+        Point {
+            x: fe_neg(&self.x),
+            t: fe_neg(&self.t),
+            ..*self
+        }
+    }
+
+    /// Multiplies the point by `scalar`, given little endian, adding at
+    /// every bit so that the time taken does not depend on it.
+    fn mul(&self, scalar: &[u8; 32]) -> Point {
+        let mut result = Point::IDENTITY;
+        for bit in (0..256).rev() {
+            result = result.add(&result);
+            let mut sum = result.add(self);
+            let swap = u64::from(scalar[bit / 8] >> (bit % 8) & 1);
+            fe_swap(swap, &mut result.x, &mut sum.x);
+            fe_swap(swap, &mut result.y, &mut sum.y);
+            fe_swap(swap, &mut result.z, &mut sum.z);
+            fe_swap(swap, &mut result.t, &mut sum.t);
+        }
+        result
+    }
+}
+
+/// The order of the base point of edwards25519, 2^252 +
+/// 27742317777372353535851937790883648493, as little endian 64 bit words.
+const ORDER: [u64; 4] = [
+    0x5812_631a_5cf5_d3ed,
+    0x14de_f9de_a2f7_9cd6,
+    0,
+    0x1000_0000_0000_0000,
+];
+
+/// Scalar modulo [`ORDER`], as little endian 64 bit words.
+type Scalar = [u64; 4];
+
+/// Computes `a + b` modulo [`ORDER`], both being below it, in constant time.
+fn sc_add(a: &Scalar, b: &Scalar) -> Scalar {
+    let mut sum = [0; 4];
+    let mut carry = 0;
+    for i in 0..4 {
+        let (s, c1) = a[i].overflowing_add(b[i]);
+        let (s, c2) = s.overflowing_add(carry);
+        sum[i] = s;
+        carry = u64::from(c1 || c2);
+    }
+    // The sum is below 2^254, so it only needs the order subtracted once.
+    let mut reduced = [0; 4];
+    let mut borrow = 0;
+    for i in 0..4 {
+        let (d, b1) = sum[i].overflowing_sub(ORDER[i]);
+        let (d, b2) = d.overflowing_sub(borrow);
+        reduced[i] = d;
+        borrow = u64::from(b1 || b2);
+    }
+    let keep = borrow.wrapping_neg();
+    [0, 1, 2, 3].map(|i| (sum[i] & keep) | (reduced[i] & !keep))
+}
+
+/// Reduces the little endian integer `bytes` modulo [`ORDER`].
+fn sc_reduce(bytes: &[u8]) -> Scalar {
+    sc_mul_add(&[1, 0, 0, 0], bytes, &[0; 4])
+}
+
+/// Computes `a * bytes + c` modulo [`ORDER`], `bytes` being little endian.
+fn sc_mul_add(a: &Scalar, bytes: &[u8], c: &Scalar) -> Scalar {
+    let mut result = [0; 4];
+    for bit in (0..bytes.len() * 8).rev() {
+        result = sc_add(&result, &result);
+        let sum = sc_add(&result, a);
+        let mask = u64::from(bytes[bit / 8] >> (bit % 8) & 1).wrapping_neg();
+        result = [0, 1, 2, 3].map(|i| (sum[i] & mask) | (result[i] & !mask));
+    }
+    sc_add(&result, c)
+}
+
+fn sc_to_bytes(s: &Scalar) -> [u8; 32] {
+    let mut bytes = [0; 32];
+    for (chunk, word) in bytes.chunks_mut(8).zip(s) {
+        chunk.copy_from_slice(&word.to_le_bytes());
+    }
+    bytes
+}
+
+fn sha512(parts: &[&[u8]]) -> [u8; 64] {
+    let mut hasher = Sha512::new();
+    for part in parts {
+        hasher.update(part);
+    }
+    hasher.finalize().into()
+}
+
+/// Expands the 32 byte secret `seed` of an Ed25519 key into its secret
+/// scalar and the prefix hashed into signatures.
+fn ed25519_expand(seed: &[u8; 32]) -> ([u8; 32], [u8; 32]) {
+    let hash = sha512(&[seed]);
+    let mut scalar: [u8; 32] = hash[..32].try_into().unwrap();
+    scalar[0] &= 248;
+    scalar[31] &= 127;
+    scalar[31] |= 64;
+    (scalar, hash[32..].try_into().unwrap())
+}
+
+/// Derives the public key of the Ed25519 secret `seed` (RFC 8032).
+pub(crate) fn ed25519_public(seed: &[u8; 32]) -> [u8; 32] {
+    let (scalar, _) = ed25519_expand(seed);
+    Point::decode(&ED25519_BASE)
+        .expect("the base point is on the curve")
+        .mul(&scalar)
+        .encode()
+}
+
+/// Signs `message` with the Ed25519 secret `seed` (RFC 8032).
+pub(crate) fn ed25519_sign(seed: &[u8; 32], message: &[u8]) -> [u8; 64] {
+    let (scalar, prefix) = ed25519_expand(seed);
+    let base = Point::decode(&ED25519_BASE).expect("the base point is on the curve");
+    let public = base.mul(&scalar).encode();
+    let r = sc_to_bytes(&sc_reduce(&sha512(&[&prefix, message])));
+    let big_r = base.mul(&r).encode();
+    let k = sc_reduce(&sha512(&[&big_r, &public, message]));
+    let s = sc_mul_add(&k, &scalar, &sc_reduce(&r));
+    let mut signature = [0; 64];
+    signature[..32].copy_from_slice(&big_r);
+    signature[32..].copy_from_slice(&sc_to_bytes(&s));
+    signature
+}
+
+/// Checks the Ed25519 `signature` of `message` by the key `public`
+/// (RFC 8032), rejecting non-canonical signatures.
+pub(crate) fn ed25519_verify(public: &[u8; 32], message: &[u8], signature: &[u8; 64]) -> bool {
+    let Some(key) = Point::decode(public) else {
+        return false;
+    };
+    let big_r: [u8; 32] = signature[..32].try_into().unwrap();
+    let s: [u8; 32] = signature[32..].try_into().unwrap();
+    if sc_to_bytes(&sc_reduce(&s)) != s {
+        return false;
+    }
+    let k = sc_to_bytes(&sc_reduce(&sha512(&[&big_r, public, message])));
+    let base = Point::decode(&ED25519_BASE).expect("the base point is on the curve");
+    // [s]B = R + [k]A, checked as [s]B - [k]A encoding to R.
+    base.mul(&s).add(&key.mul(&k).neg()).encode() == big_r
+}
+
+fn quarter_round(state: &mut [u32; 16], a: usize, b: usize, c: usize, d: usize) {
+    state[a] = state[a].wrapping_add(state[b]);
+    state[d] = (state[d] ^ state[a]).rotate_left(16);
+    state[c] = state[c].wrapping_add(state[d]);
+    state[b] = (state[b] ^ state[c]).rotate_left(12);
+    state[a] = state[a].wrapping_add(state[b]);
+    state[d] = (state[d] ^ state[a]).rotate_left(8);
+    state[c] = state[c].wrapping_add(state[d]);
+    state[b] = (state[b] ^ state[c]).rotate_left(7);
+}
+
+/// Computes the ChaCha20 block of RFC 8439 for `counter`.
+fn chacha20_block(key: &[u8; 32], counter: u32, nonce: &[u8; 12]) -> [u8; 64] {
+    let word =
+        |bytes: &[u8], i: usize| u32::from_le_bytes(bytes[i * 4..i * 4 + 4].try_into().unwrap());
+    let mut initial = [0; 16];
+    initial[..4].copy_from_slice(&[0x6170_7865, 0x3320_646e, 0x7962_2d32, 0x6b20_6574]);
+    for i in 0..8 {
+        initial[4 + i] = word(key, i);
+    }
+    initial[12] = counter;
+    for i in 0..3 {
+        initial[13 + i] = word(nonce, i);
+    }
+
+    let mut state = initial;
+    for _ in 0..10 {
+        quarter_round(&mut state, 0, 4, 8, 12);
+        quarter_round(&mut state, 1, 5, 9, 13);
+        quarter_round(&mut state, 2, 6, 10, 14);
+        quarter_round(&mut state, 3, 7, 11, 15);
+        quarter_round(&mut state, 0, 5, 10, 15);
+        quarter_round(&mut state, 1, 6, 11, 12);
+        quarter_round(&mut state, 2, 7, 8, 13);
+        quarter_round(&mut state, 3, 4, 9, 14);
+    }
+    let mut block = [0; 64];
+    for i in 0..16 {
+        let word = state[i].wrapping_add(initial[i]);
+        block[i * 4..i * 4 + 4].copy_from_slice(&word.to_le_bytes());
+    }
+    block
+}
+
+/// Encrypts or decrypts `data` in place with ChaCha20, starting at `counter`.
+fn chacha20(key: &[u8; 32], counter: u32, nonce: &[u8; 12], data: &mut [u8]) {
+    for (i, chunk) in data.chunks_mut(64).enumerate() {
+        let block = chacha20_block(key, counter.wrapping_add(i as u32), nonce);
+        for (byte, key) in chunk.iter_mut().zip(block) {
+            *byte ^= key;
+        }
+    }
+}
+
+/// Computes the Poly1305 tag of `message` with the one-time `key`.
+fn poly1305(key: &[u8; 32], message: &[u8]) -> [u8; 16] {
+    let le = |bytes: &[u8], i: usize| u32::from_le_bytes(bytes[i..i + 4].try_into().unwrap());
+    let r = [
+        le(key, 0) & 0x3ff_ffff,
+        (le(key, 3) >> 2) & 0x3ff_ff03,
+        (le(key, 6) >> 4) & 0x3ff_c0ff,
+        (le(key, 9) >> 6) & 0x3f0_3fff,
+        (le(key, 12) >> 8) & 0x00f_ffff,
+    ]
+    .map(u64::from);
+    let s = [r[1] * 5, r[2] * 5, r[3] * 5, r[4] * 5];
+    let mut h = [0u64; 5];
+
+    for chunk in message.chunks(16) {
+        let mut block = [0; 17];
+        block[..chunk.len()].copy_from_slice(chunk);
+        block[chunk.len()] = 1;
+        let hibit = match chunk.len() {
+            16 => 1 << 24,
+            _ => 0,
+        };
+        h[0] += u64::from(le(&block, 0) & 0x3ff_ffff);
+        h[1] += u64::from((le(&block, 3) >> 2) & 0x3ff_ffff);
+        h[2] += u64::from((le(&block, 6) >> 4) & 0x3ff_ffff);
+        h[3] += u64::from((le(&block, 9) >> 6) & 0x3ff_ffff);
+        h[4] += u64::from(le(&block, 12) >> 8) | hibit;
+
+        let d = [
+            h[0] * r[0] + h[1] * s[3] + h[2] * s[2] + h[3] * s[1] + h[4] * s[0],
+            h[0] * r[1] + h[1] * r[0] + h[2] * s[3] + h[3] * s[2] + h[4] * s[1],
+            h[0] * r[2] + h[1] * r[1] + h[2] * r[0] + h[3] * s[3] + h[4] * s[2],
+            h[0] * r[3] + h[1] * r[2] + h[2] * r[1] + h[3] * r[0] + h[4] * s[3],
+            h[0] * r[4] + h[1] * r[3] + h[2] * r[2] + h[3] * r[1] + h[4] * r[0],
+        ];
+        let mut carry = 0;
+        for i in 0..5 {
+            let d = d[i] + carry;
+            h[i] = d & 0x3ff_ffff;
+            carry = d >> 26;
+        }
+        h[0] += carry * 5;
+        h[1] += h[0] >> 26;
+        h[0] &= 0x3ff_ffff;
+    }
+
+    // Fully carry h, then subtract p if h is at least p.
+    let mut carry = 0;
+    for _ in 0..2 {
+        for limb in &mut h[1..] {
+            *limb += carry;
+            carry = *limb >> 26;
+            *limb &= 0x3ff_ffff;
+        }
+        h[0] += carry * 5;
+        carry = h[0] >> 26;
+        h[0] &= 0x3ff_ffff;
+    }
+    h[1] += carry;
+    let mut g = [0u64; 5];
+    let mut carry = 5;
+    for i in 0..5 {
+        g[i] = h[i] + carry;
+        carry = g[i] >> 26;
+        g[i] &= 0x3ff_ffff;
+    }
+    // g holds h + 5 - 2^130 when the carry out of it is set, that is h >= p.
+    let mask = carry.wrapping_neg();
+    for i in 0..5 {
+        h[i] = (h[i] & !mask) | (g[i] & mask);
+    }
+
+    let value = h.iter().enumerate().fold(0u128, |value, (i, &limb)| {
+        value.wrapping_add(u128::from(limb) << (26 * i))
+    });
+    let pad = u128::from_le_bytes(key[16..].try_into().unwrap());
+    value.wrapping_add(pad).to_le_bytes()
+}
+
+/// Computes the Poly1305 tag of the ChaCha20-Poly1305 construction over
+/// `aad` and `ciphertext`.
+fn aead_tag(key: &[u8; 32], nonce: &[u8; 12], aad: &[u8], ciphertext: &[u8]) -> [u8; 16] {
+    let block = chacha20_block(key, 0, nonce);
+    let one_time: [u8; 32] = block[..32].try_into().unwrap();
+    let padding = |len: usize| vec![0; (16 - len % 16) % 16];
+    let mut data = aad.to_vec();
+    data.extend(padding(aad.len()));
+    data.extend_from_slice(ciphertext);
+    data.extend(padding(ciphertext.len()));
+    data.extend((aad.len() as u64).to_le_bytes());
+    data.extend((ciphertext.len() as u64).to_le_bytes());
+    poly1305(&one_time, &data)
+}
+
+/// Encrypts `plaintext` with ChaCha20-Poly1305 (RFC 8439), returning the
+/// ciphertext followed by its 16 byte tag.
+pub(crate) fn seal(key: &[u8; 32], nonce: &[u8; 12], aad: &[u8], plaintext: &[u8]) -> Vec<u8> {
+    let mut sealed = plaintext.to_vec();
+    chacha20(key, 1, nonce, &mut sealed);
+    let tag = aead_tag(key, nonce, aad, &sealed);
+    sealed.extend_from_slice(&tag);
+    sealed
+}
+
+/// Decrypts what [`seal`] returned, or returns `None` if it was not sealed
+/// with this key, nonce and additional data.
+pub(crate) fn open(key: &[u8; 32], nonce: &[u8; 12], aad: &[u8], sealed: &[u8]) -> Option<Vec<u8>> {
+    let (ciphertext, tag) = sealed.split_at_checked(sealed.len().checked_sub(16)?)?;
+    if !equal(&aead_tag(key, nonce, aad, ciphertext), tag) {
+        return None;
+    }
+    let mut plaintext = ciphertext.to_vec();
+    chacha20(key, 1, nonce, &mut plaintext);
+    Some(plaintext)
+}
+
+const BECH32_CHARSET: &[u8; 32] = b"qpzry9x8gf2tvdw0s3jn54khce6mua7l";
+
+fn bech32_polymod(values: impl IntoIterator<Item = u8>) -> u32 {
+    const GENERATOR: [u32; 5] = [
+        0x3b6a_57b2,
+        0x2650_8e6d,
+        0x1ea1_19fa,
+        0x3d42_33dd,
+        0x2a14_62b3,
+    ];
+    values.into_iter().fold(1, |checksum, value| {
+        let top = checksum >> 25;
+        let checksum = (checksum & 0x1ff_ffff) << 5 ^ u32::from(value);
+        (0..5)
+            .filter(|i| top >> i & 1 == 1)
+            .fold(checksum, |checksum, i| checksum ^ GENERATOR[i])
+    })
+}
+
+fn bech32_hrp(hrp: &str) -> impl Iterator<Item = u8> + '_ {
+    let hrp = hrp.bytes().map(|byte| byte.to_ascii_lowercase());
+    hrp.clone()
+        .map(|byte| byte >> 5)
+        .chain([0])
+        .chain(hrp.map(|byte| byte & 31))
+}
+
+/// Regroups `data` from words of `from` bits to words of `to` bits, padding
+/// the last one with zeros if `pad` is set.
+fn regroup(data: &[u8], from: u32, to: u32, pad: bool) -> Option<Vec<u8>> {
+    let (mut acc, mut bits, mut out) = (0u32, 0, Vec::new());
+    for &value in data {
+        acc = acc << from | u32::from(value);
+        bits += from;
+        while bits >= to {
+            bits -= to;
+            out.push((acc >> bits & ((1 << to) - 1)) as u8);
+        }
+    }
+    match pad {
+        true if bits > 0 => out.push((acc << (to - bits) & ((1 << to) - 1)) as u8),
+        false if bits >= from || acc & ((1 << bits) - 1) != 0 => return None,
+        _ => {}
+    }
+    Some(out)
+}
+
+/// Encodes `data` as bech32 (BIP 173) with the human readable part `hrp`, in
+/// lower case.
+pub(crate) fn bech32_encode(hrp: &str, data: &[u8]) -> String {
+    let words = regroup(data, 8, 5, true).expect("padding always succeeds");
+    let checksum = bech32_polymod(bech32_hrp(hrp).chain(words.iter().copied()).chain([0; 6])) ^ 1;
+    let checksum = (0..6).map(|i| (checksum >> (5 * (5 - i)) & 31) as u8);
+    let mut encoded = hrp.to_ascii_lowercase();
+    encoded.push('1');
+    encoded.extend(
+        words
+            .into_iter()
+            .chain(checksum)
+            .map(|word| char::from(BECH32_CHARSET[usize::from(word)])),
+    );
+    encoded
+}
+
+/// Decodes a bech32 string, in either case but not mixed, returning its
+/// human readable part in lower case and its data.
+pub(crate) fn bech32_decode(encoded: &str) -> Option<(String, Vec<u8>)> {
+    let lower = encoded.to_ascii_lowercase();
+    if lower != encoded && encoded.to_ascii_uppercase() != encoded {
+        return None;
+    }
+    let (hrp, data) = lower.rsplit_once('1')?;
+    if hrp.is_empty() || data.len() < 6 {
+        return None;
+    }
+    let words = data
+        .bytes()
+        .map(|byte| {
+            BECH32_CHARSET
+                .iter()
+                .position(|&c| c == byte)
+                .map(|i| i as u8)
+        })
+        .collect::<Option<Vec<_>>>()?;
+    if bech32_polymod(bech32_hrp(hrp).chain(words.iter().copied())) != 1 {
+        return None;
+    }
+    let data = regroup(&words[..words.len() - 6], 5, 8, false)?;
+    Some((hrp.to_owned(), data))
+}
+
+/// Per-step constants of MD5, the integer parts of `abs(sin(i + 1)) * 2^32`.
+const MD5_CONSTANTS: [u32; 64] = [
+    0xd76aa478, 0xe8c7b756, 0x242070db, 0xc1bdceee, 0xf57c0faf, 0x4787c62a, 0xa8304613, 0xfd469501,
+    0x698098d8, 0x8b44f7af, 0xffff5bb1, 0x895cd7be, 0x6b901122, 0xfd987193, 0xa679438e, 0x49b40821,
+    0xf61e2562, 0xc040b340, 0x265e5a51, 0xe9b6c7aa, 0xd62f105d, 0x02441453, 0xd8a1e681, 0xe7d3fbc8,
+    0x21e1cde6, 0xc33707d6, 0xf4d50d87, 0x455a14ed, 0xa9e3e905, 0xfcefa3f8, 0x676f02d9, 0x8d2a4c8a,
+    0xfffa3942, 0x8771f681, 0x6d9d6122, 0xfde5380c, 0xa4beea44, 0x4bdecfa9, 0xf6bb4b60, 0xbebfbc70,
+    0x289b7ec6, 0xeaa127fa, 0xd4ef3085, 0x04881d05, 0xd9d4d039, 0xe6db99e5, 0x1fa27cf8, 0xc4ac5665,
+    0xf4292244, 0x432aff97, 0xab9423a7, 0xfc93a039, 0x655b59c3, 0x8f0ccc92, 0xffeff47d, 0x85845dd1,
+    0x6fa87e4f, 0xfe2ce6e0, 0xa3014314, 0x4e0811a1, 0xf7537e82, 0xbd3af235, 0x2ad7d2bb, 0xeb86d391,
+];
+
+/// Per-round shift amounts of MD5.
+const MD5_SHIFTS: [u32; 16] = [7, 12, 17, 22, 5, 9, 14, 20, 4, 11, 16, 23, 6, 10, 15, 21];
+
+/// Computes MD5 (RFC 1321) incrementally.
+#[derive(Clone)]
+pub(crate) struct Md5 {
+    state: [u32; 4],
+    buffer: Vec<u8>,
+    length: u64,
+}
+
+impl Md5 {
+    pub(crate) fn new() -> Self {
+        Md5 {
+            state: [0x67452301, 0xefcdab89, 0x98badcfe, 0x10325476],
+            buffer: Vec::with_capacity(64),
+            length: 0,
+        }
+    }
+
+    pub(crate) fn update(&mut self, mut data: &[u8]) {
+        self.length = self.length.wrapping_add(data.len() as u64);
+        if !self.buffer.is_empty() {
+            let taken = data.len().min(64 - self.buffer.len());
+            self.buffer.extend_from_slice(&data[..taken]);
+            data = &data[taken..];
+            if self.buffer.len() < 64 {
+                return;
+            }
+            let block: [u8; 64] = self.buffer[..].try_into().expect("full block");
+            self.compress(&block);
+            self.buffer.clear();
+        }
+        let mut blocks = data.chunks_exact(64);
+        for block in &mut blocks {
+            self.compress(block.try_into().expect("full block"));
+        }
+        self.buffer.extend_from_slice(blocks.remainder());
+    }
+
+    pub(crate) fn finalize(mut self) -> [u8; 16] {
+        let bits = self.length.wrapping_mul(8);
+        let padding = (55usize.wrapping_sub(self.buffer.len()) % 64) + 1;
+        let mut tail = vec![0; padding];
+        tail[0] = 0x80;
+        self.update(&tail);
+        self.update(&bits.to_le_bytes());
+        let mut digest = [0; 16];
+        for (chunk, word) in digest.chunks_exact_mut(4).zip(self.state) {
+            chunk.copy_from_slice(&word.to_le_bytes());
+        }
+        digest
+    }
+
+    fn compress(&mut self, block: &[u8; 64]) {
+        let mut words = [0; 16];
+        for (word, bytes) in words.iter_mut().zip(block.chunks_exact(4)) {
+            *word = u32::from_le_bytes(bytes.try_into().expect("four bytes"));
+        }
+        let [mut a, mut b, mut c, mut d] = self.state;
+        for i in 0..64 {
+            let (f, g) = match i / 16 {
+                0 => ((b & c) | (!b & d), i),
+                1 => ((d & b) | (!d & c), (5 * i + 1) % 16),
+                2 => (b ^ c ^ d, (3 * i + 5) % 16),
+                _ => (c ^ (b | !d), (7 * i) % 16),
+            };
+            let rotated = a
+                .wrapping_add(f)
+                .wrapping_add(MD5_CONSTANTS[i])
+                .wrapping_add(words[g])
+                .rotate_left(MD5_SHIFTS[i / 16 * 4 + i % 4]);
+            (a, d, c) = (d, c, b);
+            b = b.wrapping_add(rotated);
+        }
+        for (state, value) in self.state.iter_mut().zip([a, b, c, d]) {
+            *state = state.wrapping_add(value);
+        }
+    }
+}
+
+const BASE64_CHARSET: &[u8; 64] =
+    b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+/// Encodes `data` as standard base64 without padding.
+pub(crate) fn base64_encode(data: &[u8]) -> String {
+    regroup(data, 8, 6, true)
+        .expect("padding always succeeds")
+        .into_iter()
+        .map(|word| char::from(BASE64_CHARSET[usize::from(word)]))
+        .collect()
+}
+
+/// Decodes standard base64 without padding, rejecting non-canonical input.
+pub(crate) fn base64_decode(encoded: &str) -> Option<Vec<u8>> {
+    let words = encoded
+        .bytes()
+        .map(|byte| {
+            BASE64_CHARSET
+                .iter()
+                .position(|&c| c == byte)
+                .map(|i| i as u8)
+        })
+        .collect::<Option<Vec<_>>>()?;
+    regroup(&words, 6, 8, false)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hex(text: &str) -> Vec<u8> {
+        (0..text.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&text[i..i + 2], 16).unwrap())
+            .collect()
+    }
+
+    fn array<const N: usize>(text: &str) -> [u8; N] {
+        hex(text).try_into().unwrap()
+    }
+
+    #[test]
+    fn scrypt_matches_rfc_7914() {
+        let mut output = [0; 64];
+        pbkdf2_sha256(b"passwd", b"salt", 1, &mut output);
+        assert_eq!(output.to_vec(), hex("55ac046e56e3089fec1691c22544b605f94185216dde0465e68b9d57c20dacbc49ca9cccf179b645991664b39d77ef317c71b845b1e30bd509112041d3a19783"));
+
+        scrypt_into(b"", b"", 4, 1, 1, &mut output);
+        assert_eq!(output.to_vec(), hex("77d6576238657b203b19ca42c18a0497f16b4844e3074ae8dfdffa3fede21442fcd0069ded0948f8326a753a0fc81f17e8d3e0fb2e0d3628cf35e20c38d18906"));
+        scrypt_into(b"password", b"NaCl", 10, 8, 16, &mut output);
+        assert_eq!(output.to_vec(), hex("fdbabe1c9d3472007856e7190d01e9fe7c6ad7cbc8237830e77376634b3731622eaf30d92e22a3886ff109279d9830dac727afb94a83ee6d8360cbdfa2cc0640"));
+        assert_eq!(scrypt(b"password", b"NaCl", 10, 8, 16), output[..32]);
+    }
+
+    #[test]
+    fn ed25519_matches_rfc_8032() {
+        let seed = array("9d61b19deffd5a60ba844af492ec2cc44449c5697b326919703bac031cae7f60");
+        let public = array("d75a980182b10ab7d54bfed3c964073a0ee172f3daa62325af021a68f707511a");
+        let signature = array("e5564300c360ac729086e2cc806e828a84877f1eb8e5d974d873e065224901555fb8821590a33bacc61e39701cf9b46bd25bf5f0595bbe24655141438e7a100b");
+        assert_eq!(ed25519_public(&seed), public);
+        assert_eq!(ed25519_sign(&seed, b""), signature);
+        assert!(ed25519_verify(&public, b"", &signature));
+        assert!(!ed25519_verify(&public, b"x", &signature));
+
+        let seed = array("4ccd089b28ff96da9db6c346ec114e0f5b8a319f35aba624da8cf6ed4fb8a6fb");
+        let public = array("3d4017c3e843895a92b70aa74d1b7ebc9c982ccf2ec4968cc0cd55f12af4660c");
+        let signature = array("92a009a9f0d4cab8720e820b5f642540a2b27b5416503f8fb3762223ebdb69da085ac1e43e15996e458f3613d0f11d8c387b2eaeb4302aeeb00d291612bb0c00");
+        assert_eq!(ed25519_public(&seed), public);
+        assert_eq!(ed25519_sign(&seed, &[0x72]), signature);
+        assert!(ed25519_verify(&public, &[0x72], &signature));
+        let mut forged = signature;
+        forged[63] ^= 0x10;
+        assert!(!ed25519_verify(&public, &[0x72], &forged));
+    }
+
+    #[test]
+    fn x25519_matches_rfc_7748() {
+        let scalar = array("a546e36bf0527c9d3b16154b82465edd62144c0ac1fc5a18506a2244ba449ac4");
+        let u = array("e6db6867583030db3594c1a424b15f7c726624ec26b3353b10a903a6d0ab1c4c");
+        assert_eq!(
+            x25519(&scalar, &u),
+            array("c3da55379de9c6908e94ea4df28d084f32eccf03491c71f754b4075577a28552")
+        );
+
+        let alice = array("77076d0a7318a57d3c16c17251b26645df4c2f87ebc0992ab177fba51db92c2a");
+        let bob = array("5dab087e624a8a4b79e17f8b83800ee66f3bb1292618b6fd1c2f8b27ff88e0eb");
+        let alice_public = x25519(&alice, &BASEPOINT);
+        assert_eq!(
+            alice_public,
+            array("8520f0098930a754748b7ddcb43ef75a0dbf3a0d26381af4eba4a98eaa9b4e6a")
+        );
+        let shared =
+            array::<32>("4a5d9d5ba4ce2de1728e3bf480350f25e07e21c947d19e3376f09b3c1e161742");
+        assert_eq!(x25519(&bob, &alice_public), shared);
+        assert_eq!(x25519(&alice, &x25519(&bob, &BASEPOINT)), shared);
+    }
+
+    #[test]
+    fn chacha20_poly1305_matches_rfc_8439() {
+        let key = array("000102030405060708090a0b0c0d0e0f101112131415161718191a1b1c1d1e1f");
+        let block = chacha20_block(&key, 1, &array("000000090000004a00000000"));
+        assert_eq!(block[..16], hex("10f1e7e4d13b5915500fdd1fa32071c4"));
+
+        let tag = poly1305(
+            &array("85d6be7857556d337f4452fe42d506a80103808afb0db2fd4abff6af4149f51b"),
+            b"Cryptographic Forum Research Group",
+        );
+        assert_eq!(tag, array::<16>("a8061dc1305136c6c22b8baf0c0127a9"));
+
+        let plaintext = b"Ladies and Gentlemen of the class of '99: If I could offer you \
+                          only one tip for the future, sunscreen would be it.";
+        let key = array("808182838485868788898a8b8c8d8e8f909192939495969798999a9b9c9d9e9f");
+        let nonce = array("070000004041424344454647");
+        let aad = hex("50515253c0c1c2c3c4c5c6c7");
+        let sealed = seal(&key, &nonce, &aad, plaintext);
+        assert_eq!(sealed[..16], hex("d31a8d34648e60db7b86afbc53ef7ec2"));
+        assert_eq!(
+            sealed[sealed.len() - 16..],
+            hex("1ae10b594f09e26a7e902ecbd0600691")
+        );
+        assert_eq!(open(&key, &nonce, &aad, &sealed).unwrap(), plaintext);
+
+        let mut tampered = sealed.clone();
+        tampered[0] ^= 1;
+        assert!(open(&key, &nonce, &aad, &tampered).is_none());
+        assert!(open(&key, &nonce, b"", &sealed).is_none());
+    }
+
+    #[test]
+    fn md5_matches_rfc_1321() {
+        let digest = |data: &[u8]| {
+            let mut md5 = Md5::new();
+            for chunk in data.chunks(7) {
+                md5.update(chunk);
+            }
+            md5.finalize().to_vec()
+        };
+        assert_eq!(digest(b""), hex("d41d8cd98f00b204e9800998ecf8427e"));
+        assert_eq!(digest(b"abc"), hex("900150983cd24fb0d6963f7d28e17f72"));
+        assert_eq!(
+            digest(
+                b"12345678901234567890123456789012345678901234567890123456789012345678901234567890"
+            ),
+            hex("57edf4a22be3c955ac49da2e2107b67a")
+        );
+    }
+
+    #[test]
+    fn hkdf_matches_rfc_5869() {
+        let tag = hmac_sha256(&[0x0b; 20], b"Hi There");
+        assert_eq!(
+            tag,
+            array("b0344c61d8db38535ca8afceaf0bf12b881dc200c9833da726e9376c2e32cff7")
+        );
+
+        let okm = hkdf_sha256(
+            &[0x0b; 22],
+            &hex("000102030405060708090a0b0c"),
+            &hex("f0f1f2f3f4f5f6f7f8f9"),
+        );
+        assert_eq!(
+            okm,
+            array("3cb25f25faacd57a90434f64d0362f2a2d2d0a90cf1a5a4c5db02d56ecc4c5bf")
+        );
+    }
+
+    #[test]
+    fn bech32_round_trips_and_checks_its_checksum() {
+        assert_eq!(
+            bech32_decode("A12UEL5L"),
+            Some(("a".to_owned(), Vec::new()))
+        );
+        let encoded = bech32_encode("age", &[7; 32]);
+        assert_eq!(
+            bech32_decode(&encoded),
+            Some(("age".to_owned(), vec![7; 32]))
+        );
+        assert_eq!(
+            bech32_decode(&encoded.to_ascii_uppercase()),
+            Some(("age".to_owned(), vec![7; 32]))
+        );
+
+        let mut corrupted = encoded.into_bytes();
+        corrupted[10] = if corrupted[10] == b'q' { b'p' } else { b'q' };
+        assert!(bech32_decode(std::str::from_utf8(&corrupted).unwrap()).is_none());
+    }
+
+    #[test]
+    fn base64_has_no_padding() {
+        assert_eq!(base64_encode(b"fooba"), "Zm9vYmE");
+        assert_eq!(base64_decode("Zm9vYmE").unwrap(), b"fooba");
+        assert!(base64_decode("Zm9vYmE=").is_none());
+        assert!(base64_decode("Zm9vYmF").is_none());
+    }
+}
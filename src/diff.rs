@@ -0,0 +1,261 @@
+//! Comparison of a backup with the current state of its source.
+
+use std::collections::BTreeMap;
+use std::fs::{self, Metadata};
+use std::io;
+use std::path::{Path, PathBuf};
+
+use filetime::FileTime;
+use serde::Serialize;
+
+use crate::archive;
+use crate::backup::{Compression, DEFAULT_BUFFER_SIZE};
+use crate::checksum::{self, Algorithm};
+use crate::error::BackupError;
+use crate::list::{self, EntryKind};
+use crate::split;
+use crate::walk::Walker;
+
+/// How an entry differs between a backup and the current source.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Change {
+    /// The entry only exists in the current source.
+    Added,
+    /// The entry only exists in the backup.
+    Removed,
+    /// The entry exists on both sides with a different type, size,
+    /// modification time, link target or, when comparing checksums, contents.
+    Modified,
+}
+
+/// An entry differing between a backup and the current source.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Difference {
+    /// Path of the entry relative to the backup root, empty for the root itself.
+    pub path: PathBuf,
+    /// How the entry differs.
+    pub change: Change,
+}
+
+/// Type of an entry, with the target of symbolic links.
+#[derive(Debug, PartialEq, Eq)]
+enum Kind {
+    Directory,
+    File,
+    Link(PathBuf),
+    Other,
+}
+
+/// Contents of a file, hashed when comparing checksums.
+#[derive(Debug, Clone)]
+enum Contents {
+    /// Digest computed while reading an archive.
+    Digest(String),
+    /// File to hash when needed.
+    Path(PathBuf),
+    /// Contents that are not compared.
+    Unknown,
+}
+
+/// An entry of one side of the comparison.
+#[derive(Debug)]
+struct Node {
+    kind: Kind,
+    size: u64,
+    /// Modification time in whole seconds, the precision of tar archives.
+    mtime: i64,
+    contents: Contents,
+}
+
+impl Node {
+    fn new(path: &Path, metadata: &Metadata) -> Self {
+        let kind = if metadata.is_dir() {
+            Kind::Directory
+        } else if metadata.is_symlink() {
+            Kind::Link(fs::read_link(path).unwrap_or_default())
+        } else if metadata.is_file() {
+            Kind::File
+        } else {
+            Kind::Other
+        };
+
+        Node {
+            kind,
+            size: metadata.len(),
+            mtime: FileTime::from_last_modification_time(metadata).unix_seconds(),
+            contents: Contents::Path(path.to_path_buf()),
+        }
+    }
+
+    fn digest(&self) -> Option<String> {
+        match &self.contents {
+            Contents::Digest(digest) => Some(digest.clone()),
+            Contents::Path(path) => checksum::hash_file(path, Algorithm::default()).ok(),
+            Contents::Unknown => None,
+        }
+    }
+
+    /// Checks whether `self` and `other` differ, comparing the contents of
+    /// files rather than their modification time if `compare_checksums` is set.
+    fn differs(&self, other: &Node, compare_checksums: bool) -> bool {
+        if self.kind != other.kind {
+            return true;
+        }
+        if self.kind != Kind::File {
+            return false;
+        }
+        if self.size != other.size {
+            return true;
+        }
+        if compare_checksums {
+            let digest = self.digest();
+            return digest.is_none() || digest != other.digest();
+        }
+        self.mtime != other.mtime
+    }
+}
+
+/// Compares the backup at `backup` with the file or directory at `current`,
+/// returning the differing entries sorted by path.
+///
+/// The backup may be a copy of a file or directory, or an archive, whose
+/// entries are then read from the archive listing, or the link to the newest
+/// backup of a name, see [`list::resolve_latest`]. An entry found on one
+/// side only is reported, but not the entries below it. Directories only
+/// differ from each other by their contents, and files by their size and
+/// modification time, or their contents with `compare_checksums`.
+///
+/// # Examples
+///
+/// ```no_run
+/// use std::path::Path;
+///
+/// let backup = Path::new("/var/backups/etc.2024-05-01_10-00-00.backup");
+/// for difference in backup::diff::diff(backup, Path::new("/etc"), false)? {
+///     println!("{:?} {}", difference.change, difference.path.display());
+/// }
+/// # Ok::<(), backup::BackupError>(())
+/// ```
+pub fn diff(
+    backup: &Path,
+    current: &Path,
+    compare_checksums: bool,
+) -> Result<Vec<Difference>, BackupError> {
+    let before = read_tree(&list::resolve_latest(backup), compare_checksums)?;
+    let after = read_tree(current, false)?;
+
+    let mut differences: Vec<Difference> = Vec::new();
+    let mut report = |path: &Path, change| {
+        let reported = differences
+            .last()
+            .is_some_and(|last| path.starts_with(&last.path));
+        if !reported {
+            differences.push(Difference {
+                path: path.to_path_buf(),
+                change,
+            });
+        }
+    };
+
+    let mut after = after.into_iter().peekable();
+    for (path, node) in before {
+        while let Some((added, _)) = after.next_if(|(added, _)| *added < path) {
+            report(&added, Change::Added);
+        }
+        match after.next_if(|(current, _)| *current == path) {
+            Some((_, current)) if node.differs(&current, compare_checksums) => {
+                report(&path, Change::Modified)
+            }
+            Some(_) => {}
+            None => report(&path, Change::Removed),
+        }
+    }
+    for (added, _) in after {
+        report(&added, Change::Added);
+    }
+
+    Ok(differences)
+}
+
+/// Reads the entries of the file, directory or archive at `path`, by path
+/// relative to it. Archive contents are hashed if `hash_archive` is set.
+fn read_tree(path: &Path, hash_archive: bool) -> Result<BTreeMap<PathBuf, Node>, BackupError> {
+    let metadata = split::metadata(path).map_err(|e| match e.kind() {
+        io::ErrorKind::NotFound => BackupError::NotFound(path.to_path_buf()),
+        _ => BackupError::ReadFailed {
+            path: path.to_path_buf(),
+            source: e,
+        },
+    })?;
+
+    let mut tree = BTreeMap::new();
+    if !metadata.is_dir() {
+        match archive::format(path) {
+            Some(compression) => {
+                read_archive(path, compression, hash_archive, &mut tree).map_err(|source| {
+                    BackupError::ExtractFailed {
+                        path: path.to_path_buf(),
+                        source,
+                    }
+                })?;
+            }
+            None => {
+                tree.insert(PathBuf::new(), Node::new(path, &metadata));
+            }
+        }
+        return Ok(tree);
+    }
+
+    tree.insert(PathBuf::new(), Node::new(path, &metadata));
+    for entry in Walker::new(path, false)? {
+        let entry = entry?;
+        tree.insert(entry.relative, Node::new(&entry.path, &entry.metadata));
+    }
+    Ok(tree)
+}
+
+/// Reads the entries of the archive at `path` into `tree`, hashing the
+/// contents of files if `hash` is set.
+fn read_archive(
+    path: &Path,
+    compression: Compression,
+    hash: bool,
+    tree: &mut BTreeMap<PathBuf, Node>,
+) -> io::Result<()> {
+    archive::for_each(path, compression, |entry, mut contents| {
+        let kind = match entry.kind {
+            EntryKind::Directory => Kind::Directory,
+            EntryKind::File => Kind::File,
+            EntryKind::Link => Kind::Link(entry.link.clone().unwrap_or_default()),
+            EntryKind::Other => Kind::Other,
+        };
+        // A hard link has the contents of the file it links to.
+        let linked = match (&kind, &entry.link) {
+            (Kind::File, Some(link)) => tree.get(link).map(|node| node.contents.clone()),
+            _ => None,
+        };
+        let contents = if let Some(contents) = linked {
+            contents
+        } else if hash && kind == Kind::File {
+            let (_, digest) = checksum::copy_hashed(
+                &mut contents,
+                io::sink(),
+                Algorithm::default(),
+                DEFAULT_BUFFER_SIZE,
+            )?;
+            Contents::Digest(digest)
+        } else {
+            Contents::Unknown
+        };
+
+        let node = Node {
+            kind,
+            size: entry.size,
+            mtime: entry.modified.map_or(0, |modified| modified.timestamp()),
+            contents,
+        };
+        tree.insert(entry.path, node);
+        Ok(())
+    })
+}
@@ -0,0 +1,366 @@
+//! Checks of the environment backups are written to.
+//!
+//! [`diagnose`] looks at a backup directory and the configuration file: that
+//! the directory can be written to and has room for another backup, what its
+//! filesystem supports, which it finds out by creating links and attributes
+//! in a scratch directory removed afterwards, whether existing backups are
+//! dated in the future, which tells a wrong clock, and whether interrupted
+//! backups left partial files or stale locks behind. Each [`Check`] tells
+//! whether it passed and how to fix what it found.
+//!
+//! # Examples
+//!
+//! ```
+//! use backup::doctor::{self, Status};
+//!
+//! let dir = std::env::temp_dir().join("doctor-example");
+//! std::fs::create_dir_all(&dir)?;
+//! let checks = doctor::diagnose(&dir, None);
+//! assert!(checks.iter().any(|check| check.name == "writable" && check.status == Status::Pass));
+//! # Ok::<(), std::io::Error>(())
+//! ```
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use chrono::{Local, TimeDelta, Utc};
+use serde::Serialize;
+
+use crate::backup::{self, LOCK_SUFFIX};
+use crate::config::Config;
+use crate::error::BackupError;
+use crate::list;
+use crate::lock;
+use crate::writer;
+use crate::xattrs;
+
+/// Time backups may be dated ahead of the clock before it is suspected to be
+/// wrong, which absorbs small drifts between the machines writing them.
+const CLOCK_TOLERANCE: TimeDelta = TimeDelta::minutes(1);
+
+/// Outcome of a check, ordered from best to worst.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Status {
+    Pass,
+    /// Backups can be made, but may miss something or fail later.
+    Warn,
+    /// Backups cannot be made until it is fixed.
+    Fail,
+}
+
+/// A check of [`diagnose`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Check {
+    /// Short name of what was checked, such as `free-space`.
+    pub name: &'static str,
+    pub status: Status,
+    /// What was found.
+    pub message: String,
+    /// How to fix it, unless the check passed.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub hint: Option<String>,
+}
+
+impl Check {
+    fn pass(name: &'static str, message: impl Into<String>) -> Self {
+        Check {
+            name,
+            status: Status::Pass,
+            message: message.into(),
+            hint: None,
+        }
+    }
+
+    fn warn(name: &'static str, message: impl Into<String>, hint: impl Into<String>) -> Self {
+        Check {
+            name,
+            status: Status::Warn,
+            message: message.into(),
+            hint: Some(hint.into()),
+        }
+    }
+
+    fn fail(name: &'static str, message: impl Into<String>, hint: impl Into<String>) -> Self {
+        Check {
+            name,
+            status: Status::Fail,
+            message: message.into(),
+            hint: Some(hint.into()),
+        }
+    }
+}
+
+/// Checks the backup directory `dir` and the configuration file at `config`,
+/// if there is one to look for, see the [module](self) docs.
+///
+/// The filesystem is only probed if `dir` can be written to.
+pub fn diagnose(dir: &Path, config: Option<&Path>) -> Vec<Check> {
+    let mut checks = Vec::new();
+    if !dir.is_dir() {
+        checks.push(Check::fail(
+            "writable",
+            format!("'{}' is not a directory", dir.display()),
+            "Create it, or check the path given",
+        ));
+        checks.push(check_config(config));
+        return checks;
+    }
+
+    let scratch = dir.join(format!(".backup-doctor.{}", std::process::id()));
+    match fs::create_dir(&scratch).and_then(|()| fs::write(scratch.join("file"), "probe")) {
+        Ok(()) => {
+            checks.push(Check::pass(
+                "writable",
+                format!("'{}' can be written to", dir.display()),
+            ));
+            checks.extend(probe(&scratch));
+        }
+        Err(error) => checks.push(Check::fail(
+            "writable",
+            format!("'{}' cannot be written to: {error}", dir.display()),
+            "Check the owner and permissions of the directory, or run as a user who may write to it",
+        )),
+    }
+    let _ = fs::remove_dir_all(&scratch);
+
+    let backups = list::list(dir, None, false);
+    checks.push(check_space(dir, backups.as_deref().ok()));
+    checks.push(check_clock(backups));
+    let leftovers = leftovers(dir);
+    checks.push(check_partial(&leftovers));
+    checks.push(check_locks(&leftovers));
+    checks.push(check_config(config));
+    checks
+}
+
+/// Checks the links and attributes the filesystem of the `scratch`
+/// directory supports, by creating them next to its `file`.
+fn probe(scratch: &Path) -> [Check; 3] {
+    let file = scratch.join("file");
+    let symlinks = match backup::symlink(Path::new("file"), &scratch.join("symlink")) {
+        Ok(()) => Check::pass("symlinks", "symbolic links are supported"),
+        Err(error) => Check::warn(
+            "symlinks",
+            format!("symbolic links cannot be created: {error}"),
+            "Back up as an archive with --compress, which records links",
+        ),
+    };
+    let hardlinks = match fs::hard_link(&file, scratch.join("hardlink")) {
+        Ok(()) => Check::pass("hardlinks", "hard links are supported"),
+        Err(error) => Check::warn(
+            "hardlinks",
+            format!("hard links cannot be created: {error}"),
+            "Incremental backups copy unchanged files in full here; use a filesystem with hard links to share them",
+        ),
+    };
+    let xattrs = match xattrs::probe(&file) {
+        Ok(true) => Check::pass("xattrs", "extended attributes are supported"),
+        Ok(false) => Check::warn(
+            "xattrs",
+            "extended attributes are not supported",
+            "Attributes, ACLs and SELinux labels are not preserved here; back up as an archive with --compress to keep them",
+        ),
+        Err(error) => Check::warn(
+            "xattrs",
+            format!("extended attributes cannot be set: {error}"),
+            "Attributes, ACLs and SELinux labels may not be preserved here",
+        ),
+    };
+    [symlinks, hardlinks, xattrs]
+}
+
+/// Checks that the filesystem of `dir` has room for another backup as large
+/// as the largest of its `backups`, if they could be listed.
+fn check_space(dir: &Path, backups: Option<&[list::ListEntry]>) -> Check {
+    let Some(free) = backup::free_space(dir) else {
+        return Check::warn(
+            "free-space",
+            "the free space cannot be found out",
+            "Check it with df",
+        );
+    };
+    let largest = backups
+        .unwrap_or_default()
+        .iter()
+        .map(|entry| entry.size)
+        .max()
+        .unwrap_or(0);
+    let message = format!("{} free", writer::human_bytes(free));
+    match free {
+        0 => Check::fail(
+            "free-space",
+            message,
+            "Free some space, for example with 'backup prune'",
+        ),
+        _ if free < largest => Check::warn(
+            "free-space",
+            format!(
+                "{message}, less than the largest backup ({})",
+                writer::human_bytes(largest)
+            ),
+            "Free some space, for example with 'backup prune', or compress backups with --compress",
+        ),
+        _ => Check::pass("free-space", message),
+    }
+}
+
+/// Checks that none of the `backups` is dated in the future.
+fn check_clock(backups: Result<Vec<list::ListEntry>, BackupError>) -> Check {
+    let backups = match backups {
+        Ok(backups) => backups,
+        Err(error) => {
+            return Check::warn(
+                "clock",
+                format!("backups cannot be listed: {error}"),
+                "Check the permissions of the directory",
+            )
+        }
+    };
+    // Names may be in local time or UTC, whichever is later.
+    let now = Local::now().naive_local().max(Utc::now().naive_utc()) + CLOCK_TOLERANCE;
+    let future: Vec<_> = backups
+        .iter()
+        .filter(|entry| entry.timestamp.is_some_and(|timestamp| timestamp > now))
+        .collect();
+    match future.iter().max_by_key(|entry| entry.timestamp) {
+        None => Check::pass("clock", "no backup is dated in the future"),
+        Some(latest) => Check::warn(
+            "clock",
+            format!(
+                "{} backups are dated in the future, up to '{}'",
+                future.len(),
+                latest.path.display()
+            ),
+            "Check the system clock and its time zone; retention counts such backups as the newest",
+        ),
+    }
+}
+
+/// Returns the hidden files of `dir` left by backups: partial files, and
+/// lock files.
+fn leftovers(dir: &Path) -> Vec<PathBuf> {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return Vec::new();
+    };
+    let mut leftovers: Vec<_> = entries
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(|path| {
+            let name = path.file_name().unwrap_or_default().to_string_lossy();
+            name.starts_with('.') && (name.ends_with(".partial") || name.ends_with(LOCK_SUFFIX))
+        })
+        .collect();
+    leftovers.sort();
+    leftovers
+}
+
+/// Checks that no partial file is left among the `leftovers`.
+fn check_partial(leftovers: &[PathBuf]) -> Check {
+    let partial: Vec<_> = leftovers
+        .iter()
+        .filter(|path| path.to_string_lossy().ends_with(".partial"))
+        .collect();
+    match partial.as_slice() {
+        [] => Check::pass("partial-files", "no partial file is left"),
+        partial => Check::warn(
+            "partial-files",
+            format!(
+                "{} partial files left by interrupted backups: {}",
+                partial.len(),
+                names(partial)
+            ),
+            "Remove them with 'backup gc' once no backup is running",
+        ),
+    }
+}
+
+/// Checks that none of the lock files among the `leftovers` is stale.
+fn check_locks(leftovers: &[PathBuf]) -> Check {
+    let stale: Vec<_> = leftovers
+        .iter()
+        .filter(|path| path.to_string_lossy().ends_with(LOCK_SUFFIX))
+        .filter(|path| lock::is_stale(path).unwrap_or(false))
+        .collect();
+    match stale.as_slice() {
+        [] => Check::pass("locks", "no stale lock is left"),
+        stale => Check::warn(
+            "locks",
+            format!("{} stale locks: {}", stale.len(), names(stale)),
+            "Remove them with 'backup gc'; the next backup also takes them over",
+        ),
+    }
+}
+
+/// Checks that the configuration file at `path` is valid, if it exists.
+fn check_config(path: Option<&Path>) -> Check {
+    let Some(path) = path else {
+        return Check::warn(
+            "config",
+            "neither XDG_CONFIG_HOME nor HOME is set",
+            "Pass the configuration file with --config",
+        );
+    };
+    match Config::load(path) {
+        Ok(config) => Check::pass(
+            "config",
+            format!(
+                "'{}' is valid, with {} profiles",
+                path.display(),
+                config.profiles.len()
+            ),
+        ),
+        Err(BackupError::NotFound(_)) => Check::pass(
+            "config",
+            format!("no configuration file at '{}'", path.display()),
+        ),
+        Err(error) => Check::fail(
+            "config",
+            error.to_string(),
+            "Fix the file, then check it again with 'backup config check'",
+        ),
+    }
+}
+
+/// Returns the file names of `paths`, comma separated.
+fn names(paths: &[&PathBuf]) -> String {
+    let names: Vec<_> = paths
+        .iter()
+        .map(|path| path.file_name().unwrap_or_default().to_string_lossy())
+        .collect();
+    names.join(", ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn leftovers_are_reported() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join(".notes.partial"), "half").unwrap();
+        fs::write(dir.path().join(".notes.backup.lock"), "4294967295").unwrap();
+        fs::write(dir.path().join("notes.partial"), "not ours").unwrap();
+
+        let checks = diagnose(dir.path(), None);
+        let check = |name| checks.iter().find(|check| check.name == name).unwrap();
+        assert_eq!(check("writable").status, Status::Pass);
+        assert_eq!(check("partial-files").status, Status::Warn);
+        assert!(check("partial-files").message.ends_with(": .notes.partial"));
+        assert_eq!(check("locks").status, Status::Warn);
+        assert_eq!(check("config").status, Status::Warn);
+        assert!(!dir
+            .path()
+            .join(format!(".backup-doctor.{}", std::process::id()))
+            .exists());
+    }
+
+    #[test]
+    fn a_missing_directory_fails() {
+        let dir = tempfile::tempdir().unwrap();
+        let checks = diagnose(&dir.path().join("missing"), Some(&dir.path().join("none")));
+        assert_eq!(checks.len(), 2);
+        assert_eq!(checks[0].status, Status::Fail);
+        assert_eq!(checks[1].status, Status::Pass);
+    }
+}
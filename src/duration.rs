@@ -0,0 +1,90 @@
+//! Parsing of human-friendly durations such as `30d` or `2h30m`.
+
+use std::time::Duration;
+
+/// Parses a duration made of one or more `<number><unit>` components.
+///
+/// Supported units are `s` (seconds), `m` (minutes), `h` (hours), `d` (days)
+/// and `w` (weeks). Components are added together, so `1h30m` is ninety
+/// minutes.
+pub fn parse_duration(text: &str) -> Result<Duration, String> {
+    let invalid = || format!("Invalid duration '{text}', expected e.g. 30d, 12h or 2w");
+
+    let mut total: u64 = 0;
+    let mut rest = text.trim();
+    if rest.is_empty() {
+        return Err(invalid());
+    }
+
+    while !rest.is_empty() {
+        let digits = rest
+            .find(|c: char| !c.is_ascii_digit())
+            .ok_or_else(invalid)?;
+        if digits == 0 {
+            return Err(invalid());
+        }
+        let value: u64 = rest[..digits].parse().map_err(|_| invalid())?;
+
+        let unit = rest[digits..].chars().next().ok_or_else(invalid)?;
+        let seconds = match unit {
+            's' => 1,
+            'm' => 60,
+            'h' => 60 * 60,
+            'd' => 24 * 60 * 60,
+            'w' => 7 * 24 * 60 * 60,
+            _ => return Err(invalid()),
+        };
+
+        total = value
+            .checked_mul(seconds)
+            .and_then(|seconds| total.checked_add(seconds))
+            .ok_or_else(invalid)?;
+        rest = &rest[digits + unit.len_utf8()..];
+    }
+
+    Ok(Duration::from_secs(total))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HOUR: u64 = 60 * 60;
+    const DAY: u64 = 24 * HOUR;
+
+    #[test]
+    fn parses_single_units() {
+        assert_eq!(parse_duration("45s"), Ok(Duration::from_secs(45)));
+        assert_eq!(parse_duration("10m"), Ok(Duration::from_secs(600)));
+        assert_eq!(parse_duration("12h"), Ok(Duration::from_secs(12 * HOUR)));
+        assert_eq!(parse_duration("30d"), Ok(Duration::from_secs(30 * DAY)));
+        assert_eq!(parse_duration("2w"), Ok(Duration::from_secs(14 * DAY)));
+    }
+
+    #[test]
+    fn adds_compound_components() {
+        assert_eq!(
+            parse_duration("2h30m"),
+            Ok(Duration::from_secs(2 * HOUR + 1800))
+        );
+        assert_eq!(parse_duration("1w1d"), Ok(Duration::from_secs(8 * DAY)));
+    }
+
+    #[test]
+    fn accepts_zero() {
+        assert_eq!(parse_duration("0d"), Ok(Duration::ZERO));
+    }
+
+    #[test]
+    fn rejects_malformed_input() {
+        for text in ["", "d", "30", "30x", "-1d", "1.5h", "h30", "30d5"] {
+            assert!(parse_duration(text).is_err(), "{text:?} should be rejected");
+        }
+    }
+
+    #[test]
+    fn rejects_overflow() {
+        assert!(parse_duration("99999999999999999999w").is_err());
+        assert!(parse_duration(&format!("{}w", u64::MAX / 2)).is_err());
+    }
+}
@@ -0,0 +1,502 @@
+//! Errors reported by backup and restore operations.
+
+use std::error::Error;
+use std::fmt;
+use std::io;
+use std::path::PathBuf;
+use std::process;
+
+use crate::writer;
+
+/// An error raised while backing up or restoring, carrying the path involved.
+#[derive(Debug)]
+pub enum BackupError {
+    /// The path does not exist.
+    NotFound(PathBuf),
+    /// The destination exists and overwriting was not requested.
+    AlreadyExists(PathBuf),
+    /// The file name of the path cannot be used to name a backup.
+    InvalidName(PathBuf),
+    /// The path is a symbolic link to a nonexistent path and cannot be followed.
+    DanglingLink(PathBuf),
+    /// The path is a symbolic link leading back to one of its ancestors.
+    SymlinkLoop(PathBuf),
+    /// The path is a special file, such as a named pipe, a socket or a
+    /// device, that was not asked to be recorded.
+    SpecialFile(PathBuf),
+    /// The filesystem of the directory at the path has less free space than
+    /// the backup needs, both in bytes.
+    NoSpace {
+        path: PathBuf,
+        needed: u64,
+        free: u64,
+    },
+    /// The path does not follow the backup naming convention.
+    NotABackup(PathBuf),
+    /// The target is the source itself or lies inside it, so the backup would
+    /// copy itself. Both paths are canonical.
+    TargetInsideSource { source: PathBuf, target: PathBuf },
+    /// A command line option has an invalid value.
+    InvalidOption(String),
+    /// Reading the path or its metadata failed.
+    ReadFailed { path: PathBuf, source: io::Error },
+    /// Creating the destination failed.
+    CreateFailed { path: PathBuf, source: io::Error },
+    /// Copying data between two paths failed.
+    CopyFailed {
+        from: PathBuf,
+        to: PathBuf,
+        source: io::Error,
+    },
+    /// Setting the mode or timestamps of a copy failed.
+    MetadataFailed { path: PathBuf, source: io::Error },
+    /// Removing an existing destination failed.
+    RemoveFailed { path: PathBuf, source: io::Error },
+    /// Extracting an archive failed.
+    ExtractFailed { path: PathBuf, source: io::Error },
+    /// The directory at the path holds more files than the limit given.
+    TooManyFiles { path: PathBuf, limit: u64 },
+    /// Some entries of a recursive copy failed and were reported individually.
+    PartialCopy { path: PathBuf, failures: usize },
+    /// Some backups in a directory could not be pruned and were reported individually.
+    PruneFailed { path: PathBuf, failures: usize },
+    /// The backup has no metadata file recording its original path.
+    MetaMissing(PathBuf),
+    /// The backup has no checksum manifest to verify it against.
+    ChecksumMissing(PathBuf),
+    /// Some files of a backup do not match their checksum and were reported individually.
+    VerifyFailed { path: PathBuf, failures: usize },
+    /// No backup of the file or directory at the path was found.
+    NoBackup(PathBuf),
+    /// Backups of several sources share the name, so which one is meant is unknown.
+    AmbiguousBackup {
+        name: PathBuf,
+        sources: Vec<PathBuf>,
+    },
+    /// No entry of the backup matches the pattern; the closest paths found
+    /// are suggested instead.
+    NoMatch {
+        pattern: String,
+        suggestions: Vec<PathBuf>,
+    },
+    /// The target of a mirror at the path is not a mirror of the same source,
+    /// and starting one there was not requested.
+    NotAMirror(PathBuf),
+    /// Nothing changed since the previous backup at the path, so no backup
+    /// was created.
+    Unchanged(PathBuf),
+    /// Another backup of the same source into the same target holds the lock
+    /// file at the path, from the process with the PID if known.
+    Locked { path: PathBuf, pid: Option<u32> },
+    /// The backup was interrupted, see [`crate::interrupt`], and what it had
+    /// written was removed.
+    Interrupted,
+    /// Entries restored from the backup at the path do not match its checksum
+    /// manifest, listed as in the manifest.
+    Corrupted {
+        path: PathBuf,
+        entries: Vec<PathBuf>,
+    },
+    /// No existing path matches the wildcard pattern given as a source.
+    GlobEmpty(String),
+    /// Some of the sources of a backup failed and were reported individually.
+    SourcesFailed { failures: usize, total: usize },
+    /// Some of the targets of a backup failed and were reported individually.
+    TargetsFailed { failures: usize, total: usize },
+    /// Every operation recorded in the journal was already undone.
+    NothingToUndo,
+    /// The operation involving the path cannot be undone, for the reason given.
+    CannotUndo { path: PathBuf, reason: &'static str },
+    /// The configuration file at the path is invalid at the line given.
+    Config {
+        path: PathBuf,
+        line: usize,
+        message: String,
+    },
+    /// None of the identities given, as many as counted, decrypts the
+    /// encrypted archive at the path.
+    NoIdentity { path: PathBuf, tried: usize },
+    /// The archive at the path is encrypted with a passphrase and none was
+    /// given.
+    NoPassphrase(PathBuf),
+    /// The archive at the path is encrypted with another passphrase than the
+    /// one given.
+    WrongPassphrase(PathBuf),
+    /// The backup at the path has no signature, while one was required.
+    SignatureMissing(PathBuf),
+    /// The signature of the backup at the path does not check, for the
+    /// reason given.
+    BadSignature { path: PathBuf, reason: &'static str },
+    /// Parts of the split archive at the path are missing.
+    MissingParts { path: PathBuf, parts: Vec<PathBuf> },
+    /// The notification command could not run or exited with a failure.
+    NotifyFailed { command: String, source: io::Error },
+    /// Neither journald nor a syslog daemon could be reached.
+    SyslogFailed(io::Error),
+}
+
+/// Exit status of the command line tool, which scripts can rely on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ExitCode {
+    /// 0: the operation succeeded.
+    Success,
+    /// 1: the operation failed.
+    Failure,
+    /// 2: the command line or the configuration file is invalid.
+    Usage,
+    /// 3: there was nothing to do, such as a backup skipped by
+    /// `--skip-unchanged`.
+    Skipped,
+    /// 4: the operation went through, but some entries, sources or targets
+    /// failed.
+    Partial,
+    /// 5: a backup did not pass verification: its checksums or signature do
+    /// not match, or it is corrupted.
+    VerifyFailed,
+    /// 6: another backup of the same source into the same target was
+    /// running.
+    Locked,
+    /// 130: the operation was interrupted, as for a command killed by
+    /// SIGINT.
+    Interrupted,
+}
+
+impl ExitCode {
+    /// Returns the numeric exit status.
+    pub fn code(self) -> i32 {
+        match self {
+            ExitCode::Success => 0,
+            ExitCode::Failure => 1,
+            ExitCode::Usage => 2,
+            ExitCode::Skipped => 3,
+            ExitCode::Partial => 4,
+            ExitCode::VerifyFailed => 5,
+            ExitCode::Locked => 6,
+            ExitCode::Interrupted => 130,
+        }
+    }
+
+    /// Checks whether the operation succeeded, which a skipped one did as
+    /// there was nothing to do.
+    pub fn is_success(self) -> bool {
+        matches!(self, ExitCode::Success | ExitCode::Skipped)
+    }
+}
+
+impl fmt::Display for ExitCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.code())
+    }
+}
+
+impl From<ExitCode> for process::ExitCode {
+    fn from(code: ExitCode) -> Self {
+        // Every status fits in the byte an exit status is.
+        process::ExitCode::from(code.code() as u8)
+    }
+}
+
+impl BackupError {
+    /// Exit status the command line tool should report for this error, see
+    /// [`ExitCode`].
+    pub fn exit_code(&self) -> ExitCode {
+        match self {
+            BackupError::InvalidOption(_) | BackupError::Config { .. } => ExitCode::Usage,
+            BackupError::Unchanged(_) => ExitCode::Skipped,
+            BackupError::PartialCopy { .. } | BackupError::PruneFailed { .. } => ExitCode::Partial,
+            BackupError::SourcesFailed { failures, total }
+            | BackupError::TargetsFailed { failures, total }
+                if failures < total =>
+            {
+                ExitCode::Partial
+            }
+            BackupError::VerifyFailed { .. }
+            | BackupError::Corrupted { .. }
+            | BackupError::SignatureMissing(_)
+            | BackupError::BadSignature { .. } => ExitCode::VerifyFailed,
+            BackupError::Locked { .. } => ExitCode::Locked,
+            BackupError::Interrupted => ExitCode::Interrupted,
+            _ => ExitCode::Failure,
+        }
+    }
+
+    /// Stable identifier of the kind of error, reported in JSON output.
+    pub fn code(&self) -> &'static str {
+        match self {
+            BackupError::NotFound(_) => "not_found",
+            BackupError::AlreadyExists(_) => "already_exists",
+            BackupError::InvalidName(_) => "invalid_name",
+            BackupError::DanglingLink(_) => "dangling_link",
+            BackupError::SymlinkLoop(_) => "symlink_loop",
+            BackupError::SpecialFile(_) => "special_file",
+            BackupError::NoSpace { .. } => "no_space",
+            BackupError::NotABackup(_) => "not_a_backup",
+            BackupError::TargetInsideSource { .. } => "target_inside_source",
+            BackupError::InvalidOption(_) => "invalid_option",
+            BackupError::ReadFailed { .. } => "read_failed",
+            BackupError::CreateFailed { .. } => "create_failed",
+            BackupError::CopyFailed { .. } => "copy_failed",
+            BackupError::MetadataFailed { .. } => "metadata_failed",
+            BackupError::RemoveFailed { .. } => "remove_failed",
+            BackupError::ExtractFailed { .. } => "extract_failed",
+            BackupError::TooManyFiles { .. } => "too_many_files",
+            BackupError::PartialCopy { .. } => "partial_copy",
+            BackupError::PruneFailed { .. } => "prune_failed",
+            BackupError::MetaMissing(_) => "meta_missing",
+            BackupError::ChecksumMissing(_) => "checksum_missing",
+            BackupError::VerifyFailed { .. } => "verify_failed",
+            BackupError::NoBackup(_) => "no_backup",
+            BackupError::AmbiguousBackup { .. } => "ambiguous_backup",
+            BackupError::NoMatch { .. } => "no_match",
+            BackupError::NotAMirror(_) => "not_a_mirror",
+            BackupError::Unchanged(_) => "unchanged",
+            BackupError::Locked { .. } => "locked",
+            BackupError::Interrupted => "interrupted",
+            BackupError::Corrupted { .. } => "corrupted",
+            BackupError::GlobEmpty(_) => "glob_empty",
+            BackupError::SourcesFailed { .. } => "sources_failed",
+            BackupError::TargetsFailed { .. } => "targets_failed",
+            BackupError::NothingToUndo => "nothing_to_undo",
+            BackupError::CannotUndo { .. } => "cannot_undo",
+            BackupError::Config { .. } => "config",
+            BackupError::NoIdentity { .. } => "no_identity",
+            BackupError::NoPassphrase(_) => "no_passphrase",
+            BackupError::WrongPassphrase(_) => "wrong_passphrase",
+            BackupError::SignatureMissing(_) => "signature_missing",
+            BackupError::BadSignature { .. } => "bad_signature",
+            BackupError::MissingParts { .. } => "missing_parts",
+            BackupError::NotifyFailed { .. } => "notify_failed",
+            BackupError::SyslogFailed(_) => "syslog_failed",
+        }
+    }
+}
+
+impl fmt::Display for BackupError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BackupError::NotFound(path) => {
+                write!(f, "'{}': No such file or directory", path.display())
+            }
+            BackupError::AlreadyExists(path) => write!(
+                f,
+                "'{}': Target already exists, use --force to overwrite it",
+                path.display()
+            ),
+            BackupError::InvalidName(path) => write!(f, "'{}': Invalid file name", path.display()),
+            BackupError::DanglingLink(path) => write!(
+                f,
+                "'{}': Symbolic link points to a nonexistent path",
+                path.display()
+            ),
+            BackupError::SymlinkLoop(path) => write!(
+                f,
+                "'{}': Symbolic link loop detected, skipping",
+                path.display()
+            ),
+            BackupError::SpecialFile(path) => write!(
+                f,
+                "'{}': Special file, use --special-files record to back it up",
+                path.display()
+            ),
+            BackupError::NoSpace { path, needed, free } => write!(
+                f,
+                "Not enough space, need {}, only {} free on {}, use --no-space-check to back up anyway",
+                writer::human_bytes(*needed),
+                writer::human_bytes(*free),
+                path.display()
+            ),
+            BackupError::NotABackup(path) => write!(f, "'{}': Not a backup", path.display()),
+            BackupError::TargetInsideSource { source, target } => write!(
+                f,
+                "'{}': Cannot back up into itself, the target '{}' is the source or inside it",
+                source.display(),
+                target.display()
+            ),
+            BackupError::InvalidOption(message) => f.write_str(message),
+            BackupError::ReadFailed { path, source } => {
+                write!(f, "'{}': {source}", path.display())
+            }
+            BackupError::CreateFailed { path, source } => {
+                write!(f, "'{}': Could not create: {source}", path.display())
+            }
+            BackupError::CopyFailed { from, to, source } => {
+                write!(f, "'{}' -> '{}': {source}", from.display(), to.display())
+            }
+            BackupError::MetadataFailed { path, source } => {
+                write!(f, "'{}': Could not set metadata: {source}", path.display())
+            }
+            BackupError::RemoveFailed { path, source } => {
+                write!(f, "'{}': Could not remove: {source}", path.display())
+            }
+            BackupError::ExtractFailed { path, source } => {
+                write!(
+                    f,
+                    "'{}': Could not extract archive: {source}",
+                    path.display()
+                )
+            }
+            BackupError::TooManyFiles { path, limit } => write!(
+                f,
+                "'{}': More than {limit} files, the limit set with --max-files",
+                path.display()
+            ),
+            BackupError::PartialCopy { path, failures } => write!(
+                f,
+                "'{}': {failures} entries could not be copied",
+                path.display()
+            ),
+            BackupError::PruneFailed { path, failures } => write!(
+                f,
+                "'{}': {failures} backups could not be removed",
+                path.display()
+            ),
+            BackupError::MetaMissing(path) => write!(
+                f,
+                "'{}': No metadata file found, the original path is unknown",
+                path.display()
+            ),
+            BackupError::ChecksumMissing(path) => {
+                write!(f, "'{}': No checksum manifest found", path.display())
+            }
+            BackupError::VerifyFailed { path, failures } => write!(
+                f,
+                "'{}': {failures} files do not match their checksum",
+                path.display()
+            ),
+            BackupError::NoBackup(path) => write!(f, "'{}': No backup found", path.display()),
+            BackupError::AmbiguousBackup { name, sources } => {
+                write!(
+                    f,
+                    "'{}': Backups of several sources share this name, give the full path of one of:",
+                    name.display()
+                )?;
+                for source in sources {
+                    write!(f, "\n  {}", source.display())?;
+                }
+                Ok(())
+            }
+            BackupError::NoMatch {
+                pattern,
+                suggestions,
+            } => {
+                write!(f, "'{pattern}': No such path in the backup")?;
+                if !suggestions.is_empty() {
+                    f.write_str(", did you mean:")?;
+                }
+                for path in suggestions {
+                    write!(f, "\n  {}", path.display())?;
+                }
+                Ok(())
+            }
+            BackupError::NotAMirror(path) => write!(
+                f,
+                "'{}': Not a mirror of this source, use --init to start one there",
+                path.display()
+            ),
+            BackupError::Unchanged(path) => write!(
+                f,
+                "'{}': Source unchanged since this backup, skipping",
+                path.display()
+            ),
+            BackupError::Locked { path, pid } => {
+                write!(f, "'{}': Locked by another backup", path.display())?;
+                if let Some(pid) = pid {
+                    write!(f, " (process {pid})")?;
+                }
+                write!(f, ", use --wait-lock to wait for it")
+            }
+            BackupError::Interrupted => write!(f, "Backup aborted, partial artifacts removed"),
+            BackupError::Corrupted { path, entries } => {
+                write!(
+                    f,
+                    "'{}': Restored entries do not match the checksums of the backup:",
+                    path.display()
+                )?;
+                for entry in entries {
+                    write!(f, "\n  {}", entry.display())?;
+                }
+                Ok(())
+            }
+            BackupError::Config {
+                path,
+                line,
+                message,
+            } => write!(f, "'{}', line {line}: {message}", path.display()),
+            BackupError::GlobEmpty(pattern) => {
+                write!(f, "'{pattern}': No path matches the pattern")
+            }
+            BackupError::SourcesFailed { failures, total } => {
+                write!(f, "{failures} of {total} sources could not be backed up")
+            }
+            BackupError::TargetsFailed { failures, total } => {
+                write!(f, "{failures} of {total} targets could not be backed up to")
+            }
+            BackupError::NothingToUndo => f.write_str("No operation left to undo"),
+            BackupError::CannotUndo { path, reason } => {
+                write!(f, "'{}': Cannot undo, {reason}", path.display())
+            }
+            BackupError::NoIdentity { path, tried: 0 } => write!(
+                f,
+                "'{}': Archive is encrypted, pass the key file with --identity or AGE_IDENTITY",
+                path.display()
+            ),
+            BackupError::NoIdentity { path, tried } => write!(
+                f,
+                "'{}': Archive is encrypted to none of the {tried} identities given",
+                path.display()
+            ),
+            BackupError::NoPassphrase(path) => write!(
+                f,
+                "'{}': Archive is encrypted with a passphrase, pass it with --passphrase-file or BACKUP_PASSPHRASE",
+                path.display()
+            ),
+            BackupError::WrongPassphrase(path) => write!(
+                f,
+                "'{}': Wrong passphrase, the archive was encrypted with another one",
+                path.display()
+            ),
+            BackupError::SignatureMissing(path) => write!(
+                f,
+                "'{}': Backup is not signed",
+                path.display()
+            ),
+            BackupError::BadSignature { path, reason } => write!(
+                f,
+                "'{}': Bad signature, {reason}",
+                path.display()
+            ),
+            BackupError::MissingParts { path, parts } => {
+                let names: Vec<_> = parts
+                    .iter()
+                    .map(|part| part.file_name().unwrap_or_default().to_string_lossy())
+                    .collect();
+                write!(
+                    f,
+                    "'{}': Parts of the split archive are missing: {}",
+                    path.display(),
+                    names.join(", ")
+                )
+            }
+            BackupError::NotifyFailed { command, source } => {
+                write!(f, "Notification command '{command}' failed: {source}")
+            }
+            BackupError::SyslogFailed(source) => write!(f, "Cannot log to syslog: {source}"),
+        }
+    }
+}
+
+impl Error for BackupError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            BackupError::ReadFailed { source, .. }
+            | BackupError::CreateFailed { source, .. }
+            | BackupError::CopyFailed { source, .. }
+            | BackupError::MetadataFailed { source, .. }
+            | BackupError::RemoveFailed { source, .. }
+            | BackupError::ExtractFailed { source, .. }
+            | BackupError::NotifyFailed { source, .. }
+            | BackupError::SyslogFailed(source) => Some(source),
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,296 @@
+//! Exclusion of entries from directory backups by glob patterns.
+
+use std::fs;
+use std::io::{self, Read};
+use std::path::Path;
+
+use crate::error::BackupError;
+use crate::pattern;
+
+/// Name of the ignore files read in the directories being backed up.
+pub const IGNORE_FILE: &str = ".backupignore";
+
+/// Directories of version control systems, left out with `--exclude-vcs`.
+pub const VCS_DIRS: [&str; 3] = [".git", ".hg", ".svn"];
+
+/// Name of the file marking a directory as a cache, see
+/// <https://bford.info/cachedir/>.
+pub const CACHEDIR_TAG: &str = "CACHEDIR.TAG";
+
+/// First bytes of a valid [`CACHEDIR_TAG`] file.
+const CACHEDIR_SIGNATURE: &[u8] = b"Signature: 8a477f597d28d172789f06886806bc55";
+
+/// A set of patterns excluding entries from a directory backup.
+///
+/// Patterns are matched against paths relative to the source root, using `/`
+/// as separator. `*` and `?` match within a single component and `**` matches
+/// any number of components. A pattern without a `/` matches an entry of that
+/// name at any depth, while a pattern containing one is anchored at the root.
+/// A trailing `/` restricts the pattern to directories, and a leading `!`
+/// negates the pattern, including again the entries it matches. When several
+/// patterns match an entry, the last one decides.
+///
+/// ```
+/// use std::path::Path;
+///
+/// use backup::exclude::Excludes;
+///
+/// let mut excludes = Excludes::new();
+/// excludes.add("target/");
+/// excludes.add("src/**/*.tmp");
+/// excludes.add("!src/keep.tmp");
+/// assert!(excludes.is_excluded(Path::new("crates/cli/target"), true));
+/// assert!(!excludes.is_excluded(Path::new("target"), false));
+/// assert!(excludes.is_excluded(Path::new("src/a/b.tmp"), false));
+/// assert!(!excludes.is_excluded(Path::new("src/keep.tmp"), false));
+/// assert!(!excludes.is_excluded(Path::new("b.tmp"), false));
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct Excludes {
+    patterns: Vec<Exclude>,
+}
+
+/// A single parsed exclude pattern.
+#[derive(Debug, Clone)]
+struct Exclude {
+    /// Pattern matched against the whole relative path.
+    pattern: String,
+    /// Whether the pattern only matches directories.
+    directory_only: bool,
+    /// Whether matching entries are included rather than excluded.
+    negated: bool,
+}
+
+impl Excludes {
+    /// Creates an empty set, excluding nothing.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a pattern to the set.
+    pub fn add(&mut self, pattern: &str) {
+        let (pattern, negated) = match pattern.strip_prefix('!') {
+            Some(pattern) => (pattern, true),
+            None => (pattern, false),
+        };
+        let (pattern, directory_only) = match pattern.strip_suffix('/') {
+            Some(pattern) => (pattern, true),
+            None => (pattern, false),
+        };
+        let pattern = match pattern.strip_prefix('/') {
+            Some(anchored) => anchored.to_owned(),
+            None if pattern.contains('/') => pattern.to_owned(),
+            None => format!("**/{pattern}"),
+        };
+
+        self.patterns.push(Exclude {
+            pattern,
+            directory_only,
+            negated,
+        });
+    }
+
+    /// Reads the patterns listed in the file at `path`, one per line.
+    ///
+    /// Blank lines and lines starting with `#` are ignored.
+    pub fn read(path: &Path) -> Result<Vec<String>, BackupError> {
+        let contents = fs::read_to_string(path).map_err(|source| BackupError::ReadFailed {
+            path: path.to_path_buf(),
+            source,
+        })?;
+
+        Ok(patterns(&contents).map(str::to_owned).collect())
+    }
+
+    /// Reads the ignore file in `dir`, returning `None` if there is none.
+    pub(crate) fn read_ignore_file(dir: &Path) -> io::Result<Option<Self>> {
+        let contents = match fs::read_to_string(dir.join(IGNORE_FILE)) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e),
+        };
+
+        let mut excludes = Excludes::new();
+        for pattern in patterns(&contents) {
+            excludes.add(pattern);
+        }
+        Ok(Some(excludes))
+    }
+
+    /// Checks whether the set contains no pattern.
+    pub fn is_empty(&self) -> bool {
+        self.patterns.is_empty()
+    }
+
+    /// Checks whether the entry at `relative`, a path relative to the source
+    /// root, is excluded.
+    pub fn is_excluded(&self, relative: &Path, is_dir: bool) -> bool {
+        self.decide(relative, is_dir).unwrap_or(false)
+    }
+
+    /// Returns whether the last pattern matching `relative` excludes it, or
+    /// `None` if no pattern matches it.
+    fn decide(&self, relative: &Path, is_dir: bool) -> Option<bool> {
+        if self.patterns.is_empty() {
+            return None;
+        }
+
+        let relative = relative.to_string_lossy().replace('\\', "/");
+        self.patterns
+            .iter()
+            .rev()
+            .find(|exclude| {
+                (is_dir || !exclude.directory_only)
+                    && pattern::matches_path(&exclude.pattern, &relative)
+            })
+            .map(|exclude| !exclude.negated)
+    }
+}
+
+/// Checks whether the directory at `dir` holds a [`CACHEDIR_TAG`] file
+/// starting with the signature of the specification, marking it as a cache
+/// that need not be backed up.
+pub fn is_cache_dir(dir: &Path) -> bool {
+    let mut signature = [0; CACHEDIR_SIGNATURE.len()];
+    fs::File::open(dir.join(CACHEDIR_TAG))
+        .and_then(|mut file| file.read_exact(&mut signature))
+        .is_ok_and(|()| signature == CACHEDIR_SIGNATURE)
+}
+
+/// Lists the patterns in the contents of an exclude file, skipping blank lines
+/// and `#` comments.
+fn patterns(contents: &str) -> impl Iterator<Item = &str> {
+    contents
+        .lines()
+        .map(str::trim_end)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+}
+
+/// Checks whether the entry at `relative` is ignored by the ignore files
+/// found along its path.
+///
+/// `files` lists the directories containing an ignore file, relative to the
+/// root like `relative`, along with their patterns, from the shallowest to
+/// the deepest. Each file matches paths relative to its own directory, and
+/// the deepest file with a pattern matching the entry decides.
+pub(crate) fn is_ignored<'a>(
+    files: impl DoubleEndedIterator<Item = (&'a Path, &'a Excludes)>,
+    relative: &Path,
+    is_dir: bool,
+) -> bool {
+    files
+        .rev()
+        .find_map(|(dir, excludes)| {
+            let relative = relative.strip_prefix(dir).ok()?;
+            excludes.decide(relative, is_dir)
+        })
+        .unwrap_or(false)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn excludes(patterns: &[&str]) -> Excludes {
+        let mut excludes = Excludes::new();
+        for pattern in patterns {
+            excludes.add(pattern);
+        }
+        excludes
+    }
+
+    #[test]
+    fn cache_dirs_need_the_signature() {
+        let tmp = tempfile::TempDir::new().unwrap();
+        assert!(!is_cache_dir(tmp.path()));
+
+        let tag = tmp.path().join(CACHEDIR_TAG);
+        fs::write(&tag, "Signature: 8a477f597d28d172789f06886806bc5").unwrap();
+        assert!(!is_cache_dir(tmp.path()));
+        fs::write(&tag, "signature: 8a477f597d28d172789f06886806bc55").unwrap();
+        assert!(!is_cache_dir(tmp.path()));
+
+        fs::write(&tag, "Signature: 8a477f597d28d172789f06886806bc55").unwrap();
+        assert!(is_cache_dir(tmp.path()));
+        fs::write(
+            &tag,
+            "Signature: 8a477f597d28d172789f06886806bc55\n# This file is a cache tag.\n",
+        )
+        .unwrap();
+        assert!(is_cache_dir(tmp.path()));
+    }
+
+    #[test]
+    fn last_matching_pattern_wins() {
+        let set = excludes(&["*.log", "!keep.log"]);
+        assert!(set.is_excluded(Path::new("debug.log"), false));
+        assert!(!set.is_excluded(Path::new("logs/keep.log"), false));
+
+        let set = excludes(&["!keep.log", "*.log"]);
+        assert!(set.is_excluded(Path::new("keep.log"), false));
+    }
+
+    #[test]
+    fn unmatched_entries_are_undecided() {
+        let set = excludes(&["*.log", "!keep.log"]);
+        assert_eq!(set.decide(Path::new("main.rs"), false), None);
+        assert_eq!(set.decide(Path::new("keep.log"), false), Some(false));
+    }
+
+    #[test]
+    fn directory_patterns_skip_files() {
+        let set = excludes(&["build/"]);
+        assert!(set.is_excluded(Path::new("build"), true));
+        assert!(!set.is_excluded(Path::new("build"), false));
+    }
+
+    #[test]
+    fn deeper_ignore_files_override_shallower_ones() {
+        let root = excludes(&["*.log"]);
+        let nested = excludes(&["!*.log", "debug.log"]);
+        let files = [(Path::new(""), &root), (Path::new("app"), &nested)];
+
+        assert!(is_ignored(files.into_iter(), Path::new("top.log"), false));
+        assert!(!is_ignored(
+            files.into_iter(),
+            Path::new("app/info.log"),
+            false
+        ));
+        assert!(is_ignored(
+            files.into_iter(),
+            Path::new("app/debug.log"),
+            false
+        ));
+        assert!(is_ignored(files.into_iter(), Path::new("lib/x.log"), false));
+    }
+
+    #[test]
+    fn shallower_files_decide_when_deeper_ones_do_not_match() {
+        let root = excludes(&["*.tmp"]);
+        let nested = excludes(&["*.log"]);
+        let files = [(Path::new(""), &root), (Path::new("app"), &nested)];
+
+        assert!(is_ignored(files.into_iter(), Path::new("app/a.tmp"), false));
+        assert!(!is_ignored(files.into_iter(), Path::new("app/a.rs"), false));
+    }
+
+    #[test]
+    fn nested_patterns_are_relative_to_their_directory() {
+        let nested = excludes(&["/out/"]);
+        let files = [(Path::new("app"), &nested)];
+
+        assert!(is_ignored(files.into_iter(), Path::new("app/out"), true));
+        assert!(!is_ignored(files.into_iter(), Path::new("out"), true));
+        assert!(!is_ignored(
+            files.into_iter(),
+            Path::new("app/src/out"),
+            true
+        ));
+    }
+
+    #[test]
+    fn comments_and_blank_lines_are_skipped() {
+        let lines: Vec<_> = patterns("# comment\n\n*.log  \n!keep.log\n").collect();
+        assert_eq!(lines, ["*.log", "!keep.log"]);
+    }
+}
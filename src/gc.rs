@@ -0,0 +1,226 @@
+//! Collection of the files backups leave behind in a backup directory.
+//!
+//! A backup interrupted by a crash leaves its hidden `.<name>.partial` file
+//! or directory, and may leave its lock file behind, while removing a backup
+//! by hand leaves its checksum manifests, metadata file and signature file.
+//! [`find`] tells them apart from what running backups still use: partial
+//! files are only collected once old enough for no backup to be writing them,
+//! and lock files once stale, that is once the backup holding them is gone.
+//! [`remove`] removes them.
+//!
+//! Only sidecars named after a backup following the naming convention are
+//! collected, so that files of other programs sharing the directory are left
+//! alone.
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use serde::Serialize;
+
+use crate::backup::LOCK_SUFFIX;
+use crate::checksum::Algorithm;
+use crate::error::BackupError;
+use crate::list;
+use crate::lock;
+use crate::meta::META_EXTENSION;
+use crate::restore::{self, BackupName};
+use crate::sign;
+use crate::split;
+use crate::writer::{self, Level};
+
+/// What a file left behind is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Kind {
+    /// The partial file or directory of an interrupted backup.
+    Partial,
+    /// A checksum manifest, metadata file or signature file of a backup that
+    /// is gone.
+    Sidecar,
+    /// A lock file whose owner is gone.
+    Lock,
+}
+
+impl Kind {
+    /// Describes the kind in messages.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Kind::Partial => "partial file",
+            Kind::Sidecar => "orphaned sidecar",
+            Kind::Lock => "stale lock",
+        }
+    }
+}
+
+/// A file left behind, found by [`find`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Garbage {
+    pub path: PathBuf,
+    pub kind: Kind,
+    /// Size in bytes, summed over all files for partial directories.
+    pub size: u64,
+}
+
+/// Outcome of removing the files left behind.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct GcReport {
+    /// Files removed, or that would be removed by a dry run.
+    pub removed: Vec<PathBuf>,
+    /// Number of bytes freed, or that would be freed by a dry run.
+    pub freed: u64,
+}
+
+/// Finds the files left behind in `dir`, sorted by path: partial files last
+/// modified at least `partial_age` ago, sidecars of backups that are gone,
+/// and stale lock files.
+pub fn find(dir: &Path, partial_age: Duration) -> Result<Vec<Garbage>, BackupError> {
+    let read_error = |source| BackupError::ReadFailed {
+        path: dir.to_path_buf(),
+        source,
+    };
+
+    let mut garbage = Vec::new();
+    for entry in fs::read_dir(dir).map_err(read_error)? {
+        let entry = entry.map_err(read_error)?;
+        let path = entry.path();
+        let name = entry.file_name();
+        let name = name.to_string_lossy();
+        let kind = if name.starts_with('.') && name.ends_with(".partial") {
+            let old = entry
+                .metadata()
+                .and_then(|metadata| metadata.modified())
+                .is_ok_and(|modified| modified.elapsed().is_ok_and(|age| age >= partial_age));
+            old.then_some(Kind::Partial)
+        } else if name.starts_with('.') && name.ends_with(LOCK_SUFFIX) {
+            lock::is_stale(&path).unwrap_or(false).then_some(Kind::Lock)
+        } else {
+            is_orphaned(&path).then_some(Kind::Sidecar)
+        };
+        let Some(kind) = kind else {
+            continue;
+        };
+        let size = match fs::symlink_metadata(&path) {
+            Ok(metadata) if metadata.is_dir() => list::tree_size(&path),
+            Ok(metadata) => metadata.len(),
+            Err(_) => 0,
+        };
+        garbage.push(Garbage { path, kind, size });
+    }
+    garbage.sort_by(|a, b| a.path.cmp(&b.path));
+    Ok(garbage)
+}
+
+/// Checks whether `path` is the sidecar of a backup that does not exist, as
+/// a whole file or as the parts of a split archive.
+fn is_orphaned(path: &Path) -> bool {
+    let Some(name) = path.file_name().and_then(|name| name.to_str()) else {
+        return false;
+    };
+    let extensions = Algorithm::ALL
+        .map(Algorithm::extension)
+        .into_iter()
+        .chain([META_EXTENSION, sign::EXTENSION]);
+    let backup = extensions
+        .filter_map(|extension| name.strip_suffix(extension)?.strip_suffix('.'))
+        .find(|backup| BackupName::parse(backup.as_ref()).is_some());
+    backup.is_some_and(|backup| split::metadata(&path.with_file_name(backup)).is_err())
+}
+
+/// Removes the files `garbage`, checking again that lock files are stale.
+///
+/// With `dry_run` set, the files that would be removed are printed but left
+/// in place.
+pub fn remove(garbage: &[Garbage], dry_run: bool) -> Result<GcReport, BackupError> {
+    let mut report = GcReport::default();
+    for Garbage { path, kind, size } in garbage {
+        if dry_run {
+            writer::log(
+                Level::Info,
+                format_args!("would remove {} ({})", path.display(), kind.as_str()),
+            );
+        } else {
+            // A backup may have taken the lock over since it was found.
+            if *kind == Kind::Lock && !lock::is_stale(path).unwrap_or(false) {
+                continue;
+            }
+            match restore::remove(path) {
+                Err(BackupError::RemoveFailed { source, .. })
+                    if source.kind() == io::ErrorKind::NotFound => {}
+                result => result?,
+            }
+            writer::log(
+                Level::Success,
+                format_args!("removed {} ({})", path.display(), kind.as_str()),
+            );
+        }
+        report.removed.push(path.clone());
+        report.freed += size;
+    }
+
+    let verb = if dry_run { "would free" } else { "freed" };
+    writer::log(
+        Level::Info,
+        format_args!("{verb} {}", writer::human_bytes(report.freed)),
+    );
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sidecars_of_existing_backups_are_kept() {
+        let dir = tempfile::tempdir().unwrap();
+        let backup = "notes.txt.2024-05-01_10-00-00.backup";
+        let gone = "todo.txt.2024-05-01_10-00-00.backup";
+        let split = "data.2024-05-01_10-00-00.backup.tar";
+        for name in [
+            backup.to_owned(),
+            format!("{backup}.sha256"),
+            format!("{gone}.meta.json"),
+            format!("{gone}.blake3"),
+            format!("{gone}.sig"),
+            format!("{split}.000"),
+            format!("{split}.sha256"),
+            "checksums.sha256".to_owned(),
+        ] {
+            fs::write(dir.path().join(name), "x").unwrap();
+        }
+
+        let garbage = find(dir.path(), Duration::ZERO).unwrap();
+        let names: Vec<_> = garbage
+            .iter()
+            .map(|garbage| garbage.path.file_name().unwrap().to_str().unwrap())
+            .collect();
+        assert_eq!(
+            names,
+            [
+                format!("{gone}.blake3"),
+                format!("{gone}.meta.json"),
+                format!("{gone}.sig"),
+            ]
+        );
+        assert!(garbage.iter().all(|garbage| garbage.kind == Kind::Sidecar));
+    }
+
+    #[test]
+    fn only_old_partial_files_are_found() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir(dir.path().join(".data.partial")).unwrap();
+        fs::write(dir.path().join(".data.partial/a"), "12345").unwrap();
+
+        assert!(find(dir.path(), Duration::from_secs(3600))
+            .unwrap()
+            .is_empty());
+        let garbage = find(dir.path(), Duration::ZERO).unwrap();
+        assert_eq!(garbage.len(), 1);
+        assert_eq!((garbage[0].kind, garbage[0].size), (Kind::Partial, 5));
+
+        let report = remove(&garbage, false).unwrap();
+        assert_eq!(report.freed, 5);
+        assert!(!dir.path().join(".data.partial").exists());
+    }
+}
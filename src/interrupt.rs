@@ -0,0 +1,84 @@
+//! Cancellation of backups in progress, such as on Ctrl+C.
+//!
+//! Interrupting sets a flag that backups check between files and between
+//! chunks of the files they copy. They then stop, remove what they wrote and
+//! fail with [`BackupError::Interrupted`].
+//!
+//! # Examples
+//!
+//! ```
+//! use backup::interrupt;
+//!
+//! assert!(!interrupt::is_interrupted());
+//! ```
+
+use std::io::{self, Read, Write};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::error::BackupError;
+
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+/// Longest time [`sleep`] goes without checking for an interruption.
+const NAP: Duration = Duration::from_millis(100);
+
+/// Asks the backups in progress, and any started afterwards, to stop.
+///
+/// It only sets a flag, so it may be called from a signal handler.
+pub fn interrupt() {
+    INTERRUPTED.store(true, Ordering::SeqCst);
+}
+
+/// Checks whether [`interrupt`] was called.
+pub fn is_interrupted() -> bool {
+    INTERRUPTED.load(Ordering::SeqCst)
+}
+
+/// Sleeps for `duration` in short naps, returning `false` as soon as
+/// [`interrupt`] is called.
+pub(crate) fn sleep(duration: Duration) -> bool {
+    let end = Instant::now() + duration;
+    while let Some(left) = end.checked_duration_since(Instant::now()) {
+        if is_interrupted() {
+            return false;
+        }
+        thread::sleep(left.min(NAP));
+    }
+    !is_interrupted()
+}
+
+/// Fails with [`BackupError::Interrupted`] if [`interrupt`] was called.
+pub(crate) fn check() -> io::Result<()> {
+    match is_interrupted() {
+        true => Err(io::Error::other(BackupError::Interrupted)),
+        false => Ok(()),
+    }
+}
+
+/// A reader or writer failing with [`BackupError::Interrupted`] once
+/// [`interrupt`] is called, including while blocked in a system call that the
+/// signal interrupted.
+pub(crate) struct Interruptible<T>(pub(crate) T);
+
+impl<R: Read> Read for Interruptible<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        check()?;
+        match self.0.read(buf) {
+            Err(error) if error.kind() == io::ErrorKind::Interrupted => check().and(Err(error)),
+            result => result,
+        }
+    }
+}
+
+impl<W: Write> Write for Interruptible<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        check()?;
+        self.0.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.0.flush()
+    }
+}
@@ -0,0 +1,435 @@
+//! Journal of the backups and restores performed, used to undo them.
+//!
+//! Every operation is appended as one line of JSON to `history.jsonl` in the
+//! data directory of the tool, `$XDG_DATA_HOME/backup` or
+//! `~/.local/share/backup`. Undoing an operation appends an entry of its own,
+//! so the journal is never rewritten.
+
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+use chrono::{DateTime, Local};
+use serde::{Deserialize, Serialize};
+
+use crate::backup::{self, BackupReport};
+use crate::catalog;
+use crate::checksum;
+use crate::config;
+use crate::error::BackupError;
+use crate::meta;
+use crate::restore::{self, RestoreReport};
+use crate::sign;
+use crate::split;
+use crate::walk::Walker;
+use crate::writer::{self, Level};
+
+/// Name of the journal file inside the data directory.
+pub const JOURNAL_NAME: &str = "history.jsonl";
+
+/// Kind of operation recorded in the journal.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Action {
+    /// A backup was created.
+    Backup,
+    /// A backup was restored.
+    Restore,
+    /// The most recent operation not yet undone was undone.
+    Undo,
+}
+
+impl Action {
+    /// Lowercase name of the action, as shown in the history.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Action::Backup => "backup",
+            Action::Restore => "restore",
+            Action::Undo => "undo",
+        }
+    }
+}
+
+/// A file or directory created by an operation, with its state right after.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Artifact {
+    /// Absolute path of the artifact.
+    pub path: PathBuf,
+    /// Size in bytes, summed over all files for directories and over all
+    /// parts for split archives.
+    pub size: u64,
+    /// Last modification time, of the directory itself for directories and
+    /// of the first part for split archives.
+    pub modified: Option<DateTime<Local>>,
+    /// BLAKE3 digest of the path, size and modification time of every entry
+    /// below a directory, which tells files edited in place. Missing for
+    /// files, and for directories recorded by earlier versions.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fingerprint: Option<String>,
+}
+
+impl Artifact {
+    /// Describes the current state of the file or directory at `path`, or of
+    /// the split archive it names, see [`split`].
+    pub fn new(path: &Path) -> Self {
+        let path = absolute(path);
+        let metadata = split::metadata(&path);
+        let (size, fingerprint) = match &metadata {
+            Ok(metadata) if metadata.is_dir() => {
+                let (size, fingerprint) = fingerprint(&path);
+                (size, Some(fingerprint))
+            }
+            Ok(_) if split::is_split(&path) => (split::size(&path), None),
+            Ok(metadata) => (metadata.len(), None),
+            Err(_) => (0, None),
+        };
+        let modified = metadata.and_then(|metadata| metadata.modified()).ok();
+
+        Artifact {
+            path,
+            size,
+            modified: modified.map(DateTime::from),
+            fingerprint,
+        }
+    }
+
+    /// Checks whether the artifact was removed or modified since recorded.
+    fn changed(&self) -> bool {
+        if split::metadata(&self.path).is_err() {
+            return true;
+        }
+        let current = Artifact::new(&self.path);
+        let fingerprint_changed =
+            self.fingerprint.is_some() && current.fingerprint != self.fingerprint;
+        current.size != self.size || current.modified != self.modified || fingerprint_changed
+    }
+}
+
+/// Returns the size of the files below the directory `path`, and the digest
+/// of the path, size and modification time of each of its entries.
+fn fingerprint(path: &Path) -> (u64, String) {
+    let mut entries: Vec<_> = match Walker::new(path, false) {
+        Ok(walker) => walker
+            .filter_map(Result::ok)
+            .map(|entry| {
+                let modified = entry
+                    .metadata
+                    .modified()
+                    .ok()
+                    .and_then(|modified| modified.duration_since(UNIX_EPOCH).ok())
+                    .unwrap_or_default();
+                let size = match entry.metadata.is_dir() {
+                    true => 0,
+                    false => entry.metadata.len(),
+                };
+                (entry.relative, size, modified)
+            })
+            .collect(),
+        Err(_) => Vec::new(),
+    };
+    entries.sort();
+
+    let mut hasher = blake3::Hasher::new();
+    for (relative, size, modified) in &entries {
+        hasher.update(relative.as_os_str().as_encoded_bytes());
+        hasher.update(&[0]);
+        hasher.update(&size.to_le_bytes());
+        hasher.update(&modified.as_nanos().to_le_bytes());
+    }
+    let size = entries.iter().map(|(_, size, _)| size).sum();
+    (size, hasher.finalize().to_hex().to_string())
+}
+
+/// An operation recorded in the journal.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Operation {
+    /// Time the operation was recorded.
+    pub timestamp: DateTime<Local>,
+    /// What was done.
+    pub action: Action,
+    /// Absolute path of what was backed up or restored.
+    pub source: PathBuf,
+    /// Absolute path of the backup or of the restored copy.
+    pub destination: PathBuf,
+    /// What the operation created, which undoing it removes. Empty for
+    /// operations that cannot be undone, such as partial restores.
+    pub artifacts: Vec<Artifact>,
+    /// Safety copy of the destination a restore replaced, which undoing it
+    /// moves back.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub safety: Option<PathBuf>,
+}
+
+impl Operation {
+    /// Describes the backup of `source` summarized by `report`.
+    pub fn backup(source: &Path, report: &BackupReport) -> Self {
+        Operation::new(
+            Action::Backup,
+            source,
+            &report.path,
+            vec![Artifact::new(&report.path)],
+        )
+    }
+
+    /// Describes the restore of `source` summarized by `report`. Restores of
+    /// selected paths into an existing directory are recorded with no
+    /// artifact, as undoing them would remove more than they restored.
+    pub fn restore(source: &Path, report: &RestoreReport, selected_paths: bool) -> Self {
+        let artifacts = match selected_paths {
+            true => Vec::new(),
+            false => vec![Artifact::new(&report.path)],
+        };
+        Operation {
+            safety: report.safety.as_deref().map(absolute),
+            ..Operation::new(Action::Restore, source, &report.path, artifacts)
+        }
+    }
+
+    fn new(action: Action, source: &Path, destination: &Path, artifacts: Vec<Artifact>) -> Self {
+        Operation {
+            timestamp: Local::now(),
+            action,
+            // Stdin, backed up as `-`, is no path.
+            source: match source == Path::new("-") {
+                true => source.to_path_buf(),
+                false => absolute(source),
+            },
+            destination: absolute(destination),
+            artifacts,
+            safety: None,
+        }
+    }
+}
+
+/// Returns the absolute form of `path`, or `path` itself if there is none.
+fn absolute(path: &Path) -> PathBuf {
+    std::path::absolute(path).unwrap_or_else(|_| path.to_path_buf())
+}
+
+/// Returns the path of the journal, `$XDG_DATA_HOME/backup/history.jsonl` or
+/// `~/.local/share/backup/history.jsonl`, or `None` if neither the variable
+/// nor the home directory is set.
+pub fn path() -> Option<PathBuf> {
+    Some(config::data_home()?.join("backup").join(JOURNAL_NAME))
+}
+
+/// Appends `operation` to the journal at `journal`, creating it and its
+/// directory if needed.
+pub fn record(journal: &Path, operation: &Operation) -> Result<(), BackupError> {
+    let mut line = serde_json::to_vec(operation).map_err(io::Error::from);
+    if let Ok(line) = &mut line {
+        line.push(b'\n');
+    }
+
+    let written = line.and_then(|line| {
+        if let Some(dir) = journal.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(journal)?
+            .write_all(&line)
+    });
+    written.map_err(|source| BackupError::CreateFailed {
+        path: journal.to_path_buf(),
+        source,
+    })
+}
+
+/// Reads the operations recorded in the journal at `journal`, oldest first.
+///
+/// A missing journal has no operations, and lines that cannot be parsed are
+/// skipped with a warning.
+pub fn read(journal: &Path) -> Result<Vec<Operation>, BackupError> {
+    let contents = match fs::read(journal) {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(source) => {
+            return Err(BackupError::ReadFailed {
+                path: journal.to_path_buf(),
+                source,
+            })
+        }
+    };
+
+    let mut operations = Vec::new();
+    for (number, line) in contents.split(|&b| b == b'\n').enumerate() {
+        if line.is_empty() {
+            continue;
+        }
+        match serde_json::from_slice(line) {
+            Ok(operation) => operations.push(operation),
+            Err(e) => writer::log(
+                Level::Warning,
+                format_args!("{}:{}: Skipping entry: {e}", journal.display(), number + 1),
+            ),
+        }
+    }
+    Ok(operations)
+}
+
+/// Undoes the most recent operation of the journal at `journal` that was not
+/// undone yet, and records that it was. Returns the undone operation.
+///
+/// Undoing a backup removes it along with its checksum manifest, metadata
+/// file and the link to it as the latest backup. Undoing a restore removes
+/// the restored copy and moves the safety copy of what it replaced back, if
+/// there is one. Nothing is done if an artifact was removed or modified since
+/// the operation, as told by its size and modification time.
+///
+/// # Examples
+///
+/// ```no_run
+/// use backup::journal;
+///
+/// let journal = journal::path().expect("no home directory");
+/// let undone = journal::undo(&journal)?;
+/// println!("undid {} of {}", undone.action.as_str(), undone.source.display());
+/// # Ok::<(), backup::BackupError>(())
+/// ```
+pub fn undo(journal: &Path) -> Result<Operation, BackupError> {
+    let operations = read(journal)?;
+    let operation = pending(&operations).ok_or(BackupError::NothingToUndo)?;
+
+    if operation.artifacts.is_empty() {
+        return Err(BackupError::CannotUndo {
+            path: operation.destination.clone(),
+            reason: "only selected paths were restored",
+        });
+    }
+    if let Some(artifact) = operation
+        .artifacts
+        .iter()
+        .find(|artifact| artifact.changed())
+    {
+        return Err(BackupError::CannotUndo {
+            path: artifact.path.clone(),
+            reason: "it changed since the operation",
+        });
+    }
+    if let Some(safety) = &operation.safety {
+        if fs::symlink_metadata(safety).is_err() {
+            return Err(BackupError::CannotUndo {
+                path: safety.clone(),
+                reason: "the safety copy of what it replaced is gone",
+            });
+        }
+    }
+
+    for artifact in &operation.artifacts {
+        restore::remove(&artifact.path)?;
+        if operation.action == Action::Backup {
+            checksum::remove_manifests(&artifact.path)?;
+            meta::remove(&artifact.path)?;
+            sign::remove(&artifact.path)?;
+            backup::remove_latest(&artifact.path).map_err(|source| BackupError::RemoveFailed {
+                path: artifact.path.clone(),
+                source,
+            })?;
+            let dir = artifact.path.parent().unwrap_or(Path::new("."));
+            catalog::forget(dir, std::slice::from_ref(&artifact.path));
+        }
+        writer::log(
+            Level::Verbose,
+            format_args!("removed {}", artifact.path.display()),
+        );
+    }
+    if let Some(safety) = &operation.safety {
+        fs::rename(safety, &operation.destination).map_err(|e| BackupError::CopyFailed {
+            from: safety.clone(),
+            to: operation.destination.clone(),
+            source: e,
+        })?;
+        writer::log(
+            Level::Verbose,
+            format_args!(
+                "moved {} back to {}",
+                safety.display(),
+                operation.destination.display()
+            ),
+        );
+    }
+
+    let undo = Operation::new(
+        Action::Undo,
+        &operation.source,
+        &operation.destination,
+        Vec::new(),
+    );
+    if let Err(error) = record(journal, &undo) {
+        writer::log(
+            Level::Warning,
+            format_args!("{error}, the undo is not in the history"),
+        );
+    }
+    Ok(operation.clone())
+}
+
+/// Finds the most recent of `operations` not undone yet, each undo entry
+/// cancelling the most recent operation before it that is not undone.
+fn pending(operations: &[Operation]) -> Option<&Operation> {
+    let mut undone = 0;
+    for operation in operations.iter().rev() {
+        match operation.action {
+            Action::Undo => undone += 1,
+            _ if undone > 0 => undone -= 1,
+            _ => return Some(operation),
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn operation(action: Action, source: &str) -> Operation {
+        Operation::new(action, Path::new(source), Path::new("/backups"), Vec::new())
+    }
+
+    #[test]
+    fn undo_entries_cancel_the_latest_pending_operation() {
+        let mut operations = vec![
+            operation(Action::Backup, "/a"),
+            operation(Action::Restore, "/b"),
+        ];
+        assert_eq!(pending(&operations).unwrap().source, Path::new("/b"));
+
+        operations.push(operation(Action::Undo, "/b"));
+        assert_eq!(pending(&operations).unwrap().source, Path::new("/a"));
+
+        operations.push(operation(Action::Backup, "/c"));
+        assert_eq!(pending(&operations).unwrap().source, Path::new("/c"));
+
+        operations.push(operation(Action::Undo, "/c"));
+        operations.push(operation(Action::Undo, "/a"));
+        assert!(pending(&operations).is_none());
+    }
+
+    #[test]
+    fn operations_round_trip_through_the_journal() {
+        let tmp = tempfile::TempDir::new().unwrap();
+        let journal = tmp.path().join("data/backup").join(JOURNAL_NAME);
+        let file = tmp.path().join("hosts");
+        fs::write(&file, "abc").unwrap();
+
+        let recorded = Operation::new(
+            Action::Backup,
+            Path::new("/etc/hosts"),
+            &file,
+            vec![Artifact::new(&file)],
+        );
+        record(&journal, &recorded).unwrap();
+        record(&journal, &operation(Action::Undo, "/etc/hosts")).unwrap();
+
+        let operations = read(&journal).unwrap();
+        assert_eq!(operations.len(), 2);
+        assert_eq!(operations[0], recorded);
+        assert!(!operations[0].artifacts[0].changed());
+
+        fs::write(&file, "abcd").unwrap();
+        assert!(operations[0].artifacts[0].changed());
+    }
+}
@@ -0,0 +1,77 @@
+//! Timestamped backups of files and directories.
+//!
+//! A backup of `hosts` is a copy named `hosts.<timestamp>.backup`, and a
+//! directory may be backed up as a copy of the tree or as a tar archive,
+//! optionally compressed with gzip or zstd. Backups can be restored under
+//! their original name, listed, pruned according to retention rules,
+//! verified against the checksums recorded when they were created and
+//! compared with the current state of their source, and the operations
+//! performed are kept in a journal to undo them.
+//!
+//! The library never prints or exits: operations return a [`Result`], and
+//! progress messages are handed to the logger installed with
+//! [`writer::set_logger`].
+//!
+//! # Examples
+//!
+//! ```no_run
+//! use std::path::Path;
+//!
+//! use backup::backup::BackupOptions;
+//! use backup::restore::RestoreOptions;
+//!
+//! let report = backup::backup(Path::new("notes.txt"), Path::new("."), &BackupOptions::new())?;
+//! std::fs::remove_file("notes.txt").ok();
+//! backup::restore(&report.path, None, &RestoreOptions::new())?;
+//! # Ok::<(), backup::BackupError>(())
+//! ```
+
+pub mod age;
+pub mod archive;
+pub mod backup;
+pub mod catalog;
+pub mod checksum;
+pub mod config;
+pub mod diff;
+pub mod doctor;
+pub mod duration;
+pub mod error;
+pub mod exclude;
+pub mod gc;
+pub mod interrupt;
+pub mod journal;
+pub mod list;
+pub mod logfile;
+pub mod meta;
+pub mod metrics;
+pub mod naming;
+pub mod notify;
+pub mod pattern;
+pub mod pipe;
+pub mod prune;
+pub mod remote;
+pub mod restore;
+pub mod s3;
+pub mod schedule;
+pub mod sign;
+pub mod split;
+pub mod stats;
+pub mod syslog;
+pub mod systemd;
+pub mod target;
+pub mod trash;
+pub mod verify;
+pub mod watch;
+pub mod writer;
+
+mod crypto;
+mod lock;
+mod reflink;
+mod store;
+mod throttle;
+mod walk;
+mod xattrs;
+
+pub use crate::backup::{backup, BackupOptions, BackupReport};
+pub use crate::error::{BackupError, ExitCode};
+pub use crate::restore::{restore, RestoreOptions, RestoreReport};
@@ -0,0 +1,472 @@
+//! Listing of the backups found in a directory.
+
+use std::borrow::Cow;
+use std::collections::BTreeSet;
+use std::ffi::OsStr;
+use std::fs::{self, Metadata};
+use std::io;
+use std::path::{Path, PathBuf};
+
+use chrono::{DateTime, Local, NaiveDateTime};
+use serde::{Deserialize, Serialize};
+
+use crate::archive;
+use crate::catalog;
+use crate::error::BackupError;
+use crate::meta::{self, BackupMeta};
+use crate::naming;
+use crate::pattern;
+use crate::restore::BackupName;
+use crate::s3::Bucket;
+use crate::split;
+use crate::walk::Walker;
+
+/// What a backup consists of.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BackupKind {
+    /// A plain copy of a file.
+    File,
+    /// A copy of a directory tree.
+    Directory,
+    /// A tar archive of a directory tree, possibly compressed.
+    Archive,
+    /// A copy of a symbolic link.
+    Link,
+}
+
+impl BackupKind {
+    /// Lowercase name of the kind, as shown in listings.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            BackupKind::File => "file",
+            BackupKind::Directory => "directory",
+            BackupKind::Archive => "archive",
+            BackupKind::Link => "link",
+        }
+    }
+}
+
+/// A backup found in a directory.
+#[derive(Debug, Serialize)]
+pub struct ListEntry {
+    /// Original name of the backed up file or directory, or the file name
+    /// itself for entries not following the naming convention. Names that
+    /// are not valid UTF-8 are converted lossily.
+    pub name: String,
+    /// Time the backup was created, if the name follows the naming convention.
+    pub timestamp: Option<NaiveDateTime>,
+    /// Total size in bytes, summed over all files for directory backups.
+    pub size: u64,
+    /// What the backup consists of.
+    pub kind: BackupKind,
+    /// Path of the backup.
+    pub path: PathBuf,
+    /// Contents of the metadata file of the backup, if it has a readable one.
+    pub meta: Option<BackupMeta>,
+}
+
+/// Type of an entry inside a backup.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum EntryKind {
+    /// A regular file.
+    File,
+    /// A directory.
+    Directory,
+    /// A symbolic link.
+    Link,
+    /// Anything else, such as a device or a named pipe.
+    Other,
+}
+
+/// An entry inside a backup, as listed by [`contents`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ContentEntry {
+    /// Path of the entry relative to the backup root, which is itself empty.
+    /// For a copy of a single file, this is its original name.
+    pub path: PathBuf,
+    /// Type of the entry.
+    pub kind: EntryKind,
+    /// Size in bytes, zero for directories and links.
+    pub size: u64,
+    /// Permission bits, including the setuid, setgid and sticky bits.
+    pub mode: u32,
+    /// Last modification time, if known.
+    pub modified: Option<DateTime<Local>>,
+    /// Target of a symbolic link, or for a file stored in an archive as a
+    /// hard link, the path of the entry it links to.
+    pub link: Option<PathBuf>,
+}
+
+impl ContentEntry {
+    /// Describes the entry at `path` from its `metadata`, under the
+    /// `relative` path.
+    fn new(relative: PathBuf, path: &Path, metadata: &Metadata) -> Self {
+        let kind = if metadata.is_dir() {
+            EntryKind::Directory
+        } else if metadata.is_symlink() {
+            EntryKind::Link
+        } else if metadata.is_file() {
+            EntryKind::File
+        } else {
+            EntryKind::Other
+        };
+
+        ContentEntry {
+            path: relative,
+            kind,
+            size: if kind == EntryKind::File {
+                metadata.len()
+            } else {
+                0
+            },
+            mode: mode(metadata),
+            modified: metadata.modified().ok().map(DateTime::from),
+            link: fs::read_link(path).ok(),
+        }
+    }
+}
+
+/// Lists the backups in `dir`, grouped by original name and sorted by timestamp.
+///
+/// Only names matching `pattern` are kept when one is given. Entries that do
+/// not follow the backup naming convention are skipped unless `all` is set.
+///
+/// The backups are read from the catalog of `dir` when it matches it, see
+/// [`catalog`], unless `all` is set, as it only records backups.
+pub fn list(dir: &Path, pattern: Option<&str>, all: bool) -> Result<Vec<ListEntry>, BackupError> {
+    let Some(mut entries) = catalog::entries(dir).filter(|_| !all) else {
+        return Ok(sorted(scan(dir, pattern, all)?));
+    };
+    if let Some(pattern) = pattern {
+        entries.retain(|(entry, _)| pattern::matches(pattern, &entry.name));
+    }
+    Ok(sorted(entries))
+}
+
+/// Describes the backups in `dir` as [`list`] does, without the catalog and
+/// unsorted, along with their sequence numbers.
+pub(crate) fn scan(
+    dir: &Path,
+    pattern: Option<&str>,
+    all: bool,
+) -> Result<Vec<(ListEntry, u32)>, BackupError> {
+    let read_error = |source| BackupError::ReadFailed {
+        path: dir.to_path_buf(),
+        source,
+    };
+
+    let mut entries = Vec::new();
+    for entry in fs::read_dir(dir).map_err(read_error)? {
+        let entry = entry.map_err(read_error)?;
+        let file_name = entry.file_name();
+        if naming::is_latest(&file_name) {
+            continue;
+        }
+        let Some(file_name) = backup_name(&file_name) else {
+            continue;
+        };
+        let parsed = BackupName::parse(file_name);
+        if parsed.is_none() && !all {
+            continue;
+        }
+
+        let name = parsed
+            .as_ref()
+            .map_or(file_name, |parsed| &parsed.original)
+            .to_string_lossy();
+        if pattern.is_some_and(|pattern| !pattern::matches(pattern, &name)) {
+            continue;
+        }
+
+        let Ok(entry) = describe(&dir.join(file_name)) else {
+            continue;
+        };
+        let sequence = parsed.map_or(0, |parsed| parsed.sequence);
+        entries.push((entry, sequence));
+    }
+
+    Ok(entries)
+}
+
+/// Returns the file names of the backups in `dir` that [`list`] would
+/// describe, without looking into them. Names that are not valid UTF-8 are
+/// converted lossily.
+pub(crate) fn backup_names(dir: &Path) -> io::Result<BTreeSet<String>> {
+    let mut names = BTreeSet::new();
+    for entry in fs::read_dir(dir)? {
+        let file_name = entry?.file_name();
+        if naming::is_latest(&file_name) {
+            continue;
+        }
+        match backup_name(&file_name) {
+            Some(name) if BackupName::parse(name).is_some() => {
+                names.insert(name.to_string_lossy().into_owned());
+            }
+            _ => {}
+        }
+    }
+    Ok(names)
+}
+
+/// Returns the sequence number of the backup named `file_name` among those
+/// of the same name and second, 0 if it does not follow the convention.
+pub(crate) fn sequence(file_name: &OsStr) -> u32 {
+    BackupName::parse(file_name).map_or(0, |parsed| parsed.sequence)
+}
+
+/// Lists the backups under the prefix `prefix` of an S3 bucket, see
+/// [`crate::s3`], as [`list`] does in a directory.
+///
+/// Every backup is a single object, so directory backups are listed as
+/// archives, and their metadata files are not read.
+///
+/// # Examples
+///
+/// ```no_run
+/// use backup::s3::Bucket;
+///
+/// let prefix = Bucket::parse("s3://backups/web1").unwrap();
+/// for entry in backup::list::list_s3(&prefix, None, false)? {
+///     println!("{} {}", entry.name, entry.path.display());
+/// }
+/// # Ok::<(), backup::BackupError>(())
+/// ```
+pub fn list_s3(
+    prefix: &Bucket,
+    pattern: Option<&str>,
+    all: bool,
+) -> Result<Vec<ListEntry>, BackupError> {
+    let mut entries = Vec::new();
+    for (file_name, size) in prefix.list()? {
+        let parsed = BackupName::parse(OsStr::new(&file_name));
+        if parsed.is_none() && !all {
+            continue;
+        }
+        let name = parsed.as_ref().map_or(file_name.clone(), |parsed| {
+            parsed.original.to_string_lossy().into_owned()
+        });
+        if pattern.is_some_and(|pattern| !pattern::matches(pattern, &name)) {
+            continue;
+        }
+
+        let kind = match parsed
+            .as_ref()
+            .is_some_and(|parsed| parsed.archive.is_some())
+        {
+            true => BackupKind::Archive,
+            false => BackupKind::File,
+        };
+        let entry = ListEntry {
+            name,
+            timestamp: parsed.as_ref().and_then(|parsed| parsed.timestamp),
+            size,
+            kind,
+            path: prefix.join(&file_name).display_path(),
+            meta: None,
+        };
+        entries.push((entry, parsed.map_or(0, |parsed| parsed.sequence)));
+    }
+
+    Ok(sorted(entries))
+}
+
+/// Sorts `entries` by original name and timestamp, then by their sequence
+/// number within the same second.
+fn sorted(mut entries: Vec<(ListEntry, u32)>) -> Vec<ListEntry> {
+    entries.sort_by(|(a, a_sequence), (b, b_sequence)| {
+        (&a.name, a.timestamp.is_none(), a.timestamp, a_sequence).cmp(&(
+            &b.name,
+            b.timestamp.is_none(),
+            b.timestamp,
+            b_sequence,
+        ))
+    });
+    entries.into_iter().map(|(entry, _)| entry).collect()
+}
+
+/// Describes the backup at `path` as [`list`] does, or the one it points to
+/// if a link to the newest backup, see [`resolve_latest`].
+pub fn describe(path: &Path) -> Result<ListEntry, BackupError> {
+    let path = &*resolve_latest(path);
+    let metadata = split::metadata(path).map_err(|source| BackupError::ReadFailed {
+        path: path.to_path_buf(),
+        source,
+    })?;
+    let file_name = path.file_name().unwrap_or(path.as_os_str());
+    let parsed = BackupName::parse(file_name);
+
+    let kind = if metadata.is_dir() {
+        BackupKind::Directory
+    } else if metadata.is_symlink() {
+        BackupKind::Link
+    } else if parsed
+        .as_ref()
+        .is_some_and(|parsed| parsed.archive.is_some())
+        || archive::format(path).is_some()
+    {
+        BackupKind::Archive
+    } else {
+        BackupKind::File
+    };
+    let size = if metadata.is_dir() {
+        tree_size(path)
+    } else if split::is_split(path) {
+        split::size(path)
+    } else {
+        metadata.len()
+    };
+
+    Ok(ListEntry {
+        name: parsed
+            .as_ref()
+            .map_or(file_name, |parsed| &parsed.original)
+            .to_string_lossy()
+            .into_owned(),
+        timestamp: parsed.and_then(|parsed| parsed.timestamp),
+        size,
+        kind,
+        meta: meta::read(path).ok().flatten(),
+        path: path.to_path_buf(),
+    })
+}
+
+/// Returns the backup the link at `path` points to if its name is that of a
+/// link to the newest backup, see [`naming::latest_name`], or `path` itself.
+///
+/// The link to a split archive points to its first part, which is resolved
+/// to the archive, see [`split::archive_of`].
+pub fn resolve_latest(path: &Path) -> Cow<'_, Path> {
+    let is_latest = path.file_name().is_some_and(naming::is_latest);
+    match fs::read_link(path) {
+        Ok(target) if is_latest => Cow::Owned(split::archive_of(
+            &path.parent().unwrap_or(Path::new("")).join(target),
+        )),
+        _ => Cow::Borrowed(path),
+    }
+}
+
+/// Lists the entries inside the backup at `path`, without restoring it.
+///
+/// Directory backups are walked and archives read in order, both starting
+/// with their root. A copy of a single file is listed as one entry named
+/// after the original file.
+///
+/// # Examples
+///
+/// ```no_run
+/// use std::path::Path;
+///
+/// let backup = Path::new("/var/backups/etc.2024-05-01_10-00-00.backup.tar.gz");
+/// for entry in backup::list::contents(backup)? {
+///     println!("{} {}", entry.size, entry.path.display());
+/// }
+/// # Ok::<(), backup::BackupError>(())
+/// ```
+pub fn contents(path: &Path) -> Result<Vec<ContentEntry>, BackupError> {
+    let path = &*resolve_latest(path);
+    let read_error = |source| BackupError::ReadFailed {
+        path: path.to_path_buf(),
+        source,
+    };
+    let metadata = split::metadata(path).map_err(read_error)?;
+
+    if metadata.is_dir() {
+        let mut contents = vec![ContentEntry::new(PathBuf::new(), path, &metadata)];
+        for entry in Walker::new(path, false)? {
+            let entry = entry?;
+            contents.push(ContentEntry::new(
+                entry.relative,
+                &entry.path,
+                &entry.metadata,
+            ));
+        }
+        return Ok(contents);
+    }
+
+    if let Some(compression) = archive::format(path) {
+        return archive::contents(path, compression).map_err(|source| BackupError::ExtractFailed {
+            path: path.to_path_buf(),
+            source,
+        });
+    }
+
+    let file_name = path.file_name().unwrap_or(path.as_os_str());
+    let name = BackupName::parse(file_name).map_or(file_name.into(), |parsed| parsed.original);
+    Ok(vec![ContentEntry::new(
+        name.into_owned().into(),
+        path,
+        &metadata,
+    )])
+}
+
+/// Returns the permission bits of `metadata`.
+#[cfg(unix)]
+fn mode(metadata: &Metadata) -> u32 {
+    use std::os::unix::fs::PermissionsExt;
+
+    metadata.permissions().mode() & 0o7777
+}
+
+/// Returns the permission bits of `metadata`, as far as they can be told.
+#[cfg(not(unix))]
+fn mode(metadata: &Metadata) -> u32 {
+    match (metadata.is_dir(), metadata.permissions().readonly()) {
+        (true, _) => 0o755,
+        (false, true) => 0o444,
+        (false, false) => 0o644,
+    }
+}
+
+/// Finds the backups of the file or directory named `original` in `dir`,
+/// sorted from the oldest to the newest.
+///
+/// Unlike [`list`], nothing but the names is read, so this stays cheap for
+/// large directory backups. An unreadable `dir` has no backups.
+pub(crate) fn backups_of(dir: &Path, original: &OsStr) -> Vec<PathBuf> {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return Vec::new();
+    };
+
+    let mut backups: Vec<_> = entries
+        .filter_map(|entry| {
+            let file_name = entry.ok()?.file_name();
+            let file_name = backup_name(&file_name)?;
+            let parsed = BackupName::parse(file_name)?;
+            let key = (
+                parsed.timestamp.is_none(),
+                parsed.timestamp,
+                parsed.sequence,
+            );
+            naming::names_match(original, &parsed.original).then(|| (key, dir.join(file_name)))
+        })
+        .collect();
+    backups.sort();
+    backups.into_iter().map(|(_, path)| path).collect()
+}
+
+/// Returns the name of the backup the entry named `file_name` stands for:
+/// that of its archive for the first part of a split one, see [`split`],
+/// `None` for its other parts, and `file_name` itself otherwise.
+fn backup_name(file_name: &OsStr) -> Option<&OsStr> {
+    match split::part_name(file_name) {
+        Some((archive, index)) if BackupName::parse(archive).is_some() => {
+            (index == 0).then_some(archive)
+        }
+        _ => Some(file_name),
+    }
+}
+
+/// Sums the sizes of all files below `path`, ignoring unreadable entries.
+pub(crate) fn tree_size(path: &Path) -> u64 {
+    Walker::new(path, false)
+        .map(|walker| {
+            walker
+                .filter_map(Result::ok)
+                .filter(|entry| !entry.metadata.is_dir())
+                .map(|entry| entry.metadata.len())
+                .sum()
+        })
+        .unwrap_or(0)
+}
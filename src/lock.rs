@@ -0,0 +1,241 @@
+//! Lock files keeping two backups of the same source into the same target
+//! from running at once.
+//!
+//! A lock is a file created only if it does not exist yet, holding the PID of
+//! its owner, who also takes an advisory `flock` on it for as long as it runs.
+//! A lock file whose owner is gone, which the kernel tells by releasing the
+//! `flock`, or whose PID no longer exists is stale and taken over.
+
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::error::BackupError;
+use crate::writer::{self, Level};
+
+/// Time between two attempts at taking a lock held by another process.
+const RETRY: Duration = Duration::from_millis(100);
+
+/// Time after which a lock file without a PID is considered abandoned rather
+/// than being written by its owner.
+const UNWRITTEN: Duration = Duration::from_secs(1);
+
+/// Number of attempts at taking the `flock` of a lock file just created,
+/// which another process inspecting it holds for a moment.
+const FLOCK_ATTEMPTS: u32 = 10;
+
+/// A lock held until dropped, which removes its file.
+#[derive(Debug)]
+pub(crate) struct Lock {
+    path: PathBuf,
+    _file: File,
+}
+
+impl Lock {
+    /// Takes the lock at `path`, waiting up to `wait` for another process to
+    /// release it.
+    pub(crate) fn acquire(path: &Path, wait: Duration) -> Result<Lock, BackupError> {
+        let deadline = Instant::now() + wait;
+        loop {
+            let pid = match try_acquire(path) {
+                Ok(Ok(lock)) => return Ok(lock),
+                Ok(Err(pid)) => pid,
+                Err(source) => {
+                    return Err(BackupError::CreateFailed {
+                        path: path.to_path_buf(),
+                        source,
+                    })
+                }
+            };
+            if Instant::now() >= deadline {
+                return Err(BackupError::Locked {
+                    path: path.to_path_buf(),
+                    pid,
+                });
+            }
+            thread::sleep(RETRY.min(deadline - Instant::now()));
+        }
+    }
+}
+
+impl Drop for Lock {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+/// Takes the lock at `path` if it is free or stale, or returns the PID of
+/// the process holding it, if known.
+fn try_acquire(path: &Path) -> io::Result<Result<Lock, Option<u32>>> {
+    loop {
+        match OpenOptions::new().write(true).create_new(true).open(path) {
+            Ok(mut file) => {
+                // Without the flock, the lock would later look released.
+                if !hold(&file) {
+                    drop(file);
+                    let _ = fs::remove_file(path);
+                    return Ok(Err(None));
+                }
+                write!(file, "{}", std::process::id())?;
+                let path = path.to_path_buf();
+                return Ok(Ok(Lock { path, _file: file }));
+            }
+            Err(error) if error.kind() == io::ErrorKind::AlreadyExists => {}
+            Err(error) => return Err(error),
+        }
+
+        let file = match File::open(path) {
+            Ok(file) => file,
+            Err(error) if error.kind() == io::ErrorKind::NotFound => continue,
+            Err(error) => return Err(error),
+        };
+        let (pid, stale) = inspect(path, &file);
+        if !stale {
+            return Ok(Err(pid));
+        }
+
+        // Another process may have taken the stale lock over in the meantime,
+        // and its new lock file must be left alone.
+        if !is_same_file(path, &file) {
+            continue;
+        }
+        writer::log(
+            Level::Warning,
+            format_args!("'{}': Removing stale lock", path.display()),
+        );
+        match fs::remove_file(path) {
+            Err(error) if error.kind() != io::ErrorKind::NotFound => return Err(error),
+            _ => {}
+        }
+    }
+}
+
+/// Checks whether the lock file at `path` is stale, see the [module](self)
+/// docs, without taking it over.
+pub(crate) fn is_stale(path: &Path) -> io::Result<bool> {
+    let file = File::open(path)?;
+    Ok(inspect(path, &file).1)
+}
+
+/// Returns the PID held by the lock file at `path`, open as `file`, if
+/// known, and whether the lock is stale.
+fn inspect(path: &Path, file: &File) -> (Option<u32>, bool) {
+    let pid = fs::read_to_string(path)
+        .ok()
+        .and_then(|pid| pid.trim().parse().ok());
+    let released = flock(file);
+    let stale = match pid {
+        Some(pid) => released || !is_running(pid),
+        None => {
+            released
+                && file
+                    .metadata()
+                    .and_then(|metadata| metadata.modified())
+                    .is_ok_and(|modified| modified.elapsed().is_ok_and(|age| age > UNWRITTEN))
+        }
+    };
+    (pid, stale)
+}
+
+/// Takes an exclusive advisory lock on `file` without waiting, returning
+/// whether it was free.
+#[cfg(unix)]
+fn flock(file: &File) -> bool {
+    use std::os::fd::AsRawFd;
+
+    // SAFETY: the descriptor stays open for the duration of the call.
+    unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX | libc::LOCK_NB) == 0 }
+}
+
+/// Takes the `flock` of the lock file `file` just created, trying again for
+/// a moment while another process inspecting it holds it, and returns
+/// whether it was taken.
+#[cfg(unix)]
+fn hold(file: &File) -> bool {
+    for _ in 1..FLOCK_ATTEMPTS {
+        if flock(file) {
+            return true;
+        }
+        thread::sleep(RETRY / FLOCK_ATTEMPTS);
+    }
+    flock(file)
+}
+
+/// Checks whether the entry at `path` is still the open `file`.
+#[cfg(unix)]
+fn is_same_file(path: &Path, file: &File) -> bool {
+    use std::os::unix::fs::MetadataExt;
+
+    match (fs::symlink_metadata(path), file.metadata()) {
+        (Ok(entry), Ok(open)) => entry.dev() == open.dev() && entry.ino() == open.ino(),
+        _ => false,
+    }
+}
+
+/// Checks whether a process with the given `pid` exists.
+#[cfg(unix)]
+fn is_running(pid: u32) -> bool {
+    let Some(pid) = libc::pid_t::try_from(pid).ok().filter(|&pid| pid > 0) else {
+        return false;
+    };
+    // SAFETY: signal 0 only checks that the process can be signaled.
+    let result = unsafe { libc::kill(pid, 0) };
+    result == 0 || io::Error::last_os_error().raw_os_error() == Some(libc::EPERM)
+}
+
+/// Advisory locks are not taken here, so no lock is known to be released.
+#[cfg(not(unix))]
+fn flock(_file: &File) -> bool {
+    false
+}
+
+/// Advisory locks are not taken here, so there is none to hold.
+#[cfg(not(unix))]
+fn hold(_file: &File) -> bool {
+    true
+}
+
+/// Files cannot be told apart here, so the entry is assumed to be the same.
+#[cfg(not(unix))]
+fn is_same_file(_path: &Path, _file: &File) -> bool {
+    true
+}
+
+/// Processes cannot be looked up here, so every PID is assumed to run.
+#[cfg(not(unix))]
+fn is_running(_pid: u32) -> bool {
+    true
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn a_lock_file_replaced_since_it_was_opened_is_told_apart() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(".project.backup.lock");
+        fs::write(&path, "1").unwrap();
+        let file = File::open(&path).unwrap();
+        assert!(is_same_file(&path, &file));
+
+        fs::remove_file(&path).unwrap();
+        fs::write(&path, "2").unwrap();
+        assert!(!is_same_file(&path, &file));
+    }
+
+    #[test]
+    fn a_lock_file_flocked_elsewhere_is_not_held() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(".project.backup.lock");
+        fs::write(&path, "").unwrap();
+        let inspector = File::open(&path).unwrap();
+        assert!(flock(&inspector));
+
+        assert!(!hold(&File::open(&path).unwrap()));
+        drop(inspector);
+        assert!(hold(&File::open(&path).unwrap()));
+    }
+}
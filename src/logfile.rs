@@ -0,0 +1,241 @@
+//! Log file recording the messages of unattended runs.
+//!
+//! Each message is appended as a timestamped line tagged with its level, as
+//! text or as a JSON object per line. A line is written with a single
+//! `write_all` on a file opened for appending, so that runs logging into the
+//! same file at once never interleave partial lines, and the file is opened
+//! again for each line, so that a run keeps logging into the current file
+//! once another one rotated it.
+//!
+//! Once the file would grow past its maximum size, it is rotated: `backup.log`
+//! becomes `backup.log.1`, `backup.log.1` becomes `backup.log.2`, and so on,
+//! keeping the given number of old files.
+//!
+//! # Examples
+//!
+//! ```
+//! use backup::logfile::{LogFile, LogFormat};
+//! use backup::writer::Level;
+//!
+//! let dir = std::env::temp_dir().join("logfile-example");
+//! std::fs::create_dir_all(&dir)?;
+//! let log = LogFile::open(&dir.join("backup.log"), LogFormat::Json)?.rotate(10 << 20, 5);
+//! log.write(Level::Info, format_args!("backed up {}", "/srv/data"));
+//! # Ok::<(), Box<dyn std::error::Error>>(())
+//! ```
+
+use std::ffi::OsString;
+use std::fmt;
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+
+use chrono::{Local, SecondsFormat};
+
+use crate::error::BackupError;
+use crate::lock::Lock;
+use crate::writer::Level;
+
+/// Longest time waited for another run rotating the same file, after which
+/// the line is appended without rotating.
+const LOCK_WAIT: Duration = Duration::from_secs(1);
+
+/// Format of the lines of a log file.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum LogFormat {
+    /// `<time> <level> [<pid>] <message>`, with the lines of a message after
+    /// the first indented.
+    #[default]
+    Text,
+    /// `{"time": ..., "level": ..., "pid": ..., "message": ...}`.
+    Json,
+}
+
+impl LogFormat {
+    /// Parses `text` or `json`.
+    pub fn parse(name: &str) -> Result<LogFormat, BackupError> {
+        match name {
+            "text" => Ok(LogFormat::Text),
+            "json" => Ok(LogFormat::Json),
+            _ => Err(BackupError::InvalidOption(format!(
+                "Invalid log format '{name}', expected 'text' or 'json'"
+            ))),
+        }
+    }
+}
+
+/// A log file messages are appended to.
+#[derive(Debug)]
+pub struct LogFile {
+    path: PathBuf,
+    format: LogFormat,
+    /// Size past which the file is rotated, and the number of old files
+    /// kept, if rotated at all.
+    rotation: Option<(u64, usize)>,
+    /// Set while rotating, so that the messages of the lock taken to rotate
+    /// do not rotate again.
+    rotating: AtomicBool,
+}
+
+impl LogFile {
+    /// Opens the log file at `path`, creating it if needed, to append lines
+    /// in `format` to it. It is never rotated unless [`rotate`](Self::rotate)
+    /// is called.
+    pub fn open(path: &Path, format: LogFormat) -> Result<LogFile, BackupError> {
+        append(path).map_err(|source| BackupError::CreateFailed {
+            path: path.to_path_buf(),
+            source,
+        })?;
+        Ok(LogFile {
+            path: path.to_path_buf(),
+            format,
+            rotation: None,
+            rotating: AtomicBool::new(false),
+        })
+    }
+
+    /// Rotates the file once it would grow past `max_size` bytes, keeping
+    /// `keep` old files.
+    pub fn rotate(mut self, max_size: u64, keep: usize) -> Self {
+        self.rotation = Some((max_size, keep));
+        self
+    }
+
+    /// Appends `message` at `level`. Failing to is silently ignored, as there
+    /// is nowhere left to report it.
+    pub fn write(&self, level: Level, message: fmt::Arguments<'_>) {
+        let line = self.line(level, &message.to_string());
+        if let Some((max_size, keep)) = self.rotation {
+            let size = fs::metadata(&self.path).map_or(0, |metadata| metadata.len());
+            if size > 0
+                && size + line.len() as u64 > max_size
+                && !self.rotating.swap(true, Ordering::Relaxed)
+            {
+                self.rotate_files(max_size, keep, line.len() as u64);
+                self.rotating.store(false, Ordering::Relaxed);
+            }
+        }
+        let _ = append(&self.path).and_then(|mut file| file.write_all(line.as_bytes()));
+    }
+
+    /// Formats `message` at `level` as a line of the file.
+    fn line(&self, level: Level, message: &str) -> String {
+        let time = Local::now().to_rfc3339_opts(SecondsFormat::Millis, false);
+        let level = level_name(level);
+        let pid = std::process::id();
+        match self.format {
+            LogFormat::Text => {
+                let message = message.trim_end().replace('\n', "\n    ");
+                format!("{time} {level} [{pid}] {message}\n")
+            }
+            LogFormat::Json => {
+                let object = serde_json::json!({
+                    "time": time,
+                    "level": level,
+                    "pid": pid,
+                    "message": message.trim_end(),
+                });
+                format!("{object}\n")
+            }
+        }
+    }
+
+    /// Moves the file to `<path>.1`, and each old file one number up,
+    /// removing those past `keep`, unless another run did it meanwhile.
+    fn rotate_files(&self, max_size: u64, keep: usize, adding: u64) {
+        let mut lock_name = OsString::from(".");
+        lock_name.push(self.path.file_name().unwrap_or_default());
+        lock_name.push(".lock");
+        let Ok(_lock) = Lock::acquire(&self.path.with_file_name(lock_name), LOCK_WAIT) else {
+            return;
+        };
+        let size = fs::metadata(&self.path).map_or(0, |metadata| metadata.len());
+        if size == 0 || size + adding <= max_size {
+            return;
+        }
+
+        let _ = fs::remove_file(numbered(&self.path, keep));
+        for number in (1..keep).rev() {
+            let _ = fs::rename(
+                numbered(&self.path, number),
+                numbered(&self.path, number + 1),
+            );
+        }
+        let _ = match keep {
+            0 => fs::remove_file(&self.path),
+            _ => fs::rename(&self.path, numbered(&self.path, 1)),
+        };
+    }
+}
+
+/// Returns the name of `level` in the file.
+fn level_name(level: Level) -> &'static str {
+    match level {
+        Level::Error => "error",
+        Level::Warning => "warning",
+        Level::Success => "success",
+        Level::Info => "info",
+        Level::Verbose => "verbose",
+    }
+}
+
+/// Returns the path of the old file `number` of the log file at `path`.
+fn numbered(path: &Path, number: usize) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(format!(".{number}"));
+    PathBuf::from(name)
+}
+
+/// Opens the file at `path` for appending, creating it if needed.
+fn append(path: &Path) -> io::Result<fs::File> {
+    OpenOptions::new().create(true).append(true).open(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn full_files_are_rotated() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("backup.log");
+        let log = LogFile::open(&path, LogFormat::Text)
+            .unwrap()
+            .rotate(100, 2);
+        for number in 0..8 {
+            log.write(Level::Info, format_args!("message {number}"));
+        }
+
+        let current = fs::read_to_string(&path).unwrap();
+        let last = format!(" info [{}] message 7\n", std::process::id());
+        assert!(current.ends_with(&last), "{current}");
+        assert!(current.len() <= 100, "{current}");
+        assert!(numbered(&path, 1).exists());
+        assert!(numbered(&path, 2).exists());
+        assert!(!numbered(&path, 3).exists());
+        let old = fs::read_to_string(numbered(&path, 1)).unwrap();
+        assert!(!old.contains("message 7"), "{old}");
+    }
+
+    #[test]
+    fn json_lines_hold_one_message_each() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("backup.log");
+        let log = LogFile::open(&path, LogFormat::Json).unwrap();
+        log.write(Level::Error, format_args!("two\nlines"));
+        log.write(Level::Success, format_args!("done"));
+
+        let text = fs::read_to_string(&path).unwrap();
+        let lines: Vec<serde_json::Value> = text
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0]["level"], "error");
+        assert_eq!(lines[0]["message"], "two\nlines");
+        assert_eq!(lines[1]["message"], "done");
+        assert!(lines[1]["time"].as_str().unwrap().contains('T'));
+    }
+}
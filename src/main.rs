@@ -1,3 +1,2539 @@
-fn main() {
-    println!("Hello, world!");
+mod cli;
+mod console;
+
+use console::{Outcome, TargetOutcome, Version};
+
+use std::cell::Cell;
+use std::env;
+use std::fs;
+use std::io::{self, IsTerminal, Read};
+use std::num::{NonZeroU64, NonZeroUsize};
+use std::path::{Path, PathBuf};
+use std::process;
+use std::str::FromStr;
+use std::time::{Duration, Instant, SystemTime};
+
+use chrono::{DateTime, Local, NaiveTime};
+
+use backup::age::{self, Identity, Passphrase};
+use backup::backup::{
+    BackupOptions, BackupReport, Compression, Fsync, Reflink, SpecialFiles, BACKUP_EXTENSION,
+};
+use backup::catalog;
+use backup::checksum::Algorithm;
+use backup::config::{self, Config, Settings};
+use backup::doctor::{self, Status};
+use backup::exclude::Excludes;
+use backup::gc;
+use backup::journal::{self, Operation};
+use backup::list::BackupKind;
+use backup::logfile::{LogFile, LogFormat};
+use backup::metrics::{self, RunMetrics};
+use backup::naming::{self, NameFormat, Precision};
+use backup::notify::{Notification, Notifier, NotifyOn};
+use backup::pipe::Pipe;
+use backup::prune::Retention;
+use backup::remote::Remote;
+use backup::restore::{self, RestoreOptions, RestoreReport};
+use backup::s3::Bucket;
+use backup::schedule::{self, RunStatus, Schedule};
+use backup::sign::{self, SigningKey, VerifyingKey};
+use backup::split;
+use backup::stats::Stats;
+use backup::syslog::Syslog;
+use backup::systemd::{self, Units};
+use backup::target::Target;
+use backup::trash::Trash;
+use backup::watch::Watcher;
+use backup::writer::{self, Level};
+use backup::{diff, duration, interrupt, list, pattern, prune, verify, BackupError, ExitCode};
+
+/// Time the program was built, in seconds since the Unix epoch.
+const BUILD_EPOCH: &str = env!("BACKUP_BUILD_EPOCH");
+
+/// Schedule of the timers written by `backup systemd-install`, unless given
+/// with `--on-calendar`.
+const DEFAULT_CALENDAR: &str = "daily";
+
+/// Time without changes `backup watch` waits for before backing up again,
+/// unless given with `--settle`.
+const DEFAULT_SETTLE: Duration = Duration::from_secs(5);
+
+/// Age past which `backup gc` removes partial files, unless given with
+/// `--older-than`.
+const DEFAULT_PARTIAL_AGE: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// Number of old log files kept once `--log-max-size` is reached, unless
+/// given with `--log-keep`.
+const DEFAULT_LOG_KEEP: usize = 5;
+
+thread_local! {
+    /// Files and bytes written by the backups of this run, for the
+    /// `--metrics-file`.
+    static WRITTEN: Cell<(u64, u64)> = const { Cell::new((0, 0)) };
+}
+
+/// Operation requested on the command line.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Mode {
+    Backup,
+    Restore,
+    List,
+    ListContents,
+    Prune,
+    Gc,
+    Catalog,
+    Doctor,
+    Verify,
+    Keygen,
+    Diff,
+    History,
+    Undo,
+    Run,
+    Watch,
+    SystemdInstall,
+    SystemdUninstall,
+    Config,
+    Version,
+    Help,
+}
+
+/// Arguments received on the command line.
+#[derive(Debug)]
+struct ArgumentConfig {
+    mode: Mode,
+    help: bool,
+    force: bool,
+    dereference: bool,
+    strict: bool,
+    compress: Option<String>,
+    level: Option<u32>,
+    encrypt: bool,
+    recipients: Vec<String>,
+    identities: Vec<String>,
+    encrypt_passphrase: bool,
+    passphrase_file: Option<String>,
+    work_factor: Option<u8>,
+    name: Option<String>,
+    json: bool,
+    all: bool,
+    retention: Retention,
+    dry_run: bool,
+    trash: bool,
+    trash_dir: Option<String>,
+    empty_trash: bool,
+    yes: bool,
+    preserve: bool,
+    preserve_owner: bool,
+    xattrs: Option<bool>,
+    reflink: Option<String>,
+    buffer_size: Option<NonZeroUsize>,
+    fsync: Option<String>,
+    bwlimit: Option<u64>,
+    special_files: Option<String>,
+    excludes: Vec<String>,
+    exclude_from: Vec<String>,
+    ignore_file: bool,
+    exclude_vcs: bool,
+    exclude_caches: bool,
+    max_depth: Option<NonZeroUsize>,
+    max_files: Option<NonZeroU64>,
+    min_file_size: Option<u64>,
+    max_file_size: Option<u64>,
+    as_file: bool,
+    algorithm: Option<String>,
+    original_path: bool,
+    incremental: bool,
+    compare_checksums: bool,
+    skip_unchanged: bool,
+    mirror: bool,
+    delete: bool,
+    init: bool,
+    jobs: Option<NonZeroUsize>,
+    keep_going: bool,
+    space_check: bool,
+    wait_lock: Option<u64>,
+    retries: u32,
+    retry_delay: Option<Duration>,
+    settle: Option<Duration>,
+    every: Option<Duration>,
+    at: Option<NaiveTime>,
+    status_file: Option<String>,
+    on_calendar: Option<String>,
+    stdout: bool,
+    notify_cmd: Option<String>,
+    notify_on: NotifyOn,
+    metrics_file: Option<String>,
+    log_file: Option<String>,
+    log_format: LogFormat,
+    log_max_size: Option<u64>,
+    log_keep: Option<usize>,
+    log_syslog: bool,
+    /// Profile run by `run`, set once the configuration file is read.
+    profile: Option<String>,
+    allow_empty_glob: bool,
+    files_from: Option<String>,
+    null: bool,
+    latest: Option<String>,
+    from: Option<String>,
+    select: Option<NonZeroUsize>,
+    paths: Vec<String>,
+    list: bool,
+    verify: bool,
+    sign: Option<String>,
+    split: Option<u64>,
+    ssh_options: Vec<String>,
+    endpoint_url: Option<String>,
+    pipe_to: Option<String>,
+    pipe_from: Option<String>,
+    /// Every `--target` given, of which `target` is the last.
+    targets: Vec<String>,
+    verify_key: Option<String>,
+    verify_signature: bool,
+    safety: bool,
+    config: Option<String>,
+    verbosity: Option<Level>,
+    color: Option<bool>,
+    name_format: Option<String>,
+    timestamp_format: Option<String>,
+    utc: bool,
+    no_timestamp: bool,
+    suffix: Option<String>,
+    precision: Option<String>,
+    source: Option<String>,
+    target: Option<String>,
+    /// Every source of a backup, of which `source` is the first.
+    sources: Vec<String>,
+}
+
+impl ArgumentConfig {
+    /// Parses `<mode> [options] [source] [target]`, see [`cli::parse`].
+    fn parse(args: impl IntoIterator<Item = String>) -> Result<Self, String> {
+        let parsed = cli::parse(args)?;
+
+        let mut force = false;
+        let mut dereference = false;
+        let mut strict = false;
+        let mut compress = None;
+        let mut level = None;
+        let mut encrypt = false;
+        let mut recipients = Vec::new();
+        let mut identities = Vec::new();
+        let mut encrypt_passphrase = false;
+        let mut passphrase_file = None;
+        let mut work_factor = None;
+        let mut name = None;
+        let mut json = false;
+        let mut all = false;
+        let mut retention = Retention::default();
+        let mut dry_run = false;
+        let mut trash = false;
+        let mut trash_dir = None;
+        let mut empty_trash = false;
+        let mut yes = false;
+        let mut preserve = true;
+        let mut preserve_owner = false;
+        let mut xattrs = None;
+        let mut reflink = None;
+        let mut buffer_size = None;
+        let mut fsync = None;
+        let mut bwlimit = None;
+        let mut special_files = None;
+        let mut excludes = Vec::new();
+        let mut exclude_from = Vec::new();
+        let mut ignore_file = true;
+        let mut exclude_vcs = false;
+        let mut exclude_caches = false;
+        let mut as_file = false;
+        let mut algorithm = None;
+        let mut original_path = false;
+        let mut incremental = false;
+        let mut compare_checksums = false;
+        let mut skip_unchanged = false;
+        let mut mirror = false;
+        let mut delete = false;
+        let mut init = false;
+        let mut jobs = None;
+        let mut max_depth = None;
+        let mut max_files = None;
+        let mut min_file_size = None;
+        let mut max_file_size = None;
+        let mut keep_going = true;
+        let mut space_check = true;
+        let mut wait_lock = None;
+        let mut retries = 0;
+        let mut retry_delay = None;
+        let mut settle = None;
+        let mut every = None;
+        let mut at = None;
+        let mut status_file = None;
+        let mut on_calendar = None;
+        let mut stdout = false;
+        let mut notify_cmd = None;
+        let mut notify_on = NotifyOn::default();
+        let mut metrics_file = None;
+        let mut log_file = None;
+        let mut log_format = LogFormat::default();
+        let mut log_max_size = None;
+        let mut log_keep = None;
+        let mut log_syslog = false;
+        let mut allow_empty_glob = false;
+        let mut files_from = None;
+        let mut null = false;
+        let mut latest = None;
+        let mut from = None;
+        let mut select = None;
+        let mut paths = Vec::new();
+        let mut list = false;
+        let mut verify = true;
+        let mut sign = None;
+        let mut split = None;
+        let mut ssh_options = Vec::new();
+        let mut endpoint_url = None;
+        let mut pipe_to = None;
+        let mut pipe_from = None;
+        let mut targets = Vec::new();
+        let mut verify_key = None;
+        let mut verify_signature = true;
+        let mut safety = true;
+        let mut config = None;
+        let mut verbosity = None;
+        let mut help = false;
+        let mut target = None;
+        let mut name_format = None;
+        let mut timestamp_format = None;
+        let mut utc = false;
+        let mut no_timestamp = false;
+        let mut suffix = None;
+        let mut precision = None;
+        for (opt, value) in parsed.options {
+            let flag = format!("--{}", opt.long);
+            let value = value.unwrap_or_default();
+            match opt.long {
+                "force" => force = true,
+                "dereference" => dereference = true,
+                "strict" => strict = true,
+                "name" => name = Some(value),
+                "json" => json = true,
+                "all" => all = true,
+                "compress" => compress = Some(value),
+                "level" => level = Some(parsed_value(&value, &flag)?),
+                "encrypt" => encrypt = true,
+                "recipient" => recipients.push(value),
+                "identity" => identities.push(value),
+                "encrypt-passphrase" => encrypt_passphrase = true,
+                "passphrase-file" => passphrase_file = Some(value),
+                "work-factor" => work_factor = Some(parsed_value(&value, &flag)?),
+                "keep-last" => retention.keep_last = Some(parsed_value(&value, &flag)?),
+                "keep-daily" => retention.keep_daily = Some(parsed_value(&value, &flag)?),
+                "keep-weekly" => retention.keep_weekly = Some(parsed_value(&value, &flag)?),
+                "keep-monthly" => retention.keep_monthly = Some(parsed_value(&value, &flag)?),
+                "keep-yearly" => retention.keep_yearly = Some(parsed_value(&value, &flag)?),
+                "older-than" => retention.older_than = Some(duration::parse_duration(&value)?),
+                "allow-empty" => retention.allow_empty = true,
+                "dry-run" => dry_run = true,
+                "trash" => trash = true,
+                "trash-dir" => trash_dir = Some(value),
+                "empty-trash" => empty_trash = true,
+                "yes" => yes = true,
+                "no-preserve" => preserve = false,
+                "preserve-owner" => preserve_owner = true,
+                "xattrs" => xattrs = Some(true),
+                "no-xattrs" => xattrs = Some(false),
+                "reflink" => reflink = Some(value),
+                "buffer-size" => buffer_size = Some(parsed_value(&value, &flag)?),
+                "fsync" => fsync = Some(value),
+                "bwlimit" => bwlimit = Some(writer::parse_size(&value)?).filter(|&rate| rate > 0),
+                "special-files" => special_files = Some(value),
+                "exclude" => excludes.push(value),
+                "exclude-from" => exclude_from.push(value),
+                "no-ignore-file" => ignore_file = false,
+                "exclude-vcs" => exclude_vcs = true,
+                "exclude-caches" => exclude_caches = true,
+                "as-file" => as_file = true,
+                "algorithm" => algorithm = Some(value),
+                "original-path" => original_path = true,
+                "incremental" => incremental = true,
+                "checksum" => compare_checksums = true,
+                "skip-unchanged" => skip_unchanged = true,
+                "mirror" => mirror = true,
+                "delete" => delete = true,
+                "init" => init = true,
+                "jobs" => jobs = Some(parsed_value(&value, &flag)?),
+                "max-depth" => max_depth = Some(parsed_value(&value, &flag)?),
+                "max-files" => max_files = Some(parsed_value(&value, &flag)?),
+                "min-file-size" => min_file_size = Some(writer::parse_size(&value)?),
+                "max-file-size" => max_file_size = Some(writer::parse_size(&value)?),
+                "keep-going" => keep_going = true,
+                "fail-fast" => keep_going = false,
+                "no-space-check" => space_check = false,
+                "wait-lock" => wait_lock = Some(parsed_value(&value, &flag)?),
+                "retries" => retries = parsed_value(&value, &flag)?,
+                "retry-delay" => retry_delay = Some(duration::parse_duration(&value)?),
+                "settle" => settle = Some(duration::parse_duration(&value)?),
+                "every" => every = Some(duration::parse_duration(&value)?),
+                "at" => at = Some(schedule::parse_time(&value)?),
+                "status-file" => status_file = Some(value),
+                "on-calendar" => on_calendar = Some(value),
+                "stdout" => stdout = true,
+                "notify-cmd" => notify_cmd = Some(value),
+                "notify-on" => {
+                    notify_on = NotifyOn::parse(&value).map_err(|error| error.to_string())?
+                }
+                "metrics-file" => metrics_file = Some(value),
+                "log-file" => log_file = Some(value),
+                "log-format" => {
+                    log_format = LogFormat::parse(&value).map_err(|error| error.to_string())?
+                }
+                "log-max-size" => log_max_size = Some(writer::parse_size(&value)?),
+                "log-keep" => log_keep = Some(parsed_value(&value, &flag)?),
+                "log-syslog" => log_syslog = true,
+                "allow-empty-glob" => allow_empty_glob = true,
+                "files-from" => files_from = Some(value),
+                "null" => null = true,
+                "latest" => latest = Some(value),
+                "from" => from = Some(value),
+                "select" => select = Some(parsed_value(&value, &flag)?),
+                "path" => paths.push(value),
+                "list" => list = true,
+                "no-verify" => verify = false,
+                "sign" => sign = Some(value),
+                "ssh-option" => ssh_options.push(value),
+                "endpoint-url" => {
+                    if !value.starts_with("http://") && !value.starts_with("https://") {
+                        return Err(format!("Invalid value '{value}' for '{flag}'"));
+                    }
+                    endpoint_url = Some(value);
+                }
+                "pipe-to" => pipe_to = Some(value),
+                "pipe-from" => pipe_from = Some(value),
+                "split" => {
+                    split = match writer::parse_size(&value)? {
+                        0 => return Err(format!("Invalid value '{value}' for '{flag}'")),
+                        size => Some(size),
+                    }
+                }
+                "verify-key" => verify_key = Some(value),
+                "no-verify-signature" => verify_signature = false,
+                "no-safety" => safety = false,
+                "config" => config = Some(value),
+                "verbose" => verbosity = Some(Level::Verbose),
+                "quiet" => verbosity = Some(Level::Error),
+                "help" => help = true,
+                "target" => {
+                    target = Some(value.clone());
+                    targets.push(value);
+                }
+                "name-format" => name_format = Some(value),
+                "timestamp-format" => timestamp_format = Some(value),
+                "utc" => utc = true,
+                "no-timestamp" => no_timestamp = true,
+                "suffix" => suffix = Some(value),
+                "precision" => precision = Some(value),
+                other => unreachable!("option '--{other}' is not handled"),
+            }
+        }
+
+        if !help {
+            check_arguments(
+                parsed.command,
+                &parsed.arguments,
+                target.is_some(),
+                files_from.is_some(),
+                latest.is_some(),
+            )?;
+        }
+
+        // A backup takes every argument but the last as a source when given
+        // more than two, or when the sources are listed with --files-from, or
+        // all of them with --target.
+        let mut sources = parsed.arguments;
+        let backs_up = parsed.command.mode == Mode::Backup;
+        if target.is_none() && backs_up && (sources.len() > 2 || files_from.is_some()) {
+            target = sources.pop();
+        } else if target.is_none() && sources.len() >= 2 {
+            target = Some(sources.remove(1));
+        }
+        if !backs_up {
+            sources.truncate(1);
+        }
+        Ok(ArgumentConfig {
+            mode: parsed.command.mode,
+            help,
+            force,
+            dereference,
+            strict,
+            compress,
+            level,
+            encrypt,
+            recipients,
+            identities,
+            encrypt_passphrase,
+            passphrase_file,
+            work_factor,
+            name,
+            json,
+            all,
+            retention,
+            dry_run,
+            trash,
+            trash_dir,
+            empty_trash,
+            yes,
+            preserve,
+            preserve_owner,
+            xattrs,
+            reflink,
+            buffer_size,
+            fsync,
+            bwlimit,
+            special_files,
+            excludes,
+            exclude_from,
+            ignore_file,
+            exclude_vcs,
+            exclude_caches,
+            as_file,
+            algorithm,
+            original_path,
+            incremental,
+            compare_checksums,
+            skip_unchanged,
+            mirror,
+            delete,
+            init,
+            jobs,
+            max_depth,
+            max_files,
+            min_file_size,
+            max_file_size,
+            keep_going,
+            space_check,
+            wait_lock,
+            retries,
+            retry_delay,
+            settle,
+            every,
+            at,
+            status_file,
+            on_calendar,
+            stdout,
+            notify_cmd,
+            notify_on,
+            metrics_file,
+            log_file,
+            log_format,
+            log_max_size,
+            log_keep,
+            log_syslog,
+            profile: None,
+            allow_empty_glob,
+            files_from,
+            null,
+            latest,
+            from,
+            select,
+            paths,
+            list,
+            verify,
+            sign,
+            split,
+            ssh_options,
+            endpoint_url,
+            pipe_to,
+            pipe_from,
+            targets,
+            verify_key,
+            verify_signature,
+            safety,
+            config,
+            verbosity,
+            color: None,
+            name_format,
+            timestamp_format,
+            utc,
+            no_timestamp,
+            suffix,
+            precision,
+            source: sources.first().cloned(),
+            target,
+            sources,
+        })
+    }
+
+    /// Returns the path of the configuration file given with `--config`, or
+    /// the default one.
+    fn config_path(&self) -> Option<PathBuf> {
+        self.config
+            .as_ref()
+            .map(PathBuf::from)
+            .or_else(Config::default_path)
+    }
+
+    /// Fills the options not given on the command line from the environment,
+    /// then from the defaults of the configuration file or the profile to run.
+    ///
+    /// A missing configuration file is only an error if given with
+    /// `--config`, or when running a profile.
+    fn configure(&mut self) -> Result<(), BackupError> {
+        let settings = self.config_settings()?;
+        let settings = config::resolve(self.settings(), Settings::from_env(), &settings);
+        self.apply(settings);
+        if self.suffix.is_some() && !self.no_timestamp {
+            return Err(BackupError::InvalidOption(
+                "--suffix requires --no-timestamp".to_owned(),
+            ));
+        }
+        if self.no_timestamp {
+            if self.name_format.is_some() {
+                return Err(BackupError::InvalidOption(
+                    "--no-timestamp cannot be combined with --name-format".to_owned(),
+                ));
+            }
+            let suffix = match &self.suffix {
+                Some(suffix) => suffix.clone(),
+                None => format!(".{BACKUP_EXTENSION}"),
+            };
+            naming::set_format(NameFormat::without_timestamp(&suffix)?.utc(self.utc));
+        } else if self.name_format.is_some()
+            || self.timestamp_format.is_some()
+            || self.utc
+            || self.precision.is_some()
+        {
+            let format = match &self.name_format {
+                Some(template) => NameFormat::new(template)?,
+                None => NameFormat::default(),
+            };
+            let format = match &self.timestamp_format {
+                Some(timestamp_format) => format.timestamp_format(timestamp_format)?,
+                None => format,
+            };
+            let precision = self.precision.as_deref().map(Precision::parse);
+            let precision = precision.transpose()?.unwrap_or_default();
+            naming::set_format(format.utc(self.utc).precision(precision));
+        }
+        Ok(())
+    }
+
+    /// Reads the settings of the configuration file that apply to this
+    /// invocation.
+    fn config_settings(&mut self) -> Result<Settings, BackupError> {
+        if matches!(
+            self.mode,
+            Mode::Config | Mode::Catalog | Mode::Doctor | Mode::Version | Mode::Help
+        ) {
+            return Ok(Settings::default());
+        }
+
+        let config = match self.config_path() {
+            Some(path) => match Config::load(&path) {
+                Err(BackupError::NotFound(_))
+                    if self.config.is_none() && self.mode != Mode::Run =>
+                {
+                    Config::default()
+                }
+                config => config?,
+            },
+            None if self.mode == Mode::Run => {
+                return Err(BackupError::InvalidOption(
+                    "Neither XDG_CONFIG_HOME nor HOME is set, pass --config".to_owned(),
+                ))
+            }
+            None => Config::default(),
+        };
+
+        let settings = match self.mode {
+            Mode::Run => {
+                let name = self.source.take().ok_or_else(|| {
+                    BackupError::InvalidOption("Run requires the name of a profile".to_owned())
+                })?;
+                let profile = config.profile(&name)?;
+                let source = profile.source.as_ref().ok_or_else(|| {
+                    BackupError::InvalidOption(format!("Profile '{name}' has no source"))
+                })?;
+                self.source = Some(source.to_string_lossy().into_owned());
+                self.sources = self.source.iter().cloned().collect();
+                self.profile = Some(name);
+                profile
+            }
+            _ => config.defaults,
+        };
+        Ok(settings)
+    }
+
+    /// Returns the settings given on the command line.
+    fn settings(&self) -> Settings {
+        let flag = |set: bool| set.then_some(true);
+        Settings {
+            source: None,
+            target: self.target.as_ref().map(PathBuf::from),
+            compress: self.compress.clone(),
+            level: self.level,
+            excludes: self.excludes.clone(),
+            verbosity: self.verbosity,
+            algorithm: self.algorithm.clone(),
+            incremental: flag(self.incremental),
+            skip_unchanged: flag(self.skip_unchanged),
+            exclude_vcs: flag(self.exclude_vcs),
+            exclude_caches: flag(self.exclude_caches),
+            dereference: flag(self.dereference),
+            jobs: self.jobs.map(NonZeroUsize::get),
+            color: self.color,
+            name_format: self.name_format.clone(),
+            timestamp_format: self.timestamp_format.clone(),
+            utc: flag(self.utc),
+            no_timestamp: flag(self.no_timestamp),
+            suffix: self.suffix.clone(),
+            precision: self.precision.clone(),
+        }
+    }
+
+    /// Replaces the options with the resolved `settings`. Only the verbosity,
+    /// colors and name format apply to modes other than backups.
+    fn apply(&mut self, settings: Settings) {
+        self.verbosity = settings.verbosity;
+        self.color = settings.color;
+        self.name_format = settings.name_format;
+        self.timestamp_format = settings.timestamp_format;
+        self.utc = settings.utc.unwrap_or(false);
+        self.no_timestamp = settings.no_timestamp.unwrap_or(false);
+        self.suffix = settings.suffix;
+        self.precision = settings.precision;
+        if !matches!(self.mode, Mode::Backup | Mode::Run | Mode::Watch) {
+            return;
+        }
+
+        let path = |path: PathBuf| path.to_string_lossy().into_owned();
+        self.target = settings.target.map(path);
+        self.compress = settings.compress;
+        self.level = settings.level;
+        self.excludes = settings.excludes;
+        self.algorithm = settings.algorithm;
+        self.incremental = settings.incremental.unwrap_or(false);
+        self.skip_unchanged = settings.skip_unchanged.unwrap_or(false);
+        self.exclude_vcs = settings.exclude_vcs.unwrap_or(false);
+        self.exclude_caches = settings.exclude_caches.unwrap_or(false);
+        self.dereference = settings.dereference.unwrap_or(false);
+        self.jobs = settings.jobs.and_then(NonZeroUsize::new);
+    }
+}
+
+/// Interrupts the backups in progress on the first SIGINT or SIGTERM, so that
+/// they remove what they wrote, and exits at once on the second.
+///
+/// The handlers are installed without `SA_RESTART`, so that a backup blocked
+/// reading stdin notices the interruption.
+#[cfg(unix)]
+fn handle_interrupts() {
+    extern "C" fn handle(_signal: libc::c_int) {
+        if interrupt::is_interrupted() {
+            // SAFETY: _exit is async-signal-safe.
+            unsafe { libc::_exit(130) };
+        }
+        interrupt::interrupt();
+    }
+
+    for signal in [libc::SIGINT, libc::SIGTERM] {
+        // SAFETY: the handler only touches an atomic flag or exits, and an
+        // all-zero sigaction with an empty mask is valid.
+        unsafe {
+            let mut action: libc::sigaction = std::mem::zeroed();
+            action.sa_sigaction = handle as extern "C" fn(libc::c_int) as libc::sighandler_t;
+            libc::sigemptyset(&mut action.sa_mask);
+            libc::sigaction(signal, &action, std::ptr::null_mut());
+        }
+    }
+}
+
+/// Ctrl+C keeps ending the process at once where signals are not handled.
+#[cfg(not(unix))]
+fn handle_interrupts() {}
+
+/// Checks the number of positional `arguments` against the usage of
+/// `command`, where the `target` given with `--target`, the sources listed
+/// with `--files-from` and the backup found with `--latest` stand for some.
+fn check_arguments(
+    command: &cli::Command,
+    arguments: &[String],
+    target: bool,
+    files_from: bool,
+    latest: bool,
+) -> Result<(), String> {
+    let usage = || {
+        let usage = format!(
+            "usage: backup {} [options] {}",
+            command.name, command.arguments
+        );
+        usage.trim_end().to_owned()
+    };
+    let given = |name: &str| target && name == "[target]" || latest && name == "<backup>";
+    let optional = |name: &str| name.starts_with('[') || files_from && name.ends_with("...");
+    let expected: Vec<_> = command
+        .arguments
+        .split_whitespace()
+        .filter(|name| !given(name))
+        .collect();
+    let repeated = expected.iter().any(|name| name.ends_with("..."));
+    let mut required = expected.iter().filter(|name| !optional(name));
+
+    if let Some(missing) = required.nth(arguments.len()) {
+        let missing = missing.trim_end_matches("...");
+        return Err(format!("Missing {missing} argument; {}", usage()));
+    }
+    match arguments.get(expected.len()) {
+        Some(extra) if !repeated => Err(format!("Unexpected argument '{extra}'; {}", usage())),
+        _ => Ok(()),
+    }
+}
+
+/// Parses the `value` given to `flag`.
+fn parsed_value<T: FromStr>(value: &str, flag: &str) -> Result<T, String> {
+    value
+        .parse()
+        .map_err(|_| format!("Invalid value '{value}' for '{flag}'"))
+}
+
+fn main() -> process::ExitCode {
+    start().into()
+}
+
+/// Runs the command line, returning the exit status.
+fn start() -> ExitCode {
+    let mut args = match ArgumentConfig::parse(env::args().skip(1)) {
+        Ok(args) => args,
+        Err(message) => {
+            console::log(Level::Error, message);
+            console::usage_hint();
+            return ExitCode::Usage;
+        }
+    };
+    if args.help && args.mode != Mode::Help {
+        console::command_usage(cli::Command::of(args.mode));
+        return ExitCode::Success;
+    }
+
+    if let Err(error) = open_logs(&args).and_then(|()| args.configure()) {
+        console::init(Level::Info, true, args.json, false);
+        console::report(Level::Error, &error);
+        notify(&args, error.exit_code(), Some(&error));
+        write_metrics(&args, error.exit_code(), Duration::ZERO);
+        return error.exit_code();
+    }
+    // Backups and restores print the path they wrote to on stdout, and
+    // keygen the public key, and nothing else, so that scripts can capture
+    // it.
+    console::init(
+        args.verbosity.unwrap_or(Level::Info),
+        args.color.unwrap_or(true),
+        args.json,
+        matches!(
+            args.mode,
+            Mode::Backup | Mode::Run | Mode::Watch | Mode::Restore | Mode::Keygen
+        ),
+    );
+
+    if matches!(args.mode, Mode::Backup | Mode::Run | Mode::Watch) && !args.dry_run {
+        handle_interrupts();
+    }
+    let scheduled = args.every.is_some() || args.at.is_some();
+    let started = Instant::now();
+    let result = match scheduled {
+        true => run_scheduled(&args),
+        false => run(&args),
+    };
+    // Scheduled runs notify of each run themselves.
+    match &result {
+        Ok(status) if !scheduled => notify(&args, *status, None),
+        Err(error) => notify(&args, error.exit_code(), Some(error)),
+        Ok(_) => {}
+    }
+    match &result {
+        Ok(status) if !scheduled => write_metrics(&args, *status, started.elapsed()),
+        Err(error) => write_metrics(&args, error.exit_code(), started.elapsed()),
+        Ok(_) => {}
+    }
+    let status = match result {
+        Ok(status) => status,
+        Err(error) => {
+            let level = match error {
+                BackupError::Unchanged(_) => Level::Info,
+                _ => Level::Error,
+            };
+            console::report(level, &error);
+            // Differences already exit with 1, so diff reports errors with 2.
+            match args.mode {
+                Mode::Diff => ExitCode::Usage,
+                _ => error.exit_code(),
+            }
+        }
+    };
+    let level = match status.is_success() {
+        true => Level::Info,
+        false => Level::Error,
+    };
+    console::record(level, format_args!("finished with exit status {status}"));
+    status
+}
+
+/// Opens the `--log-file` and connects to the system log with
+/// `--log-syslog`, if given, which then record every message at the info
+/// level or above whatever the verbosity, starting with the command line.
+///
+/// The system log being unreachable is reported without failing.
+fn open_logs(args: &ArgumentConfig) -> Result<(), BackupError> {
+    if let Some(path) = &args.log_file {
+        let mut file = LogFile::open(Path::new(path), args.log_format)?;
+        if let Some(max_size) = args.log_max_size {
+            file = file.rotate(max_size, args.log_keep.unwrap_or(DEFAULT_LOG_KEEP));
+        }
+        console::set_log_file(file);
+    }
+    if args.log_syslog {
+        match Syslog::connect() {
+            Ok(syslog) => console::set_syslog(syslog),
+            Err(error) => console::report(Level::Warning, &error),
+        }
+    }
+    let command: Vec<_> = env::args().skip(1).collect();
+    console::record(
+        Level::Info,
+        format_args!("started: backup {}", command.join(" ")),
+    );
+    Ok(())
+}
+
+/// Picks one of the backups of `name` in `dir`: the `select`th newest if
+/// given, the only one if there is a single backup, or the one chosen at a
+/// prompt when stdin is a terminal.
+fn choose_version(
+    dir: &Path,
+    name: &Path,
+    select: Option<NonZeroUsize>,
+) -> Result<PathBuf, BackupError> {
+    let mut versions = restore::versions(dir, name)?;
+    versions.reverse();
+    let count = versions.len();
+    let index = match select.map(NonZeroUsize::get) {
+        _ if count == 0 => return Err(BackupError::NoBackup(dir.join(name))),
+        Some(n) if n > count => {
+            return Err(BackupError::InvalidOption(format!(
+                "Only {count} backups of '{}' found, cannot select the {n}th newest",
+                name.display()
+            )))
+        }
+        Some(n) => n - 1,
+        None if count == 1 => 0,
+        None if io::stdin().is_terminal() => {
+            let entries = versions
+                .iter()
+                .map(|path| list::describe(path))
+                .collect::<Result<Vec<_>, _>>()?;
+            console::pick(&entries)
+                .ok_or_else(|| BackupError::InvalidOption("No backup selected".to_owned()))?
+        }
+        None => {
+            return Err(BackupError::InvalidOption(format!(
+            "{count} backups of '{}' found, pass --latest, --select <n> or the path of one of them",
+            name.display()
+        )))
+        }
+    };
+    Ok(versions.swap_remove(index))
+}
+
+/// Prints the entries inside the backup at `path`, along with the metadata of
+/// copies of a single file.
+fn list_contents(path: &Path, json: bool) -> Result<(), BackupError> {
+    let contents = list::contents(path)?;
+    if json {
+        console::print_json(&contents);
+        return Ok(());
+    }
+
+    console::print_contents(&contents);
+    let backup = list::describe(path)?;
+    if matches!(backup.kind, BackupKind::File | BackupKind::Link) {
+        if let Some(meta) = &backup.meta {
+            console::print_meta(meta);
+        }
+    }
+    Ok(())
+}
+
+/// Returns the sources to back up, expanding the wildcards of those that do
+/// not name an existing path, followed by those listed with --files-from.
+/// Matches are sorted, and a path given or matched more than once is only kept
+/// the first time.
+fn expand_sources(args: &ArgumentConfig) -> Result<Vec<PathBuf>, BackupError> {
+    let mut sources = Vec::new();
+    for source in &args.sources {
+        let path = PathBuf::from(source);
+        let matches = match pattern::is_glob(source) && fs::symlink_metadata(&path).is_err() {
+            true => pattern::glob(source),
+            false => vec![path],
+        };
+        if matches.is_empty() {
+            let error = BackupError::GlobEmpty(source.clone());
+            if !args.allow_empty_glob {
+                return Err(error);
+            }
+            console::log(Level::Warning, &error);
+        }
+        for path in matches {
+            if !sources.contains(&path) {
+                sources.push(path);
+            }
+        }
+    }
+    if let Some(list) = &args.files_from {
+        for path in listed_sources(list, args.null)? {
+            if fs::symlink_metadata(&path).is_err() {
+                let error = BackupError::NotFound(path);
+                if !args.keep_going {
+                    return Err(error);
+                }
+                console::log(Level::Warning, format_args!("{error}, skipping"));
+            } else if !sources.contains(&path) {
+                sources.push(path);
+            }
+        }
+    }
+    Ok(sources)
+}
+
+/// Reads the paths listed in the file at `list`, or on stdin if it is `-`,
+/// one per line or separated by NUL characters if `null` is set. Blank lines
+/// and lines starting with `#` are ignored.
+fn listed_sources(list: &str, null: bool) -> Result<Vec<PathBuf>, BackupError> {
+    let mut contents = Vec::new();
+    let read = match list {
+        "-" => io::stdin().read_to_end(&mut contents),
+        _ => fs::File::open(list).and_then(|mut file| file.read_to_end(&mut contents)),
+    };
+    read.map_err(|source| BackupError::ReadFailed {
+        path: PathBuf::from(list),
+        source,
+    })?;
+
+    let contents = String::from_utf8_lossy(&contents);
+    let paths: Vec<_> = match null {
+        true => contents
+            .split('\0')
+            .filter(|path| !path.is_empty())
+            .collect(),
+        false => contents
+            .lines()
+            .filter(|line| !line.trim().is_empty() && !line.starts_with('#'))
+            .collect(),
+    };
+    Ok(paths.into_iter().map(PathBuf::from).collect())
+}
+
+/// Writes the backup of the single directory in `sources` to stdout as an
+/// archive, leaving stdout to the archive alone.
+fn stream_backup(
+    args: &ArgumentConfig,
+    sources: &[PathBuf],
+    options: &BackupOptions,
+) -> Result<ExitCode, BackupError> {
+    let [source] = sources else {
+        return Err(BackupError::InvalidOption(
+            "Only a single source can be written to stdout".to_owned(),
+        ));
+    };
+    for (set, flag) in [
+        (args.json, "--json"),
+        (args.dry_run, "--dry-run"),
+        (args.as_file, "--as-file"),
+    ] {
+        if set {
+            return Err(BackupError::InvalidOption(format!(
+                "{flag} cannot be used when writing the backup to stdout"
+            )));
+        }
+    }
+    if io::stdout().is_terminal() {
+        return Err(BackupError::InvalidOption(
+            "Refusing to write an archive to a terminal, redirect stdout".to_owned(),
+        ));
+    }
+
+    let report = backup::backup::stream(source, io::stdout().lock(), options)?;
+    tally(report.files, report.bytes);
+    console::log(
+        Level::Verbose,
+        format_args!(
+            "wrote {} files of {} to stdout ({})",
+            report.files,
+            source.display(),
+            writer::human_bytes(report.bytes)
+        ),
+    );
+    console::log(Level::Info, report.stats);
+    Ok(ExitCode::Success)
+}
+
+/// Parses `spec` as a path on another machine, `[user@]host:path`, passing
+/// it the `--ssh-option`s. A local path of that form that exists is taken as
+/// local.
+fn remote(args: &ArgumentConfig, spec: &str) -> Option<Remote> {
+    let remote = Remote::parse(spec)?;
+    if fs::symlink_metadata(spec).is_ok() {
+        return None;
+    }
+    Some(
+        args.ssh_options
+            .iter()
+            .fold(remote, |remote, option| remote.option(option)),
+    )
+}
+
+/// Parses `spec` as a key or prefix in an S3 bucket, `s3://bucket/prefix`,
+/// sent to the `--endpoint-url` if given.
+fn bucket(args: &ArgumentConfig, spec: &str) -> Option<Bucket> {
+    let bucket = Bucket::parse(spec)?;
+    Some(match &args.endpoint_url {
+        Some(url) => bucket.endpoint(url),
+        None => bucket,
+    })
+}
+
+/// Backs up the single source in `sources` with `back_up`, to another
+/// machine over SSH, to an S3 bucket or through a command.
+fn back_up_remote(
+    args: &ArgumentConfig,
+    sources: &[PathBuf],
+    back_up: impl FnOnce(&Path) -> Result<BackupReport, BackupError>,
+) -> Result<ExitCode, BackupError> {
+    let [source] = sources else {
+        return Err(BackupError::InvalidOption(
+            "Only a single source can be backed up to a remote target".to_owned(),
+        ));
+    };
+    if source == Path::new("-") || args.dry_run {
+        return Err(BackupError::InvalidOption(
+            "stdin and --dry-run cannot be used with a remote target".to_owned(),
+        ));
+    }
+
+    let report = back_up(source)?;
+    tally(report.files, report.bytes);
+    if args.json {
+        console::print_outcome(&Outcome::Backup {
+            source: &absolute(source),
+            backup_path: &report.path,
+            bytes: report.bytes,
+            files: report.files,
+            duration_ms: report.duration.as_millis(),
+            stats: &report.stats,
+        });
+    } else {
+        console::out(report.path.display());
+        console::log(Level::Info, report.stats);
+    }
+    Ok(ExitCode::Success)
+}
+
+/// Backs up `source` into `target`, then again whenever it changes once the
+/// changes settle, pruning the target after each backup if retention rules
+/// are given, until interrupted.
+///
+/// Backups skip the source when unchanged since the previous one, and
+/// failures are reported without ending the watch, unless caused by the
+/// options.
+fn watch(
+    args: &ArgumentConfig,
+    source: &Path,
+    target: &Path,
+    options: &BackupOptions,
+) -> Result<ExitCode, BackupError> {
+    if !naming::format().has_timestamp() {
+        return Err(BackupError::InvalidOption(
+            "Watch requires timestamped backup names".to_owned(),
+        ));
+    }
+    let retention = &args.retention;
+    let prunes = retention.validate().is_ok();
+    let options = options.clone().skip_unchanged(true);
+    let mut watcher = Watcher::new(source)?;
+    console::log(
+        Level::Info,
+        format_args!("watching {} for changes", source.display()),
+    );
+
+    loop {
+        match backup::backup(source, target, &options) {
+            Ok(report) => {
+                tally(report.files, report.bytes);
+                record(&Operation::backup(source, &report));
+                if args.json {
+                    console::print_outcome(&Outcome::Backup {
+                        source: &absolute(source),
+                        backup_path: &printed_path(&report.path),
+                        bytes: report.bytes,
+                        files: report.files,
+                        duration_ms: report.duration.as_millis(),
+                        stats: &report.stats,
+                    });
+                } else {
+                    console::out(printed_path(&report.path).display());
+                    console::log(Level::Info, report.stats);
+                }
+                if !report.failed.is_empty() {
+                    console::print_failures(&report.failed);
+                }
+            }
+            Err(BackupError::Interrupted) => break,
+            Err(error @ BackupError::Unchanged(_)) => console::report(Level::Info, &error),
+            Err(error) if error.exit_code() == ExitCode::Usage => return Err(error),
+            Err(error) => console::report(Level::Error, &error),
+        }
+        if prunes {
+            if let Err(error) = prune::prune(target, retention, false, None) {
+                console::report(Level::Error, &error);
+            }
+        }
+        if !watcher.wait(args.settle.unwrap_or(DEFAULT_SETTLE))? {
+            break;
+        }
+        console::log(
+            Level::Verbose,
+            format_args!("{} changed, backing up", source.display()),
+        );
+    }
+    console::log(
+        Level::Info,
+        format_args!("stopped watching {}", source.display()),
+    );
+    Ok(ExitCode::Success)
+}
+
+/// Backs up the single source in `sources` to every `--target` given,
+/// reporting the outcome for each.
+fn back_up_to_targets(
+    args: &ArgumentConfig,
+    sources: &[PathBuf],
+    options: &BackupOptions,
+) -> Result<ExitCode, BackupError> {
+    let [source] = sources else {
+        return Err(BackupError::InvalidOption(
+            "Only a single source can be backed up to several targets".to_owned(),
+        ));
+    };
+    if source == Path::new("-") || args.dry_run {
+        return Err(BackupError::InvalidOption(
+            "stdin and --dry-run cannot be used with several targets".to_owned(),
+        ));
+    }
+    let targets: Vec<_> = args
+        .targets
+        .iter()
+        .map(|spec| {
+            if let Some(bucket) = bucket(args, spec) {
+                Target::S3(bucket)
+            } else if let Some(remote) = remote(args, spec) {
+                Target::Remote(remote)
+            } else {
+                Target::Local(PathBuf::from(spec))
+            }
+        })
+        .collect();
+
+    let report = backup::backup::backup_to_targets(source, &targets, options)?;
+    let absolute_source = absolute(source);
+    let mut backups = Vec::new();
+    for (target, (_, result)) in targets.iter().zip(&report.results) {
+        let Ok(backup) = result else {
+            continue;
+        };
+        tally(backup.files, backup.bytes);
+        let backup_path = match target {
+            Target::Local(_) => {
+                record(&Operation::backup(source, backup));
+                printed_path(&backup.path)
+            }
+            _ => backup.path.clone(),
+        };
+        if !backup.failed.is_empty() {
+            console::print_failures(&backup.failed);
+        }
+        backups.push((backup_path, backup));
+    }
+    if args.json {
+        let mut backups = backups.iter();
+        let outcomes: Vec<_> = report
+            .results
+            .iter()
+            .map(|(target, result)| match result {
+                Ok(_) => {
+                    let (path, backup) = backups.next().expect("one backup per success");
+                    TargetOutcome::Done {
+                        target,
+                        outcome: Outcome::Backup {
+                            source: &absolute_source,
+                            backup_path: path,
+                            bytes: backup.bytes,
+                            files: backup.files,
+                            duration_ms: backup.duration.as_millis(),
+                            stats: &backup.stats,
+                        },
+                    }
+                }
+                Err(error) => TargetOutcome::Failed {
+                    target,
+                    error: error.to_string(),
+                    code: error.code(),
+                },
+            })
+            .collect();
+        console::print_json(&outcomes);
+    } else {
+        for (path, _) in &backups {
+            console::out(path.display());
+        }
+        if let Some((_, backup)) = backups.first() {
+            console::log(Level::Info, backup.stats);
+        }
+    }
+
+    if interrupt::is_interrupted() {
+        return Err(BackupError::Interrupted);
+    }
+    match report.failures() {
+        0 => Ok(ExitCode::Success),
+        failures => Err(BackupError::TargetsFailed {
+            failures,
+            total: targets.len(),
+        }),
+    }
+}
+
+/// Restores the backup at `source`, on another machine over SSH, in an S3
+/// bucket or read from a command, with `restore`.
+fn restore_remote(
+    args: &ArgumentConfig,
+    source: &Path,
+    restore: impl FnOnce(Option<&Path>, &RestoreOptions) -> Result<RestoreReport, BackupError>,
+) -> Result<(), BackupError> {
+    let options = RestoreOptions::new()
+        .force(args.force)
+        .strict(args.strict)
+        .preserve(args.preserve)
+        .preserve_owner(args.preserve_owner)
+        .original_path(args.original_path)
+        .verify_signature(args.verify_signature)
+        .safety(args.safety);
+    let options = match verify_key(args)? {
+        Some(key) => options.verify_key(key),
+        None => options,
+    };
+    let options = identities(args)?
+        .into_iter()
+        .fold(options, RestoreOptions::identity);
+    let options = match args.xattrs {
+        Some(xattrs) => options.xattrs(xattrs),
+        None => options,
+    };
+    let options = match given_passphrase(args)? {
+        Some(passphrase) => options.passphrase(passphrase),
+        None => options,
+    };
+    let options = args
+        .paths
+        .iter()
+        .fold(options, |options, pattern| options.path(pattern));
+
+    let started = Instant::now();
+    let target = args.target.as_deref().map(Path::new);
+    let report = restore(target, &options)?;
+    if args.json {
+        console::print_outcome(&Outcome::Restore {
+            source,
+            restore_path: &absolute(&report.path),
+            bytes: report.bytes,
+            files: report.files,
+            duration_ms: started.elapsed().as_millis(),
+            safety_path: report.safety.as_deref().map(absolute).as_deref(),
+            stats: &report.stats,
+        });
+    } else {
+        console::out(absolute(&report.path).display());
+        console::log(Level::Info, report.stats);
+    }
+    Ok(())
+}
+
+/// Backs up stdin, the single source in `sources`, to a file named after
+/// `--name` in `target`.
+fn back_up_stdin(
+    args: &ArgumentConfig,
+    sources: &[PathBuf],
+    target: &Path,
+    options: &BackupOptions,
+) -> Result<ExitCode, BackupError> {
+    if sources.len() > 1 {
+        return Err(BackupError::InvalidOption(
+            "stdin cannot be backed up along with other sources".to_owned(),
+        ));
+    }
+    let name = args
+        .name
+        .as_deref()
+        .ok_or_else(|| BackupError::InvalidOption("Backing up stdin requires --name".to_owned()))?;
+    if args.dry_run || args.as_file {
+        return Err(BackupError::InvalidOption(
+            "--dry-run and --as-file cannot be used when backing up stdin".to_owned(),
+        ));
+    }
+
+    let report = backup::backup::backup_reader(io::stdin().lock(), name, target, options)?;
+    tally(report.files, report.bytes);
+    record(&Operation::backup(Path::new("-"), &report));
+    if args.json {
+        console::print_outcome(&Outcome::Backup {
+            source: Path::new("-"),
+            backup_path: &absolute(&report.path),
+            bytes: report.bytes,
+            files: report.files,
+            duration_ms: report.duration.as_millis(),
+            stats: &report.stats,
+        });
+    } else {
+        console::out(absolute(&report.path).display());
+        console::log(Level::Info, report.stats);
+    }
+    Ok(ExitCode::Success)
+}
+
+/// Backs up the several `sources` into `target`, printing the path of each
+/// backup created. A source that fails does not stop the others, but makes the
+/// whole operation fail.
+fn back_up_all(
+    args: &ArgumentConfig,
+    sources: &[PathBuf],
+    target: &Path,
+    options: &BackupOptions,
+) -> Result<ExitCode, BackupError> {
+    if args.as_file {
+        return Err(BackupError::InvalidOption(
+            "--as-file requires a single source".to_owned(),
+        ));
+    }
+    let absolute_sources: Vec<_> = sources.iter().map(|source| absolute(source)).collect();
+
+    let mut failures = 0;
+    if args.dry_run {
+        let mut plans = Vec::new();
+        for (source, absolute_source) in sources.iter().zip(&absolute_sources) {
+            match backup::backup::plan(source, target, options) {
+                Ok(plan) => {
+                    failures += usize::from(plan.is_blocked());
+                    plans.push((absolute_source, absolute(&plan.destination), plan));
+                }
+                Err(error) => {
+                    console::log(Level::Error, &error);
+                    failures += 1;
+                }
+            }
+        }
+        if args.json {
+            let outcomes: Vec<_> = plans
+                .iter()
+                .map(|(source, destination, plan)| Outcome::Plan {
+                    source,
+                    backup_path: destination,
+                    bytes: plan.bytes,
+                    files: plan.files,
+                    blocked: plan.is_blocked(),
+                })
+                .collect();
+            console::print_json(&outcomes);
+        } else {
+            let plans: Vec<_> = plans.iter().map(|(_, _, plan)| plan.to_string()).collect();
+            console::out(plans.join("\n\n"));
+        }
+    } else {
+        let report = backup::backup::backup_all(sources, target, options);
+        failures = report.failures();
+        let mut backups = Vec::new();
+        for ((source, result), absolute_source) in report.results.iter().zip(&absolute_sources) {
+            let Ok(backup) = result else {
+                continue;
+            };
+            tally(backup.files, backup.bytes);
+            record(&Operation::backup(source, backup));
+            if !backup.failed.is_empty() {
+                console::print_failures(&backup.failed);
+            }
+            backups.push((absolute_source, printed_path(&backup.path), backup));
+        }
+        if args.json {
+            let outcomes: Vec<_> = backups
+                .iter()
+                .map(|(source, path, backup)| Outcome::Backup {
+                    source,
+                    backup_path: path,
+                    bytes: backup.bytes,
+                    files: backup.files,
+                    duration_ms: backup.duration.as_millis(),
+                    stats: &backup.stats,
+                })
+                .collect();
+            console::print_json(&outcomes);
+        } else {
+            let mut stats = Stats::default();
+            for (_, path, backup) in &backups {
+                console::out(path.display());
+                stats.merge(&backup.stats);
+            }
+            console::log(Level::Info, stats);
+        }
+    }
+
+    if interrupt::is_interrupted() {
+        return Err(BackupError::Interrupted);
+    }
+    match failures {
+        0 => Ok(ExitCode::Success),
+        failures => Err(BackupError::SourcesFailed {
+            failures,
+            total: sources.len(),
+        }),
+    }
+}
+
+/// Returns the absolute form of `path`, or `path` itself if there is none.
+fn absolute(path: &Path) -> PathBuf {
+    std::path::absolute(path).unwrap_or_else(|_| path.to_path_buf())
+}
+
+/// Returns the absolute path printed for the local backup at `path`, that of
+/// its first part if it is split.
+fn printed_path(path: &Path) -> PathBuf {
+    absolute(&split::first_part(path))
+}
+
+/// Returns the directory of the user's systemd units, failing if there is no
+/// home directory.
+fn systemd_dir() -> Result<PathBuf, BackupError> {
+    systemd::user_dir().ok_or_else(|| {
+        BackupError::InvalidOption(
+            "Neither XDG_CONFIG_HOME nor HOME is set, so there is no unit directory".to_owned(),
+        )
+    })
+}
+
+/// Returns the path of the journal, failing if there is no home directory.
+fn journal_path() -> Result<PathBuf, BackupError> {
+    journal::path().ok_or_else(|| {
+        BackupError::InvalidOption(
+            "Neither XDG_DATA_HOME nor HOME is set, so there is no history".to_owned(),
+        )
+    })
+}
+
+/// Returns the trash given with `--trash-dir`, or that of the user, failing
+/// if there is no home directory.
+fn trash(args: &ArgumentConfig) -> Result<Trash, BackupError> {
+    match &args.trash_dir {
+        Some(dir) => Ok(Trash::new(dir)),
+        None => Trash::home().ok_or_else(|| {
+            BackupError::InvalidOption(
+                "Neither XDG_DATA_HOME nor HOME is set, pass --trash-dir".to_owned(),
+            )
+        }),
+    }
+}
+
+/// Loads the identities that decrypt archives from the key files given with
+/// `--identity`, or else from `AGE_IDENTITY`, which holds either the path of
+/// a key file or an identity itself.
+fn identities(args: &ArgumentConfig) -> Result<Vec<Identity>, BackupError> {
+    if !args.identities.is_empty() {
+        let loaded = args
+            .identities
+            .iter()
+            .map(|path| Identity::load(Path::new(path)));
+        return Ok(loaded.collect::<Result<Vec<_>, _>>()?.concat());
+    }
+    match env::var("AGE_IDENTITY") {
+        Ok(key) if key.starts_with("AGE-SECRET-KEY-") => match Identity::parse(key.trim()) {
+            Some(identity) => Ok(vec![identity]),
+            None => Err(BackupError::InvalidOption(
+                "AGE_IDENTITY does not hold a valid age identity".to_owned(),
+            )),
+        },
+        Ok(path) if !path.is_empty() => Identity::load(Path::new(&path)),
+        _ => Ok(Vec::new()),
+    }
+}
+
+/// Reads the public key that backups must be signed by from `--verify-key`,
+/// or else from `BACKUP_VERIFY_KEY`, either the key itself or the path of
+/// a file holding it.
+fn verify_key(args: &ArgumentConfig) -> Result<Option<VerifyingKey>, BackupError> {
+    let key = match (&args.verify_key, env::var("BACKUP_VERIFY_KEY")) {
+        (Some(key), _) => key.clone(),
+        (None, Ok(key)) if !key.is_empty() => key,
+        _ => return Ok(None),
+    };
+    VerifyingKey::load(&key).map(Some)
+}
+
+/// Reads the passphrase given with `--passphrase-file`, the first line of
+/// the file, or else with `BACKUP_PASSPHRASE`.
+fn given_passphrase(args: &ArgumentConfig) -> Result<Option<Passphrase>, BackupError> {
+    if let Some(path) = &args.passphrase_file {
+        let text = fs::read_to_string(path).map_err(|source| BackupError::ReadFailed {
+            path: PathBuf::from(path),
+            source,
+        })?;
+        let line = text.lines().next().unwrap_or_default();
+        return Passphrase::new(line.to_owned()).map(Some);
+    }
+    match env::var("BACKUP_PASSPHRASE") {
+        Ok(text) => {
+            console::log(
+                Level::Warning,
+                "BACKUP_PASSPHRASE can be read by other processes of the same user, \
+                 prefer --passphrase-file",
+            );
+            Passphrase::new(text).map(Some)
+        }
+        Err(_) => Ok(None),
+    }
+}
+
+/// Asks for the passphrase after `prompt` when stdin is a terminal.
+fn ask_passphrase(prompt: &str) -> Result<Passphrase, BackupError> {
+    if !io::stdin().is_terminal() {
+        return Err(BackupError::InvalidOption(
+            "No terminal to ask for the passphrase, pass --passphrase-file or BACKUP_PASSPHRASE"
+                .to_owned(),
+        ));
+    }
+    let passphrase = console::ask_passphrase(prompt).map_err(|source| BackupError::ReadFailed {
+        path: PathBuf::from("-"),
+        source,
+    })?;
+    Passphrase::new(passphrase)
+}
+
+/// Returns the passphrase to encrypt archives with, asking for it twice if
+/// not given, so that a typo does not make the backup unrecoverable.
+fn backup_passphrase(args: &ArgumentConfig) -> Result<Passphrase, BackupError> {
+    if let Some(passphrase) = given_passphrase(args)? {
+        return Ok(passphrase);
+    }
+    let passphrase = ask_passphrase("Passphrase")?;
+    if ask_passphrase("Passphrase again")? != passphrase {
+        return Err(BackupError::InvalidOption(
+            "The passphrases entered differ".to_owned(),
+        ));
+    }
+    Ok(passphrase)
+}
+
+/// Returns the passphrase to decrypt an archive with, asking for it once if
+/// not given and stdin is a terminal.
+fn restore_passphrase(args: &ArgumentConfig) -> Result<Option<Passphrase>, BackupError> {
+    match given_passphrase(args)? {
+        Some(passphrase) => Ok(Some(passphrase)),
+        None if io::stdin().is_terminal() => ask_passphrase("Passphrase").map(Some),
+        None => Ok(None),
+    }
+}
+
+/// Appends `operation` to the journal, only warning if it cannot be written.
+fn record(operation: &Operation) {
+    let Some(path) = journal::path() else {
+        return;
+    };
+    if let Err(error) = journal::record(&path, operation) {
+        console::log(
+            Level::Warning,
+            format_args!("{error}, the operation is not in the history"),
+        );
+    }
+}
+
+/// Runs the backup requested on the command line on the schedule given with
+/// `--every` and `--at` until interrupted, writing the outcome of each run to
+/// the `--status-file` if given.
+///
+/// A failing run is reported and the next one happens as scheduled, but
+/// errors in the options end the schedule at once.
+fn run_scheduled(args: &ArgumentConfig) -> Result<ExitCode, BackupError> {
+    let from_stdin = args.sources.iter().any(|source| source == "-");
+    if args.dry_run || from_stdin || args.target.as_deref() == Some("-") {
+        return Err(BackupError::InvalidOption(
+            "--every and --at cannot be used with --dry-run, stdin or stdout".to_owned(),
+        ));
+    }
+    let schedule = Schedule::new(args.every, args.at)?;
+
+    let mut due = schedule.first(Local::now());
+    loop {
+        if due > Local::now() {
+            console::log(
+                Level::Info,
+                format_args!("next backup at {}", due.format(console::DISPLAY_FORMAT)),
+            );
+        }
+        if !schedule::sleep_until(due) {
+            break;
+        }
+        let started = Local::now();
+        let elapsed = Instant::now();
+        let (status, error) = match run(args) {
+            Ok(status) => (status, None),
+            Err(error @ BackupError::Interrupted) => return Err(error),
+            Err(error) if error.exit_code() == ExitCode::Usage => return Err(error),
+            Err(error) => {
+                let level = match error {
+                    BackupError::Unchanged(_) => Level::Info,
+                    _ => Level::Error,
+                };
+                console::report(level, &error);
+                (error.exit_code(), Some(error))
+            }
+        };
+        notify(args, status, error.as_ref());
+        write_metrics(args, status, elapsed.elapsed());
+        let finished = Local::now();
+        due = schedule.next(due, finished);
+        if let Some(path) = &args.status_file {
+            let status = RunStatus {
+                status: status.code(),
+                started,
+                finished,
+                next: due,
+                error: error.as_ref().map(ToString::to_string),
+                code: error.as_ref().map(BackupError::code),
+            };
+            if let Err(error) = status.write(Path::new(path)) {
+                console::report(Level::Error, &error);
+            }
+        }
+    }
+    console::log(Level::Info, "stopped the schedule");
+    Ok(ExitCode::Success)
+}
+
+/// Runs the `--notify-cmd`, if given, for a backup that finished with
+/// `status` or failed with `error`. The command failing is reported without
+/// changing the exit status.
+fn notify(args: &ArgumentConfig, status: ExitCode, error: Option<&BackupError>) {
+    let Some(command) = &args.notify_cmd else {
+        return;
+    };
+    let source = match args.sources.is_empty() {
+        true => args.source.clone().unwrap_or_else(|| ".".to_owned()),
+        false => args.sources.join(", "),
+    };
+    let target = match args.targets.len() > 1 {
+        true => args.targets.join(", "),
+        false => args
+            .target
+            .clone()
+            .or_else(|| args.pipe_to.clone())
+            .unwrap_or_else(|| ".".to_owned()),
+    };
+    let notification = Notification {
+        status,
+        source: &source,
+        target: &target,
+        error,
+    };
+    if let Err(error) = Notifier::new(command, args.notify_on).notify(&notification) {
+        console::report(Level::Warning, &error);
+    }
+}
+
+/// Writes the outcome of a backup that finished with `status` after
+/// `duration` into the `--metrics-file`, if given, labeled with the profile
+/// run or the sources. Failing to is reported without changing the exit
+/// status.
+fn write_metrics(args: &ArgumentConfig, status: ExitCode, duration: Duration) {
+    let Some(path) = &args.metrics_file else {
+        return;
+    };
+    if args.dry_run {
+        return;
+    }
+    let sources = match args.sources.is_empty() {
+        true => vec![args.source.as_deref().unwrap_or(".")],
+        false => args.sources.iter().map(String::as_str).collect(),
+    };
+    let label = match &args.profile {
+        Some(profile) => ("profile", profile.clone()),
+        None => {
+            let sources: Vec<_> = sources
+                .iter()
+                .map(|source| match *source {
+                    "-" => "-".to_owned(),
+                    source => absolute(Path::new(source)).display().to_string(),
+                })
+                .collect();
+            ("source", sources.join(", "))
+        }
+    };
+    let succeeded = status.is_success();
+    let (files, bytes) = WRITTEN.with(|written| written.take());
+    let metrics = RunMetrics {
+        labels: &[(label.0, &label.1)],
+        succeeded,
+        finished: SystemTime::now(),
+        duration,
+        bytes,
+        files,
+    };
+    if let Err(error) = metrics::write(Path::new(path), &metrics) {
+        console::report(Level::Warning, &error);
+    }
+}
+
+/// Counts `files` and `bytes` written by a backup for the `--metrics-file`.
+fn tally(files: u64, bytes: u64) {
+    WRITTEN.with(|written| {
+        let (all_files, all_bytes) = written.get();
+        written.set((all_files + files, all_bytes + bytes));
+    });
+}
+
+/// Executes the operation requested on the command line, returning the exit
+/// status.
+fn run(args: &ArgumentConfig) -> Result<ExitCode, BackupError> {
+    let source = args.source.as_deref().unwrap_or(".");
+
+    match args.mode {
+        Mode::Backup | Mode::Run | Mode::Watch => {
+            let target = Path::new(args.target.as_deref().unwrap_or("."));
+            let mut excludes = args.excludes.clone();
+            for path in &args.exclude_from {
+                excludes.extend(Excludes::read(Path::new(path))?);
+            }
+            let algorithm = args.algorithm.as_deref().map(Algorithm::parse);
+            let algorithm = algorithm.transpose()?.unwrap_or_default();
+            let reflink = args.reflink.as_deref().map(Reflink::parse);
+            let fsync = args.fsync.as_deref().map(Fsync::parse);
+            let special_files = args.special_files.as_deref().map(SpecialFiles::parse);
+            let options = BackupOptions::new()
+                .force(args.force)
+                .dereference(args.dereference)
+                .preserve(args.preserve)
+                .preserve_owner(args.preserve_owner)
+                .ignore_files(args.ignore_file)
+                .exclude_vcs(args.exclude_vcs)
+                .exclude_caches(args.exclude_caches)
+                .max_depth(args.max_depth.map(NonZeroUsize::get))
+                .max_files(args.max_files.map(NonZeroU64::get))
+                .min_file_size(args.min_file_size)
+                .max_file_size(args.max_file_size)
+                .as_file(args.as_file)
+                .checksum(Some(algorithm))
+                .incremental(args.incremental)
+                .compare_checksums(args.compare_checksums)
+                .skip_unchanged(args.skip_unchanged)
+                .mirror(args.mirror)
+                .delete(args.delete)
+                .init(args.init)
+                .keep_going(args.keep_going)
+                .space_check(args.space_check)
+                .wait_lock(Duration::from_secs(args.wait_lock.unwrap_or(0)))
+                .retries(args.retries)
+                .reflink(reflink.transpose()?.unwrap_or_default())
+                .fsync(fsync.transpose()?.unwrap_or_default())
+                .bwlimit(args.bwlimit)
+                .special_files(special_files.transpose()?.unwrap_or_default())
+                .compress(Compression::resolve(
+                    args.compress.as_deref(),
+                    args.level,
+                    target,
+                )?);
+            let options = match args.xattrs {
+                Some(xattrs) => options.xattrs(xattrs),
+                None => options,
+            };
+            let options = match args.jobs {
+                Some(jobs) => options.jobs(jobs.get()),
+                None => options,
+            };
+            let options = match args.buffer_size {
+                Some(size) => options.buffer_size(size.get()),
+                None => options,
+            };
+            let options = match args.retry_delay {
+                Some(delay) => options.retry_delay(delay),
+                None => options,
+            };
+            let options = excludes
+                .iter()
+                .fold(options, |options, pattern| options.exclude(pattern));
+            if args.encrypt == args.recipients.is_empty() {
+                return Err(BackupError::InvalidOption(
+                    "--encrypt requires at least one --recipient, and --recipient requires --encrypt"
+                        .to_owned(),
+                ));
+            }
+            let options = args
+                .recipients
+                .iter()
+                .try_fold(options, |options, recipient| {
+                    Ok::<_, BackupError>(options.recipient(recipient.parse()?))
+                })?;
+            if !args.encrypt_passphrase
+                && (args.passphrase_file.is_some() || args.work_factor.is_some())
+            {
+                return Err(BackupError::InvalidOption(
+                    "--passphrase-file and --work-factor require --encrypt-passphrase".to_owned(),
+                ));
+            }
+            let options = match args.encrypt_passphrase {
+                true => options.passphrase(backup_passphrase(args)?),
+                false => options,
+            };
+            let options = match &args.sign {
+                Some(path) => options.sign(SigningKey::load(Path::new(path))?),
+                None => options,
+            };
+            let options = match args.split {
+                Some(size) => options.split(size),
+                None => options,
+            };
+            let options = match args.work_factor {
+                Some(factor) if !(1..=age::MAX_WORK_FACTOR).contains(&factor) => {
+                    return Err(BackupError::InvalidOption(format!(
+                        "Invalid work factor {factor}, expected 1 to {}",
+                        age::MAX_WORK_FACTOR
+                    )))
+                }
+                Some(factor) => options.work_factor(factor),
+                None => options,
+            };
+            let sources = match args.sources.is_empty() && args.files_from.is_none() {
+                true => vec![PathBuf::from(source)],
+                false => expand_sources(args)?,
+            };
+            if (args.delete || args.init) && !args.mirror {
+                return Err(BackupError::InvalidOption(
+                    "--delete and --init require --mirror".to_owned(),
+                ));
+            }
+            let from_stdin = sources.iter().any(|source| source == Path::new("-"));
+            if args.mirror && (sources.len() != 1 || from_stdin || target == Path::new("-")) {
+                return Err(BackupError::InvalidOption(
+                    "--mirror requires a single source directory and a target directory".to_owned(),
+                ));
+            }
+            if let Some(command) = &args.pipe_to {
+                if args.target.is_some() {
+                    return Err(BackupError::InvalidOption(
+                        "--pipe-to cannot be used with a target".to_owned(),
+                    ));
+                }
+                let pipe = Pipe::new(command);
+                return back_up_remote(args, &sources, |source| {
+                    backup::backup::backup_pipe(source, &pipe, &options)
+                });
+            }
+            if args.mode == Mode::Watch {
+                return watch(args, Path::new(source), target, &options);
+            }
+            if args.targets.len() > 1 {
+                return back_up_to_targets(args, &sources, &options);
+            }
+            if target == Path::new("-") && args.sign.is_some() {
+                return Err(BackupError::InvalidOption(
+                    "--sign requires a target directory, not stdout".to_owned(),
+                ));
+            }
+            if target == Path::new("-") && args.split.is_some() {
+                return Err(BackupError::InvalidOption(
+                    "--split requires a target directory, not stdout".to_owned(),
+                ));
+            }
+            if target == Path::new("-") {
+                return stream_backup(args, &sources, &options);
+            }
+            if let Some(bucket) = args
+                .target
+                .as_deref()
+                .and_then(|target| bucket(args, target))
+            {
+                return back_up_remote(args, &sources, |source| {
+                    backup::backup::backup_s3(source, &bucket, &options)
+                });
+            }
+            if let Some(remote) = args
+                .target
+                .as_deref()
+                .and_then(|target| remote(args, target))
+            {
+                return back_up_remote(args, &sources, |source| {
+                    backup::backup::backup_remote(source, &remote, &options)
+                });
+            }
+            if from_stdin {
+                return back_up_stdin(args, &sources, target, &options);
+            }
+            let source = match sources.as_slice() {
+                [] => return Ok(ExitCode::Success),
+                [source] => source.as_path(),
+                _ => return back_up_all(args, &sources, target, &options),
+            };
+            if args.dry_run {
+                let plan = backup::backup::plan(source, target, &options)?;
+                if args.json {
+                    console::print_outcome(&Outcome::Plan {
+                        source: &absolute(source),
+                        backup_path: &absolute(&plan.destination),
+                        bytes: plan.bytes,
+                        files: plan.files,
+                        blocked: plan.is_blocked(),
+                    });
+                } else {
+                    console::out(&plan);
+                }
+                if let Some(previous) = plan.unchanged_since {
+                    return Err(BackupError::Unchanged(previous));
+                }
+                if plan.is_blocked() && args.mirror {
+                    return Err(BackupError::NotAMirror(plan.destination));
+                }
+                if plan.is_blocked() {
+                    return Err(BackupError::AlreadyExists(plan.destination));
+                }
+            } else {
+                let report = backup::backup(source, target, &options)?;
+                tally(report.files, report.bytes);
+                // Undoing a mirror would remove all of it.
+                if !args.mirror {
+                    record(&Operation::backup(source, &report));
+                }
+                if args.json {
+                    console::print_outcome(&Outcome::Backup {
+                        source: &absolute(source),
+                        backup_path: &printed_path(&report.path),
+                        bytes: report.bytes,
+                        files: report.files,
+                        duration_ms: report.duration.as_millis(),
+                        stats: &report.stats,
+                    });
+                } else {
+                    console::out(printed_path(&report.path).display());
+                    console::log(Level::Info, report.stats);
+                }
+                if !report.failed.is_empty() {
+                    console::print_failures(&report.failed);
+                    return Err(BackupError::PartialCopy {
+                        path: source.to_path_buf(),
+                        failures: report.failed.len(),
+                    });
+                }
+            }
+        }
+        Mode::Restore if args.list => list_contents(Path::new(source), args.json)?,
+        Mode::Restore if args.pipe_from.is_some() => {
+            if args.latest.is_some() || args.from.is_some() {
+                return Err(BackupError::InvalidOption(
+                    "--latest and --from cannot be used with --pipe-from".to_owned(),
+                ));
+            }
+            if let Some(command) = &args.pipe_from {
+                let pipe = Pipe::new(command);
+                restore_remote(args, Path::new(source), |target, options| {
+                    restore::restore_pipe(&pipe, source, target, options)
+                })?;
+            }
+        }
+        Mode::Restore if source == "-" => {
+            let target = args.target.as_deref().ok_or_else(|| {
+                BackupError::InvalidOption("Restoring from stdin requires a target".to_owned())
+            })?;
+            if !args.paths.is_empty() {
+                return Err(BackupError::InvalidOption(
+                    "--path cannot be used when restoring from stdin".to_owned(),
+                ));
+            }
+            let options = RestoreOptions::new()
+                .force(args.force)
+                .preserve(args.preserve)
+                .preserve_owner(args.preserve_owner)
+                .safety(args.safety);
+            let options = identities(args)?
+                .into_iter()
+                .fold(options, RestoreOptions::identity);
+            let options = match args.xattrs {
+                Some(xattrs) => options.xattrs(xattrs),
+                None => options,
+            };
+            let options = match given_passphrase(args)? {
+                Some(passphrase) => options.passphrase(passphrase),
+                None => options,
+            };
+            let options = match verify_key(args)? {
+                Some(key) => options.verify_key(key),
+                None => options,
+            };
+            let options = options.verify_signature(args.verify_signature);
+            let started = Instant::now();
+            let report = restore::restore_stream(io::stdin().lock(), Path::new(target), &options)?;
+            if args.json {
+                console::print_outcome(&Outcome::Restore {
+                    source: Path::new("-"),
+                    restore_path: &absolute(&report.path),
+                    bytes: report.bytes,
+                    files: report.files,
+                    duration_ms: started.elapsed().as_millis(),
+                    safety_path: report.safety.as_deref().map(absolute).as_deref(),
+                    stats: &report.stats,
+                });
+            } else {
+                console::out(absolute(&report.path).display());
+                console::log(Level::Info, report.stats);
+            }
+        }
+        Mode::Restore
+            if args.latest.is_none() && args.from.is_none() && bucket(args, source).is_some() =>
+        {
+            if let Some(bucket) = bucket(args, source) {
+                restore_remote(args, &bucket.display_path(), |target, options| {
+                    restore::restore_s3(&bucket, target, options)
+                })?;
+            }
+        }
+        Mode::Restore
+            if args.latest.is_none() && args.from.is_none() && remote(args, source).is_some() =>
+        {
+            if let Some(remote) = remote(args, source) {
+                restore_remote(args, &remote.display_path(), |target, options| {
+                    restore::restore_remote(&remote, target, options)
+                })?;
+            }
+        }
+        Mode::Restore => {
+            let options = RestoreOptions::new()
+                .force(args.force)
+                .strict(args.strict)
+                .preserve(args.preserve)
+                .preserve_owner(args.preserve_owner)
+                .original_path(args.original_path)
+                .verify(args.verify)
+                .verify_signature(args.verify_signature)
+                .safety(args.safety);
+            let options = match verify_key(args)? {
+                Some(key) => options.verify_key(key),
+                None => options,
+            };
+            let options = identities(args)?
+                .into_iter()
+                .fold(options, RestoreOptions::identity);
+            let options = match args.xattrs {
+                Some(xattrs) => options.xattrs(xattrs),
+                None => options,
+            };
+            let options = args
+                .paths
+                .iter()
+                .fold(options, |options, pattern| options.path(pattern));
+            let mut target = args.target.as_deref().map(Path::new);
+            let backup = match (&args.latest, &args.from) {
+                (Some(name), from) => {
+                    let dir = Path::new(from.as_deref().unwrap_or("."));
+                    target = args.source.as_deref().map(Path::new);
+                    restore::latest(dir, Path::new(name))?
+                }
+                (None, Some(dir)) => {
+                    choose_version(Path::new(dir), Path::new(source), args.select)?
+                }
+                (None, None) if args.select.is_some() => {
+                    return Err(BackupError::InvalidOption(
+                        "--select requires --from and the name of the backed up file".to_owned(),
+                    ));
+                }
+                (None, None) => PathBuf::from(source),
+            };
+            let passphrase = match age::needs_passphrase(&backup) {
+                true => restore_passphrase(args)?,
+                false => None,
+            };
+            let options = match passphrase {
+                Some(passphrase) => options.passphrase(passphrase),
+                None => options,
+            };
+            let started = Instant::now();
+            let report = backup::restore(&backup, target, &options)?;
+            record(&Operation::restore(
+                &backup,
+                &report,
+                !args.paths.is_empty(),
+            ));
+            if args.json {
+                console::print_outcome(&Outcome::Restore {
+                    source: &absolute(&backup),
+                    restore_path: &absolute(&report.path),
+                    bytes: report.bytes,
+                    files: report.files,
+                    duration_ms: started.elapsed().as_millis(),
+                    safety_path: report.safety.as_deref().map(absolute).as_deref(),
+                    stats: &report.stats,
+                });
+            } else {
+                console::out(absolute(&report.path).display());
+                console::log(Level::Info, report.stats);
+            }
+        }
+        Mode::History => {
+            let operations = journal::read(&journal_path()?)?;
+            if args.json {
+                console::print_json(&operations);
+            } else {
+                console::print_history(&operations);
+            }
+        }
+        Mode::Undo => {
+            let undone = journal::undo(&journal_path()?)?;
+            if args.json {
+                console::print_outcome(&Outcome::Undo { undone: &undone });
+                return Ok(ExitCode::Success);
+            }
+            console::log(
+                Level::Success,
+                format_args!(
+                    "undid the {} of '{}' to '{}'",
+                    undone.action.as_str(),
+                    undone.source.display(),
+                    undone.destination.display()
+                ),
+            );
+        }
+        Mode::List => {
+            let entries = match bucket(args, source) {
+                Some(prefix) => list::list_s3(&prefix, args.name.as_deref(), args.all)?,
+                None => list::list(Path::new(source), args.name.as_deref(), args.all)?,
+            };
+            if args.json {
+                console::print_json(&entries);
+            } else {
+                console::print_table(&entries);
+            }
+        }
+        Mode::ListContents => list_contents(Path::new(source), args.json)?,
+        Mode::Prune if bucket(args, source).is_some() => {
+            if args.trash || args.trash_dir.is_some() {
+                return Err(BackupError::InvalidOption(
+                    "--trash and --trash-dir cannot be used with an S3 bucket".to_owned(),
+                ));
+            }
+            let Some(prefix) = bucket(args, source) else {
+                return Ok(ExitCode::Success);
+            };
+            let report = prune::prune_s3(&prefix, &args.retention, args.dry_run)?;
+            if args.json {
+                console::print_outcome(&Outcome::Prune {
+                    source: &prefix.display_path(),
+                    removed: &report.removed,
+                    bytes: report.freed,
+                    dry_run: args.dry_run,
+                });
+            }
+        }
+        Mode::Prune => {
+            let dir = Path::new(source);
+            let trash = match args.trash || args.trash_dir.is_some() {
+                true => Some(trash(args)?),
+                false => None,
+            };
+            let report = prune::prune(dir, &args.retention, args.dry_run, trash.as_ref())?;
+            if args.json {
+                let removed: Vec<_> = report.removed.iter().map(|path| absolute(path)).collect();
+                console::print_outcome(&Outcome::Prune {
+                    source: &absolute(dir),
+                    removed: &removed,
+                    bytes: report.freed,
+                    dry_run: args.dry_run,
+                });
+            }
+        }
+        Mode::Gc if !args.empty_trash => {
+            let dir = Path::new(args.source.as_deref().ok_or_else(|| {
+                BackupError::InvalidOption("Gc requires a directory or --empty-trash".to_owned())
+            })?);
+            let partial_age = args.retention.older_than.unwrap_or(DEFAULT_PARTIAL_AGE);
+            let garbage = gc::find(dir, partial_age)?;
+            let confirmed = garbage.is_empty() || args.dry_run || args.yes;
+            if !confirmed && !console::confirm_removal(&garbage) {
+                console::log(Level::Info, "nothing removed");
+                return Ok(ExitCode::Skipped);
+            }
+            let report = gc::remove(&garbage, args.dry_run)?;
+            if args.json {
+                let removed: Vec<_> = report.removed.iter().map(|path| absolute(path)).collect();
+                console::print_outcome(&Outcome::Gc {
+                    source: &absolute(dir),
+                    removed: &removed,
+                    bytes: report.freed,
+                    dry_run: args.dry_run,
+                });
+            }
+        }
+        Mode::Gc => {
+            if args.source.is_some() {
+                return Err(BackupError::InvalidOption(
+                    "--empty-trash cannot be given a directory".to_owned(),
+                ));
+            }
+            let trash = trash(args)?;
+            let report = trash.empty(args.retention.older_than, args.dry_run)?;
+            if args.json {
+                let removed: Vec<_> = report.removed.iter().map(|path| absolute(path)).collect();
+                console::print_outcome(&Outcome::Gc {
+                    source: &absolute(trash.dir()),
+                    removed: &removed,
+                    bytes: report.freed,
+                    dry_run: args.dry_run,
+                });
+            }
+        }
+        Mode::Catalog => {
+            if source != "rebuild" {
+                return Err(BackupError::InvalidOption(
+                    "Expected 'catalog rebuild [dir]'".to_owned(),
+                ));
+            }
+            let dir = Path::new(args.target.as_deref().unwrap_or("."));
+            let backups = catalog::rebuild(dir)?;
+            let path = catalog::path(dir);
+            if args.json {
+                console::print_outcome(&Outcome::Catalog {
+                    path: &absolute(&path),
+                    backups,
+                });
+                return Ok(ExitCode::Success);
+            }
+            console::log(
+                Level::Success,
+                format_args!("{}: {backups} backups cataloged", path.display()),
+            );
+        }
+        Mode::Doctor => {
+            let dir = Path::new(
+                args.source
+                    .as_deref()
+                    .or(args.target.as_deref())
+                    .unwrap_or("."),
+            );
+            let checks = doctor::diagnose(dir, args.config_path().as_deref());
+            if args.json {
+                console::print_outcome(&Outcome::Doctor {
+                    dir: &absolute(dir),
+                    checks: &checks,
+                });
+            } else {
+                console::print_checks(&checks);
+            }
+            return Ok(match checks.iter().map(|check| check.status).max() {
+                Some(Status::Fail) => ExitCode::Failure,
+                Some(Status::Warn) => ExitCode::Partial,
+                _ => ExitCode::Success,
+            });
+        }
+        Mode::Verify if bucket(args, source).is_some() => {
+            if verify_key(args)?.is_some() {
+                return Err(BackupError::InvalidOption(
+                    "Signatures cannot be checked in an S3 bucket".to_owned(),
+                ));
+            }
+            let Some(object) = bucket(args, source) else {
+                return Ok(ExitCode::Success);
+            };
+            let report = verify::verify_s3(&object)?;
+            if args.json {
+                console::print_outcome(&Outcome::Verify {
+                    source: &object.display_path(),
+                    manifest: &report.manifest,
+                    files: report.files,
+                    signed_by: None,
+                });
+            }
+        }
+        Mode::Verify => {
+            let signed_by = verify_key(args)?;
+            if let Some(key) = &signed_by {
+                sign::check(Path::new(source), key)?;
+                console::log(
+                    Level::Success,
+                    format_args!("signature: OK, signed by {key}"),
+                );
+            }
+            let report = verify::verify(Path::new(source))?;
+            if args.json {
+                console::print_outcome(&Outcome::Verify {
+                    source: &absolute(Path::new(source)),
+                    manifest: &absolute(&report.manifest),
+                    files: report.files,
+                    signed_by: signed_by.map(|key| key.to_string()),
+                });
+            }
+        }
+        Mode::Keygen => {
+            let path = Path::new(source);
+            let key = SigningKey::generate().map_err(|source| BackupError::CreateFailed {
+                path: path.to_path_buf(),
+                source,
+            })?;
+            key.write(path, args.force)?;
+            let public_key = key.verifying_key().to_string();
+            if args.json {
+                console::print_outcome(&Outcome::Keygen {
+                    path: &absolute(path),
+                    public_key_path: &absolute(&sign::public_path(path)),
+                    public_key: &public_key,
+                });
+                return Ok(ExitCode::Success);
+            }
+            console::out(public_key);
+            console::log(
+                Level::Success,
+                format_args!(
+                    "wrote the secret key to {} and the public key to {}",
+                    path.display(),
+                    sign::public_path(path).display()
+                ),
+            );
+        }
+        Mode::Diff => {
+            let backup = Path::new(source);
+            let current = Path::new(args.target.as_deref().unwrap_or("."));
+            let differences = diff::diff(backup, current, args.compare_checksums)?;
+            if args.json {
+                console::print_json(&differences);
+            } else {
+                console::print_diff(backup, current, &differences);
+            }
+            if !differences.is_empty() {
+                return Ok(ExitCode::Failure);
+            }
+        }
+        Mode::Config => {
+            if source != "check" {
+                return Err(BackupError::InvalidOption(
+                    "Expected 'config check'".to_owned(),
+                ));
+            }
+            let path = args.config_path().ok_or_else(|| {
+                BackupError::InvalidOption(
+                    "Neither XDG_CONFIG_HOME nor HOME is set, pass --config".to_owned(),
+                )
+            })?;
+            let config = Config::load(&path)?;
+            if args.json {
+                console::print_outcome(&Outcome::Config {
+                    path: &path,
+                    profiles: config.profiles.len(),
+                });
+                return Ok(ExitCode::Success);
+            }
+            console::log(
+                Level::Success,
+                format_args!("{}: OK, {} profiles", path.display(), config.profiles.len()),
+            );
+        }
+        Mode::SystemdInstall => {
+            let path = args.config_path().ok_or_else(|| {
+                BackupError::InvalidOption(
+                    "Neither XDG_CONFIG_HOME nor HOME is set, pass --config".to_owned(),
+                )
+            })?;
+            Config::load(&path)?.profile(source)?;
+            let program = env::current_exe().unwrap_or_else(|_| PathBuf::from("backup"));
+            let mut command = vec![
+                program.to_string_lossy().into_owned(),
+                "run".to_owned(),
+                source.to_owned(),
+            ];
+            if args.config.is_some() {
+                command.push("--config".to_owned());
+                command.push(absolute(&path).to_string_lossy().into_owned());
+            }
+            let calendar = args.on_calendar.as_deref().unwrap_or(DEFAULT_CALENDAR);
+            let units = Units::render(source, &command, calendar)?;
+            if args.stdout {
+                console::out(format_args!(
+                    "# {}\n{}\n# {}\n{}",
+                    units.service_name(),
+                    units.service,
+                    units.timer_name(),
+                    units.timer.trim_end_matches('\n')
+                ));
+                return Ok(ExitCode::Success);
+            }
+            let installed = units.install(&systemd_dir()?, args.force, args.dry_run)?;
+            if args.json {
+                console::print_outcome(&Outcome::SystemdInstall {
+                    profile: source,
+                    units: &installed,
+                    dry_run: args.dry_run,
+                });
+                return Ok(ExitCode::Success);
+            }
+            let verb = if args.dry_run { "would write" } else { "wrote" };
+            for path in &installed {
+                console::log(Level::Success, format_args!("{verb} {}", path.display()));
+            }
+            console::log(
+                Level::Info,
+                format_args!(
+                    "start the timer with: systemctl --user daemon-reload && \
+                     systemctl --user enable --now '{}'",
+                    units.timer_name()
+                ),
+            );
+        }
+        Mode::SystemdUninstall => {
+            let removed = systemd::uninstall(&systemd_dir()?, source, args.dry_run)?;
+            if args.json {
+                console::print_outcome(&Outcome::SystemdUninstall {
+                    profile: source,
+                    units: &removed,
+                    dry_run: args.dry_run,
+                });
+                return Ok(ExitCode::Success);
+            }
+            let verb = if args.dry_run {
+                "would remove"
+            } else {
+                "removed"
+            };
+            for path in &removed {
+                console::log(Level::Success, format_args!("{verb} {}", path.display()));
+            }
+        }
+        Mode::Version => {
+            let built = DateTime::from_timestamp(BUILD_EPOCH.parse().unwrap_or_default(), 0)
+                .unwrap_or_default();
+            let version = Version {
+                version: env!("CARGO_PKG_VERSION"),
+                commit: env!("BACKUP_COMMIT"),
+                built: built.format("%Y-%m-%d").to_string(),
+                compression: &Compression::NAMES,
+                checksums: Algorithm::ALL.map(Algorithm::extension),
+            };
+            match args.json {
+                true => console::print_outcome(&Outcome::Version(version)),
+                false => console::print_version(&version),
+            }
+        }
+        Mode::Help => match &args.source {
+            Some(name) => {
+                let command = cli::Command::find(name)
+                    .ok_or_else(|| BackupError::InvalidOption(cli::unknown_command(name)))?;
+                console::command_usage(command);
+            }
+            None => console::usage(),
+        },
+    }
+
+    Ok(ExitCode::Success)
 }
@@ -0,0 +1,122 @@
+//! Metadata files describing backups, written next to them.
+//!
+//! The metadata of a backup is stored as JSON in a file named after it with a
+//! `.meta.json` extension, e.g. `hosts.2024-05-01_10-00-00.backup.meta.json`.
+//! It is informative only: backups without one, such as those made by older
+//! versions, are restored from their name alone.
+
+use std::ffi::OsString;
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+use chrono::{DateTime, FixedOffset};
+use serde::{Deserialize, Serialize};
+
+use crate::backup::BackupType;
+use crate::error::BackupError;
+
+/// Extension appended to a backup path to name its metadata file.
+pub const META_EXTENSION: &str = "meta.json";
+
+/// Description of a backup, recorded when it is created.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BackupMeta {
+    /// Absolute path of the file or directory that was backed up.
+    pub source: PathBuf,
+    /// Time at which the backup was created, in RFC 3339 format.
+    pub created: DateTime<FixedOffset>,
+    /// Version of the tool that created the backup.
+    pub version: String,
+    /// Kind of backup performed.
+    pub backup_type: BackupType,
+    /// Total size in bytes, which is the archive size for archives.
+    pub size: u64,
+    /// Number of files backed up.
+    pub files: u64,
+    /// Checksum of the backup, if one was recorded.
+    pub checksum: Option<Checksum>,
+    /// Parts of an archive split with `--split`, in order, see
+    /// [`crate::split`].
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub parts: Vec<Part>,
+}
+
+/// Checksum summarizing a backup.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Checksum {
+    /// Name of the hash algorithm, as accepted by `--algorithm`.
+    pub algorithm: String,
+    /// Hex digest of the backup file or archive, or of the checksum manifest
+    /// for directory copies.
+    pub digest: String,
+}
+
+/// Part of a split archive.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Part {
+    /// File name of the part, next to the metadata file.
+    pub name: String,
+    /// Size of the part in bytes.
+    pub size: u64,
+    /// Hex digest of the part with the algorithm of [`Checksum`], if one
+    /// was recorded.
+    pub digest: Option<String>,
+}
+
+/// Returns the path of the metadata file of `backup`.
+pub fn path(backup: &Path) -> PathBuf {
+    let mut name = OsString::from(backup.as_os_str());
+    name.push(".");
+    name.push(META_EXTENSION);
+    PathBuf::from(name)
+}
+
+/// Reads the metadata of `backup`, returning `None` if it has none.
+pub fn read(backup: &Path) -> Result<Option<BackupMeta>, BackupError> {
+    let path = path(backup);
+    let contents = match fs::read(&path) {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(source) => return Err(BackupError::ReadFailed { path, source }),
+    };
+
+    serde_json::from_slice(&contents)
+        .map(Some)
+        .map_err(|e| BackupError::ReadFailed {
+            path,
+            source: e.into(),
+        })
+}
+
+/// Writes `meta` next to `backup`, through a temporary file renamed into place.
+pub(crate) fn write(backup: &Path, meta: &BackupMeta) -> Result<(), BackupError> {
+    let path = path(backup);
+    let mut partial = OsString::from(path.as_os_str());
+    partial.push(".partial");
+    let partial = PathBuf::from(partial);
+
+    let written = serde_json::to_vec_pretty(meta)
+        .map_err(io::Error::from)
+        .and_then(|contents| {
+            let mut file = File::create(&partial)?;
+            file.write_all(&contents)?;
+            file.sync_all()
+        })
+        .and_then(|()| fs::rename(&partial, &path));
+    written.map_err(|source| {
+        let _ = fs::remove_file(&partial);
+        BackupError::CreateFailed { path, source }
+    })
+}
+
+/// Removes the metadata file of `backup`, if any.
+pub(crate) fn remove(backup: &Path) -> Result<(), BackupError> {
+    let path = path(backup);
+    match fs::remove_file(&path) {
+        Err(source) if source.kind() != io::ErrorKind::NotFound => {
+            Err(BackupError::RemoveFailed { path, source })
+        }
+        _ => Ok(()),
+    }
+}
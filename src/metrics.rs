@@ -0,0 +1,300 @@
+//! Metrics of the last backup, written for the textfile collector of the
+//! Prometheus node exporter.
+//!
+//! Each backup writes gauges labeled with what it backed up, such as
+//! `backup_last_run_bytes{source="/srv/data"}`, into a `.prom` file. Series
+//! of other sources or profiles, and lines of other metrics, are kept as they
+//! are, so that several backups can share the file. The file is rewritten
+//! under a lock and renamed into place, so that the collector never reads it
+//! half written.
+//!
+//! # Examples
+//!
+//! ```
+//! use std::time::{Duration, SystemTime};
+//!
+//! use backup::metrics::{self, RunMetrics};
+//!
+//! let dir = std::env::temp_dir().join("metrics-example");
+//! std::fs::create_dir_all(&dir)?;
+//! let path = dir.join("backup.prom");
+//! metrics::write(&path, &RunMetrics {
+//!     labels: &[("source", "/srv/data")],
+//!     succeeded: true,
+//!     finished: SystemTime::now(),
+//!     duration: Duration::from_secs(12),
+//!     bytes: 4096,
+//!     files: 3,
+//! })?;
+//! let text = std::fs::read_to_string(&path)?;
+//! assert!(text.contains("backup_last_run_bytes{source=\"/srv/data\"} 4096\n"));
+//! # Ok::<(), Box<dyn std::error::Error>>(())
+//! ```
+
+use std::ffi::OsString;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::time::{Duration, SystemTime};
+
+use crate::backup;
+use crate::error::BackupError;
+use crate::lock::Lock;
+
+/// Gauges written, with their help text.
+const GAUGES: [(&str, &str); 6] = [
+    (
+        "backup_last_run_timestamp_seconds",
+        "Time the last backup finished, in seconds since the Unix epoch.",
+    ),
+    (
+        "backup_last_success_timestamp_seconds",
+        "Time the last successful backup finished, in seconds since the Unix epoch.",
+    ),
+    (
+        "backup_last_run_duration_seconds",
+        "Time the last backup took, in seconds.",
+    ),
+    ("backup_last_run_bytes", "Bytes written by the last backup."),
+    ("backup_last_run_files", "Files written by the last backup."),
+    (
+        "backup_last_run_failed",
+        "Whether the last backup failed, 1 if it did and 0 otherwise.",
+    ),
+];
+
+/// Longest time waited for another backup writing the same file.
+const LOCK_WAIT: Duration = Duration::from_secs(10);
+
+/// Outcome of a backup, as written to the metrics file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RunMetrics<'a> {
+    /// Labels telling the series of this backup apart, such as its source.
+    pub labels: &'a [(&'a str, &'a str)],
+    pub succeeded: bool,
+    pub finished: SystemTime,
+    pub duration: Duration,
+    /// Bytes and files written, zero if the backup failed.
+    pub bytes: u64,
+    pub files: u64,
+}
+
+/// A sample read from or written to the metrics file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Sample {
+    name: String,
+    /// The labels as written between braces, without them.
+    labels: String,
+    value: String,
+}
+
+/// Writes `metrics` into the file at `path`, replacing the series with the
+/// same labels and keeping everything else, see the [module](self) docs.
+///
+/// A failed backup keeps the time of the last successful one.
+pub fn write(path: &Path, metrics: &RunMetrics) -> Result<(), BackupError> {
+    let mut lock_name = OsString::from(".");
+    lock_name.push(path.file_name().unwrap_or_default());
+    lock_name.push(".lock");
+    let _lock = Lock::acquire(&path.with_file_name(lock_name), LOCK_WAIT)?;
+
+    let text = match fs::read_to_string(path) {
+        Err(error) if error.kind() == io::ErrorKind::NotFound => String::new(),
+        text => text.map_err(|source| BackupError::ReadFailed {
+            path: path.to_path_buf(),
+            source,
+        })?,
+    };
+    let text = merge(&text, metrics);
+
+    let partial = backup::partial_path(path);
+    fs::write(&partial, text)
+        .and_then(|()| fs::rename(&partial, path))
+        .map_err(|source| {
+            let _ = fs::remove_file(&partial);
+            BackupError::CreateFailed {
+                path: path.to_path_buf(),
+                source,
+            }
+        })
+}
+
+/// Returns the contents of a metrics file `text` with the series of
+/// `metrics` replaced.
+fn merge(text: &str, metrics: &RunMetrics) -> String {
+    let labels = render_labels(metrics.labels);
+    let ours = |name: &str| GAUGES.iter().any(|(gauge, _)| *gauge == name);
+
+    let mut kept = String::new();
+    let mut samples = Vec::new();
+    for line in text.lines() {
+        let comment = line
+            .strip_prefix("# HELP ")
+            .or_else(|| line.strip_prefix("# TYPE "));
+        if let Some(comment) = comment {
+            if ours(comment.split(' ').next().unwrap_or_default()) {
+                continue;
+            }
+        }
+        match parse_sample(line) {
+            Some(sample) if ours(&sample.name) => samples.push(sample),
+            _ if line.trim().is_empty() => {}
+            _ => {
+                kept.push_str(line);
+                kept.push('\n');
+            }
+        }
+    }
+
+    let previous_success = samples
+        .iter()
+        .find(|sample| sample.name == GAUGES[1].0 && sample.labels == labels)
+        .map(|sample| sample.value.clone());
+    samples.retain(|sample| sample.labels != labels);
+    let finished = seconds(metrics.finished);
+    let success = match metrics.succeeded {
+        true => Some(finished.clone()),
+        false => previous_success,
+    };
+    let values = [
+        Some(finished),
+        success,
+        Some(format!("{:.3}", metrics.duration.as_secs_f64())),
+        Some(metrics.bytes.to_string()),
+        Some(metrics.files.to_string()),
+        Some(u8::from(!metrics.succeeded).to_string()),
+    ];
+    for ((name, _), value) in GAUGES.iter().zip(values) {
+        if let Some(value) = value {
+            samples.push(Sample {
+                name: name.to_string(),
+                labels: labels.clone(),
+                value,
+            });
+        }
+    }
+
+    let mut text = kept;
+    for (name, help) in GAUGES {
+        let mut series: Vec<_> = samples
+            .iter()
+            .filter(|sample| sample.name == name)
+            .collect();
+        if series.is_empty() {
+            continue;
+        }
+        series.sort_by(|a, b| a.labels.cmp(&b.labels));
+        text.push_str(&format!("# HELP {name} {help}\n# TYPE {name} gauge\n"));
+        for sample in series {
+            text.push_str(&format!("{name}{{{}}} {}\n", sample.labels, sample.value));
+        }
+    }
+    text
+}
+
+/// Renders `labels` as written between the braces of a sample.
+fn render_labels(labels: &[(&str, &str)]) -> String {
+    let labels: Vec<_> = labels
+        .iter()
+        .map(|(name, value)| {
+            let value = value
+                .replace('\\', "\\\\")
+                .replace('"', "\\\"")
+                .replace('\n', "\\n");
+            format!("{name}=\"{value}\"")
+        })
+        .collect();
+    labels.join(",")
+}
+
+/// Parses the sample `name{labels} value` on `line`, or `None` if it is a
+/// comment or malformed.
+fn parse_sample(line: &str) -> Option<Sample> {
+    if line.starts_with('#') {
+        return None;
+    }
+    let line = line.trim();
+    let name_end = line.find(['{', ' '])?;
+    let name = &line[..name_end];
+    let (labels, rest) = match line[name_end..].strip_prefix('{') {
+        Some(rest) => {
+            // Braces inside quoted values do not end the labels.
+            let (mut quoted, mut escaped) = (false, false);
+            let end = rest.char_indices().find_map(|(i, c)| {
+                match c {
+                    _ if escaped => escaped = false,
+                    '\\' => escaped = true,
+                    '"' => quoted = !quoted,
+                    '}' if !quoted => return Some(i),
+                    _ => {}
+                }
+                None
+            })?;
+            (&rest[..end], &rest[end + 1..])
+        }
+        None => ("", &line[name_end..]),
+    };
+    Some(Sample {
+        name: name.to_owned(),
+        labels: labels.to_owned(),
+        value: rest.trim().to_owned(),
+    })
+}
+
+/// Returns `time` in seconds since the Unix epoch, to the millisecond.
+fn seconds(time: SystemTime) -> String {
+    let since = time
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap_or_default();
+    format!("{:.3}", since.as_secs_f64())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run<'a>(labels: &'a [(&'a str, &'a str)], succeeded: bool, at: u64) -> RunMetrics<'a> {
+        RunMetrics {
+            labels,
+            succeeded,
+            finished: SystemTime::UNIX_EPOCH + Duration::from_secs(at),
+            duration: Duration::from_millis(1500),
+            bytes: 10,
+            files: 2,
+        }
+    }
+
+    #[test]
+    fn series_of_other_backups_are_kept() {
+        let text = "# HELP other_metric Something else.\nother_metric 7\n";
+        let text = merge(text, &run(&[("profile", "photos")], true, 100));
+        let text = merge(&text, &run(&[("source", "/srv/a \"b\"")], true, 200));
+        let text = merge(&text, &run(&[("profile", "photos")], false, 300));
+
+        assert!(text.starts_with("# HELP other_metric Something else.\nother_metric 7\n"));
+        assert_eq!(
+            text.matches("# TYPE backup_last_run_bytes gauge\n").count(),
+            1
+        );
+        for line in [
+            "backup_last_run_timestamp_seconds{profile=\"photos\"} 300.000\n",
+            "backup_last_success_timestamp_seconds{profile=\"photos\"} 100.000\n",
+            "backup_last_run_failed{profile=\"photos\"} 1\n",
+            "backup_last_run_failed{source=\"/srv/a \\\"b\\\"\"} 0\n",
+            "backup_last_run_duration_seconds{source=\"/srv/a \\\"b\\\"\"} 1.500\n",
+        ] {
+            assert_eq!(text.matches(line).count(), 1, "{line} in {text}");
+        }
+        assert_eq!(text.matches("backup_last_run_files{").count(), 2, "{text}");
+    }
+
+    #[test]
+    fn samples_are_parsed_around_quoted_braces() {
+        let sample = parse_sample(r#"m{a="x}\"y",b="z"} 1.5 1700000000"#).unwrap();
+        assert_eq!(sample.name, "m");
+        assert_eq!(sample.labels, r#"a="x}\"y",b="z""#);
+        assert_eq!(sample.value, "1.5 1700000000");
+        assert_eq!(parse_sample("plain 3").unwrap().labels, "");
+        assert!(parse_sample("# TYPE m gauge").is_none());
+    }
+}
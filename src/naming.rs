@@ -0,0 +1,983 @@
+//! Names given to backups, rendered from a template such as
+//! `{name}.{timestamp}.backup` and parsed back into their parts.
+//!
+//! The template applies to every operation reading or creating backup names:
+//! the library uses the one installed with [`set_format`], or
+//! [`DEFAULT_TEMPLATE`] if there is none.
+
+use std::borrow::Cow;
+use std::ffi::{OsStr, OsString};
+use std::path::Path;
+use std::sync::OnceLock;
+
+use chrono::format::{Item, StrftimeItems};
+use chrono::{DateTime, Local, NaiveDate, NaiveDateTime, NaiveTime, Timelike, Utc};
+
+use crate::backup::TIMESTAMP_FORMAT;
+use crate::error::BackupError;
+use crate::restore::BackupName;
+
+/// Template of the backup names created by default.
+pub const DEFAULT_TEMPLATE: &str = "{name}.{timestamp}.backup";
+
+/// Suffix of the symbolic link to the newest backup of each name, such as
+/// `hosts.latest.backup`, see [`latest_name`].
+pub const LATEST_SUFFIX: &str = ".latest.backup";
+
+/// Format of the `{date}` placeholder.
+const DATE_FORMAT: &str = "%Y-%m-%d";
+
+/// Format of the `{time}` placeholder.
+const TIME_FORMAT: &str = "%H-%M-%S";
+
+/// Characters not allowed in file names on common filesystems, such as FAT.
+const INVALID_CHARACTERS: &str = "/\\:*?\"<>|";
+
+/// Names reserved for devices on Windows, whatever their extension.
+const RESERVED_NAMES: [&str; 22] = [
+    "CON", "PRN", "AUX", "NUL", "COM1", "COM2", "COM3", "COM4", "COM5", "COM6", "COM7", "COM8",
+    "COM9", "LPT1", "LPT2", "LPT3", "LPT4", "LPT5", "LPT6", "LPT7", "LPT8", "LPT9",
+];
+
+/// Longest file name, in bytes, allowed by common filesystems.
+pub(crate) const NAME_MAX: usize = 255;
+
+/// Extensions that may follow a backup name for archived backups.
+pub(crate) const ARCHIVE_EXTENSIONS: [&str; 6] = [
+    "tar",
+    "tar.gz",
+    "tar.zst",
+    "tar.age",
+    "tar.gz.age",
+    "tar.zst.age",
+];
+
+/// Format installed with [`set_format`].
+static FORMAT: OnceLock<NameFormat> = OnceLock::new();
+
+/// Installs the format of the backup names created and recognized.
+///
+/// Only the first format installed is kept; returns `false` if one was
+/// already installed.
+///
+/// # Examples
+///
+/// ```
+/// use backup::naming::{self, NameFormat};
+///
+/// naming::set_format(NameFormat::new("{name}-{timestamp}.bak")?);
+/// # Ok::<(), backup::BackupError>(())
+/// ```
+pub fn set_format(format: NameFormat) -> bool {
+    FORMAT.set(format).is_ok()
+}
+
+/// Returns the format installed with [`set_format`], or the default one.
+pub fn format() -> &'static NameFormat {
+    static DEFAULT: OnceLock<NameFormat> = OnceLock::new();
+    FORMAT
+        .get()
+        .unwrap_or_else(|| DEFAULT.get_or_init(NameFormat::default))
+}
+
+/// Name of the symbolic link to the newest backup of the file named
+/// `original`, made next to it by each backup into a directory.
+///
+/// # Examples
+///
+/// ```
+/// use std::ffi::OsStr;
+///
+/// use backup::naming;
+///
+/// let name = naming::latest_name(OsStr::new("hosts"));
+/// assert_eq!(name, "hosts.latest.backup");
+/// assert!(naming::is_latest(&name));
+/// ```
+pub fn latest_name(original: &OsStr) -> OsString {
+    let mut name = original.to_os_string();
+    name.push(LATEST_SUFFIX);
+    name
+}
+
+/// Checks whether `name` is that of a link made by [`latest_name`].
+pub fn is_latest(name: &OsStr) -> bool {
+    let name = name.as_encoded_bytes();
+    name.len() > LATEST_SUFFIX.len() && name.ends_with(LATEST_SUFFIX.as_bytes())
+}
+
+/// Resolution of the creation time in backup names.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Precision {
+    /// Whole seconds, as in `2024-05-01_10-00-00`.
+    #[default]
+    Seconds,
+    /// Milliseconds, as in `2024-05-01_10-00-00.123`.
+    Milliseconds,
+    /// Nanoseconds, as in `2024-05-01_10-00-00.123456789`.
+    Nanoseconds,
+}
+
+impl Precision {
+    /// Parses the `--precision` name.
+    pub fn parse(name: &str) -> Result<Self, BackupError> {
+        match name {
+            "s" => Ok(Precision::Seconds),
+            "ms" => Ok(Precision::Milliseconds),
+            "ns" => Ok(Precision::Nanoseconds),
+            other => Err(BackupError::InvalidOption(format!(
+                "Unknown precision '{other}', expected s, ms or ns"
+            ))),
+        }
+    }
+
+    /// `strftime` format of the fraction of a second following the time.
+    fn fraction(self) -> &'static str {
+        match self {
+            Precision::Seconds => "",
+            Precision::Milliseconds => "%.3f",
+            Precision::Nanoseconds => "%.9f",
+        }
+    }
+}
+
+/// Placeholder of a template.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Field {
+    /// File name of the source.
+    Name,
+    /// File name of the source without its extension.
+    Stem,
+    /// Extension of the source with its leading dot, or nothing.
+    Ext,
+    /// Creation time, followed by the counter of backups made within the
+    /// same second.
+    Timestamp,
+    /// Creation date.
+    Date,
+    /// Creation time of day.
+    Time,
+    /// Name of the machine.
+    Hostname,
+}
+
+impl Field {
+    const ALL: [(&'static str, Field); 7] = [
+        ("name", Field::Name),
+        ("stem", Field::Stem),
+        ("ext", Field::Ext),
+        ("timestamp", Field::Timestamp),
+        ("date", Field::Date),
+        ("time", Field::Time),
+        ("hostname", Field::Hostname),
+    ];
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Token {
+    Literal(String),
+    Field(Field),
+}
+
+/// Template of backup names, made of literal text and placeholders.
+///
+/// The placeholders are `{name}`, the file name of the source, `{stem}` and
+/// `{ext}`, the same split before its extension, which keeps its dot,
+/// `{timestamp}`, `{date}` and `{time}`, the creation time, and `{hostname}`.
+/// A template names the source with either `{name}` or `{stem}` and `{ext}`,
+/// and holds `{timestamp}` or `{date}`. Backups made within the same second
+/// get a `_<n>` counter after the timestamp, or else the time or date.
+///
+/// The timestamp follows [`TIMESTAMP_FORMAT`] in local time, unless changed
+/// with [`NameFormat::timestamp_format`] and [`NameFormat::utc`]. Names with
+/// the default timestamp are still recognized after changing it.
+/// [`NameFormat::without_timestamp`] names backups with no timestamp at all.
+///
+/// Names are parsed back by matching the template, where `{name}` and
+/// `{stem}` take as much of the name as they can, and the other placeholders
+/// as little. `{hostname}` takes the name of this machine when it can, so
+/// that names with dashes split as expected around hostnames with dashes.
+///
+/// # Examples
+///
+/// ```
+/// use std::ffi::OsStr;
+///
+/// use backup::naming::NameFormat;
+///
+/// let format = NameFormat::new("{stem}-{date}{ext}")?;
+/// let parsed = format.parse(OsStr::new("my-notes-2024-05-01.txt")).unwrap();
+/// assert_eq!(parsed.original, OsStr::new("my-notes.txt"));
+/// # Ok::<(), backup::BackupError>(())
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NameFormat {
+    template: String,
+    tokens: Vec<Token>,
+    /// Placeholder the counter of backups made within the same second
+    /// follows, or `None` for names without a timestamp.
+    sequence: Option<Field>,
+    /// Format of the `{timestamp}` placeholder.
+    timestamp_format: String,
+    /// Render times in UTC rather than local time.
+    utc: bool,
+    /// Resolution of the rendered times.
+    precision: Precision,
+}
+
+impl Default for NameFormat {
+    fn default() -> Self {
+        NameFormat::new(DEFAULT_TEMPLATE).expect("the default template is valid")
+    }
+}
+
+impl NameFormat {
+    /// Parses `template`, failing if it holds unknown or repeated
+    /// placeholders, path separators, or does not tell the original name and
+    /// the creation time apart.
+    pub fn new(template: &str) -> Result<Self, BackupError> {
+        let invalid = |message: &str| {
+            BackupError::InvalidOption(format!("Invalid name format '{template}': {message}"))
+        };
+
+        let mut tokens = Vec::new();
+        let mut rest = template;
+        while !rest.is_empty() {
+            let Some(start) = rest.find('{') else {
+                tokens.push(Token::Literal(rest.to_owned()));
+                break;
+            };
+            if start > 0 {
+                tokens.push(Token::Literal(rest[..start].to_owned()));
+            }
+            let end = rest[start..]
+                .find('}')
+                .ok_or_else(|| invalid("unterminated placeholder"))?;
+            let placeholder = &rest[start + 1..start + end];
+            let field = Field::ALL
+                .iter()
+                .find(|(name, _)| *name == placeholder)
+                .map(|&(_, field)| field)
+                .ok_or_else(|| invalid(&format!("unknown placeholder '{{{placeholder}}}'")))?;
+            if tokens.contains(&Token::Field(field)) {
+                return Err(invalid(&format!("'{{{placeholder}}}' is repeated")));
+            }
+            tokens.push(Token::Field(field));
+            rest = &rest[start + end + 1..];
+        }
+
+        let has = |field| tokens.contains(&Token::Field(field));
+        if tokens.iter().any(|token| match token {
+            Token::Literal(literal) => literal.contains(std::path::is_separator),
+            Token::Field(_) => false,
+        }) {
+            return Err(invalid("names cannot contain path separators"));
+        }
+        match (has(Field::Name), has(Field::Stem), has(Field::Ext)) {
+            (true, false, _) | (false, true, true) => {}
+            (true, true, _) => return Err(invalid("use either {name} or {stem}")),
+            (false, true, false) => return Err(invalid("{stem} requires {ext}")),
+            (false, false, _) => return Err(invalid("{name} or {stem} is required")),
+        }
+        let sequence = [Field::Timestamp, Field::Time, Field::Date]
+            .into_iter()
+            .find(|&field| has(field))
+            .ok_or_else(|| invalid("{timestamp} or {date} is required"))?;
+        if sequence == Field::Time && !has(Field::Date) {
+            return Err(invalid("{time} requires {date}"));
+        }
+
+        Ok(NameFormat {
+            template: template.to_owned(),
+            tokens,
+            sequence: Some(sequence),
+            timestamp_format: TIMESTAMP_FORMAT.to_owned(),
+            utc: false,
+            precision: Precision::Seconds,
+        })
+    }
+
+    /// Creates the format of names without a timestamp, the original name
+    /// followed by `suffix`, such as `hosts.bak` for a suffix of `.bak`.
+    ///
+    /// Backups named this way are not kept side by side: a new backup
+    /// replaces the previous one when forced, and is refused otherwise.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::ffi::OsStr;
+    ///
+    /// use backup::naming::NameFormat;
+    ///
+    /// let format = NameFormat::without_timestamp(".bak")?;
+    /// let parsed = format.parse(OsStr::new("hosts.bak")).unwrap();
+    /// assert_eq!(parsed.original, OsStr::new("hosts"));
+    /// assert_eq!(parsed.timestamp, None);
+    /// # Ok::<(), backup::BackupError>(())
+    /// ```
+    pub fn without_timestamp(suffix: &str) -> Result<Self, BackupError> {
+        let invalid = |message: &str| {
+            BackupError::InvalidOption(format!("Invalid suffix '{suffix}': {message}"))
+        };
+        if suffix.is_empty() {
+            return Err(invalid("it cannot be empty"));
+        }
+        if suffix.contains(std::path::is_separator) {
+            return Err(invalid("names cannot contain path separators"));
+        }
+
+        Ok(NameFormat {
+            template: format!("{{name}}{suffix}"),
+            tokens: vec![Token::Field(Field::Name), Token::Literal(suffix.to_owned())],
+            sequence: None,
+            timestamp_format: TIMESTAMP_FORMAT.to_owned(),
+            utc: false,
+            precision: Precision::Seconds,
+        })
+    }
+
+    /// Sets the `strftime` format of the `{timestamp}` placeholder, failing
+    /// if it is invalid, cannot be read back into a date, or renders
+    /// characters that common filesystems do not allow in names, such as `/`
+    /// or `:`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use backup::naming::NameFormat;
+    ///
+    /// assert!(NameFormat::default().timestamp_format("%Y%m%dT%H%M%S").is_ok());
+    /// assert!(NameFormat::default().timestamp_format("%Y-%m-%dT%H:%M:%S").is_err());
+    /// ```
+    pub fn timestamp_format(mut self, format: &str) -> Result<Self, BackupError> {
+        let invalid = |message: &str| {
+            BackupError::InvalidOption(format!("Invalid timestamp format '{format}': {message}"))
+        };
+        if StrftimeItems::new(format).any(|item| item == Item::Error) {
+            return Err(invalid("unknown or incomplete specifier"));
+        }
+
+        let sample = Local::now().format(format).to_string();
+        if sample.is_empty() {
+            return Err(invalid("it renders nothing"));
+        }
+        if let Some(c) = sample
+            .chars()
+            .find(|&c| INVALID_CHARACTERS.contains(c) || c.is_control())
+        {
+            return Err(invalid(&format!("'{c}' is not allowed in file names")));
+        }
+        if parse_timestamp(&sample, format).is_none() {
+            return Err(invalid("it lacks a full date"));
+        }
+
+        self.timestamp_format = format.to_owned();
+        Ok(self)
+    }
+
+    /// Renders times in UTC rather than local time, so that the names of
+    /// backups made in different time zones sort in order.
+    pub fn utc(mut self, utc: bool) -> Self {
+        self.utc = utc;
+        self
+    }
+
+    /// Sets the resolution of the times rendered by `{timestamp}`, or by
+    /// `{time}` without it. Names are read back at any precision, so that
+    /// backups made with different ones sort in order.
+    pub fn precision(mut self, precision: Precision) -> Self {
+        self.precision = precision;
+        self
+    }
+
+    /// Checks whether times are rendered in UTC.
+    pub fn is_utc(&self) -> bool {
+        self.utc
+    }
+
+    /// Checks whether names carry the creation time of their backup.
+    pub fn has_timestamp(&self) -> bool {
+        self.sequence.is_some()
+    }
+
+    /// Returns the template the format was created from.
+    pub fn template(&self) -> &str {
+        &self.template
+    }
+
+    /// Renders the name of the backup of the file named `original` created
+    /// at `created`, the `sequence`-th within the same second.
+    pub fn render(&self, original: &OsStr, created: DateTime<Local>, sequence: u32) -> OsString {
+        let path = Path::new(original);
+        let mut name = OsString::new();
+        for token in &self.tokens {
+            match token {
+                Token::Literal(literal) => name.push(literal),
+                Token::Field(Field::Name) => name.push(original),
+                Token::Field(Field::Stem) => name.push(path.file_stem().unwrap_or(original)),
+                Token::Field(Field::Ext) => {
+                    if let Some(extension) = path.extension() {
+                        name.push(".");
+                        name.push(extension);
+                    }
+                }
+                Token::Field(Field::Hostname) => name.push(hostname()),
+                Token::Field(field) => {
+                    let mut format = match field {
+                        Field::Date => DATE_FORMAT,
+                        Field::Time => TIME_FORMAT,
+                        _ => &self.timestamp_format,
+                    }
+                    .to_owned();
+                    if Some(*field) == self.sequence && *field != Field::Date {
+                        format.push_str(self.precision.fraction());
+                    }
+                    name.push(match self.utc {
+                        true => created.with_timezone(&Utc).format(&format).to_string(),
+                        false => created.format(&format).to_string(),
+                    });
+                    if Some(*field) == self.sequence && sequence > 1 {
+                        name.push(format!("_{sequence}"));
+                    }
+                }
+            }
+        }
+        name
+    }
+
+    /// Parses a backup file name, optionally followed by an archive extension,
+    /// returning `None` if it does not follow the format.
+    pub fn parse<'a>(&self, name: &'a OsStr) -> Option<BackupName<'a>> {
+        let name = name.as_encoded_bytes();
+        let (name, archive) = ARCHIVE_EXTENSIONS
+            .iter()
+            .find_map(|&extension| {
+                let stem = name
+                    .strip_suffix(extension.as_bytes())?
+                    .strip_suffix(b".")?;
+                Some((stem, Some(extension)))
+            })
+            .unwrap_or((name, None));
+
+        let mut parts = Parts::default();
+        if !self.matches(&self.tokens, name, &mut parts) {
+            return None;
+        }
+
+        // SAFETY: the parts of the name come from the encoded bytes of an
+        // `OsStr`, cut right next to literal text or to placeholders that
+        // matched valid UTF-8, which are valid boundaries.
+        let os_str = |bytes| unsafe { OsStr::from_encoded_bytes_unchecked(bytes) };
+        let original = match (parts.name, parts.stem, parts.ext) {
+            (Some(name), _, _) => Cow::Borrowed(os_str(name)),
+            (None, Some(stem), Some(ext)) => {
+                let mut original = os_str(stem).to_os_string();
+                original.push(ext);
+                // The split must be the one rendering would make.
+                let path = Path::new(&original);
+                let extension = path.extension().map(|extension| extension.len() + 1);
+                if extension.unwrap_or(0) != ext.len() {
+                    return None;
+                }
+                Cow::Owned(original)
+            }
+            _ => return None,
+        };
+        let timestamp = match (parts.timestamp, parts.date) {
+            (Some(timestamp), _) => Some(timestamp),
+            (None, Some(date)) => Some(date.and_time(parts.time.unwrap_or_default())),
+            (None, None) if !self.has_timestamp() => None,
+            (None, None) => return None,
+        };
+
+        Some(BackupName {
+            original,
+            timestamp,
+            sequence: parts.sequence,
+            archive,
+        })
+    }
+
+    /// Matches `name` against `tokens`, recording the value of each
+    /// placeholder in `parts`.
+    fn matches<'a>(&self, tokens: &[Token], name: &'a [u8], parts: &mut Parts<'a>) -> bool {
+        let Some((token, rest)) = tokens.split_first() else {
+            return name.is_empty();
+        };
+        let field = match token {
+            Token::Literal(literal) => {
+                return name
+                    .strip_prefix(literal.as_bytes())
+                    .is_some_and(|name| self.matches(rest, name, parts))
+            }
+            Token::Field(field) => *field,
+        };
+
+        let lengths: Vec<usize> = match field {
+            Field::Name | Field::Stem => (1..=name.len()).rev().collect(),
+            Field::Ext => (0..=name.len()).collect(),
+            Field::Hostname => {
+                let local = hostname().len();
+                let is_local = name.starts_with(hostname().as_bytes());
+                is_local
+                    .then_some(local)
+                    .into_iter()
+                    .chain((1..=name.len()).filter(|&len| !is_local || len != local))
+                    .collect()
+            }
+            _ => (1..=name.len()).collect(),
+        };
+        for len in lengths {
+            let value = &name[..len];
+            let saved = parts.clone();
+            if parts.set(field, value, self) && self.matches(rest, &name[len..], parts) {
+                return true;
+            }
+            *parts = saved;
+        }
+        false
+    }
+}
+
+/// Values of the placeholders of a name being parsed.
+#[derive(Debug, Clone)]
+struct Parts<'a> {
+    name: Option<&'a [u8]>,
+    stem: Option<&'a [u8]>,
+    ext: Option<&'a str>,
+    timestamp: Option<NaiveDateTime>,
+    date: Option<NaiveDate>,
+    time: Option<NaiveTime>,
+    sequence: u32,
+}
+
+impl Default for Parts<'_> {
+    fn default() -> Self {
+        Parts {
+            name: None,
+            stem: None,
+            ext: None,
+            timestamp: None,
+            date: None,
+            time: None,
+            sequence: 1,
+        }
+    }
+}
+
+impl<'a> Parts<'a> {
+    /// Records `value` as the value of `field` in names following `format`,
+    /// returning whether it is a valid one. A counter may follow the value of
+    /// the field carrying it.
+    fn set(&mut self, field: Field, value: &'a [u8], format: &NameFormat) -> bool {
+        if matches!(field, Field::Name | Field::Stem) {
+            match field {
+                Field::Name => self.name = Some(value),
+                _ => self.stem = Some(value),
+            }
+            return true;
+        }
+
+        let Ok(value) = std::str::from_utf8(value) else {
+            return false;
+        };
+        // The value may also end with digits after an underscore, as with a
+        // timestamp format of `%Y%m%d_%H%M%S`.
+        let counted = value
+            .rsplit_once('_')
+            .filter(|_| Some(field) == format.sequence)
+            .and_then(|(value, counter)| {
+                let digits = counter.bytes().all(|b| b.is_ascii_digit());
+                let counter = counter
+                    .parse()
+                    .ok()
+                    .filter(|&counter| digits && counter > 1)?;
+                Some((value, counter))
+            });
+        for (value, counter) in counted.into_iter().chain([(value, 1)]) {
+            if self.set_value(field, value, format) {
+                if Some(field) == format.sequence {
+                    self.sequence = counter;
+                }
+                return true;
+            }
+        }
+        false
+    }
+
+    fn set_value(&mut self, field: Field, value: &'a str, format: &NameFormat) -> bool {
+        match field {
+            Field::Ext => {
+                let valid = value.is_empty()
+                    || value
+                        .strip_prefix('.')
+                        .is_some_and(|extension| !extension.is_empty() && !extension.contains('.'));
+                self.ext = Some(value);
+                valid
+            }
+            Field::Timestamp => {
+                self.timestamp = with_fraction(value, |value| {
+                    parse_timestamp(value, &format.timestamp_format)
+                        .or_else(|| parse_timestamp(value, TIMESTAMP_FORMAT))
+                });
+                self.timestamp.is_some()
+            }
+            Field::Date => {
+                self.date = NaiveDate::parse_from_str(value, DATE_FORMAT).ok();
+                self.date.is_some()
+            }
+            Field::Time => {
+                self.time = with_fraction(value, |value| {
+                    NaiveTime::parse_from_str(value, TIME_FORMAT).ok()
+                });
+                self.time.is_some()
+            }
+            _ => !value.contains(std::path::is_separator),
+        }
+    }
+}
+
+/// Parses a timestamp rendered with the `strftime` `format`, which may lack
+/// the time of day.
+fn parse_timestamp(value: &str, format: &str) -> Option<NaiveDateTime> {
+    NaiveDateTime::parse_from_str(value, format)
+        .ok()
+        .or_else(|| {
+            Some(
+                NaiveDate::parse_from_str(value, format)
+                    .ok()?
+                    .and_time(NaiveTime::MIN),
+            )
+        })
+}
+
+/// Makes `name` valid on common filesystems, such as FAT, exFAT or NTFS, and
+/// at most `max_len` bytes long.
+///
+/// Characters that these do not allow, such as `:` or `|`, and trailing dots
+/// and spaces are replaced with `_`, and `_` is added after names reserved
+/// for devices, as in `CON_.txt`. Long names are truncated, keeping their
+/// extension if it is short enough.
+pub(crate) fn sanitize(name: &OsStr, max_len: usize) -> Cow<'_, OsStr> {
+    let bytes = name.as_encoded_bytes();
+    let mut sanitized: Vec<u8> = bytes
+        .iter()
+        .map(
+            |&b| match b.is_ascii_control() || INVALID_CHARACTERS.as_bytes().contains(&b) {
+                true => b'_',
+                false => b,
+            },
+        )
+        .collect();
+
+    let stem = sanitized
+        .iter()
+        .position(|&b| b == b'.')
+        .unwrap_or(sanitized.len());
+    if RESERVED_NAMES
+        .iter()
+        .any(|reserved| reserved.as_bytes().eq_ignore_ascii_case(&sanitized[..stem]))
+    {
+        sanitized.insert(stem, b'_');
+    }
+
+    if sanitized.len() > max_len {
+        let extension = Path::new(name)
+            .extension()
+            .map_or(0, |extension| extension.len() + 1)
+            .min(sanitized.len());
+        let extension = match extension <= max_len / 2 {
+            true => sanitized.split_off(sanitized.len() - extension),
+            false => Vec::new(),
+        };
+        // Cut at a character boundary, before any continuation byte.
+        let mut end = max_len - extension.len();
+        while end > 0 && sanitized[end] & 0xC0 == 0x80 {
+            end -= 1;
+        }
+        sanitized.truncate(end);
+        sanitized.extend(extension);
+    }
+
+    for b in sanitized.iter_mut().rev() {
+        match b {
+            b'.' | b' ' => *b = b'_',
+            _ => break,
+        }
+    }
+
+    if sanitized == bytes {
+        return Cow::Borrowed(name);
+    }
+    // SAFETY: only ASCII bytes were replaced or inserted, and the name was
+    // cut before a character, which keeps the encoding valid.
+    Cow::Owned(unsafe { OsString::from_encoded_bytes_unchecked(sanitized) })
+}
+
+/// Checks whether `parsed`, the original name read from a backup name, is
+/// `original` or the name [`sanitize`] made of it.
+pub(crate) fn names_match(original: &OsStr, parsed: &OsStr) -> bool {
+    original == parsed || sanitize(original, parsed.len()) == parsed
+}
+
+/// Parses a time with `parse`, which may be followed by a fraction of a
+/// second of any [`Precision`].
+fn with_fraction<T: Timelike>(value: &str, parse: impl Fn(&str) -> Option<T>) -> Option<T> {
+    parse(value).or_else(|| {
+        let (value, fraction) = value.rsplit_once('.')?;
+        if !fraction.bytes().all(|b| b.is_ascii_digit()) {
+            return None;
+        }
+        let nanoseconds = match fraction.len() {
+            3 => fraction.parse::<u32>().ok()? * 1_000_000,
+            9 => fraction.parse().ok()?,
+            _ => return None,
+        };
+        parse(value)?.with_nanosecond(nanoseconds)
+    })
+}
+
+/// Returns the name of this machine, or `localhost` if it cannot be told.
+pub(crate) fn hostname() -> &'static str {
+    static HOSTNAME: OnceLock<String> = OnceLock::new();
+    HOSTNAME.get_or_init(|| {
+        system_hostname()
+            .filter(|name| !name.is_empty() && !name.contains(std::path::is_separator))
+            .unwrap_or_else(|| "localhost".to_owned())
+    })
+}
+
+#[cfg(unix)]
+fn system_hostname() -> Option<String> {
+    let mut buffer = [0u8; 256];
+    // SAFETY: the buffer is valid for writes of its length.
+    let result = unsafe { libc::gethostname(buffer.as_mut_ptr().cast(), buffer.len()) };
+    if result != 0 {
+        return None;
+    }
+    let end = buffer.iter().position(|&b| b == 0).unwrap_or(buffer.len());
+    String::from_utf8(buffer[..end].to_vec()).ok()
+}
+
+#[cfg(not(unix))]
+fn system_hostname() -> Option<String> {
+    std::env::var("COMPUTERNAME").ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+
+    use super::*;
+
+    fn created() -> DateTime<Local> {
+        Local.with_ymd_and_hms(2024, 5, 1, 10, 0, 0).unwrap()
+    }
+
+    #[test]
+    fn latest_links_need_a_name() {
+        assert!(is_latest(OsStr::new("my.notes.latest.backup")));
+        assert!(!is_latest(OsStr::new(".latest.backup")));
+        assert!(!is_latest(OsStr::new("hosts.latest.backup.tar.gz")));
+        assert!(!is_latest(OsStr::new("hosts.2024-05-01_10-00-00.backup")));
+    }
+
+    fn round_trip(template: &str, original: &str) {
+        let format = NameFormat::new(template).unwrap();
+        for sequence in [1, 2] {
+            let name = format.render(OsStr::new(original), created(), sequence);
+            let parsed = format
+                .parse(&name)
+                .unwrap_or_else(|| panic!("{name:?} does not parse with {template}"));
+            assert_eq!(parsed.original, OsStr::new(original), "{name:?}");
+            let expected = match template.contains("{timestamp}") || template.contains("{time}") {
+                true => created().naive_local(),
+                false => created().date_naive().and_time(NaiveTime::MIN),
+            };
+            assert_eq!(parsed.timestamp, Some(expected), "{name:?}");
+            assert_eq!(parsed.sequence, sequence, "{name:?}");
+        }
+    }
+
+    #[test]
+    fn default_names_are_unchanged() {
+        let format = NameFormat::default();
+        assert_eq!(
+            format.render(OsStr::new("hosts"), created(), 1),
+            "hosts.2024-05-01_10-00-00.backup"
+        );
+        assert_eq!(
+            format.render(OsStr::new("hosts"), created(), 3),
+            "hosts.2024-05-01_10-00-00_3.backup"
+        );
+
+        let parsed = format
+            .parse(OsStr::new("my.notes.2024-05-01_10-00-00.backup.tar.gz"))
+            .unwrap();
+        assert_eq!(parsed.original, OsStr::new("my.notes"));
+        assert_eq!(parsed.archive, Some("tar.gz"));
+        assert!(format.parse(OsStr::new("hosts.backup")).is_none());
+        assert!(format
+            .parse(OsStr::new("hosts.2024-05-01_10-00-00_1.backup"))
+            .is_none());
+    }
+
+    #[test]
+    fn names_with_dashes_split_around_placeholders() {
+        round_trip("{name}-{timestamp}.bak", "my-file");
+        round_trip("{name}-{hostname}-{timestamp}.bak", "my-file");
+        round_trip("{name}-{hostname}-{timestamp}.bak", "a-b-c.tar");
+        round_trip("{stem}-{date}_{time}{ext}", "my-notes.txt");
+        round_trip("{stem}-{date}{ext}", "archive.tar.gz");
+        round_trip("{stem}-{date}{ext}", ".bashrc");
+        round_trip("{date}-{name}", "2024-05-01-notes");
+    }
+
+    #[test]
+    fn foreign_hostnames_are_the_shortest_match() {
+        let format = NameFormat::new("{name}-{hostname}-{timestamp}").unwrap();
+        let parsed = format
+            .parse(OsStr::new("my-file-elsewhere-2024-05-01_10-00-00"))
+            .unwrap();
+        assert_eq!(parsed.original, OsStr::new("my-file"));
+    }
+
+    #[test]
+    fn timestamp_formats_are_read_back_with_the_default_as_fallback() {
+        let format = NameFormat::default()
+            .timestamp_format("%Y%m%d_%H%M%S")
+            .unwrap();
+        let name = format.render(OsStr::new("hosts"), created(), 1);
+        assert_eq!(name, "hosts.20240501_100000.backup");
+        assert_eq!(format.parse(&name).unwrap().sequence, 1);
+        let name = format.render(OsStr::new("hosts"), created(), 2);
+        assert_eq!(name, "hosts.20240501_100000_2.backup");
+        assert_eq!(format.parse(&name).unwrap().sequence, 2);
+
+        let older = format
+            .parse(OsStr::new("hosts.2024-05-01_10-00-00.backup"))
+            .unwrap();
+        assert_eq!(older.timestamp, Some(created().naive_local()));
+
+        let format = NameFormat::default().timestamp_format("%F").unwrap();
+        let parsed = format.parse(OsStr::new("hosts.2024-05-01.backup")).unwrap();
+        assert_eq!(
+            parsed.timestamp,
+            Some(created().date_naive().and_time(NaiveTime::MIN))
+        );
+
+        for invalid in ["%Y-%m-%dT%H:%M:%S", "%Y/%m/%d", "%H-%M", "%Q", "backup"] {
+            assert!(
+                NameFormat::default().timestamp_format(invalid).is_err(),
+                "{invalid}"
+            );
+        }
+    }
+
+    #[test]
+    fn utc_timestamps_are_rendered_in_utc() {
+        let format = NameFormat::default().utc(true);
+        let expected = created().with_timezone(&Utc).format(TIMESTAMP_FORMAT);
+        assert_eq!(
+            format.render(OsStr::new("hosts"), created(), 1),
+            format!("hosts.{expected}.backup").as_str()
+        );
+    }
+
+    #[test]
+    fn names_without_timestamp_end_with_the_suffix() {
+        let format = NameFormat::without_timestamp(".bak").unwrap();
+        for sequence in [1, 2] {
+            assert_eq!(
+                format.render(OsStr::new("notes.txt"), created(), sequence),
+                "notes.txt.bak"
+            );
+        }
+
+        let parsed = format.parse(OsStr::new("notes.txt.bak.tar.zst")).unwrap();
+        assert_eq!(parsed.original, OsStr::new("notes.txt"));
+        assert_eq!(parsed.timestamp, None);
+        assert_eq!(parsed.archive, Some("tar.zst"));
+        assert!(format.parse(OsStr::new(".bak")).is_none());
+        assert!(format.parse(OsStr::new("notes.txt")).is_none());
+
+        assert!(NameFormat::without_timestamp("").is_err());
+        assert!(NameFormat::without_timestamp("/bak").is_err());
+    }
+
+    #[test]
+    fn precisions_are_read_back_together() {
+        let precise = created() + chrono::Duration::nanoseconds(123_456_789);
+        let mut names = Vec::new();
+        for precision in [
+            Precision::Seconds,
+            Precision::Milliseconds,
+            Precision::Nanoseconds,
+        ] {
+            let format = NameFormat::default().precision(precision);
+            names.push(format.render(OsStr::new("hosts"), precise, 2));
+        }
+        assert_eq!(
+            names,
+            [
+                "hosts.2024-05-01_10-00-00_2.backup",
+                "hosts.2024-05-01_10-00-00.123_2.backup",
+                "hosts.2024-05-01_10-00-00.123456789_2.backup",
+            ]
+        );
+
+        let format = NameFormat::default();
+        let timestamps: Vec<_> = names
+            .iter()
+            .map(|name| format.parse(name).unwrap().timestamp.unwrap())
+            .collect();
+        assert_eq!(timestamps[0], created().naive_local());
+        assert_eq!(timestamps[1].nanosecond(), 123_000_000);
+        assert_eq!(timestamps[2], precise.naive_local());
+        assert!(timestamps[0] < timestamps[1] && timestamps[1] < timestamps[2]);
+
+        let format = NameFormat::new("{stem}-{date}_{time}{ext}")
+            .unwrap()
+            .precision(Precision::Milliseconds);
+        let name = format.render(OsStr::new("notes.txt"), precise, 1);
+        assert_eq!(name, "notes-2024-05-01_10-00-00.123.txt");
+        assert_eq!(
+            format.parse(&name).unwrap().original,
+            OsStr::new("notes.txt")
+        );
+        assert!(Precision::parse("us").is_err());
+    }
+
+    #[test]
+    fn names_are_sanitized_for_portable_filesystems() {
+        let sanitized = |name: &str, max_len| sanitize(OsStr::new(name), max_len).into_owned();
+        assert_eq!(sanitized("notes.txt", NAME_MAX), "notes.txt");
+        assert_eq!(sanitized("a:b|c?.txt", NAME_MAX), "a_b_c_.txt");
+        assert_eq!(sanitized("report.", NAME_MAX), "report_");
+        assert_eq!(sanitized("CON", NAME_MAX), "CON_");
+        assert_eq!(sanitized("lpt1.tar.gz", NAME_MAX), "lpt1_.tar.gz");
+        assert_eq!(sanitized("console", NAME_MAX), "console");
+        assert_eq!(sanitized("abcdefgh.txt", 8), "abcd.txt");
+        assert_eq!(sanitized("abcdefgh.longer", 8), "abcdefgh");
+        assert_eq!(sanitized("ab.defgh", 3), "ab_");
+        assert_eq!(sanitized("aé", 2), "a");
+    }
+
+    #[test]
+    fn invalid_templates_are_rejected() {
+        for template in [
+            "{name}",
+            "{timestamp}",
+            "{stem}-{timestamp}",
+            "{name}-{stem}-{timestamp}{ext}",
+            "{name}-{nme}-{timestamp}",
+            "{name}-{timestamp}-{timestamp}",
+            "{name}-{timestamp",
+            "backups/{name}-{timestamp}",
+            "{name}-{time}",
+        ] {
+            assert!(NameFormat::new(template).is_err(), "{template}");
+        }
+    }
+}
@@ -0,0 +1,201 @@
+//! Notifications sent by a command once a backup finishes.
+//!
+//! The command runs in `sh -c` after every backup, whether it succeeded,
+//! failed or could not even start, unless filtered out with [`NotifyOn`]. It
+//! reads a short summary on its standard input, so that it can be as simple
+//! as `mail -s backup admin@example.com`, and finds the outcome in the
+//! environment:
+//!
+//! - `BACKUP_STATUS`: the exit status of the backup.
+//! - `BACKUP_RESULT`: `success` or `failure`.
+//! - `BACKUP_SOURCE` and `BACKUP_TARGET`: what was backed up, and where to.
+//! - `BACKUP_ERROR` and `BACKUP_ERROR_CODE`: the message and code of the
+//!   error the backup failed with, empty on success.
+//!
+//! Its standard output goes to the standard error of this process, which
+//! keeps its own standard output to the paths of the backups.
+
+use std::fmt;
+use std::io::{self, Write};
+use std::process::{Command, Stdio};
+
+use crate::error::{BackupError, ExitCode};
+use crate::naming;
+
+/// Outcomes a notification is sent for.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum NotifyOn {
+    #[default]
+    Always,
+    Failure,
+    Success,
+}
+
+impl NotifyOn {
+    /// Parses `always`, `failure` or `success`.
+    pub fn parse(name: &str) -> Result<NotifyOn, BackupError> {
+        match name {
+            "always" => Ok(NotifyOn::Always),
+            "failure" => Ok(NotifyOn::Failure),
+            "success" => Ok(NotifyOn::Success),
+            _ => Err(BackupError::InvalidOption(format!(
+                "Invalid notification filter '{name}', expected 'always', 'failure' or 'success'"
+            ))),
+        }
+    }
+}
+
+/// Outcome of a backup, as told to the notification command.
+#[derive(Debug)]
+pub struct Notification<'a> {
+    /// Exit status of the backup.
+    pub status: ExitCode,
+    pub source: &'a str,
+    pub target: &'a str,
+    /// Error the backup failed with, if any.
+    pub error: Option<&'a BackupError>,
+}
+
+impl Notification<'_> {
+    /// Checks whether the backup succeeded, or was skipped as
+    /// `--skip-unchanged` found nothing to back up.
+    pub fn succeeded(&self) -> bool {
+        self.status.is_success()
+    }
+}
+
+/// The summary read by the notification command.
+impl fmt::Display for Notification<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let outcome = match (self.status, self.error) {
+            (ExitCode::Success, _) => "succeeded".to_owned(),
+            (ExitCode::Skipped, _) => "was skipped, nothing changed".to_owned(),
+            (_, Some(_)) => "failed".to_owned(),
+            (status, None) => format!("finished with errors (exit status {status})"),
+        };
+        writeln!(
+            f,
+            "Backup of {} to {} on {} {outcome}.",
+            self.source,
+            self.target,
+            naming::hostname()
+        )?;
+        match self.error {
+            Some(error) if self.status != ExitCode::Skipped => writeln!(f, "{error}"),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Command notified when a backup finishes.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Notifier {
+    command: String,
+    on: NotifyOn,
+}
+
+impl Notifier {
+    /// Creates a notifier running `command` in `sh -c` for the outcomes
+    /// selected by `on`.
+    pub fn new(command: &str, on: NotifyOn) -> Self {
+        Notifier {
+            command: command.to_owned(),
+            on,
+        }
+    }
+
+    /// Runs the command for `notification` unless filtered out, returning
+    /// whether it ran. The command failing fails with
+    /// [`BackupError::NotifyFailed`].
+    pub fn notify(&self, notification: &Notification) -> Result<bool, BackupError> {
+        let wanted = match self.on {
+            NotifyOn::Always => true,
+            NotifyOn::Failure => !notification.succeeded(),
+            NotifyOn::Success => notification.succeeded(),
+        };
+        if !wanted {
+            return Ok(false);
+        }
+
+        let failed = |source| BackupError::NotifyFailed {
+            command: self.command.clone(),
+            source,
+        };
+        let result = match notification.succeeded() {
+            true => "success",
+            false => "failure",
+        };
+        let error = notification.error;
+        let mut child = Command::new("sh")
+            .arg("-c")
+            .arg(&self.command)
+            .env("BACKUP_STATUS", notification.status.to_string())
+            .env("BACKUP_RESULT", result)
+            .env("BACKUP_SOURCE", notification.source)
+            .env("BACKUP_TARGET", notification.target)
+            .env(
+                "BACKUP_ERROR",
+                error.map(ToString::to_string).unwrap_or_default(),
+            )
+            .env("BACKUP_ERROR_CODE", error.map_or("", BackupError::code))
+            .stdin(Stdio::piped())
+            .stdout(io::stderr())
+            .spawn()
+            .map_err(failed)?;
+        if let Some(mut stdin) = child.stdin.take() {
+            // The command may exit without reading its input.
+            let _ = stdin.write_all(notification.to_string().as_bytes());
+        }
+        match child.wait().map_err(failed)? {
+            status if status.success() => Ok(true),
+            status => Err(failed(io::Error::other(status.to_string()))),
+        }
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::*;
+
+    #[test]
+    fn filters_select_the_outcomes_notified() {
+        let dir = tempfile::tempdir().unwrap();
+        let out = dir.path().join("out");
+        let command = format!(
+            "cat > '{}'; echo \"$BACKUP_RESULT $BACKUP_STATUS $BACKUP_ERROR_CODE\" >> '{0}'",
+            out.display()
+        );
+        let error = BackupError::NotFound(PathBuf::from("data"));
+        let failure = Notification {
+            status: error.exit_code(),
+            source: "data",
+            target: "backups",
+            error: Some(&error),
+        };
+        let success = Notification {
+            status: ExitCode::Success,
+            error: None,
+            ..failure
+        };
+
+        let notifier = Notifier::new(&command, NotifyOn::Success);
+        assert!(!notifier.notify(&failure).unwrap());
+        assert!(!out.exists());
+        assert!(notifier.notify(&success).unwrap());
+        let text = std::fs::read_to_string(&out).unwrap();
+        assert!(text.starts_with("Backup of data to backups on "), "{text}");
+        assert!(text.ends_with(" succeeded.\nsuccess 0 \n"), "{text}");
+
+        let notifier = Notifier::new(&command, NotifyOn::Failure);
+        assert!(notifier.notify(&failure).unwrap());
+        let text = std::fs::read_to_string(&out).unwrap();
+        assert!(text.contains(" failed.\n"), "{text}");
+        assert!(text.ends_with("failure 1 not_found\n"), "{text}");
+
+        let notifier = Notifier::new("exit 4", NotifyOn::Always);
+        let error = notifier.notify(&success).unwrap_err();
+        assert!(error.to_string().contains("exit status: 4"), "{error}");
+    }
+}
@@ -0,0 +1,304 @@
+//! Shell-style wildcard matching and expansion, and suggestions of close
+//! matches.
+
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+
+/// Checks whether `text` matches `pattern`, where `*` matches any sequence of
+/// characters, `?` matches exactly one character and `[...]` matches one of
+/// the characters or ranges listed, or one not listed after `[!` or `[^`.
+pub fn matches(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+
+    let (mut p, mut t) = (0, 0);
+    let mut backtrack = None;
+    while t < text.len() {
+        if pattern.get(p) == Some(&'*') {
+            backtrack = Some((p, t));
+            p += 1;
+            continue;
+        }
+
+        let next = match pattern.get(p) {
+            Some('[') => match class(&pattern[p..], text[t]) {
+                Some((matched, len)) => matched.then_some(p + len),
+                // An unterminated class is a literal bracket.
+                None => (text[t] == '[').then_some(p + 1),
+            },
+            Some(&c) if c == '?' || c == text[t] => Some(p + 1),
+            _ => None,
+        };
+        match (next, backtrack) {
+            (Some(next), _) => {
+                p = next;
+                t += 1;
+            }
+            (None, Some((star, matched))) => {
+                p = star + 1;
+                t = matched + 1;
+                backtrack = Some((star, matched + 1));
+            }
+            (None, None) => return false,
+        }
+    }
+
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+/// Matches `c` against the class `[...]` at the start of `pattern`, returning
+/// whether it matches and the length of the class, or `None` if the class is
+/// not terminated.
+fn class(pattern: &[char], c: char) -> Option<(bool, usize)> {
+    let mut i = 1;
+    let negated = matches!(pattern.get(i), Some('!' | '^'));
+    if negated {
+        i += 1;
+    }
+
+    let start = i;
+    let mut matched = false;
+    loop {
+        match pattern.get(i) {
+            None => return None,
+            Some(']') if i > start => break,
+            Some(&low) => match (pattern.get(i + 1), pattern.get(i + 2)) {
+                (Some('-'), Some(&high)) if high != ']' => {
+                    matched |= (low..=high).contains(&c);
+                    i += 3;
+                }
+                _ => {
+                    matched |= low == c;
+                    i += 1;
+                }
+            },
+        }
+    }
+    Some((matched != negated, i + 1))
+}
+
+/// Checks whether `text` contains wildcards expanded by [`glob`].
+pub fn is_glob(text: &str) -> bool {
+    text.contains(['*', '?', '['])
+}
+
+/// Returns the existing paths matching `pattern`, sorted. Each component of
+/// the pattern is matched as by [`matches()`], and a `**` component matches any
+/// number of directories, including none. As in shells, names starting with a
+/// dot are only matched by a component starting with a dot.
+///
+/// # Examples
+///
+/// ```no_run
+/// for log in backup::pattern::glob("/var/log/**/*.log") {
+///     println!("{}", log.display());
+/// }
+/// ```
+pub fn glob(pattern: &str) -> Vec<PathBuf> {
+    let mut paths = vec![PathBuf::new()];
+    for component in Path::new(pattern).components() {
+        let name = component.as_os_str().to_string_lossy();
+        paths = match component {
+            Component::Normal(_) if name == "**" => {
+                paths.iter().flat_map(|dir| subdirectories(dir)).collect()
+            }
+            Component::Normal(_) if is_glob(&name) => {
+                paths.iter().flat_map(|dir| children(dir, &name)).collect()
+            }
+            _ => paths.into_iter().map(|path| path.join(component)).collect(),
+        };
+    }
+
+    paths.retain(|path| !path.as_os_str().is_empty() && fs::symlink_metadata(path).is_ok());
+    paths.sort();
+    paths.dedup();
+    paths
+}
+
+/// Returns the entries of `dir` whose name matches `pattern`.
+fn children(dir: &Path, pattern: &str) -> Vec<PathBuf> {
+    let Ok(entries) = fs::read_dir(if dir.as_os_str().is_empty() {
+        Path::new(".")
+    } else {
+        dir
+    }) else {
+        return Vec::new();
+    };
+    entries
+        .filter_map(Result::ok)
+        .filter(|entry| {
+            let name = entry.file_name();
+            let name = name.to_string_lossy();
+            (!name.starts_with('.') || pattern.starts_with('.')) && matches(pattern, &name)
+        })
+        .map(|entry| dir.join(entry.file_name()))
+        .collect()
+}
+
+/// Returns `dir` and every directory below it, leaving out hidden ones and
+/// not following symbolic links.
+fn subdirectories(dir: &Path) -> Vec<PathBuf> {
+    let mut dirs = vec![dir.to_path_buf()];
+    let mut i = 0;
+    while let Some(dir) = dirs.get(i).cloned() {
+        i += 1;
+        for child in children(&dir, "*") {
+            if fs::symlink_metadata(&child).is_ok_and(|metadata| metadata.is_dir()) {
+                dirs.push(child);
+            }
+        }
+    }
+    dirs
+}
+
+/// Checks whether the `/`-separated `path` matches `pattern`, where `*` and
+/// `?` match within a single component and a `**` component matches any
+/// number of components, including none.
+pub fn matches_path(pattern: &str, path: &str) -> bool {
+    let pattern: Vec<&str> = pattern.split('/').filter(|c| !c.is_empty()).collect();
+    let path: Vec<&str> = path.split('/').filter(|c| !c.is_empty()).collect();
+    matches_components(&pattern, &path)
+}
+
+fn matches_components(pattern: &[&str], path: &[&str]) -> bool {
+    match pattern.split_first() {
+        None => path.is_empty(),
+        Some((&"**", rest)) => (0..=path.len()).any(|skip| matches_components(rest, &path[skip..])),
+        Some((first, rest)) => path
+            .split_first()
+            .is_some_and(|(name, path)| matches(first, name) && matches_components(rest, path)),
+    }
+}
+
+/// Returns up to five of `paths` closest to `pattern` by edit distance, either
+/// as a whole or by file name, leaving out those too far to be a typo.
+pub fn closest(pattern: &str, paths: &[PathBuf]) -> Vec<PathBuf> {
+    let name = pattern.rsplit('/').next().unwrap_or(pattern);
+    let threshold = (pattern.chars().count() / 3).max(2);
+
+    let mut scored: Vec<(usize, &PathBuf)> = paths
+        .iter()
+        .filter_map(|path| {
+            let whole = edit_distance(pattern, &path.to_string_lossy());
+            let file_name = path.file_name().map_or(usize::MAX, |file_name| {
+                edit_distance(name, &file_name.to_string_lossy())
+            });
+            let distance = whole.min(file_name);
+            (distance <= threshold).then_some((distance, path))
+        })
+        .collect();
+    scored.sort();
+    scored
+        .into_iter()
+        .take(5)
+        .map(|(_, path)| path.clone())
+        .collect()
+}
+
+/// Returns the one of `words` closest to `word` by edit distance, unless all
+/// are too far to be a typo of it.
+///
+/// # Examples
+///
+/// ```
+/// use backup::pattern::closest_word;
+///
+/// assert_eq!(closest_word("excldue", ["exclude", "exclude-from"]), Some("exclude"));
+/// assert_eq!(closest_word("force", ["exclude", "exclude-from"]), None);
+/// ```
+pub fn closest_word<'a>(word: &str, words: impl IntoIterator<Item = &'a str>) -> Option<&'a str> {
+    let threshold = (word.chars().count() / 3).max(1);
+    words
+        .into_iter()
+        .map(|candidate| (edit_distance(word, candidate), candidate))
+        .filter(|&(distance, _)| distance <= threshold)
+        .min_by_key(|&(distance, _)| distance)
+        .map(|(_, candidate)| candidate)
+}
+
+/// Number of single character insertions, deletions and substitutions
+/// turning `a` into `b`.
+pub fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for (i, a) in a.chars().enumerate() {
+        let mut current = vec![i + 1; b.len() + 1];
+        for (j, &b) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(a != b);
+            current[j + 1] = substitution.min(previous[j + 1] + 1).min(current[j] + 1);
+        }
+        previous = current;
+    }
+    previous[b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn classes_match_listed_characters_and_ranges() {
+        assert!(matches("file[0-9].log", "file7.log"));
+        assert!(!matches("file[0-9].log", "fileA.log"));
+        assert!(matches("[!.]*", "hosts"));
+        assert!(!matches("[^.]*", ".hidden"));
+        assert!(matches("[]a]", "]"));
+        assert!(matches("[a-]", "-"));
+        assert!(matches("*[", "a["));
+    }
+
+    #[test]
+    fn globs_expand_to_sorted_existing_paths() {
+        let tmp = tempfile::TempDir::new().unwrap();
+        let root = tmp.path();
+        for path in [
+            "a.log",
+            "b.log",
+            "c.txt",
+            ".hidden.log",
+            "sub/d.log",
+            "sub/deep/e.log",
+        ] {
+            fs::create_dir_all(root.join(path).parent().unwrap()).unwrap();
+            fs::write(root.join(path), "").unwrap();
+        }
+        let glob = |pattern: &str| -> Vec<PathBuf> {
+            glob(&root.join(pattern).to_string_lossy())
+                .into_iter()
+                .map(|path| path.strip_prefix(root).unwrap().to_path_buf())
+                .collect()
+        };
+
+        assert_eq!(glob("*.log"), [Path::new("a.log"), Path::new("b.log")]);
+        assert_eq!(glob(".*.log"), [Path::new(".hidden.log")]);
+        assert_eq!(glob("[bc].*"), [Path::new("b.log"), Path::new("c.txt")]);
+        assert_eq!(
+            glob("**/*.log"),
+            ["a.log", "b.log", "sub/d.log", "sub/deep/e.log"].map(Path::new)
+        );
+        assert!(glob("*.bin").is_empty());
+    }
+
+    #[test]
+    fn edit_distances() {
+        assert_eq!(edit_distance("", "abc"), 3);
+        assert_eq!(edit_distance("nginx.conf", "nginx.conf"), 0);
+        assert_eq!(edit_distance("ngnix.conf", "nginx.conf"), 2);
+        assert_eq!(edit_distance("kitten", "sitting"), 3);
+    }
+
+    #[test]
+    fn closest_paths_are_suggested_first() {
+        let paths: Vec<PathBuf> = ["etc/nginx/nginx.conf", "etc/nginx/mime.types", "etc/hosts"]
+            .into_iter()
+            .map(PathBuf::from)
+            .collect();
+
+        assert_eq!(
+            closest("etc/nginx/ngnix.conf", &paths),
+            [PathBuf::from("etc/nginx/nginx.conf")]
+        );
+        assert_eq!(closest("hots", &paths), [PathBuf::from("etc/hosts")]);
+        assert!(closest("passwd", &paths).is_empty());
+    }
+}
@@ -0,0 +1,299 @@
+//! Backups sent through, and restores read from, an arbitrary command.
+//!
+//! Rather than speaking every protocol itself, a backup can be written to the
+//! standard input of a command such as `rclone rcat remote:backups/{filename}`
+//! and restored from the standard output of one such as
+//! `rclone cat remote:backups/{filename}`. The command runs in `sh -c`, with
+//! `{filename}` replaced by the file name of the backup and `{source}` by the
+//! source backed up, or, on restore, by the name it was backed up under. Both
+//! are quoted as single shell words, so they must not be quoted again.
+//!
+//! A command naming its file with `{filename}` also receives the checksum
+//! manifest, metadata and signature files of the backup, each through a run
+//! of its own; other commands only receive the backup. The standard error of
+//! the command is that of this process, and a command exiting with a failure
+//! status, or before reading the whole backup, fails the backup. A backup
+//! failing otherwise kills the command rather than ending its input, so that
+//! it cannot take what it read for the whole backup.
+
+use std::io::{self, Read, Write};
+use std::path::PathBuf;
+use std::process::{Child, ChildStdin, ChildStdout, Command, ExitStatus, Stdio};
+
+use crate::error::BackupError;
+use crate::remote;
+use crate::store::{self, Store};
+
+/// Shell command a backup is written to or read from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Pipe {
+    command: String,
+    source: String,
+}
+
+impl Pipe {
+    /// Creates a pipe running `command` in `sh -c`, with the placeholders
+    /// `{filename}` and `{source}` replaced when it runs.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use backup::pipe::Pipe;
+    ///
+    /// let pipe = Pipe::new("rclone rcat remote:backups/{filename}");
+    /// assert!(pipe.names_file());
+    /// assert!(!Pipe::new("gpg -e -r admin > /mnt/backup.gpg").names_file());
+    /// ```
+    pub fn new(command: &str) -> Self {
+        Pipe {
+            command: command.to_owned(),
+            source: String::new(),
+        }
+    }
+
+    /// Returns the command, before its placeholders are replaced.
+    pub fn command(&self) -> &str {
+        &self.command
+    }
+
+    /// Returns whether the command names the file it writes or reads with
+    /// `{filename}`, so that several files can be sent through it.
+    pub fn names_file(&self) -> bool {
+        self.command.contains("{filename}")
+    }
+
+    /// Returns this pipe with `{source}` standing for `source`.
+    pub(crate) fn source(&self, source: &str) -> Pipe {
+        Pipe {
+            source: source.to_owned(),
+            ..self.clone()
+        }
+    }
+
+    /// Returns the command run for the file named `name`, with its
+    /// placeholders replaced by quoted words.
+    fn expand(&self, name: &str) -> String {
+        let mut expanded = String::with_capacity(self.command.len());
+        let mut rest = self.command.as_str();
+        while let Some(start) = rest.find('{') {
+            expanded.push_str(&rest[..start]);
+            rest = &rest[start..];
+            if let Some(after) = rest.strip_prefix("{filename}") {
+                expanded.push_str(&remote::quote(name));
+                rest = after;
+            } else if let Some(after) = rest.strip_prefix("{source}") {
+                expanded.push_str(&remote::quote(&self.source));
+                rest = after;
+            } else {
+                expanded.push('{');
+                rest = &rest[1..];
+            }
+        }
+        expanded.push_str(rest);
+        expanded
+    }
+
+    /// Runs the command for the file named `name`, its standard error going
+    /// to that of this process.
+    fn spawn(&self, name: &str, stdin: Stdio, stdout: Stdio) -> io::Result<Child> {
+        Command::new("sh")
+            .arg("-c")
+            .arg(self.expand(name))
+            .stdin(stdin)
+            .stdout(stdout)
+            .stderr(Stdio::inherit())
+            .spawn()
+    }
+
+    /// Starts reading the file named `name` from the standard output of the
+    /// command.
+    pub(crate) fn download(&self, name: &str) -> Result<Download, BackupError> {
+        let mut child = self
+            .spawn(name, Stdio::null(), Stdio::piped())
+            .map_err(|source| BackupError::ReadFailed {
+                path: PathBuf::from(name),
+                source,
+            })?;
+        let stdout = child.stdout.take();
+        Ok(Download {
+            name: name.to_owned(),
+            child,
+            stdout,
+        })
+    }
+}
+
+/// The command, receiving each file on its standard input. Whether a file
+/// already exists is up to the command, so `force` has no effect.
+impl Store for Pipe {
+    type Staged = ();
+    type Upload = Upload;
+
+    fn location(&self, name: &str) -> PathBuf {
+        PathBuf::from(name)
+    }
+
+    fn create(&self, name: &str, _force: bool) -> Result<Upload, BackupError> {
+        let mut child = self
+            .spawn(name, Stdio::piped(), Stdio::null())
+            .map_err(|source| BackupError::CreateFailed {
+                path: self.location(name),
+                source,
+            })?;
+        let stdin = child.stdin.take();
+        Ok(Upload {
+            name: name.to_owned(),
+            child,
+            stdin,
+        })
+    }
+
+    fn sidecars(&self) -> bool {
+        self.names_file()
+    }
+
+    fn commit(&self, _staged: &[()]) -> Result<(), BackupError> {
+        Ok(())
+    }
+
+    fn discard(&self, _staged: &[()]) {}
+}
+
+/// File being written to the standard input of a command, see
+/// [`Store::create`].
+pub(crate) struct Upload {
+    name: String,
+    child: Child,
+    stdin: Option<ChildStdin>,
+}
+
+impl Upload {
+    /// Closes the standard input of the command and waits for it to exit,
+    /// failing unless it succeeds.
+    fn wait(&mut self) -> Result<(), BackupError> {
+        drop(self.stdin.take());
+        match self.child.wait() {
+            Ok(status) if status.success() => Ok(()),
+            status => Err(BackupError::CreateFailed {
+                path: PathBuf::from(&self.name),
+                source: status.map_or_else(|error| error, failed),
+            }),
+        }
+    }
+}
+
+impl store::Upload for Upload {
+    type Staged = ();
+
+    fn finish(mut self) -> Result<(), BackupError> {
+        self.wait()
+    }
+
+    /// Reports the command failing rather than `error` when it did, as the
+    /// backup then most likely failed from writing to it, and otherwise kills
+    /// it before it takes what it read for the whole backup.
+    fn fail(mut self, error: BackupError) -> BackupError {
+        if let Ok(Some(status)) = self.child.try_wait() {
+            drop(self.stdin.take());
+            return match status.success() {
+                true => error,
+                false => BackupError::CreateFailed {
+                    path: PathBuf::from(&self.name),
+                    source: failed(status),
+                },
+            };
+        }
+        let _ = self.child.kill();
+        error
+    }
+}
+
+impl Write for Upload {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let Some(stdin) = &mut self.stdin else {
+            return Err(io::ErrorKind::BrokenPipe.into());
+        };
+        stdin.write(buf).map_err(|error| match error.kind() {
+            io::ErrorKind::BrokenPipe => io::Error::new(
+                io::ErrorKind::BrokenPipe,
+                "the pipe command exited before reading the whole backup",
+            ),
+            _ => error,
+        })
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match &mut self.stdin {
+            Some(stdin) => stdin.flush(),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Drop for Upload {
+    fn drop(&mut self) {
+        if self.stdin.take().is_some() {
+            let _ = self.child.kill();
+            let _ = self.child.wait();
+        }
+    }
+}
+
+/// File being read from the standard output of a command, see
+/// [`Pipe::download`].
+pub(crate) struct Download {
+    name: String,
+    child: Child,
+    stdout: Option<ChildStdout>,
+}
+
+impl store::Download for Download {
+    fn finish(&mut self) -> Result<(), BackupError> {
+        drop(self.stdout.take());
+        match self.child.wait() {
+            Ok(status) if status.success() => Ok(()),
+            status => Err(BackupError::ReadFailed {
+                path: PathBuf::from(&self.name),
+                source: status.map_or_else(|error| error, failed),
+            }),
+        }
+    }
+}
+
+impl Read for Download {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match &mut self.stdout {
+            Some(stdout) => stdout.read(buf),
+            None => Ok(0),
+        }
+    }
+}
+
+impl Drop for Download {
+    fn drop(&mut self) {
+        if self.stdout.is_some() {
+            let _ = self.child.kill();
+            let _ = self.child.wait();
+        }
+    }
+}
+
+/// Describes the command exiting with `status`.
+fn failed(status: ExitStatus) -> io::Error {
+    io::Error::other(format!("pipe command failed ({status})"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn placeholders_are_replaced_by_quoted_words() {
+        let pipe = Pipe::new("cat > dir/{filename}; echo {source} {other}").source("my data");
+        assert_eq!(
+            pipe.expand("it's.tar"),
+            r"cat > dir/'it'\''s.tar'; echo 'my data' {other}"
+        );
+        assert_eq!(Pipe::new("{{filename}}").expand("a"), "{'a'}");
+    }
+}
@@ -0,0 +1,552 @@
+//! Removal of old backups according to retention rules.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use chrono::{Datelike, Local, NaiveDateTime, Utc};
+
+use crate::catalog;
+use crate::checksum;
+use crate::error::BackupError;
+use crate::list::{self, ListEntry};
+use crate::meta;
+use crate::naming;
+use crate::restore;
+use crate::s3::Bucket;
+use crate::sign;
+use crate::trash::Trash;
+use crate::writer::{self, Level};
+
+/// Rules deciding which backups of each original name are kept.
+///
+/// A backup is kept if any rule keeps it, so it is only removed when it
+/// violates all of the configured rules.
+#[derive(Debug, Clone, Default)]
+pub struct Retention {
+    /// Keep the newest `n` backups.
+    pub keep_last: Option<usize>,
+    /// Keep backups created less than this long ago.
+    pub older_than: Option<Duration>,
+    /// Keep the newest backup of each of the last `n` days with backups.
+    pub keep_daily: Option<usize>,
+    /// Keep the newest backup of each of the last `n` ISO weeks with backups.
+    pub keep_weekly: Option<usize>,
+    /// Keep the newest backup of each of the last `n` months with backups.
+    pub keep_monthly: Option<usize>,
+    /// Keep the newest backup of each of the last `n` years with backups.
+    pub keep_yearly: Option<usize>,
+    /// Allow removing every backup of a name, including the newest one.
+    pub allow_empty: bool,
+}
+
+/// Outcome of a successful prune.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PruneReport {
+    /// Backups removed or moved to the trash, or that would be by a dry run.
+    pub removed: Vec<PathBuf>,
+    /// Number of bytes freed in the directory, or that would be by a dry run.
+    pub freed: u64,
+}
+
+impl Retention {
+    /// Checks that at least one rule is configured.
+    pub fn validate(&self) -> Result<(), BackupError> {
+        let periodic = Period::ALL
+            .iter()
+            .any(|&period| self.count(period).is_some());
+        if self.keep_last.is_none() && self.older_than.is_none() && !periodic {
+            return Err(BackupError::InvalidOption(
+                "Prune requires --keep-last, --older-than, --keep-daily, --keep-weekly, \
+                 --keep-monthly or --keep-yearly"
+                    .to_owned(),
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// Checks whether the backup at `rank` in its group, counting from the
+    /// newest at 0, is kept.
+    fn keeps(&self, rank: usize, timestamp: NaiveDateTime, cutoff: NaiveDateTime) -> bool {
+        (rank == 0 && !self.allow_empty)
+            || self.keep_last.is_some_and(|keep_last| rank < keep_last)
+            || self.older_than.is_some() && timestamp >= cutoff
+    }
+
+    /// Returns the number of backups kept by the rule for `period`.
+    fn count(&self, period: Period) -> Option<usize> {
+        match period {
+            Period::Day => self.keep_daily,
+            Period::Week => self.keep_weekly,
+            Period::Month => self.keep_monthly,
+            Period::Year => self.keep_yearly,
+        }
+    }
+}
+
+/// Calendar period of the `--keep-daily`, `--keep-weekly`, `--keep-monthly`
+/// and `--keep-yearly` rules.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Period {
+    Day,
+    Week,
+    Month,
+    Year,
+}
+
+/// Year and number of a [`Period`] within it.
+type PeriodKey = (i32, u32);
+
+impl Period {
+    const ALL: [Period; 4] = [Period::Day, Period::Week, Period::Month, Period::Year];
+
+    /// Identifies the period `timestamp` falls in. Weeks are ISO weeks,
+    /// starting on Monday, and the first days of January may belong to the
+    /// last week of the previous year.
+    fn key(self, timestamp: NaiveDateTime) -> PeriodKey {
+        let date = timestamp.date();
+        match self {
+            Period::Day => (date.year(), date.ordinal()),
+            Period::Week => (date.iso_week().year(), date.iso_week().week()),
+            Period::Month => (date.year(), date.month()),
+            Period::Year => (date.year(), 0),
+        }
+    }
+}
+
+/// State of the calendar rules of a [`Retention`] while walking the backups
+/// of a name from the newest: for each rule, the number of backups it may
+/// still keep and the period of the last one it kept.
+struct Periods(Vec<(Period, usize, Option<PeriodKey>)>);
+
+impl Periods {
+    fn new(retention: &Retention) -> Self {
+        let rules = Period::ALL
+            .into_iter()
+            .filter_map(|period| Some((period, retention.count(period)?, None)))
+            .collect();
+        Periods(rules)
+    }
+
+    /// Checks whether the backup made at `timestamp`, older than all those
+    /// checked before, is the newest of its period for a rule that may still
+    /// keep one. It is counted by every such rule.
+    fn keeps(&mut self, timestamp: NaiveDateTime) -> bool {
+        let mut keeps = false;
+        for (period, left, last) in &mut self.0 {
+            let key = period.key(timestamp);
+            if *left > 0 && *last != Some(key) {
+                *left -= 1;
+                *last = Some(key);
+                keeps = true;
+            }
+        }
+        keeps
+    }
+}
+
+/// Removes the backups in `dir` that are not kept by `retention`.
+///
+/// Only entries following the backup naming convention with a timestamp are
+/// considered, and their checksum manifests and metadata files are removed
+/// along with them. Calendar periods follow the timestamps in the names, so
+/// they are UTC days with [`naming::NameFormat::utc`] and local days
+/// otherwise.
+/// The backups that links to the newest backup of a name point to, see
+/// [`naming::latest_name`], are always kept.
+/// With `trash` set, backups are moved to it rather than deleted, see
+/// [`Trash::put_backup`]. With `dry_run` set, the backups that would be
+/// removed are printed but left in place. A failure to remove one backup is
+/// reported and does not stop the removal of the others, but makes the whole
+/// operation fail.
+pub fn prune(
+    dir: &Path,
+    retention: &Retention,
+    dry_run: bool,
+    trash: Option<&Trash>,
+) -> Result<PruneReport, BackupError> {
+    retention.validate()?;
+    let entries = list::list(dir, None, false)?;
+    let latest = latest_backups(dir);
+
+    let mut report = PruneReport::default();
+    let mut failures = 0;
+    for entry in expired(&entries, retention, now()) {
+        if fs::canonicalize(&entry.path).is_ok_and(|path| latest.contains(&path)) {
+            writer::log(
+                Level::Verbose,
+                format_args!("kept {}, the latest backup", entry.path.display()),
+            );
+            continue;
+        }
+        if dry_run {
+            writer::log(
+                Level::Info,
+                format_args!("would remove {}", entry.path.display()),
+            );
+        } else if let Some(trash) = trash {
+            if let Err(error) = trash.put_backup(&entry.path) {
+                writer::log(Level::Error, error);
+                failures += 1;
+                continue;
+            }
+            writer::log(
+                Level::Success,
+                format_args!(
+                    "moved {} to {}",
+                    entry.path.display(),
+                    trash.dir().display()
+                ),
+            );
+        } else if let Err(error) = restore::remove(&entry.path)
+            .and_then(|()| checksum::remove_manifests(&entry.path))
+            .and_then(|()| meta::remove(&entry.path))
+            .and_then(|()| sign::remove(&entry.path))
+        {
+            writer::log(Level::Error, error);
+            failures += 1;
+            continue;
+        } else {
+            writer::log(
+                Level::Success,
+                format_args!("removed {}", entry.path.display()),
+            );
+        }
+        report.removed.push(entry.path.clone());
+        report.freed += entry.size;
+    }
+
+    log_freed(&report, dry_run, trash.is_some());
+    if !dry_run && !report.removed.is_empty() {
+        catalog::forget(dir, &report.removed);
+    }
+    if failures > 0 {
+        return Err(BackupError::PruneFailed {
+            path: PathBuf::from(dir),
+            failures,
+        });
+    }
+
+    Ok(report)
+}
+
+/// Removes the backups under the prefix `prefix` of an S3 bucket, see
+/// [`crate::s3`], that are not kept by `retention`, as [`prune`] does in a
+/// directory, along with their checksum manifests, metadata and signature
+/// files.
+///
+/// # Examples
+///
+/// ```no_run
+/// use backup::prune::{prune_s3, Retention};
+/// use backup::s3::Bucket;
+///
+/// let prefix = Bucket::parse("s3://backups/web1").unwrap();
+/// let retention = Retention {
+///     keep_daily: Some(7),
+///     ..Retention::default()
+/// };
+/// let report = prune_s3(&prefix, &retention, false)?;
+/// println!("{} backups removed", report.removed.len());
+/// # Ok::<(), backup::BackupError>(())
+/// ```
+pub fn prune_s3(
+    prefix: &Bucket,
+    retention: &Retention,
+    dry_run: bool,
+) -> Result<PruneReport, BackupError> {
+    retention.validate()?;
+    let entries = list::list_s3(prefix, None, false)?;
+
+    let mut report = PruneReport::default();
+    let mut failures = 0;
+    for entry in expired(&entries, retention, now()) {
+        let name = entry.path.file_name().unwrap_or_default();
+        if dry_run {
+            writer::log(
+                Level::Info,
+                format_args!("would remove {}", entry.path.display()),
+            );
+        } else if let Err(error) = remove_s3(&prefix.join(&name.to_string_lossy())) {
+            writer::log(Level::Error, error);
+            failures += 1;
+            continue;
+        } else {
+            writer::log(
+                Level::Success,
+                format_args!("removed {}", entry.path.display()),
+            );
+        }
+        report.removed.push(entry.path.clone());
+        report.freed += entry.size;
+    }
+
+    log_freed(&report, dry_run, false);
+    if failures > 0 {
+        return Err(BackupError::PruneFailed {
+            path: prefix.display_path(),
+            failures,
+        });
+    }
+
+    Ok(report)
+}
+
+/// Deletes the backup `object` along with its checksum manifests, metadata
+/// and signature files.
+fn remove_s3(object: &Bucket) -> Result<(), BackupError> {
+    let backup = Path::new(object.file_name());
+    let sidecars = checksum::Algorithm::ALL
+        .map(|algorithm| algorithm.manifest_path(backup))
+        .into_iter()
+        .chain([meta::path(backup), sign::path(backup)]);
+    object.remove()?;
+    for sidecar in sidecars {
+        object.sibling(&sidecar.to_string_lossy()).remove()?;
+    }
+    Ok(())
+}
+
+/// Returns the current time in the time zone of the timestamps in backup
+/// names.
+fn now() -> NaiveDateTime {
+    match naming::format().is_utc() {
+        true => Utc::now().naive_utc(),
+        false => Local::now().naive_local(),
+    }
+}
+
+/// Logs the space freed by a prune, moved to the trash if `trash` is set.
+fn log_freed(report: &PruneReport, dry_run: bool, trash: bool) {
+    let bytes = writer::human_bytes(report.freed);
+    match (dry_run, trash) {
+        (true, _) => writer::log(Level::Info, format_args!("would free {bytes}")),
+        (false, true) => writer::log(Level::Info, format_args!("moved {bytes} to the trash")),
+        (false, false) => writer::log(Level::Info, format_args!("freed {bytes}")),
+    }
+}
+
+/// Lists the canonical paths of the backups the links to the newest backup
+/// of each name in `dir` point to.
+fn latest_backups(dir: &Path) -> Vec<PathBuf> {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return Vec::new();
+    };
+    entries
+        .flatten()
+        .filter(|entry| naming::is_latest(&entry.file_name()))
+        .filter_map(|entry| fs::canonicalize(entry.path()).ok())
+        .collect()
+}
+
+/// Selects the entries not kept by `retention` as of `now`.
+///
+/// `entries` must be grouped by name and sorted by timestamp, as returned by
+/// [`list::list`]. Backups named without a timestamp are replaced rather than
+/// accumulated, and are never selected. A backup kept by several rules counts
+/// toward each of them.
+fn expired<'a>(
+    entries: &'a [ListEntry],
+    retention: &Retention,
+    now: NaiveDateTime,
+) -> Vec<&'a ListEntry> {
+    let cutoff = retention
+        .older_than
+        .and_then(|age| chrono::Duration::from_std(age).ok())
+        .and_then(|age| now.checked_sub_signed(age))
+        .unwrap_or(NaiveDateTime::MIN);
+
+    entries
+        .chunk_by(|a, b| a.name == b.name)
+        .flat_map(|group| {
+            let mut periods = Periods::new(retention);
+            group
+                .iter()
+                .rev()
+                .filter_map(|entry| Some((entry.timestamp?, entry)))
+                .enumerate()
+                .filter(move |(rank, (timestamp, _))| {
+                    let periodic = periods.keeps(*timestamp);
+                    !periodic && !retention.keeps(*rank, *timestamp, cutoff)
+                })
+        })
+        .map(|(_, (_, entry))| entry)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::list::BackupKind;
+
+    /// Lists backups of a single name made at `timestamps`, oldest first.
+    fn entries(timestamps: &[&str]) -> Vec<ListEntry> {
+        timestamps
+            .iter()
+            .map(|timestamp| ListEntry {
+                name: "hosts".to_owned(),
+                timestamp: Some(parse(timestamp)),
+                size: 1,
+                kind: BackupKind::File,
+                path: PathBuf::from(timestamp),
+                meta: None,
+            })
+            .collect()
+    }
+
+    fn parse(timestamp: &str) -> NaiveDateTime {
+        NaiveDateTime::parse_from_str(timestamp, "%Y-%m-%d %H:%M").unwrap()
+    }
+
+    /// Returns the timestamps of the backups of `entries` kept by `retention`.
+    fn kept<'a>(entries: &'a [ListEntry], retention: &Retention) -> Vec<&'a Path> {
+        let expired = expired(entries, retention, parse("2025-01-01 00:00"));
+        entries
+            .iter()
+            .filter(|entry| !expired.iter().any(|expired| expired.path == entry.path))
+            .map(|entry| entry.path.as_path())
+            .collect()
+    }
+
+    #[test]
+    fn daily_rule_keeps_the_newest_backup_of_each_day() {
+        let entries = entries(&[
+            "2024-03-09 23:59",
+            "2024-03-10 00:00",
+            "2024-03-10 12:00",
+            "2024-03-10 23:59",
+            "2024-03-11 00:00",
+        ]);
+        let retention = Retention {
+            keep_daily: Some(2),
+            ..Retention::default()
+        };
+        assert_eq!(
+            kept(&entries, &retention),
+            [Path::new("2024-03-10 23:59"), Path::new("2024-03-11 00:00")]
+        );
+    }
+
+    #[test]
+    fn days_without_backups_do_not_count() {
+        let entries = entries(&["2024-01-01 10:00", "2024-02-01 10:00", "2024-03-01 10:00"]);
+        let retention = Retention {
+            keep_daily: Some(3),
+            ..Retention::default()
+        };
+        assert_eq!(kept(&entries, &retention).len(), 3);
+    }
+
+    #[test]
+    fn weeks_start_on_monday_and_span_years() {
+        let entries = entries(&[
+            "2024-12-22 10:00",
+            "2024-12-29 10:00",
+            "2024-12-30 10:00",
+            "2025-01-05 10:00",
+            "2025-01-06 10:00",
+        ]);
+        let retention = Retention {
+            keep_weekly: Some(3),
+            ..Retention::default()
+        };
+        assert_eq!(
+            kept(&entries, &retention),
+            [
+                Path::new("2024-12-29 10:00"),
+                Path::new("2025-01-05 10:00"),
+                Path::new("2025-01-06 10:00"),
+            ]
+        );
+    }
+
+    #[test]
+    fn monthly_and_yearly_rules_use_calendar_boundaries() {
+        let entries = entries(&[
+            "2022-06-01 10:00",
+            "2023-01-31 23:59",
+            "2023-12-31 23:59",
+            "2024-01-01 00:00",
+            "2024-02-29 12:00",
+        ]);
+        let monthly = Retention {
+            keep_monthly: Some(3),
+            ..Retention::default()
+        };
+        assert_eq!(
+            kept(&entries, &monthly),
+            [
+                Path::new("2023-12-31 23:59"),
+                Path::new("2024-01-01 00:00"),
+                Path::new("2024-02-29 12:00"),
+            ]
+        );
+
+        let yearly = Retention {
+            keep_yearly: Some(5),
+            ..Retention::default()
+        };
+        assert_eq!(
+            kept(&entries, &yearly),
+            [
+                Path::new("2022-06-01 10:00"),
+                Path::new("2023-12-31 23:59"),
+                Path::new("2024-02-29 12:00"),
+            ]
+        );
+    }
+
+    #[test]
+    fn backups_kept_by_several_rules_count_toward_each() {
+        let entries = entries(&[
+            "2024-01-15 10:00",
+            "2024-02-15 10:00",
+            "2024-03-14 10:00",
+            "2024-03-15 10:00",
+        ]);
+        let retention = Retention {
+            keep_daily: Some(1),
+            keep_monthly: Some(2),
+            ..Retention::default()
+        };
+        assert_eq!(
+            kept(&entries, &retention),
+            [Path::new("2024-02-15 10:00"), Path::new("2024-03-15 10:00")]
+        );
+    }
+
+    #[test]
+    fn calendar_rules_combine_with_the_other_rules() {
+        let entries = entries(&[
+            "2024-12-01 10:00",
+            "2024-12-31 10:00",
+            "2024-12-31 12:00",
+            "2024-12-31 23:00",
+        ]);
+        let retention = Retention {
+            keep_last: Some(2),
+            keep_daily: Some(2),
+            ..Retention::default()
+        };
+        assert_eq!(
+            kept(&entries, &retention),
+            [
+                Path::new("2024-12-01 10:00"),
+                Path::new("2024-12-31 12:00"),
+                Path::new("2024-12-31 23:00"),
+            ]
+        );
+    }
+
+    #[test]
+    fn a_calendar_rule_is_a_retention_rule() {
+        assert!(Retention::default().validate().is_err());
+        let retention = Retention {
+            keep_yearly: Some(1),
+            ..Retention::default()
+        };
+        assert!(retention.validate().is_ok());
+    }
+}
@@ -0,0 +1,166 @@
+//! Copying of file contents, cloned rather than duplicated when the source
+//! and target share a filesystem that allows it, such as btrfs or XFS.
+//!
+//! A clone is attempted first, then `copy_file_range`, which lets the kernel
+//! copy without going through user space, then a plain buffered copy. Both
+//! are only available on Linux.
+
+use std::fs::File;
+use std::io::{self, Read, Write};
+
+use crate::backup::Reflink;
+use crate::interrupt::{self, Interruptible};
+use crate::throttle::{Throttle, Throttled};
+
+/// Number of bytes copied in the kernel at once, between which an
+/// interruption is noticed.
+#[cfg(target_os = "linux")]
+const CHUNK: usize = 1 << 26;
+
+/// Copies the contents of `source` into the empty file `target` as `reflink`
+/// allows, returning the number of bytes copied.
+///
+/// With [`Reflink::Always`], failing to clone is an error rather than a
+/// reason to fall back. A plain copy goes through a buffer of `buffer_size`
+/// bytes, and is the only one used once writes are limited by `throttle`,
+/// which a copy in the kernel would escape. The copy stops once interrupted,
+/// see [`crate::interrupt`].
+pub(crate) fn copy(
+    source: &mut File,
+    target: &mut File,
+    reflink: Reflink,
+    buffer_size: usize,
+    throttle: Option<&Throttle>,
+) -> io::Result<u64> {
+    let plain = |source: &mut File, target: &mut File| {
+        let mut target = Throttled(target, throttle);
+        copy_buffered(&mut Interruptible(source), &mut target, buffer_size)
+    };
+    if reflink == Reflink::Never {
+        return plain(source, target);
+    }
+    if clone(source, target, reflink)? {
+        return source.metadata().map(|metadata| metadata.len());
+    }
+    if throttle.is_some() {
+        return plain(source, target);
+    }
+    match copy_range(source, target)? {
+        Some(bytes) => Ok(bytes),
+        None => plain(source, target),
+    }
+}
+
+/// Copies `reader` into `writer` through a buffer of `buffer_size` bytes,
+/// returning the number of bytes copied.
+pub(crate) fn copy_buffered(
+    reader: &mut impl Read,
+    writer: &mut impl Write,
+    buffer_size: usize,
+) -> io::Result<u64> {
+    let mut buffer = vec![0; buffer_size.max(1)];
+    let mut copied = 0;
+    loop {
+        let read = match reader.read(&mut buffer) {
+            Ok(0) => return Ok(copied),
+            Ok(read) => read,
+            Err(error) if error.kind() == io::ErrorKind::Interrupted => continue,
+            Err(error) => return Err(error),
+        };
+        writer.write_all(&buffer[..read])?;
+        copied += read as u64;
+    }
+}
+
+/// Clones the contents of `source` into the empty file `target`, returning
+/// `false` if the filesystem cannot and `reflink` allows falling back.
+pub(crate) fn clone(source: &File, target: &File, reflink: Reflink) -> io::Result<bool> {
+    match ficlone(source, target) {
+        Ok(()) => Ok(true),
+        Err(error) if reflink == Reflink::Always => Err(io::Error::new(
+            error.kind(),
+            format!("cannot clone the file ({error})"),
+        )),
+        Err(error) if is_unsupported(&error) => Ok(false),
+        Err(error) => Err(error),
+    }
+}
+
+/// Checks whether `error` tells that the filesystems involved cannot clone
+/// or copy in the kernel, rather than that the copy itself failed.
+fn is_unsupported(error: &io::Error) -> bool {
+    error.kind() == io::ErrorKind::Unsupported
+        || UNSUPPORTED.contains(&error.raw_os_error().unwrap_or(0))
+}
+
+#[cfg(target_os = "linux")]
+const UNSUPPORTED: [i32; 5] = [
+    libc::EOPNOTSUPP,
+    libc::ENOTTY,
+    libc::EXDEV,
+    libc::EINVAL,
+    libc::ENOSYS,
+];
+
+#[cfg(not(target_os = "linux"))]
+const UNSUPPORTED: [i32; 0] = [];
+
+/// Shares the extents of `source` with `target`.
+#[cfg(target_os = "linux")]
+fn ficlone(source: &File, target: &File) -> io::Result<()> {
+    use std::os::fd::AsRawFd;
+
+    // SAFETY: both descriptors stay open for the duration of the call.
+    let result = unsafe { libc::ioctl(target.as_raw_fd(), libc::FICLONE, source.as_raw_fd()) };
+    match result {
+        0 => Ok(()),
+        _ => Err(io::Error::last_os_error()),
+    }
+}
+
+/// Copies `source` to `target` in the kernel, returning `None` if it cannot
+/// before anything was copied.
+#[cfg(target_os = "linux")]
+fn copy_range(source: &File, target: &File) -> io::Result<Option<u64>> {
+    use std::os::fd::AsRawFd;
+
+    let mut copied = 0;
+    loop {
+        interrupt::check()?;
+        // SAFETY: both descriptors stay open for the duration of the call,
+        // and null offsets use and advance those of the files.
+        let result = unsafe {
+            libc::copy_file_range(
+                source.as_raw_fd(),
+                std::ptr::null_mut(),
+                target.as_raw_fd(),
+                std::ptr::null_mut(),
+                CHUNK,
+                0,
+            )
+        };
+        match result {
+            0 => return Ok(Some(copied)),
+            bytes if bytes > 0 => copied += bytes as u64,
+            _ => {
+                let error = io::Error::last_os_error();
+                if copied == 0 && is_unsupported(&error) {
+                    return Ok(None);
+                }
+                if error.kind() != io::ErrorKind::Interrupted {
+                    return Err(error);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+fn ficlone(_source: &File, _target: &File) -> io::Result<()> {
+    Err(io::Error::from(io::ErrorKind::Unsupported))
+}
+
+#[cfg(not(target_os = "linux"))]
+fn copy_range(_source: &File, _target: &File) -> io::Result<Option<u64>> {
+    Ok(None)
+}
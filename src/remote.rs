@@ -0,0 +1,392 @@
+//! Backups to and restores from other machines over SSH.
+//!
+//! A target, or a restore source, written `[user@]host:path` as for `scp`
+//! names `path` on `host`, relative to the home directory of the user there
+//! unless absolute. A local path holding a colon before any slash is told
+//! apart by starting it with `./`; the command line tool also takes such a
+//! path as local when it exists.
+//!
+//! The transfer runs the `ssh` command, so the ssh agent, keys and
+//! `~/.ssh/config` apply as for any other connection, and further options
+//! are passed with [`Remote::option`]. The remote side only needs a POSIX
+//! shell: the backup is streamed into a hidden `.<name>.partial` file next to
+//! where it goes, through `mkdir -p` and `cat`, along with its checksum
+//! manifest, metadata and signature files, and all are renamed into place
+//! once every one has been written. A transfer cut short removes the partial
+//! files, as the remote shell does itself when the connection drops.
+
+use std::fmt;
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Child, ChildStdin, ChildStdout, Command, ExitStatus, Stdio};
+
+use crate::backup;
+use crate::error::BackupError;
+use crate::store::{self, Store};
+
+/// Exit status of the remote shell when the backup exists and is not to be
+/// overwritten, or when the backup to read does not exist.
+const EXISTS: i32 = 17;
+
+/// Path on another machine reached over SSH.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Remote {
+    host: String,
+    path: String,
+    options: Vec<String>,
+}
+
+impl Remote {
+    /// Parses `[user@]host:path`, returning `None` if `spec` is a local path:
+    /// one without a colon, with a slash before it, or, on Windows, with a
+    /// drive letter before it.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use backup::remote::Remote;
+    ///
+    /// let remote = Remote::parse("admin@nas:/srv/backups").unwrap();
+    /// assert_eq!(remote.host(), "admin@nas");
+    /// assert_eq!(remote.path(), "/srv/backups");
+    /// assert!(Remote::parse("./admin@nas:/srv/backups").is_none());
+    /// ```
+    pub fn parse(spec: &str) -> Option<Self> {
+        let (host, path) = spec.split_once(':')?;
+        let drive = cfg!(windows) && host.len() == 1;
+        if host.is_empty() || host.contains('/') || host.starts_with('-') || drive {
+            return None;
+        }
+        Some(Remote {
+            host: host.to_owned(),
+            path: path.to_owned(),
+            options: Vec::new(),
+        })
+    }
+
+    /// Passes `option` to `ssh` as `-o <option>`, such as `Port=2222`.
+    pub fn option(mut self, option: &str) -> Self {
+        self.options.push(option.to_owned());
+        self
+    }
+
+    /// Returns the host, with the user if one was given.
+    pub fn host(&self) -> &str {
+        &self.host
+    }
+
+    /// Returns the path on the host.
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    /// Returns the file name the path ends with.
+    pub fn file_name(&self) -> &str {
+        self.path.rsplit('/').next().unwrap_or_default()
+    }
+
+    /// Returns the remote path of the entry named `name` in the directory
+    /// this path names.
+    pub fn join(&self, name: &str) -> Remote {
+        let path = match self.path.as_str() {
+            "" => name.to_owned(),
+            path if path.ends_with('/') => format!("{path}{name}"),
+            path => format!("{path}/{name}"),
+        };
+        Remote {
+            path,
+            ..self.clone()
+        }
+    }
+
+    /// Returns this remote path as a local one, for reports and errors.
+    pub fn display_path(&self) -> PathBuf {
+        PathBuf::from(self.to_string())
+    }
+
+    /// Starts writing the file this path names, creating its directory if
+    /// needed, into a partial file renamed into place by [`Store::commit`].
+    ///
+    /// An existing file is refused with [`BackupError::AlreadyExists`] when
+    /// the upload finishes, unless `force` is set.
+    pub(crate) fn upload(&self, force: bool) -> Result<Upload, BackupError> {
+        let partial = self.partial();
+        let dir = match self.path.rsplit_once('/') {
+            Some(("", _)) => "/",
+            Some((dir, _)) => dir,
+            None => ".",
+        };
+        let script = format!(
+            "mkdir -p -- {dir} || exit 1; \
+             if [ {force} = 0 ] && {{ [ -e {path} ] || [ -L {path} ]; }}; then exit {EXISTS}; fi; \
+             trap 'rm -f -- {partial}; exit 1' HUP INT TERM PIPE; \
+             cat > {partial} || {{ rm -f -- {partial}; exit 1; }}",
+            dir = quote(dir),
+            force = u8::from(force),
+            path = quote(&self.path),
+            partial = quote(&partial.path),
+        );
+        let mut child = self
+            .command(&script)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .spawn()
+            .map_err(|source| self.create_error(source))?;
+        let stdin = child.stdin.take();
+        Ok(Upload {
+            remote: self.clone(),
+            partial,
+            child,
+            stdin,
+        })
+    }
+
+    /// Starts reading the file this path names, failing with
+    /// [`BackupError::NotFound`] once read if there is none.
+    pub(crate) fn download(&self) -> Result<Download, BackupError> {
+        let script = format!(
+            "[ -f {path} ] || exit {EXISTS}; exec cat -- {path}",
+            path = quote(&self.path)
+        );
+        let mut child = self
+            .command(&script)
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .spawn()
+            .map_err(|source| BackupError::ReadFailed {
+                path: self.display_path(),
+                source,
+            })?;
+        let stdout = child.stdout.take();
+        Ok(Download {
+            remote: self.clone(),
+            child,
+            stdout,
+        })
+    }
+
+    /// Returns the hidden partial file this path is written to first, named
+    /// as local partial files are.
+    fn partial(&self) -> Remote {
+        let partial = backup::partial_path(Path::new(&self.path));
+        Remote {
+            path: partial.to_string_lossy().into_owned(),
+            ..self.clone()
+        }
+    }
+
+    /// Removes the partial file at this path, if the host can be reached.
+    fn remove_partial(&self) {
+        let _ = self
+            .command(&format!("rm -f -- {}", quote(&self.path)))
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status();
+    }
+
+    /// Builds the `ssh` command running `script` in the shell of the host.
+    fn command(&self, script: &str) -> Command {
+        let mut command = Command::new("ssh");
+        for option in &self.options {
+            command.arg("-o").arg(option);
+        }
+        command.arg("--").arg(&self.host).arg(script);
+        command
+    }
+
+    fn create_error(&self, source: io::Error) -> BackupError {
+        BackupError::CreateFailed {
+            path: self.display_path(),
+            source,
+        }
+    }
+}
+
+/// The directory this path names, holding files written through partial
+/// files renamed into place together.
+impl Store for Remote {
+    /// The partial file written and the path it is renamed to.
+    type Staged = (Remote, Remote);
+    type Upload = Upload;
+
+    fn location(&self, name: &str) -> PathBuf {
+        self.join(name).display_path()
+    }
+
+    fn create(&self, name: &str, force: bool) -> Result<Upload, BackupError> {
+        self.join(name).upload(force)
+    }
+
+    fn commit(&self, staged: &[(Remote, Remote)]) -> Result<(), BackupError> {
+        let script = staged
+            .iter()
+            .map(|(partial, path)| {
+                format!("mv -f -- {} {}", quote(&partial.path), quote(&path.path))
+            })
+            .collect::<Vec<_>>()
+            .join(" && ");
+        let status = self
+            .command(&script)
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .status();
+        match status {
+            Ok(status) if status.success() => Ok(()),
+            status => Err(self.create_error(status.map_or_else(|error| error, failed))),
+        }
+    }
+
+    fn discard(&self, staged: &[(Remote, Remote)]) {
+        for (partial, _) in staged {
+            partial.remove_partial();
+        }
+    }
+}
+
+impl fmt::Display for Remote {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.host, self.path)
+    }
+}
+
+/// File being written on a remote host, see [`Remote::upload`].
+pub(crate) struct Upload {
+    remote: Remote,
+    partial: Remote,
+    child: Child,
+    stdin: Option<ChildStdin>,
+}
+
+impl store::Upload for Upload {
+    type Staged = (Remote, Remote);
+
+    fn finish(mut self) -> Result<(Remote, Remote), BackupError> {
+        drop(self.stdin.take());
+        let status = self.child.wait();
+        match status {
+            Ok(status) if status.success() => Ok((self.partial.clone(), self.remote.clone())),
+            Ok(status) if status.code() == Some(EXISTS) => {
+                Err(BackupError::AlreadyExists(self.remote.display_path()))
+            }
+            status => {
+                self.partial.remove_partial();
+                Err(self
+                    .remote
+                    .create_error(status.map_or_else(|error| error, failed)))
+            }
+        }
+    }
+
+    fn fail(mut self, error: BackupError) -> BackupError {
+        drop(self.stdin.take());
+        match self.child.wait() {
+            Ok(status) if status.code() == Some(EXISTS) => {
+                BackupError::AlreadyExists(self.remote.display_path())
+            }
+            _ => {
+                self.partial.remove_partial();
+                error
+            }
+        }
+    }
+}
+
+impl Write for Upload {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match &mut self.stdin {
+            Some(stdin) => stdin.write(buf),
+            None => Err(io::ErrorKind::BrokenPipe.into()),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match &mut self.stdin {
+            Some(stdin) => stdin.flush(),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Drop for Upload {
+    fn drop(&mut self) {
+        if self.stdin.take().is_some() {
+            let _ = self.child.kill();
+            let _ = self.child.wait();
+            self.partial.remove_partial();
+        }
+    }
+}
+
+/// File being read from a remote host, see [`Remote::download`].
+pub(crate) struct Download {
+    remote: Remote,
+    child: Child,
+    stdout: Option<ChildStdout>,
+}
+
+impl store::Download for Download {
+    fn finish(&mut self) -> Result<(), BackupError> {
+        drop(self.stdout.take());
+        match self.child.wait() {
+            Ok(status) if status.success() => Ok(()),
+            Ok(status) if status.code() == Some(EXISTS) => {
+                Err(BackupError::NotFound(self.remote.display_path()))
+            }
+            status => Err(BackupError::ReadFailed {
+                path: self.remote.display_path(),
+                source: status.map_or_else(|error| error, failed),
+            }),
+        }
+    }
+}
+
+impl Read for Download {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match &mut self.stdout {
+            Some(stdout) => stdout.read(buf),
+            None => Ok(0),
+        }
+    }
+}
+
+impl Drop for Download {
+    fn drop(&mut self) {
+        if self.stdout.is_some() {
+            let _ = self.child.kill();
+            let _ = self.child.wait();
+        }
+    }
+}
+
+/// Describes `ssh` exiting with `status`.
+fn failed(status: ExitStatus) -> io::Error {
+    io::Error::other(format!("ssh failed ({status})"))
+}
+
+/// Quotes `text` as a single word for a POSIX shell.
+pub(crate) fn quote(text: &str) -> String {
+    format!("'{}'", text.replace('\'', r"'\''"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn specs_are_parsed_like_scp() {
+        let remote = Remote::parse("backup@nas:").unwrap();
+        assert_eq!((remote.host(), remote.path()), ("backup@nas", ""));
+        assert_eq!(remote.join("a.backup").path(), "a.backup");
+        let remote = Remote::parse("nas:/srv/").unwrap();
+        assert_eq!(remote.join("a.backup").to_string(), "nas:/srv/a.backup");
+        assert_eq!(
+            remote.join("a.backup").partial().path(),
+            "/srv/.a.backup.partial"
+        );
+        assert!(Remote::parse("notes.txt").is_none());
+        assert!(Remote::parse("dir/a:b").is_none());
+        assert!(Remote::parse(":path").is_none());
+        assert_eq!(quote("it's"), r"'it'\''s'");
+    }
+}
@@ -0,0 +1,1460 @@
+//! Restoration of backups created by [`crate::backup()`].
+
+use std::borrow::Cow;
+use std::collections::{BTreeSet, HashMap};
+use std::ffi::OsStr;
+use std::fs::{self, File};
+use std::io::{self, Read};
+use std::path::{Component, Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use chrono::{Local, NaiveDateTime};
+use filetime::FileTime;
+
+use crate::age::{self, Decryptor, Identity, Locked, Passphrase};
+use crate::archive;
+use crate::backup::{
+    self, Compression, CopyOptions, Counter, Digests, Fsync, Reflink, SpecialFiles,
+    BACKUP_EXTENSION, DEFAULT_BUFFER_SIZE,
+};
+use crate::checksum::{self, Algorithm, HashingReader};
+use crate::error::BackupError;
+use crate::exclude::Excludes;
+use crate::list;
+use crate::meta;
+use crate::naming::{self, NameFormat};
+use crate::pattern;
+use crate::pipe::Pipe;
+use crate::remote::Remote;
+use crate::s3::Bucket;
+use crate::sign::{self, VerifyingKey};
+use crate::split;
+use crate::stats::Stats;
+use crate::store::Download;
+use crate::verify;
+use crate::walk::{self, Walker};
+use crate::writer::{self, Level};
+use crate::xattrs;
+
+/// Suffix of the name of the safety copies made of replaced destinations.
+pub const SAFETY_SUFFIX: &str = ".pre-restore";
+
+/// Settings of a restore, built by chaining setters on [`RestoreOptions::new`].
+///
+/// The default options refuse to replace an existing destination, preserve
+/// modes and modification times, verify restored files against the checksum
+/// manifest of the backup, keep a safety copy of a forcibly replaced
+/// destination and restore files not named like a backup under their own name.
+#[derive(Debug, Clone)]
+pub struct RestoreOptions {
+    force: bool,
+    strict: bool,
+    preserve: bool,
+    preserve_owner: bool,
+    xattrs: bool,
+    original_path: bool,
+    paths: Vec<String>,
+    verify: bool,
+    safety: bool,
+    identities: Vec<Identity>,
+    passphrase: Option<Passphrase>,
+    verify_key: Option<VerifyingKey>,
+    verify_signature: bool,
+}
+
+impl Default for RestoreOptions {
+    fn default() -> Self {
+        RestoreOptions {
+            force: false,
+            strict: false,
+            preserve: true,
+            preserve_owner: false,
+            xattrs: xattrs::enabled_by_default(),
+            original_path: false,
+            paths: Vec::new(),
+            verify: true,
+            safety: true,
+            identities: Vec::new(),
+            passphrase: None,
+            verify_key: None,
+            verify_signature: true,
+        }
+    }
+}
+
+impl RestoreOptions {
+    /// Creates the default options.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Replaces an existing destination.
+    pub fn force(mut self, force: bool) -> Self {
+        self.force = force;
+        self
+    }
+
+    /// Rejects sources that do not follow the backup naming convention.
+    pub fn strict(mut self, strict: bool) -> Self {
+        self.strict = strict;
+        self
+    }
+
+    /// Preserves the mode and modification time of every file and directory.
+    pub fn preserve(mut self, preserve: bool) -> Self {
+        self.preserve = preserve;
+        self
+    }
+
+    /// Gives restored files the owner and group recorded in the backup.
+    ///
+    /// Without the privilege to do so, a warning is logged and restored files
+    /// keep the current owner.
+    pub fn preserve_owner(mut self, preserve_owner: bool) -> Self {
+        self.preserve_owner = preserve_owner;
+        self
+    }
+
+    /// Restores the extended attributes of every file and directory, see
+    /// [`BackupOptions::xattrs`](backup::BackupOptions::xattrs). Enabled by
+    /// default when running as root, on Linux only.
+    pub fn xattrs(mut self, xattrs: bool) -> Self {
+        self.xattrs = xattrs;
+        self
+    }
+
+    /// Restores the backup to the path it was created from, as recorded in
+    /// its metadata file, creating missing parent directories. No target may
+    /// be given along with this option.
+    pub fn original_path(mut self, original_path: bool) -> Self {
+        self.original_path = original_path;
+        self
+    }
+
+    /// Restores only the entries of a directory backup or archive matching
+    /// `pattern`, along with their contents, instead of the whole backup.
+    ///
+    /// Patterns are matched against paths relative to the backup root, using
+    /// `/` as separator, where `*` and `?` match within a single component and
+    /// `**` matches any number of components. The entries are restored at the
+    /// same relative path inside the destination, which may already exist.
+    pub fn path(mut self, pattern: &str) -> Self {
+        self.paths.push(pattern.trim_matches('/').to_owned());
+        self
+    }
+
+    /// Hashes restored files as they are written and compares them with the
+    /// checksum manifest of the backup, failing with
+    /// [`BackupError::Corrupted`] if any differs.
+    ///
+    /// Archives are checked as a whole, while they are read. Backups without a
+    /// manifest are restored unverified, which is logged.
+    pub fn verify(mut self, verify: bool) -> Self {
+        self.verify = verify;
+        self
+    }
+
+    /// Moves a destination replaced with [`RestoreOptions::force`] aside to a
+    /// `<name>.pre-restore.<timestamp>.backup` safety copy next to it, instead
+    /// of deleting it, and moves it back if the restore fails.
+    ///
+    /// Files replaced by a restore of selected paths are not kept.
+    pub fn safety(mut self, safety: bool) -> Self {
+        self.safety = safety;
+        self
+    }
+
+    /// Decrypts encrypted archives with `identity`, along with any other
+    /// identity added. Restoring an encrypted archive fails with
+    /// [`BackupError::NoIdentity`] unless one of them is a recipient.
+    pub fn identity(mut self, identity: Identity) -> Self {
+        self.identities.push(identity);
+        self
+    }
+
+    /// Decrypts archives encrypted with a passphrase with `passphrase`.
+    /// Restoring one fails with [`BackupError::NoPassphrase`] without it, and
+    /// with [`BackupError::WrongPassphrase`] if it is another one.
+    pub fn passphrase(mut self, passphrase: Passphrase) -> Self {
+        self.passphrase = Some(passphrase);
+        self
+    }
+
+    /// Checks that backups are signed by `key` before restoring them,
+    /// failing with [`BackupError::SignatureMissing`] or
+    /// [`BackupError::BadSignature`] otherwise, see [`sign::check`]. Files
+    /// are then checked against the signed manifest even without
+    /// [`RestoreOptions::verify`].
+    pub fn verify_key(mut self, key: VerifyingKey) -> Self {
+        self.verify_key = Some(key);
+        self
+    }
+
+    /// Whether a signature that does not check with the
+    /// [`RestoreOptions::verify_key`] stops the restore, rather than only
+    /// being warned about. Defaults to true.
+    pub fn verify_signature(mut self, verify_signature: bool) -> Self {
+        self.verify_signature = verify_signature;
+        self
+    }
+
+    /// Checks whether `relative`, or one of the directories holding it, is
+    /// selected by the [`RestoreOptions::path`] patterns.
+    fn selects(&self, relative: &Path) -> bool {
+        self.paths
+            .iter()
+            .any(|pattern| selected_by(pattern, relative))
+    }
+}
+
+/// Checks whether `relative`, or one of the directories holding it, matches
+/// `pattern`.
+fn selected_by(pattern: &str, relative: &Path) -> bool {
+    relative
+        .ancestors()
+        .filter(|path| !path.as_os_str().is_empty())
+        .any(|path| pattern::matches_path(pattern, &path.to_string_lossy()))
+}
+
+/// Outcome of a successful restore.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RestoreReport {
+    /// Path the backup was restored to.
+    pub path: PathBuf,
+    /// Number of files restored, not counting directories.
+    pub files: u64,
+    /// Number of bytes restored.
+    pub bytes: u64,
+    /// Safety copy the replaced destination was moved to, see
+    /// [`RestoreOptions::safety`].
+    pub safety: Option<PathBuf>,
+    /// Statistics of the restore, summing up the counts above.
+    pub stats: Stats,
+}
+
+/// Restores the backup at `source`, returning where it was restored to.
+///
+/// Without a `target`, a backup named `hosts.2024-05-01_10-00-00.backup` is
+/// restored to `hosts` in the same directory. If `target` is an existing
+/// directory the backup is restored inside it under its original name,
+/// otherwise it is restored to exactly `target`. With
+/// [`RestoreOptions::original_path`] set, it is restored to the path recorded
+/// in its metadata file instead, see [`meta`].
+///
+/// Backups whose name does not follow the naming convention are restored under
+/// their own name with a warning, or rejected if [`RestoreOptions::strict`] is
+/// set.
+///
+/// Tarballs created from directory backups are unpacked into a directory, and
+/// symbolic links are recreated as links. If the destination already exists
+/// the restore is refused unless [`RestoreOptions::force`] is set, in which
+/// case the destination is replaced.
+///
+/// # Examples
+///
+/// ```no_run
+/// use std::path::Path;
+///
+/// use backup::restore::RestoreOptions;
+///
+/// let source = Path::new("/var/backups/hosts.2024-05-01_10-00-00.backup");
+/// let report = backup::restore(source, Some(Path::new("/tmp")), &RestoreOptions::new())?;
+/// assert_eq!(report.path, Path::new("/tmp/hosts"));
+/// # Ok::<(), backup::BackupError>(())
+/// ```
+pub fn restore(
+    source: &Path,
+    target: Option<&Path>,
+    options: &RestoreOptions,
+) -> Result<RestoreReport, BackupError> {
+    let started = Instant::now();
+    let source = &*split::archive_of(&list::resolve_latest(source));
+    let strict = options.strict;
+    let parts = split::check(source)?;
+    let metadata =
+        split::metadata(source).map_err(|_| BackupError::NotFound(source.to_path_buf()))?;
+    let size = match parts {
+        Some(_) => split::size(source),
+        None => metadata.len(),
+    };
+    let signed = match &options.verify_key {
+        Some(key) => check_signature(source, key, options.verify_signature)?,
+        None => false,
+    };
+    let archive = archive::format(source).filter(|_| metadata.is_file());
+    let stats = |files, bytes| Stats {
+        files,
+        bytes_read: archive.map_or(bytes, |_| size),
+        bytes_written: bytes,
+        compressed: archive.is_some_and(|compression| compression != Compression::None),
+        elapsed: started.elapsed(),
+        ..Stats::default()
+    };
+
+    let destination = match target {
+        _ if options.original_path => original_path(source, target)?,
+        Some(target) if target.is_dir() => target.join(original_name(source, strict)?),
+        Some(target) => target.to_path_buf(),
+        None => source.with_file_name(original_name(source, strict)?),
+    };
+    if destination == source {
+        return Err(BackupError::NotABackup(source.to_path_buf()));
+    }
+
+    let checksums = match options.verify || signed {
+        true => Checksums::load(source)?,
+        false => None,
+    };
+    let copy_options = CopyOptions {
+        dereference: false,
+        preserve: options.preserve,
+        preserve_owner: options.preserve_owner && backup::check_owner_privilege(),
+        xattrs: options.xattrs,
+        reflink: Reflink::Auto,
+        buffer_size: DEFAULT_BUFFER_SIZE,
+        fsync: Fsync::None,
+        special_files: SpecialFiles::Record,
+        excludes: &Excludes::new(),
+        ignore_files: false,
+        exclude_caches: false,
+        max_depth: None,
+        max_files: None,
+        min_file_size: None,
+        max_file_size: None,
+        checksum: checksums.as_ref().map(|checksums| checksums.algorithm),
+        previous: None,
+        mirror: None,
+        jobs: 1,
+        keep_going: true,
+        retries: 0,
+        retry_delay: Duration::ZERO,
+        throttle: None,
+    };
+    if !options.paths.is_empty() {
+        let (files, bytes, digests) = restore_paths(source, &destination, options, copy_options)?;
+        if let Some(checksums) = &checksums {
+            checksums.check(source, &digests, false)?;
+        }
+        writer::log(
+            Level::Verbose,
+            format_args!(
+                "restored {files} files from {} to {}",
+                source.display(),
+                destination.display()
+            ),
+        );
+        return Ok(RestoreReport {
+            path: destination,
+            files,
+            bytes,
+            safety: None,
+            stats: stats(files, bytes),
+        });
+    }
+
+    backup::check_overwrite(&destination, options.force)?;
+    let safety = clear_destination(&destination, options.safety)?;
+
+    let restored = restore_whole(source, &destination, &metadata, options, copy_options).and_then(
+        |(files, bytes, digests)| {
+            if let Some(checksums) = &checksums {
+                checksums.check(source, &digests, !metadata.file_type().is_symlink())?;
+            }
+            Ok((files, bytes))
+        },
+    );
+    let (files, bytes) = match restored {
+        Ok(restored) => restored,
+        Err(error) => {
+            if let Some(safety) = &safety {
+                put_back(safety, &destination);
+            }
+            return Err(error);
+        }
+    };
+
+    writer::log(
+        Level::Verbose,
+        format_args!("restored {} to {}", source.display(), destination.display()),
+    );
+    Ok(RestoreReport {
+        path: destination,
+        files,
+        bytes,
+        safety,
+        stats: stats(files, bytes),
+    })
+}
+
+/// Extracts the archive read from `reader`, such as stdin, into the directory
+/// `destination`, as [`restore`] does for archived backups. The compression
+/// is detected from the first bytes read.
+///
+/// With no backup to read a checksum manifest from, the restored files are
+/// not verified.
+///
+/// # Examples
+///
+/// ```no_run
+/// use std::io;
+/// use std::path::Path;
+///
+/// use backup::restore::{restore_stream, RestoreOptions};
+///
+/// let report = restore_stream(io::stdin().lock(), Path::new("/srv/data"), &RestoreOptions::new())?;
+/// println!("{} files restored", report.files);
+/// # Ok::<(), backup::BackupError>(())
+/// ```
+pub fn restore_stream(
+    reader: impl Read,
+    destination: &Path,
+    options: &RestoreOptions,
+) -> Result<RestoreReport, BackupError> {
+    let started = Instant::now();
+    let source = Path::new("-");
+    let mut reader = Counter {
+        inner: reader,
+        bytes: 0,
+    };
+    let extract_error = |e| BackupError::ExtractFailed {
+        path: source.to_path_buf(),
+        source: e,
+    };
+    if options.verify_key.is_some() && options.verify_signature {
+        return Err(BackupError::InvalidOption(
+            "Signatures cannot be checked when restoring from stdin".to_owned(),
+        ));
+    }
+    let (stream, compression) = open_stream(source, &mut reader, options)?;
+
+    backup::check_overwrite(destination, options.force)?;
+    let safety = clear_destination(destination, options.safety)?;
+
+    let copy_options = CopyOptions {
+        dereference: false,
+        preserve: options.preserve,
+        preserve_owner: options.preserve_owner && backup::check_owner_privilege(),
+        xattrs: options.xattrs,
+        reflink: Reflink::Auto,
+        buffer_size: DEFAULT_BUFFER_SIZE,
+        fsync: Fsync::None,
+        special_files: SpecialFiles::Record,
+        excludes: &Excludes::new(),
+        ignore_files: false,
+        exclude_caches: false,
+        max_depth: None,
+        max_files: None,
+        min_file_size: None,
+        max_file_size: None,
+        checksum: None,
+        previous: None,
+        mirror: None,
+        jobs: 1,
+        keep_going: true,
+        retries: 0,
+        retry_delay: Duration::ZERO,
+        throttle: None,
+    };
+    if let Err(e) = extract_stream(stream, destination, compression, copy_options) {
+        let _ = fs::remove_dir_all(destination);
+        if let Some(safety) = &safety {
+            put_back(safety, destination);
+        }
+        return Err(extract_error(e));
+    }
+
+    let (files, bytes) = backup::tree_totals(destination, false);
+    writer::log(
+        Level::Verbose,
+        format_args!("restored stdin to {}", destination.display()),
+    );
+    Ok(RestoreReport {
+        path: destination.to_path_buf(),
+        files,
+        bytes,
+        safety,
+        stats: Stats {
+            files,
+            bytes_read: reader.bytes,
+            bytes_written: bytes,
+            compressed: compression != Compression::None,
+            elapsed: started.elapsed(),
+            ..Stats::default()
+        },
+    })
+}
+
+/// Restores the backup at `source` on another machine over SSH, see
+/// [`crate::remote`], to `target` as [`restore`] does, or under its original
+/// name in the current directory without a target.
+///
+/// Archives are extracted as they are read, as [`restore_stream`] does, and
+/// other backups written as a file. Neither is verified against its checksum
+/// manifest.
+///
+/// # Examples
+///
+/// ```no_run
+/// use std::path::Path;
+///
+/// use backup::remote::Remote;
+/// use backup::restore::{restore_remote, RestoreOptions};
+///
+/// let source = Remote::parse("admin@nas:/srv/backups/nginx.2024-05-01_10-00-00.backup.tar").unwrap();
+/// let report = restore_remote(&source, Some(Path::new("/etc")), &RestoreOptions::new())?;
+/// assert_eq!(report.path, Path::new("/etc/nginx"));
+/// # Ok::<(), backup::BackupError>(())
+/// ```
+pub fn restore_remote(
+    source: &Remote,
+    target: Option<&Path>,
+    options: &RestoreOptions,
+) -> Result<RestoreReport, BackupError> {
+    check_download(options, "over SSH")?;
+    let destination = download_destination(source.file_name(), target, options)?;
+    restore_download(
+        source.download()?,
+        &source.display_path(),
+        &destination,
+        options,
+    )
+}
+
+/// Restores the backup at `source` in an S3 bucket, see [`crate::s3`], as
+/// [`restore_remote`] does from another machine.
+///
+/// # Examples
+///
+/// ```no_run
+/// use std::path::Path;
+///
+/// use backup::restore::{restore_s3, RestoreOptions};
+/// use backup::s3::Bucket;
+///
+/// let source = Bucket::parse("s3://backups/web1/nginx.2024-05-01_10-00-00.backup.tar").unwrap();
+/// let report = restore_s3(&source, Some(Path::new("/etc")), &RestoreOptions::new())?;
+/// assert_eq!(report.path, Path::new("/etc/nginx"));
+/// # Ok::<(), backup::BackupError>(())
+/// ```
+pub fn restore_s3(
+    source: &Bucket,
+    target: Option<&Path>,
+    options: &RestoreOptions,
+) -> Result<RestoreReport, BackupError> {
+    check_download(options, "from S3")?;
+    let destination = download_destination(source.file_name(), target, options)?;
+    restore_download(
+        source.download()?,
+        &source.display_path(),
+        &destination,
+        options,
+    )
+}
+
+/// Restores the backup named `name` read from the standard output of the
+/// command `source`, see [`crate::pipe`], as [`restore_remote`] does from
+/// another machine.
+///
+/// `{filename}` in the command stands for `name`, and `{source}` for the
+/// name the backup was taken under.
+///
+/// # Examples
+///
+/// ```no_run
+/// use std::path::Path;
+///
+/// use backup::pipe::Pipe;
+/// use backup::restore::{restore_pipe, RestoreOptions};
+///
+/// let source = Pipe::new("rclone cat remote:backups/{filename}");
+/// let name = "nginx.2024-05-01_10-00-00.backup.tar";
+/// let report = restore_pipe(&source, name, Some(Path::new("/etc")), &RestoreOptions::new())?;
+/// assert_eq!(report.path, Path::new("/etc/nginx"));
+/// # Ok::<(), backup::BackupError>(())
+/// ```
+pub fn restore_pipe(
+    source: &Pipe,
+    name: &str,
+    target: Option<&Path>,
+    options: &RestoreOptions,
+) -> Result<RestoreReport, BackupError> {
+    check_download(options, "through a pipe")?;
+    let original = original_name(Path::new(name), options.strict)?;
+    let source = source.source(&original.to_string_lossy());
+    let destination = download_destination(name, target, options)?;
+    restore_download(
+        source.download(name)?,
+        Path::new(name),
+        &destination,
+        options,
+    )
+}
+
+/// Refuses the `options` that need the backup at hand when restoring it
+/// `from` elsewhere.
+fn check_download(options: &RestoreOptions, from: &str) -> Result<(), BackupError> {
+    if options.verify_key.is_some() && options.verify_signature {
+        return Err(BackupError::InvalidOption(format!(
+            "Signatures cannot be checked when restoring {from}"
+        )));
+    }
+    if !options.paths.is_empty() || options.original_path {
+        return Err(BackupError::InvalidOption(format!(
+            "--path and --original-path cannot be used when restoring {from}"
+        )));
+    }
+    Ok(())
+}
+
+/// Returns where the backup named `name` is restored to, see
+/// [`restore_remote`].
+fn download_destination(
+    name: &str,
+    target: Option<&Path>,
+    options: &RestoreOptions,
+) -> Result<PathBuf, BackupError> {
+    let original = original_name(Path::new(name), options.strict)?;
+    Ok(match target {
+        Some(target) if target.is_dir() => target.join(original),
+        Some(target) => target.to_path_buf(),
+        None => PathBuf::from(&original),
+    })
+}
+
+/// Restores the backup at `path` read through `download` to `destination`,
+/// see [`restore_remote`].
+fn restore_download(
+    mut download: impl Download,
+    path: &Path,
+    destination: &Path,
+    options: &RestoreOptions,
+) -> Result<RestoreReport, BackupError> {
+    let started = Instant::now();
+    let mut header = Vec::with_capacity(512);
+    (&mut download)
+        .take(512)
+        .read_to_end(&mut header)
+        .map_err(|source| BackupError::ReadFailed {
+            path: path.to_path_buf(),
+            source,
+        })?;
+    if header.is_empty() {
+        download.finish()?;
+    }
+    let reader = io::Cursor::new(&header).chain(&mut download);
+    let restored = match archive::detect(&header).is_some() || age::detect(&header) {
+        true => restore_stream(reader, destination, options),
+        false => {
+            restore_file(reader, path, destination, options.force).map(|bytes| RestoreReport {
+                path: destination.to_path_buf(),
+                files: 1,
+                bytes,
+                safety: None,
+                stats: Stats {
+                    files: 1,
+                    bytes_read: bytes,
+                    bytes_written: bytes,
+                    ..Stats::default()
+                },
+            })
+        }
+    };
+    let report = restored?;
+    if let Err(error) = download.finish() {
+        let _ = remove(destination);
+        return Err(error);
+    }
+
+    writer::log(
+        Level::Verbose,
+        format_args!("restored {} to {}", path.display(), destination.display()),
+    );
+    Ok(RestoreReport {
+        stats: Stats {
+            elapsed: started.elapsed(),
+            ..report.stats
+        },
+        ..report
+    })
+}
+
+/// Writes what `reader` yields, the backup `source`, to the file at
+/// `destination` through a temporary file renamed into place, returning the
+/// number of bytes written.
+fn restore_file(
+    mut reader: impl Read,
+    source: &Path,
+    destination: &Path,
+    force: bool,
+) -> Result<u64, BackupError> {
+    backup::check_overwrite(destination, force)?;
+    let partial = backup::partial_path(destination);
+    let written = File::create(&partial)
+        .and_then(|mut file| io::copy(&mut reader, &mut file))
+        .and_then(|bytes| fs::rename(&partial, destination).map(|()| bytes));
+    written.map_err(|e| {
+        let _ = fs::remove_file(&partial);
+        BackupError::CopyFailed {
+            from: source.to_path_buf(),
+            to: destination.to_path_buf(),
+            source: e,
+        }
+    })
+}
+
+/// Clears the way for a restore to the existing `destination`, if any, by
+/// moving it aside when `safety` is set or removing it otherwise. Returns the
+/// path of the safety copy.
+fn clear_destination(destination: &Path, safety: bool) -> Result<Option<PathBuf>, BackupError> {
+    match fs::symlink_metadata(destination) {
+        Ok(_) if safety => Ok(Some(move_aside(destination)?)),
+        Ok(_) => {
+            remove(destination)?;
+            Ok(None)
+        }
+        Err(_) => Ok(None),
+    }
+}
+
+/// Restores the whole backup at `source`, described by `metadata`, to the
+/// free path `destination`, returning the number of files and bytes restored
+/// and the digests of the files if [`CopyOptions::checksum`] is set.
+fn restore_whole(
+    source: &Path,
+    destination: &Path,
+    metadata: &fs::Metadata,
+    options: &RestoreOptions,
+    copy_options: CopyOptions,
+) -> Result<(u64, u64, Digests), BackupError> {
+    let root_digest = |digest: Option<String>| {
+        digest
+            .map(|digest| vec![(PathBuf::new(), digest)])
+            .unwrap_or_default()
+    };
+
+    if metadata.is_dir() {
+        backup::copy_directory(source, destination, copy_options)
+    } else if metadata.file_type().is_symlink() {
+        let (bytes, _) = backup::copy_entry(source, destination, copy_options)?;
+        Ok((1, bytes, Vec::new()))
+    } else if archive::format(source).is_some() || age::is_encrypted(source) {
+        let digest = unpack_archive(source, destination, options, copy_options)?;
+        let (files, bytes) = backup::tree_totals(destination, false);
+        Ok((files, bytes, root_digest(digest)))
+    } else {
+        let (bytes, digest) = backup::copy_entry(source, destination, copy_options)?;
+        Ok((1, bytes, root_digest(digest)))
+    }
+}
+
+/// Moves the existing `destination` of a restore aside, to a
+/// `<name>.pre-restore.<timestamp>.backup` safety copy in the same directory,
+/// and returns the path of the copy.
+fn move_aside(destination: &Path) -> Result<PathBuf, BackupError> {
+    let mut name = destination
+        .file_name()
+        .ok_or_else(|| BackupError::InvalidName(destination.to_path_buf()))?
+        .to_os_string();
+    name.push(SAFETY_SUFFIX);
+    let dir = destination.parent().unwrap_or(Path::new(""));
+    let safety = backup::backup_path(&dir.join(name), dir, Local::now(), None)?;
+
+    fs::rename(destination, &safety).map_err(|e| BackupError::CopyFailed {
+        from: destination.to_path_buf(),
+        to: safety.clone(),
+        source: e,
+    })?;
+    writer::log(
+        Level::Info,
+        format_args!(
+            "saved the existing '{}' to '{}'",
+            destination.display(),
+            safety.display()
+        ),
+    );
+    Ok(safety)
+}
+
+/// Moves the `safety` copy back to `destination` after a failed restore,
+/// replacing whatever was partially restored there.
+fn put_back(safety: &Path, destination: &Path) {
+    if fs::symlink_metadata(destination).is_ok() && remove(destination).is_err() {
+        return;
+    }
+    match fs::rename(safety, destination) {
+        Ok(()) => writer::log(
+            Level::Info,
+            format_args!("put the existing '{}' back", destination.display()),
+        ),
+        Err(e) => writer::log(
+            Level::Warning,
+            format_args!(
+                "'{}': Could not move back to '{}': {e}",
+                safety.display(),
+                destination.display()
+            ),
+        ),
+    }
+}
+
+/// Checks that the backup at `source` is signed by `key`, returning whether
+/// it is. A signature that does not check only warns unless `required`.
+fn check_signature(source: &Path, key: &VerifyingKey, required: bool) -> Result<bool, BackupError> {
+    match sign::check(source, key) {
+        Ok(_) => {
+            writer::log(
+                Level::Verbose,
+                format_args!("'{}': Signed by {key}", source.display()),
+            );
+            Ok(true)
+        }
+        Err(error) if !required => {
+            writer::log(Level::Warning, format_args!("{error}, restoring anyway"));
+            Ok(false)
+        }
+        Err(error) => Err(error),
+    }
+}
+
+/// Digests recorded in the checksum manifest of a backup, that restored files
+/// are checked against.
+struct Checksums {
+    /// Path of the manifest.
+    manifest: PathBuf,
+    /// Algorithm of the digests.
+    algorithm: Algorithm,
+    /// Recorded digests, by path relative to the backup, where an empty path
+    /// is the backup file itself.
+    digests: HashMap<PathBuf, String>,
+}
+
+impl Checksums {
+    /// Reads the manifest of the backup at `source`, or logs that the restore
+    /// is not verified if it has none.
+    fn load(source: &Path) -> Result<Option<Self>, BackupError> {
+        let Ok((manifest, algorithm)) = verify::find_manifest(source) else {
+            writer::log(
+                Level::Info,
+                format_args!(
+                    "'{}': No checksum manifest found, skipping verification",
+                    source.display()
+                ),
+            );
+            return Ok(None);
+        };
+
+        let name = Path::new(source.file_name().unwrap_or(source.as_os_str()));
+        let digests = checksum::read_manifest(&manifest)?
+            .into_iter()
+            .filter_map(|(path, digest)| {
+                Some((path.strip_prefix(name).ok()?.to_path_buf(), digest))
+            })
+            .collect();
+        Ok(Some(Checksums {
+            manifest,
+            algorithm,
+            digests,
+        }))
+    }
+
+    /// Compares the `restored` digests of files with the recorded ones,
+    /// failing with the list of files that differ. If the restore was
+    /// `complete`, recorded files that were not restored differ too.
+    fn check(
+        &self,
+        source: &Path,
+        restored: &[(PathBuf, String)],
+        complete: bool,
+    ) -> Result<(), BackupError> {
+        let mut corrupted: Vec<&Path> = restored
+            .iter()
+            .filter(|(path, digest)| {
+                self.digests
+                    .get(path)
+                    .is_some_and(|recorded| recorded != digest)
+            })
+            .map(|(path, _)| path.as_path())
+            .collect();
+        if complete {
+            let restored: BTreeSet<&Path> =
+                restored.iter().map(|(path, _)| path.as_path()).collect();
+            corrupted.extend(
+                self.digests
+                    .keys()
+                    .filter(|path| !restored.contains(path.as_path()))
+                    .map(PathBuf::as_path),
+            );
+        }
+
+        if !corrupted.is_empty() {
+            let name = Path::new(source.file_name().unwrap_or(source.as_os_str()));
+            let mut entries: Vec<PathBuf> = corrupted
+                .into_iter()
+                .map(|path| match path.as_os_str().is_empty() {
+                    true => name.to_path_buf(),
+                    false => name.join(path),
+                })
+                .collect();
+            entries.sort();
+            return Err(BackupError::Corrupted {
+                path: source.to_path_buf(),
+                entries,
+            });
+        }
+
+        writer::log(
+            Level::Verbose,
+            format_args!(
+                "verified {} files against {}",
+                restored.len(),
+                self.manifest.display()
+            ),
+        );
+        Ok(())
+    }
+}
+
+/// Restores the entries of the directory backup or archive at `source`
+/// selected by the patterns of `options` into `destination`, returning the
+/// number of files and bytes restored, and the digests of the files if
+/// [`CopyOptions::checksum`] is set. Archives are hashed as a whole.
+///
+/// Every pattern must match an entry, otherwise nothing is restored and the
+/// closest paths of the backup are suggested. Existing directories are
+/// restored into, while other existing entries are only replaced if forced.
+fn restore_paths(
+    source: &Path,
+    destination: &Path,
+    options: &RestoreOptions,
+    copy_options: CopyOptions,
+) -> Result<(u64, u64, Digests), BackupError> {
+    let compression = archive::format(source);
+    let contents = if source.is_dir() {
+        Walker::new(source, false)?
+            .map(|entry| entry.map(|entry| entry.relative))
+            .collect::<Result<Vec<_>, _>>()?
+    } else if age::is_encrypted(source) {
+        return Err(BackupError::InvalidOption(
+            "Encrypted archives can only be restored whole".to_owned(),
+        ));
+    } else if let Some(compression) = compression {
+        archive::contents(source, compression)
+            .map_err(|e| BackupError::ExtractFailed {
+                path: source.to_path_buf(),
+                source: e,
+            })?
+            .into_iter()
+            .map(|entry| entry.path)
+            .filter(|path| !path.as_os_str().is_empty())
+            .collect()
+    } else {
+        return Err(BackupError::InvalidOption(
+            "Only directory backups and archives can be restored in part".to_owned(),
+        ));
+    };
+    for pattern in &options.paths {
+        if !contents.iter().any(|path| selected_by(pattern, path)) {
+            return Err(BackupError::NoMatch {
+                pattern: pattern.clone(),
+                suggestions: pattern::closest(pattern, &contents),
+            });
+        }
+    }
+
+    fs::create_dir_all(destination).map_err(|e| BackupError::CreateFailed {
+        path: destination.to_path_buf(),
+        source: e,
+    })?;
+    let replace = |path: &Path| -> Result<(), BackupError> {
+        match fs::symlink_metadata(path) {
+            Ok(metadata) if metadata.is_dir() => Ok(()),
+            Ok(_) => {
+                backup::check_overwrite(path, options.force)?;
+                remove(path)
+            }
+            Err(_) => Ok(()),
+        }
+    };
+
+    let (mut files, mut bytes, mut digests) = (0, 0, Vec::new());
+    match compression {
+        Some(compression) if !source.is_dir() => {
+            let extract_error = |e| BackupError::ExtractFailed {
+                path: source.to_path_buf(),
+                source: e,
+            };
+            let file = split::open(source).map_err(extract_error)?;
+            let mut reader = HashingReader::new(file, copy_options.checksum);
+            let mut archive = archive::read(&mut reader, compression).map_err(extract_error)?;
+            archive.set_preserve_mtime(copy_options.preserve);
+            archive.set_preserve_ownerships(copy_options.preserve_owner);
+            for entry in archive.entries().map_err(extract_error)? {
+                let mut entry = entry.map_err(extract_error)?;
+                let relative = archive::relative_path(&entry.path().map_err(extract_error)?);
+                if !options.selects(&relative) {
+                    continue;
+                }
+                let is_dir = entry.header().entry_type().is_dir();
+                if !is_dir {
+                    replace(&destination.join(&relative))?;
+                }
+                unpack(&mut entry, destination, copy_options).map_err(extract_error)?;
+                if !is_dir {
+                    files += 1;
+                    bytes += entry.header().size().map_err(extract_error)?;
+                }
+            }
+            drop(archive);
+            let digest = reader.finish().map_err(extract_error)?;
+            digests.extend(digest.map(|digest| (PathBuf::new(), digest)));
+        }
+        _ => {
+            for entry in Walker::new(source, false)? {
+                let entry = entry?;
+                if !options.selects(&entry.relative) {
+                    continue;
+                }
+                let target = destination.join(&entry.relative);
+                let create_error = |path: &Path, e| BackupError::CreateFailed {
+                    path: path.to_path_buf(),
+                    source: e,
+                };
+                if entry.metadata.is_dir() {
+                    fs::create_dir_all(&target).map_err(|e| create_error(&target, e))?;
+                    continue;
+                }
+                if let Some(parent) = target.parent() {
+                    fs::create_dir_all(parent).map_err(|e| create_error(parent, e))?;
+                }
+                replace(&target)?;
+                let (copied, digest) = backup::copy_entry(&entry.path, &target, copy_options)?;
+                bytes += copied;
+                files += 1;
+                digests.extend(digest.map(|digest| (entry.relative, digest)));
+            }
+        }
+    }
+
+    Ok((files, bytes, digests))
+}
+
+/// Finds the newest backup in `dir` of the file or directory named `original`,
+/// see [`versions`].
+///
+/// # Examples
+///
+/// ```no_run
+/// use std::path::Path;
+///
+/// use backup::restore::{self, RestoreOptions};
+///
+/// let latest = restore::latest(Path::new("/var/backups"), Path::new("hosts"))?;
+/// backup::restore(&latest, None, &RestoreOptions::new())?;
+/// # Ok::<(), backup::BackupError>(())
+/// ```
+pub fn latest(dir: &Path, original: &Path) -> Result<PathBuf, BackupError> {
+    versions(dir, original)?
+        .pop()
+        .ok_or_else(|| BackupError::NoBackup(dir.join(original.file_name().unwrap_or_default())))
+}
+
+/// Finds the backups in `dir` of the file or directory named `original`,
+/// from the oldest to the newest.
+///
+/// When backups of several sources with the same name are found, as told by
+/// their metadata files, the choice is refused with
+/// [`BackupError::AmbiguousBackup`]. An `original` with a directory part, such
+/// as `/etc/hosts`, only matches the backups recorded as made from that path.
+pub fn versions(dir: &Path, original: &Path) -> Result<Vec<PathBuf>, BackupError> {
+    let Some(name) = original.file_name() else {
+        return Ok(Vec::new());
+    };
+    let wanted = match original.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => Some(
+            fs::canonicalize(parent)
+                .map(|parent| parent.join(name))
+                .or_else(|_| std::path::absolute(original))
+                .map_err(|source| BackupError::ReadFailed {
+                    path: original.to_path_buf(),
+                    source,
+                })?,
+        ),
+        _ => None,
+    };
+
+    let mut sources = BTreeSet::new();
+    let mut backups = Vec::new();
+    for backup in list::backups_of(dir, name) {
+        let source = meta::read(&backup).ok().flatten().map(|meta| meta.source);
+        if wanted.is_some() && source != wanted {
+            continue;
+        }
+        sources.extend(source);
+        backups.push(backup);
+    }
+
+    if sources.len() > 1 {
+        return Err(BackupError::AmbiguousBackup {
+            name: name.into(),
+            sources: sources.into_iter().collect(),
+        });
+    }
+    Ok(backups)
+}
+
+/// Extracts the archive at `source` into the directory `destination`,
+/// decrypting it with the keys of `restore_options` if encrypted, and returns
+/// the digest of the archive if [`CopyOptions::checksum`] is set.
+///
+/// Modes are always taken from the archive, and modification times and owners
+/// too if preserving them. A partially extracted destination is removed if
+/// extraction fails.
+fn unpack_archive(
+    source: &Path,
+    destination: &Path,
+    restore_options: &RestoreOptions,
+    options: CopyOptions,
+) -> Result<Option<String>, BackupError> {
+    let extract_error = |e| BackupError::ExtractFailed {
+        path: source.to_path_buf(),
+        source: e,
+    };
+    let file = split::open(source).map_err(extract_error)?;
+    let mut reader = HashingReader::new(file, options.checksum);
+    let (stream, compression) = open_stream(source, &mut reader, restore_options)?;
+    let result =
+        extract_stream(stream, destination, compression, options).and_then(|_| reader.finish());
+
+    result.map_err(|e| {
+        let _ = fs::remove_dir_all(destination);
+        extract_error(e)
+    })
+}
+
+/// Detects the archive read from `input`, decrypting it with the keys of
+/// `options` first if it is encrypted, and returns the stream of the archive
+/// along with its compression.
+fn open_stream<'a>(
+    source: &Path,
+    input: impl Read + 'a,
+    options: &RestoreOptions,
+) -> Result<(Box<dyn Read + 'a>, Compression), BackupError> {
+    let extract_error = |e| BackupError::ExtractFailed {
+        path: source.to_path_buf(),
+        source: e,
+    };
+    let read_header = |input: &mut dyn Read| {
+        let mut header = Vec::with_capacity(512);
+        input.take(512).read_to_end(&mut header).map(|_| header)
+    };
+
+    let mut input: Box<dyn Read + 'a> = Box::new(input);
+    let mut header = read_header(&mut input).map_err(extract_error)?;
+    if age::detect(&header) {
+        let encrypted = io::Cursor::new(header).chain(input);
+        let passphrase = options.passphrase.as_ref();
+        let decryptor = Decryptor::new(encrypted, &options.identities, passphrase)
+            .map_err(extract_error)?
+            .map_err(|locked| match locked {
+                Locked::NoIdentity => BackupError::NoIdentity {
+                    path: source.to_path_buf(),
+                    tried: options.identities.len(),
+                },
+                Locked::NoPassphrase => BackupError::NoPassphrase(source.to_path_buf()),
+                Locked::WrongPassphrase => BackupError::WrongPassphrase(source.to_path_buf()),
+            })?;
+        input = Box::new(decryptor);
+        header = read_header(&mut input).map_err(extract_error)?;
+    }
+    let compression =
+        archive::detect(&header).ok_or_else(|| BackupError::NotABackup(source.to_path_buf()))?;
+    Ok((Box::new(io::Cursor::new(header).chain(input)), compression))
+}
+
+/// Extracts the archive compressed with `compression` read from `stream`
+/// into `destination`, see [`extract`], then reads the rest of the stream so
+/// that the whole of an encrypted archive is authenticated.
+fn extract_stream(
+    mut stream: impl Read,
+    destination: &Path,
+    compression: Compression,
+    options: CopyOptions,
+) -> io::Result<()> {
+    let mut archive = archive::read(&mut stream, compression)?;
+    archive.set_preserve_mtime(options.preserve);
+    archive.set_preserve_ownerships(options.preserve_owner);
+    extract(&mut archive, destination, options)?;
+    drop(archive);
+    io::copy(&mut stream, &mut io::sink()).map(|_| ())
+}
+
+/// Extracts the entries of `archive` into `destination`, creating it.
+///
+/// Like [`tar::Archive::unpack`], directories are extracted last, deepest
+/// first, so that their modes do not prevent extracting their contents. Unlike
+/// it, the mode, modification time and owner of the root entry are applied to
+/// `destination` itself.
+fn extract<R: Read>(
+    archive: &mut tar::Archive<R>,
+    destination: &Path,
+    options: CopyOptions,
+) -> io::Result<()> {
+    let destination = walk::long_path(destination);
+    let destination = &*destination;
+    fs::create_dir_all(destination)?;
+
+    let mut directories = Vec::new();
+    let mut root = None;
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        if !entry.header().entry_type().is_dir() {
+            unpack(&mut entry, destination, options)?;
+        } else if entry
+            .path()?
+            .components()
+            .all(|component| component == Component::CurDir)
+        {
+            let xattrs = match options.xattrs {
+                true => xattrs::from_pax(&mut entry)?,
+                false => Vec::new(),
+            };
+            root = Some((entry.header().clone(), xattrs));
+        } else {
+            directories.push(entry);
+        }
+    }
+
+    directories.sort_by(|a, b| b.path_bytes().cmp(&a.path_bytes()));
+    for mut directory in directories {
+        unpack(&mut directory, destination, options)?;
+    }
+
+    if let Some((header, xattrs)) = root {
+        xattrs::apply(destination, &xattrs)?;
+        if options.preserve_owner {
+            set_owner(destination, header.uid()?, header.gid()?)?;
+        }
+        set_mode(destination, header.mode()?)?;
+        if options.preserve {
+            let mtime = FileTime::from_unix_time(header.mtime()? as i64, 0);
+            filetime::set_file_mtime(destination, mtime)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Unpacks `entry` inside `destination`, along with the extended attributes
+/// recorded for it if [`CopyOptions::xattrs`] is set.
+fn unpack<R: Read>(
+    entry: &mut tar::Entry<'_, R>,
+    destination: &Path,
+    options: CopyOptions,
+) -> io::Result<()> {
+    let xattrs = match options.xattrs {
+        true => xattrs::from_pax(entry)?,
+        false => Vec::new(),
+    };
+    let unpacked = match unpack_special(entry, destination, options)? {
+        true => true,
+        false => entry.unpack_in(destination)?,
+    };
+    if unpacked && !xattrs.is_empty() {
+        let path = destination.join(archive::relative_path(&entry.path()?));
+        xattrs::apply(&path, &xattrs)?;
+    }
+    Ok(())
+}
+
+/// Creates the named pipe or device that `entry` describes inside
+/// `destination`, which [`tar::Entry::unpack_in`] would make a regular file.
+/// Returns `false` for any other entry, and for a path leaving `destination`.
+#[cfg(unix)]
+fn unpack_special<R: Read>(
+    entry: &mut tar::Entry<'_, R>,
+    destination: &Path,
+    options: CopyOptions,
+) -> io::Result<bool> {
+    let header = entry.header();
+    // File type bits of the mode, the same on every Unix.
+    let kind = match header.entry_type() {
+        tar::EntryType::Fifo => 0o010000,
+        tar::EntryType::Char => 0o020000,
+        tar::EntryType::Block => 0o060000,
+        _ => return Ok(false),
+    };
+    let relative = archive::relative_path(&entry.path()?);
+    if !relative
+        .components()
+        .all(|component| matches!(component, Component::Normal(_)))
+    {
+        return Ok(false);
+    }
+
+    let path = destination.join(relative);
+    let device = libc::makedev(
+        header.device_major()?.unwrap_or(0) as _,
+        header.device_minor()?.unwrap_or(0) as _,
+    );
+    backup::make_node(&path, kind | (header.mode()? & 0o7777), device)?;
+    if options.preserve_owner {
+        set_owner(&path, header.uid()?, header.gid()?)?;
+    }
+    set_mode(&path, header.mode()?)?;
+    if options.preserve {
+        // Setting the modification time alone would open the pipe.
+        let mtime = FileTime::from_unix_time(header.mtime()? as i64, 0);
+        filetime::set_symlink_file_times(&path, mtime, mtime)?;
+    }
+    Ok(true)
+}
+
+#[cfg(not(unix))]
+fn unpack_special<R: Read>(
+    _entry: &mut tar::Entry<'_, R>,
+    _destination: &Path,
+    _options: CopyOptions,
+) -> io::Result<bool> {
+    Ok(false)
+}
+
+/// Sets the permission bits of `path` to those of a tar header `mode`.
+#[cfg(unix)]
+fn set_mode(path: &Path, mode: u32) -> io::Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+
+    fs::set_permissions(path, fs::Permissions::from_mode(mode & 0o777))
+}
+
+#[cfg(not(unix))]
+fn set_mode(_path: &Path, _mode: u32) -> io::Result<()> {
+    Ok(())
+}
+
+/// Sets the owner and group of `path` to those of a tar header.
+#[cfg(unix)]
+fn set_owner(path: &Path, uid: u64, gid: u64) -> io::Result<()> {
+    let id = |id: u64| u32::try_from(id).map_err(io::Error::other);
+    std::os::unix::fs::chown(path, Some(id(uid)?), Some(id(gid)?))
+}
+
+#[cfg(not(unix))]
+fn set_owner(_path: &Path, _uid: u64, _gid: u64) -> io::Result<()> {
+    Ok(())
+}
+
+/// The components of a backup name, `<original>.<timestamp>.backup` optionally
+/// followed by an archive extension such as `.tar.zst`, or as laid out by the
+/// installed [`naming::NameFormat`].
+///
+/// The timestamp may carry a `_<n>` counter, added when several backups of the
+/// same name are made within the same second.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BackupName<'a> {
+    /// Name of the file or directory that was backed up, which may not be
+    /// valid UTF-8.
+    pub original: Cow<'a, OsStr>,
+    /// Time at which the backup was created, unless named without a
+    /// timestamp.
+    pub timestamp: Option<NaiveDateTime>,
+    /// Position among the backups made within the same second, starting at 1.
+    pub sequence: u32,
+    /// Archive extension following the `.backup` suffix, if any.
+    pub archive: Option<&'static str>,
+}
+
+impl<'a> BackupName<'a> {
+    /// Parses a backup file name with the installed [`naming::NameFormat`],
+    /// returning `None` if it does not follow it.
+    ///
+    /// With the default format, original names may themselves contain dots:
+    /// only the last two dot-separated components before any archive
+    /// extension are the timestamp and the `backup` suffix.
+    pub fn parse(name: &'a OsStr) -> Option<Self> {
+        naming::format().parse(name)
+    }
+}
+
+/// Returns the path `source` was backed up from, read from its metadata file,
+/// and creates its missing parent directories.
+fn original_path(source: &Path, target: Option<&Path>) -> Result<PathBuf, BackupError> {
+    if target.is_some() {
+        return Err(BackupError::InvalidOption(
+            "A target cannot be given along with --original-path".to_owned(),
+        ));
+    }
+
+    let path = meta::read(source)?
+        .ok_or_else(|| BackupError::MetaMissing(source.to_path_buf()))?
+        .source;
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).map_err(|e| BackupError::CreateFailed {
+            path: parent.to_path_buf(),
+            source: e,
+        })?;
+    }
+    Ok(path)
+}
+
+/// Returns the name `source` should be restored under.
+///
+/// Names following the backup convention, or made of the original name and
+/// a `.backup` suffix without a timestamp, are stripped back to the original
+/// name, which is read from the metadata file if it was sanitized for the
+/// target filesystem. Other names are rejected if `strict` is set, and
+/// otherwise kept as-is with a warning.
+fn original_name(source: &Path, strict: bool) -> Result<Cow<'_, OsStr>, BackupError> {
+    let not_a_backup = || BackupError::NotABackup(source.to_path_buf());
+
+    let name = source.file_name().ok_or_else(not_a_backup)?;
+    let untimestamped = || {
+        NameFormat::without_timestamp(&format!(".{BACKUP_EXTENSION}"))
+            .ok()?
+            .parse(name)
+    };
+
+    match BackupName::parse(name).or_else(untimestamped) {
+        Some(parsed) => {
+            let recorded = meta::read(source)
+                .ok()
+                .flatten()
+                .and_then(|meta| Some(meta.source.file_name()?.to_os_string()))
+                .filter(|recorded| naming::names_match(recorded, &parsed.original));
+            Ok(recorded.map_or(parsed.original, Cow::Owned))
+        }
+        None if strict => Err(not_a_backup()),
+        None => {
+            writer::log(
+                Level::Warning,
+                format_args!(
+                    "'{}': Not a backup name, restoring it under the same name",
+                    source.display()
+                ),
+            );
+            Ok(Cow::Borrowed(name))
+        }
+    }
+}
+
+/// Removes the file, link or directory tree at `path`, or the parts of the
+/// archive split there.
+///
+/// Links to directories are removed as directories on Windows.
+pub(crate) fn remove(path: &Path) -> Result<(), BackupError> {
+    if split::is_split(path) {
+        return split::remove(path);
+    }
+    let is_dir = fs::symlink_metadata(path).is_ok_and(|metadata| metadata.is_dir());
+    let result = if is_dir {
+        fs::remove_dir_all(path)
+    } else if cfg!(windows) {
+        fs::remove_file(path).or_else(|error| fs::remove_dir(path).map_err(|_| error))
+    } else {
+        fs::remove_file(path)
+    };
+
+    result.map_err(|source| BackupError::RemoveFailed {
+        path: path.to_path_buf(),
+        source,
+    })
+}
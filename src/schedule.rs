@@ -0,0 +1,212 @@
+//! Backups repeated on a schedule, for machines without cron.
+//!
+//! A schedule is due every interval, starting at once, or at a time of day
+//! and every interval from it. Due times lie on a fixed grid rather than
+//! being counted from the end of the previous run, so runs do not drift.
+//! Waiting compares the wall clock with the next due time every second, so a
+//! run missed while the machine was asleep happens as soon as it wakes up,
+//! once however many were missed.
+//!
+//! After each run a status file can be written for health checks, see
+//! [`RunStatus`].
+//!
+//! # Examples
+//!
+//! ```
+//! use std::time::Duration;
+//!
+//! use backup::schedule::{self, Schedule};
+//! use chrono::{Local, TimeZone, Timelike};
+//!
+//! let schedule = Schedule::new(None, Some(schedule::parse_time("03:30").unwrap()))?;
+//! let now = Local.with_ymd_and_hms(2024, 5, 1, 12, 0, 0).unwrap();
+//! let first = schedule.first(now);
+//! assert_eq!((first.hour(), first.minute()), (3, 30));
+//! assert_eq!(first.date_naive(), now.date_naive().succ_opt().unwrap());
+//! # Ok::<(), backup::BackupError>(())
+//! ```
+
+use std::fs;
+use std::path::Path;
+use std::thread;
+use std::time::Duration;
+
+use chrono::{DateTime, Local, NaiveDateTime, NaiveTime, TimeDelta, TimeZone};
+use serde::Serialize;
+
+use crate::backup;
+use crate::error::BackupError;
+use crate::interrupt;
+
+/// Interval of schedules given a time of day but no interval.
+pub const DAY: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// Longest time slept before looking at the wall clock again.
+const CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+/// When scheduled runs are due.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Schedule {
+    every: Duration,
+    at: Option<NaiveTime>,
+}
+
+impl Schedule {
+    /// Creates a schedule due every `every`, or every day if only `at` is
+    /// given, starting at once or at the next time of day `at`.
+    pub fn new(every: Option<Duration>, at: Option<NaiveTime>) -> Result<Schedule, BackupError> {
+        let every = every.unwrap_or(DAY);
+        if every.is_zero() {
+            return Err(BackupError::InvalidOption(
+                "The interval of --every cannot be zero".to_owned(),
+            ));
+        }
+        Ok(Schedule { every, at })
+    }
+
+    /// Returns the first due time at or after `now`.
+    pub fn first(&self, now: DateTime<Local>) -> DateTime<Local> {
+        let Some(at) = self.at else {
+            return now;
+        };
+        let today = now.date_naive().and_time(at);
+        match local(today) >= now {
+            true => local(today),
+            false => local(today + TimeDelta::days(1)),
+        }
+    }
+
+    /// Returns the first due time after both `due` and `now`, on the grid of
+    /// `due`.
+    ///
+    /// With a time of day the grid follows local time, so that a daily run
+    /// stays at the same time across daylight saving changes.
+    pub fn next(&self, due: DateTime<Local>, now: DateTime<Local>) -> DateTime<Local> {
+        let every = TimeDelta::from_std(self.every).unwrap_or(TimeDelta::MAX);
+        let steps = |elapsed: TimeDelta| {
+            let missed = elapsed.num_milliseconds().max(0) / every.num_milliseconds().max(1);
+            i32::try_from(missed + 1).unwrap_or(i32::MAX)
+        };
+        match self.at {
+            Some(_) => {
+                let (due, now) = (due.naive_local(), now.naive_local());
+                local(due + every * steps(now - due))
+            }
+            None => due + every * steps(now - due),
+        }
+    }
+}
+
+/// Returns the local time `naive`, moved past the gap if skipped by a
+/// daylight saving change, or the earlier of two if repeated.
+fn local(naive: NaiveDateTime) -> DateTime<Local> {
+    Local
+        .from_local_datetime(&naive)
+        .earliest()
+        .or_else(|| {
+            Local
+                .from_local_datetime(&(naive + TimeDelta::hours(1)))
+                .earliest()
+        })
+        .unwrap_or_else(|| Local.from_utc_datetime(&naive))
+}
+
+/// Parses a time of day written `HH:MM` or `HH:MM:SS`.
+pub fn parse_time(text: &str) -> Result<NaiveTime, String> {
+    NaiveTime::parse_from_str(text, "%H:%M")
+        .or_else(|_| NaiveTime::parse_from_str(text, "%H:%M:%S"))
+        .map_err(|_| format!("Invalid time '{text}', expected e.g. 03:30"))
+}
+
+/// Sleeps until the wall clock reaches `when`, returning `false` if
+/// interrupted first, see [`crate::interrupt`].
+pub fn sleep_until(when: DateTime<Local>) -> bool {
+    loop {
+        if interrupt::is_interrupted() {
+            return false;
+        }
+        let Ok(left) = (when - Local::now()).to_std() else {
+            return true;
+        };
+        if left.is_zero() {
+            return true;
+        }
+        thread::sleep(left.min(CHECK_INTERVAL));
+    }
+}
+
+/// Outcome of the last scheduled run, written as JSON to a status file so
+/// that health checks can watch it.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct RunStatus {
+    /// Exit status the run would have had on its own.
+    pub status: i32,
+    pub started: DateTime<Local>,
+    pub finished: DateTime<Local>,
+    /// When the next run is due.
+    pub next: DateTime<Local>,
+    /// Message and code of the error that failed the run, if any.
+    pub error: Option<String>,
+    pub code: Option<&'static str>,
+}
+
+impl RunStatus {
+    /// Writes the status to `path`, through a temporary file renamed over it
+    /// so that readers never see it half written.
+    pub fn write(&self, path: &Path) -> Result<(), BackupError> {
+        let partial = backup::partial_path(path);
+        let mut json = serde_json::to_vec_pretty(self).expect("statuses serialize");
+        json.push(b'\n');
+        fs::write(&partial, json)
+            .and_then(|()| fs::rename(&partial, path))
+            .map_err(|source| {
+                let _ = fs::remove_file(&partial);
+                BackupError::CreateFailed {
+                    path: path.to_path_buf(),
+                    source,
+                }
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(hour: u32, minute: u32) -> DateTime<Local> {
+        Local
+            .with_ymd_and_hms(2024, 1, 10, hour, minute, 0)
+            .unwrap()
+    }
+
+    #[test]
+    fn intervals_start_at_once_and_stay_on_their_grid() {
+        let schedule = Schedule::new(Some(Duration::from_secs(3600)), None).unwrap();
+        let start = at(10, 0);
+        assert_eq!(schedule.first(start), start);
+        assert_eq!(schedule.next(start, at(10, 20)), at(11, 0));
+        // A run missed while asleep is not repeated.
+        assert_eq!(schedule.next(start, at(13, 30)), at(14, 0));
+        assert_eq!(schedule.next(at(11, 0), at(10, 59)), at(12, 0));
+    }
+
+    #[test]
+    fn times_of_day_are_due_daily_by_default() {
+        let schedule = Schedule::new(None, Some(parse_time("03:30").unwrap())).unwrap();
+        assert_eq!(schedule.first(at(1, 0)), at(3, 30));
+        let first = schedule.first(at(4, 0));
+        assert_eq!(first, at(3, 30) + TimeDelta::days(1));
+        assert_eq!(schedule.next(first, first), first + TimeDelta::days(1));
+
+        let twice = Schedule::new(Some(12 * DAY / 24), Some(parse_time("03:30").unwrap()));
+        assert_eq!(twice.unwrap().next(at(3, 30), at(9, 0)), at(15, 30));
+    }
+
+    #[test]
+    fn invalid_schedules_are_refused() {
+        assert!(parse_time("3h").is_err());
+        assert!(parse_time("25:00").is_err());
+        assert_eq!(parse_time("03:30:15").unwrap().to_string(), "03:30:15");
+        assert!(Schedule::new(Some(Duration::ZERO), None).is_err());
+    }
+}
@@ -0,0 +1,422 @@
+//! Detached Ed25519 signatures of backups.
+//!
+//! A backup is signed by signing its checksum manifest, which records the
+//! digest of every file of the backup, so that modifying the backup, its
+//! manifest or the signature is detected. The signature is written next to
+//! the backup, in a file named after it with a `.sig` extension, e.g.
+//! `hosts.2024-05-01_10-00-00.backup.sig`, made of `key: value` lines:
+//!
+//! ```text
+//! backup-signature: 1
+//! algorithm: ed25519
+//! key: ed25519:<public key>
+//! manifest: hosts.2024-05-01_10-00-00.backup.sha256
+//! signature: <signature>
+//! ```
+//!
+//! The signature is the Ed25519 signature (RFC 8032) of the exact bytes of
+//! the manifest named, which lies next to the backup. Keys and signatures
+//! are encoded in standard base64 without padding. Lines may come in any
+//! order, and lines with other keys are ignored; a format incompatible with
+//! this one would change the version on the first line.
+//!
+//! Secret keys are stored one per file as `ed25519-secret:<seed>`, the 32
+//! bytes seed of RFC 8032, and public keys as `ed25519:<public key>`; lines
+//! starting with `#` are comments. Secret keys are never printed: their
+//! `Debug` output only shows the public key.
+
+use std::ffi::OsString;
+use std::fmt;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use crate::crypto;
+use crate::error::BackupError;
+use crate::list;
+use crate::split;
+use crate::verify;
+
+/// Extension appended to a backup path to name its signature file.
+pub const EXTENSION: &str = "sig";
+
+/// Version of the signature format, on the first line of signature files.
+const VERSION: &str = "1";
+
+/// Prefix of encoded public keys.
+const PUBLIC_PREFIX: &str = "ed25519:";
+
+/// Prefix of encoded secret keys.
+const SECRET_PREFIX: &str = "ed25519-secret:";
+
+/// An Ed25519 secret key that backups are signed with.
+#[derive(Clone)]
+pub struct SigningKey([u8; 32]);
+
+/// An Ed25519 public key that signatures are checked against.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct VerifyingKey([u8; 32]);
+
+impl SigningKey {
+    /// Generates a new random key.
+    pub fn generate() -> io::Result<Self> {
+        let mut seed = [0; 32];
+        crypto::random(&mut seed)?;
+        Ok(SigningKey(seed))
+    }
+
+    /// Parses a secret key such as `ed25519-secret:...`, returning `None` if
+    /// it is not one.
+    pub fn parse(text: &str) -> Option<Self> {
+        decode(text.strip_prefix(SECRET_PREFIX)?).map(SigningKey)
+    }
+
+    /// Reads the secret key of the file at `path`, as written by
+    /// [`SigningKey::write`].
+    pub fn load(path: &Path) -> Result<Self, BackupError> {
+        let (line, text) = key_line(path)?;
+        SigningKey::parse(&text).ok_or_else(|| BackupError::Config {
+            path: path.to_path_buf(),
+            line,
+            message: "Expected an ed25519 secret key, ed25519-secret:...".to_owned(),
+        })
+    }
+
+    /// Writes the key to a new file at `path`, readable by its owner only,
+    /// and the public key to `path` with a `.pub` extension appended.
+    ///
+    /// Existing files are replaced if `force` is set, and refused otherwise.
+    pub fn write(&self, path: &Path, force: bool) -> Result<(), BackupError> {
+        let public = public_path(path);
+        for path in [path, &public] {
+            if fs::symlink_metadata(path).is_ok() {
+                if !force {
+                    return Err(BackupError::AlreadyExists(path.to_path_buf()));
+                }
+                fs::remove_file(path).map_err(|source| BackupError::RemoveFailed {
+                    path: path.to_path_buf(),
+                    source,
+                })?;
+            }
+        }
+
+        let verifying_key = self.verifying_key();
+        let mut options = OpenOptions::new();
+        options.write(true).create_new(true);
+        #[cfg(unix)]
+        std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+        let secret = format!(
+            "# public key: {verifying_key}\n{SECRET_PREFIX}{}\n",
+            crypto::base64_encode(&self.0)
+        );
+        options
+            .open(path)
+            .and_then(|mut file| file.write_all(secret.as_bytes()))
+            .map_err(|source| BackupError::CreateFailed {
+                path: path.to_path_buf(),
+                source,
+            })?;
+        fs::write(&public, format!("{verifying_key}\n")).map_err(|source| {
+            BackupError::CreateFailed {
+                path: public,
+                source,
+            }
+        })
+    }
+
+    /// Returns the public key that signatures made with this key are checked
+    /// against.
+    pub fn verifying_key(&self) -> VerifyingKey {
+        VerifyingKey(crypto::ed25519_public(&self.0))
+    }
+}
+
+impl fmt::Debug for SigningKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("SigningKey")
+            .field(&self.verifying_key())
+            .finish()
+    }
+}
+
+impl VerifyingKey {
+    /// Parses the public key `text`, such as `ed25519:...`, or reads it from
+    /// the file at that path, as written by [`SigningKey::write`].
+    pub fn load(text: &str) -> Result<Self, BackupError> {
+        if let Ok(key) = text.parse() {
+            return Ok(key);
+        }
+        let path = Path::new(text);
+        if !text.starts_with(PUBLIC_PREFIX) && path.is_file() {
+            let (line, text) = key_line(path)?;
+            return text.parse().map_err(|_| BackupError::Config {
+                path: path.to_path_buf(),
+                line,
+                message: "Expected an ed25519 public key, ed25519:...".to_owned(),
+            });
+        }
+        text.parse()
+    }
+}
+
+impl FromStr for VerifyingKey {
+    type Err = BackupError;
+
+    /// Parses a public key such as `ed25519:...`.
+    fn from_str(text: &str) -> Result<Self, BackupError> {
+        text.strip_prefix(PUBLIC_PREFIX)
+            .and_then(decode)
+            .map(VerifyingKey)
+            .ok_or_else(|| {
+                BackupError::InvalidOption(format!(
+                    "Invalid public key '{text}', expected ed25519:... or the path of a .pub file"
+                ))
+            })
+    }
+}
+
+impl fmt::Display for VerifyingKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{PUBLIC_PREFIX}{}", crypto::base64_encode(&self.0))
+    }
+}
+
+impl fmt::Debug for VerifyingKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "VerifyingKey({self})")
+    }
+}
+
+/// Decodes the base64 of a 32 bytes key.
+fn decode(text: &str) -> Option<[u8; 32]> {
+    crypto::base64_decode(text)?.try_into().ok()
+}
+
+/// Reads the first line of the file at `path` that is neither blank nor a
+/// comment, with its number.
+fn key_line(path: &Path) -> Result<(usize, String), BackupError> {
+    let text = fs::read_to_string(path).map_err(|source| BackupError::ReadFailed {
+        path: path.to_path_buf(),
+        source,
+    })?;
+    text.lines()
+        .enumerate()
+        .map(|(i, line)| (i + 1, line.trim()))
+        .find(|(_, line)| !line.is_empty() && !line.starts_with('#'))
+        .map(|(line, text)| (line, text.to_owned()))
+        .ok_or_else(|| BackupError::Config {
+            path: path.to_path_buf(),
+            line: 1,
+            message: "Expected an ed25519 key".to_owned(),
+        })
+}
+
+/// Returns the path of the public key written next to the secret key at
+/// `path`.
+pub fn public_path(path: &Path) -> PathBuf {
+    let mut name = OsString::from(path.as_os_str());
+    name.push(".pub");
+    PathBuf::from(name)
+}
+
+/// Returns the path of the signature file of `backup`.
+pub fn path(backup: &Path) -> PathBuf {
+    let mut name = OsString::from(backup.as_os_str());
+    name.push(".");
+    name.push(EXTENSION);
+    PathBuf::from(name)
+}
+
+/// Returns the contents of the signature file of the manifest named `name`
+/// holding `contents`.
+pub(crate) fn signature(name: &str, contents: &[u8], key: &SigningKey) -> String {
+    let signature = crypto::ed25519_sign(&key.0, contents);
+    format!(
+        "backup-signature: {VERSION}\nalgorithm: ed25519\nkey: {}\nmanifest: {name}\nsignature: {}\n",
+        key.verifying_key(),
+        crypto::base64_encode(&signature)
+    )
+}
+
+/// Signs the checksum manifest at `manifest` of `backup` with `key`, writing
+/// the signature next to the backup through a temporary file renamed into
+/// place, and returns its path.
+pub(crate) fn sign(
+    backup: &Path,
+    manifest: &Path,
+    key: &SigningKey,
+) -> Result<PathBuf, BackupError> {
+    let contents = fs::read(manifest).map_err(|source| BackupError::ReadFailed {
+        path: manifest.to_path_buf(),
+        source,
+    })?;
+    let name = manifest.file_name().unwrap_or_default().to_string_lossy();
+    let signature = signature(&name, &contents, key);
+
+    let path = path(backup);
+    let mut partial = OsString::from(path.as_os_str());
+    partial.push(".partial");
+    let partial = PathBuf::from(partial);
+    let written = File::create(&partial)
+        .and_then(|mut file| {
+            file.write_all(signature.as_bytes())?;
+            file.sync_all()
+        })
+        .and_then(|()| fs::rename(&partial, &path));
+    match written {
+        Ok(()) => Ok(path),
+        Err(source) => {
+            let _ = fs::remove_file(&partial);
+            Err(BackupError::CreateFailed { path, source })
+        }
+    }
+}
+
+/// Checks that the backup at `backup` is signed by `key`, returning the path
+/// of the checksum manifest signed.
+///
+/// `backup` may be the link to the newest backup of a name, see
+/// [`list::resolve_latest`]. The manifest signed must be the one the backup
+/// is verified against, see [`verify::verify`]; the files of the backup are
+/// then checked against it by verifying or restoring the backup.
+///
+/// # Examples
+///
+/// ```no_run
+/// use std::path::Path;
+///
+/// use backup::sign::{self, VerifyingKey};
+///
+/// let key = VerifyingKey::load("backup.key.pub")?;
+/// sign::check(Path::new("hosts.2024-05-01_10-00-00.backup"), &key)?;
+/// # Ok::<(), backup::BackupError>(())
+/// ```
+pub fn check(backup: &Path, key: &VerifyingKey) -> Result<PathBuf, BackupError> {
+    let backup = &*split::archive_of(&list::resolve_latest(backup));
+    let path = path(backup);
+    let bad = |reason| BackupError::BadSignature {
+        path: backup.to_path_buf(),
+        reason,
+    };
+    let text = match fs::read_to_string(&path) {
+        Ok(text) => text,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {
+            return Err(BackupError::SignatureMissing(backup.to_path_buf()))
+        }
+        Err(source) => return Err(BackupError::ReadFailed { path, source }),
+    };
+    let field = |name: &str| {
+        text.lines()
+            .filter_map(|line| line.split_once(':'))
+            .find(|(key, _)| key.trim() == name)
+            .map(|(_, value)| value.trim())
+    };
+    if field("backup-signature") != Some(VERSION) || field("algorithm") != Some("ed25519") {
+        return Err(bad("the signature file is not in a supported format"));
+    }
+    let signed_by = field("key").and_then(|key| key.parse::<VerifyingKey>().ok());
+    let manifest = field("manifest")
+        .filter(|name| !name.is_empty() && Path::new(name).file_name() == Some(name.as_ref()));
+    let signature = field("signature")
+        .and_then(crypto::base64_decode)
+        .and_then(|signature| <[u8; 64]>::try_from(signature).ok());
+    let (Some(signed_by), Some(manifest), Some(signature)) = (signed_by, manifest, signature)
+    else {
+        return Err(bad("the signature file is malformed"));
+    };
+    if signed_by != *key {
+        return Err(bad("it was made with another key"));
+    }
+
+    let manifest = backup.with_file_name(manifest);
+    match verify::find_manifest(backup) {
+        Ok((found, _)) if found == manifest => {}
+        _ => return Err(bad("the manifest signed is not the one of the backup")),
+    }
+    let contents = fs::read(&manifest).map_err(|source| BackupError::ReadFailed {
+        path: manifest.clone(),
+        source,
+    })?;
+    match crypto::ed25519_verify(&key.0, &contents, &signature) {
+        true => Ok(manifest),
+        false => Err(bad("the checksum manifest was modified")),
+    }
+}
+
+/// Removes the signature file of `backup`, if any.
+pub(crate) fn remove(backup: &Path) -> Result<(), BackupError> {
+    let path = path(backup);
+    match fs::remove_file(&path) {
+        Err(source) if source.kind() != io::ErrorKind::NotFound => {
+            Err(BackupError::RemoveFailed { path, source })
+        }
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::checksum::{self, Algorithm};
+
+    #[test]
+    fn signatures_cover_the_manifest() {
+        let dir = tempfile::tempdir().unwrap();
+        let backup = dir.path().join("notes.2024-05-01_10-00-00.backup");
+        fs::write(&backup, "notes").unwrap();
+        let digest = checksum::hash_file(&backup, Algorithm::Sha256).unwrap();
+        let manifest =
+            checksum::write_manifest(&backup, Algorithm::Sha256, &[(PathBuf::new(), digest)])
+                .unwrap();
+        let key = SigningKey([7; 32]);
+        let signature = sign(&backup, &manifest, &key).unwrap();
+        assert_eq!(signature, path(&backup));
+        assert_eq!(check(&backup, &key.verifying_key()).unwrap(), manifest);
+
+        let other = SigningKey([8; 32]).verifying_key();
+        assert!(matches!(
+            check(&backup, &other),
+            Err(BackupError::BadSignature { .. })
+        ));
+        fs::write(&manifest, "0000  notes.2024-05-01_10-00-00.backup\n").unwrap();
+        assert!(matches!(
+            check(&backup, &key.verifying_key()),
+            Err(BackupError::BadSignature { .. })
+        ));
+        remove(&backup).unwrap();
+        assert!(matches!(
+            check(&backup, &key.verifying_key()),
+            Err(BackupError::SignatureMissing(_))
+        ));
+    }
+
+    #[test]
+    fn keys_round_trip_through_their_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("backup.key");
+        let key = SigningKey([7; 32]);
+        key.write(&path, false).unwrap();
+        assert!(matches!(
+            key.write(&path, false),
+            Err(BackupError::AlreadyExists(_))
+        ));
+        let loaded = SigningKey::load(&path).unwrap();
+        assert_eq!(loaded.verifying_key(), key.verifying_key());
+        let public = public_path(&path);
+        assert_eq!(
+            VerifyingKey::load(public.to_str().unwrap()).unwrap(),
+            key.verifying_key()
+        );
+        let text = key.verifying_key().to_string();
+        assert_eq!(VerifyingKey::load(&text).unwrap(), key.verifying_key());
+        assert!(!format!("{loaded:?}").contains(&crypto::base64_encode(&[7; 32])));
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = fs::metadata(&path).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+    }
+}
@@ -0,0 +1,409 @@
+//! Archives split into numbered parts of a fixed size.
+//!
+//! With [`BackupOptions::split`](crate::backup::BackupOptions::split), the
+//! archive of a backup is written as `<backup>.000`, `<backup>.001`, and so
+//! on, each at most the size given, e.g. to fit a filesystem limiting the
+//! size of files. The backup keeps its name without the number: its checksum
+//! manifest records the digest of the whole archive under that name, its
+//! metadata file the name, size and digest of every part, and restoring,
+//! verifying or listing it reads the parts back to back after checking that
+//! none is missing. Where a file that exists is expected, as in the path
+//! printed once it is written and the link to the newest backup, the backup
+//! is named by its first part, which every command takes for the whole
+//! archive.
+
+use std::ffi::{OsStr, OsString};
+use std::fs::{self, File, Metadata};
+use std::io::{self, BufWriter, IntoInnerError, Read, Write};
+use std::path::{Path, PathBuf};
+
+use crate::backup;
+use crate::checksum::{Algorithm, HashingWriter};
+use crate::error::BackupError;
+use crate::meta::{self, Part};
+
+/// Number of digits of part numbers, which grow longer past 1000 parts.
+const DIGITS: usize = 3;
+
+/// Returns the path of the part numbered `index`, from 0, of the archive
+/// `backup`.
+pub fn part_path(backup: &Path, index: usize) -> PathBuf {
+    let mut name = OsString::from(backup.as_os_str());
+    name.push(format!(".{index:0DIGITS$}"));
+    PathBuf::from(name)
+}
+
+/// Splits the file name of a part into that of its archive and its number,
+/// returning `None` if `name` does not end with a part number.
+pub fn part_name(name: &OsStr) -> Option<(&OsStr, usize)> {
+    let bytes = name.as_encoded_bytes();
+    let dot = bytes.iter().rposition(|&byte| byte == b'.')?;
+    let digits = &bytes[dot + 1..];
+    if dot == 0 || digits.len() < DIGITS || !digits.iter().all(u8::is_ascii_digit) {
+        return None;
+    }
+    let index = std::str::from_utf8(digits).ok()?.parse().ok()?;
+    // SAFETY: the name is cut right before an ASCII '.', a valid boundary of
+    // the encoded bytes of an `OsStr`.
+    let archive = unsafe { OsStr::from_encoded_bytes_unchecked(&bytes[..dot]) };
+    Some((archive, index))
+}
+
+/// Finds the parts of the archive `backup` next to it, by number.
+pub fn parts(backup: &Path) -> Vec<(usize, PathBuf)> {
+    let (Some(dir), Some(name)) = (backup.parent(), backup.file_name()) else {
+        return Vec::new();
+    };
+    let dir = match dir.as_os_str().is_empty() {
+        true => Path::new("."),
+        false => dir,
+    };
+    let Ok(entries) = fs::read_dir(dir) else {
+        return Vec::new();
+    };
+    let mut parts: Vec<_> = entries
+        .filter_map(|entry| {
+            let file_name = entry.ok()?.file_name();
+            let (archive, index) = part_name(&file_name)?;
+            (archive == name).then(|| (index, backup.with_file_name(&file_name)))
+        })
+        .collect();
+    parts.sort();
+    parts
+}
+
+/// Checks whether `backup` is an archive split into parts: it does not
+/// exist itself, but parts of it do.
+pub fn is_split(backup: &Path) -> bool {
+    fs::symlink_metadata(backup).is_err() && !parts(backup).is_empty()
+}
+
+/// Returns the path of the first part of `backup` if it is split, or
+/// `backup` itself: the file naming the backup where one that exists is
+/// expected, as on stdout and by the link to the newest backup.
+pub fn first_part(backup: &Path) -> PathBuf {
+    match is_split(backup) {
+        true => part_path(backup, 0),
+        false => backup.to_path_buf(),
+    }
+}
+
+/// Returns the archive that the part at `path` belongs to, or `path` itself
+/// if it is not a part of a split archive.
+pub fn archive_of(path: &Path) -> PathBuf {
+    path.file_name()
+        .and_then(part_name)
+        .map(|(archive, _)| path.with_file_name(archive))
+        .filter(|archive| is_split(archive))
+        .unwrap_or_else(|| path.to_path_buf())
+}
+
+/// Returns the parts of `backup` in order if it is split, or `None` if it is
+/// not, failing with [`BackupError::MissingParts`] if any is missing.
+///
+/// The parts expected are those recorded in the metadata file of the
+/// backup, or else those numbered up to the last one found.
+pub fn check(backup: &Path) -> Result<Option<Vec<PathBuf>>, BackupError> {
+    if fs::symlink_metadata(backup).is_ok() {
+        return Ok(None);
+    }
+    let found = parts(backup);
+    let Some((last, _)) = found.last() else {
+        return Ok(None);
+    };
+    let expected = meta::read(backup)
+        .ok()
+        .flatten()
+        .map(|meta| meta.parts.len())
+        .filter(|&count| count > 0)
+        .unwrap_or(last + 1);
+    let missing: Vec<PathBuf> = (0..expected)
+        .filter(|index| !found.iter().any(|(found, _)| found == index))
+        .map(|index| part_path(backup, index))
+        .collect();
+    match missing.is_empty() {
+        true => Ok(Some(
+            found
+                .into_iter()
+                .filter(|(index, _)| *index < expected)
+                .map(|(_, path)| path)
+                .collect(),
+        )),
+        false => Err(BackupError::MissingParts {
+            path: backup.to_path_buf(),
+            parts: missing,
+        }),
+    }
+}
+
+/// Returns the metadata of `path`, or of its first part if it is a split
+/// archive.
+pub(crate) fn metadata(path: &Path) -> io::Result<Metadata> {
+    fs::symlink_metadata(path).or_else(|error| match parts(path).first() {
+        Some((0, part)) => fs::symlink_metadata(part),
+        _ => Err(error),
+    })
+}
+
+/// Returns the total size of the parts of the split archive `backup`.
+pub(crate) fn size(backup: &Path) -> u64 {
+    parts(backup)
+        .iter()
+        .filter_map(|(_, part)| fs::metadata(part).ok())
+        .map(|metadata| metadata.len())
+        .sum()
+}
+
+/// Opens the file at `path` for reading, or the parts of the split archive
+/// it names back to back.
+pub(crate) fn open(path: &Path) -> io::Result<Box<dyn Read>> {
+    let parts = check(path).map_err(|error| match error {
+        BackupError::MissingParts { parts, .. } => {
+            let names: Vec<_> = parts
+                .iter()
+                .map(|part| part.file_name().unwrap_or_default().to_string_lossy())
+                .collect();
+            io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("missing parts {}", names.join(", ")),
+            )
+        }
+        error => io::Error::other(error.to_string()),
+    })?;
+    match parts {
+        None => Ok(Box::new(File::open(path)?)),
+        Some(parts) => Ok(Box::new(Parts {
+            parts: parts.into_iter(),
+            current: None,
+        })),
+    }
+}
+
+/// Removes the parts of the split archive `backup`.
+pub(crate) fn remove(backup: &Path) -> Result<(), BackupError> {
+    for (_, part) in parts(backup) {
+        fs::remove_file(&part)
+            .map_err(|source| BackupError::RemoveFailed { path: part, source })?;
+    }
+    Ok(())
+}
+
+/// Reader of the parts of a split archive, back to back.
+struct Parts {
+    parts: std::vec::IntoIter<PathBuf>,
+    current: Option<File>,
+}
+
+impl Read for Parts {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            let file = match &mut self.current {
+                Some(file) => file,
+                None => match self.parts.next() {
+                    Some(part) => self.current.insert(File::open(part)?),
+                    None => return Ok(0),
+                },
+            };
+            match file.read(buf)? {
+                0 if !buf.is_empty() => self.current = None,
+                read => return Ok(read),
+            }
+        }
+    }
+}
+
+/// Writer of an archive into parts of at most a given size, each written to
+/// a temporary file then renamed into place by [`SplitWriter::finish`].
+/// Parts written are removed if it is dropped unfinished.
+pub(crate) struct SplitWriter {
+    backup: PathBuf,
+    size: u64,
+    algorithm: Option<Algorithm>,
+    buffer_size: usize,
+    sync: bool,
+    /// The part being written, with the number of bytes written to it.
+    current: Option<(HashingWriter<BufWriter<File>>, u64)>,
+    /// The parts written, by temporary path.
+    written: Vec<(PathBuf, Part)>,
+    finished: bool,
+}
+
+impl SplitWriter {
+    /// Starts writing the archive `backup` into parts of at most `size`
+    /// bytes, at least one, hashed with `algorithm` and flushed to disk
+    /// before being renamed if `sync` is set.
+    pub(crate) fn new(
+        backup: &Path,
+        size: u64,
+        algorithm: Option<Algorithm>,
+        buffer_size: usize,
+        sync: bool,
+    ) -> Self {
+        SplitWriter {
+            backup: backup.to_path_buf(),
+            size: size.max(1),
+            algorithm,
+            buffer_size,
+            sync,
+            current: None,
+            written: Vec::new(),
+            finished: false,
+        }
+    }
+
+    /// Closes the part being written, if any.
+    fn close(&mut self) -> io::Result<()> {
+        let Some((writer, size)) = self.current.take() else {
+            return Ok(());
+        };
+        let (file, digest) = writer.finish();
+        let file = file.into_inner().map_err(IntoInnerError::into_error)?;
+        if self.sync {
+            file.sync_all()?;
+        }
+        let index = self.written.len();
+        let path = part_path(&self.backup, index);
+        let name = path.file_name().unwrap_or_default().to_string_lossy();
+        let part = Part {
+            name: name.into_owned(),
+            size,
+            digest,
+        };
+        self.written.push((backup::partial_path(&path), part));
+        Ok(())
+    }
+
+    /// Closes the part being written and starts the next one.
+    fn next(&mut self) -> io::Result<()> {
+        self.close()?;
+        let partial = backup::partial_path(&part_path(&self.backup, self.written.len()));
+        let file = File::create(&partial)?;
+        self.current = Some((
+            HashingWriter::new(
+                BufWriter::with_capacity(self.buffer_size, file),
+                self.algorithm,
+            ),
+            0,
+        ));
+        Ok(())
+    }
+
+    /// Closes the last part and renames every part into place, replacing
+    /// those of an archive of the same name, and returns them.
+    pub(crate) fn finish(mut self) -> io::Result<Vec<Part>> {
+        if self.current.is_none() && self.written.is_empty() {
+            self.next()?;
+        }
+        self.close()?;
+        remove(&self.backup).map_err(|error| io::Error::other(error.to_string()))?;
+        for (index, (partial, _)) in self.written.iter().enumerate() {
+            fs::rename(partial, part_path(&self.backup, index))?;
+        }
+        self.finished = true;
+        Ok(self.written.drain(..).map(|(_, part)| part).collect())
+    }
+}
+
+impl Write for SplitWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        if self
+            .current
+            .as_ref()
+            .is_none_or(|(_, size)| *size >= self.size)
+        {
+            self.next()?;
+        }
+        let (writer, size) = self.current.as_mut().expect("a part was just started");
+        let room = usize::try_from(self.size - *size).unwrap_or(usize::MAX);
+        let written = writer.write(&buf[..buf.len().min(room)])?;
+        *size += written as u64;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match &mut self.current {
+            Some((writer, _)) => writer.flush(),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Drop for SplitWriter {
+    fn drop(&mut self) {
+        if self.finished {
+            return;
+        }
+        let current = self
+            .current
+            .is_some()
+            .then(|| backup::partial_path(&part_path(&self.backup, self.written.len())));
+        self.current = None;
+        for partial in self
+            .written
+            .iter()
+            .map(|(partial, _)| partial)
+            .chain(&current)
+        {
+            let _ = fs::remove_file(partial);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parts_are_written_in_order_and_read_back_to_back() {
+        let dir = tempfile::tempdir().unwrap();
+        let backup = dir.path().join("data.2024-05-01_10-00-00.backup.tar");
+        let data: Vec<u8> = (0..2500u32).map(|i| i as u8).collect();
+        let mut writer = SplitWriter::new(&backup, 1000, Some(Algorithm::Sha256), 64, false);
+        writer.write_all(&data).unwrap();
+        let parts = writer.finish().unwrap();
+        assert_eq!(
+            parts.iter().map(|part| part.size).collect::<Vec<_>>(),
+            [1000, 1000, 500]
+        );
+        assert_eq!(parts[2].name, "data.2024-05-01_10-00-00.backup.tar.002");
+        assert!(is_split(&backup));
+        assert_eq!(size(&backup), 2500);
+        assert_eq!(archive_of(&part_path(&backup, 1)), backup);
+
+        let mut read = Vec::new();
+        open(&backup).unwrap().read_to_end(&mut read).unwrap();
+        assert_eq!(read, data);
+
+        fs::remove_file(part_path(&backup, 1)).unwrap();
+        match check(&backup) {
+            Err(BackupError::MissingParts { parts, .. }) => {
+                assert_eq!(parts, [part_path(&backup, 1)])
+            }
+            other => panic!("{other:?}"),
+        }
+    }
+
+    #[test]
+    fn unfinished_parts_are_removed() {
+        let dir = tempfile::tempdir().unwrap();
+        let backup = dir.path().join("data.tar");
+        let mut writer = SplitWriter::new(&backup, 10, None, 64, false);
+        writer.write_all(&[0; 25]).unwrap();
+        drop(writer);
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 0);
+    }
+
+    #[test]
+    fn part_names_end_with_a_number() {
+        assert_eq!(
+            part_name(OsStr::new("a.backup.tar.012")),
+            Some((OsStr::new("a.backup.tar"), 12))
+        );
+        assert_eq!(part_name(OsStr::new("a.backup.tar.12")), None);
+        assert_eq!(part_name(OsStr::new("a.backup.tar")), None);
+        assert_eq!(part_name(OsStr::new(".000")), None);
+    }
+}
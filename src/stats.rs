@@ -0,0 +1,112 @@
+//! Statistics of a backup or restore, summarizing what it read and wrote.
+//!
+//! The counts are kept by the copy and archive code as it goes and gathered
+//! into a [`Stats`] once the operation completes, which reports such as
+//! [`BackupReport`](crate::backup::BackupReport) expose and the command line
+//! prints as a closing summary.
+//!
+//! # Examples
+//!
+//! ```
+//! use std::time::Duration;
+//!
+//! use backup::stats::Stats;
+//!
+//! let stats = Stats {
+//!     files: 3,
+//!     bytes_read: 4096,
+//!     bytes_written: 4096,
+//!     elapsed: Duration::from_secs(2),
+//!     ..Stats::default()
+//! };
+//! assert_eq!(stats.throughput(), 2048);
+//! ```
+
+use std::fmt;
+use std::time::Duration;
+
+use serde::{Serialize, Serializer};
+
+use crate::writer;
+
+/// Counts of a backup or restore.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct Stats {
+    /// Number of files processed, not counting directories.
+    pub files: u64,
+    /// Number of bytes read from the source.
+    pub bytes_read: u64,
+    /// Number of bytes written, which is the archive size for archives.
+    pub bytes_written: u64,
+    /// Whether what was written is compressed, giving the compression ratio
+    /// a meaning.
+    pub compressed: bool,
+    /// Number of entries left out, such as special files.
+    pub skipped: u64,
+    /// Number of entries left out by exclude patterns.
+    pub excluded: u64,
+    /// Number of entries that could not be processed.
+    pub failed: u64,
+    /// Time the operation took.
+    #[serde(rename = "elapsed_ms", serialize_with = "milliseconds")]
+    pub elapsed: Duration,
+}
+
+impl Stats {
+    /// Adds the counts of `other`, which ran after these, to these.
+    pub fn merge(&mut self, other: &Stats) {
+        self.files += other.files;
+        self.bytes_read += other.bytes_read;
+        self.bytes_written += other.bytes_written;
+        self.compressed |= other.compressed;
+        self.skipped += other.skipped;
+        self.excluded += other.excluded;
+        self.failed += other.failed;
+        self.elapsed += other.elapsed;
+    }
+
+    /// Number of bytes read for each byte written, for compressed output
+    /// that is not empty.
+    pub fn ratio(&self) -> Option<f64> {
+        match self.compressed && self.bytes_written > 0 {
+            true => Some(self.bytes_read as f64 / self.bytes_written as f64),
+            false => None,
+        }
+    }
+
+    /// Average number of bytes read per second.
+    pub fn throughput(&self) -> u64 {
+        match self.elapsed.as_secs_f64() {
+            seconds if seconds > 0.0 => (self.bytes_read as f64 / seconds) as u64,
+            _ => 0,
+        }
+    }
+}
+
+impl fmt::Display for Stats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} files processed, {} read, {} written",
+            self.files,
+            writer::human_bytes(self.bytes_read),
+            writer::human_bytes(self.bytes_written)
+        )?;
+        if let Some(ratio) = self.ratio() {
+            write!(f, " (compression ratio {ratio:.2})")?;
+        }
+        write!(
+            f,
+            ", {} skipped, {} excluded, {} failed in {} at {}/s",
+            self.skipped,
+            self.excluded,
+            self.failed,
+            writer::human_duration(self.elapsed),
+            writer::human_bytes(self.throughput())
+        )
+    }
+}
+
+fn milliseconds<S: Serializer>(duration: &Duration, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_u128(duration.as_millis())
+}
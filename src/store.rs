@@ -0,0 +1,79 @@
+//! Places backups are sent to and read from other than local directories:
+//! another machine over SSH, see [`crate::remote`], an S3 bucket, see
+//! [`crate::s3`], or a command, see [`crate::pipe`].
+
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+
+use crate::error::BackupError;
+
+/// Directory-like place a backup is written to along with its checksum
+/// manifest, metadata and signature files, each staged by an [`Upload`] and
+/// put into place together by [`Store::commit`].
+pub(crate) trait Store {
+    /// File written but not yet in place.
+    type Staged;
+    /// File being written.
+    type Upload: Upload<Staged = Self::Staged>;
+
+    /// Returns the location of the file named `name`, for reports and errors.
+    fn location(&self, name: &str) -> PathBuf;
+
+    /// Starts writing the file named `name`. An existing file is refused
+    /// with [`BackupError::AlreadyExists`], when the upload starts or
+    /// finishes, unless `force` is set.
+    fn create(&self, name: &str, force: bool) -> Result<Self::Upload, BackupError>;
+
+    /// Puts the `staged` files into place.
+    fn commit(&self, staged: &[Self::Staged]) -> Result<(), BackupError>;
+
+    /// Returns whether there is a file named `name`, so that a new backup is
+    /// named with the next sequence number instead. Only checked where that
+    /// is cheap, other stores refusing the file when it is written.
+    fn taken(&self, _name: &Path) -> bool {
+        false
+    }
+
+    /// Returns whether the checksum manifest, metadata and signature files are
+    /// written along with the backup.
+    fn sidecars(&self) -> bool {
+        true
+    }
+
+    /// Removes the `staged` files, as far as possible.
+    fn discard(&self, staged: &[Self::Staged]);
+
+    /// Writes `contents` to the file named `name`, overwriting it, and
+    /// returns the staged file.
+    fn put(&self, name: &str, contents: &[u8]) -> Result<Self::Staged, BackupError> {
+        let mut upload = self.create(name, true)?;
+        match upload.write_all(contents) {
+            Ok(()) => upload.finish(),
+            Err(source) => Err(upload.fail(BackupError::CreateFailed {
+                path: self.location(name),
+                source,
+            })),
+        }
+    }
+}
+
+/// File being written to a [`Store`].
+pub(crate) trait Upload: Write {
+    /// File written but not yet in place.
+    type Staged;
+
+    /// Waits for the whole file to be written, returning it to commit.
+    fn finish(self) -> Result<Self::Staged, BackupError>;
+
+    /// Stops the upload after `error`, removing what was written, and returns
+    /// the error to report: [`BackupError::AlreadyExists`] if the upload was
+    /// refused.
+    fn fail(self, error: BackupError) -> BackupError;
+}
+
+/// File being read from another machine or a bucket.
+pub(crate) trait Download: Read {
+    /// Waits for the transfer to end, failing if the file could not be read
+    /// whole. Nothing more is read afterwards.
+    fn finish(&mut self) -> Result<(), BackupError>;
+}
@@ -0,0 +1,218 @@
+//! Messages sent to the system log.
+//!
+//! Messages go to journald through its native socket when it runs, which
+//! keeps multi-line messages whole, and otherwise to the syslog daemon
+//! listening on `/dev/log`, or `/var/run/syslog` on macOS, one datagram per
+//! line. They are sent with the user facility under the [`IDENTIFIER`]
+//! `backup`, at the priority matching their [`Level`]: `err` for errors,
+//! `warning` for warnings, `info` for successes and progress, and `debug` for
+//! verbose messages.
+//!
+//! Sending a message never fails: a message the log does not take is lost.
+
+use std::fmt;
+use std::io;
+use std::path::Path;
+
+use crate::error::BackupError;
+use crate::writer::Level;
+
+/// Identifier the messages are logged under.
+pub const IDENTIFIER: &str = "backup";
+
+/// Native socket of journald.
+const JOURNAL_SOCKET: &str = "/run/systemd/journal/socket";
+
+/// Sockets of syslog daemons, tried in order when journald does not run.
+const SYSLOG_SOCKETS: [&str; 2] = ["/dev/log", "/var/run/syslog"];
+
+/// Facility of user-level messages.
+const FACILITY: u8 = 1;
+
+/// A connection to the system log.
+#[derive(Debug)]
+pub struct Syslog {
+    socket: Socket,
+    /// Whether the socket is that of journald, which takes structured
+    /// entries rather than syslog lines.
+    journal: bool,
+}
+
+impl Syslog {
+    /// Connects to journald, or else to the syslog daemon. Fails with
+    /// [`BackupError::SyslogFailed`] if neither can be reached.
+    pub fn connect() -> Result<Syslog, BackupError> {
+        let mut error = io::Error::from(io::ErrorKind::NotFound);
+        let sockets = [(JOURNAL_SOCKET, true)]
+            .into_iter()
+            .chain(SYSLOG_SOCKETS.map(|path| (path, false)));
+        for (path, journal) in sockets {
+            match Syslog::connect_to(Path::new(path), journal) {
+                Ok(syslog) => return Ok(syslog),
+                Err(failed) => error = failed,
+            }
+        }
+        Err(BackupError::SyslogFailed(error))
+    }
+
+    /// Connects to the socket at `path`, of journald if `journal` is set.
+    fn connect_to(path: &Path, journal: bool) -> io::Result<Syslog> {
+        Ok(Syslog {
+            socket: connect(path)?,
+            journal,
+        })
+    }
+
+    /// Checks whether messages go to journald rather than a syslog daemon.
+    pub fn is_journal(&self) -> bool {
+        self.journal
+    }
+
+    /// Logs `message` at the priority of `level`.
+    pub fn send(&self, level: Level, message: fmt::Arguments<'_>) {
+        let message = message.to_string();
+        let message = message.trim_end();
+        let priority = priority(level);
+        if self.journal {
+            let _ = send(&self.socket, &journal_entry(priority, message));
+            return;
+        }
+        for line in message.lines() {
+            let _ = send(&self.socket, syslog_line(priority, line).as_bytes());
+        }
+    }
+}
+
+/// Returns the syslog severity of messages at `level`.
+fn priority(level: Level) -> u8 {
+    match level {
+        Level::Error => 3,
+        Level::Warning => 4,
+        Level::Success | Level::Info => 6,
+        Level::Verbose => 7,
+    }
+}
+
+/// Returns the journald entry of `message` at `priority`, in the native
+/// protocol: a `FIELD=value` line per field, or the field name, the length of
+/// the value as a little endian `u64` and the value for values spanning
+/// several lines.
+fn journal_entry(priority: u8, message: &str) -> Vec<u8> {
+    let mut entry = Vec::new();
+    let fields = [
+        ("MESSAGE", message.to_owned()),
+        ("PRIORITY", priority.to_string()),
+        ("SYSLOG_IDENTIFIER", IDENTIFIER.to_owned()),
+        ("SYSLOG_FACILITY", FACILITY.to_string()),
+        ("SYSLOG_PID", std::process::id().to_string()),
+    ];
+    for (name, value) in fields {
+        entry.extend_from_slice(name.as_bytes());
+        if value.contains('\n') {
+            entry.push(b'\n');
+            entry.extend_from_slice(&(value.len() as u64).to_le_bytes());
+        } else {
+            entry.push(b'=');
+        }
+        entry.extend_from_slice(value.as_bytes());
+        entry.push(b'\n');
+    }
+    entry
+}
+
+/// Returns the syslog line of `message` at `priority`, leaving the time to
+/// the daemon as local daemons do.
+fn syslog_line(priority: u8, message: &str) -> String {
+    format!(
+        "<{}>{IDENTIFIER}[{}]: {message}",
+        FACILITY * 8 + priority,
+        std::process::id()
+    )
+}
+
+#[cfg(unix)]
+type Socket = std::os::unix::net::UnixDatagram;
+
+#[cfg(unix)]
+fn connect(path: &Path) -> io::Result<Socket> {
+    let socket = Socket::unbound()?;
+    socket.connect(path)?;
+    Ok(socket)
+}
+
+#[cfg(unix)]
+fn send(socket: &Socket, datagram: &[u8]) -> io::Result<()> {
+    socket.send(datagram).map(|_| ())
+}
+
+#[cfg(not(unix))]
+type Socket = ();
+
+#[cfg(not(unix))]
+fn connect(_path: &Path) -> io::Result<Socket> {
+    Err(io::ErrorKind::Unsupported.into())
+}
+
+#[cfg(not(unix))]
+fn send(_socket: &Socket, _datagram: &[u8]) -> io::Result<()> {
+    Ok(())
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use std::os::unix::net::UnixDatagram;
+
+    use super::*;
+
+    /// Returns the datagrams received on `socket` so far.
+    fn received(socket: &UnixDatagram) -> Vec<Vec<u8>> {
+        socket.set_nonblocking(true).unwrap();
+        let mut datagrams = Vec::new();
+        let mut buffer = [0; 4096];
+        while let Ok(length) = socket.recv(&mut buffer) {
+            datagrams.push(buffer[..length].to_vec());
+        }
+        datagrams
+    }
+
+    #[test]
+    fn syslog_gets_a_line_per_line() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("log");
+        let daemon = UnixDatagram::bind(&path).unwrap();
+        let syslog = Syslog::connect_to(&path, false).unwrap();
+        syslog.send(Level::Error, format_args!("failed\nbadly\n"));
+        syslog.send(Level::Success, format_args!("done"));
+
+        let pid = std::process::id();
+        assert_eq!(
+            received(&daemon),
+            [
+                format!("<11>backup[{pid}]: failed"),
+                format!("<11>backup[{pid}]: badly"),
+                format!("<14>backup[{pid}]: done"),
+            ]
+            .map(String::into_bytes)
+        );
+    }
+
+    #[test]
+    fn journald_gets_whole_messages() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("socket");
+        let journal = UnixDatagram::bind(&path).unwrap();
+        let syslog = Syslog::connect_to(&path, true).unwrap();
+        assert!(syslog.is_journal());
+        syslog.send(Level::Warning, format_args!("one"));
+        syslog.send(Level::Info, format_args!("two\nlines"));
+
+        let entries = received(&journal);
+        let entry = String::from_utf8(entries[0].clone()).unwrap();
+        assert!(entry.starts_with("MESSAGE=one\nPRIORITY=4\n"), "{entry}");
+        assert!(entry.contains("SYSLOG_IDENTIFIER=backup\n"), "{entry}");
+        let mut multiline = b"MESSAGE\n".to_vec();
+        multiline.extend_from_slice(&9u64.to_le_bytes());
+        multiline.extend_from_slice(b"two\nlines\nPRIORITY=6\n");
+        assert!(entries[1].starts_with(&multiline));
+    }
+}
@@ -0,0 +1,298 @@
+//! systemd units running a profile of the configuration file on a schedule.
+//!
+//! A profile gets a `backup-<profile>.service` unit running the backup once,
+//! and a `backup-<profile>.timer` unit starting it on an `OnCalendar`
+//! schedule, written for the user's service manager into
+//! `$XDG_CONFIG_HOME/systemd/user` or `~/.config/systemd/user`. The timer is
+//! persistent, so a run missed while the machine was off happens once it is
+//! back on.
+//!
+//! The units are rendered from [`SERVICE_TEMPLATE`] and [`TIMER_TEMPLATE`].
+//! The profile is escaped into the unit names as `systemd-escape` would, and
+//! the command line into `ExecStart=` with the quoting of systemd, so that
+//! paths with spaces or `%` survive.
+//!
+//! # Examples
+//!
+//! ```
+//! use backup::systemd::Units;
+//!
+//! let command = ["/usr/bin/backup".to_owned(), "run".to_owned(), "my photos".to_owned()];
+//! let units = Units::render("my photos", &command, "daily")?;
+//! assert_eq!(units.timer_name(), r"backup-my\x20photos.timer");
+//! assert!(units.service.contains(r#"ExecStart=/usr/bin/backup run "my photos""#));
+//! assert!(units.timer.contains("OnCalendar=daily"));
+//! # Ok::<(), backup::BackupError>(())
+//! ```
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::backup;
+use crate::config;
+use crate::error::BackupError;
+
+/// Template of the service unit, where `{profile}` is replaced by the name
+/// of the profile and `{command}` by the command line running it.
+pub const SERVICE_TEMPLATE: &str = "\
+[Unit]
+Description=Back up the {profile} profile
+Wants=network-online.target
+After=network-online.target
+
+[Service]
+Type=oneshot
+ExecStart={command}
+# Backups skipped by --skip-unchanged exit with 3.
+SuccessExitStatus=3
+Nice=10
+IOSchedulingClass=idle
+";
+
+/// Template of the timer unit, where `{profile}` is replaced by the name of
+/// the profile and `{calendar}` by when it runs.
+pub const TIMER_TEMPLATE: &str = "\
+[Unit]
+Description=Back up the {profile} profile on schedule
+
+[Timer]
+OnCalendar={calendar}
+Persistent=true
+
+[Install]
+WantedBy=timers.target
+";
+
+/// Service and timer units of a profile.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Units {
+    /// Name of both units, without their extension.
+    pub name: String,
+    pub service: String,
+    pub timer: String,
+}
+
+impl Units {
+    /// Renders the units running `command`, the program followed by its
+    /// arguments, for `profile` at the `OnCalendar` expression `calendar`.
+    ///
+    /// Fails with [`BackupError::InvalidOption`] if a value holds a line
+    /// break, which would end the setting it is written in.
+    pub fn render(profile: &str, command: &[String], calendar: &str) -> Result<Units, BackupError> {
+        let values = [profile, calendar].into_iter();
+        if let Some(value) = values
+            .chain(command.iter().map(String::as_str))
+            .find(|value| value.contains(['\n', '\r']))
+        {
+            return Err(BackupError::InvalidOption(format!(
+                "Cannot write {value:?} in a systemd unit, it holds a line break"
+            )));
+        }
+        if calendar.trim().is_empty() {
+            return Err(BackupError::InvalidOption(
+                "--on-calendar cannot be empty".to_owned(),
+            ));
+        }
+
+        let command: Vec<_> = command.iter().map(|arg| quote(arg)).collect();
+        let name = unit_name(profile);
+        let profile = profile.replace('%', "%%");
+        Ok(Units {
+            name,
+            service: render(
+                SERVICE_TEMPLATE,
+                &[("profile", &profile), ("command", &command.join(" "))],
+            ),
+            timer: render(
+                TIMER_TEMPLATE,
+                &[
+                    ("profile", &profile),
+                    ("calendar", &calendar.replace('%', "%%")),
+                ],
+            ),
+        })
+    }
+
+    /// Returns the file name of the service unit.
+    pub fn service_name(&self) -> String {
+        format!("{}.service", self.name)
+    }
+
+    /// Returns the file name of the timer unit.
+    pub fn timer_name(&self) -> String {
+        format!("{}.timer", self.name)
+    }
+
+    /// Writes the units into `dir`, creating it if needed, and returns their
+    /// paths. Existing units are refused with [`BackupError::AlreadyExists`]
+    /// unless `force` is set, and nothing is written if `dry_run` is set.
+    pub fn install(
+        &self,
+        dir: &Path,
+        force: bool,
+        dry_run: bool,
+    ) -> Result<Vec<PathBuf>, BackupError> {
+        let files = [
+            (dir.join(self.service_name()), &self.service),
+            (dir.join(self.timer_name()), &self.timer),
+        ];
+        for (path, _) in &files {
+            backup::check_overwrite(path, force)?;
+        }
+        if dry_run {
+            return Ok(files.map(|(path, _)| path).to_vec());
+        }
+
+        fs::create_dir_all(dir).map_err(|source| BackupError::CreateFailed {
+            path: dir.to_path_buf(),
+            source,
+        })?;
+        let mut written = Vec::new();
+        for (path, contents) in files {
+            fs::write(&path, contents).map_err(|source| BackupError::CreateFailed {
+                path: path.clone(),
+                source,
+            })?;
+            written.push(path);
+        }
+        Ok(written)
+    }
+}
+
+/// Removes the units of `profile` from `dir` and returns their paths, or
+/// only returns them if `dry_run` is set. Fails with
+/// [`BackupError::NotFound`] if the profile has no unit there.
+pub fn uninstall(dir: &Path, profile: &str, dry_run: bool) -> Result<Vec<PathBuf>, BackupError> {
+    let name = unit_name(profile);
+    let units: Vec<_> = ["service", "timer"]
+        .into_iter()
+        .map(|extension| dir.join(format!("{name}.{extension}")))
+        .filter(|path| fs::symlink_metadata(path).is_ok())
+        .collect();
+    if units.is_empty() {
+        return Err(BackupError::NotFound(dir.join(format!("{name}.timer"))));
+    }
+    if dry_run {
+        return Ok(units);
+    }
+
+    for path in &units {
+        fs::remove_file(path).map_err(|source| BackupError::RemoveFailed {
+            path: path.clone(),
+            source,
+        })?;
+    }
+    Ok(units)
+}
+
+/// Returns the directory of the units of the user's service manager,
+/// `$XDG_CONFIG_HOME/systemd/user` or `~/.config/systemd/user`, or `None` if
+/// neither the variable nor the home directory is set.
+pub fn user_dir() -> Option<PathBuf> {
+    config::config_home().map(|dir| dir.join("systemd").join("user"))
+}
+
+/// Returns the name of the units of `profile`, escaped as `systemd-escape`
+/// does: bytes other than ASCII letters, digits, `:`, `_` and `.` are written
+/// `\xNN`, as is a leading `.`.
+fn unit_name(profile: &str) -> String {
+    let mut name = String::from("backup-");
+    for (i, byte) in profile.bytes().enumerate() {
+        match byte {
+            b'.' if i == 0 => name.push_str("\\x2e"),
+            b'a'..=b'z' | b'A'..=b'Z' | b'0'..=b'9' | b':' | b'_' | b'.' => {
+                name.push(char::from(byte));
+            }
+            _ => name.push_str(&format!("\\x{byte:02x}")),
+        }
+    }
+    name
+}
+
+/// Quotes `arg` as a single word of an `ExecStart=` command line, escaping
+/// the `%` specifiers and `$` variables systemd would otherwise expand.
+fn quote(arg: &str) -> String {
+    let arg = arg.replace('%', "%%").replace('$', "$$");
+    let plain = !arg.is_empty()
+        && !arg
+            .chars()
+            .any(|c| c.is_whitespace() || matches!(c, '"' | '\'' | '\\' | ';'));
+    if plain {
+        return arg;
+    }
+    let mut quoted = String::with_capacity(arg.len() + 2);
+    quoted.push('"');
+    for c in arg.chars() {
+        if matches!(c, '"' | '\\') {
+            quoted.push('\\');
+        }
+        quoted.push(c);
+    }
+    quoted.push('"');
+    quoted
+}
+
+/// Replaces each `{key}` of `template` with its value in `values`.
+fn render(template: &str, values: &[(&str, &str)]) -> String {
+    values
+        .iter()
+        .fold(template.to_owned(), |text, (key, value)| {
+            text.replace(&format!("{{{key}}}"), value)
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn command(args: &[&str]) -> Vec<String> {
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    #[test]
+    fn paths_with_spaces_are_quoted() {
+        let units = Units::render(
+            "photos",
+            &command(&[
+                "/opt/my tools/backup",
+                "run",
+                "photos",
+                "--config",
+                "/home/a b/c.toml",
+            ]),
+            "*-*-* 03:30:00",
+        )
+        .unwrap();
+        assert!(
+            units.service.contains(
+                r#"ExecStart="/opt/my tools/backup" run photos --config "/home/a b/c.toml""#
+            ),
+            "{}",
+            units.service
+        );
+        assert!(units.timer.contains("OnCalendar=*-*-* 03:30:00\n"));
+        assert_eq!(units.service_name(), "backup-photos.service");
+    }
+
+    #[test]
+    fn specifiers_quotes_and_names_are_escaped() {
+        assert_eq!(quote(r#"say "hi"\now"#), r#""say \"hi\"\\now""#);
+        assert_eq!(quote("100%$HOME"), "100%%$$HOME");
+        assert_eq!(quote(""), r#""""#);
+        assert_eq!(unit_name("a-b c/é"), r"backup-a\x2db\x20c\x2f\xc3\xa9");
+        assert_eq!(unit_name(".hidden"), r"backup-\x2ehidden");
+
+        let units = Units::render("50%", &command(&["backup"]), "daily").unwrap();
+        assert!(units
+            .service
+            .contains("Description=Back up the 50%% profile"));
+        assert_eq!(units.name, r"backup-50\x25");
+    }
+
+    #[test]
+    fn line_breaks_are_refused() {
+        let error = Units::render("a\nExecStartPre=rm", &command(&["backup"]), "daily");
+        assert!(matches!(error, Err(BackupError::InvalidOption(_))));
+        assert!(Units::render("a", &command(&["backup"]), " ").is_err());
+    }
+}
@@ -0,0 +1,282 @@
+//! Targets of a backup sent to several places at once, see
+//! [`crate::backup::backup_to_targets`].
+//!
+//! When the backup is an archive, it is written once and copied as it is
+//! written to every target: into a hidden `.<name>.partial` file in a local
+//! directory, renamed into place with its checksum manifest, metadata and
+//! signature files, or uploaded as it is to another machine or a bucket. A
+//! target failing is dropped without stopping the others.
+
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, IntoInnerError, Write};
+use std::path::{Path, PathBuf};
+
+use crate::backup::{self, Fsync};
+use crate::error::BackupError;
+use crate::remote::{self, Remote};
+use crate::s3::{self, Bucket};
+use crate::store::{self, Store};
+
+/// One of the places a backup is sent to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Target {
+    /// Directory on this machine.
+    Local(PathBuf),
+    /// Directory on another machine, reached over SSH.
+    Remote(Remote),
+    /// Prefix in an S3 bucket.
+    S3(Bucket),
+}
+
+impl Target {
+    /// Returns the path of the target, for reports and errors.
+    pub fn display_path(&self) -> PathBuf {
+        match self {
+            Target::Local(path) => path.clone(),
+            Target::Remote(remote) => remote.display_path(),
+            Target::S3(bucket) => bucket.display_path(),
+        }
+    }
+}
+
+impl fmt::Display for Target {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Target::Local(path) => write!(f, "{}", path.display()),
+            Target::Remote(remote) => remote.fmt(f),
+            Target::S3(bucket) => bucket.fmt(f),
+        }
+    }
+}
+
+/// A [`Target`] written as a [`Store`], flushing local files to disk as set
+/// by `fsync`.
+pub(crate) struct TargetStore<'a> {
+    pub(crate) target: &'a Target,
+    pub(crate) fsync: Fsync,
+}
+
+/// File written to a [`TargetStore`] but not yet in place.
+pub(crate) enum Staged {
+    /// The partial file written and the path it is renamed to.
+    Local(PathBuf, PathBuf),
+    Remote((Remote, Remote)),
+    S3(Bucket),
+}
+
+impl Store for TargetStore<'_> {
+    type Staged = Staged;
+    type Upload = Upload;
+
+    fn location(&self, name: &str) -> PathBuf {
+        match self.target {
+            Target::Local(dir) => dir.join(name),
+            Target::Remote(remote) => remote.location(name),
+            Target::S3(bucket) => bucket.location(name),
+        }
+    }
+
+    fn create(&self, name: &str, force: bool) -> Result<Upload, BackupError> {
+        match self.target {
+            Target::Local(dir) => {
+                LocalUpload::create(&dir.join(name), force, self.fsync).map(Upload::Local)
+            }
+            Target::Remote(remote) => remote.create(name, force).map(Upload::Remote),
+            Target::S3(bucket) => bucket.create(name, force).map(Upload::S3),
+        }
+    }
+
+    fn taken(&self, name: &Path) -> bool {
+        match self.target {
+            Target::Local(dir) => backup::exists(&dir.join(name)),
+            _ => false,
+        }
+    }
+
+    fn commit(&self, staged: &[Staged]) -> Result<(), BackupError> {
+        match self.target {
+            Target::Local(_) => {
+                for staged in staged {
+                    let Staged::Local(partial, path) = staged else {
+                        continue;
+                    };
+                    fs::rename(partial, path)
+                        .and_then(|()| backup::sync_parent(path, self.fsync))
+                        .map_err(|source| BackupError::CreateFailed {
+                            path: path.clone(),
+                            source,
+                        })?;
+                }
+                Ok(())
+            }
+            Target::Remote(remote) => remote.commit(&remote_staged(staged)),
+            Target::S3(bucket) => bucket.commit(&s3_staged(staged)),
+        }
+    }
+
+    fn discard(&self, staged: &[Staged]) {
+        match self.target {
+            Target::Local(_) => {
+                for staged in staged {
+                    if let Staged::Local(partial, _) = staged {
+                        let _ = fs::remove_file(partial);
+                    }
+                }
+            }
+            Target::Remote(remote) => remote.discard(&remote_staged(staged)),
+            Target::S3(bucket) => bucket.discard(&s3_staged(staged)),
+        }
+    }
+}
+
+/// Returns the files of `staged` written to another machine.
+fn remote_staged(staged: &[Staged]) -> Vec<(Remote, Remote)> {
+    staged
+        .iter()
+        .filter_map(|staged| match staged {
+            Staged::Remote(staged) => Some(staged.clone()),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Returns the objects of `staged` written to a bucket.
+fn s3_staged(staged: &[Staged]) -> Vec<Bucket> {
+    staged
+        .iter()
+        .filter_map(|staged| match staged {
+            Staged::S3(object) => Some(object.clone()),
+            _ => None,
+        })
+        .collect()
+}
+
+/// File being written to a [`TargetStore`].
+pub(crate) enum Upload {
+    Local(LocalUpload),
+    Remote(remote::Upload),
+    S3(s3::Upload),
+}
+
+impl store::Upload for Upload {
+    type Staged = Staged;
+
+    fn finish(self) -> Result<Staged, BackupError> {
+        match self {
+            Upload::Local(upload) => upload
+                .finish()
+                .map(|(partial, path)| Staged::Local(partial, path)),
+            Upload::Remote(upload) => upload.finish().map(Staged::Remote),
+            Upload::S3(upload) => upload.finish().map(Staged::S3),
+        }
+    }
+
+    fn fail(self, error: BackupError) -> BackupError {
+        match self {
+            Upload::Local(upload) => upload.fail(error),
+            Upload::Remote(upload) => upload.fail(error),
+            Upload::S3(upload) => upload.fail(error),
+        }
+    }
+}
+
+impl Write for Upload {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Upload::Local(upload) => upload.write(buf),
+            Upload::Remote(upload) => upload.write(buf),
+            Upload::S3(upload) => upload.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Upload::Local(upload) => upload.flush(),
+            Upload::Remote(upload) => upload.flush(),
+            Upload::S3(upload) => upload.flush(),
+        }
+    }
+}
+
+/// File being written into a local directory, through a partial file next
+/// to it that is removed unless the upload finishes.
+pub(crate) struct LocalUpload {
+    file: Option<BufWriter<File>>,
+    partial: PathBuf,
+    path: PathBuf,
+    fsync: Fsync,
+}
+
+impl LocalUpload {
+    /// Starts writing the file at `path`, creating its directory if needed.
+    /// An existing file is refused with [`BackupError::AlreadyExists`] unless
+    /// `force` is set.
+    fn create(path: &Path, force: bool, fsync: Fsync) -> Result<LocalUpload, BackupError> {
+        backup::check_overwrite(path, force)?;
+        let create_error = |source| BackupError::CreateFailed {
+            path: path.to_path_buf(),
+            source,
+        };
+        if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+            fs::create_dir_all(dir).map_err(create_error)?;
+        }
+        let partial = backup::partial_path(path);
+        let file = File::create(&partial).map_err(create_error)?;
+        Ok(LocalUpload {
+            file: Some(BufWriter::new(file)),
+            partial,
+            path: path.to_path_buf(),
+            fsync,
+        })
+    }
+
+    fn finish(mut self) -> Result<(PathBuf, PathBuf), BackupError> {
+        let written = self.file.take().map_or(Ok(()), |file| {
+            let file = file.into_inner().map_err(IntoInnerError::into_error)?;
+            match self.fsync {
+                Fsync::None => Ok(()),
+                _ => file.sync_all(),
+            }
+        });
+        match written {
+            Ok(()) => Ok((self.partial.clone(), self.path.clone())),
+            Err(source) => {
+                let _ = fs::remove_file(&self.partial);
+                Err(BackupError::CreateFailed {
+                    path: self.path.clone(),
+                    source,
+                })
+            }
+        }
+    }
+
+    fn fail(self, error: BackupError) -> BackupError {
+        drop(self);
+        error
+    }
+}
+
+impl Write for LocalUpload {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match &mut self.file {
+            Some(file) => file.write(buf),
+            None => Err(io::ErrorKind::BrokenPipe.into()),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match &mut self.file {
+            Some(file) => file.flush(),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Drop for LocalUpload {
+    fn drop(&mut self) {
+        if self.file.take().is_some() {
+            let _ = fs::remove_file(&self.partial);
+        }
+    }
+}
@@ -0,0 +1,126 @@
+//! Limiting of the rate at which backups write, set with `--bwlimit`.
+//!
+//! A [`Throttle`] is a token bucket refilled with `rate` bytes each second and
+//! shared by every copy of a run, including those made by concurrent jobs.
+//! Each write takes its size from the bucket, and the writer sleeps off what
+//! the bucket lacks, so that the writes of all of them together stay at the
+//! rate.
+//!
+//! The rate written over each [`REPORT`] is logged as a verbose message while
+//! the copies run, to show that the limit is respected.
+
+use std::io::{self, Write};
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::interrupt;
+use crate::writer::{self, Level};
+
+/// Longest sleep between two checks for an interruption.
+const NAP: Duration = Duration::from_millis(100);
+
+/// Interval over which the rate written is measured and logged.
+const REPORT: Duration = Duration::from_secs(1);
+
+/// A limit on the bytes written each second.
+#[derive(Debug)]
+pub(crate) struct Throttle {
+    rate: u64,
+    bucket: Mutex<Bucket>,
+}
+
+#[derive(Debug)]
+struct Bucket {
+    /// Bytes that may be written at once, negative once overdrawn.
+    tokens: f64,
+    updated: Instant,
+    /// Bytes written since the rate was last logged.
+    written: u64,
+    reported: Instant,
+}
+
+impl Throttle {
+    /// Limits writes to `rate` bytes per second, at least one.
+    pub(crate) fn new(rate: u64) -> Self {
+        Throttle {
+            rate: rate.max(1),
+            bucket: Mutex::new(Bucket {
+                tokens: 0.0,
+                updated: Instant::now(),
+                written: 0,
+                reported: Instant::now(),
+            }),
+        }
+    }
+
+    /// Bytes allowed per second.
+    pub(crate) fn rate(&self) -> u64 {
+        self.rate
+    }
+
+    /// Takes `bytes` from the bucket, sleeping until the rate allows them.
+    /// A burst is allowed after an idle period, but no more than one second
+    /// of writes.
+    fn consume(&self, bytes: usize) {
+        let rate = self.rate as f64;
+        let wait = {
+            let mut bucket = self.bucket.lock().unwrap_or_else(|e| e.into_inner());
+            let now = Instant::now();
+            let refill = now.duration_since(bucket.updated).as_secs_f64() * rate;
+            bucket.tokens = (bucket.tokens + refill).min(rate) - bytes as f64;
+            bucket.updated = now;
+            Duration::from_secs_f64((-bucket.tokens).max(0.0) / rate)
+        };
+
+        let deadline = Instant::now() + wait;
+        while !interrupt::is_interrupted() {
+            let left = deadline.saturating_duration_since(Instant::now());
+            if left.is_zero() {
+                break;
+            }
+            thread::sleep(left.min(NAP));
+        }
+        self.report(bytes);
+    }
+
+    /// Counts `bytes` as written now, once they were let through, and logs
+    /// the rate written once a [`REPORT`] has passed since it was last logged.
+    fn report(&self, bytes: usize) {
+        let mut bucket = self.bucket.lock().unwrap_or_else(|e| e.into_inner());
+        bucket.written += bytes as u64;
+        let elapsed = bucket.reported.elapsed();
+        if elapsed < REPORT {
+            return;
+        }
+        let written = bucket.written as f64 / elapsed.as_secs_f64();
+        bucket.written = 0;
+        bucket.reported = Instant::now();
+        drop(bucket);
+        writer::log(
+            Level::Verbose,
+            format_args!(
+                "writing at {}/s with --bwlimit {}/s",
+                writer::human_bytes(written as u64),
+                writer::human_bytes(self.rate)
+            ),
+        );
+    }
+}
+
+/// A writer whose writes are limited by a [`Throttle`], if any.
+pub(crate) struct Throttled<'a, T>(pub(crate) T, pub(crate) Option<&'a Throttle>);
+
+impl<W: Write> Write for Throttled<'_, W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.0.write(buf)?;
+        if let Some(throttle) = self.1 {
+            throttle.consume(written);
+        }
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.0.flush()
+    }
+}
@@ -0,0 +1,461 @@
+//! Moving of removed backups to a trash rather than deleting them.
+//!
+//! A trash follows the layout of the freedesktop.org trash specification, as
+//! `~/.local/share/Trash` does: entries are moved into its `files` directory,
+//! and an `info/<name>.trashinfo` file records their original path and when
+//! they were moved, so that file managers can put them back. A quarantine
+//! directory given instead is laid out the same way. Backups only leave the
+//! trash for good through [`Trash::empty`].
+
+use std::ffi::{OsStr, OsString};
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use chrono::{Local, NaiveDateTime};
+
+use crate::backup::{self, CopyOptions, Fsync, Reflink, SpecialFiles, DEFAULT_BUFFER_SIZE};
+use crate::checksum::Algorithm;
+use crate::config;
+use crate::error::BackupError;
+use crate::exclude::Excludes;
+use crate::list;
+use crate::meta::{self, META_EXTENSION};
+use crate::restore::{self, BackupName};
+use crate::sign;
+use crate::split;
+use crate::writer::{self, Level};
+
+/// Extension of the files recording where a trashed entry came from.
+pub const INFO_EXTENSION: &str = "trashinfo";
+
+/// Format of the deletion dates of the info files, in local time.
+const DATE_FORMAT: &str = "%Y-%m-%dT%H:%M:%S";
+
+/// A trash directory, see the [module documentation](self).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Trash {
+    dir: PathBuf,
+}
+
+/// Outcome of emptying a trash.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct EmptyReport {
+    /// Entries removed from the trash, or that would be removed by a dry run.
+    pub removed: Vec<PathBuf>,
+    /// Number of bytes freed, or that would be freed by a dry run.
+    pub freed: u64,
+}
+
+impl Trash {
+    /// Uses `dir` as a trash, such as a quarantine directory given with
+    /// `--trash-dir`. Its directories are created on first use.
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Trash { dir: dir.into() }
+    }
+
+    /// Returns the trash of the user, `$XDG_DATA_HOME/Trash` or
+    /// `~/.local/share/Trash`, or `None` if neither the variable nor the home
+    /// directory is set.
+    pub fn home() -> Option<Self> {
+        config::data_home().map(|data| Trash::new(data.join("Trash")))
+    }
+
+    /// Returns the directory of the trash.
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Moves the backup at `backup` into the trash along with its checksum
+    /// manifests, metadata and signature files, returning where the backup
+    /// went, or its first part if it is split.
+    pub fn put_backup(&self, backup: &Path) -> Result<PathBuf, BackupError> {
+        let trashed = match split::is_split(backup) {
+            true => {
+                let mut trashed = Vec::new();
+                for (_, part) in split::parts(backup) {
+                    trashed.push(self.put(&part)?);
+                }
+                trashed.remove(0)
+            }
+            false => self.put(backup)?,
+        };
+        let sidecars = Algorithm::ALL
+            .map(|algorithm| algorithm.manifest_path(backup))
+            .into_iter()
+            .chain([meta::path(backup), sign::path(backup)]);
+        for sidecar in sidecars {
+            if fs::symlink_metadata(&sidecar).is_ok() {
+                self.put(&sidecar)?;
+            }
+        }
+        Ok(trashed)
+    }
+
+    /// Moves the file, link or directory tree at `path` into the trash,
+    /// returning where it went.
+    ///
+    /// The info file is created first, which reserves the name in the trash;
+    /// a number is appended to names already taken. Entries on another
+    /// filesystem than the trash are copied then removed, with a warning.
+    pub fn put(&self, path: &Path) -> Result<PathBuf, BackupError> {
+        let files = self.dir.join("files");
+        let info = self.dir.join("info");
+        for dir in [&files, &info] {
+            fs::create_dir_all(dir).map_err(|source| BackupError::CreateFailed {
+                path: dir.clone(),
+                source,
+            })?;
+        }
+
+        let original = std::path::absolute(path).unwrap_or_else(|_| path.to_path_buf());
+        let name = path.file_name().unwrap_or(path.as_os_str());
+        let contents = format!(
+            "[Trash Info]\nPath={}\nDeletionDate={}\n",
+            encode(&original),
+            Local::now().format(DATE_FORMAT)
+        );
+        let mut n = 1;
+        let (trashed, info_path) = loop {
+            let mut name = name.to_os_string();
+            if n > 1 {
+                name.push(format!(".{n}"));
+            }
+            n += 1;
+            let (trashed, info_path) = (files.join(&name), info_file(&info, &name));
+            if fs::symlink_metadata(&trashed).is_ok() {
+                continue;
+            }
+            let file = OpenOptions::new()
+                .write(true)
+                .create_new(true)
+                .open(&info_path);
+            match file.and_then(|mut file| file.write_all(contents.as_bytes())) {
+                Ok(()) => break (trashed, info_path),
+                Err(e) if e.kind() == io::ErrorKind::AlreadyExists => continue,
+                Err(source) => {
+                    return Err(BackupError::CreateFailed {
+                        path: info_path,
+                        source,
+                    })
+                }
+            }
+        };
+
+        move_entry(path, &trashed).inspect_err(|_| {
+            let _ = fs::remove_file(&info_path);
+        })?;
+        Ok(trashed)
+    }
+
+    /// Removes for good the backups and their checksum manifests and
+    /// metadata files moved into the trash more than `older_than` ago, or
+    /// all of them. Other entries of the trash are left alone.
+    ///
+    /// With `dry_run` set, the entries that would be removed are printed but
+    /// left in place.
+    pub fn empty(
+        &self,
+        older_than: Option<Duration>,
+        dry_run: bool,
+    ) -> Result<EmptyReport, BackupError> {
+        let cutoff = older_than
+            .and_then(|age| chrono::Duration::from_std(age).ok())
+            .and_then(|age| Local::now().naive_local().checked_sub_signed(age))
+            .unwrap_or(NaiveDateTime::MAX);
+
+        let info = self.dir.join("info");
+        let entries = match fs::read_dir(&info) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(EmptyReport::default()),
+            Err(source) => return Err(BackupError::ReadFailed { path: info, source }),
+        };
+        let mut infos: Vec<_> = entries
+            .flatten()
+            .map(|entry| entry.path())
+            .filter(|path| path.extension() == Some(OsStr::new(INFO_EXTENSION)))
+            .collect();
+        infos.sort();
+
+        let mut report = EmptyReport::default();
+        for info_path in infos {
+            let Some((original, deleted)) = fs::read_to_string(&info_path)
+                .ok()
+                .and_then(|contents| parse_info(&contents))
+            else {
+                continue;
+            };
+            if deleted > cutoff || !original.file_name().is_some_and(is_backup_name) {
+                continue;
+            }
+
+            let trashed = self
+                .dir
+                .join("files")
+                .join(info_path.file_stem().unwrap_or_default());
+            let size = match fs::symlink_metadata(&trashed) {
+                Ok(metadata) if metadata.is_dir() => list::tree_size(&trashed),
+                Ok(metadata) => metadata.len(),
+                Err(_) => 0,
+            };
+            if dry_run {
+                writer::log(
+                    Level::Info,
+                    format_args!("would remove {}", trashed.display()),
+                );
+            } else {
+                if fs::symlink_metadata(&trashed).is_ok() {
+                    restore::remove(&trashed)?;
+                }
+                fs::remove_file(&info_path).map_err(|source| BackupError::RemoveFailed {
+                    path: info_path.clone(),
+                    source,
+                })?;
+                writer::log(
+                    Level::Success,
+                    format_args!("removed {}", trashed.display()),
+                );
+            }
+            report.removed.push(trashed);
+            report.freed += size;
+        }
+
+        let verb = if dry_run { "would free" } else { "freed" };
+        writer::log(
+            Level::Info,
+            format_args!("{verb} {}", writer::human_bytes(report.freed)),
+        );
+        Ok(report)
+    }
+}
+
+/// Returns the path of the info file of the entry named `name` in the trash.
+fn info_file(info: &Path, name: &OsStr) -> PathBuf {
+    let mut name = name.to_os_string();
+    name.push(".");
+    name.push(INFO_EXTENSION);
+    info.join(name)
+}
+
+/// Checks whether `name` is that of a backup, or of a checksum manifest,
+/// metadata or signature file of one.
+fn is_backup_name(name: &OsStr) -> bool {
+    let name = name.to_string_lossy();
+    let backup = Algorithm::ALL
+        .map(Algorithm::extension)
+        .into_iter()
+        .chain([META_EXTENSION, sign::EXTENSION])
+        .find_map(|extension| name.strip_suffix(extension)?.strip_suffix('.'))
+        .unwrap_or(&name);
+    let backup = OsStr::new(backup);
+    let backup = split::part_name(backup).map_or(backup, |(archive, _)| archive);
+    BackupName::parse(backup).is_some()
+}
+
+/// Moves the entry at `from` to `to`, copying it then removing it if they
+/// are on different filesystems.
+fn move_entry(from: &Path, to: &Path) -> Result<(), BackupError> {
+    match fs::rename(from, to) {
+        Err(e) if e.kind() == io::ErrorKind::CrossesDevices => {
+            writer::log(
+                Level::Warning,
+                format_args!(
+                    "'{}': The trash is on another filesystem, so it is copied there then removed",
+                    from.display()
+                ),
+            );
+            copy_entry(from, to)?;
+            restore::remove(from)
+        }
+        result => result.map_err(|source| BackupError::CopyFailed {
+            from: from.to_path_buf(),
+            to: to.to_path_buf(),
+            source,
+        }),
+    }
+}
+
+/// Copies the file, link or directory tree at `from` to `to`, keeping modes
+/// and modification times.
+fn copy_entry(from: &Path, to: &Path) -> Result<(), BackupError> {
+    let copy_error = |source| BackupError::CopyFailed {
+        from: from.to_path_buf(),
+        to: to.to_path_buf(),
+        source,
+    };
+    let metadata = fs::symlink_metadata(from).map_err(copy_error)?;
+    if metadata.is_symlink() {
+        let target = fs::read_link(from).map_err(copy_error)?;
+        return backup::symlink(&target, to).map_err(copy_error);
+    }
+    if metadata.is_file() {
+        fs::copy(from, to).map_err(copy_error)?;
+        let mtime = filetime::FileTime::from_last_modification_time(&metadata);
+        return filetime::set_file_mtime(to, mtime).map_err(copy_error);
+    }
+
+    let options = CopyOptions {
+        dereference: false,
+        preserve: true,
+        preserve_owner: false,
+        xattrs: true,
+        reflink: Reflink::Auto,
+        buffer_size: DEFAULT_BUFFER_SIZE,
+        fsync: Fsync::None,
+        special_files: SpecialFiles::Record,
+        excludes: &Excludes::new(),
+        ignore_files: false,
+        exclude_caches: false,
+        max_depth: None,
+        max_files: None,
+        min_file_size: None,
+        max_file_size: None,
+        checksum: None,
+        previous: None,
+        mirror: None,
+        jobs: 1,
+        keep_going: false,
+        retries: 0,
+        retry_delay: Duration::ZERO,
+        throttle: None,
+    };
+    backup::copy_directory(from, to, options).map(|_| ())
+}
+
+/// Reads the original path and deletion date of an info file.
+fn parse_info(contents: &str) -> Option<(PathBuf, NaiveDateTime)> {
+    let value = |key: &str| {
+        contents
+            .lines()
+            .find_map(|line| line.strip_prefix(key)?.strip_prefix('='))
+    };
+    let path = decode(value("Path")?)?;
+    let deleted = NaiveDateTime::parse_from_str(value("DeletionDate")?, DATE_FORMAT).ok()?;
+    Some((path, deleted))
+}
+
+/// Escapes `path` as a URL path, as info files record it.
+fn encode(path: &Path) -> String {
+    let mut encoded = String::new();
+    for &byte in path_bytes(path).iter() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' | b'/' => {
+                encoded.push(char::from(byte));
+            }
+            _ => encoded.push_str(&format!("%{byte:02X}")),
+        }
+    }
+    encoded
+}
+
+/// Reverts [`encode`].
+fn decode(encoded: &str) -> Option<PathBuf> {
+    let mut bytes = Vec::new();
+    let mut rest = encoded.as_bytes();
+    while let Some((&byte, tail)) = rest.split_first() {
+        if byte == b'%' {
+            let hex = std::str::from_utf8(tail.get(..2)?).ok()?;
+            bytes.push(u8::from_str_radix(hex, 16).ok()?);
+            rest = &tail[2..];
+        } else {
+            bytes.push(byte);
+            rest = tail;
+        }
+    }
+    Some(path_from_bytes(bytes))
+}
+
+#[cfg(unix)]
+fn path_bytes(path: &Path) -> Vec<u8> {
+    use std::os::unix::ffi::OsStrExt;
+    path.as_os_str().as_bytes().to_vec()
+}
+
+#[cfg(not(unix))]
+fn path_bytes(path: &Path) -> Vec<u8> {
+    path.to_string_lossy().replace('\\', "/").into_bytes()
+}
+
+#[cfg(unix)]
+fn path_from_bytes(bytes: Vec<u8>) -> PathBuf {
+    use std::os::unix::ffi::OsStringExt;
+    PathBuf::from(OsString::from_vec(bytes))
+}
+
+#[cfg(not(unix))]
+fn path_from_bytes(bytes: Vec<u8>) -> PathBuf {
+    PathBuf::from(String::from_utf8_lossy(&bytes).into_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn info_files_round_trip_escaped_paths() {
+        let path = Path::new("/backups/my notes%.2024-05-01_10-00-00.backup");
+        let encoded = encode(path);
+        assert_eq!(encoded, "/backups/my%20notes%25.2024-05-01_10-00-00.backup");
+
+        let contents = format!("[Trash Info]\nPath={encoded}\nDeletionDate=2024-05-02T08:30:00\n");
+        let (original, deleted) = parse_info(&contents).unwrap();
+        assert_eq!(original, path);
+        assert_eq!(deleted.to_string(), "2024-05-02 08:30:00");
+        assert!(parse_info("[Trash Info]\nPath=/a%2\n").is_none());
+    }
+
+    #[test]
+    fn only_backups_and_their_sidecars_are_emptied() {
+        for name in [
+            "hosts.2024-05-01_10-00-00.backup",
+            "hosts.2024-05-01_10-00-00.backup.sha256",
+            "hosts.2024-05-01_10-00-00.backup.meta.json",
+        ] {
+            assert!(is_backup_name(OsStr::new(name)), "{name}");
+        }
+        for name in ["hosts", "photo.jpg", "notes.sha256"] {
+            assert!(!is_backup_name(OsStr::new(name)), "{name}");
+        }
+    }
+
+    #[test]
+    fn trashed_names_are_made_unique() {
+        let tmp = tempfile::TempDir::new().unwrap();
+        let trash = Trash::new(tmp.path().join("trash"));
+        let backup = tmp.path().join("hosts.2024-05-01_10-00-00.backup");
+        for contents in ["first", "second"] {
+            fs::write(&backup, contents).unwrap();
+            trash.put(&backup).unwrap();
+        }
+
+        let files = tmp.path().join("trash/files");
+        assert_eq!(
+            fs::read_to_string(files.join("hosts.2024-05-01_10-00-00.backup")).unwrap(),
+            "first"
+        );
+        assert_eq!(
+            fs::read_to_string(files.join("hosts.2024-05-01_10-00-00.backup.2")).unwrap(),
+            "second"
+        );
+        assert!(tmp
+            .path()
+            .join("trash/info/hosts.2024-05-01_10-00-00.backup.2.trashinfo")
+            .is_file());
+    }
+
+    #[test]
+    fn entries_are_copied_across_filesystems() {
+        let tmp = tempfile::TempDir::new().unwrap();
+        let from = tmp.path().join("etc");
+        fs::create_dir_all(from.join("sub")).unwrap();
+        fs::write(from.join("sub/hosts"), "localhost").unwrap();
+
+        let to = tmp.path().join("copy");
+        copy_entry(&from, &to).unwrap();
+        assert_eq!(
+            fs::read_to_string(to.join("sub/hosts")).unwrap(),
+            "localhost"
+        );
+    }
+}
@@ -0,0 +1,177 @@
+//! Verification of backups against their checksum manifest.
+
+use std::fmt::Display;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::backup::DEFAULT_BUFFER_SIZE;
+use crate::checksum::{self, Algorithm};
+use crate::error::BackupError;
+use crate::list;
+use crate::s3::Bucket;
+use crate::split;
+use crate::store::Download;
+use crate::writer::{self, Level};
+
+/// Outcome of a successful verification.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VerifyReport {
+    /// Path of the manifest the backup was verified against.
+    pub manifest: PathBuf,
+    /// Number of files checked.
+    pub files: usize,
+}
+
+/// Recomputes the checksums of the files of the backup at `path` and compares
+/// them with its manifest.
+///
+/// `path` may be the backup itself, its manifest, a part of it if it is
+/// split, see [`split`], or the link to the newest backup of a name, see
+/// [`list::resolve_latest`]. Each file is reported as
+/// OK or FAILED as it is checked; a mismatch or an unreadable file makes the
+/// whole verification fail once every file has been checked.
+///
+/// # Examples
+///
+/// ```no_run
+/// use std::path::Path;
+///
+/// let report = backup::verify::verify(Path::new("hosts.2024-05-01_10-00-00.backup"))?;
+/// println!("{} files verified", report.files);
+/// # Ok::<(), backup::BackupError>(())
+/// ```
+pub fn verify(path: &Path) -> Result<VerifyReport, BackupError> {
+    let path = &*split::archive_of(&list::resolve_latest(path));
+    split::check(path)?;
+    let (manifest, algorithm) = find_manifest(path)?;
+    let dir = manifest.parent().unwrap_or(Path::new(""));
+
+    let entries = checksum::read_manifest(&manifest)?;
+    let failures = check(&entries, |file| {
+        checksum::hash_file(&dir.join(file), algorithm)
+    });
+
+    if failures > 0 {
+        return Err(BackupError::VerifyFailed {
+            path: path.to_path_buf(),
+            failures,
+        });
+    }
+
+    Ok(VerifyReport {
+        manifest,
+        files: entries.len(),
+    })
+}
+
+/// Downloads the backup at `object` in an S3 bucket, see [`crate::s3`], and
+/// compares its checksum with the manifest next to it, as [`verify`] does
+/// for a local backup. `object` may also be the manifest itself.
+///
+/// # Examples
+///
+/// ```no_run
+/// use backup::s3::Bucket;
+///
+/// let object = Bucket::parse("s3://backups/web1/etc.2024-05-01_10-00-00.backup.tar.gz").unwrap();
+/// backup::verify::verify_s3(&object)?;
+/// # Ok::<(), backup::BackupError>(())
+/// ```
+pub fn verify_s3(object: &Bucket) -> Result<VerifyReport, BackupError> {
+    let (manifest, algorithm, contents) = find_manifest_s3(object)?;
+    let entries = checksum::parse_manifest(&manifest.display_path(), &contents)?;
+    let failures = check(&entries, |file| {
+        let file = manifest.sibling(&file.to_string_lossy());
+        let mut download = file.download()?;
+        let (_, digest) =
+            checksum::copy_hashed(&mut download, io::sink(), algorithm, DEFAULT_BUFFER_SIZE)
+                .map_err(|source| BackupError::ReadFailed {
+                    path: file.display_path(),
+                    source,
+                })?;
+        download.finish()?;
+        Ok::<_, BackupError>(digest)
+    });
+
+    if failures > 0 {
+        return Err(BackupError::VerifyFailed {
+            path: object.display_path(),
+            failures,
+        });
+    }
+
+    Ok(VerifyReport {
+        manifest: manifest.display_path(),
+        files: entries.len(),
+    })
+}
+
+/// Compares the digests `hash` computes for the files of `entries` with
+/// those listed, reporting each file as OK or FAILED, and returns the number
+/// of failures.
+fn check<E: Display>(
+    entries: &[(PathBuf, String)],
+    hash: impl Fn(&Path) -> Result<String, E>,
+) -> usize {
+    let mut failures = 0;
+    for (file, expected) in entries {
+        match hash(file) {
+            Ok(digest) if digest == *expected => {
+                writer::log(Level::Success, format_args!("{}: OK", file.display()));
+            }
+            Ok(_) => {
+                writer::log(Level::Error, format_args!("{}: FAILED", file.display()));
+                failures += 1;
+            }
+            Err(e) => {
+                writer::log(
+                    Level::Error,
+                    format_args!("{}: FAILED ({e})", file.display()),
+                );
+                failures += 1;
+            }
+        }
+    }
+    failures
+}
+
+/// Finds the manifest of the backup at `path`, or recognizes `path` as one.
+pub(crate) fn find_manifest(path: &Path) -> Result<(PathBuf, Algorithm), BackupError> {
+    for algorithm in Algorithm::ALL {
+        if path
+            .extension()
+            .is_some_and(|extension| extension == algorithm.extension())
+            && path.is_file()
+        {
+            return Ok((path.to_path_buf(), algorithm));
+        }
+
+        let manifest = algorithm.manifest_path(path);
+        if manifest.is_file() {
+            return Ok((manifest, algorithm));
+        }
+    }
+
+    Err(BackupError::ChecksumMissing(path.to_path_buf()))
+}
+
+/// Reads the manifest of the backup at `object`, or `object` itself if it is
+/// one, returning its key, algorithm and contents.
+fn find_manifest_s3(object: &Bucket) -> Result<(Bucket, Algorithm, Vec<u8>), BackupError> {
+    for algorithm in Algorithm::ALL {
+        let manifest = match object
+            .file_name()
+            .ends_with(&format!(".{}", algorithm.extension()))
+        {
+            true => object.clone(),
+            false => object.sibling(&format!("{}.{}", object.file_name(), algorithm.extension())),
+        };
+        match manifest.read() {
+            Ok(contents) => return Ok((manifest, algorithm, contents)),
+            Err(BackupError::NotFound(_)) => continue,
+            Err(error) => return Err(error),
+        }
+    }
+
+    Err(BackupError::ChecksumMissing(object.display_path()))
+}
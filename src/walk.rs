@@ -0,0 +1,389 @@
+//! Recursive traversal of source trees.
+
+use std::borrow::Cow;
+use std::fs::{self, Metadata, ReadDir};
+use std::path::{Path, PathBuf};
+
+use crate::error::BackupError;
+use crate::exclude::{self, Excludes};
+use crate::writer;
+
+/// An entry found while walking a tree.
+#[derive(Debug)]
+pub struct Entry {
+    /// Full path of the entry.
+    pub path: PathBuf,
+    /// Path of the entry relative to the root of the walk.
+    pub relative: PathBuf,
+    /// Metadata of the entry, or of its target when following symbolic links.
+    pub metadata: Metadata,
+}
+
+/// Depth-first walk over the entries below a directory.
+///
+/// Directories are yielded before their contents. When following symbolic
+/// links, a link leading back to one of its ancestors is reported as
+/// [`BackupError::SymlinkLoop`] and not descended into, and a link to a
+/// nonexistent path is reported as [`BackupError::DanglingLink`].
+///
+/// Entries matching the [`Excludes`] given to [`Walker::exclude`], or the
+/// ignore files read with [`Walker::ignore_files`], and the caches skipped
+/// with [`Walker::exclude_caches`], are skipped without
+/// descending into excluded directories, and remembered in
+/// [`Walker::excluded`]. So are the regular files outside the sizes set with
+/// [`Walker::min_file_size`] and [`Walker::max_file_size`], in
+/// [`Walker::size_skipped`].
+///
+/// Directories at the depth set with [`Walker::max_depth`] are yielded but not
+/// descended into, and remembered in [`Walker::too_deep`] unless empty. The walk ends
+/// early once it yielded the number of files set with [`Walker::max_files`].
+pub struct Walker {
+    dereference: bool,
+    excludes: Excludes,
+    ignore_files: bool,
+    exclude_caches: bool,
+    excluded: Vec<PathBuf>,
+    min_file_size: Option<u64>,
+    max_file_size: Option<u64>,
+    size_skipped: Vec<(PathBuf, u64)>,
+    max_depth: Option<usize>,
+    too_deep: Vec<PathBuf>,
+    max_files: Option<u64>,
+    files: u64,
+    reached_max_files: bool,
+    stack: Vec<Level>,
+    pending: Option<BackupError>,
+}
+
+/// A directory being read by the walker.
+struct Level {
+    path: PathBuf,
+    relative: PathBuf,
+    canonical: Option<PathBuf>,
+    ignore: Option<Excludes>,
+    entries: ReadDir,
+}
+
+impl Walker {
+    /// Starts a walk below `root`, following symbolic links if `dereference` is set.
+    ///
+    /// On Windows, the paths of the entries are in the form given by
+    /// [`long_path`], so that trees of any depth are walked.
+    pub fn new(root: &Path, dereference: bool) -> Result<Self, BackupError> {
+        let mut walker = Walker {
+            dereference,
+            excludes: Excludes::new(),
+            ignore_files: false,
+            exclude_caches: false,
+            excluded: Vec::new(),
+            min_file_size: None,
+            max_file_size: None,
+            size_skipped: Vec::new(),
+            max_depth: None,
+            too_deep: Vec::new(),
+            max_files: None,
+            files: 0,
+            reached_max_files: false,
+            stack: Vec::new(),
+            pending: None,
+        };
+        walker.descend(&long_path(root), PathBuf::new())?;
+        Ok(walker)
+    }
+
+    /// Skips the entries matching `excludes`.
+    pub fn exclude(mut self, excludes: &Excludes) -> Self {
+        self.excludes = excludes.clone();
+        self
+    }
+
+    /// Reads the ignore file of each directory, if any, and skips the entries
+    /// it excludes.
+    pub fn ignore_files(mut self, enabled: bool) -> Self {
+        self.ignore_files = enabled;
+        if enabled {
+            for level in &mut self.stack {
+                level.ignore = read_ignore_file(&level.path);
+            }
+        }
+        self
+    }
+
+    /// Skips the directories tagged as caches, see [`exclude::is_cache_dir`].
+    pub fn exclude_caches(mut self, enabled: bool) -> Self {
+        self.exclude_caches = enabled;
+        self
+    }
+
+    /// Paths of the entries skipped so far, relative to the root of the walk.
+    pub fn excluded(&self) -> &[PathBuf] {
+        &self.excluded
+    }
+
+    /// Skips the regular files smaller than `bytes`.
+    pub fn min_file_size(mut self, bytes: Option<u64>) -> Self {
+        self.min_file_size = bytes;
+        self
+    }
+
+    /// Skips the regular files larger than `bytes`.
+    pub fn max_file_size(mut self, bytes: Option<u64>) -> Self {
+        self.max_file_size = bytes;
+        self
+    }
+
+    /// Paths of the files skipped so far for their size, relative to the root
+    /// of the walk, along with that size.
+    pub fn size_skipped(&self) -> &[(PathBuf, u64)] {
+        &self.size_skipped
+    }
+
+    /// Leaves out the contents of the directories `depth` levels below the
+    /// root, the entries of the root itself being at depth 1.
+    pub fn max_depth(mut self, depth: Option<usize>) -> Self {
+        self.max_depth = depth;
+        self
+    }
+
+    /// Paths of the directories whose contents were left out so far by the
+    /// depth limit, relative to the root of the walk.
+    pub fn too_deep(&self) -> &[PathBuf] {
+        &self.too_deep
+    }
+
+    /// Ends the walk once `files` entries other than directories were yielded
+    /// and more are found.
+    pub fn max_files(mut self, files: Option<u64>) -> Self {
+        self.max_files = files;
+        self
+    }
+
+    /// Whether the walk ended early because there were more files than
+    /// allowed by [`Walker::max_files`].
+    pub fn reached_max_files(&self) -> bool {
+        self.reached_max_files
+    }
+
+    /// Pushes the directory at `path` onto the stack of directories being read.
+    fn descend(&mut self, path: &Path, relative: PathBuf) -> Result<(), BackupError> {
+        let canonical = if self.dereference {
+            let canonical = fs::canonicalize(path).map_err(|e| read_error(path, e))?;
+            if self
+                .stack
+                .iter()
+                .any(|level| level.canonical.as_ref() == Some(&canonical))
+            {
+                return Err(BackupError::SymlinkLoop(path.to_path_buf()));
+            }
+            Some(canonical)
+        } else {
+            None
+        };
+
+        let entries = fs::read_dir(path).map_err(|e| read_error(path, e))?;
+        let ignore = if self.ignore_files {
+            read_ignore_file(path)
+        } else {
+            None
+        };
+        self.stack.push(Level {
+            path: path.to_path_buf(),
+            relative,
+            canonical,
+            ignore,
+            entries,
+        });
+        Ok(())
+    }
+}
+
+impl Walker {
+    /// Checks whether the entry at `relative` is excluded by the exclude
+    /// patterns or by the ignore files of the directories being read.
+    fn is_excluded(&self, relative: &Path, is_dir: bool) -> bool {
+        let ignore_files = self.stack.iter().filter_map(|level| {
+            let ignore = level.ignore.as_ref()?;
+            Some((level.relative.as_path(), ignore))
+        });
+
+        self.excludes.is_excluded(relative, is_dir)
+            || exclude::is_ignored(ignore_files, relative, is_dir)
+    }
+}
+
+impl Iterator for Walker {
+    type Item = Result<Entry, BackupError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(error) = self.pending.take() {
+            return Some(Err(error));
+        }
+
+        loop {
+            let level = self.stack.last_mut()?;
+            let entry = match level.entries.next() {
+                Some(Ok(entry)) => entry,
+                Some(Err(e)) => return Some(Err(read_error(&level.path, e))),
+                None => {
+                    self.stack.pop();
+                    continue;
+                }
+            };
+
+            let path = entry.path();
+            let relative = level.relative.join(entry.file_name());
+            let metadata = if self.dereference {
+                fs::metadata(&path).map_err(|e| match fs::symlink_metadata(&path) {
+                    Ok(link) if link.is_symlink() => BackupError::DanglingLink(path.clone()),
+                    _ => read_error(&path, e),
+                })
+            } else {
+                fs::symlink_metadata(&path).map_err(|e| read_error(&path, e))
+            };
+            let metadata = match metadata {
+                Ok(metadata) => metadata,
+                Err(error) => return Some(Err(error)),
+            };
+
+            let excluded = self.is_excluded(&relative, metadata.is_dir())
+                || metadata.is_dir() && self.exclude_caches && exclude::is_cache_dir(&path);
+            if excluded {
+                self.excluded.push(relative);
+                continue;
+            }
+            let size = metadata.len();
+            if metadata.is_file()
+                && (self.min_file_size.is_some_and(|min| size < min)
+                    || self.max_file_size.is_some_and(|max| size > max))
+            {
+                self.size_skipped.push((relative, size));
+                continue;
+            }
+
+            let depth = relative.components().count();
+            if metadata.is_dir() && self.max_depth.is_some_and(|max| depth >= max) {
+                if fs::read_dir(&path).is_ok_and(|mut entries| entries.next().is_some()) {
+                    self.too_deep.push(relative.clone());
+                }
+            } else if metadata.is_dir() {
+                match self.descend(&path, relative.clone()) {
+                    Err(BackupError::SymlinkLoop(path)) => {
+                        return Some(Err(BackupError::SymlinkLoop(path)))
+                    }
+                    Err(error) => self.pending = Some(error),
+                    Ok(()) => {}
+                }
+            } else if self.max_files.is_some_and(|max| self.files >= max) {
+                self.reached_max_files = true;
+                self.stack.clear();
+                return None;
+            } else {
+                self.files += 1;
+            }
+
+            return Some(Ok(Entry {
+                path,
+                relative,
+                metadata,
+            }));
+        }
+    }
+}
+
+/// Returns `path` in a form that is not limited to 260 characters on
+/// Windows, so that deep trees can be walked and copied. Paths below it, made
+/// by joining names to it, are not limited either.
+///
+/// This is `path` itself on other platforms, or if it cannot be made absolute.
+pub(crate) fn long_path(path: &Path) -> Cow<'_, Path> {
+    if !cfg!(windows) {
+        return Cow::Borrowed(path);
+    }
+
+    std::path::absolute(path)
+        .ok()
+        .and_then(|absolute| verbatim(absolute.to_str()?))
+        .map_or(Cow::Borrowed(path), |verbatim| Cow::Owned(verbatim.into()))
+}
+
+/// Turns an absolute Windows path, such as `C:\data` or `\\server\share`,
+/// into its verbatim form starting with `\\?\`. Returns `None` for paths
+/// already in that form, device paths, and relative paths.
+fn verbatim(path: &str) -> Option<String> {
+    if path.starts_with(r"\\?\") || path.starts_with(r"\\.\") {
+        return None;
+    }
+    if let Some(unc) = path.strip_prefix(r"\\") {
+        let (server, share) = unc.split_once('\\')?;
+        if server.is_empty() || share.is_empty() {
+            return None;
+        }
+        return Some(format!(r"\\?\UNC\{unc}"));
+    }
+
+    let mut chars = path.chars();
+    match (chars.next(), chars.next(), chars.next()) {
+        (Some(drive), Some(':'), Some('\\')) if drive.is_ascii_alphabetic() => {
+            Some(format!(r"\\?\{path}"))
+        }
+        _ => None,
+    }
+}
+
+/// Reads the ignore file of the directory at `path`, warning if it exists but
+/// cannot be read.
+fn read_ignore_file(path: &Path) -> Option<Excludes> {
+    Excludes::read_ignore_file(path).unwrap_or_else(|e| {
+        let path = path.join(exclude::IGNORE_FILE);
+        writer::log(writer::Level::Warning, read_error(&path, e));
+        None
+    })
+}
+
+fn read_error(path: &Path, source: std::io::Error) -> BackupError {
+    BackupError::ReadFailed {
+        path: path.to_path_buf(),
+        source,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn windows_paths_are_made_verbatim() {
+        assert_eq!(
+            verbatim(r"C:\Users\me\backups").as_deref(),
+            Some(r"\\?\C:\Users\me\backups")
+        );
+        assert_eq!(verbatim(r"d:\").as_deref(), Some(r"\\?\d:\"));
+        assert_eq!(
+            verbatim(r"\\nas\share\photos").as_deref(),
+            Some(r"\\?\UNC\nas\share\photos")
+        );
+        for unchanged in [
+            r"\\?\C:\Users",
+            r"\\?\UNC\nas\share",
+            r"\\.\COM1",
+            r"\\nas",
+            r"C:relative",
+            r"relative\path",
+            "/home/me",
+        ] {
+            assert_eq!(verbatim(unchanged), None, "{unchanged}");
+        }
+    }
+
+    #[test]
+    fn long_paths_are_absolute_on_windows() {
+        let path = long_path(Path::new("data"));
+        match cfg!(windows) {
+            true => {
+                assert!(path.to_str().unwrap().starts_with(r"\\?\"), "{path:?}");
+                assert!(path.ends_with("data"), "{path:?}");
+            }
+            false => assert_eq!(path, Path::new("data")),
+        }
+    }
+}
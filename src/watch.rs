@@ -0,0 +1,341 @@
+//! Waiting for a file or directory to change, to back it up again.
+//!
+//! On Linux the source is watched with inotify: every directory of the tree,
+//! including those created later, or the directory holding a single file.
+//! Elsewhere the tree is scanned every second, comparing the sizes and
+//! modification times of its entries with the previous scan.
+//!
+//! Changes are only reported once they settle, when none was seen for a
+//! while, so that an editor saving through temporary files or a build
+//! writing many files leads to a single backup.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! use std::path::Path;
+//! use std::time::Duration;
+//!
+//! use backup::watch::Watcher;
+//!
+//! let mut watcher = Watcher::new(Path::new("/srv/data"))?;
+//! while watcher.wait(Duration::from_secs(5))? {
+//!     println!("/srv/data changed");
+//! }
+//! # Ok::<(), backup::BackupError>(())
+//! ```
+
+use std::path::Path;
+use std::time::Duration;
+
+use crate::error::BackupError;
+
+/// Watches a file or directory for changes.
+pub struct Watcher {
+    inner: imp::Watcher,
+}
+
+impl Watcher {
+    /// Starts watching the file or directory at `path`.
+    pub fn new(path: &Path) -> Result<Watcher, BackupError> {
+        let inner = imp::Watcher::new(path).map_err(|source| BackupError::ReadFailed {
+            path: path.to_path_buf(),
+            source,
+        })?;
+        Ok(Watcher { inner })
+    }
+
+    /// Waits for changes since the last call, or since the watcher was
+    /// created, until none is seen for `settle`. Returns `false` if
+    /// interrupted first, see [`crate::interrupt`].
+    pub fn wait(&mut self, settle: Duration) -> Result<bool, BackupError> {
+        self.inner
+            .wait(settle)
+            .map_err(|source| BackupError::ReadFailed {
+                path: self.inner.path().to_path_buf(),
+                source,
+            })
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod imp {
+    use std::collections::HashMap;
+    use std::ffi::{CString, OsStr, OsString};
+    use std::fs;
+    use std::io;
+    use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+    use std::os::unix::ffi::OsStrExt;
+    use std::path::{Path, PathBuf};
+    use std::time::Duration;
+
+    use crate::interrupt;
+
+    /// Events telling that an entry of a watched directory changed.
+    const MASK: u32 = libc::IN_CREATE
+        | libc::IN_DELETE
+        | libc::IN_MODIFY
+        | libc::IN_ATTRIB
+        | libc::IN_CLOSE_WRITE
+        | libc::IN_MOVED_FROM
+        | libc::IN_MOVED_TO
+        | libc::IN_DELETE_SELF
+        | libc::IN_MOVE_SELF;
+
+    /// Size of the header of each event read, before its name.
+    const EVENT_SIZE: usize = 16;
+
+    pub(super) struct Watcher {
+        path: PathBuf,
+        fd: OwnedFd,
+        /// Directory watched for each watch descriptor.
+        dirs: HashMap<i32, PathBuf>,
+        /// Name of the file watched in its directory, if the path is not a
+        /// directory.
+        file: Option<OsString>,
+    }
+
+    impl Watcher {
+        pub(super) fn new(path: &Path) -> io::Result<Watcher> {
+            // SAFETY: inotify_init1 takes no pointer, and the descriptor it
+            // returns is owned here.
+            let fd = unsafe { libc::inotify_init1(libc::IN_CLOEXEC | libc::IN_NONBLOCK) };
+            if fd < 0 {
+                return Err(io::Error::last_os_error());
+            }
+            // SAFETY: fd is a new descriptor nothing else owns.
+            let fd = unsafe { OwnedFd::from_raw_fd(fd) };
+            let mut watcher = Watcher {
+                path: path.to_path_buf(),
+                fd,
+                dirs: HashMap::new(),
+                file: None,
+            };
+            match fs::metadata(path)?.is_dir() {
+                true => watcher.add_tree(path, true)?,
+                false => {
+                    let dir = path.parent().filter(|dir| !dir.as_os_str().is_empty());
+                    watcher.add_tree(dir.unwrap_or(Path::new(".")), false)?;
+                    watcher.file = path.file_name().map(OsStr::to_os_string);
+                }
+            }
+            Ok(watcher)
+        }
+
+        pub(super) fn path(&self) -> &Path {
+            &self.path
+        }
+
+        /// Watches the directory `dir`, and its subdirectories if `recursive`
+        /// is set. Subdirectories that cannot be watched are left out.
+        fn add_tree(&mut self, dir: &Path, recursive: bool) -> io::Result<()> {
+            let name = CString::new(dir.as_os_str().as_bytes())?;
+            // SAFETY: name is a valid C string for the duration of the call.
+            let wd = unsafe {
+                libc::inotify_add_watch(self.fd.as_raw_fd(), name.as_ptr(), MASK | libc::IN_ONLYDIR)
+            };
+            if wd < 0 {
+                return Err(io::Error::last_os_error());
+            }
+            self.dirs.insert(wd, dir.to_path_buf());
+            if !recursive {
+                return Ok(());
+            }
+            for entry in fs::read_dir(dir)?.flatten() {
+                if entry.file_type().is_ok_and(|kind| kind.is_dir()) {
+                    let _ = self.add_tree(&entry.path(), true);
+                }
+            }
+            Ok(())
+        }
+
+        pub(super) fn wait(&mut self, settle: Duration) -> io::Result<bool> {
+            loop {
+                if interrupt::is_interrupted() {
+                    return Ok(false);
+                }
+                if self.poll(None)? && self.read_events()? {
+                    break;
+                }
+            }
+            loop {
+                if interrupt::is_interrupted() {
+                    return Ok(false);
+                }
+                match self.poll(Some(settle))? {
+                    true => {
+                        self.read_events()?;
+                    }
+                    false if interrupt::is_interrupted() => return Ok(false),
+                    false => return Ok(true),
+                }
+            }
+        }
+
+        /// Waits for events for at most `timeout`, returning whether there
+        /// are some to read. A signal ends the wait early.
+        fn poll(&self, timeout: Option<Duration>) -> io::Result<bool> {
+            let mut fds = libc::pollfd {
+                fd: self.fd.as_raw_fd(),
+                events: libc::POLLIN,
+                revents: 0,
+            };
+            let timeout = timeout.map_or(-1, |timeout| {
+                libc::c_int::try_from(timeout.as_millis()).unwrap_or(libc::c_int::MAX)
+            });
+            // SAFETY: fds is a single valid pollfd.
+            match unsafe { libc::poll(&mut fds, 1, timeout) } {
+                -1 => match io::Error::last_os_error() {
+                    error if error.kind() == io::ErrorKind::Interrupted => Ok(false),
+                    error => Err(error),
+                },
+                ready => Ok(ready > 0),
+            }
+        }
+
+        /// Reads the pending events, watching the directories created, and
+        /// returns whether any tells of a change to the source.
+        fn read_events(&mut self) -> io::Result<bool> {
+            let mut changed = false;
+            let mut buf = vec![0u8; 64 * 1024];
+            loop {
+                // SAFETY: buf is valid for writes of its whole length.
+                let read =
+                    unsafe { libc::read(self.fd.as_raw_fd(), buf.as_mut_ptr().cast(), buf.len()) };
+                let read = match usize::try_from(read) {
+                    Ok(read) => read,
+                    Err(_) => match io::Error::last_os_error() {
+                        error if error.kind() == io::ErrorKind::WouldBlock => return Ok(changed),
+                        error if error.kind() == io::ErrorKind::Interrupted => return Ok(changed),
+                        error => return Err(error),
+                    },
+                };
+                let mut events = &buf[..read];
+                while events.len() >= EVENT_SIZE {
+                    let field = |at: usize| {
+                        u32::from_ne_bytes(events[at..at + 4].try_into().expect("four bytes"))
+                    };
+                    let wd = field(0) as i32;
+                    let mask = field(4);
+                    let len = field(12) as usize;
+                    let end = (EVENT_SIZE + len).min(events.len());
+                    let name = &events[EVENT_SIZE..end];
+                    let name = OsStr::from_bytes(name.split(|&b| b == 0).next().unwrap_or(name));
+                    changed |= self.event(wd, mask, name);
+                    events = &events[end..];
+                }
+            }
+        }
+
+        /// Handles the event `mask` about the entry `name` of the directory
+        /// watched as `wd`, returning whether it tells of a change.
+        fn event(&mut self, wd: i32, mask: u32, name: &OsStr) -> bool {
+            if mask & libc::IN_Q_OVERFLOW != 0 {
+                return true;
+            }
+            if mask & libc::IN_IGNORED != 0 {
+                self.dirs.remove(&wd);
+                return false;
+            }
+            if let Some(file) = &self.file {
+                return name == file.as_os_str();
+            }
+            let created = libc::IN_CREATE | libc::IN_MOVED_TO;
+            if mask & libc::IN_ISDIR != 0 && mask & created != 0 {
+                if let Some(dir) = self.dirs.get(&wd) {
+                    let _ = self.add_tree(&dir.join(name), true);
+                }
+            }
+            true
+        }
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+mod imp {
+    use std::collections::hash_map::DefaultHasher;
+    use std::fs;
+    use std::hash::{Hash, Hasher};
+    use std::io;
+    use std::path::{Path, PathBuf};
+    use std::thread;
+    use std::time::{Duration, Instant};
+
+    use crate::interrupt;
+
+    /// Time between two scans of the tree while waiting for a change.
+    const INTERVAL: Duration = Duration::from_secs(1);
+
+    pub(super) struct Watcher {
+        path: PathBuf,
+        /// Digest of the sizes and modification times of the tree when last
+        /// scanned.
+        scanned: u64,
+    }
+
+    impl Watcher {
+        pub(super) fn new(path: &Path) -> io::Result<Watcher> {
+            fs::symlink_metadata(path)?;
+            Ok(Watcher {
+                path: path.to_path_buf(),
+                scanned: scan(path),
+            })
+        }
+
+        pub(super) fn path(&self) -> &Path {
+            &self.path
+        }
+
+        pub(super) fn wait(&mut self, settle: Duration) -> io::Result<bool> {
+            let mut wait = INTERVAL;
+            let mut changed = false;
+            loop {
+                if !sleep(wait) {
+                    return Ok(false);
+                }
+                let scanned = scan(&self.path);
+                if scanned != self.scanned {
+                    self.scanned = scanned;
+                    changed = true;
+                    wait = settle;
+                } else if changed {
+                    return Ok(true);
+                }
+            }
+        }
+    }
+
+    /// Sleeps for `duration`, returning `false` if interrupted first.
+    fn sleep(duration: Duration) -> bool {
+        let end = Instant::now() + duration;
+        while let Some(left) = end.checked_duration_since(Instant::now()) {
+            if interrupt::is_interrupted() {
+                return false;
+            }
+            thread::sleep(left.min(Duration::from_millis(100)));
+        }
+        !interrupt::is_interrupted()
+    }
+
+    /// Returns a digest of the paths, sizes and modification times of the
+    /// entries of the tree at `path`.
+    fn scan(path: &Path) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        let mut pending = vec![path.to_path_buf()];
+        while let Some(path) = pending.pop() {
+            let Ok(metadata) = fs::symlink_metadata(&path) else {
+                continue;
+            };
+            path.hash(&mut hasher);
+            metadata.len().hash(&mut hasher);
+            metadata.modified().ok().hash(&mut hasher);
+            if metadata.is_dir() {
+                if let Ok(entries) = fs::read_dir(&path) {
+                    let mut entries: Vec<_> = entries.flatten().map(|entry| entry.path()).collect();
+                    entries.sort();
+                    pending.extend(entries);
+                }
+            }
+        }
+        hasher.finish()
+    }
+}
@@ -0,0 +1,322 @@
+//! Reporting of messages emitted while an operation runs, and helpers
+//! formatting sizes and durations for them and parsing sizes back.
+//!
+//! The library never prints anything itself: messages are handed to the
+//! logger installed with [`set_logger`], and discarded if there is none.
+
+use std::fmt::{self, Display};
+use std::sync::OnceLock;
+use std::time::Duration;
+
+/// Importance of a message, from the most to the least important.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Level {
+    /// A failure, such as an entry that could not be copied.
+    Error,
+    /// A problem that does not stop the operation.
+    Warning,
+    /// The successful outcome of an operation or of one of its steps.
+    Success,
+    /// A regular progress message.
+    Info,
+    /// A detailed progress message, such as each file being copied.
+    Verbose,
+}
+
+/// Function receiving the messages emitted by the library.
+pub type Logger = fn(Level, fmt::Arguments<'_>);
+
+/// Logger installed with [`set_logger`].
+static LOGGER: OnceLock<Logger> = OnceLock::new();
+
+/// Installs the function receiving the messages emitted by the library.
+///
+/// Only the first logger installed is kept; returns `false` if one was
+/// already installed.
+///
+/// # Examples
+///
+/// ```
+/// use backup::writer::{self, Level};
+///
+/// writer::set_logger(|level, message| {
+///     if level <= Level::Warning {
+///         eprintln!("{level:?}: {message}");
+///     }
+/// });
+/// ```
+pub fn set_logger(logger: Logger) -> bool {
+    LOGGER.set(logger).is_ok()
+}
+
+/// Hands `message` to the installed logger, if any.
+pub(crate) fn log(level: Level, message: impl Display) {
+    if let Some(logger) = LOGGER.get() {
+        logger(level, format_args!("{message}"));
+    }
+}
+
+/// Formats a byte count with binary units, e.g. `1.4 GiB`.
+///
+/// # Examples
+///
+/// ```
+/// assert_eq!(backup::writer::human_bytes(1536), "1.5 KiB");
+/// ```
+pub fn human_bytes(bytes: u64) -> String {
+    const UNITS: [&str; 7] = ["B", "KiB", "MiB", "GiB", "TiB", "PiB", "EiB"];
+
+    // Moving to the next unit once the value would be rounded up to 1024, so
+    // that 1048575 bytes are 1.0 MiB rather than 1024.0 KiB.
+    let mut value = bytes as f64;
+    let mut unit = 0;
+    while value >= 1023.95 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+
+    if unit == 0 {
+        format!("{bytes} B")
+    } else {
+        format!("{value:.1} {}", UNITS[unit])
+    }
+}
+
+/// Formats a duration as seconds with a tenth below a minute, e.g. `4.2s`,
+/// and as whole days, hours, minutes and seconds above, e.g. `2h30m`, which
+/// [`parse_duration`](crate::duration::parse_duration) reads back.
+///
+/// # Examples
+///
+/// ```
+/// use std::time::Duration;
+///
+/// assert_eq!(backup::writer::human_duration(Duration::from_millis(4200)), "4.2s");
+/// assert_eq!(backup::writer::human_duration(Duration::from_secs(9000)), "2h30m");
+/// ```
+pub fn human_duration(duration: Duration) -> String {
+    const UNITS: [(u128, &str); 4] = [(24 * 60 * 60, "d"), (60 * 60, "h"), (60, "m"), (1, "s")];
+
+    let tenths = (duration.as_millis() + 50) / 100;
+    if tenths < 600 {
+        return format!("{}.{}s", tenths / 10, tenths % 10);
+    }
+
+    let mut seconds = (duration.as_millis() + 500) / 1000;
+    let mut text = String::new();
+    for (length, unit) in UNITS {
+        if seconds >= length {
+            text.push_str(&format!("{}{unit}", seconds / length));
+            seconds %= length;
+        }
+    }
+    text
+}
+
+/// Parses a byte count with an optional binary unit, such as `500K`, `10M` or
+/// `1.5GiB`.
+///
+/// Units go from `K` to `E`, in either case and optionally followed by `B` or
+/// `iB`, and are all powers of 1024.
+///
+/// # Examples
+///
+/// ```
+/// assert_eq!(backup::writer::parse_size("10M"), Ok(10 << 20));
+/// ```
+pub fn parse_size(text: &str) -> Result<u64, String> {
+    let invalid = || format!("Invalid size '{text}', expected e.g. 500K, 10M or 1G");
+
+    let trimmed = text.trim();
+    let digits = trimmed
+        .find(|c: char| !c.is_ascii_digit() && c != '.')
+        .unwrap_or(trimmed.len());
+    let (number, unit) = trimmed.split_at(digits);
+    let unit = unit.trim_start();
+    let unit = unit
+        .strip_suffix("iB")
+        .or_else(|| unit.strip_suffix('B'))
+        .filter(|prefix| !prefix.is_empty())
+        .unwrap_or(unit);
+    let power = match unit.to_ascii_uppercase().as_str() {
+        "" | "B" => 0,
+        "K" => 1,
+        "M" => 2,
+        "G" => 3,
+        "T" => 4,
+        "P" => 5,
+        "E" => 6,
+        _ => return Err(invalid()),
+    };
+    let multiplier = 1u64 << (10 * power);
+
+    let (whole, fraction) = number.split_once('.').unwrap_or((number, ""));
+    if whole.is_empty() && fraction.is_empty() || fraction.contains('.') {
+        return Err(invalid());
+    }
+    let whole: u64 = match whole {
+        "" => 0,
+        whole => whole.parse().map_err(|_| invalid())?,
+    };
+    let fraction = match fraction {
+        "" => 0,
+        fraction => {
+            let value: f64 = format!("0.{fraction}").parse().map_err(|_| invalid())?;
+            (value * multiplier as f64) as u64
+        }
+    };
+    whole
+        .checked_mul(multiplier)
+        .and_then(|bytes| bytes.checked_add(fraction))
+        .ok_or_else(invalid)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KIB: u64 = 1 << 10;
+    const MIB: u64 = 1 << 20;
+    const GIB: u64 = 1 << 30;
+    const EIB: u64 = 1 << 60;
+
+    #[test]
+    fn bytes_below_a_kibibyte_are_exact() {
+        assert_eq!(human_bytes(0), "0 B");
+        assert_eq!(human_bytes(1), "1 B");
+        assert_eq!(human_bytes(1023), "1023 B");
+    }
+
+    #[test]
+    fn exact_powers_have_a_single_decimal() {
+        assert_eq!(human_bytes(KIB), "1.0 KiB");
+        assert_eq!(human_bytes(MIB), "1.0 MiB");
+        assert_eq!(human_bytes(GIB), "1.0 GiB");
+        assert_eq!(human_bytes(1 << 40), "1.0 TiB");
+        assert_eq!(human_bytes(1 << 50), "1.0 PiB");
+        assert_eq!(human_bytes(EIB), "1.0 EiB");
+    }
+
+    #[test]
+    fn bytes_are_rounded_to_a_tenth() {
+        assert_eq!(human_bytes(1536), "1.5 KiB");
+        assert_eq!(human_bytes(1500 * KIB), "1.5 MiB");
+        assert_eq!(human_bytes(KIB + 51), "1.0 KiB");
+        assert_eq!(human_bytes(KIB + 52), "1.1 KiB");
+        assert_eq!(human_bytes(1434 * MIB), "1.4 GiB");
+    }
+
+    #[test]
+    fn values_rounded_up_to_the_next_unit_move_to_it() {
+        assert_eq!(human_bytes(1023 * KIB + 1000), "1.0 MiB");
+        assert_eq!(human_bytes(MIB - 1), "1.0 MiB");
+        assert_eq!(human_bytes(GIB - 1), "1.0 GiB");
+        assert_eq!(human_bytes(1023 * KIB), "1023.0 KiB");
+    }
+
+    #[test]
+    fn huge_values_stay_in_exbibytes() {
+        assert_eq!(human_bytes(u64::MAX), "16.0 EiB");
+        assert_eq!(human_bytes(15 * EIB), "15.0 EiB");
+    }
+
+    #[test]
+    fn short_durations_have_a_tenth_of_a_second() {
+        assert_eq!(human_duration(Duration::ZERO), "0.0s");
+        assert_eq!(human_duration(Duration::from_millis(49)), "0.0s");
+        assert_eq!(human_duration(Duration::from_millis(50)), "0.1s");
+        assert_eq!(human_duration(Duration::from_millis(4200)), "4.2s");
+        assert_eq!(human_duration(Duration::from_millis(59_949)), "59.9s");
+    }
+
+    #[test]
+    fn long_durations_are_split_into_units() {
+        assert_eq!(human_duration(Duration::from_millis(59_950)), "1m");
+        assert_eq!(human_duration(Duration::from_secs(60)), "1m");
+        assert_eq!(human_duration(Duration::from_secs(61)), "1m1s");
+        assert_eq!(human_duration(Duration::from_millis(89_500)), "1m30s");
+        assert_eq!(human_duration(Duration::from_secs(3600)), "1h");
+        assert_eq!(human_duration(Duration::from_secs(9000)), "2h30m");
+        assert_eq!(human_duration(Duration::from_secs(86_400 + 5)), "1d5s");
+        assert_eq!(human_duration(Duration::from_secs(400 * 86_400)), "400d");
+    }
+
+    #[test]
+    fn huge_durations_are_formatted() {
+        assert_eq!(
+            human_duration(Duration::from_secs(u64::MAX)),
+            "213503982334601d7h15s"
+        );
+    }
+
+    #[test]
+    fn long_durations_are_read_back() {
+        for seconds in [60, 61, 3599, 3600, 9000, 86_400, 1_000_000] {
+            let text = human_duration(Duration::from_secs(seconds));
+            assert_eq!(
+                crate::duration::parse_duration(&text),
+                Ok(Duration::from_secs(seconds)),
+                "{text}"
+            );
+        }
+    }
+
+    #[test]
+    fn sizes_without_a_unit_are_bytes() {
+        assert_eq!(parse_size("0"), Ok(0));
+        assert_eq!(parse_size("512"), Ok(512));
+        assert_eq!(parse_size("512B"), Ok(512));
+        assert_eq!(parse_size(" 7 "), Ok(7));
+    }
+
+    #[test]
+    fn size_units_are_powers_of_1024() {
+        assert_eq!(parse_size("1K"), Ok(KIB));
+        assert_eq!(parse_size("500k"), Ok(500 * KIB));
+        assert_eq!(parse_size("10M"), Ok(10 * MIB));
+        assert_eq!(parse_size("10MB"), Ok(10 * MIB));
+        assert_eq!(parse_size("10MiB"), Ok(10 * MIB));
+        assert_eq!(parse_size("1G"), Ok(GIB));
+        assert_eq!(parse_size("2 T"), Ok(2 << 40));
+        assert_eq!(parse_size("1P"), Ok(1 << 50));
+        assert_eq!(parse_size("1E"), Ok(EIB));
+    }
+
+    #[test]
+    fn fractional_sizes_are_truncated_to_bytes() {
+        assert_eq!(parse_size("1.5G"), Ok(GIB + GIB / 2));
+        assert_eq!(parse_size("0.5K"), Ok(512));
+        assert_eq!(parse_size(".5K"), Ok(512));
+        assert_eq!(parse_size("1.5"), Ok(1));
+        assert_eq!(parse_size("0.001K"), Ok(1));
+    }
+
+    #[test]
+    fn sizes_beyond_u64_are_rejected() {
+        assert_eq!(parse_size("15E"), Ok(15 * EIB));
+        assert!(parse_size("16E").is_err());
+        assert!(parse_size("18446744073709551616").is_err());
+        assert_eq!(parse_size("18446744073709551615"), Ok(u64::MAX));
+    }
+
+    #[test]
+    fn malformed_sizes_are_rejected() {
+        for text in ["", "K", ".", "1.2.3", "-1", "+1", "1X", "1iB", "1KK", "ten"] {
+            assert_eq!(
+                parse_size(text),
+                Err(format!(
+                    "Invalid size '{text}', expected e.g. 500K, 10M or 1G"
+                )),
+                "{text}"
+            );
+        }
+    }
+
+    #[test]
+    fn formatted_sizes_are_read_back() {
+        for bytes in [0, 1, 1023, KIB, 10 * MIB, GIB] {
+            let text = human_bytes(bytes).replace(' ', "");
+            assert_eq!(parse_size(&text), Ok(bytes), "{text}");
+        }
+    }
+}
@@ -0,0 +1,217 @@
+//! Extended attributes of files, which also hold their POSIX ACLs and
+//! SELinux labels, copied along with the files or recorded in archives.
+//!
+//! Attributes are only supported on Linux. Elsewhere, files are copied
+//! without them.
+
+use std::ffi::OsString;
+use std::io;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use crate::writer::{self, Level};
+
+/// Prefix of the keys of pax extended headers recording attributes, as
+/// written by GNU tar and bsdtar.
+pub(crate) const PAX_PREFIX: &str = "SCHILY.xattr.";
+
+/// Extended attributes of a file, as names and values.
+pub(crate) type Xattrs = Vec<(OsString, Vec<u8>)>;
+
+/// Whether attributes are preserved unless told otherwise, which is when
+/// running as root, the only user that may set all of them.
+pub(crate) fn enabled_by_default() -> bool {
+    #[cfg(target_os = "linux")]
+    // SAFETY: geteuid has no preconditions and cannot fail.
+    let root = unsafe { libc::geteuid() } == 0;
+    #[cfg(not(target_os = "linux"))]
+    let root = false;
+    root
+}
+
+/// Copies the extended attributes of `source` to `target`, without following
+/// either if it is a symbolic link.
+///
+/// Attributes that the filesystem of `target` does not support, or that the
+/// process may not set, are skipped with a single warning per run.
+pub(crate) fn copy(source: &Path, target: &Path) -> io::Result<()> {
+    match read(source) {
+        Ok(xattrs) => apply(target, &xattrs),
+        Err(error) if is_unsupported(&error) => Ok(()),
+        Err(error) => Err(error),
+    }
+}
+
+/// Sets the extended attributes `xattrs` on `path`, skipping those that
+/// cannot be set as [`copy`] does.
+pub(crate) fn apply(path: &Path, xattrs: &Xattrs) -> io::Result<()> {
+    for (name, value) in xattrs {
+        match write(path, name, value) {
+            Ok(()) => {}
+            Err(error) if is_unsupported(&error) => warn_once(path, &error),
+            Err(error) => return Err(error),
+        }
+    }
+    Ok(())
+}
+
+/// Returns the pax extended header records holding the attributes of `path`.
+pub(crate) fn pax_records(path: &Path) -> io::Result<Vec<(String, Vec<u8>)>> {
+    let xattrs = match read(path) {
+        Ok(xattrs) => xattrs,
+        Err(error) if is_unsupported(&error) => return Ok(Vec::new()),
+        Err(error) => return Err(error),
+    };
+
+    Ok(xattrs
+        .into_iter()
+        .filter_map(|(name, value)| Some((format!("{PAX_PREFIX}{}", name.to_str()?), value)))
+        .collect())
+}
+
+/// Reads the attributes recorded in the pax extended header of an archive
+/// entry.
+pub(crate) fn from_pax<R: io::Read>(entry: &mut tar::Entry<'_, R>) -> io::Result<Xattrs> {
+    let Some(extensions) = entry.pax_extensions()? else {
+        return Ok(Vec::new());
+    };
+
+    let mut xattrs = Vec::new();
+    for extension in extensions {
+        let extension = extension?;
+        let Ok(key) = extension.key() else {
+            continue;
+        };
+        if let Some(name) = key.strip_prefix(PAX_PREFIX) {
+            xattrs.push((OsString::from(name), extension.value_bytes().to_vec()));
+        }
+    }
+    Ok(xattrs)
+}
+
+/// Checks whether extended attributes can be set on `path`, by setting and
+/// reading back a `user.` attribute, which is left in place.
+pub(crate) fn probe(path: &Path) -> io::Result<bool> {
+    let name = OsString::from("user.backup.probe");
+    match write(path, &name, b"1") {
+        Ok(()) => Ok(read(path)?.iter().any(|(read, _)| *read == name)),
+        Err(error) if is_unsupported(&error) => Ok(false),
+        Err(error) => Err(error),
+    }
+}
+
+/// Checks whether `error` tells that attributes are not supported by the
+/// filesystem or may not be set by the process.
+fn is_unsupported(error: &io::Error) -> bool {
+    matches!(
+        error.kind(),
+        io::ErrorKind::Unsupported | io::ErrorKind::PermissionDenied
+    ) || error.raw_os_error() == Some(ENOTSUP)
+}
+
+/// Warns that attributes are not preserved, the first time only.
+fn warn_once(path: &Path, error: &io::Error) {
+    static WARNED: AtomicBool = AtomicBool::new(false);
+    if !WARNED.swap(true, Ordering::Relaxed) {
+        writer::log(
+            Level::Warning,
+            format_args!(
+                "'{}': Extended attributes cannot be set ({error}), they are not preserved",
+                path.display()
+            ),
+        );
+    }
+}
+
+#[cfg(target_os = "linux")]
+const ENOTSUP: i32 = libc::ENOTSUP;
+
+#[cfg(not(target_os = "linux"))]
+const ENOTSUP: i32 = -1;
+
+/// Reads the extended attributes of `path`, without following it.
+#[cfg(target_os = "linux")]
+fn read(path: &Path) -> io::Result<Xattrs> {
+    use std::ffi::{CString, OsStr};
+    use std::os::unix::ffi::OsStrExt;
+
+    let path = CString::new(path.as_os_str().as_bytes())?;
+    let names = read_buffer(|buffer, size| {
+        // SAFETY: the path is NUL-terminated and the buffer valid for `size`
+        // bytes, or null with a size of zero.
+        unsafe { libc::llistxattr(path.as_ptr(), buffer.cast(), size) }
+    })?;
+
+    let mut xattrs = Vec::new();
+    for name in names.split(|&b| b == 0).filter(|name| !name.is_empty()) {
+        let name = CString::new(name)?;
+        let value = read_buffer(|buffer, size| {
+            // SAFETY: as above, with a NUL-terminated name.
+            unsafe { libc::lgetxattr(path.as_ptr(), name.as_ptr(), buffer.cast(), size) }
+        });
+        match value {
+            Ok(value) => xattrs.push((OsStr::from_bytes(name.as_bytes()).to_os_string(), value)),
+            // Removed since listed.
+            Err(error) if error.raw_os_error() == Some(libc::ENODATA) => {}
+            Err(error) => return Err(error),
+        }
+    }
+    Ok(xattrs)
+}
+
+/// Calls `get` with a buffer large enough for what it returns, asking for
+/// the size first, and again if it grew in between.
+#[cfg(target_os = "linux")]
+fn read_buffer(get: impl Fn(*mut u8, usize) -> isize) -> io::Result<Vec<u8>> {
+    loop {
+        let size = get(std::ptr::null_mut(), 0);
+        if size < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let mut buffer = vec![0u8; size as usize];
+        let read = get(buffer.as_mut_ptr(), buffer.len());
+        if read >= 0 {
+            buffer.truncate(read as usize);
+            return Ok(buffer);
+        }
+        let error = io::Error::last_os_error();
+        if error.raw_os_error() != Some(libc::ERANGE) {
+            return Err(error);
+        }
+    }
+}
+
+/// Sets the extended attribute `name` of `path`, without following it.
+#[cfg(target_os = "linux")]
+fn write(path: &Path, name: &std::ffi::OsStr, value: &[u8]) -> io::Result<()> {
+    use std::ffi::CString;
+    use std::os::unix::ffi::OsStrExt;
+
+    let path = CString::new(path.as_os_str().as_bytes())?;
+    let name = CString::new(name.as_bytes())?;
+    // SAFETY: the path and name are NUL-terminated, and the value valid for
+    // its length.
+    let result = unsafe {
+        libc::lsetxattr(
+            path.as_ptr(),
+            name.as_ptr(),
+            value.as_ptr().cast(),
+            value.len(),
+            0,
+        )
+    };
+    match result {
+        0 => Ok(()),
+        _ => Err(io::Error::last_os_error()),
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+fn read(_path: &Path) -> io::Result<Xattrs> {
+    Ok(Vec::new())
+}
+
+#[cfg(not(target_os = "linux"))]
+fn write(_path: &Path, _name: &std::ffi::OsStr, _value: &[u8]) -> io::Result<()> {
+    Err(io::Error::from(io::ErrorKind::Unsupported))
+}
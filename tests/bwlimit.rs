@@ -0,0 +1,78 @@
+mod common;
+
+use std::fs;
+use std::time::{Duration, Instant};
+
+use tempfile::TempDir;
+
+#[test]
+fn bwlimit_caps_the_rate_of_copies_and_archives() {
+    let tmp = TempDir::new().unwrap();
+    let project = tmp.path().join("project");
+    fs::create_dir(&project).unwrap();
+    for name in ["one.bin", "two.bin"] {
+        fs::write(project.join(name), vec![7; 1 << 20]).unwrap();
+    }
+
+    for compress in ["none", "gzip"] {
+        let target = format!("copies-{compress}");
+        let started = Instant::now();
+        let output = common::run(
+            tmp.path(),
+            &[
+                "b",
+                "-v",
+                "--jobs",
+                "2",
+                "--bwlimit",
+                "4M",
+                "-c",
+                compress,
+                "project",
+                &target,
+            ],
+        );
+        let elapsed = started.elapsed();
+        assert!(output.status.success(), "{output:?}");
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert!(stderr.contains("/s with --bwlimit 4.0 MiB/s"), "{stderr}");
+        if compress == "none" {
+            // Two MiB at four MiB per second, from an empty bucket.
+            assert!(elapsed >= Duration::from_millis(450), "{elapsed:?}");
+        }
+    }
+}
+
+#[test]
+fn the_current_rate_is_shown_while_copying() {
+    let tmp = TempDir::new().unwrap();
+    let project = tmp.path().join("project");
+    fs::create_dir(&project).unwrap();
+    fs::write(project.join("data.bin"), vec![7; 3 << 20]).unwrap();
+
+    let output = common::run(
+        tmp.path(),
+        &["b", "-v", "--bwlimit", "2M", "project", "copies"],
+    );
+    assert!(output.status.success(), "{output:?}");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    let line = stderr
+        .lines()
+        .find(|line| line.contains("writing at "))
+        .unwrap_or_else(|| panic!("{stderr}"));
+    assert!(line.ends_with("/s with --bwlimit 2.0 MiB/s"), "{line}");
+}
+
+#[test]
+fn invalid_rates_are_usage_errors() {
+    let tmp = TempDir::new().unwrap();
+    fs::write(tmp.path().join("notes.txt"), "notes").unwrap();
+
+    let output = common::run(tmp.path(), &["b", "--bwlimit", "fast", "notes.txt"]);
+    assert_eq!(output.status.code(), Some(2), "{output:?}");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("Invalid size 'fast', expected e.g. 500K, 10M or 1G"),
+        "{stderr}"
+    );
+}
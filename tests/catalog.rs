@@ -0,0 +1,94 @@
+mod common;
+
+use std::fs;
+
+use tempfile::TempDir;
+
+/// Creates a directory with a `notes.txt` file and a `backups` directory
+/// holding one backup of it.
+fn setup() -> TempDir {
+    let tmp = TempDir::new().unwrap();
+    fs::create_dir(tmp.path().join("backups")).unwrap();
+    fs::write(tmp.path().join("notes.txt"), "notes").unwrap();
+    let output = common::run(tmp.path(), &["b", "notes.txt", "backups"]);
+    assert!(output.status.success(), "{output:?}");
+    tmp
+}
+
+#[test]
+fn backups_are_listed_from_the_catalog() {
+    let tmp = setup();
+    let catalog = tmp.path().join("backups/.backup-catalog.json");
+    let recorded: serde_json::Value = serde_json::from_slice(&fs::read(&catalog).unwrap()).unwrap();
+    let backups = recorded["backups"].as_array().unwrap();
+    assert_eq!(backups.len(), 1, "{recorded}");
+    assert_eq!(backups[0]["name"], "notes.txt");
+    assert_eq!(backups[0]["size"], 5);
+
+    // The catalog is trusted: a backup changed in place keeps its recorded size.
+    let backup = common::single_entry(&tmp.path().join("backups"));
+    fs::write(&backup, "notes, longer").unwrap();
+    let output = common::run(tmp.path(), &["list", "--json", "backups"]);
+    assert!(output.status.success(), "{output:?}");
+    let listed: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(listed[0]["size"], 5, "{listed}");
+    assert!(output.stderr.is_empty(), "{output:?}");
+}
+
+#[test]
+fn a_stale_catalog_is_ignored_until_rebuilt() {
+    let tmp = setup();
+    let backups = tmp.path().join("backups");
+    fs::write(backups.join("todo.txt.2024-05-01_10-00-00.backup"), "todo").unwrap();
+
+    let output = common::run(tmp.path(), &["list", "--json", "backups"]);
+    assert!(output.status.success(), "{output:?}");
+    let listed: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(listed.as_array().unwrap().len(), 2, "{listed}");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains(
+            "The catalog does not match the backups, run 'backup catalog rebuild backups'"
+        ),
+        "{stderr}"
+    );
+
+    let output = common::run(tmp.path(), &["catalog", "--json", "rebuild", "backups"]);
+    assert!(output.status.success(), "{output:?}");
+    let outcome: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(outcome["action"], "catalog");
+    assert_eq!(outcome["backups"], 2);
+
+    let output = common::run(tmp.path(), &["list", "backups"]);
+    assert!(output.status.success(), "{output:?}");
+    assert!(output.stderr.is_empty(), "{output:?}");
+
+    let output = common::run(tmp.path(), &["catalog", "backups"]);
+    assert_eq!(output.status.code(), Some(2), "{output:?}");
+}
+
+#[test]
+fn pruned_backups_leave_the_catalog() {
+    let tmp = setup();
+    let backups = tmp.path().join("backups");
+    fs::write(backups.join("notes.txt.2024-05-01_10-00-00.backup"), "old").unwrap();
+    let output = common::run(tmp.path(), &["catalog", "rebuild", "backups"]);
+    assert!(output.status.success(), "{output:?}");
+    assert!(String::from_utf8_lossy(&output.stdout).contains("2 backups cataloged"));
+
+    let output = common::run(tmp.path(), &["prune", "--keep-last", "1", "backups"]);
+    assert!(output.status.success(), "{output:?}");
+    let catalog = fs::read(backups.join(".backup-catalog.json")).unwrap();
+    let recorded: serde_json::Value = serde_json::from_slice(&catalog).unwrap();
+    let names: Vec<_> = recorded["backups"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|backup| backup["file_name"].as_str().unwrap())
+        .collect();
+    assert_eq!(names.len(), 1);
+    assert_ne!(names[0], "notes.txt.2024-05-01_10-00-00.backup");
+    assert!(!backups
+        .join("notes.txt.2024-05-01_10-00-00.backup")
+        .exists());
+}
@@ -0,0 +1,254 @@
+mod common;
+
+use std::fs;
+
+use tempfile::TempDir;
+
+#[test]
+fn help_prints_usage_and_succeeds() {
+    let tmp = TempDir::new().unwrap();
+
+    for args in [&[][..], &["help"], &["--help"]] {
+        let output = common::run(tmp.path(), args);
+        assert!(output.status.success(), "{output:?}");
+        assert!(String::from_utf8_lossy(&output.stdout).starts_with("Usage: backup"));
+    }
+}
+
+#[test]
+fn argument_counts_are_checked_per_mode() {
+    let tmp = TempDir::new().unwrap();
+
+    let cases: [(&[&str], &str); 5] = [
+        (
+            &["b"],
+            "Missing <source> argument; usage: backup backup [options] <source>... [target]",
+        ),
+        (
+            &["r"],
+            "Missing <backup> argument; usage: backup restore [options] <backup> [target]",
+        ),
+        (&["diff", "backup"], "Missing <path> argument;"),
+        (&["verify", "a", "b"], "Unexpected argument 'b';"),
+        (
+            &["history", "extra"],
+            "Unexpected argument 'extra'; usage: backup history [options]\n",
+        ),
+    ];
+    for (args, message) in cases {
+        let output = common::run(tmp.path(), args);
+        assert_eq!(output.status.code(), Some(2), "{output:?}");
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert!(stderr.contains(message), "{stderr}");
+        assert!(output.stdout.is_empty(), "{output:?}");
+    }
+
+    // Options can stand for arguments.
+    fs::write(tmp.path().join("hosts"), "hosts").unwrap();
+    let output = common::run(tmp.path(), &["b", "-t", "backups", "hosts"]);
+    assert!(output.status.success(), "{output:?}");
+    let output = common::run(tmp.path(), &["b", "-t", "backups"]);
+    assert_eq!(output.status.code(), Some(2), "{output:?}");
+
+    let output = common::run(tmp.path(), &["help", "restore"]);
+    assert!(output.status.success(), "{output:?}");
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.starts_with("Usage: backup restore "), "{stdout}");
+}
+
+#[test]
+fn unknown_mode_and_option_are_usage_errors() {
+    let tmp = TempDir::new().unwrap();
+
+    let output = common::run(tmp.path(), &["bogus"]);
+    assert_eq!(output.status.code(), Some(2), "{output:?}");
+    assert!(String::from_utf8_lossy(&output.stderr).contains("Unknown command 'bogus'\n"));
+    assert!(output.stdout.is_empty(), "{output:?}");
+
+    for (typo, suggested) in [
+        ("bakup", "backup"),
+        ("hist", "history"),
+        ("verfy", "verify"),
+    ] {
+        let output = common::run(tmp.path(), &[typo, "/etc/hosts"]);
+        assert_eq!(output.status.code(), Some(2), "{output:?}");
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert!(
+            stderr.contains(&format!(
+                "Unknown command '{typo}', did you mean '{suggested}'?"
+            )),
+            "{stderr}"
+        );
+    }
+
+    let output = common::run(tmp.path(), &["b", "--bogus", "file"]);
+    assert_eq!(output.status.code(), Some(2), "{output:?}");
+    assert!(String::from_utf8_lossy(&output.stderr).contains("Unknown option '--bogus'"));
+}
+
+#[test]
+fn invalid_option_value_is_a_usage_error() {
+    let tmp = TempDir::new().unwrap();
+    fs::create_dir(tmp.path().join("project")).unwrap();
+
+    let output = common::run(tmp.path(), &["b", "-c", "lzma", "project"]);
+    assert_eq!(output.status.code(), Some(2), "{output:?}");
+    assert_eq!(common::single_entry(tmp.path()), tmp.path().join("project"));
+}
+
+#[test]
+fn failed_operation_exits_with_one() {
+    let tmp = TempDir::new().unwrap();
+    fs::write(tmp.path().join("hosts"), "new").unwrap();
+    fs::write(tmp.path().join("hosts.copy"), "old").unwrap();
+
+    let output = common::run(tmp.path(), &["b", "hosts", "hosts.copy"]);
+    assert_eq!(output.status.code(), Some(1), "{output:?}");
+    assert!(String::from_utf8_lossy(&output.stderr).starts_with("backup: "));
+    // Output that is not a terminal is never colored.
+    assert!(!output.stderr.contains(&0x1b), "{output:?}");
+}
+
+#[test]
+fn exit_statuses_tell_partial_failures_and_skips_apart() {
+    let tmp = TempDir::new().unwrap();
+    fs::write(tmp.path().join("hosts"), "hosts").unwrap();
+
+    let output = common::run(tmp.path(), &["b", "hosts", "missing", "backups"]);
+    assert_eq!(output.status.code(), Some(4), "{output:?}");
+    let output = common::run(tmp.path(), &["b", "missing", "gone", "backups"]);
+    assert_eq!(output.status.code(), Some(1), "{output:?}");
+
+    let args = ["b", "--skip-unchanged", "hosts", "skipped"];
+    let output = common::run(tmp.path(), &args);
+    assert_eq!(output.status.code(), Some(0), "{output:?}");
+    let output = common::run(tmp.path(), &args);
+    assert_eq!(output.status.code(), Some(3), "{output:?}");
+
+    let backup = common::single_entry(&tmp.path().join("skipped"));
+    fs::write(&backup, "tampered").unwrap();
+    let output = common::run(tmp.path(), &["verify", backup.to_str().unwrap()]);
+    assert_eq!(output.status.code(), Some(5), "{output:?}");
+}
+
+#[test]
+fn modes_print_their_own_usage() {
+    let tmp = TempDir::new().unwrap();
+
+    let output = common::run(tmp.path(), &["r", "--help"]);
+    assert!(output.status.success(), "{output:?}");
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(
+        stdout.starts_with("Usage: backup restore [options] <backup> [target]"),
+        "{stdout}"
+    );
+    assert!(stdout.contains("--no-safety"), "{stdout}");
+    assert!(!stdout.contains("--incremental"), "{stdout}");
+}
+
+#[test]
+fn closed_stdout_ends_the_output_quietly() {
+    let tmp = TempDir::new().unwrap();
+    // More rows than a pipe buffers, so that writing them fails.
+    for day in 0..1000 {
+        let name = format!(
+            "notes.txt.{}-{:02}-{:02}_10-00-00.backup",
+            2000 + day / 336,
+            day / 28 % 12 + 1,
+            day % 28 + 1
+        );
+        fs::write(tmp.path().join(name), "").unwrap();
+    }
+
+    for args in [&["list", "."][..], &["help"]] {
+        let mut child = common::spawn(tmp.path(), args);
+        drop(child.stdout.take());
+        let output = child.wait_with_output().unwrap();
+        assert_eq!(output.status.code(), Some(0), "{output:?}");
+        assert!(output.stderr.is_empty(), "{output:?}");
+    }
+}
+
+#[test]
+fn options_accept_values_inline_and_follow_arguments() {
+    let tmp = TempDir::new().unwrap();
+    fs::write(tmp.path().join("-hosts"), "").unwrap();
+    fs::create_dir_all(tmp.path().join("project/target")).unwrap();
+
+    let output = common::run(
+        tmp.path(),
+        &[
+            "b",
+            "project",
+            "--exclude=target/",
+            "-fvc",
+            "gzip",
+            "backups",
+        ],
+    );
+    assert!(output.status.success(), "{output:?}");
+    let backup = common::single_entry(&tmp.path().join("backups"));
+    assert!(backup.to_str().unwrap().ends_with(".tar.gz"), "{backup:?}");
+
+    // Everything after -- is an argument, even if it looks like an option.
+    let output = common::run(tmp.path(), &["b", "--", "-hosts", "backups"]);
+    assert!(output.status.success(), "{output:?}");
+}
+
+#[test]
+fn option_errors_name_the_option() {
+    let tmp = TempDir::new().unwrap();
+
+    let cases: [(&[&str], &str); 5] = [
+        (
+            &["b", "--excldue", "x", "file"],
+            "Unknown option '--excldue', did you mean '--exclude'?",
+        ),
+        (
+            &["b", "--exclude-f", "x", "file"],
+            "Unknown option '--exclude-f', did you mean '--exclude-from'?",
+        ),
+        (
+            &["b", "--strict", "file"],
+            "Option '--strict' does not apply to backup",
+        ),
+        (
+            &["b", "--force=yes", "file"],
+            "Option '--force' takes no value",
+        ),
+        (
+            &["b", "file", "--level"],
+            "Option '--level' requires a value",
+        ),
+    ];
+    for (args, message) in cases {
+        let output = common::run(tmp.path(), args);
+        assert_eq!(output.status.code(), Some(2), "{output:?}");
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert!(stderr.contains(message), "{stderr}");
+        assert!(output.stdout.is_empty(), "{output:?}");
+    }
+}
+
+#[test]
+fn version_describes_the_build() {
+    let tmp = TempDir::new().unwrap();
+
+    for args in [&["version"][..], &["--version"], &["-V"]] {
+        let output = common::run(tmp.path(), args);
+        assert!(output.status.success(), "{output:?}");
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        let expected = format!("backup {}\n", env!("CARGO_PKG_VERSION"));
+        assert!(stdout.starts_with(&expected), "{stdout}");
+        assert!(stdout.contains("\ncommit: "), "{stdout}");
+    }
+
+    let output = common::run(tmp.path(), &["version", "--json"]);
+    assert!(output.status.success(), "{output:?}");
+    let json: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(json["version"], env!("CARGO_PKG_VERSION"));
+    assert_eq!(
+        json["compression"],
+        serde_json::json!(["none", "gzip", "zstd"])
+    );
+}
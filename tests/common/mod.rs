@@ -0,0 +1,128 @@
+//! Helpers shared by the integration tests.
+
+#![allow(dead_code)]
+
+use std::env;
+use std::ffi::OsStr;
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, Output, Stdio};
+
+/// Runs the `backup` binary with `args` from `cwd`.
+///
+/// The history is kept in a directory shared by all tests rather than in the
+/// home directory, and no configuration file is read, see [`run_with_env`].
+pub fn run(cwd: &Path, args: &[&str]) -> Output {
+    run_with_env(cwd, args, &[])
+}
+
+/// Runs the `backup` binary with `args` from `cwd`, keeping the history in
+/// the `data` directory.
+pub fn run_with_history(cwd: &Path, args: &[&str], data: &Path) -> Output {
+    run_with_env(cwd, args, &[("XDG_DATA_HOME", data.as_os_str())])
+}
+
+/// Runs the `backup` binary with `args` from `cwd` and the environment
+/// variables `vars` set, after those set for every test.
+pub fn run_with_env(cwd: &Path, args: &[&str], vars: &[(&str, &OsStr)]) -> Output {
+    command(cwd, args)
+        .envs(vars.iter().copied())
+        .output()
+        .expect("failed to run the backup binary")
+}
+
+/// Runs the `backup` binary with `args` from `cwd`, writing `input` to its
+/// stdin.
+pub fn run_with_stdin(cwd: &Path, args: &[&str], input: &[u8]) -> Output {
+    run_with_stdin_and_env(cwd, args, input, &[])
+}
+
+/// Runs the `backup` binary with `args` from `cwd` and the environment
+/// variables `vars` set, writing `input` to its stdin.
+pub fn run_with_stdin_and_env(
+    cwd: &Path,
+    args: &[&str],
+    input: &[u8],
+    vars: &[(&str, &OsStr)],
+) -> Output {
+    let mut child = command(cwd, args)
+        .envs(vars.iter().copied())
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("failed to run the backup binary");
+    // The binary may exit without reading everything, closing the pipe.
+    let _ = child.stdin.take().unwrap().write_all(input);
+    child.wait_with_output().unwrap()
+}
+
+/// Starts the `backup` binary with `args` from `cwd`, with its standard
+/// streams piped.
+pub fn spawn(cwd: &Path, args: &[&str]) -> Child {
+    command(cwd, args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("failed to run the backup binary")
+}
+
+fn command(cwd: &Path, args: &[&str]) -> Command {
+    let shared = env::temp_dir().join("backup-tests");
+    let mut command = Command::new(env!("CARGO_BIN_EXE_backup"));
+    command
+        .current_dir(cwd)
+        .args(args)
+        .env("XDG_DATA_HOME", &shared)
+        .env("XDG_CONFIG_HOME", shared.join("no-config"));
+    command
+}
+
+/// Returns the only entry inside `dir`, panicking if there is not exactly one.
+///
+/// Checksum manifests, metadata files, catalogs and links to the latest backup
+/// are not counted.
+pub fn single_entry(dir: &Path) -> PathBuf {
+    let entries: Vec<_> = fs::read_dir(dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| !is_sidecar(path))
+        .collect();
+    assert_eq!(entries.len(), 1, "expected one entry in {entries:?}");
+    entries.into_iter().next().unwrap()
+}
+
+/// Checks whether `path` is a checksum manifest, metadata or signature file
+/// or link to the latest backup written next to a backup, or the catalog of
+/// the backups.
+pub fn is_sidecar(path: &Path) -> bool {
+    let is_latest = path
+        .file_name()
+        .is_some_and(|name| name.to_string_lossy().ends_with(".latest.backup"));
+    is_latest
+        || path.extension().is_some_and(|extension| {
+            ["sha256", "blake3", "json", "sig"]
+                .map(OsStr::new)
+                .contains(&extension)
+        })
+}
+
+/// Collects every path below `root`, relative to it, in sorted order.
+pub fn tree(root: &Path) -> Vec<PathBuf> {
+    fn walk(root: &Path, dir: &Path, paths: &mut Vec<PathBuf>) {
+        for entry in fs::read_dir(dir).unwrap() {
+            let path = entry.unwrap().path();
+            paths.push(path.strip_prefix(root).unwrap().to_path_buf());
+            if path.is_dir() {
+                walk(root, &path, paths);
+            }
+        }
+    }
+
+    let mut paths = Vec::new();
+    walk(root, root, &mut paths);
+    paths.sort();
+    paths
+}
@@ -0,0 +1,123 @@
+mod common;
+
+use std::fs;
+use std::path::Path;
+use std::process::Output;
+
+use tempfile::TempDir;
+
+/// Runs the binary from `root`, with `root/config/backup/config.toml` as
+/// default configuration file.
+fn run(root: &Path, args: &[&str]) -> Output {
+    let config = root.join("config");
+    common::run_with_env(root, args, &[("XDG_CONFIG_HOME", config.as_os_str())])
+}
+
+fn setup(config: &str) -> TempDir {
+    let tmp = TempDir::new().unwrap();
+    fs::create_dir_all(tmp.path().join("config/backup")).unwrap();
+    fs::write(tmp.path().join("config/backup/config.toml"), config).unwrap();
+    fs::create_dir_all(tmp.path().join("project")).unwrap();
+    fs::write(tmp.path().join("project/main.rs"), "fn main() {}").unwrap();
+    fs::write(tmp.path().join("project/notes.tmp"), "").unwrap();
+    tmp
+}
+
+#[test]
+fn defaults_apply_to_plain_invocations() {
+    let tmp = setup("target = 'backups'\ncompress = 'gzip'\n");
+    fs::create_dir(tmp.path().join("backups")).unwrap();
+
+    let output = run(tmp.path(), &["b", "project"]);
+    assert!(output.status.success(), "{output:?}");
+    let backup = common::single_entry(&tmp.path().join("backups"));
+    assert!(backup.to_str().unwrap().ends_with(".tar.gz"), "{backup:?}");
+
+    // Flags take precedence over the defaults.
+    fs::create_dir(tmp.path().join("other")).unwrap();
+    let output = run(tmp.path(), &["b", "-c", "none", "project", "other"]);
+    assert!(output.status.success(), "{output:?}");
+    let backup = common::single_entry(&tmp.path().join("other"));
+    assert!(backup.is_dir(), "{backup:?}");
+}
+
+#[test]
+fn profiles_are_run_by_name() {
+    let tmp = setup(
+        r#"
+exclude = ["*.tmp"]
+
+[profile.project]
+source = "project"
+target = "backups"
+"#,
+    );
+    fs::create_dir(tmp.path().join("backups")).unwrap();
+
+    let output = run(tmp.path(), &["run", "project"]);
+    assert!(output.status.success(), "{output:?}");
+    let backup = common::single_entry(&tmp.path().join("backups"));
+    assert!(backup.join("main.rs").exists());
+    assert!(!backup.join("notes.tmp").exists());
+
+    let output = run(tmp.path(), &["run", "photos"]);
+    assert_eq!(output.status.code(), Some(2), "{output:?}");
+    assert!(String::from_utf8_lossy(&output.stderr).contains("Unknown profile 'photos'"));
+}
+
+#[test]
+fn errors_name_the_line_and_key() {
+    let tmp = setup("[profile.project]\nsource = 'project'\nincremental = 'yes'\n");
+
+    let output = run(tmp.path(), &["config", "check"]);
+    assert_eq!(output.status.code(), Some(2), "{output:?}");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("line 3: 'incremental' in [profile.project]: expected a boolean"),
+        "{stderr}"
+    );
+
+    // Every other mode refuses to run with an invalid configuration too.
+    let output = run(tmp.path(), &["b", "project"]);
+    assert_eq!(output.status.code(), Some(2), "{output:?}");
+}
+
+#[test]
+fn check_validates_an_explicit_file() {
+    let tmp = setup("");
+    fs::write(tmp.path().join("other.toml"), "[profile.a]\n[profile.b]\n").unwrap();
+
+    let output = run(tmp.path(), &["config", "--config", "other.toml", "check"]);
+    assert!(output.status.success(), "{output:?}");
+    assert!(String::from_utf8_lossy(&output.stdout).contains("OK, 2 profiles"));
+
+    let output = run(tmp.path(), &["b", "--config", "missing.toml", "project"]);
+    assert_eq!(output.status.code(), Some(1), "{output:?}");
+}
+
+#[test]
+fn environment_overrides_the_configuration() {
+    let tmp = setup("target = 'backups'\ncompress = 'gzip'\n");
+    fs::create_dir(tmp.path().join("backups")).unwrap();
+    fs::create_dir(tmp.path().join("env")).unwrap();
+    let config = tmp.path().join("config");
+    let vars = [
+        ("XDG_CONFIG_HOME", config.as_os_str()),
+        ("BACKUP_DIR", "env".as_ref()),
+        ("BACKUP_COMPRESS", "zstd".as_ref()),
+    ];
+
+    let output = common::run_with_env(tmp.path(), &["b", "project"], &vars);
+    assert!(output.status.success(), "{output:?}");
+    let backup = common::single_entry(&tmp.path().join("env"));
+    assert!(backup.to_str().unwrap().ends_with(".tar.zst"), "{backup:?}");
+
+    // The command line still takes precedence.
+    let output = common::run_with_env(
+        tmp.path(),
+        &["b", "-c", "none", "project", "backups"],
+        &vars,
+    );
+    assert!(output.status.success(), "{output:?}");
+    assert!(common::single_entry(&tmp.path().join("backups")).is_dir());
+}
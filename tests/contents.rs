@@ -0,0 +1,130 @@
+mod common;
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use tempfile::TempDir;
+
+fn project(root: &Path) {
+    fs::create_dir_all(root.join("project/src")).unwrap();
+    fs::write(root.join("project/src/main.rs"), "fn main() {}").unwrap();
+    fs::write(root.join("project/README"), "readme").unwrap();
+}
+
+fn back_up(root: &Path, args: &[&str], source: &str) -> PathBuf {
+    fs::create_dir_all(root.join("backups")).unwrap();
+    let args: Vec<_> = ["b"]
+        .iter()
+        .chain(args)
+        .chain(&[source, "backups"])
+        .copied()
+        .collect();
+    let output = common::run(root, &args);
+    assert!(output.status.success(), "{output:?}");
+    common::single_entry(&root.join("backups"))
+}
+
+fn list_contents(root: &Path, args: &[&str], backup: &Path) -> String {
+    let args: Vec<_> = args
+        .iter()
+        .copied()
+        .chain([backup.to_str().unwrap()])
+        .collect();
+    let output = common::run(root, &args);
+    assert!(output.status.success(), "{output:?}");
+    String::from_utf8(output.stdout).unwrap()
+}
+
+/// Takes the path column of each line, sorted.
+fn paths(listing: &str) -> Vec<&str> {
+    let mut paths: Vec<_> = listing
+        .lines()
+        .map(|line| line.split_whitespace().nth(4).unwrap())
+        .collect();
+    paths.sort();
+    paths
+}
+
+#[test]
+fn directory_backups_are_listed_like_tar() {
+    let tmp = TempDir::new().unwrap();
+    project(tmp.path());
+    let backup = back_up(tmp.path(), &[], "project");
+
+    let listing = list_contents(tmp.path(), &["list-contents"], &backup);
+    assert_eq!(
+        paths(&listing),
+        ["./", "README", "src/", "src/main.rs"],
+        "{listing}"
+    );
+    let readme = listing
+        .lines()
+        .find(|line| line.ends_with("README"))
+        .unwrap();
+    let columns: Vec<_> = readme.split_whitespace().collect();
+    assert!(columns[0].starts_with('-'), "{readme}");
+    assert_eq!(columns[1], "6");
+
+    // Restoring with --list only lists.
+    let restore = list_contents(tmp.path(), &["r", "--list"], &backup);
+    assert_eq!(restore, listing);
+    assert!(!tmp.path().join("backups/project").exists());
+}
+
+#[test]
+fn archives_are_listed_from_their_entries() {
+    for compression in ["gzip", "zstd", "none"] {
+        let tmp = TempDir::new().unwrap();
+        project(tmp.path());
+        let backup = back_up(tmp.path(), &["-c", compression], "project");
+
+        let listing = list_contents(tmp.path(), &["list-contents"], &backup);
+        assert_eq!(
+            paths(&listing),
+            ["./", "README", "src/", "src/main.rs"],
+            "{compression}: {listing}"
+        );
+    }
+}
+
+#[test]
+fn file_backups_are_listed_with_their_metadata() {
+    let tmp = TempDir::new().unwrap();
+    project(tmp.path());
+    let backup = back_up(tmp.path(), &[], "project/README");
+
+    let listing = list_contents(tmp.path(), &["list-contents"], &backup);
+    let lines: Vec<_> = listing.lines().collect();
+    assert!(lines[0].ends_with(" README"), "{listing}");
+    let source = tmp.path().canonicalize().unwrap().join("project/README");
+    assert!(lines.contains(&format!("source: {}", source.display()).as_str()));
+    assert!(lines
+        .iter()
+        .any(|line| line.starts_with("checksum: sha256:")));
+}
+
+#[test]
+fn contents_can_be_listed_as_json() {
+    let tmp = TempDir::new().unwrap();
+    project(tmp.path());
+    let backup = back_up(tmp.path(), &["-c", "gzip"], "project");
+
+    let listing = list_contents(tmp.path(), &["list-contents", "--json"], &backup);
+    let entries: serde_json::Value = serde_json::from_str(&listing).unwrap();
+    let main = entries
+        .as_array()
+        .unwrap()
+        .iter()
+        .find(|entry| entry["path"] == "src/main.rs")
+        .unwrap();
+    assert_eq!(main["kind"], "file");
+    assert_eq!(main["size"], 12);
+}
+
+#[test]
+fn missing_backups_fail() {
+    let tmp = TempDir::new().unwrap();
+
+    let output = common::run(tmp.path(), &["list-contents", "missing"]);
+    assert_eq!(output.status.code(), Some(1));
+}
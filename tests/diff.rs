@@ -0,0 +1,128 @@
+mod common;
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use tempfile::TempDir;
+
+fn project(root: &Path) {
+    fs::create_dir_all(root.join("project/src/old")).unwrap();
+    fs::write(root.join("project/src/main.rs"), "fn main() {}").unwrap();
+    fs::write(root.join("project/src/old/lib.rs"), "").unwrap();
+    fs::write(root.join("project/README"), "readme").unwrap();
+}
+
+fn back_up(root: &Path, args: &[&str]) -> PathBuf {
+    fs::create_dir_all(root.join("backups")).unwrap();
+    let args: Vec<_> = ["b"]
+        .iter()
+        .chain(args)
+        .chain(&["project", "backups"])
+        .copied()
+        .collect();
+    let output = common::run(root, &args);
+    assert!(output.status.success(), "{output:?}");
+    common::single_entry(&root.join("backups"))
+}
+
+fn diff(root: &Path, backup: &Path, options: &[&str]) -> (Option<i32>, String) {
+    let backup = backup.to_str().unwrap();
+    let args: Vec<_> = ["diff"]
+        .iter()
+        .chain(options)
+        .chain(&[backup, "project"])
+        .copied()
+        .collect();
+    let output = common::run(root, &args);
+    let stdout = String::from_utf8_lossy(&output.stdout).into_owned();
+    (output.status.code(), stdout)
+}
+
+fn change_project(root: &Path) {
+    fs::write(root.join("project/README"), "new readme").unwrap();
+    fs::remove_dir_all(root.join("project/src/old")).unwrap();
+    fs::write(root.join("project/NOTES"), "notes").unwrap();
+}
+
+#[test]
+fn identical_copy_has_no_differences() {
+    let tmp = TempDir::new().unwrap();
+    project(tmp.path());
+    let backup = back_up(tmp.path(), &[]);
+
+    assert_eq!(diff(tmp.path(), &backup, &[]), (Some(0), String::new()));
+}
+
+#[test]
+fn the_latest_link_is_compared_as_the_backup() {
+    let tmp = TempDir::new().unwrap();
+    project(tmp.path());
+    back_up(tmp.path(), &[]);
+    let link = tmp.path().join("backups/project.latest.backup");
+
+    assert_eq!(diff(tmp.path(), &link, &[]), (Some(0), String::new()));
+}
+
+#[test]
+fn changes_are_listed_like_diff() {
+    let tmp = TempDir::new().unwrap();
+    project(tmp.path());
+    let backup = back_up(tmp.path(), &[]);
+    change_project(tmp.path());
+
+    let (status, stdout) = diff(tmp.path(), &backup, &[]);
+    assert_eq!(status, Some(1));
+    let backup = backup.display();
+    assert_eq!(
+        stdout.lines().collect::<Vec<_>>(),
+        [
+            "Only in project: NOTES".to_owned(),
+            format!("Files {backup}/README and project/README differ"),
+            format!("Only in {backup}/src: old"),
+        ]
+    );
+}
+
+#[test]
+fn archives_are_compared_through_their_listing() {
+    let tmp = TempDir::new().unwrap();
+    project(tmp.path());
+    let backup = back_up(tmp.path(), &["-c", "gzip"]);
+    assert_eq!(diff(tmp.path(), &backup, &[]).0, Some(0));
+    assert_eq!(diff(tmp.path(), &backup, &["--checksum"]).0, Some(0));
+
+    change_project(tmp.path());
+    let (status, stdout) = diff(tmp.path(), &backup, &["--json"]);
+    assert_eq!(status, Some(1));
+    let differences: serde_json::Value = serde_json::from_str(&stdout).unwrap();
+    assert_eq!(
+        differences,
+        serde_json::json!([
+            {"path": "NOTES", "change": "added"},
+            {"path": "README", "change": "modified"},
+            {"path": "src/old", "change": "removed"},
+        ])
+    );
+}
+
+#[test]
+fn checksums_ignore_touched_files() {
+    let tmp = TempDir::new().unwrap();
+    project(tmp.path());
+    let backup = back_up(tmp.path(), &[]);
+    let touched = filetime::FileTime::from_unix_time(1_000_000_000, 0);
+    filetime::set_file_mtime(tmp.path().join("project/README"), touched).unwrap();
+
+    assert_eq!(diff(tmp.path(), &backup, &[]).0, Some(1));
+    assert_eq!(diff(tmp.path(), &backup, &["--checksum"]).0, Some(0));
+}
+
+#[test]
+fn errors_exit_with_two() {
+    let tmp = TempDir::new().unwrap();
+    project(tmp.path());
+
+    assert_eq!(diff(tmp.path(), Path::new("missing"), &[]).0, Some(2));
+    let output = common::run(tmp.path(), &["diff", "project"]);
+    assert_eq!(output.status.code(), Some(2), "{output:?}");
+}
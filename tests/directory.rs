@@ -0,0 +1,383 @@
+mod common;
+
+use std::fs;
+use std::path::Path;
+
+use tempfile::TempDir;
+
+/// Builds a tree with several levels, files at each level and empty directories.
+fn nested_tree(root: &Path) {
+    fs::create_dir_all(root.join("a/b/c/d")).unwrap();
+    fs::create_dir_all(root.join("empty")).unwrap();
+    fs::create_dir_all(root.join("a/b/empty")).unwrap();
+    fs::write(root.join("top.txt"), "top").unwrap();
+    fs::write(root.join("a/one.txt"), "one").unwrap();
+    fs::write(root.join("a/b/two.txt"), "two").unwrap();
+    fs::write(root.join("a/b/c/d/four.bin"), [0u8, 1, 2, 3, 255]).unwrap();
+}
+
+#[test]
+fn directory_backup_copies_whole_tree() {
+    let tmp = TempDir::new().unwrap();
+    let source = tmp.path().join("project");
+    let target = tmp.path().join("backups");
+    nested_tree(&source);
+    fs::create_dir(&target).unwrap();
+
+    let output = common::run(tmp.path(), &["b", "project", "backups"]);
+    assert!(output.status.success(), "{output:?}");
+
+    let backup = common::single_entry(&target);
+    let name = backup.file_name().unwrap().to_str().unwrap();
+    assert!(name.starts_with("project.") && name.ends_with(".backup"));
+    assert_eq!(common::tree(&source), common::tree(&backup));
+    assert!(backup.join("empty").is_dir());
+    assert!(backup.join("a/b/empty").is_dir());
+    assert_eq!(
+        fs::read(backup.join("a/b/c/d/four.bin")).unwrap(),
+        [0, 1, 2, 3, 255]
+    );
+    assert_eq!(
+        fs::read_to_string(backup.join("a/b/two.txt")).unwrap(),
+        "two"
+    );
+}
+
+#[test]
+fn directory_backup_restores_whole_tree() {
+    let tmp = TempDir::new().unwrap();
+    let source = tmp.path().join("project");
+    nested_tree(&source);
+
+    let output = common::run(tmp.path(), &["b", "project"]);
+    assert!(output.status.success(), "{output:?}");
+    fs::rename(&source, tmp.path().join("original")).unwrap();
+
+    let backup = fs::read_dir(tmp.path())
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .find(|path| path.extension().is_some_and(|ext| ext == "backup"))
+        .unwrap();
+    let output = common::run(tmp.path(), &["r", backup.to_str().unwrap()]);
+    assert!(output.status.success(), "{output:?}");
+
+    assert_eq!(
+        common::tree(&source),
+        common::tree(&tmp.path().join("original"))
+    );
+}
+
+#[cfg(unix)]
+#[test]
+fn unreadable_entries_are_reported_by_path() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let tmp = TempDir::new().unwrap();
+    let source = tmp.path().join("project");
+    let target = tmp.path().join("backups");
+    nested_tree(&source);
+    fs::create_dir(&target).unwrap();
+    let locked = source.join("a/locked.txt");
+    fs::write(&locked, "secret").unwrap();
+    fs::set_permissions(&locked, fs::Permissions::from_mode(0o000)).unwrap();
+    if fs::read(&locked).is_ok() {
+        // Running with privileges that bypass permission checks.
+        return;
+    }
+
+    let output = common::run(tmp.path(), &["b", "project", "backups"]);
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("locked.txt"), "{stderr}");
+
+    let backup = common::single_entry(&target);
+    assert!(backup.join("a/b/c/d/four.bin").is_file());
+}
+
+#[test]
+fn directory_to_file_round_trips_through_tarball() {
+    let tmp = TempDir::new().unwrap();
+    let source = tmp.path().join("project");
+    let archive = tmp.path().join("project.2024-05-01_10-00-00.backup");
+    nested_tree(&source);
+    fs::write(&archive, "").unwrap();
+
+    let output = common::run(
+        tmp.path(),
+        &["b", "--force", "project", archive.to_str().unwrap()],
+    );
+    assert!(output.status.success(), "{output:?}");
+    assert!(archive.is_file());
+    assert_eq!(&fs::read(&archive).unwrap()[257..262], b"ustar");
+
+    fs::rename(&source, tmp.path().join("original")).unwrap();
+    let output = common::run(tmp.path(), &["r", archive.to_str().unwrap()]);
+    assert!(output.status.success(), "{output:?}");
+
+    let original = tmp.path().join("original");
+    assert_eq!(common::tree(&source), common::tree(&original));
+    for path in common::tree(&original) {
+        if original.join(&path).is_file() {
+            assert_eq!(
+                fs::read(source.join(&path)).unwrap(),
+                fs::read(original.join(&path)).unwrap()
+            );
+        }
+    }
+}
+
+#[test]
+fn gzip_archive_round_trips() {
+    let tmp = TempDir::new().unwrap();
+    let source = tmp.path().join("project");
+    let archive = tmp.path().join("project.2024-05-01_10-00-00.backup");
+    nested_tree(&source);
+    fs::write(&archive, "").unwrap();
+
+    let args = ["b", "-f", "--compress", "gzip", "--level", "9", "project"];
+    let output = common::run(
+        tmp.path(),
+        &[&args[..], &[archive.to_str().unwrap()]].concat(),
+    );
+    assert!(output.status.success(), "{output:?}");
+    assert_eq!(&fs::read(&archive).unwrap()[..2], [0x1f, 0x8b]);
+
+    fs::rename(&source, tmp.path().join("original")).unwrap();
+    let output = common::run(tmp.path(), &["r", archive.to_str().unwrap()]);
+    assert!(output.status.success(), "{output:?}");
+    assert_eq!(
+        common::tree(&source),
+        common::tree(&tmp.path().join("original"))
+    );
+}
+
+#[test]
+fn corrupted_gzip_archive_is_reported() {
+    let tmp = TempDir::new().unwrap();
+    let archive = tmp.path().join("project.2024-05-01_10-00-00.backup");
+    fs::write(&archive, [0x1f, 0x8b, 0x08, 0x00, 0xde, 0xad, 0xbe, 0xef]).unwrap();
+
+    let output = common::run(tmp.path(), &["r", archive.to_str().unwrap()]);
+    assert_eq!(output.status.code(), Some(1));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("project.2024-05-01_10-00-00.backup"),
+        "{stderr}"
+    );
+    assert!(!tmp.path().join("project").exists());
+}
+
+#[test]
+fn zstd_archive_defaults_to_timestamped_name_and_round_trips() {
+    let tmp = TempDir::new().unwrap();
+    let source = tmp.path().join("project");
+    let target = tmp.path().join("backups");
+    nested_tree(&source);
+    fs::create_dir(&target).unwrap();
+
+    let output = common::run(tmp.path(), &["b", "-c", "zstd", "project", "backups"]);
+    assert!(output.status.success(), "{output:?}");
+
+    let archive = common::single_entry(&target);
+    let name = archive.file_name().unwrap().to_str().unwrap();
+    assert!(name.starts_with("project.") && name.ends_with(".backup.tar.zst"));
+    assert_eq!(&fs::read(&archive).unwrap()[..4], [0x28, 0xb5, 0x2f, 0xfd]);
+
+    let output = common::run(tmp.path(), &["r", archive.to_str().unwrap()]);
+    assert!(output.status.success(), "{output:?}");
+    assert_eq!(common::tree(&source), common::tree(&target.join("project")));
+}
+
+#[test]
+fn dry_run_reports_plan_without_creating_anything() {
+    let tmp = TempDir::new().unwrap();
+    let source = tmp.path().join("project");
+    let target = tmp.path().join("backups");
+    nested_tree(&source);
+    fs::create_dir(&target).unwrap();
+
+    let output = common::run(tmp.path(), &["b", "-n", "-c", "gzip", "project", "backups"]);
+    assert!(output.status.success(), "{output:?}");
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("type: DirectoryDirectory"), "{stdout}");
+    assert!(stdout.contains("destination: backups/project."), "{stdout}");
+    assert!(stdout.contains(".backup.tar.gz"), "{stdout}");
+    assert!(stdout.contains("files: 4 (14 B)"), "{stdout}");
+    assert!(stdout.contains("destination exists: no"), "{stdout}");
+    assert_eq!(fs::read_dir(&target).unwrap().count(), 0);
+}
+
+#[test]
+fn verbose_logs_each_file_and_a_summary() {
+    let tmp = TempDir::new().unwrap();
+    nested_tree(&tmp.path().join("project"));
+
+    let output = common::run(tmp.path(), &["b", "--verbose", "project"]);
+    assert!(output.status.success(), "{output:?}");
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("project/a/b/two.txt (3 B)"), "{stderr}");
+    assert!(
+        stderr.contains("project/a/b/c/d/four.bin (5 B)"),
+        "{stderr}"
+    );
+    assert!(stderr.contains("backed up 4 files (14 B) to"), "{stderr}");
+    assert_eq!(String::from_utf8_lossy(&output.stdout).lines().count(), 1);
+}
+
+#[test]
+fn backup_prints_only_its_path() {
+    let tmp = TempDir::new().unwrap();
+    nested_tree(&tmp.path().join("project"));
+
+    fs::create_dir(tmp.path().join("backups")).unwrap();
+
+    let output = common::run(tmp.path(), &["b", "project", "backups"]);
+    assert!(output.status.success(), "{output:?}");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.starts_with("4 files processed, 14 B read"),
+        "{stderr}"
+    );
+    assert_eq!(stderr.lines().count(), 1, "{stderr}");
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let backup = common::single_entry(&tmp.path().join("backups"));
+    assert_eq!(stdout, format!("{}\n", backup.display()));
+    assert!(Path::new(stdout.trim_end()).is_absolute());
+
+    let output = common::run(tmp.path(), &["r", stdout.trim_end(), "restored"]);
+    assert!(output.status.success(), "{output:?}");
+    let restored = tmp.path().join("restored");
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        format!("{}\n", restored.display())
+    );
+}
+
+#[test]
+fn backup_into_the_source_is_refused() {
+    let tmp = TempDir::new().unwrap();
+    let source = tmp.path().join("project");
+    nested_tree(&source);
+    fs::create_dir(source.join("backups")).unwrap();
+
+    let output = common::run(tmp.path(), &["b", "project", "project/backups"]);
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    let canonical = fs::canonicalize(&source).unwrap();
+    assert!(stderr.contains(&*canonical.to_string_lossy()), "{stderr}");
+    assert!(stderr.contains(&*canonical.join("backups").to_string_lossy()));
+    assert_eq!(fs::read_dir(source.join("backups")).unwrap().count(), 0);
+
+    let output = common::run(&source, &["b", "."]);
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("into itself"));
+}
+
+#[test]
+fn parallel_copies_match_sequential_ones() {
+    let tmp = TempDir::new().unwrap();
+    let source = tmp.path().join("project");
+    nested_tree(&source);
+    for i in 0..200 {
+        let dir = source.join(format!("a/many/{}", i % 7));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join(format!("{i}.txt")), i.to_string()).unwrap();
+    }
+
+    let mut manifests = Vec::new();
+    for jobs in ["1", "4"] {
+        let target = tmp.path().join(format!("jobs-{jobs}"));
+        let args = [
+            "b",
+            "-v",
+            "--jobs",
+            jobs,
+            "project",
+            target.to_str().unwrap(),
+        ];
+        let output = common::run(tmp.path(), &args);
+        assert!(output.status.success(), "{output:?}");
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert!(stderr.contains("backed up 204 files"), "{stderr}");
+
+        let backup = common::single_entry(&target);
+        assert_eq!(common::tree(&source), common::tree(&backup));
+        let manifest = fs::read_to_string(backup.with_extension("backup.sha256")).unwrap();
+        let name = backup.file_name().unwrap().to_str().unwrap().to_owned();
+        manifests.push(manifest.replace(&name, "project.backup"));
+    }
+    assert_eq!(manifests[0], manifests[1]);
+}
+
+#[test]
+fn zero_jobs_is_rejected() {
+    let tmp = TempDir::new().unwrap();
+    nested_tree(&tmp.path().join("project"));
+
+    let output = common::run(tmp.path(), &["b", "--jobs", "0", "project"]);
+    assert_eq!(output.status.code(), Some(2), "{output:?}");
+}
+
+#[cfg(unix)]
+#[test]
+fn failed_entries_are_summarized_at_the_end() {
+    let tmp = TempDir::new().unwrap();
+    let source = tmp.path().join("project");
+    let target = tmp.path().join("backups");
+    nested_tree(&source);
+    std::os::unix::fs::symlink("missing", source.join("a/b/dangling")).unwrap();
+
+    let output = common::run(tmp.path(), &["b", "-L", "project", "backups"]);
+    assert_eq!(output.status.code(), Some(4), "{output:?}");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    let summary = stderr
+        .split_once("could not be backed up:")
+        .map(|(_, summary)| summary)
+        .unwrap_or_default();
+    assert!(summary.contains("dangling"), "{stderr}");
+    let backup = common::single_entry(&target);
+    assert!(backup.join("a/b/c/d/four.bin").is_file());
+
+    fs::remove_dir_all(&target).unwrap();
+    let output = common::run(
+        tmp.path(),
+        &["b", "-L", "--fail-fast", "project", "backups"],
+    );
+    assert_eq!(output.status.code(), Some(1), "{output:?}");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert_eq!(stderr.matches("dangling").count(), 1, "{stderr}");
+    assert_eq!(fs::read_dir(&target).unwrap().count(), 0);
+}
+
+#[cfg(unix)]
+#[test]
+fn missing_entries_are_not_retried() {
+    let tmp = TempDir::new().unwrap();
+    nested_tree(&tmp.path().join("project"));
+    std::os::unix::fs::symlink("missing", tmp.path().join("project/a/dangling")).unwrap();
+
+    let output = common::run(
+        tmp.path(),
+        &[
+            "b",
+            "-v",
+            "-L",
+            "--retries",
+            "3",
+            "--retry-delay",
+            "1h",
+            "project",
+            "backups",
+        ],
+    );
+    assert_eq!(output.status.code(), Some(4), "{output:?}");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("dangling"), "{stderr}");
+    assert!(!stderr.contains("retrying"), "{stderr}");
+    assert!(!stderr.contains("needed retries"), "{stderr}");
+
+    let output = common::run(tmp.path(), &["b", "--retry-delay", "soon", "project"]);
+    assert_eq!(output.status.code(), Some(2), "{output:?}");
+}
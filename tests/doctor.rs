@@ -0,0 +1,85 @@
+mod common;
+
+use std::fs;
+
+use tempfile::TempDir;
+
+/// Returns the status of the check `name` in the JSON output of doctor.
+fn status<'a>(outcome: &'a serde_json::Value, name: &str) -> &'a str {
+    let checks = outcome["checks"].as_array().unwrap();
+    let check = checks.iter().find(|check| check["name"] == name).unwrap();
+    check["status"].as_str().unwrap()
+}
+
+#[test]
+fn a_healthy_directory_passes() {
+    let tmp = TempDir::new().unwrap();
+    fs::create_dir(tmp.path().join("backups")).unwrap();
+    fs::write(tmp.path().join("notes.txt"), "notes").unwrap();
+    let output = common::run(tmp.path(), &["b", "notes.txt", "backups"]);
+    assert!(output.status.success(), "{output:?}");
+
+    let output = common::run(tmp.path(), &["doctor", "--json", "backups"]);
+    let outcome: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(outcome["action"], "doctor");
+    for name in [
+        "writable",
+        "free-space",
+        "clock",
+        "partial-files",
+        "locks",
+        "config",
+    ] {
+        assert_eq!(status(&outcome, name), "pass", "{name}: {outcome}");
+    }
+    // The filesystem of the temporary directory may lack some features.
+    let warned = outcome["checks"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .any(|check| check["status"] != "pass");
+    let expected = if warned { 4 } else { 0 };
+    assert_eq!(output.status.code(), Some(expected), "{output:?}");
+    assert!(fs::read_dir(tmp.path().join("backups"))
+        .unwrap()
+        .flatten()
+        .all(|entry| !entry.file_name().to_string_lossy().contains("doctor")));
+}
+
+#[test]
+fn leftovers_and_future_backups_are_warned_about() {
+    let tmp = TempDir::new().unwrap();
+    let backups = tmp.path().join("backups");
+    fs::create_dir(&backups).unwrap();
+    fs::write(backups.join(".data.partial"), "half").unwrap();
+    fs::write(backups.join(".data.backup.lock"), "4294967295").unwrap();
+    fs::write(backups.join("notes.txt.2999-01-01_00-00-00.backup"), "x").unwrap();
+
+    let output = common::run(tmp.path(), &["doctor", "backups"]);
+    assert_eq!(output.status.code(), Some(4), "{output:?}");
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    for line in [
+        "partial files left by interrupted backups: .data.partial",
+        "1 stale locks: .data.backup.lock",
+        "1 backups are dated in the future",
+    ] {
+        assert!(stdout.contains(line), "{line} in {stdout}");
+    }
+    assert!(stdout.contains("hint: Remove them with 'backup gc' once no backup is running"));
+    assert!(backups.join(".data.backup.lock").exists());
+}
+
+#[test]
+fn an_invalid_configuration_fails() {
+    let tmp = TempDir::new().unwrap();
+    fs::write(tmp.path().join("config.toml"), "[profile.photos\n").unwrap();
+
+    let output = common::run(tmp.path(), &["doctor", "--json", "--config", "config.toml"]);
+    assert_eq!(output.status.code(), Some(1), "{output:?}");
+    let outcome: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(status(&outcome, "config"), "fail", "{outcome}");
+    assert_eq!(status(&outcome, "writable"), "pass", "{outcome}");
+
+    let output = common::run(tmp.path(), &["doctor", "missing"]);
+    assert_eq!(output.status.code(), Some(1), "{output:?}");
+}
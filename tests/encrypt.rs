@@ -0,0 +1,254 @@
+mod common;
+
+use std::ffi::OsStr;
+use std::fs;
+use std::path::Path;
+
+use backup::meta;
+use tempfile::TempDir;
+
+const IDENTITY: &str = "AGE-SECRET-KEY-1GFPYYSJZGFPYYSJZGFPYYSJZGFPYYSJZGFPYYSJZGFPYYSJZGFPQ4EGAEX";
+const RECIPIENT: &str = "age1zvkyg2lqzraa2lnjvqej32nkuu0ues2s82hzrye869xeexvn73equnujwj";
+const OTHER_IDENTITY: &str =
+    "AGE-SECRET-KEY-1QURSWPC8QURSWPC8QURSWPC8QURSWPC8QURSWPC8QURSWPC8QURSKMP32K";
+
+/// Backs up the directory `data` of `root`, holding a secret file, encrypted
+/// to [`RECIPIENT`], and returns the path of the archive.
+fn encrypted_backup(root: &Path, args: &[&str]) -> std::path::PathBuf {
+    fs::create_dir(root.join("data")).unwrap();
+    fs::write(root.join("data/secret.txt"), "attack at dawn").unwrap();
+    let mut all = vec!["b", "--encrypt", "--recipient", RECIPIENT];
+    all.extend_from_slice(args);
+    all.extend(["data", "backups"]);
+    let output = common::run(root, &all);
+    assert!(output.status.success(), "{output:?}");
+    common::single_entry(&root.join("backups"))
+}
+
+#[test]
+fn encrypted_archives_restore_with_the_identity() {
+    let tmp = TempDir::new().unwrap();
+    let archive = encrypted_backup(tmp.path(), &["-c", "zstd"]);
+    let name = archive.file_name().unwrap().to_string_lossy().into_owned();
+    assert!(name.ends_with(".backup.tar.zst.age"), "{name}");
+    let contents = fs::read(&archive).unwrap();
+    assert!(contents.starts_with(b"age-encryption.org/v1\n"));
+    let meta = fs::read_to_string(meta::path(&archive)).unwrap();
+    assert!(!meta.contains(RECIPIENT), "{meta}");
+
+    fs::write(
+        tmp.path().join("key.txt"),
+        format!("# public key: {RECIPIENT}\n{IDENTITY}\n"),
+    )
+    .unwrap();
+    fs::create_dir(tmp.path().join("restored")).unwrap();
+    let output = common::run(
+        tmp.path(),
+        &[
+            "r",
+            "--identity",
+            "key.txt",
+            archive.to_str().unwrap(),
+            "restored",
+        ],
+    );
+    assert!(output.status.success(), "{output:?}");
+    assert_eq!(
+        fs::read_to_string(tmp.path().join("restored/data/secret.txt")).unwrap(),
+        "attack at dawn"
+    );
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(!stderr.contains("GFPYY"), "{stderr}");
+}
+
+#[test]
+fn identities_are_taken_from_the_environment_or_key_files() {
+    let tmp = TempDir::new().unwrap();
+    let archive = encrypted_backup(tmp.path(), &[]);
+    assert!(archive.to_string_lossy().ends_with(".backup.tar.age"));
+
+    let output = common::run_with_env(
+        tmp.path(),
+        &["r", archive.to_str().unwrap(), "plain"],
+        &[("AGE_IDENTITY", OsStr::new(IDENTITY))],
+    );
+    assert!(output.status.success(), "{output:?}");
+    assert_eq!(
+        fs::read_to_string(tmp.path().join("plain/secret.txt")).unwrap(),
+        "attack at dawn"
+    );
+
+    fs::write(tmp.path().join("key.txt"), IDENTITY).unwrap();
+    let input = fs::read(&archive).unwrap();
+    let output = common::run_with_stdin(
+        tmp.path(),
+        &["r", "--identity", "key.txt", "-", "streamed"],
+        &input,
+    );
+    assert!(output.status.success(), "{output:?}");
+    assert_eq!(
+        fs::read_to_string(tmp.path().join("streamed/secret.txt")).unwrap(),
+        "attack at dawn"
+    );
+}
+
+#[test]
+fn restoring_without_a_matching_identity_fails() {
+    let tmp = TempDir::new().unwrap();
+    let archive = encrypted_backup(tmp.path(), &["-c", "gzip"]);
+
+    let output = common::run(tmp.path(), &["r", archive.to_str().unwrap(), "restored"]);
+    assert_eq!(output.status.code(), Some(1), "{output:?}");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("--identity"), "{stderr}");
+
+    fs::write(tmp.path().join("other.txt"), OTHER_IDENTITY).unwrap();
+    let output = common::run(
+        tmp.path(),
+        &[
+            "r",
+            "--identity",
+            "other.txt",
+            archive.to_str().unwrap(),
+            "restored",
+        ],
+    );
+    assert!(!output.status.success(), "{output:?}");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("none of the 1 identities"), "{stderr}");
+    assert!(!tmp.path().join("restored").exists());
+}
+
+#[test]
+fn encryption_needs_recipients_and_a_directory_archive() {
+    let tmp = TempDir::new().unwrap();
+    fs::write(tmp.path().join("notes.txt"), "notes").unwrap();
+
+    let output = common::run(tmp.path(), &["b", "--encrypt", "notes.txt", "backups"]);
+    assert_eq!(output.status.code(), Some(2), "{output:?}");
+
+    let output = common::run(
+        tmp.path(),
+        &[
+            "b",
+            "--encrypt",
+            "--recipient",
+            "age1nope",
+            "notes.txt",
+            "backups",
+        ],
+    );
+    assert_eq!(output.status.code(), Some(2), "{output:?}");
+
+    let output = common::run(
+        tmp.path(),
+        &[
+            "b",
+            "--encrypt",
+            "--recipient",
+            RECIPIENT,
+            "notes.txt",
+            "backups",
+        ],
+    );
+    assert_eq!(output.status.code(), Some(2), "{output:?}");
+    assert!(!tmp.path().join("backups").exists());
+}
+
+#[test]
+fn passphrases_encrypt_and_decrypt_archives() {
+    let tmp = TempDir::new().unwrap();
+    fs::create_dir(tmp.path().join("data")).unwrap();
+    fs::write(tmp.path().join("data/secret.txt"), "attack at dawn").unwrap();
+    fs::write(tmp.path().join("pass.txt"), "correct horse\n").unwrap();
+    let output = common::run(
+        tmp.path(),
+        &[
+            "b",
+            "--encrypt-passphrase",
+            "--passphrase-file",
+            "pass.txt",
+            "--work-factor",
+            "2",
+            "data",
+            "backups",
+        ],
+    );
+    assert!(output.status.success(), "{output:?}");
+    let archive = common::single_entry(&tmp.path().join("backups"));
+    assert!(archive.to_string_lossy().ends_with(".backup.tar.age"));
+    let archive = archive.to_str().unwrap();
+
+    let output = common::run_with_env(
+        tmp.path(),
+        &["r", archive, "restored"],
+        &[("BACKUP_PASSPHRASE", OsStr::new("correct horse"))],
+    );
+    assert!(output.status.success(), "{output:?}");
+    assert_eq!(
+        fs::read_to_string(tmp.path().join("restored/secret.txt")).unwrap(),
+        "attack at dawn"
+    );
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("BACKUP_PASSPHRASE can be read"), "{stderr}");
+    assert!(!stderr.contains("horse"), "{stderr}");
+
+    let output = common::run_with_env(
+        tmp.path(),
+        &["r", archive, "wrong"],
+        &[("BACKUP_PASSPHRASE", OsStr::new("wrong horse"))],
+    );
+    assert_eq!(output.status.code(), Some(1), "{output:?}");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("Wrong passphrase"), "{stderr}");
+
+    let output = common::run(tmp.path(), &["r", archive, "missing"]);
+    assert!(!output.status.success(), "{output:?}");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("--passphrase-file"), "{stderr}");
+
+    let mut corrupted = fs::read(archive).unwrap();
+    let last = corrupted.len() - 1;
+    corrupted[last] ^= 1;
+    let output = common::run_with_stdin(
+        tmp.path(),
+        &["r", "--passphrase-file", "pass.txt", "-", "corrupted"],
+        &corrupted,
+    );
+    assert!(!output.status.success(), "{output:?}");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(!stderr.contains("Wrong passphrase"), "{stderr}");
+    assert!(stderr.contains("modified"), "{stderr}");
+}
+
+#[test]
+fn passphrases_need_a_terminal_or_a_file() {
+    let tmp = TempDir::new().unwrap();
+    fs::create_dir(tmp.path().join("data")).unwrap();
+
+    let output = common::run(
+        tmp.path(),
+        &["b", "--encrypt-passphrase", "data", "backups"],
+    );
+    assert_eq!(output.status.code(), Some(2), "{output:?}");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("No terminal"), "{stderr}");
+
+    fs::write(tmp.path().join("pass.txt"), "secret").unwrap();
+    let output = common::run(
+        tmp.path(),
+        &[
+            "b",
+            "--encrypt-passphrase",
+            "--passphrase-file",
+            "pass.txt",
+            "--encrypt",
+            "--recipient",
+            RECIPIENT,
+            "data",
+            "backups",
+        ],
+    );
+    assert_eq!(output.status.code(), Some(2), "{output:?}");
+    assert!(!tmp.path().join("backups").exists());
+}
@@ -0,0 +1,221 @@
+mod common;
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use tempfile::TempDir;
+
+/// Builds a project with build output, dependencies and temporary files.
+fn project(root: &Path) {
+    fs::create_dir_all(root.join("src/target")).unwrap();
+    fs::create_dir_all(root.join("target/debug")).unwrap();
+    fs::create_dir_all(root.join("web/node_modules/left-pad")).unwrap();
+    fs::write(root.join("Cargo.toml"), "[package]").unwrap();
+    fs::write(root.join("src/main.rs"), "fn main() {}").unwrap();
+    fs::write(root.join("src/scratch.tmp"), "scratch").unwrap();
+    fs::write(
+        root.join("src/target/notes"),
+        "a file in a dir named target",
+    )
+    .unwrap();
+    fs::write(root.join("target/debug/app"), "binary").unwrap();
+    fs::write(root.join("web/node_modules/left-pad/index.js"), "pad").unwrap();
+}
+
+fn backup_of(dir: &Path) -> PathBuf {
+    fs::read_dir(dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .find(|path| path.to_string_lossy().contains(".backup") && !common::is_sidecar(path))
+        .unwrap()
+}
+
+#[test]
+fn excluded_entries_are_left_out_of_a_copy() {
+    let tmp = TempDir::new().unwrap();
+    project(&tmp.path().join("project"));
+
+    let output = common::run(
+        tmp.path(),
+        &[
+            "b",
+            "--exclude",
+            "/target/",
+            "--exclude",
+            "node_modules/",
+            "--exclude",
+            "*.tmp",
+            "project",
+        ],
+    );
+    assert!(output.status.success(), "{output:?}");
+
+    let backup = backup_of(tmp.path());
+    assert_eq!(
+        common::tree(&backup),
+        [
+            "Cargo.toml",
+            "src",
+            "src/main.rs",
+            "src/target",
+            "src/target/notes",
+            "web",
+        ]
+        .map(PathBuf::from)
+    );
+}
+
+#[test]
+fn excluded_entries_are_left_out_of_an_archive() {
+    let tmp = TempDir::new().unwrap();
+    project(&tmp.path().join("project"));
+
+    let output = common::run(
+        tmp.path(),
+        &["b", "-c", "gzip", "--exclude", "**/target", "project"],
+    );
+    assert!(output.status.success(), "{output:?}");
+
+    let archive = backup_of(tmp.path());
+    fs::rename(tmp.path().join("project"), tmp.path().join("original")).unwrap();
+    let output = common::run(tmp.path(), &["r", archive.to_str().unwrap()]);
+    assert!(output.status.success(), "{output:?}");
+
+    let restored = tmp.path().join("project");
+    assert!(restored.join("src/main.rs").is_file());
+    assert!(!restored.join("target").exists());
+    assert!(!restored.join("src/target").exists());
+}
+
+#[test]
+fn patterns_are_read_from_a_file() {
+    let tmp = TempDir::new().unwrap();
+    project(&tmp.path().join("project"));
+    fs::write(
+        tmp.path().join("excludes"),
+        "# build output\ntarget/\n\nnode_modules/\n",
+    )
+    .unwrap();
+
+    let output = common::run(tmp.path(), &["b", "--exclude-from", "excludes", "project"]);
+    assert!(output.status.success(), "{output:?}");
+
+    let backup = backup_of(tmp.path());
+    assert!(backup.join("src/scratch.tmp").is_file());
+    assert!(!backup.join("target").exists());
+    assert!(!backup.join("src/target").exists());
+    assert!(!backup.join("web/node_modules").exists());
+}
+
+#[test]
+fn dry_run_and_verbose_show_excluded_entries() {
+    let tmp = TempDir::new().unwrap();
+    project(&tmp.path().join("project"));
+
+    let output = common::run(tmp.path(), &["b", "-n", "--exclude", "target/", "project"]);
+    assert!(output.status.success(), "{output:?}");
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("excluded: target\n"), "{stdout}");
+    assert!(stdout.contains("excluded: src/target\n"), "{stdout}");
+    assert!(stdout.contains("files: 4 "), "{stdout}");
+    assert_eq!(common::single_entry(tmp.path()), tmp.path().join("project"));
+
+    let output = common::run(tmp.path(), &["b", "-v", "--exclude", "*.tmp", "project"]);
+    assert!(output.status.success(), "{output:?}");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("excluded project/src/scratch.tmp"),
+        "{stderr}"
+    );
+}
+
+#[test]
+fn ignore_files_are_honored_at_every_level() {
+    let tmp = TempDir::new().unwrap();
+    let source = tmp.path().join("project");
+    project(&source);
+    fs::write(
+        source.join(".backupignore"),
+        "# build output\ntarget/\n*.tmp\n",
+    )
+    .unwrap();
+    fs::write(source.join("src/.backupignore"), "!scratch.tmp\n").unwrap();
+    fs::write(source.join("web/.backupignore"), "node_modules/\n").unwrap();
+
+    let output = common::run(tmp.path(), &["b", "project"]);
+    assert!(output.status.success(), "{output:?}");
+
+    let backup = backup_of(tmp.path());
+    assert!(backup.join(".backupignore").is_file());
+    assert!(backup.join("src/scratch.tmp").is_file());
+    assert!(!backup.join("target").exists());
+    assert!(!backup.join("src/target").exists());
+    assert!(!backup.join("web/node_modules").exists());
+}
+
+#[test]
+fn no_ignore_file_disables_ignore_files() {
+    let tmp = TempDir::new().unwrap();
+    let source = tmp.path().join("project");
+    project(&source);
+    fs::write(source.join(".backupignore"), "target/\n").unwrap();
+
+    let output = common::run(tmp.path(), &["b", "--no-ignore-file", "project"]);
+    assert!(output.status.success(), "{output:?}");
+    assert!(backup_of(tmp.path()).join("target/debug/app").is_file());
+}
+
+#[test]
+fn vcs_and_cache_directories_are_left_out_on_request() {
+    let tmp = TempDir::new().unwrap();
+    let source = tmp.path().join("project");
+    project(&source);
+    fs::create_dir_all(source.join(".git/objects")).unwrap();
+    fs::write(source.join(".git/HEAD"), "ref: refs/heads/main").unwrap();
+    fs::create_dir_all(source.join("web/.hg")).unwrap();
+    fs::write(
+        source.join("target/CACHEDIR.TAG"),
+        "Signature: 8a477f597d28d172789f06886806bc55\n# Created by cargo.\n",
+    )
+    .unwrap();
+    fs::write(source.join("src/target/CACHEDIR.TAG"), "not a cache tag").unwrap();
+
+    let output = common::run(
+        tmp.path(),
+        &["b", "-n", "--exclude-vcs", "--exclude-caches", "project"],
+    );
+    assert!(output.status.success(), "{output:?}");
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("excluded: .git\n"), "{stdout}");
+    assert!(stdout.contains("excluded: web/.hg\n"), "{stdout}");
+    assert!(stdout.contains("excluded: target\n"), "{stdout}");
+    assert!(!stdout.contains("excluded: src/target"), "{stdout}");
+
+    let output = common::run(
+        tmp.path(),
+        &[
+            "b",
+            "--exclude-vcs",
+            "--exclude-caches",
+            "--exclude",
+            "*.tmp",
+            "--exclude",
+            "!.hg/",
+            "project",
+            "copies",
+        ],
+    );
+    assert!(output.status.success(), "{output:?}");
+    let backup = backup_of(&tmp.path().join("copies"));
+    assert!(!backup.join(".git").exists());
+    assert!(backup.join("web/.hg").is_dir());
+    assert!(!backup.join("target").exists());
+    assert!(backup.join("src/target/notes").is_file());
+    assert!(!backup.join("src/scratch.tmp").exists());
+
+    let output = common::run(tmp.path(), &["b", "project", "plain"]);
+    assert!(output.status.success(), "{output:?}");
+    let backup = backup_of(&tmp.path().join("plain"));
+    assert!(backup.join(".git/HEAD").is_file());
+    assert!(backup.join("target/CACHEDIR.TAG").is_file());
+}
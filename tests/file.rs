@@ -0,0 +1,155 @@
+mod common;
+
+use std::fs;
+use std::path::Path;
+
+use tempfile::TempDir;
+
+#[test]
+fn file_to_directory_creates_timestamped_copy() {
+    let tmp = TempDir::new().unwrap();
+    fs::write(tmp.path().join("hosts"), "127.0.0.1 localhost").unwrap();
+    fs::create_dir(tmp.path().join("backups")).unwrap();
+
+    let output = common::run(tmp.path(), &["b", "hosts", "backups"]);
+    assert!(output.status.success(), "{output:?}");
+
+    let backup = common::single_entry(&tmp.path().join("backups"));
+    let name = backup.file_name().unwrap().to_str().unwrap();
+    assert!(name.starts_with("hosts.") && name.ends_with(".backup"));
+    assert_eq!(fs::read_to_string(backup).unwrap(), "127.0.0.1 localhost");
+}
+
+#[test]
+fn file_to_file_requires_force() {
+    let tmp = TempDir::new().unwrap();
+    fs::write(tmp.path().join("hosts"), "new").unwrap();
+    fs::write(tmp.path().join("hosts.copy"), "old").unwrap();
+
+    let output = common::run(tmp.path(), &["b", "hosts", "hosts.copy"]);
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("hosts.copy"));
+    assert_eq!(
+        fs::read_to_string(tmp.path().join("hosts.copy")).unwrap(),
+        "old"
+    );
+}
+
+#[test]
+fn file_to_file_writes_the_target_itself() {
+    let tmp = TempDir::new().unwrap();
+    fs::write(tmp.path().join("hosts"), "new").unwrap();
+    fs::write(tmp.path().join("hosts.copy"), "old").unwrap();
+
+    let output = common::run(tmp.path(), &["b", "--force", "hosts", "hosts.copy"]);
+    assert!(output.status.success(), "{output:?}");
+    assert!(tmp.path().join("hosts.copy").is_file());
+    assert_eq!(
+        fs::read_to_string(tmp.path().join("hosts.copy")).unwrap(),
+        "new"
+    );
+}
+
+#[test]
+fn missing_source_fails_with_its_path() {
+    let tmp = TempDir::new().unwrap();
+
+    let output = common::run(tmp.path(), &["b", "does-not-exist"]);
+    assert_eq!(output.status.code(), Some(1));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("'does-not-exist'"));
+}
+
+#[test]
+fn dry_run_reports_blocked_file_without_writing() {
+    let tmp = TempDir::new().unwrap();
+    fs::write(tmp.path().join("hosts"), "new").unwrap();
+    fs::write(tmp.path().join("hosts.copy"), "old").unwrap();
+
+    let output = common::run(tmp.path(), &["b", "--dry-run", "hosts", "hosts.copy"]);
+    assert!(!output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("type: FileFile"), "{stdout}");
+    assert!(stdout.contains("the backup is blocked"), "{stdout}");
+    assert_eq!(
+        fs::read_to_string(tmp.path().join("hosts.copy")).unwrap(),
+        "old"
+    );
+}
+
+#[test]
+fn backup_onto_itself_is_refused() {
+    let tmp = TempDir::new().unwrap();
+    fs::write(tmp.path().join("hosts"), "127.0.0.1 localhost").unwrap();
+    fs::hard_link(tmp.path().join("hosts"), tmp.path().join("hosts.link")).unwrap();
+
+    for target in ["hosts", "./hosts", "hosts.link"] {
+        let output = common::run(tmp.path(), &["b", "--force", "hosts", target]);
+        assert!(!output.status.success(), "{output:?}");
+        assert!(String::from_utf8_lossy(&output.stderr).contains("into itself"));
+    }
+    assert_eq!(
+        fs::read_to_string(tmp.path().join("hosts")).unwrap(),
+        "127.0.0.1 localhost"
+    );
+}
+
+#[test]
+fn backups_within_the_same_second_are_all_kept() {
+    let tmp = TempDir::new().unwrap();
+    fs::write(tmp.path().join("hosts"), "127.0.0.1 localhost").unwrap();
+    fs::create_dir(tmp.path().join("backups")).unwrap();
+
+    for _ in 0..3 {
+        let output = common::run(tmp.path(), &["b", "hosts", "backups"]);
+        assert!(output.status.success(), "{output:?}");
+    }
+
+    let names: Vec<_> = fs::read_dir(tmp.path().join("backups"))
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+        .filter(|name| !common::is_sidecar(Path::new(name)))
+        .collect();
+    assert_eq!(names.len(), 3, "{names:?}");
+    assert!(names.iter().all(|name| name.ends_with(".backup")));
+}
+
+#[test]
+fn reflink_modes_copy_the_same_contents() {
+    let tmp = TempDir::new().unwrap();
+    let contents = "0123456789".repeat(100_000);
+    fs::write(tmp.path().join("disk.img"), &contents).unwrap();
+
+    for mode in ["auto", "never"] {
+        let output = common::run(tmp.path(), &["b", "--reflink", mode, "disk.img", mode]);
+        assert!(output.status.success(), "{output:?}");
+        let backup = common::single_entry(&tmp.path().join(mode));
+        assert_eq!(fs::read_to_string(backup).unwrap(), contents);
+    }
+}
+
+#[test]
+fn reflink_always_fails_where_files_cannot_be_cloned() {
+    let tmp = TempDir::new().unwrap();
+    fs::write(tmp.path().join("disk.img"), "contents").unwrap();
+
+    let output = common::run(
+        tmp.path(),
+        &["b", "--reflink", "always", "disk.img", "backups"],
+    );
+    let backups = tmp.path().join("backups");
+    match output.status.success() {
+        // The temporary directory is on a filesystem that clones.
+        true => {
+            let backup = common::single_entry(&backups);
+            assert_eq!(fs::read_to_string(backup).unwrap(), "contents");
+        }
+        false => {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            assert!(stderr.contains("cannot clone the file"), "{stderr}");
+            assert_eq!(fs::read_dir(&backups).unwrap().count(), 0);
+        }
+    }
+
+    let output = common::run(tmp.path(), &["b", "--reflink", "sometimes", "disk.img"]);
+    assert_eq!(output.status.code(), Some(2), "{output:?}");
+}
@@ -0,0 +1,81 @@
+mod common;
+
+use std::fs;
+
+use tempfile::TempDir;
+
+#[test]
+fn every_fsync_policy_and_buffer_size_backs_up_the_same_contents() {
+    let tmp = TempDir::new().unwrap();
+    let project = tmp.path().join("project");
+    fs::create_dir_all(project.join("src")).unwrap();
+    let contents: Vec<u8> = (0..100_000u32).map(|i| (i % 251) as u8).collect();
+    fs::write(project.join("src/data.bin"), &contents).unwrap();
+    fs::write(project.join("notes.txt"), "notes").unwrap();
+
+    for (fsync, compress) in [("none", "none"), ("file", "gzip"), ("dir", "none")] {
+        let target = format!("{fsync}-{compress}");
+        let output = common::run(
+            tmp.path(),
+            &[
+                "b",
+                "--fsync",
+                fsync,
+                "--buffer-size",
+                "4096",
+                "--reflink",
+                "never",
+                "-c",
+                compress,
+                "project",
+                &target,
+            ],
+        );
+        assert!(output.status.success(), "{output:?}");
+
+        let backup = common::single_entry(&tmp.path().join(&target));
+        let restored = tmp.path().join(format!("restored-{target}"));
+        let output = common::run(
+            tmp.path(),
+            &["r", backup.to_str().unwrap(), restored.to_str().unwrap()],
+        );
+        assert!(output.status.success(), "{output:?}");
+        assert_eq!(fs::read(restored.join("src/data.bin")).unwrap(), contents);
+    }
+
+    let output = common::run_with_stdin(
+        tmp.path(),
+        &[
+            "b",
+            "--fsync",
+            "dir",
+            "--buffer-size",
+            "1",
+            "--name",
+            "piped",
+            "-",
+            "stdin",
+        ],
+        b"piped",
+    );
+    assert!(output.status.success(), "{output:?}");
+    let backup = common::single_entry(&tmp.path().join("stdin"));
+    assert_eq!(fs::read(backup).unwrap(), b"piped");
+}
+
+#[test]
+fn invalid_fsync_policies_and_buffer_sizes_are_usage_errors() {
+    let tmp = TempDir::new().unwrap();
+    fs::write(tmp.path().join("notes.txt"), "notes").unwrap();
+
+    let output = common::run(tmp.path(), &["b", "--fsync", "always", "notes.txt"]);
+    assert_eq!(output.status.code(), Some(2), "{output:?}");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("Unknown fsync policy 'always', expected none, file or dir"),
+        "{stderr}"
+    );
+
+    let output = common::run(tmp.path(), &["b", "--buffer-size", "0", "notes.txt"]);
+    assert_eq!(output.status.code(), Some(2), "{output:?}");
+}
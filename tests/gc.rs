@@ -0,0 +1,101 @@
+mod common;
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use tempfile::TempDir;
+
+/// Creates a backup directory holding a backup with its sidecars, the
+/// sidecars of a backup removed by hand, an old and a fresh partial file, and
+/// a stale lock.
+fn leftovers() -> TempDir {
+    let tmp = TempDir::new().unwrap();
+    let backups = tmp.path().join("backups");
+    fs::create_dir(&backups).unwrap();
+    for name in [
+        "hosts.2024-05-01_10-00-00.backup",
+        "hosts.2024-05-01_10-00-00.backup.sha256",
+        "hosts.2024-05-01_10-00-00.backup.meta.json",
+        "fstab.2024-05-01_10-00-00.backup.sha256",
+        "fstab.2024-05-01_10-00-00.backup.meta.json",
+        ".data.partial",
+        ".notes.partial",
+    ] {
+        fs::write(backups.join(name), "1234567890").unwrap();
+    }
+    fs::write(backups.join(".data.backup.lock"), "4294967295").unwrap();
+    let old = filetime::FileTime::from_unix_time(1_000_000_000, 0);
+    filetime::set_file_mtime(backups.join(".data.partial"), old).unwrap();
+    tmp
+}
+
+/// Returns the paths of the entries left in `dir`, relative to it.
+fn remaining(dir: &Path) -> Vec<PathBuf> {
+    let mut paths = common::tree(dir);
+    paths.sort();
+    paths
+}
+
+#[test]
+fn leftovers_are_only_removed_once_confirmed() {
+    let tmp = leftovers();
+    let backups = tmp.path().join("backups");
+    let before = remaining(&backups);
+
+    let output = common::run(tmp.path(), &["gc", "--dry-run", "backups"]);
+    assert!(output.status.success(), "{output:?}");
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains(".data.partial (partial file)"), "{stdout}");
+    assert!(
+        stdout.contains(".data.backup.lock (stale lock)"),
+        "{stdout}"
+    );
+    assert!(stdout.contains("would free 40 B"), "{stdout}");
+    assert_eq!(remaining(&backups), before);
+
+    let output = common::run_with_stdin(tmp.path(), &["gc", "backups"], b"n\n");
+    assert_eq!(output.status.code(), Some(3), "{output:?}");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("Remove 4 files, freeing 40 B? [y/N]"),
+        "{stderr}"
+    );
+    assert_eq!(remaining(&backups), before);
+
+    let output = common::run_with_stdin(tmp.path(), &["gc", "backups"], b"y\n");
+    assert!(output.status.success(), "{output:?}");
+    assert_eq!(
+        remaining(&backups),
+        [
+            ".notes.partial",
+            "hosts.2024-05-01_10-00-00.backup",
+            "hosts.2024-05-01_10-00-00.backup.meta.json",
+            "hosts.2024-05-01_10-00-00.backup.sha256",
+        ]
+        .map(PathBuf::from)
+    );
+}
+
+#[test]
+fn yes_removes_without_asking() {
+    let tmp = leftovers();
+    let backups = tmp.path().join("backups");
+
+    let args = ["gc", "--json", "--yes", "--older-than", "1s", "backups"];
+    let output = common::run_with_stdin(tmp.path(), &args, b"");
+    assert!(output.status.success(), "{output:?}");
+    let outcome: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(outcome["action"], "gc");
+    assert_eq!(outcome["bytes"], 40);
+    assert_eq!(outcome["dry_run"], false);
+    assert_eq!(outcome["removed"].as_array().unwrap().len(), 4);
+    assert!(backups.join(".notes.partial").exists());
+    assert!(!backups.join(".data.partial").exists());
+
+    let output = common::run(tmp.path(), &["gc", "--yes", "backups"]);
+    assert!(output.status.success(), "{output:?}");
+    assert!(String::from_utf8_lossy(&output.stdout).contains("freed 0 B"));
+
+    let output = common::run(tmp.path(), &["gc", "--empty-trash", "backups"]);
+    assert_eq!(output.status.code(), Some(2), "{output:?}");
+}
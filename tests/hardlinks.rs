@@ -0,0 +1,86 @@
+#![cfg(unix)]
+
+mod common;
+
+use std::fs;
+use std::os::unix::fs::MetadataExt;
+use std::path::Path;
+
+use tempfile::TempDir;
+
+/// Creates a project holding a file hard linked under two other names.
+fn linked_project(root: &Path) {
+    let project = root.join("project");
+    fs::create_dir_all(project.join("docs")).unwrap();
+    fs::write(project.join("data.bin"), "0123456789").unwrap();
+    fs::hard_link(project.join("data.bin"), project.join("docs/copy.bin")).unwrap();
+    fs::hard_link(project.join("data.bin"), project.join("same.bin")).unwrap();
+    fs::write(project.join("other.txt"), "other").unwrap();
+}
+
+fn inode(path: &Path) -> u64 {
+    fs::metadata(path).unwrap().ino()
+}
+
+#[test]
+fn copies_keep_hard_links() {
+    let tmp = TempDir::new().unwrap();
+    linked_project(tmp.path());
+
+    let output = common::run(tmp.path(), &["b", "-v", "-j", "4", "project", "copies"]);
+    assert!(output.status.success(), "{output:?}");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("backed up 4 files (15 B, 2 files stored as hardlinks, saving 20 B)"),
+        "{stderr}"
+    );
+
+    let copy = common::single_entry(&tmp.path().join("copies"));
+    assert_eq!(inode(&copy.join("data.bin")), inode(&copy.join("same.bin")));
+    assert_eq!(
+        inode(&copy.join("data.bin")),
+        inode(&copy.join("docs/copy.bin"))
+    );
+    assert_ne!(
+        inode(&copy.join("data.bin")),
+        inode(&copy.join("other.txt"))
+    );
+    assert_eq!(
+        fs::read_to_string(copy.join("docs/copy.bin")).unwrap(),
+        "0123456789"
+    );
+}
+
+#[test]
+fn archives_store_hard_links_and_restore_them() {
+    let tmp = TempDir::new().unwrap();
+    linked_project(tmp.path());
+
+    let output = common::run(
+        tmp.path(),
+        &["b", "-v", "--compress", "gzip", "project", "archives"],
+    );
+    assert!(output.status.success(), "{output:?}");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("2 files stored as hardlinks, saving 20 B"),
+        "{stderr}"
+    );
+    let archive = common::single_entry(&tmp.path().join("archives"));
+    let archive = archive.to_str().unwrap();
+
+    let output = common::run(tmp.path(), &["diff", "--checksum", archive, "project"]);
+    assert_eq!(output.status.code(), Some(0), "{output:?}");
+
+    let output = common::run(tmp.path(), &["r", archive, "restored"]);
+    assert!(output.status.success(), "{output:?}");
+    let restored = tmp.path().join("restored");
+    assert_eq!(
+        inode(&restored.join("data.bin")),
+        inode(&restored.join("docs/copy.bin"))
+    );
+    assert_eq!(
+        fs::read_to_string(restored.join("same.bin")).unwrap(),
+        "0123456789"
+    );
+}
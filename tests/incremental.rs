@@ -0,0 +1,104 @@
+#![cfg(unix)]
+
+mod common;
+
+use std::fs;
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+
+use tempfile::TempDir;
+
+fn photos(root: &Path) {
+    fs::create_dir_all(root.join("photos/2024")).unwrap();
+    fs::write(root.join("photos/2024/a.jpg"), "aaaa").unwrap();
+    fs::write(root.join("photos/2024/b.jpg"), "bbbb").unwrap();
+    fs::write(root.join("photos/c.jpg"), "cccc").unwrap();
+    fs::create_dir(root.join("backups")).unwrap();
+}
+
+fn backups(root: &Path) -> Vec<PathBuf> {
+    let mut backups: Vec<_> = fs::read_dir(root.join("backups"))
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| !common::is_sidecar(path))
+        .collect();
+    backups.sort();
+    backups
+}
+
+fn same_inode(a: &Path, b: &Path) -> bool {
+    fs::metadata(a).unwrap().ino() == fs::metadata(b).unwrap().ino()
+}
+
+#[test]
+fn unchanged_files_are_linked_to_the_previous_backup() {
+    let tmp = TempDir::new().unwrap();
+    photos(tmp.path());
+
+    let args = ["b", "-v", "--incremental", "photos", "backups"];
+    let output = common::run(tmp.path(), &args);
+    assert!(output.status.success(), "{output:?}");
+
+    fs::write(tmp.path().join("photos/2024/b.jpg"), "edited").unwrap();
+    fs::write(tmp.path().join("photos/d.jpg"), "dddd").unwrap();
+    fs::remove_file(tmp.path().join("photos/c.jpg")).unwrap();
+    let output = common::run(tmp.path(), &args);
+    assert!(output.status.success(), "{output:?}");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("backed up 3 files (10 B, 1 linked"),
+        "{stderr}"
+    );
+
+    let [first, second] = backups(tmp.path()).try_into().unwrap();
+    assert!(same_inode(
+        &first.join("2024/a.jpg"),
+        &second.join("2024/a.jpg")
+    ));
+    assert!(!same_inode(
+        &first.join("2024/b.jpg"),
+        &second.join("2024/b.jpg")
+    ));
+    assert_eq!(
+        fs::read_to_string(second.join("2024/b.jpg")).unwrap(),
+        "edited"
+    );
+    assert_eq!(fs::read_to_string(second.join("d.jpg")).unwrap(), "dddd");
+    assert!(!second.join("c.jpg").exists());
+    assert_eq!(fs::read_to_string(first.join("c.jpg")).unwrap(), "cccc");
+
+    let output = common::run(tmp.path(), &["verify", second.to_str().unwrap()]);
+    assert!(output.status.success(), "{output:?}");
+}
+
+#[test]
+fn checksum_comparison_ignores_touched_files() {
+    let tmp = TempDir::new().unwrap();
+    photos(tmp.path());
+
+    let args = ["b", "--incremental", "--checksum", "photos", "backups"];
+    let output = common::run(tmp.path(), &args);
+    assert!(output.status.success(), "{output:?}");
+
+    let touched = filetime::FileTime::from_unix_time(1_000_000_000, 0);
+    filetime::set_file_mtime(tmp.path().join("photos/c.jpg"), touched).unwrap();
+    let output = common::run(tmp.path(), &args);
+    assert!(output.status.success(), "{output:?}");
+
+    let [first, second] = backups(tmp.path()).try_into().unwrap();
+    assert!(same_inode(&first.join("c.jpg"), &second.join("c.jpg")));
+}
+
+#[test]
+fn without_incremental_everything_is_copied() {
+    let tmp = TempDir::new().unwrap();
+    photos(tmp.path());
+
+    for _ in 0..2 {
+        let output = common::run(tmp.path(), &["b", "photos", "backups"]);
+        assert!(output.status.success(), "{output:?}");
+    }
+
+    let [first, second] = backups(tmp.path()).try_into().unwrap();
+    assert!(!same_inode(&first.join("c.jpg"), &second.join("c.jpg")));
+}
@@ -0,0 +1,74 @@
+#![cfg(unix)]
+
+mod common;
+
+use std::fs::{self, File};
+use std::io::Write;
+use std::process::Child;
+use std::thread;
+use std::time::Duration;
+
+use tempfile::TempDir;
+
+/// Sends SIGINT to `child` once it had time to start copying.
+fn interrupt(child: &Child) {
+    thread::sleep(Duration::from_millis(300));
+    assert_eq!(
+        unsafe { libc::kill(child.id() as libc::pid_t, libc::SIGINT) },
+        0
+    );
+}
+
+#[test]
+fn interrupted_stdin_backups_are_removed() {
+    let tmp = TempDir::new().unwrap();
+
+    let mut child = common::spawn(tmp.path(), &["b", "--name", "dump.sql", "-", "backups"]);
+    let mut stdin = child.stdin.take().unwrap();
+    stdin.write_all(b"partial dump").unwrap();
+    interrupt(&child);
+    let output = child.wait_with_output().unwrap();
+    drop(stdin);
+
+    assert_eq!(output.status.code(), Some(130), "{output:?}");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("Backup aborted, partial artifacts removed"),
+        "{stderr}"
+    );
+    assert_eq!(fs::read_dir(tmp.path().join("backups")).unwrap().count(), 0);
+}
+
+#[test]
+fn interrupted_directory_backups_are_removed() {
+    let tmp = TempDir::new().unwrap();
+    fs::create_dir(tmp.path().join("project")).unwrap();
+    fs::write(tmp.path().join("project/notes"), "notes").unwrap();
+    File::create(tmp.path().join("project/disk.img"))
+        .unwrap()
+        .set_len(1 << 36)
+        .unwrap();
+    fs::create_dir(tmp.path().join("backups")).unwrap();
+
+    let child = common::spawn(
+        tmp.path(),
+        &[
+            "b",
+            "--no-space-check",
+            "--reflink",
+            "never",
+            "project",
+            "backups",
+        ],
+    );
+    interrupt(&child);
+    let output = child.wait_with_output().unwrap();
+
+    assert_eq!(output.status.code(), Some(130), "{output:?}");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("Backup aborted, partial artifacts removed"),
+        "{stderr}"
+    );
+    assert_eq!(fs::read_dir(tmp.path().join("backups")).unwrap().count(), 0);
+}
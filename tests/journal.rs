@@ -0,0 +1,164 @@
+mod common;
+
+use std::fs;
+use std::path::Path;
+use std::process::Output;
+
+use tempfile::TempDir;
+
+/// Runs the binary from `root`, keeping the history in `root/data`.
+fn run(root: &Path, args: &[&str]) -> Output {
+    common::run_with_history(root, args, &root.join("data"))
+}
+
+fn setup() -> TempDir {
+    let tmp = TempDir::new().unwrap();
+    fs::write(tmp.path().join("hosts"), "live").unwrap();
+    fs::create_dir(tmp.path().join("backups")).unwrap();
+    tmp
+}
+
+#[test]
+fn history_lists_backups_and_restores() {
+    let tmp = setup();
+
+    let output = run(tmp.path(), &["b", "hosts", "backups"]);
+    assert!(output.status.success(), "{output:?}");
+    let backup = common::single_entry(&tmp.path().join("backups"));
+    let output = run(tmp.path(), &["r", backup.to_str().unwrap(), "restored"]);
+    assert!(output.status.success(), "{output:?}");
+
+    let output = run(tmp.path(), &["history"]);
+    assert!(output.status.success(), "{output:?}");
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let lines: Vec<_> = stdout.lines().collect();
+    assert_eq!(lines.len(), 3, "{stdout}");
+    assert!(lines[0].starts_with("TIME"));
+    assert!(lines[1].contains(" backup "), "{stdout}");
+    assert!(lines[2].contains(" restore "), "{stdout}");
+    assert!(lines[2].ends_with("restored"), "{stdout}");
+
+    let output = run(tmp.path(), &["history", "--json"]);
+    let operations: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(operations[0]["action"], "backup");
+    assert_eq!(operations[0]["artifacts"][0]["size"], 4);
+}
+
+#[test]
+fn undo_removes_the_latest_backup() {
+    let tmp = setup();
+    let output = run(tmp.path(), &["b", "hosts", "backups"]);
+    assert!(output.status.success(), "{output:?}");
+
+    let output = run(tmp.path(), &["undo"]);
+    assert!(output.status.success(), "{output:?}");
+    let left: Vec<_> = fs::read_dir(tmp.path().join("backups"))
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name())
+        .collect();
+    assert_eq!(left, [".backup-catalog.json"]);
+
+    let output = run(tmp.path(), &["undo"]);
+    assert_eq!(output.status.code(), Some(1), "{output:?}");
+    assert!(String::from_utf8_lossy(&output.stderr).contains("No operation left to undo"));
+}
+
+#[test]
+fn stdin_backups_are_in_the_history_and_undone() {
+    let tmp = setup();
+    let data = tmp.path().join("data");
+    let output = common::run_with_stdin_and_env(
+        tmp.path(),
+        &["b", "-", "backups", "--name", "dump.sql"],
+        b"hello\n",
+        &[("XDG_DATA_HOME", data.as_os_str())],
+    );
+    assert!(output.status.success(), "{output:?}");
+    let backup = common::single_entry(&tmp.path().join("backups"));
+
+    let output = run(tmp.path(), &["history"]);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let lines: Vec<_> = stdout.lines().collect();
+    assert_eq!(lines.len(), 2, "{stdout}");
+    let fields: Vec<_> = lines[1].split_whitespace().collect();
+    assert_eq!(fields[2..4], ["backup", "-"], "{stdout}");
+
+    let output = run(tmp.path(), &["undo"]);
+    assert!(output.status.success(), "{output:?}");
+    assert!(!backup.exists());
+}
+
+#[test]
+fn undo_puts_back_what_a_restore_replaced() {
+    let tmp = setup();
+    let output = run(tmp.path(), &["b", "hosts", "backups"]);
+    assert!(output.status.success(), "{output:?}");
+    let backup = common::single_entry(&tmp.path().join("backups"));
+    fs::write(tmp.path().join("hosts"), "edited").unwrap();
+
+    let output = run(tmp.path(), &["r", "--force", backup.to_str().unwrap(), "."]);
+    assert!(output.status.success(), "{output:?}");
+    assert_eq!(
+        fs::read_to_string(tmp.path().join("hosts")).unwrap(),
+        "live"
+    );
+
+    let output = run(tmp.path(), &["undo"]);
+    assert!(output.status.success(), "{output:?}");
+    assert_eq!(
+        fs::read_to_string(tmp.path().join("hosts")).unwrap(),
+        "edited"
+    );
+    let names: Vec<_> = fs::read_dir(tmp.path())
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+        .filter(|name| name.contains("pre-restore"))
+        .collect();
+    assert!(names.is_empty(), "{names:?}");
+
+    // The backup is next in line.
+    let output = run(tmp.path(), &["undo"]);
+    assert!(output.status.success(), "{output:?}");
+    assert!(!backup.exists());
+}
+
+#[test]
+fn undo_refuses_changed_artifacts() {
+    let tmp = setup();
+    let output = run(tmp.path(), &["b", "hosts", "backups"]);
+    assert!(output.status.success(), "{output:?}");
+    let backup = common::single_entry(&tmp.path().join("backups"));
+    fs::write(&backup, "tampered").unwrap();
+
+    let output = run(tmp.path(), &["undo"]);
+    assert_eq!(output.status.code(), Some(1), "{output:?}");
+    assert!(String::from_utf8_lossy(&output.stderr).contains("changed since"));
+    assert!(backup.exists());
+}
+
+#[test]
+fn undo_refuses_directories_edited_in_place() {
+    let tmp = setup();
+    fs::create_dir(tmp.path().join("etc")).unwrap();
+    fs::write(tmp.path().join("etc/hosts"), "live").unwrap();
+    let output = run(tmp.path(), &["b", "etc", "backups"]);
+    assert!(output.status.success(), "{output:?}");
+    let backup = common::single_entry(&tmp.path().join("backups"));
+    fs::write(backup.join("hosts"), "edit").unwrap();
+
+    let output = run(tmp.path(), &["undo"]);
+    assert_eq!(output.status.code(), Some(1), "{output:?}");
+    assert!(String::from_utf8_lossy(&output.stderr).contains("changed since"));
+    assert_eq!(fs::read(backup.join("hosts")).unwrap(), b"edit");
+}
+
+#[test]
+fn unwritable_history_does_not_fail_the_backup() {
+    let tmp = setup();
+    // A file where the data directory should be.
+    fs::write(tmp.path().join("data"), "").unwrap();
+
+    let output = run(tmp.path(), &["b", "hosts", "backups"]);
+    assert!(output.status.success(), "{output:?}");
+    assert!(String::from_utf8_lossy(&output.stderr).contains("not in the history"));
+}
@@ -0,0 +1,136 @@
+mod common;
+
+use std::fs;
+use std::path::Path;
+
+use serde_json::Value;
+use tempfile::TempDir;
+
+/// Runs the binary with `--json` after the mode, parsing stdout.
+fn run_json(root: &Path, args: &[&str]) -> (Option<i32>, Value, String) {
+    let args: Vec<_> = args[..1]
+        .iter()
+        .chain(&["--json"])
+        .chain(&args[1..])
+        .copied()
+        .collect();
+    let output = common::run(root, &args);
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let json = match stdout.is_empty() {
+        true => Value::Null,
+        false => serde_json::from_str(&stdout).unwrap_or_else(|e| panic!("{e}: {stdout}")),
+    };
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    (output.status.code(), json, stderr)
+}
+
+fn setup() -> TempDir {
+    let tmp = TempDir::new().unwrap();
+    fs::create_dir_all(tmp.path().join("project/src")).unwrap();
+    fs::write(tmp.path().join("project/src/main.rs"), "fn main() {}").unwrap();
+    fs::write(tmp.path().join("project/README"), "readme").unwrap();
+    fs::create_dir(tmp.path().join("backups")).unwrap();
+    tmp
+}
+
+#[test]
+fn backups_and_restores_are_described() {
+    let tmp = setup();
+    let root = tmp.path().canonicalize().unwrap();
+
+    let (status, json, _) = run_json(tmp.path(), &["b", "-v", "project", "backups"]);
+    assert_eq!(status, Some(0));
+    let backup = common::single_entry(&tmp.path().join("backups"));
+    assert_eq!(json["action"], "backup");
+    assert_eq!(json["source"], root.join("project").to_str().unwrap());
+    assert_eq!(
+        json["backup_path"],
+        root.join(backup.strip_prefix(tmp.path()).unwrap())
+            .to_str()
+            .unwrap()
+    );
+    assert_eq!(json["files"], 2);
+    assert_eq!(json["bytes"], 18);
+    assert!(json["duration_ms"].is_u64());
+
+    let (status, json, _) = run_json(tmp.path(), &["r", backup.to_str().unwrap(), "restored"]);
+    assert_eq!(status, Some(0));
+    assert_eq!(json["action"], "restore");
+    assert_eq!(
+        json["restore_path"],
+        root.join("restored").to_str().unwrap()
+    );
+    assert_eq!(json["files"], 2);
+    assert_eq!(json["safety_path"], Value::Null);
+
+    let (status, json, _) = run_json(tmp.path(), &["verify", backup.to_str().unwrap()]);
+    assert_eq!(status, Some(0));
+    assert_eq!(json["action"], "verify");
+    assert_eq!(json["files"], 2);
+}
+
+#[test]
+fn prune_lists_the_removed_backups() {
+    let tmp = setup();
+    for name in [
+        "hosts.2020-01-01_00-00-00.backup",
+        "hosts.2021-01-01_00-00-00.backup",
+    ] {
+        fs::write(tmp.path().join("backups").join(name), "abc").unwrap();
+    }
+
+    let (status, json, stderr) = run_json(tmp.path(), &["prune", "--keep-last", "1", "backups"]);
+    assert_eq!(status, Some(0));
+    assert_eq!(json["action"], "prune");
+    assert_eq!(json["bytes"], 3);
+    let removed = json["removed"].as_array().unwrap();
+    assert_eq!(removed.len(), 1);
+    assert!(removed[0]
+        .as_str()
+        .unwrap()
+        .ends_with("hosts.2020-01-01_00-00-00.backup"));
+    // Messages go to stderr, leaving stdout to the JSON.
+    assert!(stderr.contains("freed 3 B"), "{stderr}");
+}
+
+#[test]
+fn errors_are_objects_on_stderr() {
+    let tmp = setup();
+
+    let (status, json, stderr) = run_json(tmp.path(), &["b", "missing", "backups"]);
+    assert_eq!(status, Some(1));
+    assert_eq!(json, Value::Null);
+    let error: Value = serde_json::from_str(&stderr).unwrap();
+    assert_eq!(error["code"], "not_found");
+    assert!(error["error"].as_str().unwrap().contains("missing"));
+
+    let (status, _, stderr) = run_json(tmp.path(), &["prune", "backups"]);
+    assert_eq!(status, Some(2));
+    let error: Value = serde_json::from_str(&stderr).unwrap();
+    assert_eq!(error["code"], "invalid_option");
+}
+
+#[test]
+fn backups_and_restores_include_their_stats() {
+    let tmp = setup();
+    fs::write(tmp.path().join("project/big.txt"), "a".repeat(100_000)).unwrap();
+    fs::write(tmp.path().join("project/.backupignore"), "README\n").unwrap();
+
+    let (code, json, _) = run_json(tmp.path(), &["b", "-c", "gzip", "project", "backups"]);
+    assert_eq!(code, Some(0));
+    let stats = &json["stats"];
+    assert_eq!(stats["files"], 3, "{json}");
+    assert_eq!(stats["bytes_read"], 100_000 + 12 + 7, "{json}");
+    assert_eq!(stats["compressed"], true, "{json}");
+    assert_eq!(stats["excluded"], 1, "{json}");
+    assert_eq!(stats["failed"], 0, "{json}");
+    assert!(stats["bytes_written"].as_u64().unwrap() < 100_000, "{json}");
+    assert!(stats["elapsed_ms"].is_u64(), "{json}");
+
+    let archive = json["backup_path"].as_str().unwrap().to_owned();
+    let output = common::run(tmp.path(), &["r", &archive, "restored"]);
+    assert!(output.status.success(), "{output:?}");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("3 files processed, "), "{stderr}");
+    assert!(stderr.contains("(compression ratio "), "{stderr}");
+}
@@ -0,0 +1,56 @@
+#![cfg(unix)]
+
+mod common;
+
+use std::fs;
+
+use tempfile::TempDir;
+
+#[test]
+fn latest_link_follows_the_newest_backup() {
+    let tmp = TempDir::new().unwrap();
+    fs::write(tmp.path().join("notes.txt"), "first").unwrap();
+    let output = common::run(tmp.path(), &["b", "notes.txt", "backups"]);
+    assert!(output.status.success(), "{output:?}");
+    let first = common::single_entry(&tmp.path().join("backups"));
+
+    let link = tmp.path().join("backups/notes.txt.latest.backup");
+    assert_eq!(fs::read_link(&link).unwrap(), first.file_name().unwrap());
+
+    fs::write(tmp.path().join("notes.txt"), "second").unwrap();
+    let output = common::run(tmp.path(), &["b", "notes.txt", "backups"]);
+    assert!(output.status.success(), "{output:?}");
+    let second = fs::read_link(&link).unwrap();
+    assert_ne!(second, first.file_name().unwrap());
+    assert_eq!(
+        fs::read_to_string(tmp.path().join("backups").join(second)).unwrap(),
+        "second"
+    );
+
+    let output = common::run(tmp.path(), &["l", "backups"]);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert_eq!(stdout.lines().count(), 3, "{stdout}");
+    assert!(!stdout.contains("latest"), "{stdout}");
+}
+
+#[test]
+fn restore_and_listing_resolve_the_latest_link() {
+    let tmp = TempDir::new().unwrap();
+    fs::create_dir(tmp.path().join("etc")).unwrap();
+    fs::write(tmp.path().join("etc/hosts"), "localhost").unwrap();
+    let output = common::run(tmp.path(), &["b", "etc", "backups"]);
+    assert!(output.status.success(), "{output:?}");
+
+    let link = "backups/etc.latest.backup";
+    let output = common::run(tmp.path(), &["list-contents", link]);
+    assert!(output.status.success(), "{output:?}");
+    assert!(String::from_utf8_lossy(&output.stdout).contains("hosts"));
+
+    fs::create_dir(tmp.path().join("restored")).unwrap();
+    let output = common::run(tmp.path(), &["r", link, "restored"]);
+    assert!(output.status.success(), "{output:?}");
+    assert_eq!(
+        fs::read_to_string(tmp.path().join("restored/etc/hosts")).unwrap(),
+        "localhost"
+    );
+}
@@ -0,0 +1,118 @@
+use std::fs;
+
+use backup::backup::{BackupOptions, Compression};
+use backup::restore::RestoreOptions;
+use backup::BackupError;
+use tempfile::TempDir;
+
+#[test]
+fn backup_reports_path_files_and_bytes() {
+    let tmp = TempDir::new().unwrap();
+    let source = tmp.path().join("project");
+    fs::create_dir_all(source.join("src")).unwrap();
+    fs::write(source.join("README"), "readme").unwrap();
+    fs::write(source.join("src/main.rs"), "fn main() {}").unwrap();
+
+    let report = backup::backup(&source, tmp.path(), &BackupOptions::new()).unwrap();
+    assert!(report.path.is_dir());
+    assert_eq!(report.files, 2);
+    assert_eq!(report.bytes, 18);
+
+    fs::remove_dir_all(&source).unwrap();
+    let restored = backup::restore(&report.path, None, &RestoreOptions::new()).unwrap();
+    assert_eq!(restored.path, source);
+    assert_eq!((restored.files, restored.bytes), (2, 18));
+    assert_eq!(fs::read_to_string(source.join("README")).unwrap(), "readme");
+}
+
+#[test]
+fn archive_report_counts_the_archive_size() {
+    let tmp = TempDir::new().unwrap();
+    let source = tmp.path().join("project");
+    fs::create_dir(&source).unwrap();
+    fs::write(source.join("data"), vec![b'x'; 4096]).unwrap();
+
+    let options = BackupOptions::new().compress(Compression::Gzip(9));
+    let report = backup::backup(&source, tmp.path(), &options).unwrap();
+    assert_eq!(report.files, 1);
+    assert_eq!(report.bytes, fs::metadata(&report.path).unwrap().len());
+    assert!(report.bytes < 4096);
+}
+
+#[test]
+fn errors_are_returned_instead_of_exiting() {
+    let tmp = TempDir::new().unwrap();
+    fs::write(tmp.path().join("hosts"), "new").unwrap();
+    fs::write(tmp.path().join("hosts.copy"), "old").unwrap();
+
+    let result = backup::backup(
+        &tmp.path().join("hosts"),
+        &tmp.path().join("hosts.copy"),
+        &BackupOptions::new(),
+    );
+    assert!(matches!(result, Err(BackupError::AlreadyExists(_))));
+}
+
+#[cfg(unix)]
+#[test]
+fn non_utf8_names_round_trip() {
+    use std::ffi::OsStr;
+    use std::os::unix::ffi::OsStrExt;
+
+    let tmp = TempDir::new().unwrap();
+    let name = OsStr::from_bytes(b"caf\xe9");
+    let source = tmp.path().join(name);
+    fs::create_dir(&source).unwrap();
+    fs::write(source.join(name), "latin-1").unwrap();
+
+    for compression in [Compression::None, Compression::Zstd(3)] {
+        let options = BackupOptions::new().compress(compression);
+        let report = backup::backup(&source, tmp.path(), &options).unwrap();
+        assert_eq!(report.files, 1);
+        assert!(report
+            .path
+            .file_name()
+            .unwrap()
+            .as_bytes()
+            .starts_with(b"caf\xe9."));
+
+        let target = tmp.path().join("restored");
+        let restored =
+            backup::restore(&report.path, Some(&target), &RestoreOptions::new()).unwrap();
+        assert_eq!(restored.path, target);
+        assert_eq!(fs::read_to_string(target.join(name)).unwrap(), "latin-1");
+        fs::remove_dir_all(&target).unwrap();
+    }
+
+    let file = tmp.path().join(name).join(name);
+    let report = backup::backup(&file, tmp.path(), &BackupOptions::new()).unwrap();
+    fs::remove_dir_all(&source).unwrap();
+    fs::create_dir(&source).unwrap();
+    let restored = backup::restore(&report.path, Some(&source), &RestoreOptions::new()).unwrap();
+    assert_eq!(restored.path, file);
+    assert_eq!(fs::read_to_string(&file).unwrap(), "latin-1");
+}
+
+#[cfg(unix)]
+#[test]
+fn failed_entries_are_reported_or_abort_the_backup() {
+    let tmp = TempDir::new().unwrap();
+    let source = tmp.path().join("project");
+    fs::create_dir(&source).unwrap();
+    fs::write(source.join("README"), "readme").unwrap();
+    std::os::unix::fs::symlink("missing", source.join("dangling")).unwrap();
+    let target = tmp.path().join("backups");
+
+    let options = BackupOptions::new().dereference(true);
+    let report = backup::backup(&source, &target, &options).unwrap();
+    assert_eq!(report.files, 1);
+    assert_eq!(report.failed.len(), 1);
+    assert!(report.failed[0].contains("dangling"), "{:?}", report.failed);
+    assert!(report.path.join("README").is_file());
+
+    fs::remove_dir_all(&target).unwrap();
+    let options = options.keep_going(false);
+    let error = backup::backup(&source, &target, &options).unwrap_err();
+    assert!(matches!(error, BackupError::DanglingLink(_)), "{error:?}");
+    assert_eq!(fs::read_dir(&target).unwrap().count(), 0);
+}
@@ -0,0 +1,187 @@
+mod common;
+
+use std::fs;
+use std::path::Path;
+
+use tempfile::TempDir;
+
+/// Builds a tree of four files, two of them below `a/b`.
+fn nested_tree(root: &Path) {
+    fs::create_dir_all(root.join("a/b/c")).unwrap();
+    fs::create_dir_all(root.join("a/empty")).unwrap();
+    fs::write(root.join("top.txt"), "top").unwrap();
+    fs::write(root.join("a/one.txt"), "one").unwrap();
+    fs::write(root.join("a/b/two.txt"), "two").unwrap();
+    fs::write(root.join("a/b/c/three.txt"), "three").unwrap();
+}
+
+#[test]
+fn max_depth_leaves_out_deeper_contents() {
+    let tmp = TempDir::new().unwrap();
+    nested_tree(&tmp.path().join("project"));
+
+    let output = common::run(
+        tmp.path(),
+        &["b", "-v", "--max-depth", "2", "project", "copies"],
+    );
+    assert!(output.status.success(), "{output:?}");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("project': Contents of 1 directories not backed up due to depth limit"),
+        "{stderr}"
+    );
+    assert!(
+        stderr.contains("contents of 1 directories not backed up due to depth limit"),
+        "{stderr}"
+    );
+
+    let copy = common::single_entry(&tmp.path().join("copies"));
+    assert!(copy.join("a/one.txt").is_file());
+    assert!(copy.join("a/b").is_dir());
+    assert!(!copy.join("a/b/two.txt").exists());
+
+    let output = common::run(tmp.path(), &["b", "--max-depth", "0", "project"]);
+    assert_eq!(output.status.code(), Some(2), "{output:?}");
+}
+
+#[test]
+fn max_files_stops_with_a_warning_or_fails_fast() {
+    let tmp = TempDir::new().unwrap();
+    nested_tree(&tmp.path().join("project"));
+
+    let output = common::run(
+        tmp.path(),
+        &["b", "-v", "--max-files", "2", "project", "copies"],
+    );
+    assert!(output.status.success(), "{output:?}");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("project': Stopped after 2 files as set with --max-files"),
+        "{stderr}"
+    );
+    assert!(stderr.contains("backed up 2 files"), "{stderr}");
+
+    for compress in ["none", "gzip"] {
+        let output = common::run(
+            tmp.path(),
+            &[
+                "b",
+                "--fail-fast",
+                "--max-files",
+                "3",
+                "-c",
+                compress,
+                "project",
+                "failed",
+            ],
+        );
+        assert_eq!(output.status.code(), Some(1), "{output:?}");
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert!(
+            stderr.contains("project': More than 3 files, the limit set with --max-files"),
+            "{stderr}"
+        );
+    }
+
+    let output = common::run(
+        tmp.path(),
+        &["b", "--fail-fast", "--max-files", "4", "project", "copies"],
+    );
+    assert!(output.status.success(), "{output:?}");
+}
+
+#[test]
+fn dry_run_shows_the_limits() {
+    let tmp = TempDir::new().unwrap();
+    nested_tree(&tmp.path().join("project"));
+
+    let output = common::run(
+        tmp.path(),
+        &[
+            "b",
+            "-n",
+            "--max-depth",
+            "2",
+            "--max-files",
+            "10",
+            "project",
+            "copies",
+        ],
+    );
+    assert!(output.status.success(), "{output:?}");
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("max depth: 2"), "{stdout}");
+    assert!(stdout.contains("beyond max depth: a/b"), "{stdout}");
+    assert!(stdout.contains("max files: 10\n"), "{stdout}");
+    assert!(stdout.contains("files: 2 (6 B)"), "{stdout}");
+
+    let output = common::run(tmp.path(), &["b", "-n", "--max-files", "1", "project"]);
+    assert!(output.status.success(), "{output:?}");
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("max files: 1, exceeded"), "{stdout}");
+    assert!(stdout.contains("files: 1 ("), "{stdout}");
+    assert!(!tmp.path().join("copies").exists());
+}
+
+#[test]
+fn file_size_limits_skip_files_before_the_space_check() {
+    let tmp = TempDir::new().unwrap();
+    let project = tmp.path().join("project");
+    nested_tree(&project);
+    fs::write(project.join("a/empty.txt"), "").unwrap();
+    // Far larger than any disk, but sparse.
+    fs::File::create(project.join("a/b/disk.img"))
+        .unwrap()
+        .set_len(1 << 43)
+        .unwrap();
+
+    let output = common::run(
+        tmp.path(),
+        &[
+            "b",
+            "-v",
+            "--min-file-size",
+            "1",
+            "--max-file-size",
+            "1K",
+            "project",
+            "copies",
+        ],
+    );
+    assert!(output.status.success(), "{output:?}");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("disk.img (8.0 TiB), larger than --max-file-size"),
+        "{stderr}"
+    );
+    assert!(
+        stderr.contains("empty.txt (0 B), smaller than --min-file-size"),
+        "{stderr}"
+    );
+    assert!(
+        stderr.contains("2 files skipped for their size"),
+        "{stderr}"
+    );
+    assert!(stderr.contains("4 files processed"), "{stderr}");
+
+    let copy = common::single_entry(&tmp.path().join("copies"));
+    assert!(copy.join("a/b/c/three.txt").is_file());
+    assert!(!copy.join("a/b/disk.img").exists());
+    assert!(!copy.join("a/empty.txt").exists());
+
+    let output = common::run(
+        tmp.path(),
+        &["b", "-n", "--max-file-size", "1M", "project", "copies"],
+    );
+    assert!(output.status.success(), "{output:?}");
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("max file size: 1.0 MiB"), "{stdout}");
+    assert!(
+        stdout.contains("skipped for its size: a/b/disk.img (8.0 TiB)"),
+        "{stdout}"
+    );
+    assert!(!stdout.contains("not enough"), "{stdout}");
+
+    let output = common::run(tmp.path(), &["b", "--max-file-size", "big", "project"]);
+    assert_eq!(output.status.code(), Some(2), "{output:?}");
+}
@@ -0,0 +1,95 @@
+mod common;
+
+use std::fs;
+
+use tempfile::TempDir;
+
+fn backups() -> TempDir {
+    let tmp = TempDir::new().unwrap();
+    fs::write(tmp.path().join("hosts.2024-05-02_10-00-00.backup"), "12345").unwrap();
+    fs::write(tmp.path().join("hosts.2024-05-01_10-00-00.backup"), "123").unwrap();
+    fs::create_dir(tmp.path().join("etc.2024-05-01_09-00-00.backup")).unwrap();
+    fs::write(
+        tmp.path().join("etc.2024-05-01_09-00-00.backup/fstab"),
+        "abcd",
+    )
+    .unwrap();
+    fs::write(tmp.path().join("notes.txt"), "unrelated").unwrap();
+    tmp
+}
+
+fn stdout_lines(output: &std::process::Output) -> Vec<String> {
+    String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .map(str::to_owned)
+        .collect()
+}
+
+#[test]
+fn lists_backups_grouped_and_sorted() {
+    let tmp = backups();
+
+    let output = common::run(tmp.path(), &["list", "."]);
+    assert!(output.status.success(), "{output:?}");
+
+    let lines = stdout_lines(&output);
+    assert_eq!(lines.len(), 4, "{lines:?}");
+    assert!(lines[0].starts_with("NAME"));
+    assert!(lines[1].starts_with("etc") && lines[1].contains("directory"));
+    assert!(lines[1].contains("4 B"));
+    assert!(lines[2].contains("2024-05-01 10:00:00") && lines[2].contains("3 B"));
+    assert!(lines[3].contains("2024-05-02 10:00:00") && lines[3].contains("5 B"));
+    assert!(!lines.iter().any(|line| line.contains("notes.txt")));
+}
+
+#[test]
+fn filters_by_name_and_includes_unrelated_with_all() {
+    let tmp = backups();
+
+    let output = common::run(tmp.path(), &["l", "--name", "h*s"]);
+    let lines = stdout_lines(&output);
+    assert_eq!(lines.len(), 3, "{lines:?}");
+    assert!(lines[1..].iter().all(|line| line.starts_with("hosts")));
+
+    let output = common::run(tmp.path(), &["l", "--all"]);
+    assert!(stdout_lines(&output)
+        .iter()
+        .any(|line| line.starts_with("notes.txt")));
+}
+
+#[test]
+fn prints_json() {
+    let tmp = backups();
+
+    let output = common::run(tmp.path(), &["l", "--json", "--name", "etc"]);
+    assert!(output.status.success(), "{output:?}");
+
+    let json: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    let entries = json.as_array().unwrap();
+    assert_eq!(entries.len(), 1);
+    assert_eq!(entries[0]["name"], "etc");
+    assert_eq!(entries[0]["kind"], "directory");
+    assert_eq!(entries[0]["size"], 4);
+    assert_eq!(entries[0]["timestamp"], "2024-05-01T09:00:00");
+}
+
+#[test]
+fn backups_from_the_same_second_are_ordered_by_counter() {
+    let tmp = TempDir::new().unwrap();
+    fs::write(
+        tmp.path().join("hosts.2024-05-01_10-00-00_10.backup"),
+        "333",
+    )
+    .unwrap();
+    fs::write(tmp.path().join("hosts.2024-05-01_10-00-00.backup"), "1").unwrap();
+    fs::write(tmp.path().join("hosts.2024-05-01_10-00-00_2.backup"), "22").unwrap();
+
+    let output = common::run(tmp.path(), &["list"]);
+    assert!(output.status.success(), "{output:?}");
+
+    let lines = stdout_lines(&output);
+    assert_eq!(lines.len(), 4, "{lines:?}");
+    assert!(lines[1].contains("1 B"), "{lines:?}");
+    assert!(lines[2].contains("2 B"), "{lines:?}");
+    assert!(lines[3].contains("3 B"), "{lines:?}");
+}
@@ -0,0 +1,76 @@
+#![cfg(unix)]
+
+mod common;
+
+use std::fs::{self, File};
+use std::os::fd::AsRawFd;
+use std::path::Path;
+use std::process::Command;
+use std::time::{Duration, Instant};
+
+use tempfile::TempDir;
+
+/// Creates a project and the lock file of its backups into `backups`, held by
+/// this process.
+fn locked_project(root: &Path) -> File {
+    fs::create_dir_all(root.join("project")).unwrap();
+    fs::write(root.join("project/notes"), "notes").unwrap();
+    fs::create_dir(root.join("backups")).unwrap();
+    let lock = root.join("backups/.project.backup.lock");
+    fs::write(&lock, std::process::id().to_string()).unwrap();
+    let file = File::open(&lock).unwrap();
+    assert_eq!(
+        unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX | libc::LOCK_NB) },
+        0
+    );
+    file
+}
+
+#[test]
+fn a_locked_backup_fails_with_status_6() {
+    let tmp = TempDir::new().unwrap();
+    let lock = locked_project(tmp.path());
+
+    let output = common::run(tmp.path(), &["b", "project", "backups"]);
+    assert_eq!(output.status.code(), Some(6), "{output:?}");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains(&format!(
+            ".project.backup.lock': Locked by another backup (process {})",
+            std::process::id()
+        )),
+        "{stderr}"
+    );
+
+    let started = Instant::now();
+    let output = common::run(tmp.path(), &["b", "--wait-lock", "1", "project", "backups"]);
+    assert_eq!(output.status.code(), Some(6), "{output:?}");
+    assert!(started.elapsed() >= Duration::from_secs(1));
+    assert_eq!(fs::read_dir(tmp.path().join("backups")).unwrap().count(), 1);
+
+    drop(lock);
+    let output = common::run(tmp.path(), &["b", "project", "other"]);
+    assert!(output.status.success(), "{output:?}");
+}
+
+#[test]
+fn stale_locks_are_taken_over_and_locks_removed() {
+    let tmp = TempDir::new().unwrap();
+    drop(locked_project(tmp.path()));
+    let mut child = Command::new("true").spawn().unwrap();
+    child.wait().unwrap();
+    let lock = tmp.path().join("backups/.project.backup.lock");
+    fs::write(&lock, child.id().to_string()).unwrap();
+
+    let output = common::run(tmp.path(), &["b", "project", "backups"]);
+    assert!(output.status.success(), "{output:?}");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("Removing stale lock"), "{stderr}");
+    assert!(!lock.exists());
+    let backup = String::from_utf8_lossy(&output.stdout).trim().to_owned();
+    assert!(backup.contains("/backups/project."), "{backup}");
+    assert_eq!(
+        fs::read_to_string(Path::new(&backup).join("notes")).unwrap(),
+        "notes"
+    );
+}
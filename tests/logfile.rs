@@ -0,0 +1,94 @@
+mod common;
+
+use std::fs;
+
+use tempfile::TempDir;
+
+#[test]
+fn runs_are_logged_whatever_the_verbosity() {
+    let tmp = TempDir::new().unwrap();
+    fs::create_dir(tmp.path().join("data")).unwrap();
+    fs::write(tmp.path().join("data/notes.txt"), "notes").unwrap();
+
+    let output = common::run(
+        tmp.path(),
+        &["b", "--quiet", "--log-file", "backup.log", "data", "out"],
+    );
+    assert!(output.status.success(), "{output:?}");
+    let output = common::run(
+        tmp.path(),
+        &["b", "--log-file", "backup.log", "missing", "out"],
+    );
+    assert_eq!(output.status.code(), Some(1), "{output:?}");
+
+    let log = fs::read_to_string(tmp.path().join("backup.log")).unwrap();
+    let lines: Vec<_> = log.lines().collect();
+    assert!(
+        lines[0].ends_with("] started: backup b --quiet --log-file backup.log data out"),
+        "{log}"
+    );
+    assert!(
+        log.contains(" info [") && log.contains("finished with exit status 0\n"),
+        "{log}"
+    );
+    assert!(log.contains(" error ["), "{log}");
+    assert!(log.ends_with("finished with exit status 1\n"), "{log}");
+}
+
+#[test]
+fn json_logs_are_rotated() {
+    let tmp = TempDir::new().unwrap();
+    fs::create_dir(tmp.path().join("data")).unwrap();
+    let args = [
+        "b",
+        "--log-file",
+        "backup.log",
+        "--log-format",
+        "json",
+        "--log-max-size",
+        "1K",
+        "--log-keep",
+        "2",
+        "data",
+        "out",
+    ];
+
+    for _ in 0..12 {
+        let output = common::run(tmp.path(), &args);
+        assert!(output.status.success(), "{output:?}");
+    }
+    let log = fs::read_to_string(tmp.path().join("backup.log")).unwrap();
+    assert!(log.len() <= 1024, "{log}");
+    for line in log.lines() {
+        let event: serde_json::Value = serde_json::from_str(line).unwrap();
+        assert!(event["message"].is_string(), "{event}");
+    }
+    assert!(tmp.path().join("backup.log.1").exists());
+    assert!(tmp.path().join("backup.log.2").exists());
+    assert!(!tmp.path().join("backup.log.3").exists());
+
+    let output = common::run(tmp.path(), &["b", "--log-format", "xml", "data", "out"]);
+    assert_eq!(output.status.code(), Some(2), "{output:?}");
+}
+
+#[test]
+fn syslog_coexists_with_the_log_file() {
+    let tmp = TempDir::new().unwrap();
+    fs::create_dir(tmp.path().join("data")).unwrap();
+
+    let output = common::run(
+        tmp.path(),
+        &[
+            "b",
+            "--log-syslog",
+            "--log-file",
+            "backup.log",
+            "data",
+            "out",
+        ],
+    );
+    // Whether or not the system log can be reached, the backup goes on.
+    assert!(output.status.success(), "{output:?}");
+    let log = fs::read_to_string(tmp.path().join("backup.log")).unwrap();
+    assert!(log.ends_with("finished with exit status 0\n"), "{log}");
+}
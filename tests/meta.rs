@@ -0,0 +1,111 @@
+mod common;
+
+use std::fs;
+
+use backup::backup::BackupType;
+use backup::meta;
+use tempfile::TempDir;
+
+fn setup() -> TempDir {
+    let tmp = TempDir::new().unwrap();
+    fs::create_dir_all(tmp.path().join("etc/app")).unwrap();
+    fs::write(tmp.path().join("etc/app/config"), "abc").unwrap();
+    fs::create_dir(tmp.path().join("backups")).unwrap();
+    tmp
+}
+
+fn backup(tmp: &TempDir, source: &str) -> std::path::PathBuf {
+    let output = common::run(tmp.path(), &["b", source, "backups"]);
+    assert!(output.status.success(), "{output:?}");
+    common::single_entry(&tmp.path().join("backups"))
+}
+
+#[test]
+fn file_backup_records_its_metadata() {
+    let tmp = setup();
+    let backup = backup(&tmp, "etc/app/config");
+
+    let meta = meta::read(&backup).unwrap().unwrap();
+    let source = fs::canonicalize(tmp.path().join("etc/app/config")).unwrap();
+    assert_eq!(meta.source, source);
+    assert_eq!(meta.backup_type, BackupType::FileDirectory);
+    assert_eq!((meta.files, meta.size), (1, 3));
+    assert_eq!(meta.version, env!("CARGO_PKG_VERSION"));
+    let checksum = meta.checksum.unwrap();
+    assert_eq!(checksum.algorithm, "sha256");
+    assert!(checksum.digest.starts_with("ba7816bf"));
+
+    let timestamp = meta.created.format("%Y-%m-%d_%H-%M-%S").to_string();
+    assert!(backup.to_string_lossy().contains(&timestamp));
+    let json = fs::read_to_string(meta::path(&backup)).unwrap();
+    let rfc3339 = meta
+        .created
+        .format("\"created\": \"%Y-%m-%dT%H:%M:%S")
+        .to_string();
+    assert!(json.contains(&rfc3339), "{json}");
+}
+
+#[test]
+fn restores_to_the_original_path() {
+    let tmp = setup();
+    let backup = backup(&tmp, "etc");
+    fs::remove_dir_all(tmp.path().join("etc")).unwrap();
+
+    let output = common::run(
+        tmp.path(),
+        &["r", "--original-path", backup.to_str().unwrap()],
+    );
+    assert!(output.status.success(), "{output:?}");
+    assert_eq!(
+        fs::read_to_string(tmp.path().join("etc/app/config")).unwrap(),
+        "abc"
+    );
+}
+
+#[test]
+fn original_path_requires_metadata_and_no_target() {
+    let tmp = setup();
+    let backup = backup(&tmp, "etc/app/config");
+
+    let output = common::run(
+        tmp.path(),
+        &[
+            "r",
+            "--original-path",
+            backup.to_str().unwrap(),
+            "elsewhere",
+        ],
+    );
+    assert_eq!(output.status.code(), Some(2), "{output:?}");
+
+    fs::remove_file(meta::path(&backup)).unwrap();
+    let output = common::run(
+        tmp.path(),
+        &["r", "--original-path", backup.to_str().unwrap()],
+    );
+    assert_eq!(output.status.code(), Some(1), "{output:?}");
+    assert!(String::from_utf8_lossy(&output.stderr).contains("No metadata file"));
+
+    let output = common::run(tmp.path(), &["r", backup.to_str().unwrap(), "restored"]);
+    assert!(output.status.success(), "{output:?}");
+}
+
+#[test]
+fn list_shows_the_recorded_source() {
+    let tmp = setup();
+    backup(&tmp, "etc");
+    let source = fs::canonicalize(tmp.path().join("etc")).unwrap();
+
+    let output = common::run(tmp.path(), &["list", "backups"]);
+    assert!(output.status.success(), "{output:?}");
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.lines().next().unwrap().ends_with("SOURCE"));
+    assert!(stdout.contains(&*source.to_string_lossy()), "{stdout}");
+
+    let output = common::run(tmp.path(), &["list", "--json", "backups"]);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(
+        stdout.contains("\"backup_type\": \"directory_directory\""),
+        "{stdout}"
+    );
+}
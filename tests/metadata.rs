@@ -0,0 +1,177 @@
+#![cfg(unix)]
+
+mod common;
+
+use std::fs;
+use std::os::unix::fs::PermissionsExt;
+use std::path::Path;
+
+use filetime::FileTime;
+use tempfile::TempDir;
+
+const MTIME: i64 = 1_000_000_000;
+
+/// Creates `project/secret` with mode 0600 and a known modification time,
+/// inside a directory with mode 0750 and the same time.
+fn private_tree(root: &Path) {
+    let project = root.join("project");
+    fs::create_dir(&project).unwrap();
+    fs::write(project.join("secret"), "hunter2").unwrap();
+    fs::set_permissions(project.join("secret"), fs::Permissions::from_mode(0o600)).unwrap();
+    fs::set_permissions(&project, fs::Permissions::from_mode(0o750)).unwrap();
+    let mtime = FileTime::from_unix_time(MTIME, 0);
+    filetime::set_file_mtime(project.join("secret"), mtime).unwrap();
+    filetime::set_file_mtime(&project, mtime).unwrap();
+}
+
+fn mode_and_mtime(path: &Path) -> (u32, i64) {
+    let metadata = fs::metadata(path).unwrap();
+    (
+        metadata.permissions().mode() & 0o7777,
+        FileTime::from_last_modification_time(&metadata).unix_seconds(),
+    )
+}
+
+fn find_backup(dir: &Path) -> std::path::PathBuf {
+    fs::read_dir(dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .find(|path| path.to_string_lossy().contains(".backup") && !common::is_sidecar(path))
+        .unwrap()
+}
+
+#[test]
+fn mode_and_mtime_round_trip_through_a_copy() {
+    let tmp = TempDir::new().unwrap();
+    private_tree(tmp.path());
+
+    let output = common::run(tmp.path(), &["b", "project"]);
+    assert!(output.status.success(), "{output:?}");
+    let backup = find_backup(tmp.path());
+    assert_eq!(mode_and_mtime(&backup.join("secret")), (0o600, MTIME));
+    assert_eq!(mode_and_mtime(&backup), (0o750, MTIME));
+
+    fs::rename(tmp.path().join("project"), tmp.path().join("original")).unwrap();
+    let output = common::run(tmp.path(), &["r", backup.to_str().unwrap()]);
+    assert!(output.status.success(), "{output:?}");
+    let restored = tmp.path().join("project");
+    assert_eq!(mode_and_mtime(&restored.join("secret")), (0o600, MTIME));
+    assert_eq!(mode_and_mtime(&restored), (0o750, MTIME));
+}
+
+#[test]
+fn mode_and_mtime_round_trip_through_an_archive() {
+    let tmp = TempDir::new().unwrap();
+    private_tree(tmp.path());
+
+    let output = common::run(tmp.path(), &["b", "-c", "zstd", "project"]);
+    assert!(output.status.success(), "{output:?}");
+    let backup = find_backup(tmp.path());
+
+    fs::rename(tmp.path().join("project"), tmp.path().join("original")).unwrap();
+    let output = common::run(tmp.path(), &["r", backup.to_str().unwrap()]);
+    assert!(output.status.success(), "{output:?}");
+    let restored = tmp.path().join("project");
+    assert_eq!(mode_and_mtime(&restored.join("secret")), (0o600, MTIME));
+    assert_eq!(mode_and_mtime(&restored), (0o750, MTIME));
+}
+
+#[test]
+fn single_file_backup_keeps_mtime() {
+    let tmp = TempDir::new().unwrap();
+    private_tree(tmp.path());
+
+    let output = common::run(tmp.path(), &["b", "project/secret", "."]);
+    assert!(output.status.success(), "{output:?}");
+    assert_eq!(mode_and_mtime(&find_backup(tmp.path())), (0o600, MTIME));
+}
+
+#[test]
+fn no_preserve_leaves_the_copy_time() {
+    let tmp = TempDir::new().unwrap();
+    private_tree(tmp.path());
+
+    let output = common::run(tmp.path(), &["b", "--no-preserve", "project"]);
+    assert!(output.status.success(), "{output:?}");
+    let backup = find_backup(tmp.path());
+    assert_ne!(mode_and_mtime(&backup.join("secret")).1, MTIME);
+    assert_ne!(mode_and_mtime(&backup).1, MTIME);
+}
+
+fn owner(path: &Path) -> (u32, u32) {
+    use std::os::unix::fs::MetadataExt;
+
+    let metadata = fs::symlink_metadata(path).unwrap();
+    (metadata.uid(), metadata.gid())
+}
+
+/// Gives the private tree an unusual owner, returning `false` if the process
+/// lacks the privilege to do so.
+fn chown_tree(root: &Path) -> bool {
+    let project = root.join("project");
+    std::os::unix::fs::chown(&project, Some(1234), Some(5678)).is_ok()
+        && std::os::unix::fs::chown(project.join("secret"), Some(1234), Some(5678)).is_ok()
+}
+
+#[test]
+fn preserve_owner_round_trips_through_a_copy() {
+    let tmp = TempDir::new().unwrap();
+    private_tree(tmp.path());
+    if !chown_tree(tmp.path()) {
+        // Running without the privilege to change owners.
+        return;
+    }
+
+    let output = common::run(tmp.path(), &["b", "--preserve-owner", "project"]);
+    assert!(output.status.success(), "{output:?}");
+    let backup = find_backup(tmp.path());
+    assert_eq!(owner(&backup), (1234, 5678));
+    assert_eq!(owner(&backup.join("secret")), (1234, 5678));
+    assert_eq!(mode_and_mtime(&backup.join("secret")), (0o600, MTIME));
+
+    fs::rename(tmp.path().join("project"), tmp.path().join("original")).unwrap();
+    let output = common::run(
+        tmp.path(),
+        &["r", "--preserve-owner", backup.to_str().unwrap()],
+    );
+    assert!(output.status.success(), "{output:?}");
+    assert_eq!(owner(&tmp.path().join("project/secret")), (1234, 5678));
+}
+
+#[test]
+fn preserve_owner_round_trips_through_an_archive() {
+    let tmp = TempDir::new().unwrap();
+    private_tree(tmp.path());
+    if !chown_tree(tmp.path()) {
+        // Running without the privilege to change owners.
+        return;
+    }
+
+    let output = common::run(tmp.path(), &["b", "-c", "gzip", "project"]);
+    assert!(output.status.success(), "{output:?}");
+    let backup = find_backup(tmp.path());
+
+    fs::rename(tmp.path().join("project"), tmp.path().join("original")).unwrap();
+    let output = common::run(
+        tmp.path(),
+        &["r", "--preserve-owner", backup.to_str().unwrap()],
+    );
+    assert!(output.status.success(), "{output:?}");
+    assert_eq!(owner(&tmp.path().join("project")), (1234, 5678));
+    assert_eq!(owner(&tmp.path().join("project/secret")), (1234, 5678));
+}
+
+#[test]
+fn owners_are_not_preserved_by_default() {
+    let tmp = TempDir::new().unwrap();
+    private_tree(tmp.path());
+    if !chown_tree(tmp.path()) {
+        // Running without the privilege to change owners.
+        return;
+    }
+
+    let output = common::run(tmp.path(), &["b", "project"]);
+    assert!(output.status.success(), "{output:?}");
+    let backup = find_backup(tmp.path());
+    assert_ne!(owner(&backup.join("secret")), (1234, 5678));
+}
@@ -0,0 +1,87 @@
+mod common;
+
+use std::fs;
+
+use tempfile::TempDir;
+
+#[test]
+fn stdin_backups_count_what_they_wrote() {
+    let tmp = TempDir::new().unwrap();
+    let output = common::run_with_stdin(
+        tmp.path(),
+        &[
+            "b",
+            "--metrics-file",
+            "backup.prom",
+            "-",
+            "out",
+            "--name",
+            "x.sql",
+        ],
+        b"hello\n",
+    );
+    assert!(output.status.success(), "{output:?}");
+
+    let text = fs::read_to_string(tmp.path().join("backup.prom")).unwrap();
+    for line in [
+        "backup_last_run_files{source=\"-\"} 1\n",
+        "backup_last_run_bytes{source=\"-\"} 6\n",
+    ] {
+        assert!(text.contains(line), "{line} in {text}");
+    }
+}
+
+#[test]
+fn backups_share_the_metrics_file() {
+    let tmp = TempDir::new().unwrap();
+    for source in ["photos", "notes"] {
+        fs::create_dir(tmp.path().join(source)).unwrap();
+        fs::write(tmp.path().join(source).join("a.txt"), "12345").unwrap();
+    }
+    fs::write(tmp.path().join("backup.prom"), "other_metric 7\n").unwrap();
+    let photos = tmp.path().join("photos").canonicalize().unwrap();
+    let notes = tmp.path().join("notes").canonicalize().unwrap();
+
+    for source in ["photos", "notes"] {
+        let output = common::run(
+            tmp.path(),
+            &["b", "--metrics-file", "backup.prom", source, "out"],
+        );
+        assert!(output.status.success(), "{output:?}");
+    }
+    let output = common::run(
+        tmp.path(),
+        &["b", "--metrics-file", "backup.prom", "missing", "out"],
+    );
+    assert_eq!(output.status.code(), Some(1), "{output:?}");
+
+    let text = fs::read_to_string(tmp.path().join("backup.prom")).unwrap();
+    assert!(text.starts_with("other_metric 7\n"), "{text}");
+    for line in [
+        format!(
+            "backup_last_run_files{{source=\"{}\"}} 1\n",
+            photos.display()
+        ),
+        format!(
+            "backup_last_run_bytes{{source=\"{}\"}} 5\n",
+            notes.display()
+        ),
+        format!(
+            "backup_last_run_failed{{source=\"{}\"}} 0\n",
+            notes.display()
+        ),
+        "# TYPE backup_last_run_failed gauge\n".to_owned(),
+    ] {
+        assert_eq!(text.matches(&line).count(), 1, "{line} in {text}");
+    }
+    let missing = tmp.path().canonicalize().unwrap().join("missing");
+    let failed = format!(
+        "backup_last_run_failed{{source=\"{}\"}} 1\n",
+        missing.display()
+    );
+    assert!(text.contains(&failed), "{text}");
+    assert!(!text.contains(&format!(
+        "backup_last_success_timestamp_seconds{{source=\"{}\"}}",
+        missing.display()
+    )));
+}
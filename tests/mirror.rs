@@ -0,0 +1,131 @@
+mod common;
+
+use std::fs;
+
+use std::path::Path;
+
+use tempfile::TempDir;
+
+/// Builds a directory of two files, one of them in a subdirectory.
+fn data(root: &Path) {
+    fs::create_dir_all(root.join("sub")).unwrap();
+    fs::write(root.join("a.txt"), "alpha").unwrap();
+    fs::write(root.join("sub/b.txt"), "beta").unwrap();
+}
+
+#[test]
+fn mirror_requires_init_then_copies_only_changes() {
+    let tmp = TempDir::new().unwrap();
+    let source = tmp.path().join("data");
+    data(&source);
+    fs::create_dir(tmp.path().join("mirror")).unwrap();
+    fs::write(tmp.path().join("mirror/precious"), "keep").unwrap();
+
+    let output = common::run(tmp.path(), &["b", "--mirror", "data", "mirror"]);
+    assert_eq!(output.status.code(), Some(1), "{output:?}");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("Not a mirror of this source"), "{stderr}");
+    assert!(!tmp.path().join("mirror/.backup-mirror").exists());
+
+    let output = common::run(tmp.path(), &["b", "--mirror", "--init", "data", "mirror"]);
+    assert!(output.status.success(), "{output:?}");
+    let mirror = tmp.path().join("mirror");
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout).trim(),
+        mirror.canonicalize().unwrap().to_string_lossy()
+    );
+    assert_eq!(fs::read_to_string(mirror.join("a.txt")).unwrap(), "alpha");
+    assert_eq!(
+        fs::read_to_string(mirror.join("sub/b.txt")).unwrap(),
+        "beta"
+    );
+    assert!(mirror.join(".backup-mirror").is_file());
+    assert!(mirror.join("precious").is_file());
+    assert_eq!(fs::read_dir(tmp.path()).unwrap().count(), 2);
+
+    fs::write(source.join("a.txt"), "changed contents").unwrap();
+    fs::write(source.join("new.txt"), "new").unwrap();
+    let output = common::run(tmp.path(), &["b", "-v", "--mirror", "data", "mirror"]);
+    assert!(output.status.success(), "{output:?}");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("b.txt (up to date)"), "{stderr}");
+    assert!(!stderr.contains("a.txt (up to date)"), "{stderr}");
+    assert!(stderr.contains("3 files processed"), "{stderr}");
+    assert_eq!(
+        fs::read_to_string(mirror.join("a.txt")).unwrap(),
+        "changed contents"
+    );
+    assert!(mirror.join("new.txt").is_file());
+    assert!(mirror.join("precious").is_file());
+}
+
+#[test]
+fn delete_removes_what_left_the_source_except_excluded_entries() {
+    let tmp = TempDir::new().unwrap();
+    let source = tmp.path().join("data");
+    fs::create_dir_all(source.join("old/deep")).unwrap();
+    fs::write(source.join("old/deep/file"), "old").unwrap();
+    fs::write(source.join("kept.txt"), "kept").unwrap();
+    fs::write(source.join("scratch.tmp"), "scratch").unwrap();
+
+    let output = common::run(tmp.path(), &["b", "--mirror", "--init", "data", "mirror"]);
+    assert!(output.status.success(), "{output:?}");
+    let mirror = tmp.path().join("mirror");
+    fs::remove_dir_all(source.join("old")).unwrap();
+    fs::remove_file(source.join("scratch.tmp")).unwrap();
+    fs::write(mirror.join("notes.tmp"), "mirror only").unwrap();
+
+    let args = ["--mirror", "--delete", "--exclude", "notes.tmp"];
+    let output = common::run(
+        tmp.path(),
+        &[&["b", "-n"], &args[..], &["data", "mirror"]].concat(),
+    );
+    assert!(output.status.success(), "{output:?}");
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("removed: old\n"), "{stdout}");
+    assert!(stdout.contains("removed: scratch.tmp\n"), "{stdout}");
+    assert!(!stdout.contains("removed: old/"), "{stdout}");
+    assert!(!stdout.contains("notes.tmp"), "{stdout}");
+    assert!(stdout.contains("up to date: 1 files"), "{stdout}");
+    assert!(stdout.contains("mirror: yes"), "{stdout}");
+    assert!(mirror.join("old/deep/file").is_file());
+
+    let output = common::run(
+        tmp.path(),
+        &[&["b", "-v"], &args[..], &["data", "mirror"]].concat(),
+    );
+    assert!(output.status.success(), "{output:?}");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("1 up to date, 2 removed"), "{stderr}");
+    assert!(!mirror.join("old").exists());
+    assert!(!mirror.join("scratch.tmp").exists());
+    assert!(mirror.join("kept.txt").is_file());
+    assert!(mirror.join("notes.tmp").is_file());
+    assert!(mirror.join(".backup-mirror").is_file());
+}
+
+#[test]
+fn mirrors_are_tied_to_their_source() {
+    let tmp = TempDir::new().unwrap();
+    data(&tmp.path().join("data"));
+    data(&tmp.path().join("other"));
+
+    let output = common::run(tmp.path(), &["b", "--mirror", "--init", "data", "mirror"]);
+    assert!(output.status.success(), "{output:?}");
+    let output = common::run(tmp.path(), &["b", "-n", "--mirror", "other", "mirror"]);
+    assert_eq!(output.status.code(), Some(1), "{output:?}");
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(
+        stdout.contains("mirror: no, the mirror is blocked"),
+        "{stdout}"
+    );
+
+    for args in [
+        &["b", "--delete", "data", "mirror"][..],
+        &["b", "--mirror", "data", "other", "mirror"],
+        &["b", "--mirror", "-c", "gzip", "data", "mirror"],
+    ] {
+        let output = common::run(tmp.path(), args);
+        assert_eq!(output.status.code(), Some(2), "{args:?} {output:?}");
+    }
+}
@@ -0,0 +1,236 @@
+mod common;
+
+use std::fs;
+
+use tempfile::TempDir;
+
+const FORMAT: &str = "{stem}-{timestamp}.bak{ext}";
+
+#[test]
+fn templates_name_list_and_restore_backups() {
+    let tmp = TempDir::new().unwrap();
+    fs::write(tmp.path().join("my-notes.txt"), "notes").unwrap();
+
+    let output = common::run(
+        tmp.path(),
+        &["b", "--name-format", FORMAT, "my-notes.txt", "backups"],
+    );
+    assert!(output.status.success(), "{output:?}");
+    let backup = common::single_entry(&tmp.path().join("backups"));
+    let name = backup.file_name().unwrap().to_string_lossy().into_owned();
+    assert!(
+        name.starts_with("my-notes-") && name.ends_with(".bak.txt"),
+        "{name}"
+    );
+
+    // Without the template, the backup is not recognized.
+    let output = common::run(tmp.path(), &["l", "backups"]);
+    assert_eq!(String::from_utf8_lossy(&output.stdout).lines().count(), 1);
+    let output = common::run(tmp.path(), &["l", "--name-format", FORMAT, "backups"]);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(
+        stdout.lines().nth(1).unwrap().starts_with("my-notes.txt "),
+        "{stdout}"
+    );
+
+    fs::create_dir(tmp.path().join("restored")).unwrap();
+    let output = common::run(
+        tmp.path(),
+        &[
+            "r",
+            "--name-format",
+            FORMAT,
+            backup.to_str().unwrap(),
+            "restored",
+        ],
+    );
+    assert!(output.status.success(), "{output:?}");
+    assert_eq!(
+        fs::read_to_string(tmp.path().join("restored/my-notes.txt")).unwrap(),
+        "notes"
+    );
+}
+
+#[test]
+fn invalid_templates_are_usage_errors() {
+    let tmp = TempDir::new().unwrap();
+    fs::write(tmp.path().join("hosts"), "hosts").unwrap();
+
+    let output = common::run(tmp.path(), &["b", "--name-format", "{name}.bak", "hosts"]);
+    assert_eq!(output.status.code(), Some(2), "{output:?}");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("{timestamp} or {date} is required"),
+        "{stderr}"
+    );
+}
+
+#[test]
+fn timestamps_follow_the_format_in_utc() {
+    let tmp = TempDir::new().unwrap();
+    fs::write(tmp.path().join("hosts"), "hosts").unwrap();
+    fs::create_dir(tmp.path().join("backups")).unwrap();
+    fs::write(
+        tmp.path().join("backups/hosts.2024-05-01_10-00-00.backup"),
+        "older",
+    )
+    .unwrap();
+
+    let before = chrono::Utc::now().format("%Y%m%dT%H%M").to_string();
+    let output = common::run(
+        tmp.path(),
+        &[
+            "b",
+            "--utc",
+            "--timestamp-format",
+            "%Y%m%dT%H%M%SZ",
+            "hosts",
+            "backups",
+        ],
+    );
+    assert!(output.status.success(), "{output:?}");
+    let after = chrono::Utc::now().format("%Y%m%dT%H%M").to_string();
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let name = stdout.trim_end().rsplit('/').next().unwrap();
+    assert!(
+        [before, after]
+            .iter()
+            .any(|minute| name.starts_with(&format!("hosts.{minute}"))),
+        "{name}"
+    );
+    assert!(name.ends_with("Z.backup"), "{name}");
+
+    let output = common::run(
+        tmp.path(),
+        &["l", "--timestamp-format", "%Y%m%dT%H%M%SZ", "backups"],
+    );
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert_eq!(stdout.lines().count(), 3, "{stdout}");
+
+    let output = common::run(
+        tmp.path(),
+        &["b", "--timestamp-format", "%H:%M", "hosts", "backups"],
+    );
+    assert_eq!(output.status.code(), Some(2), "{output:?}");
+}
+
+#[test]
+fn backups_without_timestamp_are_replaced_only_when_forced() {
+    let tmp = TempDir::new().unwrap();
+    fs::write(tmp.path().join("notes.txt"), "first").unwrap();
+
+    let output = common::run(tmp.path(), &["b", "--no-timestamp", "notes.txt", "."]);
+    assert!(output.status.success(), "{output:?}");
+    assert_eq!(
+        fs::read_to_string(tmp.path().join("notes.txt.backup")).unwrap(),
+        "first"
+    );
+
+    fs::write(tmp.path().join("notes.txt"), "second").unwrap();
+    let output = common::run(tmp.path(), &["b", "--no-timestamp", "notes.txt", "."]);
+    assert_eq!(output.status.code(), Some(1), "{output:?}");
+    assert_eq!(
+        fs::read_to_string(tmp.path().join("notes.txt.backup")).unwrap(),
+        "first"
+    );
+
+    let output = common::run(
+        tmp.path(),
+        &[
+            "b",
+            "--no-timestamp",
+            "--suffix",
+            ".bak",
+            "-f",
+            "notes.txt",
+            ".",
+        ],
+    );
+    assert!(output.status.success(), "{output:?}");
+    let output = common::run(
+        tmp.path(),
+        &["b", "--no-timestamp", "--force", "notes.txt", "."],
+    );
+    assert!(output.status.success(), "{output:?}");
+    assert_eq!(
+        fs::read_to_string(tmp.path().join("notes.txt.backup")).unwrap(),
+        "second"
+    );
+
+    // Restoring needs no option for the default suffix.
+    fs::create_dir(tmp.path().join("restored")).unwrap();
+    let output = common::run(tmp.path(), &["r", "notes.txt.backup", "restored"]);
+    assert!(output.status.success(), "{output:?}");
+    assert_eq!(
+        fs::read_to_string(tmp.path().join("restored/notes.txt")).unwrap(),
+        "second"
+    );
+    let output = common::run(
+        tmp.path(),
+        &[
+            "r",
+            "--no-timestamp",
+            "--suffix",
+            ".bak",
+            "--force",
+            "notes.txt.bak",
+            "restored",
+        ],
+    );
+    assert!(output.status.success(), "{output:?}");
+    assert_eq!(
+        fs::read_to_string(tmp.path().join("restored/notes.txt")).unwrap(),
+        "second"
+    );
+
+    let output = common::run(tmp.path(), &["b", "--suffix", ".bak", "notes.txt", "."]);
+    assert_eq!(output.status.code(), Some(2), "{output:?}");
+}
+
+#[test]
+fn unportable_names_are_sanitized_and_restored() {
+    let tmp = TempDir::new().unwrap();
+    let long = format!("{}.txt", "a".repeat(250));
+    for name in ["con", "a:b|c.", long.as_str()] {
+        fs::write(tmp.path().join(name), name).unwrap();
+        let output = common::run(tmp.path(), &["b", name, "backups"]);
+        assert!(output.status.success(), "{output:?}");
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert!(stderr.contains("warning"), "{stderr}");
+    }
+
+    let mut names: Vec<_> = fs::read_dir(tmp.path().join("backups"))
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+        .collect();
+    names.sort();
+    assert!(names.iter().all(|name| name.len() <= 255), "{names:?}");
+    assert!(
+        names.iter().any(|name| name.starts_with("a_b_c_.")),
+        "{names:?}"
+    );
+    assert!(
+        names.iter().any(|name| name.starts_with("con_.")),
+        "{names:?}"
+    );
+
+    let output = common::run(tmp.path(), &["l", "backups"]);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert_eq!(stdout.lines().count(), 4, "{stdout}");
+
+    fs::create_dir(tmp.path().join("restored")).unwrap();
+    for name in names
+        .iter()
+        .filter(|name| name.ends_with(".backup") && !name.ends_with(".latest.backup"))
+    {
+        let backup = format!("backups/{name}");
+        let output = common::run(tmp.path(), &["r", &backup, "restored"]);
+        assert!(output.status.success(), "{output:?}");
+    }
+    for name in ["con", "a:b|c.", long.as_str()] {
+        assert_eq!(
+            fs::read_to_string(tmp.path().join("restored").join(name)).unwrap(),
+            name
+        );
+    }
+}
@@ -0,0 +1,95 @@
+#![cfg(unix)]
+
+mod common;
+
+use std::fs;
+
+use tempfile::TempDir;
+
+/// Command appending the summary and the outcome told to it to `notified`.
+const NOTIFY: &str = "cat >> notified; \
+                      echo \"$BACKUP_RESULT $BACKUP_STATUS $BACKUP_SOURCE $BACKUP_TARGET $BACKUP_ERROR_CODE\" >> notified";
+
+#[test]
+fn backups_notify_their_outcome() {
+    let tmp = TempDir::new().unwrap();
+    fs::create_dir(tmp.path().join("data")).unwrap();
+    fs::write(tmp.path().join("data/notes.txt"), "notes").unwrap();
+
+    let output = common::run(tmp.path(), &["b", "--notify-cmd", NOTIFY, "data", "out"]);
+    assert!(output.status.success(), "{output:?}");
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert_eq!(stdout.lines().count(), 1, "{stdout}");
+    let notified = fs::read_to_string(tmp.path().join("notified")).unwrap();
+    assert!(
+        notified.starts_with("Backup of data to out on "),
+        "{notified}"
+    );
+    assert!(
+        notified.ends_with(" succeeded.\nsuccess 0 data out \n"),
+        "{notified}"
+    );
+
+    // Failures before anything is copied notify too.
+    fs::remove_file(tmp.path().join("notified")).unwrap();
+    let output = common::run(
+        tmp.path(),
+        &[
+            "b",
+            "--notify-cmd",
+            NOTIFY,
+            "--suffix",
+            ".bak",
+            "data",
+            "out",
+        ],
+    );
+    assert_eq!(output.status.code(), Some(2), "{output:?}");
+    let notified = fs::read_to_string(tmp.path().join("notified")).unwrap();
+    assert!(
+        notified.ends_with("failure 2 data out invalid_option\n"),
+        "{notified}"
+    );
+    fs::remove_file(tmp.path().join("notified")).unwrap();
+    let output = common::run(tmp.path(), &["b", "--notify-cmd", NOTIFY, "missing", "out"]);
+    assert_eq!(output.status.code(), Some(1), "{output:?}");
+    let notified = fs::read_to_string(tmp.path().join("notified")).unwrap();
+    assert!(notified.contains(" failed.\n'missing'"), "{notified}");
+    assert!(
+        notified.ends_with("failure 1 missing out not_found\n"),
+        "{notified}"
+    );
+}
+
+#[test]
+fn notifications_are_filtered_and_their_failures_reported() {
+    let tmp = TempDir::new().unwrap();
+    fs::create_dir(tmp.path().join("data")).unwrap();
+
+    let output = common::run(
+        tmp.path(),
+        &[
+            "b",
+            "--notify-on",
+            "failure",
+            "--notify-cmd",
+            NOTIFY,
+            "data",
+            "out",
+        ],
+    );
+    assert!(output.status.success(), "{output:?}");
+    assert!(!tmp.path().join("notified").exists());
+
+    let output = common::run(
+        tmp.path(),
+        &["b", "--notify-cmd", "echo sent; exit 5", "data", "out"],
+    );
+    assert!(output.status.success(), "{output:?}");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("sent\n"), "{stderr}");
+    assert!(stderr.contains("exit status: 5"), "{stderr}");
+
+    let output = common::run(tmp.path(), &["b", "--notify-on", "never", "data", "out"]);
+    assert_eq!(output.status.code(), Some(2), "{output:?}");
+}
@@ -0,0 +1,114 @@
+mod common;
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use tempfile::TempDir;
+
+fn etc(root: &Path) {
+    fs::create_dir_all(root.join("etc/nginx/sites")).unwrap();
+    fs::write(root.join("etc/nginx/nginx.conf"), "nginx").unwrap();
+    fs::write(root.join("etc/nginx/mime.types"), "types").unwrap();
+    fs::write(root.join("etc/nginx/sites/default.conf"), "default").unwrap();
+    fs::write(root.join("etc/hosts"), "hosts").unwrap();
+}
+
+fn back_up(root: &Path, options: &[&str]) -> PathBuf {
+    let mut args = vec!["b"];
+    args.extend(options);
+    args.extend(["etc", "backups"]);
+    let output = common::run(root, &args);
+    assert!(output.status.success(), "{output:?}");
+    common::single_entry(&root.join("backups"))
+}
+
+fn restore(root: &Path, backup: &Path, options: &[&str]) -> std::process::Output {
+    let mut args = vec!["r"];
+    args.extend(options);
+    args.extend([backup.to_str().unwrap(), "restored"]);
+    common::run(root, &args)
+}
+
+#[test]
+fn single_files_are_restored_from_directories_and_archives() {
+    for options in [&[][..], &["-c", "zstd"]] {
+        let tmp = TempDir::new().unwrap();
+        etc(tmp.path());
+        let backup = back_up(tmp.path(), options);
+
+        let output = restore(tmp.path(), &backup, &["--path", "nginx/nginx.conf"]);
+        assert!(output.status.success(), "{output:?}");
+        let restored = tmp.path().join("restored");
+        assert_eq!(
+            fs::read_to_string(restored.join("nginx/nginx.conf")).unwrap(),
+            "nginx"
+        );
+        assert!(!restored.join("nginx/mime.types").exists());
+        assert!(!restored.join("hosts").exists());
+    }
+}
+
+#[test]
+fn globs_and_repeated_paths_select_several_entries() {
+    for options in [&[][..], &["-c", "gzip"]] {
+        let tmp = TempDir::new().unwrap();
+        etc(tmp.path());
+        let backup = back_up(tmp.path(), options);
+
+        let args = ["--path", "**/*.conf", "--path", "hosts"];
+        let output = restore(tmp.path(), &backup, &args);
+        assert!(output.status.success(), "{output:?}");
+        let restored = tmp.path().join("restored");
+        assert!(restored.join("nginx/nginx.conf").is_file());
+        assert!(restored.join("nginx/sites/default.conf").is_file());
+        assert!(restored.join("hosts").is_file());
+        assert!(!restored.join("nginx/mime.types").exists());
+
+        // A directory is restored with its contents.
+        fs::remove_dir_all(&restored).unwrap();
+        let output = restore(tmp.path(), &backup, &["--path", "nginx/sites"]);
+        assert!(output.status.success(), "{output:?}");
+        assert!(restored.join("nginx/sites/default.conf").is_file());
+        assert!(!restored.join("nginx/nginx.conf").exists());
+    }
+}
+
+#[test]
+fn existing_files_are_kept_unless_forced() {
+    let tmp = TempDir::new().unwrap();
+    etc(tmp.path());
+    let backup = back_up(tmp.path(), &[]);
+    fs::write(tmp.path().join("etc/hosts"), "edited").unwrap();
+    let args = |force| {
+        let mut args = vec!["--path", "hosts"];
+        args.extend(force);
+        // An existing target directory receives the backup under its
+        // original name, so the files land back in etc.
+        args.extend([backup.to_str().unwrap(), "."]);
+        args
+    };
+
+    let output = common::run(tmp.path(), &[&["r"][..], &args(None)].concat());
+    assert_eq!(output.status.code(), Some(1), "{output:?}");
+    let hosts = tmp.path().join("etc/hosts");
+    assert_eq!(fs::read_to_string(&hosts).unwrap(), "edited");
+
+    let output = common::run(tmp.path(), &[&["r"][..], &args(Some("-f"))].concat());
+    assert!(output.status.success(), "{output:?}");
+    assert_eq!(fs::read_to_string(&hosts).unwrap(), "hosts");
+    assert!(tmp.path().join("etc/nginx/mime.types").is_file());
+}
+
+#[test]
+fn unknown_paths_suggest_close_matches() {
+    let tmp = TempDir::new().unwrap();
+    etc(tmp.path());
+    let backup = back_up(tmp.path(), &[]);
+
+    let output = restore(tmp.path(), &backup, &["--path", "nginx/ngnix.conf"]);
+    assert_eq!(output.status.code(), Some(1), "{output:?}");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("No such path in the backup"), "{stderr}");
+    assert!(stderr.contains("nginx/nginx.conf"), "{stderr}");
+    assert!(!tmp.path().join("restored").exists());
+}
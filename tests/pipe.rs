@@ -0,0 +1,143 @@
+#![cfg(unix)]
+
+mod common;
+
+use std::fs;
+use std::path::Path;
+
+use tempfile::TempDir;
+
+/// Creates a directory `data` to back up and an empty `sent` directory in
+/// `root`.
+fn setup(root: &Path) {
+    fs::create_dir(root.join("data")).unwrap();
+    let blob: Vec<u8> = (0..300_000u32).map(|i| (i * 7 % 251) as u8).collect();
+    fs::write(root.join("data/blob.bin"), blob).unwrap();
+    fs::write(root.join("data/notes.txt"), "notes").unwrap();
+    fs::create_dir(root.join("sent")).unwrap();
+}
+
+/// Returns the entries of `dir` by name, in sorted order.
+fn names(dir: &Path) -> Vec<String> {
+    let mut names: Vec<_> = fs::read_dir(dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+        .collect();
+    names.sort();
+    names
+}
+
+#[test]
+fn backups_are_piped_to_and_from_commands() {
+    let tmp = TempDir::new().unwrap();
+    setup(tmp.path());
+
+    let output = common::run(
+        tmp.path(),
+        &[
+            "b",
+            "-c",
+            "gzip",
+            "--pipe-to",
+            "echo {source} >> sources; cat > sent/{filename}",
+            "data",
+        ],
+    );
+    assert!(output.status.success(), "{output:?}");
+    let archive = String::from_utf8_lossy(&output.stdout).trim().to_owned();
+    assert!(
+        archive.starts_with("data.") && archive.ends_with(".backup.tar.gz"),
+        "{archive}"
+    );
+    assert_eq!(
+        names(&tmp.path().join("sent")),
+        [
+            archive.clone(),
+            format!("{archive}.meta.json"),
+            format!("{archive}.sha256")
+        ]
+    );
+    assert_eq!(
+        fs::read_to_string(tmp.path().join("sources")).unwrap(),
+        "data\n".repeat(3)
+    );
+    let output = common::run(&tmp.path().join("sent"), &["verify", &archive]);
+    assert!(output.status.success(), "{output:?}");
+
+    let output = common::run(
+        tmp.path(),
+        &[
+            "r",
+            "--pipe-from",
+            "echo {source} > restored-source; cat sent/{filename}",
+            &archive,
+            "restored",
+        ],
+    );
+    assert!(output.status.success(), "{output:?}");
+    assert_eq!(
+        fs::read(tmp.path().join("restored/blob.bin")).unwrap(),
+        fs::read(tmp.path().join("data/blob.bin")).unwrap()
+    );
+    assert_eq!(
+        fs::read_to_string(tmp.path().join("restored-source")).unwrap(),
+        "data\n"
+    );
+
+    // Without {filename} only the backup itself goes through the command.
+    let output = common::run(tmp.path(), &["b", "--pipe-to", "cat > single", "data"]);
+    assert!(output.status.success(), "{output:?}");
+    let output = common::run(tmp.path(), &["list-contents", "single"]);
+    assert!(output.status.success(), "{output:?}");
+    assert!(String::from_utf8_lossy(&output.stdout).contains("notes.txt"));
+}
+
+#[test]
+fn failing_commands_fail_the_backup() {
+    let tmp = TempDir::new().unwrap();
+    setup(tmp.path());
+
+    let output = common::run(
+        tmp.path(),
+        &[
+            "b",
+            "--pipe-to",
+            "cat > /dev/null; echo 'upload refused' >&2; exit 3",
+            "data",
+        ],
+    );
+    assert_eq!(output.status.code(), Some(1), "{output:?}");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("upload refused"), "{stderr}");
+    assert!(stderr.contains("exit status: 3"), "{stderr}");
+
+    let output = common::run(
+        tmp.path(),
+        &["b", "--pipe-to", "head -c 10 > /dev/null", "data"],
+    );
+    assert_eq!(output.status.code(), Some(1), "{output:?}");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("exited before reading the whole backup"),
+        "{stderr}"
+    );
+
+    let output = common::run(
+        tmp.path(),
+        &[
+            "r",
+            "--pipe-from",
+            "cat sent/{filename}",
+            "data.backup.tar",
+            "out",
+        ],
+    );
+    assert_eq!(output.status.code(), Some(1), "{output:?}");
+    assert!(!tmp.path().join("out").exists());
+
+    let output = common::run(
+        tmp.path(),
+        &["b", "--pipe-to", "cat > /dev/null", "data", "sent"],
+    );
+    assert_eq!(output.status.code(), Some(2), "{output:?}");
+}
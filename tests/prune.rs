@@ -0,0 +1,186 @@
+mod common;
+
+use std::fs;
+
+use tempfile::TempDir;
+
+fn backups() -> TempDir {
+    let tmp = TempDir::new().unwrap();
+    for day in 1..=4 {
+        let name = format!("hosts.2024-05-0{day}_10-00-00.backup");
+        fs::write(tmp.path().join(name), "1234567890").unwrap();
+    }
+    fs::create_dir(tmp.path().join("etc.2024-05-01_09-00-00.backup")).unwrap();
+    fs::write(tmp.path().join("notes.txt"), "unrelated").unwrap();
+    tmp
+}
+
+fn names(tmp: &TempDir) -> Vec<String> {
+    let mut names: Vec<_> = fs::read_dir(tmp.path())
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+        .collect();
+    names.sort();
+    names
+}
+
+#[test]
+fn keeps_newest_backups_per_name() {
+    let tmp = backups();
+
+    let output = common::run(tmp.path(), &["prune", "--keep-last", "2", "."]);
+    assert!(output.status.success(), "{output:?}");
+    assert_eq!(
+        names(&tmp),
+        [
+            "etc.2024-05-01_09-00-00.backup",
+            "hosts.2024-05-03_10-00-00.backup",
+            "hosts.2024-05-04_10-00-00.backup",
+            "notes.txt",
+        ]
+    );
+    assert!(String::from_utf8_lossy(&output.stdout).contains("freed 20 B"));
+}
+
+#[test]
+fn dry_run_leaves_backups_in_place() {
+    let tmp = backups();
+    let before = names(&tmp);
+
+    let output = common::run(tmp.path(), &["prune", "--dry-run", "--keep-last", "1"]);
+    assert!(output.status.success(), "{output:?}");
+    assert_eq!(names(&tmp), before);
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert_eq!(stdout.matches("would remove").count(), 3, "{stdout}");
+    assert!(stdout.contains("would free 30 B"));
+}
+
+#[test]
+fn requires_a_retention_rule() {
+    let tmp = backups();
+
+    let output = common::run(tmp.path(), &["prune"]);
+    assert!(!output.status.success());
+    assert_eq!(names(&tmp).len(), 6);
+}
+
+fn recent_backup(tmp: &TempDir) -> String {
+    let timestamp = chrono::Local::now() - chrono::Duration::hours(1);
+    let name = format!("hosts.{}.backup", timestamp.format("%Y-%m-%d_%H-%M-%S"));
+    fs::write(tmp.path().join(&name), "recent").unwrap();
+    name
+}
+
+#[test]
+fn older_than_keeps_recent_and_newest_backups() {
+    let tmp = backups();
+    let recent = recent_backup(&tmp);
+
+    let output = common::run(tmp.path(), &["prune", "--older-than", "30d"]);
+    assert!(output.status.success(), "{output:?}");
+    assert_eq!(
+        names(&tmp),
+        [
+            "etc.2024-05-01_09-00-00.backup",
+            recent.as_str(),
+            "notes.txt"
+        ]
+    );
+}
+
+#[test]
+fn keep_rules_are_combined() {
+    let tmp = backups();
+    let recent = recent_backup(&tmp);
+
+    let args = ["prune", "--keep-last", "2", "--older-than", "2w"];
+    let output = common::run(tmp.path(), &args);
+    assert!(output.status.success(), "{output:?}");
+    assert_eq!(
+        names(&tmp),
+        [
+            "etc.2024-05-01_09-00-00.backup",
+            "hosts.2024-05-04_10-00-00.backup",
+            recent.as_str(),
+            "notes.txt",
+        ]
+    );
+}
+
+#[test]
+fn allow_empty_removes_every_expired_backup() {
+    let tmp = backups();
+
+    let args = ["prune", "--older-than", "1d", "--allow-empty"];
+    let output = common::run(tmp.path(), &args);
+    assert!(output.status.success(), "{output:?}");
+    assert_eq!(names(&tmp), ["notes.txt"]);
+}
+
+#[test]
+fn calendar_rules_keep_the_newest_backup_of_each_period() {
+    let tmp = backups();
+    fs::write(tmp.path().join("hosts.2024-04-30_23-00-00.backup"), "1").unwrap();
+
+    let args = ["prune", "--keep-monthly", "2", "--keep-daily", "1"];
+    let output = common::run(tmp.path(), &args);
+    assert!(output.status.success(), "{output:?}");
+    assert_eq!(
+        names(&tmp),
+        [
+            "etc.2024-05-01_09-00-00.backup",
+            "hosts.2024-04-30_23-00-00.backup",
+            "hosts.2024-05-04_10-00-00.backup",
+            "notes.txt",
+        ]
+    );
+}
+
+#[cfg(unix)]
+#[test]
+fn the_latest_backup_is_never_removed() {
+    let tmp = backups();
+    std::os::unix::fs::symlink(
+        "hosts.2024-05-02_10-00-00.backup",
+        tmp.path().join("hosts.latest.backup"),
+    )
+    .unwrap();
+
+    let args = ["prune", "--older-than", "1d", "--allow-empty"];
+    let output = common::run(tmp.path(), &args);
+    assert!(output.status.success(), "{output:?}");
+    assert_eq!(
+        names(&tmp),
+        [
+            "hosts.2024-05-02_10-00-00.backup",
+            "hosts.latest.backup",
+            "notes.txt",
+        ]
+    );
+}
+
+#[test]
+fn quiet_prints_nothing() {
+    let tmp = backups();
+
+    let output = common::run(tmp.path(), &["prune", "-q", "--keep-last", "1"]);
+    assert!(output.status.success(), "{output:?}");
+    assert!(output.stdout.is_empty(), "{output:?}");
+    assert!(output.stderr.is_empty(), "{output:?}");
+    assert_eq!(names(&tmp).len(), 3);
+}
+
+#[test]
+fn checksum_manifests_are_removed_with_their_backup() {
+    let tmp = backups();
+    fs::write(
+        tmp.path().join("hosts.2024-05-01_10-00-00.backup.sha256"),
+        "",
+    )
+    .unwrap();
+
+    let output = common::run(tmp.path(), &["prune", "--keep-last", "3"]);
+    assert!(output.status.success(), "{output:?}");
+    assert!(!names(&tmp).iter().any(|name| name.ends_with(".sha256")));
+}
@@ -0,0 +1,250 @@
+#![cfg(unix)]
+
+mod common;
+
+use std::ffi::OsString;
+use std::fs;
+use std::os::unix::fs::PermissionsExt;
+use std::path::Path;
+use std::process::Output;
+
+use tempfile::TempDir;
+
+/// Stand-in for `ssh` running the remote command locally from the directory
+/// `home` of the test, logging its arguments. With `FAIL` set, the
+/// connection drops after the first 1000 bytes sent.
+const FAKE_SSH: &str = r#"#!/bin/sh
+echo "$@" >> "$FAKE_SSH_HOME/../ssh.log"
+while [ "$1" != "--" ]; do shift; done
+cd "$FAKE_SSH_HOME" || exit 255
+if [ -n "$FAIL" ]; then
+    head -c 1000 | sh -c "$3"
+    exit 255
+fi
+exec sh -c "$3"
+"#;
+
+/// Sets up the fake `ssh` and the home directory of the remote host in
+/// `root`, with a directory `data` to back up.
+fn setup(root: &Path) {
+    fs::create_dir_all(root.join("bin")).unwrap();
+    fs::create_dir_all(root.join("home")).unwrap();
+    let ssh = root.join("bin/ssh");
+    fs::write(&ssh, FAKE_SSH).unwrap();
+    fs::set_permissions(&ssh, fs::Permissions::from_mode(0o755)).unwrap();
+    fs::create_dir(root.join("data")).unwrap();
+    let blob: Vec<u8> = (0..100_000u32).map(|i| (i * 7 % 251) as u8).collect();
+    fs::write(root.join("data/blob.bin"), blob).unwrap();
+    fs::write(root.join("data/notes.txt"), "notes").unwrap();
+}
+
+/// Runs the binary from `root` with the fake `ssh` first in the path.
+fn run(root: &Path, args: &[&str], fail: bool) -> Output {
+    let mut path = OsString::from(root.join("bin"));
+    path.push(":");
+    path.push(std::env::var_os("PATH").unwrap_or_default());
+    let home = root.join("home");
+    let mut vars = vec![
+        ("PATH", path.as_os_str()),
+        ("FAKE_SSH_HOME", home.as_os_str()),
+    ];
+    if fail {
+        vars.push(("FAIL", "1".as_ref()));
+    }
+    common::run_with_env(root, args, &vars)
+}
+
+/// Returns the entries of `dir` by name, in sorted order.
+fn names(dir: &Path) -> Vec<String> {
+    let mut names: Vec<_> = fs::read_dir(dir)
+        .map(|entries| {
+            entries
+                .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+                .collect()
+        })
+        .unwrap_or_default();
+    names.sort();
+    names
+}
+
+#[test]
+fn backups_are_sent_and_restored_over_ssh() {
+    let tmp = TempDir::new().unwrap();
+    setup(tmp.path());
+
+    let output = run(
+        tmp.path(),
+        &[
+            "b",
+            "-c",
+            "gzip",
+            "--ssh-option",
+            "Port=2222",
+            "data",
+            "admin@nas:backups/daily",
+        ],
+        false,
+    );
+    assert!(output.status.success(), "{output:?}");
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(
+        stdout.starts_with("admin@nas:backups/daily/data."),
+        "{stdout}"
+    );
+    let log = fs::read_to_string(tmp.path().join("ssh.log")).unwrap();
+    assert!(log.starts_with("-o Port=2222 -- admin@nas "), "{log}");
+
+    let dir = tmp.path().join("home/backups/daily");
+    let names = names(&dir);
+    assert_eq!(names.len(), 3, "{names:?}");
+    let archive = &names[0];
+    assert!(archive.ends_with(".backup.tar.gz"), "{names:?}");
+    assert_eq!(names[1], format!("{archive}.meta.json"));
+    assert_eq!(names[2], format!("{archive}.sha256"));
+    let output = common::run(&dir, &["verify", archive]);
+    assert!(output.status.success(), "{output:?}");
+
+    let source = format!("admin@nas:backups/daily/{archive}");
+    let output = run(tmp.path(), &["r", &source, "restored"], false);
+    assert!(output.status.success(), "{output:?}");
+    assert_eq!(
+        fs::read(tmp.path().join("restored/blob.bin")).unwrap(),
+        fs::read(tmp.path().join("data/blob.bin")).unwrap()
+    );
+
+    let output = run(tmp.path(), &["b", "data/notes.txt", "nas:"], false);
+    assert!(output.status.success(), "{output:?}");
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let name = stdout.trim().strip_prefix("nas:").unwrap();
+    assert_eq!(
+        fs::read_to_string(tmp.path().join("home").join(name)).unwrap(),
+        "notes"
+    );
+    let output = run(tmp.path(), &["r", stdout.trim()], false);
+    assert!(output.status.success(), "{output:?}");
+    assert_eq!(
+        fs::read_to_string(tmp.path().join("notes.txt")).unwrap(),
+        "notes"
+    );
+}
+
+#[test]
+fn failed_transfers_leave_nothing_behind() {
+    let tmp = TempDir::new().unwrap();
+    setup(tmp.path());
+
+    let output = run(tmp.path(), &["b", "data", "nas:backups"], true);
+    assert_eq!(output.status.code(), Some(1), "{output:?}");
+    assert_eq!(
+        names(&tmp.path().join("home/backups")),
+        Vec::<String>::new()
+    );
+
+    let output = run(
+        tmp.path(),
+        &["r", "nas:backups/data.backup.tar", "out"],
+        false,
+    );
+    assert_eq!(output.status.code(), Some(1), "{output:?}");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("No such file"), "{stderr}");
+    assert!(!tmp.path().join("out").exists());
+}
+
+#[test]
+fn existing_remote_backups_need_force() {
+    let tmp = TempDir::new().unwrap();
+    setup(tmp.path());
+    let args = ["b", "--no-timestamp", "data", "nas:backups"];
+
+    let output = run(tmp.path(), &args, false);
+    assert!(output.status.success(), "{output:?}");
+    let output = run(tmp.path(), &args, false);
+    assert_eq!(output.status.code(), Some(1), "{output:?}");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("--force"), "{stderr}");
+    assert!(stderr.contains("nas:backups/data.backup.tar"), "{stderr}");
+
+    let output = run(
+        tmp.path(),
+        &["b", "--force", "--no-timestamp", "data", "nas:backups"],
+        false,
+    );
+    assert!(output.status.success(), "{output:?}");
+    assert_eq!(
+        names(&tmp.path().join("home/backups")),
+        [
+            "data.backup.tar",
+            "data.backup.tar.meta.json",
+            "data.backup.tar.sha256"
+        ]
+    );
+}
+
+#[test]
+fn archives_are_sent_to_local_and_remote_targets_at_once() {
+    let tmp = TempDir::new().unwrap();
+    setup(tmp.path());
+
+    let output = run(
+        tmp.path(),
+        &[
+            "b",
+            "-c",
+            "zstd",
+            "data",
+            "--target",
+            "local",
+            "--target",
+            "nas:backups",
+        ],
+        false,
+    );
+    assert!(output.status.success(), "{output:?}");
+    let local = names(&tmp.path().join("local"));
+    let remote = names(&tmp.path().join("home/backups"));
+    assert_eq!(remote.len(), 3, "{remote:?}");
+    // Only local targets keep a catalog of their backups.
+    assert_eq!(local[0], ".backup-catalog.json");
+    assert!(local[1..].starts_with(&remote), "{local:?} {remote:?}");
+    assert_eq!(
+        fs::read(tmp.path().join("local").join(&remote[0])).unwrap(),
+        fs::read(tmp.path().join("home/backups").join(&remote[0])).unwrap()
+    );
+
+    let output = run(
+        tmp.path(),
+        &[
+            "b",
+            "-c",
+            "zstd",
+            "data",
+            "--target",
+            "local",
+            "--target",
+            "nas:backups",
+        ],
+        true,
+    );
+    assert_eq!(output.status.code(), Some(4), "{output:?}");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("1 of 2 targets"), "{stderr}");
+    assert_eq!(names(&tmp.path().join("home/backups")), remote);
+    assert_eq!(names(&tmp.path().join("local")).len(), local.len() + 3);
+}
+
+#[test]
+fn existing_local_paths_with_a_colon_stay_local() {
+    let tmp = TempDir::new().unwrap();
+    setup(tmp.path());
+    let backup = "x:notes.txt.2024-05-01_10-00-00.backup";
+    fs::write(tmp.path().join(backup), "local notes").unwrap();
+
+    let output = run(tmp.path(), &["r", backup, "restored"], false);
+    assert!(output.status.success(), "{output:?}");
+    assert_eq!(
+        fs::read_to_string(tmp.path().join("restored")).unwrap(),
+        "local notes"
+    );
+    assert!(!tmp.path().join("ssh.log").exists());
+}
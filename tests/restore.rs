@@ -0,0 +1,320 @@
+mod common;
+
+use std::fs;
+
+use tempfile::TempDir;
+
+const BACKUP: &str = "hosts.2024-05-01_10-00-00.backup";
+
+fn setup() -> TempDir {
+    let tmp = TempDir::new().unwrap();
+    fs::write(tmp.path().join(BACKUP), "backed up").unwrap();
+    tmp
+}
+
+#[test]
+fn restores_next_to_backup_without_target() {
+    let tmp = setup();
+
+    let output = common::run(tmp.path(), &["r", BACKUP]);
+    assert!(output.status.success(), "{output:?}");
+    assert_eq!(
+        fs::read_to_string(tmp.path().join("hosts")).unwrap(),
+        "backed up"
+    );
+}
+
+#[test]
+fn restores_inside_existing_directory_target() {
+    let tmp = setup();
+    fs::create_dir(tmp.path().join("staging")).unwrap();
+
+    let output = common::run(tmp.path(), &["r", BACKUP, "staging"]);
+    assert!(output.status.success(), "{output:?}");
+    assert_eq!(
+        fs::read_to_string(tmp.path().join("staging/hosts")).unwrap(),
+        "backed up"
+    );
+}
+
+#[test]
+fn restores_to_exact_nonexistent_target() {
+    let tmp = setup();
+
+    let output = common::run(tmp.path(), &["r", BACKUP, "hosts.restored"]);
+    assert!(output.status.success(), "{output:?}");
+    assert_eq!(
+        fs::read_to_string(tmp.path().join("hosts.restored")).unwrap(),
+        "backed up"
+    );
+}
+
+#[test]
+fn existing_target_requires_force() {
+    let tmp = setup();
+    fs::write(tmp.path().join("current"), "live").unwrap();
+
+    let output = common::run(tmp.path(), &["r", BACKUP, "current"]);
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("current"));
+    assert_eq!(
+        fs::read_to_string(tmp.path().join("current")).unwrap(),
+        "live"
+    );
+
+    let output = common::run(tmp.path(), &["r", "--force", BACKUP, "current"]);
+    assert!(output.status.success(), "{output:?}");
+    assert_eq!(
+        fs::read_to_string(tmp.path().join("current")).unwrap(),
+        "backed up"
+    );
+}
+
+#[test]
+fn original_names_containing_dots_are_recovered() {
+    let tmp = TempDir::new().unwrap();
+    fs::write(
+        tmp.path().join("site.conf.d.2024-05-01_10-00-00.backup"),
+        "conf",
+    )
+    .unwrap();
+
+    let output = common::run(tmp.path(), &["r", "site.conf.d.2024-05-01_10-00-00.backup"]);
+    assert!(output.status.success(), "{output:?}");
+    assert_eq!(
+        fs::read_to_string(tmp.path().join("site.conf.d")).unwrap(),
+        "conf"
+    );
+}
+
+#[test]
+fn unrecognized_names_are_restored_as_is_with_a_warning() {
+    let tmp = TempDir::new().unwrap();
+    fs::write(tmp.path().join("notes.txt"), "notes").unwrap();
+    fs::create_dir(tmp.path().join("staging")).unwrap();
+
+    let output = common::run(tmp.path(), &["r", "notes.txt", "staging"]);
+    assert!(output.status.success(), "{output:?}");
+    assert!(String::from_utf8_lossy(&output.stderr).contains("warning"));
+    assert_eq!(
+        fs::read_to_string(tmp.path().join("staging/notes.txt")).unwrap(),
+        "notes"
+    );
+}
+
+#[test]
+fn strict_rejects_unrecognized_names() {
+    let tmp = TempDir::new().unwrap();
+    fs::write(tmp.path().join("notes.txt"), "notes").unwrap();
+    fs::create_dir(tmp.path().join("staging")).unwrap();
+
+    let output = common::run(tmp.path(), &["r", "--strict", "notes.txt", "staging"]);
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("Not a backup"));
+    assert!(!tmp.path().join("staging/notes.txt").exists());
+}
+
+#[test]
+fn quiet_suppresses_the_name_warning() {
+    let tmp = TempDir::new().unwrap();
+    fs::write(tmp.path().join("notes.txt"), "notes").unwrap();
+    fs::create_dir(tmp.path().join("staging")).unwrap();
+
+    let output = common::run(tmp.path(), &["r", "--quiet", "notes.txt", "staging"]);
+    assert!(output.status.success(), "{output:?}");
+    assert!(output.stderr.is_empty(), "{output:?}");
+    assert!(tmp.path().join("staging/notes.txt").is_file());
+}
+
+#[test]
+fn counter_suffixed_names_are_recovered() {
+    let tmp = TempDir::new().unwrap();
+    fs::write(
+        tmp.path().join("hosts.2024-05-01_10-00-00_2.backup"),
+        "second",
+    )
+    .unwrap();
+
+    let output = common::run(
+        tmp.path(),
+        &["r", "--strict", "hosts.2024-05-01_10-00-00_2.backup"],
+    );
+    assert!(output.status.success(), "{output:?}");
+    assert_eq!(
+        fs::read_to_string(tmp.path().join("hosts")).unwrap(),
+        "second"
+    );
+}
+
+#[test]
+fn latest_restores_the_newest_backup() {
+    let tmp = setup();
+    fs::create_dir(tmp.path().join("backups")).unwrap();
+    for (name, contents) in [
+        ("hosts.2024-05-01_10-00-00.backup", "old"),
+        ("hosts.2024-06-01_10-00-00.backup", "newest"),
+        ("hosts.2024-05-15_10-00-00.backup", "middle"),
+        ("other.2025-01-01_10-00-00.backup", "other"),
+    ] {
+        fs::write(tmp.path().join("backups").join(name), contents).unwrap();
+    }
+
+    let args = ["r", "--latest", "hosts", "--from", "backups", "restored"];
+    let output = common::run(tmp.path(), &args);
+    assert!(output.status.success(), "{output:?}");
+    let restored = fs::read_to_string(tmp.path().join("restored")).unwrap();
+    assert_eq!(restored, "newest");
+
+    // Without --from, the backups are searched in the current directory.
+    let output = common::run(tmp.path(), &["r", "--latest", "hosts"]);
+    assert!(output.status.success(), "{output:?}");
+    assert_eq!(
+        fs::read_to_string(tmp.path().join("hosts")).unwrap(),
+        "backed up"
+    );
+
+    let output = common::run(
+        tmp.path(),
+        &["r", "--latest", "missing", "--from", "backups"],
+    );
+    assert_eq!(output.status.code(), Some(1), "{output:?}");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("No backup found"), "{stderr}");
+}
+
+#[test]
+fn latest_requires_a_path_when_sources_share_a_name() {
+    let tmp = TempDir::new().unwrap();
+    for dir in ["etc", "home"] {
+        fs::create_dir(tmp.path().join(dir)).unwrap();
+        fs::write(tmp.path().join(dir).join("hosts"), dir).unwrap();
+        let source = format!("{dir}/hosts");
+        let output = common::run(tmp.path(), &["b", &source, "backups"]);
+        assert!(output.status.success(), "{output:?}");
+    }
+
+    let args = ["r", "--latest", "hosts", "--from", "backups", "restored"];
+    let output = common::run(tmp.path(), &args);
+    assert_eq!(output.status.code(), Some(1), "{output:?}");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("several sources"), "{stderr}");
+    assert!(
+        stderr.contains("etc/hosts") && stderr.contains("home/hosts"),
+        "{stderr}"
+    );
+
+    let args = [
+        "r",
+        "--latest",
+        "etc/hosts",
+        "--from",
+        "backups",
+        "restored",
+    ];
+    let output = common::run(tmp.path(), &args);
+    assert!(output.status.success(), "{output:?}");
+    assert_eq!(
+        fs::read_to_string(tmp.path().join("restored")).unwrap(),
+        "etc"
+    );
+}
+
+#[test]
+fn versions_are_selected_by_rank() {
+    let tmp = TempDir::new().unwrap();
+    fs::create_dir(tmp.path().join("backups")).unwrap();
+    for (name, contents) in [
+        ("hosts.2024-05-01_10-00-00.backup", "old"),
+        ("hosts.2024-06-01_10-00-00.backup", "newest"),
+        ("hosts.2024-05-15_10-00-00.backup", "middle"),
+    ] {
+        fs::write(tmp.path().join("backups").join(name), contents).unwrap();
+    }
+
+    let args = [
+        "r", "--from", "backups", "--select", "2", "hosts", "restored",
+    ];
+    let output = common::run(tmp.path(), &args);
+    assert!(output.status.success(), "{output:?}");
+    let restored = fs::read_to_string(tmp.path().join("restored")).unwrap();
+    assert_eq!(restored, "middle");
+
+    let args = ["r", "--from", "backups", "--select", "4", "hosts", "other"];
+    let output = common::run(tmp.path(), &args);
+    assert_eq!(output.status.code(), Some(2), "{output:?}");
+
+    // Tests run without a terminal on stdin, so there is no one to ask.
+    let args = ["r", "--from", "backups", "hosts", "other"];
+    let output = common::run(tmp.path(), &args);
+    assert_eq!(output.status.code(), Some(2), "{output:?}");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("3 backups of 'hosts' found"), "{stderr}");
+    assert!(!tmp.path().join("other").exists());
+}
+
+#[test]
+fn single_version_is_restored_without_asking() {
+    let tmp = setup();
+
+    let output = common::run(tmp.path(), &["r", "--from", ".", "hosts", "restored"]);
+    assert!(output.status.success(), "{output:?}");
+    let restored = fs::read_to_string(tmp.path().join("restored")).unwrap();
+    assert_eq!(restored, "backed up");
+}
+
+#[test]
+fn forced_restore_keeps_a_safety_copy() {
+    let tmp = setup();
+    fs::write(tmp.path().join("current"), "live").unwrap();
+
+    let output = common::run(tmp.path(), &["r", "--force", BACKUP, "current"]);
+    assert!(output.status.success(), "{output:?}");
+    let safety: Vec<_> = fs::read_dir(tmp.path())
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+        .filter(|name| name.starts_with("current.pre-restore."))
+        .collect();
+    assert_eq!(safety.len(), 1, "{safety:?}");
+    assert!(safety[0].ends_with(".backup"));
+    assert_eq!(
+        fs::read_to_string(tmp.path().join(&safety[0])).unwrap(),
+        "live"
+    );
+    assert!(String::from_utf8_lossy(&output.stderr).contains(&safety[0]));
+}
+
+#[test]
+fn no_safety_deletes_the_replaced_destination() {
+    let tmp = setup();
+    fs::write(tmp.path().join("current"), "live").unwrap();
+
+    let output = common::run(
+        tmp.path(),
+        &["r", "--force", "--no-safety", BACKUP, "current"],
+    );
+    assert!(output.status.success(), "{output:?}");
+    assert_eq!(
+        fs::read_to_string(tmp.path().join("current")).unwrap(),
+        "backed up"
+    );
+    assert_eq!(fs::read_dir(tmp.path()).unwrap().count(), 2);
+}
+
+#[test]
+fn failed_restore_puts_the_safety_copy_back() {
+    let tmp = setup();
+    fs::write(tmp.path().join("current"), "live").unwrap();
+    fs::write(
+        tmp.path().join(format!("{BACKUP}.sha256")),
+        format!("0000  {BACKUP}\n"),
+    )
+    .unwrap();
+
+    let output = common::run(tmp.path(), &["r", "--force", BACKUP, "current"]);
+    assert_eq!(output.status.code(), Some(5), "{output:?}");
+    assert_eq!(
+        fs::read_to_string(tmp.path().join("current")).unwrap(),
+        "live"
+    );
+    assert_eq!(fs::read_dir(tmp.path()).unwrap().count(), 3);
+}
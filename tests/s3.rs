@@ -0,0 +1,436 @@
+#![cfg(unix)]
+
+mod common;
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::path::Path;
+use std::process::{Command, Output, Stdio};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use sha2::{Digest, Sha256};
+use tempfile::TempDir;
+
+/// Objects and multipart uploads of the fake S3 service, with the requests
+/// it received.
+#[derive(Default)]
+struct State {
+    objects: BTreeMap<String, Vec<u8>>,
+    uploads: BTreeMap<String, BTreeMap<u32, Vec<u8>>>,
+    requests: Vec<String>,
+    /// Answer uploads with an ETag not matching the data.
+    wrong_etag: bool,
+    /// Fail the upload of the second part of multipart uploads.
+    fail_part: bool,
+}
+
+/// Minimal S3 service on a local port, keeping objects in memory and
+/// checking that requests are signed and their bodies match their digest.
+struct FakeS3 {
+    url: String,
+    state: Arc<Mutex<State>>,
+}
+
+impl FakeS3 {
+    fn start() -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let state = Arc::new(Mutex::new(State::default()));
+        let shared = Arc::clone(&state);
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                handle(stream.unwrap(), &shared);
+            }
+        });
+        FakeS3 { url, state }
+    }
+
+    /// Runs the binary from `root` against this service.
+    fn run(&self, root: &Path, args: &[&str]) -> Output {
+        let mut args = args.to_vec();
+        args.splice(1..1, ["--endpoint-url", &self.url]);
+        common::run_with_env(
+            root,
+            &args,
+            &[
+                ("AWS_ACCESS_KEY_ID", "test".as_ref()),
+                ("AWS_SECRET_ACCESS_KEY", "secret".as_ref()),
+                ("HOME", root.as_os_str()),
+            ],
+        )
+    }
+
+    fn keys(&self) -> Vec<String> {
+        self.state.lock().unwrap().objects.keys().cloned().collect()
+    }
+}
+
+fn handle(stream: TcpStream, state: &Mutex<State>) {
+    let mut reader = BufReader::new(stream.try_clone().unwrap());
+    let mut line = String::new();
+    reader.read_line(&mut line).unwrap();
+    let mut parts = line.split_whitespace();
+    let method = parts.next().unwrap().to_owned();
+    let target = parts.next().unwrap().to_owned();
+    let mut headers = BTreeMap::new();
+    loop {
+        line.clear();
+        reader.read_line(&mut line).unwrap();
+        match line.trim_end().split_once(':') {
+            Some((name, value)) => {
+                headers.insert(name.to_ascii_lowercase(), value.trim().to_owned());
+            }
+            None => break,
+        }
+    }
+    let length = headers
+        .get("content-length")
+        .map_or(0, |length| length.parse().unwrap());
+    let mut body = vec![0; length];
+    reader.read_exact(&mut body).unwrap();
+
+    let (status, extra, response) = respond(&method, &target, &headers, body, state);
+    let mut stream = stream;
+    let head = format!(
+        "HTTP/1.1 {status} X\r\nContent-Length: {}\r\nConnection: close\r\n{extra}\r\n",
+        response.len()
+    );
+    stream.write_all(head.as_bytes()).unwrap();
+    if method != "HEAD" {
+        stream.write_all(&response).unwrap();
+    }
+}
+
+fn respond(
+    method: &str,
+    target: &str,
+    headers: &BTreeMap<String, String>,
+    body: Vec<u8>,
+    state: &Mutex<State>,
+) -> (u16, String, Vec<u8>) {
+    let mut state = state.lock().unwrap();
+    state.requests.push(format!("{method} {target}"));
+    let authorized = headers
+        .get("authorization")
+        .is_some_and(|value| value.starts_with("AWS4-HMAC-SHA256 Credential=test/"));
+    if !authorized {
+        return (403, String::new(), error("AccessDenied"));
+    }
+    if headers.get("x-amz-content-sha256") != Some(&hex(&Sha256::digest(&body))) {
+        return (400, String::new(), error("XAmzContentSHA256Mismatch"));
+    }
+
+    let (path, query) = target.split_once('?').unwrap_or((target, ""));
+    let query: BTreeMap<String, String> = query
+        .split('&')
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| {
+            let (name, value) = pair.split_once('=').unwrap_or((pair, ""));
+            (decode(name), decode(value))
+        })
+        .collect();
+    let path = decode(path);
+    let (bucket, key) = path[1..].split_once('/').unwrap_or((&path[1..], ""));
+    assert_eq!(bucket, "bucket");
+    let key = key.to_owned();
+    let etag = |data: &[u8], wrong: bool| match wrong {
+        true => "\"00000000000000000000000000000000\"".to_owned(),
+        false => format!("\"{}\"", md5(data)),
+    };
+
+    match method {
+        "GET" if key.is_empty() => {
+            let prefix = query.get("prefix").cloned().unwrap_or_default();
+            let start: usize = query
+                .get("continuation-token")
+                .map_or(0, |token| token.parse().unwrap());
+            let matching: Vec<_> = state
+                .objects
+                .iter()
+                .filter(|(key, _)| {
+                    key.strip_prefix(&prefix)
+                        .is_some_and(|name| !name.contains('/'))
+                })
+                .collect();
+            let mut xml = String::from("<ListBucketResult>");
+            for (key, data) in matching.iter().skip(start).take(2) {
+                let key = key.replace('&', "&amp;");
+                xml += &format!(
+                    "<Contents><Key>{key}</Key><Size>{}</Size></Contents>",
+                    data.len()
+                );
+            }
+            if matching.len() > start + 2 {
+                xml += &format!(
+                    "<IsTruncated>true</IsTruncated><NextContinuationToken>{}</NextContinuationToken>",
+                    start + 2
+                );
+            }
+            xml += "</ListBucketResult>";
+            (200, String::new(), xml.into_bytes())
+        }
+        "GET" | "HEAD" => match state.objects.get(&key) {
+            Some(data) => (200, String::new(), data.clone()),
+            None => (404, String::new(), error("NoSuchKey")),
+        },
+        "PUT" => {
+            let wrong = state.wrong_etag;
+            let etag = etag(&body, wrong);
+            match (query.get("partNumber"), query.get("uploadId")) {
+                (Some(number), Some(id)) => {
+                    let number: u32 = number.parse().unwrap();
+                    if number == 2 && state.fail_part {
+                        return (500, String::new(), error("InternalError"));
+                    }
+                    state.uploads.get_mut(id).unwrap().insert(number, body);
+                }
+                _ => {
+                    state.objects.insert(key, body);
+                }
+            }
+            (200, format!("ETag: {etag}\r\n"), Vec::new())
+        }
+        "POST" if query.contains_key("uploads") => {
+            let id = format!("upload-{}", state.requests.len());
+            state.uploads.insert(id.clone(), BTreeMap::new());
+            let xml = format!("<InitiateMultipartUploadResult><UploadId>{id}</UploadId></InitiateMultipartUploadResult>");
+            (200, String::new(), xml.into_bytes())
+        }
+        "POST" => {
+            let parts = state.uploads.remove(&query["uploadId"]).unwrap();
+            let digests: Vec<u8> = parts
+                .values()
+                .flat_map(|part| {
+                    let digest = md5(part);
+                    (0..32)
+                        .step_by(2)
+                        .map(|i| u8::from_str_radix(&digest[i..i + 2], 16).unwrap())
+                        .collect::<Vec<_>>()
+                })
+                .collect();
+            let etag = format!("{}-{}", md5(&digests), parts.len());
+            state
+                .objects
+                .insert(key, parts.into_values().flatten().collect());
+            let xml = format!("<CompleteMultipartUploadResult><ETag>&quot;{etag}&quot;</ETag></CompleteMultipartUploadResult>");
+            (200, String::new(), xml.into_bytes())
+        }
+        "DELETE" => {
+            match query.get("uploadId") {
+                Some(id) => state.uploads.remove(id),
+                None => state.objects.remove(&key).map(|_| BTreeMap::new()),
+            };
+            (204, String::new(), Vec::new())
+        }
+        _ => (405, String::new(), error("MethodNotAllowed")),
+    }
+}
+
+fn error(code: &str) -> Vec<u8> {
+    format!("<Error><Code>{code}</Code><Message>fake</Message></Error>").into_bytes()
+}
+
+fn decode(text: &str) -> String {
+    let bytes = text.as_bytes();
+    let mut decoded = Vec::new();
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' {
+            decoded.push(u8::from_str_radix(&text[i + 1..i + 3], 16).unwrap());
+            i += 3;
+        } else {
+            decoded.push(bytes[i]);
+            i += 1;
+        }
+    }
+    String::from_utf8(decoded).unwrap()
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
+fn md5(data: &[u8]) -> String {
+    let mut child = Command::new("md5sum")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child.stdin.take().unwrap().write_all(data).unwrap();
+    let output = child.wait_with_output().unwrap();
+    String::from_utf8_lossy(&output.stdout)[..32].to_owned()
+}
+
+/// Creates the directory `data` in `root`, holding `size` bytes that do not
+/// compress.
+fn setup(root: &Path, size: usize) {
+    fs::create_dir(root.join("data")).unwrap();
+    let mut state = 0x2545f4914f6cdd1du64;
+    let blob: Vec<u8> = (0..size)
+        .map(|_| {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            state as u8
+        })
+        .collect();
+    fs::write(root.join("data/blob.bin"), blob).unwrap();
+    fs::write(root.join("data/notes.txt"), "notes").unwrap();
+}
+
+#[test]
+fn backups_are_listed_verified_restored_and_pruned_in_a_bucket() {
+    let tmp = TempDir::new().unwrap();
+    setup(tmp.path(), 10_000);
+    let s3 = FakeS3::start();
+
+    let output = s3.run(
+        tmp.path(),
+        &["b", "-c", "gzip", "data", "s3://bucket/daily"],
+    );
+    assert!(output.status.success(), "{output:?}");
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let url = stdout.trim();
+    assert!(url.starts_with("s3://bucket/daily/data."), "{url}");
+    let key = url.strip_prefix("s3://bucket/").unwrap();
+    assert_eq!(
+        s3.keys(),
+        [
+            key.to_owned(),
+            format!("{key}.meta.json"),
+            format!("{key}.sha256")
+        ]
+    );
+
+    let output = s3.run(tmp.path(), &["verify", url]);
+    assert!(output.status.success(), "{output:?}");
+    let output = s3.run(tmp.path(), &["r", url, "restored"]);
+    assert!(output.status.success(), "{output:?}");
+    assert_eq!(
+        fs::read(tmp.path().join("restored/blob.bin")).unwrap(),
+        fs::read(tmp.path().join("data/blob.bin")).unwrap()
+    );
+
+    {
+        let mut state = s3.state.lock().unwrap();
+        for name in [
+            "data.2020-01-01_00-00-00.backup.tar.gz",
+            "data.2020-01-01_00-00-00.backup.tar.gz.sha256",
+            "notes.2020-01-01_00-00-00.backup",
+            "other/data.2020-01-01_00-00-00.backup",
+        ] {
+            state
+                .objects
+                .insert(format!("daily/{name}"), b"old".to_vec());
+        }
+    }
+    let output = s3.run(tmp.path(), &["list", "--json", "s3://bucket/daily"]);
+    assert!(output.status.success(), "{output:?}");
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert_eq!(stdout.matches("\"path\"").count(), 3, "{stdout}");
+    assert!(stdout.contains(url), "{stdout}");
+
+    let output = s3.run(
+        tmp.path(),
+        &["prune", "--keep-last", "1", "s3://bucket/daily/"],
+    );
+    assert!(output.status.success(), "{output:?}");
+    assert_eq!(
+        s3.keys(),
+        [
+            key.to_owned(),
+            format!("{key}.meta.json"),
+            format!("{key}.sha256"),
+            "daily/notes.2020-01-01_00-00-00.backup".to_owned(),
+            "daily/other/data.2020-01-01_00-00-00.backup".to_owned(),
+        ]
+    );
+
+    s3.state
+        .lock()
+        .unwrap()
+        .objects
+        .get_mut(key)
+        .unwrap()
+        .push(0);
+    let output = s3.run(tmp.path(), &["verify", url]);
+    assert_eq!(output.status.code(), Some(5), "{output:?}");
+
+    let output = s3.run(
+        tmp.path(),
+        &["r", "s3://bucket/daily/missing.backup", "out"],
+    );
+    assert_eq!(output.status.code(), Some(1), "{output:?}");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("s3://bucket/daily/missing.backup"),
+        "{stderr}"
+    );
+}
+
+#[test]
+fn large_backups_are_sent_in_parts_and_aborted_on_failure() {
+    let tmp = TempDir::new().unwrap();
+    setup(tmp.path(), 9 << 20);
+    let s3 = FakeS3::start();
+
+    let output = s3.run(tmp.path(), &["b", "data", "s3://bucket"]);
+    assert!(output.status.success(), "{output:?}");
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let url = stdout.trim();
+    {
+        let state = s3.state.lock().unwrap();
+        let uploads = state
+            .requests
+            .iter()
+            .filter(|request| request.contains("partNumber="))
+            .count();
+        assert_eq!(uploads, 2, "{:?}", state.requests);
+        assert!(state.uploads.is_empty());
+    }
+    let output = s3.run(tmp.path(), &["r", url, "restored"]);
+    assert!(output.status.success(), "{output:?}");
+    assert_eq!(
+        fs::read(tmp.path().join("restored/blob.bin")).unwrap(),
+        fs::read(tmp.path().join("data/blob.bin")).unwrap()
+    );
+
+    s3.state.lock().unwrap().objects.clear();
+    s3.state.lock().unwrap().fail_part = true;
+    let output = s3.run(tmp.path(), &["b", "data", "s3://bucket/failed"]);
+    assert_eq!(output.status.code(), Some(1), "{output:?}");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("InternalError"), "{stderr}");
+    let state = s3.state.lock().unwrap();
+    assert!(state.objects.is_empty(), "{:?}", state.objects.keys());
+    assert!(state.uploads.is_empty(), "{:?}", state.requests);
+}
+
+#[test]
+fn uploads_not_matching_their_etag_fail() {
+    let tmp = TempDir::new().unwrap();
+    setup(tmp.path(), 1000);
+    let s3 = FakeS3::start();
+    s3.state.lock().unwrap().wrong_etag = true;
+
+    let output = s3.run(tmp.path(), &["b", "data/notes.txt", "s3://bucket/x"]);
+    assert_eq!(output.status.code(), Some(1), "{output:?}");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("ETag"), "{stderr}");
+    assert_eq!(s3.keys(), Vec::<String>::new());
+
+    let output = s3.run(tmp.path(), &["b", "data", "s3://bucket/x"]);
+    assert_eq!(output.status.code(), Some(1), "{output:?}");
+    let home = tmp.path().as_os_str();
+    let output = common::run_with_env(
+        tmp.path(),
+        &["b", "data", "s3://bucket/x"],
+        &[("HOME", home)],
+    );
+    assert_eq!(output.status.code(), Some(1), "{output:?}");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("credentials"), "{stderr}");
+}
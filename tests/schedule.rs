@@ -0,0 +1,111 @@
+#![cfg(unix)]
+
+mod common;
+
+use std::fs;
+use std::io::{BufRead, BufReader};
+use std::path::{Path, PathBuf};
+use std::process::Child;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use tempfile::TempDir;
+
+/// Reads the status file at `path` once written, panicking after ten
+/// seconds.
+fn status(path: &Path) -> serde_json::Value {
+    let start = Instant::now();
+    loop {
+        if let Ok(json) = fs::read(path) {
+            return serde_json::from_slice(&json).unwrap();
+        }
+        assert!(start.elapsed() < Duration::from_secs(10), "no {path:?}");
+        thread::sleep(Duration::from_millis(50));
+    }
+}
+
+/// Sends SIGINT to `child` and waits for it to stop the schedule.
+fn interrupt(child: Child) {
+    assert_eq!(
+        unsafe { libc::kill(child.id() as libc::pid_t, libc::SIGINT) },
+        0
+    );
+    let output = child.wait_with_output().unwrap();
+    assert!(output.status.success(), "{output:?}");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("stopped the schedule"), "{stderr}");
+}
+
+#[test]
+fn backups_are_repeated_until_interrupted() {
+    let tmp = TempDir::new().unwrap();
+    fs::create_dir(tmp.path().join("data")).unwrap();
+    fs::write(tmp.path().join("data/notes.txt"), "notes").unwrap();
+
+    let mut child = common::spawn(
+        tmp.path(),
+        &[
+            "b",
+            "--every",
+            "1s",
+            "--status-file",
+            "status.json",
+            "--precision",
+            "ms",
+            "data",
+            "out",
+        ],
+    );
+    let mut stdout = BufReader::new(child.stdout.take().unwrap()).lines();
+    let first = PathBuf::from(stdout.next().unwrap().unwrap());
+    let second = PathBuf::from(stdout.next().unwrap().unwrap());
+    assert!(first < second, "{first:?} {second:?}");
+    assert!(second.join("notes.txt").exists());
+
+    let status = status(&tmp.path().join("status.json"));
+    assert_eq!(status["status"], 0, "{status}");
+    assert!(status["error"].is_null(), "{status}");
+    assert!(
+        status["next"].as_str() > status["finished"].as_str(),
+        "{status}"
+    );
+    interrupt(child);
+}
+
+#[test]
+fn failing_runs_are_recorded_without_stopping_the_schedule() {
+    let tmp = TempDir::new().unwrap();
+
+    let child = common::spawn(
+        tmp.path(),
+        &[
+            "b",
+            "--every",
+            "1h",
+            "--status-file",
+            "status.json",
+            "missing",
+            "out",
+        ],
+    );
+    let status = status(&tmp.path().join("status.json"));
+    assert_eq!(status["status"], 1, "{status}");
+    assert_eq!(status["code"], "not_found", "{status}");
+    interrupt(child);
+}
+
+#[test]
+fn invalid_schedules_are_usage_errors() {
+    let tmp = TempDir::new().unwrap();
+    fs::create_dir(tmp.path().join("data")).unwrap();
+
+    for args in [
+        &["b", "--at", "25:00", "data", "out"][..],
+        &["b", "--every", "0s", "data", "out"],
+        &["b", "--every", "1h", "--dry-run", "data", "out"],
+    ] {
+        let output = common::run(tmp.path(), args);
+        assert_eq!(output.status.code(), Some(2), "{args:?} {output:?}");
+    }
+    assert!(!tmp.path().join("out").exists());
+}
@@ -0,0 +1,141 @@
+mod common;
+
+use std::ffi::OsStr;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use sha2::{Digest, Sha256};
+use tempfile::TempDir;
+
+/// Generates a key pair at `sign.key` in `root`, returning the public key
+/// printed.
+fn keygen(root: &Path) -> String {
+    let output = common::run(root, &["keygen", "sign.key"]);
+    assert!(output.status.success(), "{output:?}");
+    String::from_utf8(output.stdout).unwrap().trim().to_owned()
+}
+
+/// Backs up the directory `data` of `root` signed with `sign.key`, returning
+/// the path of the backup.
+fn signed_backup(root: &Path) -> PathBuf {
+    fs::create_dir(root.join("data")).unwrap();
+    fs::write(root.join("data/notes.txt"), "notes").unwrap();
+    let output = common::run(root, &["b", "--sign", "sign.key", "data", "backups"]);
+    assert!(output.status.success(), "{output:?}");
+    common::single_entry(&root.join("backups"))
+}
+
+#[test]
+fn keygen_writes_a_private_key_and_its_public_key() {
+    let tmp = TempDir::new().unwrap();
+    let public = keygen(tmp.path());
+    assert!(public.starts_with("ed25519:"), "{public}");
+    assert_eq!(
+        fs::read_to_string(tmp.path().join("sign.key.pub")).unwrap(),
+        format!("{public}\n")
+    );
+    let secret = fs::read_to_string(tmp.path().join("sign.key")).unwrap();
+    assert!(secret.contains("ed25519-secret:"), "{secret}");
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        let mode = fs::metadata(tmp.path().join("sign.key"))
+            .unwrap()
+            .permissions()
+            .mode();
+        assert_eq!(mode & 0o777, 0o600);
+    }
+
+    let output = common::run(tmp.path(), &["keygen", "sign.key"]);
+    assert!(!output.status.success(), "{output:?}");
+    assert_eq!(
+        fs::read_to_string(tmp.path().join("sign.key")).unwrap(),
+        secret
+    );
+    let output = common::run(tmp.path(), &["keygen", "--force", "sign.key"]);
+    assert!(output.status.success(), "{output:?}");
+    assert_ne!(
+        fs::read_to_string(tmp.path().join("sign.key")).unwrap(),
+        secret
+    );
+}
+
+#[test]
+fn signed_backups_verify_and_restore_with_the_public_key() {
+    let tmp = TempDir::new().unwrap();
+    let public = keygen(tmp.path());
+    let backup = signed_backup(tmp.path());
+    let signature = fs::read_to_string(format!("{}.sig", backup.display())).unwrap();
+    assert!(
+        signature.starts_with("backup-signature: 1\n"),
+        "{signature}"
+    );
+    assert!(
+        signature.contains(&format!("key: {public}\n")),
+        "{signature}"
+    );
+    let backup = backup.to_str().unwrap();
+
+    let output = common::run(tmp.path(), &["verify", "--verify-key", &public, backup]);
+    assert!(output.status.success(), "{output:?}");
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("signature: OK"), "{stdout}");
+
+    let output = common::run_with_env(
+        tmp.path(),
+        &["r", backup, "restored"],
+        &[("BACKUP_VERIFY_KEY", OsStr::new("sign.key.pub"))],
+    );
+    assert!(output.status.success(), "{output:?}");
+    assert_eq!(
+        fs::read_to_string(tmp.path().join("restored/notes.txt")).unwrap(),
+        "notes"
+    );
+}
+
+#[test]
+fn modified_or_unsigned_backups_are_refused() {
+    let tmp = TempDir::new().unwrap();
+    let public = keygen(tmp.path());
+    let backup = signed_backup(tmp.path());
+    let manifest = PathBuf::from(format!("{}.sha256", backup.display()));
+    let file = backup.join("notes.txt");
+    fs::write(&file, "tampered").unwrap();
+    let digest: String = Sha256::digest(b"tampered")
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect();
+    let contents = fs::read_to_string(&manifest).unwrap();
+    let line = contents
+        .lines()
+        .find(|line| line.ends_with("notes.txt"))
+        .unwrap();
+    let (old, _) = line.split_once(' ').unwrap();
+    fs::write(&manifest, contents.replace(old, &digest)).unwrap();
+    let backup = backup.to_str().unwrap();
+
+    let output = common::run(tmp.path(), &["verify", backup]);
+    assert!(output.status.success(), "{output:?}");
+    let output = common::run(tmp.path(), &["verify", "--verify-key", &public, backup]);
+    assert_eq!(output.status.code(), Some(5), "{output:?}");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("Bad signature"), "{stderr}");
+
+    let restore = ["r", "--verify-key", public.as_str(), backup, "restored"];
+    let output = common::run(tmp.path(), &restore);
+    assert_eq!(output.status.code(), Some(5), "{output:?}");
+    assert!(!tmp.path().join("restored").exists());
+    let output = common::run(
+        tmp.path(),
+        &[&restore[..4], &["--no-verify-signature", "restored"]].concat(),
+    );
+    assert!(output.status.success(), "{output:?}");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("restoring anyway"), "{stderr}");
+
+    fs::remove_file(format!("{backup}.sig")).unwrap();
+    let output = common::run(tmp.path(), &["verify", "--verify-key", &public, backup]);
+    assert_eq!(output.status.code(), Some(5), "{output:?}");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("not signed"), "{stderr}");
+}
@@ -0,0 +1,163 @@
+mod common;
+
+use std::fs;
+use std::path::Path;
+
+use tempfile::TempDir;
+
+fn setup() -> TempDir {
+    let tmp = TempDir::new().unwrap();
+    fs::write(tmp.path().join("hosts"), "hosts").unwrap();
+    fs::write(tmp.path().join("fstab"), "fstab").unwrap();
+    fs::create_dir(tmp.path().join("project")).unwrap();
+    fs::write(tmp.path().join("project/main.rs"), "fn main() {}").unwrap();
+    tmp
+}
+
+fn backups(dir: &Path) -> Vec<String> {
+    let mut names: Vec<_> = fs::read_dir(dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| !common::is_sidecar(path))
+        .map(|path| path.file_name().unwrap().to_string_lossy().into_owned())
+        .collect();
+    names.sort();
+    names
+}
+
+#[test]
+fn every_source_gets_a_backup() {
+    let tmp = setup();
+
+    let output = common::run(tmp.path(), &["b", "hosts", "fstab", "project", "backups"]);
+    assert!(output.status.success(), "{output:?}");
+    let names = backups(&tmp.path().join("backups"));
+    assert_eq!(names.len(), 3, "{names:?}");
+    for (name, source) in names.iter().zip(["fstab", "hosts", "project"]) {
+        assert!(name.starts_with(&format!("{source}.")), "{names:?}");
+    }
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert_eq!(stdout.lines().count(), 3, "{stdout}");
+
+    // With --target, every argument is a source.
+    let output = common::run(tmp.path(), &["b", "-t", "other", "hosts", "fstab"]);
+    assert!(output.status.success(), "{output:?}");
+    assert_eq!(backups(&tmp.path().join("other")).len(), 2);
+}
+
+#[test]
+fn failed_sources_do_not_stop_the_others() {
+    let tmp = setup();
+
+    let output = common::run(tmp.path(), &["b", "hosts", "missing", "fstab", "backups"]);
+    assert_eq!(output.status.code(), Some(4), "{output:?}");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("'missing': No such file or directory"),
+        "{stderr}"
+    );
+    assert!(
+        stderr.contains("1 of 3 sources could not be backed up"),
+        "{stderr}"
+    );
+    assert_eq!(backups(&tmp.path().join("backups")).len(), 2);
+}
+
+#[test]
+fn json_describes_each_backup() {
+    let tmp = setup();
+
+    let output = common::run(
+        tmp.path(),
+        &["b", "--json", "-t", "backups", "hosts", "project"],
+    );
+    assert!(output.status.success(), "{output:?}");
+    let json: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    let outcomes = json.as_array().unwrap();
+    assert_eq!(outcomes.len(), 2);
+    assert!(outcomes[0]["source"].as_str().unwrap().ends_with("hosts"));
+    assert_eq!(outcomes[1]["files"], 1);
+}
+
+#[test]
+fn patterns_expand_to_the_paths_they_match() {
+    let tmp = setup();
+    fs::write(tmp.path().join("hosts.allow"), "allow").unwrap();
+
+    let output = common::run(
+        tmp.path(),
+        &["b", "-t", "backups", "hosts*", "*tab", "hosts"],
+    );
+    assert!(output.status.success(), "{output:?}");
+    let names = backups(&tmp.path().join("backups"));
+    assert_eq!(names.len(), 3, "{names:?}");
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let sources: Vec<_> = stdout
+        .lines()
+        .map(|line| Path::new(line).file_name().unwrap().to_string_lossy())
+        .map(|name| name.split(".2").next().unwrap().to_owned())
+        .collect();
+    assert_eq!(sources, ["hosts", "hosts.allow", "fstab"], "{stdout}");
+}
+
+#[test]
+fn patterns_matching_nothing_fail_unless_allowed() {
+    let tmp = setup();
+
+    let output = common::run(tmp.path(), &["b", "-t", "backups", "hosts", "*.bin"]);
+    assert_eq!(output.status.code(), Some(1), "{output:?}");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("'*.bin': No path matches the pattern"),
+        "{stderr}"
+    );
+    assert!(!tmp.path().join("backups").exists());
+
+    let output = common::run(
+        tmp.path(),
+        &["b", "--allow-empty-glob", "-t", "backups", "hosts", "*.bin"],
+    );
+    assert!(output.status.success(), "{output:?}");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("warning: '*.bin'"), "{stderr}");
+    assert_eq!(backups(&tmp.path().join("backups")).len(), 1);
+}
+
+#[test]
+fn listed_paths_are_backed_up() {
+    let tmp = setup();
+    fs::write(
+        tmp.path().join("list.txt"),
+        "# configuration\nhosts\n\nproject\nmissing\n",
+    )
+    .unwrap();
+
+    let output = common::run(tmp.path(), &["b", "--files-from", "list.txt", "backups"]);
+    assert!(output.status.success(), "{output:?}");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("'missing'"), "{stderr}");
+    let names = backups(&tmp.path().join("backups"));
+    assert_eq!(names.len(), 2, "{names:?}");
+
+    let output = common::run(
+        tmp.path(),
+        &["b", "--fail-fast", "--files-from", "list.txt", "other"],
+    );
+    assert_eq!(output.status.code(), Some(1), "{output:?}");
+    assert!(!tmp.path().join("other").exists());
+}
+
+#[test]
+fn listed_paths_are_read_from_stdin() {
+    let tmp = setup();
+
+    let output = common::run_with_stdin(
+        tmp.path(),
+        &["b", "-0", "--files-from", "-", "backups"],
+        b"hosts\0fstab\0hosts\0",
+    );
+    assert!(output.status.success(), "{output:?}");
+    let names = backups(&tmp.path().join("backups"));
+    assert_eq!(names.len(), 2, "{names:?}");
+    assert!(names[0].starts_with("fstab."), "{names:?}");
+}
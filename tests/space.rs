@@ -0,0 +1,57 @@
+#![cfg(unix)]
+
+mod common;
+
+use std::fs::{self, File};
+use std::path::Path;
+
+use tempfile::TempDir;
+
+/// Creates a project holding a sparse file larger than any test filesystem.
+fn huge_project(root: &Path) {
+    let project = root.join("project");
+    fs::create_dir(&project).unwrap();
+    File::create(project.join("disk.img"))
+        .unwrap()
+        .set_len(1 << 43)
+        .unwrap();
+}
+
+#[test]
+fn backups_larger_than_the_free_space_are_refused() {
+    let tmp = TempDir::new().unwrap();
+    huge_project(tmp.path());
+
+    let output = common::run(tmp.path(), &["b", "project", "backups"]);
+    assert_eq!(output.status.code(), Some(1), "{output:?}");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("Not enough space, need 8.0 TiB, only "),
+        "{stderr}"
+    );
+    assert!(stderr.contains(" free on backups"), "{stderr}");
+    assert!(!tmp.path().join("backups").exists());
+
+    let output = common::run(tmp.path(), &["b", "project/disk.img", "copies"]);
+    assert_eq!(output.status.code(), Some(1), "{output:?}");
+    assert!(!tmp.path().join("copies").exists());
+}
+
+#[test]
+fn dry_run_shows_the_free_space() {
+    let tmp = TempDir::new().unwrap();
+    huge_project(tmp.path());
+
+    let output = common::run(tmp.path(), &["b", "-n", "project", "backups"]);
+    assert!(output.status.success(), "{output:?}");
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("not enough for the backup"), "{stdout}");
+
+    let output = common::run(
+        tmp.path(),
+        &["b", "-n", "--no-space-check", "project", "backups"],
+    );
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("free space: "), "{stdout}");
+    assert!(!stdout.contains("not enough"), "{stdout}");
+}
@@ -0,0 +1,105 @@
+#![cfg(unix)]
+
+mod common;
+
+use std::ffi::CString;
+use std::fs;
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::fs::FileTypeExt;
+use std::os::unix::net::UnixListener;
+use std::path::Path;
+
+use tempfile::TempDir;
+
+/// Creates a project holding a file, a named pipe and a socket.
+fn project(root: &Path) -> UnixListener {
+    let project = root.join("project");
+    fs::create_dir(&project).unwrap();
+    fs::write(project.join("notes"), "notes").unwrap();
+    let fifo = CString::new(project.join("pipe").as_os_str().as_bytes()).unwrap();
+    assert_eq!(unsafe { libc::mkfifo(fifo.as_ptr(), 0o644) }, 0);
+    UnixListener::bind(project.join("socket")).unwrap()
+}
+
+fn is_fifo(path: &Path) -> bool {
+    fs::symlink_metadata(path).is_ok_and(|metadata| metadata.file_type().is_fifo())
+}
+
+#[test]
+fn special_files_are_skipped_with_a_warning() {
+    let tmp = TempDir::new().unwrap();
+    let _socket = project(tmp.path());
+
+    let output = common::run(tmp.path(), &["b", "-v", "project", "copies"]);
+    assert!(output.status.success(), "{output:?}");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("pipe': Skipping named pipe"), "{stderr}");
+    assert!(stderr.contains("socket': Skipping socket"), "{stderr}");
+    assert!(
+        stderr.contains("backed up 1 files (5 B, 2 special files skipped)"),
+        "{stderr}"
+    );
+
+    let copy = common::single_entry(&tmp.path().join("copies"));
+    assert!(copy.join("notes").exists());
+    assert!(fs::symlink_metadata(copy.join("pipe")).is_err());
+}
+
+#[test]
+fn special_files_fail_the_backup_when_asked() {
+    let tmp = TempDir::new().unwrap();
+    let _socket = project(tmp.path());
+
+    let output = common::run(
+        tmp.path(),
+        &["b", "--special-files", "fail", "project", "copies"],
+    );
+    assert_eq!(output.status.code(), Some(4), "{output:?}");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("pipe': Special file, use --special-files record"),
+        "{stderr}"
+    );
+
+    let output = common::run(tmp.path(), &["b", "--special-files", "keep", "project"]);
+    assert_eq!(output.status.code(), Some(2), "{output:?}");
+}
+
+#[test]
+fn special_files_are_recorded_in_copies_and_archives() {
+    let tmp = TempDir::new().unwrap();
+    let _socket = project(tmp.path());
+
+    let output = common::run(
+        tmp.path(),
+        &["b", "--special-files", "record", "project", "copies"],
+    );
+    assert!(output.status.success(), "{output:?}");
+    let copy = common::single_entry(&tmp.path().join("copies"));
+    assert!(is_fifo(&copy.join("pipe")));
+
+    let output = common::run(
+        tmp.path(),
+        &[
+            "b",
+            "--special-files",
+            "record",
+            "--compress",
+            "gzip",
+            "project",
+            "archives",
+        ],
+    );
+    assert!(output.status.success(), "{output:?}");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("socket': Skipping socket"), "{stderr}");
+
+    let archive = common::single_entry(&tmp.path().join("archives"));
+    let output = common::run(tmp.path(), &["r", archive.to_str().unwrap(), "restored"]);
+    assert!(output.status.success(), "{output:?}");
+    assert!(is_fifo(&tmp.path().join("restored/pipe")));
+    assert_eq!(
+        fs::read_to_string(tmp.path().join("restored/notes")).unwrap(),
+        "notes"
+    );
+}
@@ -0,0 +1,149 @@
+mod common;
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use backup::meta;
+use tempfile::TempDir;
+
+/// Backs up the directory `data` of `root`, holding 20000 bytes, in parts of
+/// 8K, returning the name the backup goes by.
+fn split_backup(root: &Path) -> PathBuf {
+    fs::create_dir(root.join("data")).unwrap();
+    let contents: Vec<u8> = (0..20000u32).map(|i| (i * 7 % 251) as u8).collect();
+    fs::write(root.join("data/blob.bin"), contents).unwrap();
+    fs::write(root.join("data/notes.txt"), "notes").unwrap();
+    let output = common::run(root, &["b", "--split", "8K", "data", "backups"]);
+    assert!(output.status.success(), "{output:?}");
+
+    fs::read_dir(root.join("backups"))
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .find(|path| path.extension().is_some_and(|extension| extension == "000"))
+        .unwrap()
+        .with_extension("")
+}
+
+#[test]
+fn split_archives_are_written_in_parts_and_restored() {
+    let tmp = TempDir::new().unwrap();
+    fs::create_dir(tmp.path().join("data")).unwrap();
+    let contents: Vec<u8> = (0..20000u32).map(|i| (i * 7 % 251) as u8).collect();
+    fs::write(tmp.path().join("data/blob.bin"), &contents).unwrap();
+    let output = common::run(tmp.path(), &["b", "--split", "8K", "data", "backups"]);
+    assert!(output.status.success(), "{output:?}");
+
+    let mut parts: Vec<_> = fs::read_dir(tmp.path().join("backups"))
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| !common::is_sidecar(path))
+        .collect();
+    parts.sort();
+    assert_eq!(parts.len(), 3, "{parts:?}");
+    for (index, part) in parts.iter().enumerate() {
+        let name = part.to_string_lossy();
+        assert!(name.ends_with(&format!(".backup.tar.{index:03}")), "{name}");
+        assert!(fs::metadata(part).unwrap().len() <= 8192);
+    }
+    let backup = parts[0].with_extension("");
+    assert!(!backup.exists());
+
+    let meta = meta::read(&backup).unwrap().unwrap();
+    assert_eq!(meta.parts.len(), 3);
+    assert_eq!(
+        meta.parts.iter().map(|part| part.size).sum::<u64>(),
+        parts
+            .iter()
+            .map(|part| fs::metadata(part).unwrap().len())
+            .sum::<u64>()
+    );
+    assert!(meta.parts.iter().all(|part| part.digest.is_some()));
+
+    let output = common::run(tmp.path(), &["r", backup.to_str().unwrap(), "restored"]);
+    assert!(output.status.success(), "{output:?}");
+    assert_eq!(
+        fs::read(tmp.path().join("restored/blob.bin")).unwrap(),
+        contents
+    );
+
+    let output = common::run(tmp.path(), &["r", parts[1].to_str().unwrap(), "from-part"]);
+    assert!(output.status.success(), "{output:?}");
+    assert!(tmp.path().join("from-part/blob.bin").is_file());
+}
+
+#[test]
+fn missing_parts_are_listed() {
+    let tmp = TempDir::new().unwrap();
+    let backup = split_backup(tmp.path());
+    let mut missing = backup.clone().into_os_string();
+    missing.push(".001");
+    fs::remove_file(&missing).unwrap();
+
+    let output = common::run(tmp.path(), &["r", backup.to_str().unwrap(), "restored"]);
+    assert_eq!(output.status.code(), Some(1), "{output:?}");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    let name = Path::new(&missing).file_name().unwrap().to_string_lossy();
+    assert!(stderr.contains("missing"), "{stderr}");
+    assert!(stderr.contains(&*name), "{stderr}");
+    assert!(!tmp.path().join("restored").exists());
+
+    let output = common::run(tmp.path(), &["verify", backup.to_str().unwrap()]);
+    assert!(!output.status.success(), "{output:?}");
+}
+
+#[test]
+fn split_backups_are_verified_and_listed_as_one() {
+    let tmp = TempDir::new().unwrap();
+    let backup = split_backup(tmp.path());
+
+    let output = common::run(tmp.path(), &["verify", backup.to_str().unwrap()]);
+    assert!(output.status.success(), "{output:?}");
+
+    let output = common::run(tmp.path(), &["list", "--json", "backups"]);
+    assert!(output.status.success(), "{output:?}");
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert_eq!(stdout.matches("\"path\"").count(), 1, "{stdout}");
+    assert!(stdout.contains("\"kind\": \"archive\""), "{stdout}");
+
+    let output = common::run(tmp.path(), &["list-contents", backup.to_str().unwrap()]);
+    assert!(output.status.success(), "{output:?}");
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("notes.txt"), "{stdout}");
+
+    let output = common::run(tmp.path(), &["b", "--split", "8K", "data", "-"]);
+    assert_eq!(output.status.code(), Some(2), "{output:?}");
+}
+
+#[test]
+fn split_backups_are_named_by_their_first_part() {
+    let tmp = TempDir::new().unwrap();
+    fs::create_dir(tmp.path().join("data")).unwrap();
+    fs::write(tmp.path().join("data/notes.txt"), "notes").unwrap();
+    let output = common::run(tmp.path(), &["b", "--split", "8K", "data", "backups"]);
+    assert!(output.status.success(), "{output:?}");
+    let printed = PathBuf::from(String::from_utf8_lossy(&output.stdout).trim());
+    assert!(printed.is_file(), "{printed:?}");
+    assert!(printed.to_string_lossy().ends_with(".backup.tar.000"));
+
+    let link = tmp.path().join("backups/data.latest.backup");
+    assert_eq!(
+        fs::read_link(&link).unwrap(),
+        Path::new(printed.file_name().unwrap())
+    );
+    for args in [
+        ["verify", "backups/data.latest.backup"],
+        ["list-contents", "backups/data.latest.backup"],
+        ["diff", "backups/data.latest.backup"],
+    ] {
+        let mut args = args.to_vec();
+        if args[0] == "diff" {
+            args.push("data");
+        }
+        let output = common::run(tmp.path(), &args);
+        assert!(output.status.success(), "{args:?}: {output:?}");
+    }
+
+    let output = common::run(tmp.path(), &["undo"]);
+    assert!(output.status.success(), "{output:?}");
+    assert!(fs::symlink_metadata(&link).is_err());
+}
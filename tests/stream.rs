@@ -0,0 +1,95 @@
+mod common;
+
+use std::fs;
+
+use tempfile::TempDir;
+
+fn setup() -> TempDir {
+    let tmp = TempDir::new().unwrap();
+    fs::create_dir_all(tmp.path().join("data/nested")).unwrap();
+    fs::write(tmp.path().join("data/a.txt"), "alpha").unwrap();
+    fs::write(tmp.path().join("data/nested/b.txt"), "beta").unwrap();
+    tmp
+}
+
+#[test]
+fn directories_round_trip_through_a_pipe() {
+    let tmp = setup();
+
+    for compression in ["none", "gzip", "zstd"] {
+        let output = common::run(tmp.path(), &["b", "-c", compression, "data", "-"]);
+        assert!(output.status.success(), "{output:?}");
+        assert!(!output.stdout.is_empty());
+        let restored = format!("restored-{compression}");
+
+        let output = common::run_with_stdin(tmp.path(), &["r", "-", &restored], &output.stdout);
+        assert!(output.status.success(), "{output:?}");
+        let stdout = String::from_utf8(output.stdout).unwrap();
+        assert!(stdout.trim_end().ends_with(&restored), "{stdout}");
+        assert_eq!(
+            common::tree(&tmp.path().join(&restored)),
+            common::tree(&tmp.path().join("data"))
+        );
+        assert_eq!(
+            fs::read_to_string(tmp.path().join(&restored).join("nested/b.txt")).unwrap(),
+            "beta"
+        );
+    }
+    assert_eq!(common::tree(tmp.path()).len(), 4 * 4);
+}
+
+#[test]
+fn only_archives_are_streamed() {
+    let tmp = setup();
+
+    let output = common::run(tmp.path(), &["b", "data/a.txt", "-"]);
+    assert_eq!(output.status.code(), Some(2), "{output:?}");
+    assert!(output.stdout.is_empty());
+
+    let output = common::run_with_stdin(tmp.path(), &["r", "-", "restored"], b"not an archive");
+    assert_eq!(output.status.code(), Some(1), "{output:?}");
+    assert!(!tmp.path().join("restored").exists());
+
+    let output = common::run_with_stdin(tmp.path(), &["r", "-"], b"");
+    assert_eq!(output.status.code(), Some(2), "{output:?}");
+}
+
+#[test]
+fn stdin_is_backed_up_under_its_name() {
+    let tmp = TempDir::new().unwrap();
+
+    let output = common::run_with_stdin(
+        tmp.path(),
+        &["b", "-", "backups", "--name", "mydb.sql"],
+        b"CREATE TABLE t;",
+    );
+    assert!(output.status.success(), "{output:?}");
+    let backup = common::single_entry(&tmp.path().join("backups"));
+    let name = backup.file_name().unwrap().to_string_lossy().into_owned();
+    assert!(
+        name.starts_with("mydb.sql.") && name.ends_with(".backup"),
+        "{name}"
+    );
+    assert_eq!(fs::read_to_string(&backup).unwrap(), "CREATE TABLE t;");
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert_eq!(stdout.trim_end(), backup.to_str().unwrap());
+
+    let output = common::run(tmp.path(), &["verify", backup.to_str().unwrap()]);
+    assert!(output.status.success(), "{output:?}");
+}
+
+#[test]
+fn stdin_requires_a_name_and_may_be_empty() {
+    let tmp = TempDir::new().unwrap();
+
+    let output = common::run_with_stdin(tmp.path(), &["b", "-", "backups"], b"data");
+    assert_eq!(output.status.code(), Some(2), "{output:?}");
+    assert!(!tmp.path().join("backups").exists());
+
+    let output = common::run_with_stdin(tmp.path(), &["b", "-", "backups", "--name", "empty"], b"");
+    assert!(output.status.success(), "{output:?}");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("warning: stdin was empty"), "{stderr}");
+    let backup = common::single_entry(&tmp.path().join("backups"));
+    assert_eq!(fs::metadata(backup).unwrap().len(), 0);
+}
@@ -0,0 +1,118 @@
+#![cfg(unix)]
+
+mod common;
+
+use std::fs;
+use std::os::unix::fs::symlink;
+use std::path::Path;
+
+use tempfile::TempDir;
+
+#[test]
+fn symlink_source_is_backed_up_as_link() {
+    let tmp = TempDir::new().unwrap();
+    fs::write(tmp.path().join("real"), "data").unwrap();
+    symlink("real", tmp.path().join("link")).unwrap();
+    fs::create_dir(tmp.path().join("backups")).unwrap();
+
+    let output = common::run(tmp.path(), &["b", "link", "backups"]);
+    assert!(output.status.success(), "{output:?}");
+
+    let backup = common::single_entry(&tmp.path().join("backups"));
+    assert!(fs::symlink_metadata(&backup).unwrap().is_symlink());
+    assert_eq!(fs::read_link(&backup).unwrap(), Path::new("real"));
+}
+
+#[test]
+fn dangling_symlink_is_backed_up_and_restored() {
+    let tmp = TempDir::new().unwrap();
+    symlink("nowhere", tmp.path().join("dangling")).unwrap();
+
+    let output = common::run(tmp.path(), &["b", "dangling"]);
+    assert!(output.status.success(), "{output:?}");
+    fs::remove_file(tmp.path().join("dangling")).unwrap();
+
+    let backup = fs::read_dir(tmp.path())
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .find(|path| path.extension().is_some_and(|ext| ext == "backup"))
+        .unwrap();
+    let output = common::run(tmp.path(), &["r", backup.to_str().unwrap()]);
+    assert!(output.status.success(), "{output:?}");
+
+    let restored = tmp.path().join("dangling");
+    assert!(fs::symlink_metadata(&restored).unwrap().is_symlink());
+    assert_eq!(fs::read_link(&restored).unwrap(), Path::new("nowhere"));
+}
+
+#[test]
+fn symlinks_inside_directories_are_preserved() {
+    let tmp = TempDir::new().unwrap();
+    let source = tmp.path().join("project");
+    fs::create_dir_all(source.join("sub")).unwrap();
+    fs::write(source.join("sub/file"), "data").unwrap();
+    symlink("sub/file", source.join("file-link")).unwrap();
+    symlink("sub", source.join("dir-link")).unwrap();
+    fs::create_dir(tmp.path().join("backups")).unwrap();
+
+    let output = common::run(tmp.path(), &["b", "project", "backups"]);
+    assert!(output.status.success(), "{output:?}");
+
+    let backup = common::single_entry(&tmp.path().join("backups"));
+    for (link, target) in [("file-link", "sub/file"), ("dir-link", "sub")] {
+        let path = backup.join(link);
+        assert!(fs::symlink_metadata(&path).unwrap().is_symlink());
+        assert_eq!(fs::read_link(&path).unwrap(), Path::new(target));
+    }
+}
+
+#[test]
+fn dereference_copies_link_contents() {
+    let tmp = TempDir::new().unwrap();
+    let source = tmp.path().join("project");
+    fs::create_dir_all(source.join("sub")).unwrap();
+    fs::write(source.join("sub/file"), "data").unwrap();
+    symlink("sub/file", source.join("file-link")).unwrap();
+    symlink("sub", source.join("dir-link")).unwrap();
+    fs::create_dir(tmp.path().join("backups")).unwrap();
+
+    let output = common::run(tmp.path(), &["b", "-L", "project", "backups"]);
+    assert!(output.status.success(), "{output:?}");
+
+    let backup = common::single_entry(&tmp.path().join("backups"));
+    assert!(!fs::symlink_metadata(backup.join("file-link"))
+        .unwrap()
+        .is_symlink());
+    assert_eq!(
+        fs::read_to_string(backup.join("file-link")).unwrap(),
+        "data"
+    );
+    assert!(fs::symlink_metadata(backup.join("dir-link"))
+        .unwrap()
+        .is_dir());
+    assert_eq!(
+        fs::read_to_string(backup.join("dir-link/file")).unwrap(),
+        "data"
+    );
+}
+
+#[test]
+fn dereference_reports_loops_and_dangling_links() {
+    let tmp = TempDir::new().unwrap();
+    let source = tmp.path().join("project");
+    fs::create_dir_all(source.join("sub")).unwrap();
+    fs::write(source.join("sub/file"), "data").unwrap();
+    symlink("..", source.join("sub/up")).unwrap();
+    symlink("missing", source.join("dangling")).unwrap();
+    fs::create_dir(tmp.path().join("backups")).unwrap();
+
+    let output = common::run(tmp.path(), &["b", "--dereference", "project", "backups"]);
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("loop") && stderr.contains("up"), "{stderr}");
+    assert!(stderr.contains("dangling"), "{stderr}");
+
+    let backup = common::single_entry(&tmp.path().join("backups"));
+    assert_eq!(fs::read_to_string(backup.join("sub/file")).unwrap(), "data");
+    assert!(!backup.join("sub/up").exists());
+}
@@ -0,0 +1,105 @@
+mod common;
+
+use std::fs;
+use std::path::Path;
+
+use tempfile::TempDir;
+
+/// Writes a configuration file with a `my photos` profile in `root`.
+fn setup(root: &Path) {
+    fs::write(
+        root.join("config.toml"),
+        "[profile.\"my photos\"]\nsource = \"photos\"\ntarget = \"backups\"\n",
+    )
+    .unwrap();
+}
+
+#[test]
+fn units_are_installed_and_uninstalled() {
+    let tmp = TempDir::new().unwrap();
+    setup(tmp.path());
+    let home = tmp.path().join("home");
+    let vars = [("XDG_CONFIG_HOME", home.as_os_str())];
+    let units = home.join("systemd/user");
+
+    let install = [
+        "systemd-install",
+        "--config",
+        "config.toml",
+        "--on-calendar",
+        "Mon..Fri 22:00",
+        "my photos",
+    ];
+    let output = common::run_with_env(tmp.path(), &[&install[..], &["--dry-run"]].concat(), &vars);
+    assert!(output.status.success(), "{output:?}");
+    assert!(!units.exists());
+
+    let output = common::run_with_env(tmp.path(), &install, &vars);
+    assert!(output.status.success(), "{output:?}");
+    let service = fs::read_to_string(units.join(r"backup-my\x20photos.service")).unwrap();
+    let config = tmp.path().join("config.toml");
+    assert!(
+        service.contains(&format!(
+            r#" run "my photos" --config {}"#,
+            config.display()
+        )),
+        "{service}"
+    );
+    let timer = fs::read_to_string(units.join(r"backup-my\x20photos.timer")).unwrap();
+    assert!(timer.contains("OnCalendar=Mon..Fri 22:00\n"), "{timer}");
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("systemctl --user enable --now"), "{stdout}");
+
+    let output = common::run_with_env(tmp.path(), &install, &vars);
+    assert_eq!(output.status.code(), Some(1), "{output:?}");
+    let output = common::run_with_env(tmp.path(), &[&install[..], &["--force"]].concat(), &vars);
+    assert!(output.status.success(), "{output:?}");
+
+    let uninstall = ["systemd-uninstall", "--json", "my photos"];
+    let output = common::run_with_env(tmp.path(), &uninstall, &vars);
+    assert!(output.status.success(), "{output:?}");
+    let outcome: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(outcome["action"], "systemd-uninstall");
+    assert_eq!(outcome["units"].as_array().unwrap().len(), 2, "{outcome}");
+    assert_eq!(fs::read_dir(&units).unwrap().count(), 0);
+
+    let output = common::run_with_env(tmp.path(), &uninstall, &vars);
+    assert_eq!(output.status.code(), Some(1), "{output:?}");
+}
+
+#[test]
+fn units_are_printed_for_known_profiles_only() {
+    let tmp = TempDir::new().unwrap();
+    setup(tmp.path());
+
+    let output = common::run(
+        tmp.path(),
+        &[
+            "systemd-install",
+            "--config",
+            "config.toml",
+            "--stdout",
+            "my photos",
+        ],
+    );
+    assert!(output.status.success(), "{output:?}");
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(
+        stdout.starts_with("# backup-my\\x20photos.service\n[Unit]\n"),
+        "{stdout}"
+    );
+    assert!(stdout.contains("OnCalendar=daily\n"), "{stdout}");
+
+    let output = common::run(
+        tmp.path(),
+        &[
+            "systemd-install",
+            "--config",
+            "config.toml",
+            "--stdout",
+            "videos",
+        ],
+    );
+    assert_eq!(output.status.code(), Some(2), "{output:?}");
+    assert!(output.stdout.is_empty());
+}
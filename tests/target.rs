@@ -0,0 +1,120 @@
+mod common;
+
+use std::fs;
+use std::path::Path;
+
+use tempfile::TempDir;
+
+fn setup() -> TempDir {
+    let tmp = TempDir::new().unwrap();
+    fs::write(tmp.path().join("hosts"), "127.0.0.1 localhost").unwrap();
+    fs::create_dir_all(tmp.path().join("project/src")).unwrap();
+    fs::write(tmp.path().join("project/src/main.rs"), "fn main() {}").unwrap();
+    tmp
+}
+
+fn backup(tmp: &TempDir, args: &[&str]) {
+    let output = common::run(tmp.path(), args);
+    assert!(output.status.success(), "{output:?}");
+}
+
+fn is_backup_name(path: &Path, original: &str) -> bool {
+    let name = path.file_name().unwrap().to_str().unwrap();
+    name.starts_with(&format!("{original}.")) && name.ends_with(".backup")
+}
+
+#[test]
+fn file_into_missing_target_creates_the_directory() {
+    let tmp = setup();
+
+    backup(&tmp, &["b", "hosts", "new/backups"]);
+    let backup = common::single_entry(&tmp.path().join("new/backups"));
+    assert!(is_backup_name(&backup, "hosts"), "{backup:?}");
+    assert_eq!(fs::read_to_string(backup).unwrap(), "127.0.0.1 localhost");
+}
+
+#[test]
+fn file_as_file_writes_the_missing_target() {
+    let tmp = setup();
+
+    backup(&tmp, &["b", "--as-file", "hosts", "new/hosts.copy"]);
+    assert_eq!(
+        fs::read_to_string(tmp.path().join("new/hosts.copy")).unwrap(),
+        "127.0.0.1 localhost"
+    );
+}
+
+#[test]
+fn directory_into_missing_target_creates_the_directory() {
+    let tmp = setup();
+
+    backup(&tmp, &["b", "project", "new/backups"]);
+    let backup = common::single_entry(&tmp.path().join("new/backups"));
+    assert!(is_backup_name(&backup, "project"), "{backup:?}");
+    assert_eq!(
+        fs::read_to_string(backup.join("src/main.rs")).unwrap(),
+        "fn main() {}"
+    );
+}
+
+#[test]
+fn directory_as_file_archives_into_the_missing_target() {
+    let tmp = setup();
+
+    backup(&tmp, &["b", "--as-file", "project", "new/project.tar.gz"]);
+    assert!(tmp.path().join("new/project.tar.gz").is_file());
+
+    fs::remove_dir_all(tmp.path().join("project")).unwrap();
+    backup(&tmp, &["r", "new/project.tar.gz", "project"]);
+    assert_eq!(
+        fs::read_to_string(tmp.path().join("project/src/main.rs")).unwrap(),
+        "fn main() {}"
+    );
+}
+
+#[test]
+fn directory_archives_into_a_missing_target_named_like_an_archive() {
+    let tmp = setup();
+
+    backup(&tmp, &["b", "project", "new/project.tar.gz"]);
+    assert!(tmp.path().join("new/project.tar.gz").is_file());
+    backup(&tmp, &["b", "project", "new/project.tar.zst"]);
+    assert!(tmp.path().join("new/project.tar.zst").is_file());
+
+    fs::remove_dir_all(tmp.path().join("project")).unwrap();
+    backup(&tmp, &["r", "new/project.tar.zst", "project"]);
+    assert_eq!(
+        fs::read_to_string(tmp.path().join("project/src/main.rs")).unwrap(),
+        "fn main() {}"
+    );
+}
+
+#[test]
+fn as_file_does_not_change_existing_targets() {
+    let tmp = setup();
+    fs::create_dir(tmp.path().join("backups")).unwrap();
+
+    backup(&tmp, &["b", "--as-file", "hosts", "backups"]);
+    let backup = common::single_entry(&tmp.path().join("backups"));
+    assert!(is_backup_name(&backup, "hosts"), "{backup:?}");
+}
+
+#[test]
+fn dry_run_does_not_create_the_target() {
+    let tmp = setup();
+
+    let output = common::run(tmp.path(), &["b", "--dry-run", "hosts", "new"]);
+    assert!(output.status.success(), "{output:?}");
+    assert!(String::from_utf8_lossy(&output.stdout).contains("it will be created"));
+    assert!(!tmp.path().join("new").exists());
+}
+
+#[test]
+fn missing_target_inside_the_source_is_refused() {
+    let tmp = setup();
+
+    let output = common::run(tmp.path(), &["b", "project", "project/new/backups"]);
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("into itself"));
+    assert!(!tmp.path().join("project/new").exists());
+}
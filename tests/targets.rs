@@ -0,0 +1,106 @@
+mod common;
+
+use std::fs;
+use std::path::Path;
+
+use tempfile::TempDir;
+
+/// Creates a directory `data` to back up in `root`.
+fn setup(root: &Path) {
+    fs::create_dir(root.join("data")).unwrap();
+    let blob: Vec<u8> = (0..200_000u32).map(|i| (i * 7 % 251) as u8).collect();
+    fs::write(root.join("data/blob.bin"), blob).unwrap();
+    fs::write(root.join("data/notes.txt"), "notes").unwrap();
+}
+
+#[test]
+fn archives_are_written_to_every_target() {
+    let tmp = TempDir::new().unwrap();
+    setup(tmp.path());
+
+    let output = common::run(
+        tmp.path(),
+        &[
+            "b", "-c", "gzip", "data", "--target", "one", "--target", "two",
+        ],
+    );
+    assert!(output.status.success(), "{output:?}");
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let paths: Vec<_> = stdout.lines().map(Path::new).collect();
+    assert_eq!(paths.len(), 2, "{stdout}");
+    assert_eq!(paths[0].file_name(), paths[1].file_name());
+    assert_eq!(
+        paths[0],
+        tmp.path().join("one").join(paths[0].file_name().unwrap())
+    );
+    assert_eq!(fs::read(paths[0]).unwrap(), fs::read(paths[1]).unwrap());
+
+    for target in ["one", "two"] {
+        let dir = tmp.path().join(target);
+        let archive = common::single_entry(&dir);
+        assert!(archive.to_string_lossy().ends_with(".backup.tar.gz"));
+        let output = common::run(&dir, &["verify", archive.to_str().unwrap()]);
+        assert!(output.status.success(), "{output:?}");
+        let output = common::run(&dir, &["r", archive.to_str().unwrap(), "restored"]);
+        assert!(output.status.success(), "{output:?}");
+        assert_eq!(
+            fs::read_to_string(dir.join("restored/notes.txt")).unwrap(),
+            "notes"
+        );
+    }
+}
+
+#[test]
+fn directories_are_copied_to_each_target_in_turn() {
+    let tmp = TempDir::new().unwrap();
+    setup(tmp.path());
+
+    let output = common::run(
+        tmp.path(),
+        &["b", "data", "--target", "one", "--target", "two"],
+    );
+    assert!(output.status.success(), "{output:?}");
+    let one = common::single_entry(&tmp.path().join("one"));
+    let two = common::single_entry(&tmp.path().join("two"));
+    assert_eq!(one.file_name(), two.file_name());
+    assert_eq!(
+        fs::read(one.join("blob.bin")).unwrap(),
+        fs::read(two.join("blob.bin")).unwrap()
+    );
+}
+
+#[test]
+fn failing_targets_do_not_stop_the_others() {
+    let tmp = TempDir::new().unwrap();
+    setup(tmp.path());
+    fs::write(tmp.path().join("file"), "not a directory").unwrap();
+
+    for compress in ["gzip", "none"] {
+        let output = common::run(
+            tmp.path(),
+            &[
+                "b",
+                "--json",
+                "-c",
+                compress,
+                "data",
+                "--target",
+                "file/below",
+                "--target",
+                compress,
+            ],
+        );
+        assert_eq!(output.status.code(), Some(4), "{output:?}");
+        let outcomes: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+        let outcomes = outcomes.as_array().unwrap();
+        assert_eq!(outcomes.len(), 2, "{outcomes:?}");
+        assert_eq!(outcomes[0]["target"], "file/below");
+        assert!(outcomes[0]["error"].is_string(), "{outcomes:?}");
+        assert_eq!(outcomes[1]["target"], compress);
+        assert_eq!(outcomes[1]["action"], "backup");
+        let backup = outcomes[1]["backup_path"].as_str().unwrap();
+        assert!(Path::new(backup).exists(), "{backup}");
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert!(stderr.contains("targets_failed"), "{stderr}");
+    }
+}
@@ -0,0 +1,101 @@
+mod common;
+
+use std::fs;
+
+use tempfile::TempDir;
+
+/// Creates two backups of `hosts`, the older one with a checksum manifest.
+fn backups() -> TempDir {
+    let tmp = TempDir::new().unwrap();
+    let backups = tmp.path().join("backups");
+    fs::create_dir(&backups).unwrap();
+    for day in 1..=2 {
+        let name = format!("hosts.2024-05-0{day}_10-00-00.backup");
+        fs::write(backups.join(name), "1234567890").unwrap();
+    }
+    fs::write(
+        backups.join("hosts.2024-05-01_10-00-00.backup.sha256"),
+        "digest",
+    )
+    .unwrap();
+    tmp
+}
+
+#[test]
+fn pruned_backups_go_to_the_trash_until_it_is_emptied() {
+    let tmp = backups();
+    let args = [
+        "prune",
+        "--keep-last",
+        "1",
+        "--trash-dir",
+        "trash",
+        "backups",
+    ];
+    let output = common::run(tmp.path(), &args);
+    assert!(output.status.success(), "{output:?}");
+    assert!(String::from_utf8_lossy(&output.stdout).contains("moved 10 B to the trash"));
+
+    let files = tmp.path().join("trash/files");
+    assert!(files.join("hosts.2024-05-01_10-00-00.backup").is_file());
+    assert!(files
+        .join("hosts.2024-05-01_10-00-00.backup.sha256")
+        .is_file());
+    let info = fs::read_to_string(
+        tmp.path()
+            .join("trash/info/hosts.2024-05-01_10-00-00.backup.trashinfo"),
+    )
+    .unwrap();
+    assert!(info.starts_with("[Trash Info]\nPath=/"), "{info}");
+    assert!(
+        info.contains("/backups/hosts.2024-05-01_10-00-00.backup\n"),
+        "{info}"
+    );
+    assert!(info.contains("DeletionDate="), "{info}");
+    assert_eq!(common::tree(&tmp.path().join("backups")).len(), 1);
+
+    fs::write(files.join("photo.jpg"), "not ours").unwrap();
+    fs::write(
+        tmp.path().join("trash/info/photo.jpg.trashinfo"),
+        "[Trash Info]\nPath=/home/user/photo.jpg\nDeletionDate=2024-01-01T00:00:00\n",
+    )
+    .unwrap();
+
+    let args = [
+        "gc",
+        "--empty-trash",
+        "--older-than",
+        "30d",
+        "--trash-dir",
+        "trash",
+    ];
+    let output = common::run(tmp.path(), &args);
+    assert!(output.status.success(), "{output:?}");
+    assert!(files.join("hosts.2024-05-01_10-00-00.backup").is_file());
+
+    let args = ["gc", "--empty-trash", "--trash-dir", "trash"];
+    let output = common::run(tmp.path(), &args);
+    assert!(output.status.success(), "{output:?}");
+    assert!(String::from_utf8_lossy(&output.stdout).contains("freed 16 B"));
+    assert_eq!(common::tree(&files), [std::path::Path::new("photo.jpg")]);
+    assert_eq!(common::tree(&tmp.path().join("trash/info")).len(), 1);
+}
+
+#[test]
+fn the_default_trash_is_in_the_data_directory() {
+    let tmp = backups();
+    let data = tmp.path().join("data");
+    let args = ["prune", "--keep-last", "1", "--trash", "backups"];
+    let output = common::run_with_history(tmp.path(), &args, &data);
+    assert!(output.status.success(), "{output:?}");
+    assert!(data
+        .join("Trash/files/hosts.2024-05-01_10-00-00.backup")
+        .is_file());
+}
+
+#[test]
+fn gc_requires_an_action() {
+    let tmp = TempDir::new().unwrap();
+    let output = common::run(tmp.path(), &["gc"]);
+    assert_eq!(output.status.code(), Some(2), "{output:?}");
+}
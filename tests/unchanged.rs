@@ -0,0 +1,103 @@
+mod common;
+
+use std::fs;
+use std::path::Path;
+
+use tempfile::TempDir;
+
+fn backups(dir: &Path) -> usize {
+    fs::read_dir(dir)
+        .unwrap()
+        .filter(|entry| !common::is_sidecar(&entry.as_ref().unwrap().path()))
+        .count()
+}
+
+#[test]
+fn unchanged_file_is_skipped() {
+    let tmp = TempDir::new().unwrap();
+    fs::write(tmp.path().join("notes"), "notes").unwrap();
+    fs::create_dir(tmp.path().join("backups")).unwrap();
+
+    let args = ["b", "--skip-unchanged", "notes", "backups"];
+    let output = common::run(tmp.path(), &args);
+    assert!(output.status.success(), "{output:?}");
+
+    let output = common::run(tmp.path(), &args);
+    assert_eq!(output.status.code(), Some(3), "{output:?}");
+    assert!(output.stdout.is_empty(), "{output:?}");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("unchanged"), "{stderr}");
+    assert_eq!(backups(&tmp.path().join("backups")), 1);
+
+    fs::write(tmp.path().join("notes"), "more notes").unwrap();
+    let output = common::run(tmp.path(), &args);
+    assert!(output.status.success(), "{output:?}");
+    assert_eq!(backups(&tmp.path().join("backups")), 2);
+}
+
+#[test]
+fn directory_changes_are_detected() {
+    let tmp = TempDir::new().unwrap();
+    fs::create_dir_all(tmp.path().join("project/src")).unwrap();
+    fs::write(tmp.path().join("project/src/main.rs"), "fn main() {}").unwrap();
+    fs::write(tmp.path().join("project/README"), "readme").unwrap();
+    fs::create_dir(tmp.path().join("backups")).unwrap();
+
+    let args = ["b", "--skip-unchanged", "project", "backups"];
+    let backups_dir = tmp.path().join("backups");
+    let output = common::run(tmp.path(), &args);
+    assert!(output.status.success(), "{output:?}");
+    let output = common::run(tmp.path(), &args);
+    assert_eq!(output.status.code(), Some(3), "{output:?}");
+
+    fs::write(tmp.path().join("project/src/lib.rs"), "").unwrap();
+    let output = common::run(tmp.path(), &args);
+    assert!(output.status.success(), "{output:?}");
+    assert_eq!(backups(&backups_dir), 2);
+
+    fs::remove_file(tmp.path().join("project/README")).unwrap();
+    let output = common::run(tmp.path(), &args);
+    assert!(output.status.success(), "{output:?}");
+    assert_eq!(backups(&backups_dir), 3);
+
+    let output = common::run(tmp.path(), &args);
+    assert_eq!(output.status.code(), Some(3), "{output:?}");
+    assert_eq!(backups(&backups_dir), 3);
+}
+
+#[test]
+fn checksum_comparison_ignores_touched_files() {
+    let tmp = TempDir::new().unwrap();
+    fs::create_dir(tmp.path().join("project")).unwrap();
+    fs::write(tmp.path().join("project/notes"), "notes").unwrap();
+    fs::create_dir(tmp.path().join("backups")).unwrap();
+
+    let output = common::run(tmp.path(), &["b", "project", "backups"]);
+    assert!(output.status.success(), "{output:?}");
+
+    let touched = filetime::FileTime::from_unix_time(1_000_000_000, 0);
+    filetime::set_file_mtime(tmp.path().join("project/notes"), touched).unwrap();
+    let output = common::run(
+        tmp.path(),
+        &["b", "--skip-unchanged", "--checksum", "project", "backups"],
+    );
+    assert_eq!(output.status.code(), Some(3), "{output:?}");
+
+    let output = common::run(tmp.path(), &["b", "--skip-unchanged", "project", "backups"]);
+    assert!(output.status.success(), "{output:?}");
+}
+
+#[test]
+fn dry_run_reports_the_skip() {
+    let tmp = TempDir::new().unwrap();
+    fs::write(tmp.path().join("notes"), "notes").unwrap();
+
+    let output = common::run(tmp.path(), &["b", "notes"]);
+    assert!(output.status.success(), "{output:?}");
+
+    let output = common::run(tmp.path(), &["b", "-n", "--skip-unchanged", "notes"]);
+    assert_eq!(output.status.code(), Some(3), "{output:?}");
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("unchanged since:"), "{stdout}");
+    assert_eq!(backups(tmp.path()), 2);
+}
@@ -0,0 +1,172 @@
+mod common;
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use tempfile::TempDir;
+
+fn backup(dir: &Path, args: &[&str]) -> PathBuf {
+    let output = common::run(dir, args);
+    assert!(output.status.success(), "{output:?}");
+    common::single_entry(&dir.join("backups"))
+}
+
+fn project(root: &Path) {
+    fs::create_dir_all(root.join("project/src")).unwrap();
+    fs::write(root.join("project/README"), "readme").unwrap();
+    fs::write(root.join("project/src/main.rs"), "fn main() {}").unwrap();
+    fs::create_dir(root.join("backups")).unwrap();
+}
+
+#[test]
+fn file_backup_manifest_is_sha256sum_compatible() {
+    let tmp = TempDir::new().unwrap();
+    fs::write(tmp.path().join("hosts"), "abc").unwrap();
+    fs::create_dir(tmp.path().join("backups")).unwrap();
+
+    let backup = backup(tmp.path(), &["b", "hosts", "backups"]);
+    let manifest = fs::read_to_string(format!("{}.sha256", backup.display())).unwrap();
+    let name = backup.file_name().unwrap().to_str().unwrap();
+    assert_eq!(
+        manifest,
+        format!("ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad  {name}\n")
+    );
+
+    let output = common::run(tmp.path(), &["verify", backup.to_str().unwrap()]);
+    assert!(output.status.success(), "{output:?}");
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        format!("{name}: OK\n")
+    );
+}
+
+#[test]
+fn directory_backup_reports_each_modified_file() {
+    let tmp = TempDir::new().unwrap();
+    project(tmp.path());
+
+    let backup = backup(tmp.path(), &["b", "project", "backups"]);
+    let output = common::run(tmp.path(), &["verify", backup.to_str().unwrap()]);
+    assert!(output.status.success(), "{output:?}");
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout)
+            .matches(": OK")
+            .count(),
+        2
+    );
+
+    fs::write(backup.join("src/main.rs"), "fn main() { rot() }").unwrap();
+    let output = common::run(tmp.path(), &["verify", backup.to_str().unwrap()]);
+    assert_eq!(output.status.code(), Some(5), "{output:?}");
+    assert!(String::from_utf8_lossy(&output.stdout).contains("README: OK"));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("src/main.rs: FAILED"), "{stderr}");
+    assert!(stderr.contains("1 files do not match"), "{stderr}");
+}
+
+#[test]
+fn archive_backup_is_verified_with_blake3() {
+    let tmp = TempDir::new().unwrap();
+    project(tmp.path());
+
+    let args = [
+        "b",
+        "-c",
+        "zstd",
+        "--algorithm",
+        "blake3",
+        "project",
+        "backups",
+    ];
+    let backup = backup(tmp.path(), &args);
+    let manifest = PathBuf::from(format!("{}.blake3", backup.display()));
+    assert!(manifest.is_file());
+
+    let output = common::run(tmp.path(), &["verify", manifest.to_str().unwrap()]);
+    assert!(output.status.success(), "{output:?}");
+
+    let mut contents = fs::read(&backup).unwrap();
+    let last = contents.len() - 1;
+    contents[last] ^= 0xff;
+    fs::write(&backup, contents).unwrap();
+    let output = common::run(tmp.path(), &["verify", backup.to_str().unwrap()]);
+    assert!(!output.status.success());
+}
+
+#[test]
+fn missing_manifest_fails() {
+    let tmp = TempDir::new().unwrap();
+    fs::write(tmp.path().join("hosts.2024-05-01_10-00-00.backup"), "old").unwrap();
+
+    let output = common::run(tmp.path(), &["verify", "hosts.2024-05-01_10-00-00.backup"]);
+    assert_eq!(output.status.code(), Some(1));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("No checksum manifest"));
+}
+
+#[test]
+fn unknown_algorithm_is_rejected() {
+    let tmp = TempDir::new().unwrap();
+    fs::write(tmp.path().join("hosts"), "abc").unwrap();
+
+    let output = common::run(tmp.path(), &["b", "--algorithm", "md5", "hosts", "."]);
+    assert_eq!(output.status.code(), Some(2));
+    assert_eq!(fs::read_dir(tmp.path()).unwrap().count(), 1);
+}
+
+#[test]
+fn restore_fails_listing_corrupted_files() {
+    let tmp = TempDir::new().unwrap();
+    project(tmp.path());
+    let backup = backup(tmp.path(), &["b", "project", "backups"]);
+    fs::write(backup.join("src/main.rs"), "fn main() { rot() }").unwrap();
+
+    let output = common::run(tmp.path(), &["r", backup.to_str().unwrap(), "restored"]);
+    assert_eq!(output.status.code(), Some(5), "{output:?}");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    let name = backup.file_name().unwrap().to_str().unwrap();
+    assert!(
+        stderr.contains(&format!("\n  {name}/src/main.rs")),
+        "{stderr}"
+    );
+    assert!(!stderr.contains("README"), "{stderr}");
+
+    let output = common::run(
+        tmp.path(),
+        &["r", "--no-verify", backup.to_str().unwrap(), "unverified"],
+    );
+    assert!(output.status.success(), "{output:?}");
+}
+
+#[test]
+fn restore_checks_archives_while_reading_them() {
+    let tmp = TempDir::new().unwrap();
+    project(tmp.path());
+    let backup = backup(tmp.path(), &["b", "-c", "gzip", "project", "backups"]);
+
+    let output = common::run(tmp.path(), &["r", "-v", backup.to_str().unwrap(), "intact"]);
+    assert!(output.status.success(), "{output:?}");
+    assert!(String::from_utf8_lossy(&output.stderr).contains("verified 1 files"));
+
+    // Append garbage after the gzip stream, which extraction never looks at.
+    let mut contents = fs::read(&backup).unwrap();
+    contents.extend_from_slice(b"garbage");
+    fs::write(&backup, contents).unwrap();
+    let output = common::run(tmp.path(), &["r", backup.to_str().unwrap(), "corrupted"]);
+    assert_eq!(output.status.code(), Some(5), "{output:?}");
+    assert!(String::from_utf8_lossy(&output.stderr).contains("do not match"));
+}
+
+#[test]
+fn restore_without_manifest_says_it_is_unverified() {
+    let tmp = TempDir::new().unwrap();
+    fs::write(tmp.path().join("hosts.2024-05-01_10-00-00.backup"), "abc").unwrap();
+
+    let output = common::run(tmp.path(), &["r", "hosts.2024-05-01_10-00-00.backup"]);
+    assert!(output.status.success(), "{output:?}");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert_eq!(
+        stderr.matches("skipping verification").count(),
+        1,
+        "{stderr}"
+    );
+}
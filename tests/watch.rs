@@ -0,0 +1,75 @@
+#![cfg(unix)]
+
+mod common;
+
+use std::fs;
+use std::io::{BufRead, BufReader};
+use std::path::PathBuf;
+use std::thread;
+use std::time::Duration;
+
+use tempfile::TempDir;
+
+#[test]
+fn changes_are_backed_up_until_interrupted() {
+    let tmp = TempDir::new().unwrap();
+    fs::create_dir(tmp.path().join("data")).unwrap();
+    fs::write(tmp.path().join("data/notes.txt"), "first").unwrap();
+
+    let mut child = common::spawn(
+        tmp.path(),
+        &[
+            "watch",
+            "--settle",
+            "1s",
+            "--precision",
+            "ms",
+            "--keep-last",
+            "5",
+            "data",
+            "out",
+        ],
+    );
+    let mut stdout = BufReader::new(child.stdout.take().unwrap()).lines();
+    let mut backed_up = || PathBuf::from(stdout.next().unwrap().unwrap());
+    let first = backed_up();
+    // Let the watcher start waiting after the backup.
+    thread::sleep(Duration::from_millis(500));
+    fs::create_dir(tmp.path().join("data/sub")).unwrap();
+    fs::write(tmp.path().join("data/sub/todo.txt"), "second").unwrap();
+    let second = backed_up();
+    thread::sleep(Duration::from_millis(500));
+    fs::write(tmp.path().join("data/sub/todo.txt"), "third").unwrap();
+    let third = backed_up();
+    assert!(
+        first < second && second < third,
+        "{first:?} {second:?} {third:?}"
+    );
+
+    assert_eq!(
+        unsafe { libc::kill(child.id() as libc::pid_t, libc::SIGINT) },
+        0
+    );
+    let output = child.wait_with_output().unwrap();
+    assert!(output.status.success(), "{output:?}");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("stopped watching data"), "{stderr}");
+
+    assert_eq!(
+        fs::read_to_string(third.join("sub/todo.txt")).unwrap(),
+        "third"
+    );
+}
+
+#[test]
+fn untimestamped_names_are_refused() {
+    let tmp = TempDir::new().unwrap();
+    fs::create_dir(tmp.path().join("data")).unwrap();
+
+    let output = common::run(
+        tmp.path(),
+        &["watch", "--name-format", "{name}.bak", "data", "out"],
+    );
+    assert_eq!(output.status.code(), Some(2), "{output:?}");
+    assert!(!tmp.path().join("out").exists());
+}
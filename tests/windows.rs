@@ -0,0 +1,71 @@
+#![cfg(windows)]
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use backup::backup::BackupOptions;
+use backup::restore::RestoreOptions;
+use tempfile::TempDir;
+
+/// Creates a tree below `root` whose deepest file lies past 260 characters.
+fn deep_tree(root: &Path) -> PathBuf {
+    let mut dir = root.to_path_buf();
+    while dir.as_os_str().len() < 300 {
+        dir.push("a-rather-long-directory-name");
+    }
+    fs::create_dir_all(format!(r"\\?\{}", dir.display())).unwrap();
+    fs::write(format!(r"\\?\{}\deep.txt", dir.display()), "deep").unwrap();
+    dir.join("deep.txt")
+}
+
+#[test]
+fn trees_deeper_than_260_characters_round_trip() {
+    let tmp = TempDir::new().unwrap();
+    let source = tmp.path().join("project");
+    let deep = deep_tree(&source);
+
+    let report =
+        backup::backup(&source, &tmp.path().join("backups"), &BackupOptions::new()).unwrap();
+    assert_eq!(report.files, 1);
+
+    fs::remove_dir_all(format!(r"\\?\{}", source.display())).unwrap();
+    backup::restore(&report.path, None, &RestoreOptions::new()).unwrap();
+    let restored = fs::read_to_string(format!(r"\\?\{}", deep.display())).unwrap();
+    assert_eq!(restored, "deep");
+}
+
+#[test]
+fn drive_letter_paths_are_accepted() {
+    let tmp = TempDir::new().unwrap();
+    let source = tmp.path().join("notes.txt");
+    fs::write(&source, "notes").unwrap();
+    let target = tmp.path().canonicalize().unwrap();
+    let target = PathBuf::from(target.to_str().unwrap().trim_start_matches(r"\\?\"));
+    assert_eq!(target.to_str().unwrap().chars().nth(1), Some(':'));
+
+    let report = backup::backup(&source, &target, &BackupOptions::new()).unwrap();
+    assert!(report.path.starts_with(&target));
+    assert_eq!(fs::read_to_string(&report.path).unwrap(), "notes");
+}
+
+#[test]
+fn unc_paths_are_accepted() {
+    let tmp = TempDir::new().unwrap();
+    let local = tmp.path().canonicalize().unwrap();
+    let local = local.to_str().unwrap().trim_start_matches(r"\\?\");
+    let (drive, rest) = local.split_once(':').unwrap();
+    // The administrative share of the drive, which may not be shared.
+    let unc = PathBuf::from(format!(r"\\localhost\{drive}${rest}"));
+    if fs::metadata(&unc).is_err() {
+        return;
+    }
+
+    fs::write(unc.join("notes.txt"), "notes").unwrap();
+    let report = backup::backup(
+        &unc.join("notes.txt"),
+        &unc.join("backups"),
+        &BackupOptions::new(),
+    )
+    .unwrap();
+    assert_eq!(fs::read_to_string(&report.path).unwrap(), "notes");
+}
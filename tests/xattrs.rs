@@ -0,0 +1,91 @@
+#![cfg(target_os = "linux")]
+
+mod common;
+
+use std::ffi::CString;
+use std::fs;
+use std::os::unix::ffi::OsStrExt;
+use std::path::Path;
+
+use tempfile::TempDir;
+
+fn c_path(path: &Path) -> CString {
+    CString::new(path.as_os_str().as_bytes()).unwrap()
+}
+
+/// Sets the attribute `user.comment` of `path`, returning `false` if the
+/// filesystem does not support it.
+fn set_comment(path: &Path, value: &str) -> bool {
+    let name = CString::new("user.comment").unwrap();
+    let result = unsafe {
+        libc::lsetxattr(
+            c_path(path).as_ptr(),
+            name.as_ptr(),
+            value.as_ptr().cast(),
+            value.len(),
+            0,
+        )
+    };
+    result == 0
+}
+
+fn comment(path: &Path) -> Option<String> {
+    let name = CString::new("user.comment").unwrap();
+    let mut buffer = [0u8; 256];
+    let size = unsafe {
+        libc::lgetxattr(
+            c_path(path).as_ptr(),
+            name.as_ptr(),
+            buffer.as_mut_ptr().cast(),
+            buffer.len(),
+        )
+    };
+    (size >= 0).then(|| String::from_utf8_lossy(&buffer[..size as usize]).into_owned())
+}
+
+#[test]
+fn attributes_are_copied_archived_and_restored() {
+    let tmp = TempDir::new().unwrap();
+    let project = tmp.path().join("project");
+    fs::create_dir(&project).unwrap();
+    fs::write(project.join("notes"), "notes").unwrap();
+    if !set_comment(&project.join("notes"), "file") || !set_comment(&project, "dir") {
+        return;
+    }
+
+    let output = common::run(tmp.path(), &["b", "--xattrs", "project", "copies"]);
+    assert!(output.status.success(), "{output:?}");
+    let copy = common::single_entry(&tmp.path().join("copies"));
+    assert_eq!(comment(&copy).as_deref(), Some("dir"));
+    assert_eq!(comment(&copy.join("notes")).as_deref(), Some("file"));
+
+    let output = common::run(
+        tmp.path(),
+        &["b", "--xattrs", "--compress", "gzip", "project", "archives"],
+    );
+    assert!(output.status.success(), "{output:?}");
+    let archive = common::single_entry(&tmp.path().join("archives"));
+
+    fs::remove_dir_all(&project).unwrap();
+    let output = common::run(
+        tmp.path(),
+        &["r", "--xattrs", archive.to_str().unwrap(), "."],
+    );
+    assert!(output.status.success(), "{output:?}");
+    assert_eq!(comment(&project).as_deref(), Some("dir"));
+    assert_eq!(comment(&project.join("notes")).as_deref(), Some("file"));
+}
+
+#[test]
+fn attributes_are_left_out_with_no_xattrs() {
+    let tmp = TempDir::new().unwrap();
+    fs::write(tmp.path().join("notes"), "notes").unwrap();
+    if !set_comment(&tmp.path().join("notes"), "file") {
+        return;
+    }
+
+    let output = common::run(tmp.path(), &["b", "--no-xattrs", "notes", "copies"]);
+    assert!(output.status.success(), "{output:?}");
+    let copy = common::single_entry(&tmp.path().join("copies"));
+    assert_eq!(comment(&copy), None);
+}